@@ -0,0 +1,102 @@
+//! Encoder benchmarks on payloads shaped like agent traffic, each against
+//! `serde_json::to_string` of the same value.
+//!
+//! Run with `cargo bench --bench encode`; `cargo bench --bench encode -- tabular`
+//! runs one group.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use glyph_rs::{canonicalize_loose, canonicalize_loose_with_opts, from_json, Encoder, LooseCanonOpts};
+use serde_json::{json, Value as JsonValue};
+use std::hint::black_box;
+
+/// A single tool call with a few nested arguments
+fn small_tool_call() -> JsonValue {
+    json!({
+        "id": "call_01",
+        "type": "function",
+        "function": {
+            "name": "search_documents",
+            "arguments": {
+                "query": "quarterly revenue by region",
+                "limit": 10,
+                "filters": {"lang": "en", "year": 2024, "draft": false},
+                "fields": ["title", "url", "score"]
+            }
+        }
+    })
+}
+
+/// A tool result of uniform rows, encoded as an `@tab` block
+fn large_tabular() -> JsonValue {
+    const REGIONS: [&str; 3] = ["emea", "apac", "amer"];
+    let rows: Vec<JsonValue> = (0..2000)
+        .map(|i| {
+            json!({
+                "id": i,
+                "name": format!("customer {}", i),
+                "region": REGIONS[i % 3],
+                "score": (i as f64) * 0.37,
+                "active": i % 4 != 0,
+                "owner": null
+            })
+        })
+        .collect();
+    json!({"results": rows, "total": 2000})
+}
+
+/// Maps nested 48 deep, each level with a few scalars alongside
+fn deep_nesting() -> JsonValue {
+    (0..48).fold(json!({"leaf": true}), |child, depth| {
+        json!({"depth": depth, "label": format!("level {}", depth), "child": child})
+    })
+}
+
+/// Long strings that need quoting and escaping: a page of text, a stack
+/// trace and many short messages
+fn string_heavy() -> JsonValue {
+    let page = "Lorem ipsum dolor sit amet, \"consectetur\" adipiscing elit.\n".repeat(200);
+    let trace: Vec<String> = (0..100).map(|i| format!("  at handler{} (src/app/handler.rs:{}:9)", i, i * 7)).collect();
+    let messages: Vec<JsonValue> = (0..300)
+        .map(|i| json!({"role": if i % 2 == 0 { "user" } else { "assistant" }, "text": format!("message {} with tabs\tand ünïcödé", i)}))
+        .collect();
+    json!({"page": page, "trace": trace.join("\n"), "messages": messages})
+}
+
+fn payloads() -> Vec<(&'static str, JsonValue)> {
+    vec![
+        ("small_tool_call", small_tool_call()),
+        ("large_tabular", large_tabular()),
+        ("deep_nesting", deep_nesting()),
+        ("string_heavy", string_heavy()),
+    ]
+}
+
+fn bench_encode(c: &mut Criterion) {
+    for (name, json) in payloads() {
+        let v = from_json(&json);
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(serde_json::to_string(&json).unwrap().len() as u64));
+
+        group.bench_function(BenchmarkId::new("glyph", "default"), |b| {
+            b.iter(|| canonicalize_loose(black_box(&v)).unwrap())
+        });
+        let llm = LooseCanonOpts::llm();
+        group.bench_function(BenchmarkId::new("glyph", "llm"), |b| {
+            b.iter(|| canonicalize_loose_with_opts(black_box(&v), &llm).unwrap())
+        });
+        let mut encoder = Encoder::new(LooseCanonOpts::default());
+        group.bench_function(BenchmarkId::new("glyph", "encoder"), |b| {
+            b.iter(|| encoder.encode(black_box(&v)).unwrap().len())
+        });
+        group.bench_function(BenchmarkId::new("glyph", "from_json"), |b| {
+            b.iter(|| canonicalize_loose(&from_json(black_box(&json))).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("serde_json", "to_string"), |b| {
+            b.iter(|| serde_json::to_string(black_box(&json)).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_encode);
+criterion_main!(benches);
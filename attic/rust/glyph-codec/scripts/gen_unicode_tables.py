@@ -0,0 +1,47 @@
+#!/usr/bin/env python3
+"""Regenerate src/unicode_tables.rs (NFC data) from Python's unicodedata.
+
+Usage: python3 scripts/gen_unicode_tables.py src/unicode_tables.rs
+"""
+import unicodedata, sys
+U = unicodedata
+decomp = {}   # full canonical decomposition (non-Hangul)
+ccc = {}
+comp = {}
+for cp in range(0x110000):
+    if 0xAC00 <= cp <= 0xD7A3: continue
+    c = chr(cp)
+    k = U.combining(c)
+    if k: ccc[cp] = k
+    d = U.decomposition(c)
+    if d and not d.startswith('<'):
+        full = U.normalize('NFD', c)
+        decomp[cp] = [ord(x) for x in full]
+        parts = [int(x,16) for x in d.split()]
+        if len(parts) == 2 and U.normalize('NFC', chr(parts[0])+chr(parts[1])) == c:
+            comp[(parts[0], parts[1])] = cp
+# ccc ranges
+ranges=[]
+for cp in sorted(ccc):
+    if ranges and ranges[-1][1]+1==cp and ranges[-1][2]==ccc[cp]:
+        ranges[-1][1]=cp
+    else: ranges.append([cp,cp,ccc[cp]])
+out=[]
+out.append("// Generated by scripts/gen_unicode_tables.py from the Unicode Character Database, version %s.\n// Do not edit by hand.\n" % U.unidata_version)
+out.append("\n/// Canonical combining classes: `(first, last, class)`, sorted, non-zero only\npub(crate) static CCC: &[(u32, u32, u8)] = &[\n")
+for a,b,k in ranges: out.append("    (0x%04X, 0x%04X, %d),\n"%(a,b,k))
+out.append("];\n")
+# decomposition: flatten
+flat=[]; entries=[]
+for cp in sorted(decomp):
+    entries.append((cp,len(flat),len(decomp[cp]))); flat+=decomp[cp]
+out.append("\n/// Full canonical decompositions (excluding Hangul): `(code point, offset, len)` into `DECOMP_CHARS`\npub(crate) static DECOMP: &[(u32, u16, u8)] = &[\n")
+for cp,o,l in entries: out.append("    (0x%04X, %d, %d),\n"%(cp,o,l))
+out.append("];\n\npub(crate) static DECOMP_CHARS: &[u32] = &[\n")
+for i in range(0,len(flat),8): out.append("    "+", ".join("0x%04X"%x for x in flat[i:i+8])+",\n")
+out.append("];\n")
+out.append("\n/// Primary composites: `(starter, combining, composite)`, sorted by pair\npub(crate) static COMPOSE: &[(u32, u32, u32)] = &[\n")
+for (a,b),c in sorted(comp.items()): out.append("    (0x%04X, 0x%04X, 0x%04X),\n"%(a,b,c))
+out.append("];\n")
+open(sys.argv[1],'w').write("".join(out))
+print(len(ranges),len(entries),len(flat),len(comp))
@@ -0,0 +1,62 @@
+//! Colorized patch rendering (feature `ansi`)
+//!
+//! `render_diff_ansi` prints a `GlyphPatch` for a terminal, one block per op
+//! in the style of a unified diff: removed values in red, added values in
+//! green, changed paths in yellow with the old and new value below, and moves
+//! and copies in cyan. Plain SGR escapes, no extra dependencies.
+
+use crate::patch::{show, GlyphPatch, PatchOp};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// `patch` as colored lines, ending in a newline unless empty
+pub fn render_diff_ansi(patch: &GlyphPatch) -> String {
+    let mut out = String::new();
+    let mut line = |color: &str, text: String| {
+        out.push_str(color);
+        out.push_str(&text);
+        out.push_str(RESET);
+        out.push('\n');
+    };
+    for op in patch {
+        match op {
+            PatchOp::Change { path, old, new } => {
+                line(YELLOW, format!("~ {}", path));
+                line(RED, format!("-   {}", show(old)));
+                line(GREEN, format!("+   {}", show(new)));
+            }
+            PatchOp::Add { path, value } => line(GREEN, format!("+ {}: {}", path, show(value))),
+            PatchOp::Remove { path, old } => line(RED, format!("- {}: {}", path, show(old))),
+            PatchOp::Move { from, path } => line(CYAN, format!("> {} -> {}", from, path)),
+            PatchOp::Copy { from, path } => line(CYAN, format!("& {} -> {}", from, path)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch::diff;
+    use crate::types::*;
+
+    #[test]
+    fn test_render_diff_ansi() {
+        let before = GValue::build_map().str("query", "rust").bool("stale", true).finish();
+        let after = GValue::build_map().str("query", "go").int("limit", 5).finish();
+        let out = render_diff_ansi(&diff(&before, &after));
+        assert_eq!(
+            out,
+            "\x1b[33m~ query\x1b[0m\n\
+             \x1b[31m-   rust\x1b[0m\n\
+             \x1b[32m+   go\x1b[0m\n\
+             \x1b[31m- stale: t\x1b[0m\n\
+             \x1b[32m+ limit: 5\x1b[0m\n"
+        );
+        assert_eq!(render_diff_ansi(&GlyphPatch::default()), "");
+    }
+}
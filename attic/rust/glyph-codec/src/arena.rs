@@ -0,0 +1,377 @@
+//! Arena-allocated value trees
+//!
+//! Every string, key, list and map in a `GValue` is its own allocation, which
+//! adds up when a service builds millions of small values a second. A
+//! `GArena` keeps whole trees in a few flat buffers instead: nodes in one
+//! `Vec`, list items and map entries in two more, and all text in one
+//! `String`. Trees are built bottom-up, children first, and addressed by
+//! `NodeId`; `clear` drops them all but keeps the buffers, so one arena can
+//! serve request after request.
+//!
+//! `from_value` and `to_value` convert to and from the owned form, and
+//! `canonicalize_arena` encodes straight from the arena. Only lists that may
+//! become `@tab` blocks are converted to owned values on the way, as are
+//! whole trees under options that rewrite values (`limits`,
+//! `max_value_bytes_per_type`) or match paths (`tabular_paths`).
+
+use crate::error::GlyphError;
+use crate::loose::{CanonWriter, LooseCanonOpts};
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use std::ops::Range;
+
+/// A node in a `GArena`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(u32);
+
+/// What a `NodeId` refers to, borrowed from its arena
+#[derive(Debug, Clone, Copy)]
+pub enum ArenaRef<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    Time(DateTime<Utc>),
+    Id { prefix: &'a str, value: &'a str },
+    List(&'a [NodeId]),
+    Map(ArenaEntries<'a>),
+    Struct { type_name: &'a str, fields: ArenaEntries<'a> },
+    Sum { tag: &'a str, value: Option<NodeId> },
+    Table(&'a TableValue),
+}
+
+/// Map entries or struct fields of an arena node, in insertion order
+#[derive(Debug, Clone, Copy)]
+pub struct ArenaEntries<'a> {
+    arena: &'a GArena,
+    entries: &'a [(Range<usize>, NodeId)],
+}
+
+impl<'a> ArenaEntries<'a> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, NodeId)> + 'a {
+        let arena = self.arena;
+        self.entries.iter().map(move |(key, id)| (&arena.text[key.clone()], *id))
+    }
+
+    /// Value of the last entry named `key`
+    pub fn get(&self, key: &str) -> Option<NodeId> {
+        self.iter().filter(|(k, _)| *k == key).map(|(_, id)| id).last()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(Range<usize>),
+    Bytes(Range<usize>),
+    Time(DateTime<Utc>),
+    Id { prefix: Range<usize>, value: Range<usize> },
+    List(Range<usize>),
+    Map(Range<usize>),
+    Struct { type_name: Range<usize>, fields: Range<usize> },
+    Sum { tag: Range<usize>, value: Option<NodeId> },
+    Table(usize),
+}
+
+/// Flat storage for many value trees; see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct GArena {
+    nodes: Vec<Node>,
+    items: Vec<NodeId>,
+    entries: Vec<(Range<usize>, NodeId)>,
+    text: String,
+    bytes: Vec<u8>,
+    tables: Vec<TableValue>,
+}
+
+impl GArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Drop every node, keeping the buffers for reuse. Ids from before are
+    /// no longer valid.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.items.clear();
+        self.entries.clear();
+        self.text.clear();
+        self.bytes.clear();
+        self.tables.clear();
+    }
+
+    fn push(&mut self, node: Node) -> NodeId {
+        let id = u32::try_from(self.nodes.len()).expect("arena holds at most u32::MAX nodes");
+        self.nodes.push(node);
+        NodeId(id)
+    }
+
+    fn push_text(&mut self, s: &str) -> Range<usize> {
+        let start = self.text.len();
+        self.text.push_str(s);
+        start..self.text.len()
+    }
+
+    fn push_entries<K: AsRef<str>>(&mut self, entries: impl IntoIterator<Item = (K, NodeId)>) -> Range<usize> {
+        let start = self.entries.len();
+        for (key, id) in entries {
+            let key = self.push_text(key.as_ref());
+            self.entries.push((key, id));
+        }
+        start..self.entries.len()
+    }
+
+    pub fn null(&mut self) -> NodeId {
+        self.push(Node::Null)
+    }
+
+    pub fn bool(&mut self, b: bool) -> NodeId {
+        self.push(Node::Bool(b))
+    }
+
+    pub fn int(&mut self, n: i64) -> NodeId {
+        self.push(Node::Int(n))
+    }
+
+    pub fn float(&mut self, f: f64) -> NodeId {
+        self.push(Node::Float(f))
+    }
+
+    pub fn str(&mut self, s: &str) -> NodeId {
+        let text = self.push_text(s);
+        self.push(Node::Str(text))
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) -> NodeId {
+        let start = self.bytes.len();
+        self.bytes.extend_from_slice(data);
+        self.push(Node::Bytes(start..self.bytes.len()))
+    }
+
+    pub fn time(&mut self, t: DateTime<Utc>) -> NodeId {
+        self.push(Node::Time(t))
+    }
+
+    pub fn id(&mut self, prefix: &str, value: &str) -> NodeId {
+        let prefix = self.push_text(prefix);
+        let value = self.push_text(value);
+        self.push(Node::Id { prefix, value })
+    }
+
+    pub fn list(&mut self, items: impl IntoIterator<Item = NodeId>) -> NodeId {
+        let start = self.items.len();
+        self.items.extend(items);
+        self.push(Node::List(start..self.items.len()))
+    }
+
+    pub fn map<K: AsRef<str>>(&mut self, entries: impl IntoIterator<Item = (K, NodeId)>) -> NodeId {
+        let entries = self.push_entries(entries);
+        self.push(Node::Map(entries))
+    }
+
+    pub fn struct_val<K: AsRef<str>>(&mut self, type_name: &str, fields: impl IntoIterator<Item = (K, NodeId)>) -> NodeId {
+        let type_name = self.push_text(type_name);
+        let fields = self.push_entries(fields);
+        self.push(Node::Struct { type_name, fields })
+    }
+
+    pub fn sum(&mut self, tag: &str, value: Option<NodeId>) -> NodeId {
+        let tag = self.push_text(tag);
+        self.push(Node::Sum { tag, value })
+    }
+
+    /// A table, stored whole
+    pub fn table(&mut self, t: TableValue) -> NodeId {
+        self.tables.push(t);
+        self.push(Node::Table(self.tables.len() - 1))
+    }
+
+    /// The node `id`. Panics if `id` is from another arena or from before
+    /// `clear`.
+    pub fn get(&self, id: NodeId) -> ArenaRef<'_> {
+        let entries = |range: &Range<usize>| ArenaEntries {
+            arena: self,
+            entries: &self.entries[range.clone()],
+        };
+        match &self.nodes[id.0 as usize] {
+            Node::Null => ArenaRef::Null,
+            Node::Bool(b) => ArenaRef::Bool(*b),
+            Node::Int(n) => ArenaRef::Int(*n),
+            Node::Float(f) => ArenaRef::Float(*f),
+            Node::Str(s) => ArenaRef::Str(&self.text[s.clone()]),
+            Node::Bytes(b) => ArenaRef::Bytes(&self.bytes[b.clone()]),
+            Node::Time(t) => ArenaRef::Time(*t),
+            Node::Id { prefix, value } => ArenaRef::Id {
+                prefix: &self.text[prefix.clone()],
+                value: &self.text[value.clone()],
+            },
+            Node::List(items) => ArenaRef::List(&self.items[items.clone()]),
+            Node::Map(fields) => ArenaRef::Map(entries(fields)),
+            Node::Struct { type_name, fields } => ArenaRef::Struct {
+                type_name: &self.text[type_name.clone()],
+                fields: entries(fields),
+            },
+            Node::Sum { tag, value } => ArenaRef::Sum {
+                tag: &self.text[tag.clone()],
+                value: *value,
+            },
+            Node::Table(i) => ArenaRef::Table(&self.tables[*i]),
+        }
+    }
+
+    /// Copy `v` into the arena
+    pub fn from_value(&mut self, v: &GValue) -> NodeId {
+        match v {
+            GValue::Null => self.null(),
+            GValue::Bool(b) => self.bool(*b),
+            GValue::Int(n) => self.int(*n),
+            GValue::Float(f) => self.float(*f),
+            GValue::Str(s) => self.str(s),
+            GValue::Bytes(b) => self.bytes(b),
+            GValue::Time(t) => self.time(*t),
+            GValue::Id(r) => self.id(&r.prefix, &r.value),
+            GValue::List(items) => {
+                let ids: Vec<NodeId> = items.iter().map(|item| self.from_value(item)).collect();
+                self.list(ids)
+            }
+            GValue::Map(entries) => {
+                let ids: Vec<(&str, NodeId)> = entries.iter().map(|e| (e.key.as_str(), self.from_value(&e.value))).collect();
+                self.map(ids)
+            }
+            GValue::Struct(s) => {
+                let ids: Vec<(&str, NodeId)> = s.fields.iter().map(|e| (e.key.as_str(), self.from_value(&e.value))).collect();
+                self.struct_val(&s.type_name, ids)
+            }
+            GValue::Sum(s) => {
+                let value = s.value.as_deref().map(|inner| self.from_value(inner));
+                self.sum(&s.tag, value)
+            }
+            GValue::Table(t) => self.table(t.clone()),
+        }
+    }
+
+    /// The tree at `id` as an owned value
+    pub fn to_value(&self, id: NodeId) -> GValue {
+        let entries = |fields: ArenaEntries<'_>| fields.iter().map(|(k, v)| MapEntry::new(k, self.to_value(v))).collect();
+        match self.get(id) {
+            ArenaRef::Null => GValue::Null,
+            ArenaRef::Bool(b) => GValue::Bool(b),
+            ArenaRef::Int(n) => GValue::Int(n),
+            ArenaRef::Float(f) => GValue::Float(f),
+            ArenaRef::Str(s) => GValue::str(s),
+            ArenaRef::Bytes(b) => GValue::bytes(b.to_vec()),
+            ArenaRef::Time(t) => GValue::Time(t),
+            ArenaRef::Id { prefix, value } => GValue::id(prefix, value),
+            ArenaRef::List(items) => GValue::List(items.iter().map(|&item| self.to_value(item)).collect()),
+            ArenaRef::Map(fields) => GValue::Map(entries(fields)),
+            ArenaRef::Struct { type_name, fields } => GValue::struct_val(type_name, entries(fields)),
+            ArenaRef::Sum { tag, value } => GValue::sum(tag, value.map(|v| self.to_value(v))),
+            ArenaRef::Table(t) => GValue::Table(t.clone()),
+        }
+    }
+}
+
+/// Canonical text of the tree at `root`, as `canonicalize_loose_with_opts`
+/// would write `arena.to_value(root)`
+pub fn canonicalize_arena(arena: &GArena, root: NodeId, opts: &LooseCanonOpts) -> Result<String, GlyphError> {
+    let mut buf = String::new();
+    CanonWriter::new(opts).write_arena(&mut buf, arena, root)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use crate::loose::canonicalize_loose_with_opts;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn sample() -> GValue {
+        let mut v = from_json(&json!({
+            "query": "two words",
+            "ok": true,
+            "score": 0.25,
+            "missing": null,
+            "note": "tab\there \"quoted\" é",
+            "rows": [{"id": 1, "name": "a"}, {"id": 2, "name": "b"}, {"id": 3, "name": "c"}],
+            "pairs": [[1, 2], [3, 4], [5, 6]],
+            "mixed": [1, "x", {"k": []}],
+            "reserved": "true"
+        }));
+        if let GValue::Map(entries) = &mut v {
+            entries.push(MapEntry::new("owner", GValue::id("user", "42")));
+            entries.push(MapEntry::new("blob", GValue::bytes(vec![0, 1, 2])));
+            entries.push(MapEntry::new("at", GValue::time(Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap())));
+            entries.push(MapEntry::new("shape", GValue::sum("Circle", Some(GValue::float(1.5)))));
+            entries.push(MapEntry::new("pt", GValue::struct_val("Point", vec![MapEntry::new("y", GValue::int(2)), MapEntry::new("x", GValue::int(1))])));
+            entries.push(MapEntry::new(
+                "table",
+                GValue::Table(TableValue::new(vec!["b".to_string(), "a".to_string()], vec![vec![GValue::int(1), GValue::int(2)]])),
+            ));
+        }
+        v
+    }
+
+    #[test]
+    fn test_arena_roundtrip() {
+        let v = sample();
+        let mut arena = GArena::new();
+        let root = arena.from_value(&v);
+        assert_eq!(arena.to_value(root), v);
+        let ArenaRef::Map(fields) = arena.get(root) else {
+            panic!("root is a map");
+        };
+        assert!(matches!(fields.get("query").map(|id| arena.get(id)), Some(ArenaRef::Str("two words"))));
+
+        arena.clear();
+        assert!(arena.is_empty());
+        let name = arena.str("glyph");
+        let root = arena.map([("name", name)]);
+        assert_eq!(arena.to_value(root), GValue::map(vec![MapEntry::new("name", GValue::str("glyph"))]));
+    }
+
+    #[test]
+    fn test_canonicalize_arena_matches_owned() {
+        let v = sample();
+        let mut arena = GArena::new();
+        let root = arena.from_value(&v);
+        let tabular_lists = LooseCanonOpts { tabular_lists: true, ..LooseCanonOpts::default() };
+        for opts in [
+            LooseCanonOpts::default(),
+            LooseCanonOpts::no_tabular(),
+            LooseCanonOpts::llm(),
+            LooseCanonOpts::compact(),
+            tabular_lists,
+        ] {
+            assert_eq!(
+                canonicalize_arena(&arena, root, &opts).unwrap(),
+                canonicalize_loose_with_opts(&v, &opts).unwrap()
+            );
+        }
+        let nan = arena.float(f64::NAN);
+        assert!(canonicalize_arena(&arena, nan, &LooseCanonOpts::default()).is_err());
+    }
+}
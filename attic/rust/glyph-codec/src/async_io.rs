@@ -0,0 +1,121 @@
+//! GLYPH over tokio streams (feature `async`)
+//!
+//! `write_value_async` writes a value's canonical text and a newline to an
+//! `AsyncWrite`, so values sent one after another form a stream that an
+//! `AsyncDecoder` on the other end reads back one at a time as they arrive.
+//! Framing is the `StreamDecoder`'s: no length prefixes, just whitespace
+//! between values.
+
+use crate::decode::{StreamDecoder, READ_CHUNK};
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::types::GValue;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Write the canonical form of `v` under `opts` and a newline, then flush.
+///
+/// The text is built before anything is written, so an encoding error leaves
+/// `out` untouched; a failed write comes back as `GlyphError::Io`.
+pub async fn write_value_async<W: AsyncWrite + Unpin>(out: &mut W, v: &GValue, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
+    let mut text = canonicalize_loose_with_opts(v, opts)?;
+    text.push('\n');
+    out.write_all(text.as_bytes()).await?;
+    out.flush().await?;
+    Ok(())
+}
+
+/// Reads values from an `AsyncRead` as they complete; see the module docs.
+///
+/// `next_value` is cancel-safe: bytes already read stay buffered, so it can
+/// sit in a `tokio::select!` loop without losing input.
+pub struct AsyncDecoder<R> {
+    inner: R,
+    decoder: StreamDecoder,
+    chunk: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            decoder: StreamDecoder::new(),
+            chunk: vec![0; READ_CHUNK],
+        }
+    }
+
+    /// Fail on a value longer than `max` bytes instead of buffering it
+    pub fn with_max_len(mut self, max: usize) -> Self {
+        self.decoder = self.decoder.with_max_len(max);
+        self
+    }
+
+    /// The next value, reading until one is complete; `None` once the
+    /// reader is exhausted
+    pub async fn next_value(&mut self) -> Result<Option<GValue>, GlyphError> {
+        loop {
+            if let Some(v) = self.decoder.next_value()? {
+                return Ok(Some(v));
+            }
+            if self.decoder.is_finished() {
+                return Ok(None);
+            }
+            let n = self.inner.read(&mut self.chunk).await?;
+            if n == 0 {
+                self.decoder.finish();
+            } else {
+                self.decoder.push(&self.chunk[..n]);
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_loose;
+    use crate::types::*;
+
+    #[tokio::test]
+    async fn test_async_round_trip() {
+        let values = vec![
+            GValue::build_map().str("tool", "search").value("args", GValue::list(vec![GValue::int(1), GValue::str("two words")])).finish(),
+            GValue::int(7),
+            GValue::list((0..4).map(|i| GValue::build_map().int("id", i).str("name", "x y").finish()).collect()),
+            GValue::str("done"),
+        ];
+        let opts = LooseCanonOpts::default();
+        // A small pipe, so the writer blocks and values arrive in pieces
+        let (mut client, server) = tokio::io::duplex(16);
+        let sent = values.clone();
+        let writer = tokio::spawn(async move {
+            for v in &sent {
+                write_value_async(&mut client, v, &opts).await.unwrap();
+            }
+        });
+
+        let mut decoder = AsyncDecoder::new(server);
+        let mut got = Vec::new();
+        while let Some(v) = decoder.next_value().await.unwrap() {
+            got.push(v);
+        }
+        writer.await.unwrap();
+        let expected: Vec<GValue> =
+            values.iter().map(|v| parse_loose(&canonicalize_loose_with_opts(v, &LooseCanonOpts::default()).unwrap()).unwrap()).collect();
+        assert_eq!(got, expected);
+    }
+
+    #[tokio::test]
+    async fn test_async_decoder_errors() {
+        let mut decoder = AsyncDecoder::new(&b"{a=1} [1 2"[..]);
+        assert!(decoder.next_value().await.unwrap().is_some());
+        assert!(decoder.next_value().await.unwrap_err().to_string().contains("ended inside a value"));
+        assert_eq!(decoder.next_value().await.unwrap(), None);
+
+        let mut decoder = AsyncDecoder::new(&b"[1 2 3 4 5 6]"[..]).with_max_len(4);
+        assert!(matches!(decoder.next_value().await, Err(GlyphError::LimitExceeded(_))));
+    }
+}
@@ -0,0 +1,293 @@
+//! Large byte strings kept out of line
+//!
+//! A megabyte image inlined as base64 costs more tokens than the rest of
+//! the conversation. `externalize_blobs` moves every `Bytes` value at or
+//! over a size threshold into a `BlobStore`, keyed by its SHA-256, and leaves
+//! a placeholder in its place:
+//!
+//! ```text
+//! Blob{ref=^blob:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 size=1048576}
+//! ```
+//!
+//! `rehydrate_blobs` and `parse_with_blobs` put the bytes back, checking them
+//! against their hash. Backends mirror the value store's: in memory, or one
+//! file per blob.
+
+use crate::error::*;
+use crate::loose::{canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::parse::parse_loose;
+use crate::types::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Type name of a blob placeholder
+pub const BLOB_TYPE: &str = "Blob";
+
+/// Ref prefix of a blob placeholder
+pub const BLOB_PREFIX: &str = "blob";
+
+/// Default `externalize_blobs` threshold: 4 KiB
+pub const DEFAULT_BLOB_THRESHOLD: usize = 4096;
+
+/// Where externalized bytes live, keyed by the hex SHA-256 of their content
+pub trait BlobStore {
+    /// Bytes stored under `hash`, if present
+    fn read(&self, hash: &str) -> Result<Option<Vec<u8>>>;
+    /// Store `bytes` under `hash`; blobs are immutable, so an existing key may
+    /// be kept as is
+    fn write(&mut self, hash: &str, bytes: &[u8]) -> Result<()>;
+}
+
+/// Blobs held in a `HashMap`
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBlobStore {
+    blobs: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blobs.is_empty()
+    }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn read(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.blobs.get(hash).cloned())
+    }
+
+    fn write(&mut self, hash: &str, bytes: &[u8]) -> Result<()> {
+        self.blobs.entry(hash.to_string()).or_insert_with(|| bytes.to_vec());
+        Ok(())
+    }
+}
+
+/// One file per blob at `<root>/<first 2 hex>/<hex>.bin`
+#[derive(Debug, Clone)]
+pub struct FsBlobStore {
+    root: PathBuf,
+}
+
+impl FsBlobStore {
+    /// Use `root`, creating it if needed
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn blob_path(&self, hash: &str) -> Result<PathBuf> {
+        // Hashes come from placeholders in untrusted text
+        if hash.len() < 2 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(GlyphError::InvalidValue(format!("invalid blob hash {:?}", hash)));
+        }
+        Ok(self.root.join(&hash[..2]).join(format!("{}.bin", hash)))
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn read(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.blob_path(hash)?) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&mut self, hash: &str, bytes: &[u8]) -> Result<()> {
+        let path = self.blob_path(hash)?;
+        if path.is_file() {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename, so readers never see a partial blob
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+/// Hex SHA-256 of `bytes`, the key they are stored under
+pub fn blob_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().fold(String::with_capacity(64), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+/// `Blob{ref=^blob:<hash> size=N}` for `bytes`
+pub fn blob_placeholder(bytes: &[u8]) -> GValue {
+    GValue::struct_val(
+        BLOB_TYPE,
+        vec![
+            MapEntry::new("ref", GValue::id(BLOB_PREFIX, blob_hash(bytes))),
+            MapEntry::new("size", GValue::int(bytes.len() as i64)),
+        ],
+    )
+}
+
+/// Move every `Bytes` value of at least `threshold` bytes into `store`,
+/// leaving a `Blob{...}` placeholder; returns how many were moved
+pub fn externalize_blobs<S: BlobStore + ?Sized>(v: &mut GValue, store: &mut S, threshold: usize) -> Result<usize> {
+    let mut moved = 0;
+    for_each_node(v, &mut |node| {
+        if let GValue::Bytes(bytes) = node {
+            if bytes.len() >= threshold {
+                let placeholder = blob_placeholder(bytes);
+                store.write(&blob_hash(bytes), bytes)?;
+                *node = placeholder;
+                moved += 1;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    })?;
+    Ok(moved)
+}
+
+/// Canonical text of `v` with large byte strings moved into `store`
+pub fn canonicalize_with_blobs<S: BlobStore + ?Sized>(
+    v: &GValue,
+    opts: &LooseCanonOpts,
+    store: &mut S,
+    threshold: usize,
+) -> Result<String> {
+    let mut v = v.clone();
+    externalize_blobs(&mut v, store, threshold)?;
+    canonicalize_loose_with_opts(&v, opts)
+}
+
+/// Replace every `Blob{...}` placeholder with its bytes from `store`;
+/// returns how many were restored. Fails on a missing blob, or one whose
+/// content or size doesn't match its placeholder.
+pub fn rehydrate_blobs<S: BlobStore + ?Sized>(v: &mut GValue, store: &S) -> Result<usize> {
+    let mut restored = 0;
+    for_each_node(v, &mut |node| {
+        let Some((hash, size)) = placeholder_parts(node) else {
+            return Ok(false);
+        };
+        let bytes = store
+            .read(&hash)?
+            .ok_or_else(|| GlyphError::InvalidValue(format!("missing blob {}", hash)))?;
+        if blob_hash(&bytes) != hash || size.is_some_and(|n| n != bytes.len()) {
+            return Err(GlyphError::InvalidValue(format!("blob {} does not match its placeholder", hash)));
+        }
+        *node = GValue::Bytes(bytes);
+        restored += 1;
+        Ok(true)
+    })?;
+    Ok(restored)
+}
+
+/// `parse_loose`, then `rehydrate_blobs`
+pub fn parse_with_blobs<S: BlobStore + ?Sized>(text: &str, store: &S) -> Result<GValue> {
+    let mut v = parse_loose(text)?;
+    rehydrate_blobs(&mut v, store)?;
+    Ok(v)
+}
+
+/// Hash and size of a blob placeholder
+fn placeholder_parts(v: &GValue) -> Option<(String, Option<usize>)> {
+    let s = v.as_struct().filter(|s| s.type_name == BLOB_TYPE)?;
+    let hash = match v.get("ref")? {
+        GValue::Id(id) if id.prefix == BLOB_PREFIX => id.value.clone(),
+        _ => return None,
+    };
+    let size = s
+        .fields
+        .iter()
+        .find(|f| f.key == "size")
+        .and_then(|f| f.value.as_int())
+        .and_then(|n| usize::try_from(n).ok());
+    Some((hash, size))
+}
+
+/// Call `f` on every node, parents first; `f` returns whether it replaced
+/// the node, which is then not descended into
+fn for_each_node(v: &mut GValue, f: &mut dyn FnMut(&mut GValue) -> Result<bool>) -> Result<()> {
+    if f(v)? {
+        return Ok(());
+    }
+    match v {
+        GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+            entries.iter_mut().try_for_each(|e| for_each_node(&mut e.value, f))
+        }
+        GValue::List(items) => items.iter_mut().try_for_each(|item| for_each_node(item, f)),
+        GValue::Sum(s) => match &mut s.value {
+            Some(inner) => for_each_node(inner, f),
+            None => Ok(()),
+        },
+        GValue::Table(t) => t.rows.iter_mut().flatten().try_for_each(|cell| for_each_node(cell, f)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::canonicalize_loose;
+
+    fn payload() -> GValue {
+        GValue::map(vec![
+            MapEntry::new("icon", GValue::bytes(vec![1, 2, 3])),
+            MapEntry::new("image", GValue::bytes(vec![7; 5000])),
+        ])
+    }
+
+    #[test]
+    fn test_externalize_and_rehydrate() {
+        let mut store = MemoryBlobStore::new();
+        let text = canonicalize_with_blobs(&payload(), &LooseCanonOpts::default(), &mut store, DEFAULT_BLOB_THRESHOLD)
+            .unwrap();
+        let hash = blob_hash(&[7; 5000]);
+        assert_eq!(text, format!("{{icon=b64\"AQID\" image=Blob{{ref=^blob:{} size=5000}}}}", hash));
+        assert_eq!(store.len(), 1);
+        assert_eq!(parse_with_blobs(&text, &store).unwrap(), payload());
+
+        let mut missing = parse_loose(&text).unwrap();
+        assert!(matches!(
+            rehydrate_blobs(&mut missing, &MemoryBlobStore::new()),
+            Err(GlyphError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_fs_blob_store_checks_content() {
+        let dir = std::env::temp_dir().join(format!("glyph-blob-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut store = FsBlobStore::open(&dir).unwrap();
+        let mut v = payload();
+        assert_eq!(externalize_blobs(&mut v, &mut store, 4).unwrap(), 1);
+        let hash = blob_hash(&[7; 5000]);
+        assert!(dir.join(&hash[..2]).join(format!("{}.bin", hash)).is_file());
+
+        let mut back = v.clone();
+        assert_eq!(rehydrate_blobs(&mut back, &store).unwrap(), 1);
+        assert_eq!(back, payload());
+
+        fs::write(dir.join(&hash[..2]).join(format!("{}.bin", hash)), b"tampered").unwrap();
+        assert!(rehydrate_blobs(&mut v.clone(), &store).is_err());
+        assert!(store.read("../etc").is_err());
+        assert!(canonicalize_loose(&v).unwrap().contains("Blob{"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+//! Canonicalization to a size budget
+//!
+//! A tool result bound for a prompt has to fit the room left in the context
+//! window. `canonicalize_budgeted` canonicalizes a value and, while the text
+//! is over budget, cuts it down:
+//!
+//! 1. drops the low-priority paths in `BudgetOpts::drop_first`, one pattern
+//!    at a time, in order;
+//! 2. shortens long strings to `"prefix…+1180 bytes"` and replaces list tails
+//!    with a `"…+42 more"` item (a table with too many rows becomes its list
+//!    of row maps first), halving both limits each round until the text fits
+//!    or both reach their floor.
+//!
+//! Every cut is recorded as an `Elision` with its path, so callers can tell
+//! the model what it is not seeing or fetch it on request.
+
+use crate::corpus::estimate_tokens;
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::path::{GlyphPath, PathPattern};
+use crate::types::*;
+
+/// How much output is allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Bytes(usize),
+    /// Tokens as counted by `BudgetOpts::count_tokens`
+    Tokens(usize),
+}
+
+/// Options for `canonicalize_budgeted_with`
+#[derive(Debug, Clone)]
+pub struct BudgetOpts {
+    pub canon: LooseCanonOpts,
+    /// Paths to drop before anything is shortened, least important first
+    pub drop_first: Vec<PathPattern>,
+    /// Strings are never cut below this many bytes
+    pub min_string_bytes: usize,
+    /// Lists keep at least this many items
+    pub min_list_items: usize,
+    /// Token counter for `Budget::Tokens`; `estimate_tokens` by default, or
+    /// e.g. `|s| token_count(s, Tokenizer::O200k)` with the `tiktoken` feature
+    pub count_tokens: fn(&str) -> usize,
+}
+
+impl Default for BudgetOpts {
+    fn default() -> Self {
+        Self {
+            canon: LooseCanonOpts::default(),
+            drop_first: Vec::new(),
+            min_string_bytes: 16,
+            min_list_items: 1,
+            count_tokens: estimate_tokens,
+        }
+    }
+}
+
+/// What was cut at one path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElisionKind {
+    /// Removed from its map, struct or list
+    Dropped,
+    /// A string of `len` bytes cut to its first `kept`
+    Truncated { len: usize, kept: usize },
+    /// A list of `len` items cut to its first `kept`
+    Tail { len: usize, kept: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Elision {
+    pub path: GlyphPath,
+    pub kind: ElisionKind,
+}
+
+/// Output of `canonicalize_budgeted`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budgeted {
+    pub text: String,
+    /// Everything cut to get there, in document order
+    pub elisions: Vec<Elision>,
+    /// False when even the smallest form is over budget; `text` is then that
+    /// smallest form
+    pub fits: bool,
+}
+
+/// Canonical text of `v` cut down to `budget`, with default options
+pub fn canonicalize_budgeted(v: &GValue, budget: Budget) -> Result<Budgeted, GlyphError> {
+    canonicalize_budgeted_with(v, budget, &BudgetOpts::default())
+}
+
+/// Canonical text of `v` cut down to `budget`; see the module docs for the
+/// order things are cut in
+pub fn canonicalize_budgeted_with(v: &GValue, budget: Budget, opts: &BudgetOpts) -> Result<Budgeted, GlyphError> {
+    let fits = |text: &str| match budget {
+        Budget::Bytes(n) => text.len() <= n,
+        Budget::Tokens(n) => (opts.count_tokens)(text) <= n,
+    };
+
+    let mut value = v.clone();
+    let mut dropped = Vec::new();
+    let mut text = canonicalize_loose_with_opts(&value, &opts.canon)?;
+    for pattern in &opts.drop_first {
+        if fits(&text) {
+            break;
+        }
+        value = value.transform(|path, node| {
+            if !path.is_root() && pattern.matches(path) {
+                dropped.push(Elision {
+                    path: path.clone(),
+                    kind: ElisionKind::Dropped,
+                });
+                return None;
+            }
+            Some(node)
+        });
+        text = canonicalize_loose_with_opts(&value, &opts.canon)?;
+    }
+    if fits(&text) {
+        return Ok(Budgeted { text, elisions: dropped, fits: true });
+    }
+
+    let (longest_str, longest_list) = longest(&value);
+    let mut round = 1;
+    loop {
+        let caps = Caps {
+            string: (longest_str >> round).max(opts.min_string_bytes),
+            list: (longest_list >> round).max(opts.min_list_items),
+        };
+        let mut shrunk = value.clone();
+        let mut elisions = dropped.clone();
+        shrink(&mut shrunk, &mut GlyphPath::root(), caps, &mut elisions);
+        let text = canonicalize_loose_with_opts(&shrunk, &opts.canon)?;
+        let at_floor = caps.string == opts.min_string_bytes && caps.list == opts.min_list_items;
+        if fits(&text) || at_floor || round >= usize::BITS as usize {
+            return Ok(Budgeted {
+                fits: fits(&text),
+                text,
+                elisions,
+            });
+        }
+        round += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Caps {
+    string: usize,
+    list: usize,
+}
+
+/// Longest string in bytes and longest list in items
+fn longest(v: &GValue) -> (usize, usize) {
+    v.walk().fold((0, 0), |(s, l), (_, node)| match node {
+        GValue::Str(text) => (s.max(text.len()), l),
+        GValue::List(items) => (s, l.max(items.len())),
+        GValue::Table(t) => (s, l.max(t.rows.len())),
+        _ => (s, l),
+    })
+}
+
+fn shrink(v: &mut GValue, path: &mut GlyphPath, caps: Caps, out: &mut Vec<Elision>) {
+    match v {
+        GValue::Str(s) if s.len() > caps.string => {
+            let cut = char_floor(s, caps.string);
+            out.push(Elision {
+                path: path.clone(),
+                kind: ElisionKind::Truncated { len: s.len(), kept: cut },
+            });
+            *v = GValue::str(cut_string(s, cut));
+        }
+        GValue::List(items) => {
+            let len = items.len();
+            if len > caps.list {
+                out.push(Elision {
+                    path: path.clone(),
+                    kind: ElisionKind::Tail { len, kept: caps.list },
+                });
+                items.truncate(caps.list);
+            }
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push_index(i);
+                shrink(item, path, caps, out);
+                path.pop();
+            }
+            if len > caps.list {
+                items.push(more_marker(len - caps.list));
+            }
+        }
+        GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+            for e in entries {
+                path.push_key(e.key.clone());
+                shrink(&mut e.value, path, caps, out);
+                path.pop();
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(inner) = &mut s.value {
+                path.push_key(s.tag.clone());
+                shrink(inner, path, caps, out);
+                path.pop();
+            }
+        }
+        // Cut like the list of row maps it stands for
+        GValue::Table(t) if t.rows.len() > caps.list => shrink(v.expand_table(), path, caps, out),
+        GValue::Table(t) => {
+            for (i, row) in t.rows.iter_mut().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    shrink(cell, path, caps, out);
+                    path.pop();
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Largest char boundary in `s` at or below `max`
+pub(crate) fn char_floor(s: &str, max: usize) -> usize {
+    let mut cut = max.min(s.len());
+    while !s.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    cut
+}
+
+/// `s` cut to `cut` bytes, a char boundary, with a `…+N bytes` badge
+pub(crate) fn cut_string(s: &str, cut: usize) -> String {
+    format!("{}…+{} bytes", &s[..cut], s.len() - cut)
+}
+
+/// List item standing in for `n` elided items
+pub(crate) fn more_marker(n: usize) -> GValue {
+    GValue::str(format!("…+{} more", n))
+}
+
+/// Whether `s` is a `more_marker`
+pub(crate) fn is_more_marker(s: &str) -> bool {
+    s.strip_prefix("…+")
+        .and_then(|rest| rest.strip_suffix(" more"))
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use crate::loose::canonicalize_loose;
+    use serde_json::json;
+
+    fn result() -> GValue {
+        from_json(&json!({
+            "debug": {"trace": ["a", "b", "c"]},
+            "hits": (0..40).collect::<Vec<_>>(),
+            "summary": "word ".repeat(100),
+        }))
+    }
+
+    #[test]
+    fn test_fits_without_cuts() {
+        let v = result();
+        let full = canonicalize_loose(&v).unwrap();
+        let out = canonicalize_budgeted(&v, Budget::Bytes(full.len())).unwrap();
+        assert_eq!(out, Budgeted { text: full, elisions: vec![], fits: true });
+    }
+
+    #[test]
+    fn test_drops_then_shrinks() {
+        let opts = BudgetOpts {
+            drop_first: vec![PathPattern::parse("debug").unwrap()],
+            ..BudgetOpts::default()
+        };
+        let out = canonicalize_budgeted_with(&result(), Budget::Bytes(200), &opts).unwrap();
+        assert!(out.fits);
+        assert!(out.text.len() <= 200, "{}", out.text);
+        assert!(!out.text.contains("debug"));
+        assert!(out.text.contains(" more\"]"), "{}", out.text);
+        assert!(out.text.contains(" bytes\"}"), "{}", out.text);
+        let kinds: Vec<_> = out.elisions.iter().map(|e| (e.path.to_string(), e.kind)).collect();
+        assert_eq!(kinds[0], ("debug".to_string(), ElisionKind::Dropped));
+        assert!(matches!(kinds[1], (ref p, ElisionKind::Tail { len: 40, .. }) if p == "hits"));
+        assert!(matches!(kinds[2], (ref p, ElisionKind::Truncated { len: 500, .. }) if p == "summary"));
+
+        let tokens = canonicalize_budgeted(&result(), Budget::Tokens(50)).unwrap();
+        assert!(tokens.fits && estimate_tokens(&tokens.text) <= 50);
+    }
+
+    #[test]
+    fn test_shrinks_parsed_tables() {
+        let rows = from_json(&json!({
+            "rows": (0..1000).map(|i| json!({"id": i, "ok": true})).collect::<Vec<_>>(),
+        }));
+        let parsed = crate::parse::parse_loose_with(&canonicalize_loose(&rows).unwrap(), crate::parse::ParseOpts { tables: true }).unwrap();
+        assert!(parsed.get("rows").unwrap().is_table());
+
+        let out = canonicalize_budgeted(&parsed, Budget::Bytes(300)).unwrap();
+        assert!(out.fits, "{}", out.text);
+        assert_eq!(out, canonicalize_budgeted(&rows, Budget::Bytes(300)).unwrap());
+        assert!(matches!(out.elisions[0].kind, ElisionKind::Tail { len: 1000, .. }));
+        assert_eq!(out.elisions[0].path.to_string(), "rows");
+    }
+
+    #[test]
+    fn test_reports_when_budget_is_unreachable() {
+        let v = GValue::str("é".repeat(40));
+        let out = canonicalize_budgeted(&v, Budget::Bytes(4)).unwrap();
+        assert!(!out.fits);
+        // Cut on a char boundary at or under the 16-byte floor
+        assert_eq!(out.text, format!("\"{}…+64 bytes\"", "é".repeat(8)));
+        assert_eq!(out.elisions[0].kind, ElisionKind::Truncated { len: 80, kept: 16 });
+    }
+}
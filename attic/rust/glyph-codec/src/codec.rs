@@ -0,0 +1,158 @@
+//! `tokio_util` codec for framed streams (feature `codec`)
+//!
+//! `GlyphCodec` frames values as GLYPH Lines records, one per line, so a
+//! `Framed` TCP or Unix socket carries values between agent components with
+//! the usual `Sink` / `Stream` back-pressure. What it writes can be read by a
+//! `LinesReader` and the other way round.
+
+use crate::error::GlyphError;
+use crate::lines::{decode_line, encode_line};
+use crate::loose::LooseCanonOpts;
+use crate::types::GValue;
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Encodes and decodes values as GLYPH Lines records; see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct GlyphCodec {
+    opts: LooseCanonOpts,
+    max_len: Option<usize>,
+    /// Bytes of the pending record already searched for a newline
+    scanned: usize,
+}
+
+impl GlyphCodec {
+    /// Records are encoded under `LooseCanonOpts::default()`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_opts(opts: &LooseCanonOpts) -> Self {
+        Self {
+            opts: opts.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Fail on a record longer than `max` bytes instead of buffering it
+    pub fn with_max_len(mut self, max: usize) -> Self {
+        self.max_len = Some(max);
+        self
+    }
+
+    fn decode_record(record: &[u8]) -> Result<Option<GValue>, GlyphError> {
+        let record = record.strip_suffix(b"\r").unwrap_or(record);
+        if record.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        let text = std::str::from_utf8(record).map_err(|e| GlyphError::Parse(format!("invalid UTF-8 in record: {}", e)))?;
+        decode_line(text).map(Some)
+    }
+}
+
+impl Decoder for GlyphCodec {
+    type Item = GValue;
+    type Error = GlyphError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<GValue>, GlyphError> {
+        loop {
+            let Some(offset) = src[self.scanned..].iter().position(|&b| b == b'\n') else {
+                self.scanned = src.len();
+                if let Some(max) = self.max_len.filter(|&max| src.len() > max) {
+                    src.clear();
+                    self.scanned = 0;
+                    return Err(GlyphError::LimitExceeded(format!("record over max_len {} bytes", max)));
+                }
+                return Ok(None);
+            };
+            let end = self.scanned + offset;
+            self.scanned = 0;
+            let line = src.split_to(end + 1);
+            if let Some(max) = self.max_len.filter(|&max| end > max) {
+                return Err(GlyphError::LimitExceeded(format!("record over max_len {} bytes", max)));
+            }
+            if let Some(v) = Self::decode_record(&line[..end])? {
+                return Ok(Some(v));
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<GValue>, GlyphError> {
+        if let Some(v) = self.decode(src)? {
+            return Ok(Some(v));
+        }
+        // A last record without its newline
+        self.scanned = 0;
+        let rest = src.split();
+        Self::decode_record(&rest)
+    }
+}
+
+impl Encoder<&GValue> for GlyphCodec {
+    type Error = GlyphError;
+
+    fn encode(&mut self, v: &GValue, dst: &mut BytesMut) -> Result<(), GlyphError> {
+        let line = encode_line(v, &self.opts)?;
+        dst.reserve(line.len() + 1);
+        dst.put_slice(line.as_bytes());
+        dst.put_u8(b'\n');
+        Ok(())
+    }
+}
+
+impl Encoder<GValue> for GlyphCodec {
+    type Error = GlyphError;
+
+    fn encode(&mut self, v: GValue, dst: &mut BytesMut) -> Result<(), GlyphError> {
+        self.encode(&v, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    #[tokio::test]
+    async fn test_framed_round_trip() {
+        let table = GValue::Table(TableValue::new(vec!["id".to_string()], vec![vec![GValue::int(1)], vec![GValue::int(2)]]));
+        let values = vec![
+            GValue::build_map().str("query", "two words").str("tool", "search").finish(),
+            table,
+            GValue::str("multi\nline"),
+            GValue::int(-4),
+        ];
+        // A small pipe, so sends wait on the reader
+        let (client, server) = tokio::io::duplex(8);
+        let sent = values.clone();
+        let writer = tokio::spawn(async move {
+            let mut sink = FramedWrite::new(client, GlyphCodec::new());
+            for v in sent {
+                sink.send(v).await.unwrap();
+            }
+        });
+        let got: Vec<GValue> = FramedRead::new(server, GlyphCodec::new()).map(Result::unwrap).collect().await;
+        writer.await.unwrap();
+        // The table reads back as its row list
+        let mut expected = values;
+        expected[1] = expected[1].as_table().unwrap().to_list();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_decoder_partial_and_limits() {
+        let mut codec = GlyphCodec::new().with_max_len(16);
+        let mut buf = BytesMut::from(&b"{a=1}\n\n[1 "[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(GValue::map(vec![MapEntry::new("a", GValue::int(1))])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"2]");
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), Some(GValue::list(vec![GValue::int(1), GValue::int(2)])));
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"[1 2 3 4 5 6 7 8 9]");
+        assert!(matches!(codec.decode(&mut buf), Err(GlyphError::LimitExceeded(_))));
+        assert!(buf.is_empty());
+    }
+}
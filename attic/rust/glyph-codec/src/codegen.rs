@@ -0,0 +1,607 @@
+//! Rust code generation from schemas
+//!
+//! `generate_rust(&schema)` emits one serde-derived Rust type per schema
+//! type: records become structs, sums become enums whose variants carry the
+//! payload. serde's default enum layout matches GLYPH sums, so
+//! `to_gvalue_with(&args, SerOpts::typed())` on a generated value comes out
+//! in the shape the schema describes.
+//!
+//! From a build script, `generate_rust_file` turns a schema file into a
+//! source file and only rewrites it when the output changed:
+//!
+//! ```ignore
+//! // build.rs
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("tools.rs");
+//! glyph_rs::generate_rust_file("schemas/tools.glyph", &out).unwrap();
+//! println!("cargo:rerun-if-changed=schemas/tools.glyph");
+//!
+//! // lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/tools.rs"));
+//! ```
+//!
+//! For a web frontend reading the same values as JSON, `generate_typescript`
+//! emits matching `.d.ts` declarations and `generate_zod` adds zod validators.
+
+use crate::error::GlyphError;
+use crate::json_schema::pascal_case;
+use crate::schema::*;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "// Generated by glyph-rs from a GLYPH schema. Do not edit.\n";
+const DERIVES: &str = "#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]\n";
+
+/// Rust source declaring every type in `schema`, in name order
+pub fn generate_rust(schema: &Schema) -> String {
+    let mut out = String::from(HEADER);
+    for def in schema.types() {
+        let rust_name = pascal_case(&def.name);
+        match def.kind {
+            TypeKind::Struct(ref fields) => {
+                let mut queue = VecDeque::from([(def.name.clone(), rust_name, fields.clone())]);
+                while let Some((name, rust_name, fields)) = queue.pop_front() {
+                    write_struct(&mut out, &name, &rust_name, &fields, &mut queue);
+                }
+            }
+            TypeKind::Sum(ref variants) => write_enum(&mut out, &def.name, &rust_name, variants),
+        }
+    }
+    out
+}
+
+/// Generate Rust for the schema file at `schema_path` into `out_path`,
+/// leaving it untouched when unchanged so cargo doesn't rebuild. Returns
+/// whether the file was written.
+pub fn generate_rust_file(schema_path: impl AsRef<Path>, out_path: impl AsRef<Path>) -> Result<bool, GlyphError> {
+    let schema = Schema::parse(&fs::read_to_string(schema_path)?)?;
+    let code = generate_rust(&schema);
+    let out_path = out_path.as_ref();
+    if fs::read_to_string(out_path).is_ok_and(|old| old == code) {
+        return Ok(false);
+    }
+    fs::write(out_path, code)?;
+    Ok(true)
+}
+
+fn write_struct(
+    out: &mut String,
+    name: &str,
+    rust_name: &str,
+    fields: &[FieldDef],
+    inline: &mut VecDeque<(String, String, Vec<FieldDef>)>,
+) {
+    out.push('\n');
+    out.push_str(DERIVES);
+    if rust_name != name {
+        let _ = writeln!(out, "#[serde(rename = \"{}\")]", name);
+    }
+    let _ = writeln!(out, "pub struct {} {{", rust_name);
+    for f in fields {
+        let mut notes: Vec<String> = f.constraints.iter().map(|c| format!("[{}]", c)).collect();
+        if let Some(ref v) = f.default {
+            notes.push(format!("default {}", v));
+        }
+        if !notes.is_empty() {
+            let _ = writeln!(out, "    /// {}", notes.join(" "));
+        }
+
+        let ident = field_ident(&f.name);
+        let mut attrs = Vec::new();
+        if ident.trim_start_matches("r#") != f.name {
+            attrs.push(format!("rename = \"{}\"", f.name));
+        }
+        if let Some(ref k) = f.wire_key {
+            attrs.push(format!("alias = \"{}\"", k));
+        }
+        if f.optional {
+            attrs.push("default, skip_serializing_if = \"Option::is_none\"".to_string());
+        }
+        if !attrs.is_empty() {
+            let _ = writeln!(out, "    #[serde({})]", attrs.join(", "));
+        }
+
+        let ty = match f.ty {
+            TypeSpec::Struct(ref nested) => {
+                let nested_name = format!("{}{}", rust_name, pascal_case(&f.name));
+                inline.push_back((nested_name.clone(), nested_name.clone(), nested.clone()));
+                nested_name
+            }
+            ref ty => rust_type(ty, name, true),
+        };
+        let ty = if f.optional { format!("Option<{}>", ty) } else { ty };
+        let _ = writeln!(out, "    pub {}: {},", ident, ty);
+    }
+    out.push_str("}\n");
+}
+
+fn write_enum(out: &mut String, name: &str, rust_name: &str, variants: &[VariantDef]) {
+    out.push('\n');
+    out.push_str(DERIVES);
+    if rust_name != name {
+        let _ = writeln!(out, "#[serde(rename = \"{}\")]", name);
+    }
+    let _ = writeln!(out, "pub enum {} {{", rust_name);
+    for v in variants {
+        let tag = pascal_case(&v.tag);
+        if tag != v.tag {
+            let _ = writeln!(out, "    #[serde(rename = \"{}\")]", v.tag);
+        }
+        match v.ty {
+            TypeSpec::Null => {
+                let _ = writeln!(out, "    {},", tag);
+            }
+            ref ty => {
+                let _ = writeln!(out, "    {}({}),", tag, rust_type(ty, name, true));
+            }
+        }
+    }
+    out.push_str("}\n");
+}
+
+/// Rust type for `ty` inside the type `owner`; a direct reference back to
+/// the owner is boxed so the type has a size
+fn rust_type(ty: &TypeSpec, owner: &str, direct: bool) -> String {
+    match ty {
+        TypeSpec::Null => "()".to_string(),
+        TypeSpec::Bool => "bool".to_string(),
+        TypeSpec::Int => "i64".to_string(),
+        TypeSpec::Float => "f64".to_string(),
+        TypeSpec::Str | TypeSpec::Id => "String".to_string(),
+        TypeSpec::Bytes => "Vec<u8>".to_string(),
+        TypeSpec::Time => "chrono::DateTime<chrono::Utc>".to_string(),
+        TypeSpec::List(elem) => format!("Vec<{}>", rust_type(elem, owner, false)),
+        TypeSpec::Map(key, value) => format!(
+            "std::collections::BTreeMap<{}, {}>",
+            if **key == TypeSpec::Int { "i64" } else { "String" },
+            rust_type(value, owner, false)
+        ),
+        TypeSpec::Ref(name) if direct && name == owner => format!("Box<{}>", pascal_case(name)),
+        TypeSpec::Ref(name) => pascal_case(name),
+        // Inline records below the top of a field have no name to give them
+        TypeSpec::Struct(_) => "std::collections::BTreeMap<String, serde_json::Value>".to_string(),
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct",
+    "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen",
+    "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// `dryRun` / `dry-run` -> `dry_run`; keywords become raw identifiers
+fn field_ident(name: &str) -> String {
+    let mut ident = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if prev_lower {
+                ident.push('_');
+            }
+            ident.extend(c.to_lowercase());
+            prev_lower = false;
+        } else if c.is_alphanumeric() || c == '_' {
+            ident.push(c);
+            prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        } else {
+            ident.push('_');
+            prev_lower = false;
+        }
+    }
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    match ident.as_str() {
+        "self" | "super" | "crate" => format!("{}_", ident),
+        kw if KEYWORDS.contains(&kw) => format!("r#{}", ident),
+        _ => ident,
+    }
+}
+
+// ============================================================
+// TypeScript
+// ============================================================
+
+/// TypeScript declarations (`.d.ts`) for the JSON form of every type in
+/// `schema`, as `to_json` writes it: records are interfaces (with the
+/// optional `_type` tag structs carry), sums are unions over `_tag`/`_value`
+pub fn generate_typescript(schema: &Schema) -> String {
+    let mut out = String::from(HEADER);
+    for def in schema.types() {
+        write_ts_decl(&mut out, def);
+    }
+    out
+}
+
+/// The TypeScript declarations plus a zod validator per type, named
+/// `<Type>Schema` and typed against the matching declaration
+pub fn generate_zod(schema: &Schema) -> String {
+    let mut out = String::from(HEADER);
+    out.push_str("import { z } from \"zod\";\n");
+    for def in schema.types() {
+        write_ts_decl(&mut out, def);
+    }
+    for def in schema.types() {
+        let name = pascal_case(&def.name);
+        let body = match def.kind {
+            TypeKind::Struct(ref fields) => {
+                let mut obj = zod_object(fields, 0);
+                // Only the struct form names its type
+                obj.insert_str(obj.len() - 2, &format!("  _type: z.literal({}).optional(),\n", js_string(&def.name)));
+                format!("{}.{}()", obj, if def.open { "passthrough" } else { "strict" })
+            }
+            TypeKind::Sum(ref variants) => {
+                let options: Vec<_> = variants
+                    .iter()
+                    .map(|v| match v.ty {
+                        TypeSpec::Null => format!("  z.object({{ _tag: z.literal({}) }}),", js_string(&v.tag)),
+                        ref ty => format!(
+                            "  z.object({{ _tag: z.literal({}), _value: {} }}),",
+                            js_string(&v.tag),
+                            zod_type(ty, &[], 1)
+                        ),
+                    })
+                    .collect();
+                format!("z.discriminatedUnion(\"_tag\", [\n{}\n])", options.join("\n"))
+            }
+        };
+        let _ = write!(out, "\nexport const {}Schema: z.ZodType<{}> = {};\n", name, name, body);
+    }
+    out
+}
+
+fn write_ts_decl(out: &mut String, def: &TypeDef) {
+    let name = pascal_case(&def.name);
+    out.push('\n');
+    match def.kind {
+        TypeKind::Struct(ref fields) => {
+            let _ = writeln!(out, "export interface {} {{", name);
+            for f in fields {
+                if !f.constraints.is_empty() {
+                    let notes: Vec<_> = f.constraints.iter().map(|c| format!("[{}]", c)).collect();
+                    let _ = writeln!(out, "  /** {} */", notes.join(" "));
+                }
+                let _ = writeln!(out, "  {};", ts_field(f, 1));
+            }
+            let _ = writeln!(out, "  _type?: {};", js_string(&def.name));
+            out.push_str("}\n");
+        }
+        TypeKind::Sum(ref variants) => {
+            let _ = writeln!(out, "export type {} =", name);
+            for v in variants {
+                match v.ty {
+                    TypeSpec::Null => {
+                        let _ = writeln!(out, "  | {{ _tag: {} }}", js_string(&v.tag));
+                    }
+                    ref ty => {
+                        let _ = writeln!(out, "  | {{ _tag: {}; _value: {} }}", js_string(&v.tag), ts_type(ty, &[], 1));
+                    }
+                }
+            }
+            // Close the union
+            out.pop();
+            out.push_str(";\n");
+        }
+    }
+}
+
+fn ts_field(f: &FieldDef, depth: usize) -> String {
+    let ty = ts_type(&f.ty, &f.constraints, depth);
+    if f.optional {
+        format!("{}?: {} | null", js_key(&f.name), ty)
+    } else {
+        format!("{}: {}", js_key(&f.name), ty)
+    }
+}
+
+fn ts_type(ty: &TypeSpec, constraints: &[Constraint], depth: usize) -> String {
+    match ty {
+        TypeSpec::Null => "null".to_string(),
+        TypeSpec::Bool => "boolean".to_string(),
+        TypeSpec::Int | TypeSpec::Float => "number".to_string(),
+        TypeSpec::Str => match enum_values(constraints) {
+            Some(values) => values.iter().map(|v| js_string(v)).collect::<Vec<_>>().join(" | "),
+            None => "string".to_string(),
+        },
+        // Bytes are base64, times RFC 3339, ids `^prefix:value`
+        TypeSpec::Bytes | TypeSpec::Time | TypeSpec::Id => "string".to_string(),
+        TypeSpec::List(elem) => {
+            let elem = ts_type(elem, &[], depth);
+            if elem.contains(' ') {
+                format!("Array<{}>", elem)
+            } else {
+                format!("{}[]", elem)
+            }
+        }
+        TypeSpec::Map(_, value) => format!("Record<string, {}>", ts_type(value, &[], depth)),
+        TypeSpec::Ref(name) => pascal_case(name),
+        TypeSpec::Struct(fields) => {
+            let fields: Vec<_> = fields.iter().map(|f| ts_field(f, depth + 1)).collect();
+            format!("{{ {} }}", fields.join("; "))
+        }
+    }
+}
+
+/// zod schema for `ty` with the constraints that apply to it
+fn zod_type(ty: &TypeSpec, constraints: &[Constraint], depth: usize) -> String {
+    let mut z = match ty {
+        TypeSpec::Null => "z.null()".to_string(),
+        TypeSpec::Bool => "z.boolean()".to_string(),
+        TypeSpec::Int => "z.number().int()".to_string(),
+        TypeSpec::Float => "z.number()".to_string(),
+        TypeSpec::Str => match enum_values(constraints) {
+            Some(values) => {
+                let values: Vec<_> = values.iter().map(|v| js_string(v)).collect();
+                return format!("z.enum([{}])", values.join(", "));
+            }
+            None => "z.string()".to_string(),
+        },
+        TypeSpec::Bytes | TypeSpec::Id => "z.string()".to_string(),
+        TypeSpec::Time => "z.string().datetime({ offset: true })".to_string(),
+        TypeSpec::List(elem) => format!("z.array({})", zod_type(elem, &[], depth)),
+        TypeSpec::Map(_, value) => format!("z.record(z.string(), {})", zod_type(value, &[], depth)),
+        TypeSpec::Ref(name) => format!("z.lazy(() => {}Schema)", pascal_case(name)),
+        TypeSpec::Struct(fields) => format!("{}.strict()", zod_object(fields, depth + 1)),
+    };
+    let numeric = matches!(ty, TypeSpec::Int | TypeSpec::Float);
+    let sized = matches!(ty, TypeSpec::Str | TypeSpec::Id | TypeSpec::Bytes | TypeSpec::List(_));
+    for c in constraints {
+        match c {
+            Constraint::Min(n) if numeric => z += &format!(".min({})", num(*n)),
+            Constraint::Max(n) if numeric => z += &format!(".max({})", num(*n)),
+            Constraint::Range(lo, hi) if numeric => z += &format!(".min({}).max({})", num(*lo), num(*hi)),
+            Constraint::MinLen(n) if sized => z += &format!(".min({})", n),
+            Constraint::MaxLen(n) if sized => z += &format!(".max({})", n),
+            Constraint::Len(n) if sized => z += &format!(".length({})", n),
+            Constraint::NonEmpty if sized => z += ".min(1)",
+            Constraint::Regex(re) if matches!(ty, TypeSpec::Str) => {
+                z += &format!(".regex(new RegExp({}))", js_string(re))
+            }
+            Constraint::Unique if matches!(ty, TypeSpec::List(_)) => {
+                z += ".refine((xs) => new Set(xs.map((x) => JSON.stringify(x))).size === xs.length, \"items must be unique\")"
+            }
+            _ => {}
+        }
+    }
+    z
+}
+
+fn zod_object(fields: &[FieldDef], depth: usize) -> String {
+    let pad = "  ".repeat(depth + 1);
+    let mut out = String::from("z.object({\n");
+    for f in fields {
+        let mut z = zod_type(&f.ty, &f.constraints, depth);
+        if f.optional {
+            z.push_str(".nullish()");
+        }
+        let _ = writeln!(out, "{}{}: {},", pad, js_key(&f.name), z);
+    }
+    out.push_str(&"  ".repeat(depth));
+    out.push_str("})");
+    out
+}
+
+fn enum_values(constraints: &[Constraint]) -> Option<&[String]> {
+    constraints.iter().find_map(|c| match c {
+        Constraint::Enum(values) => Some(values.as_slice()),
+        _ => None,
+    })
+}
+
+/// Whole bounds print as integers
+fn num(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+fn js_string(s: &str) -> String {
+    serde_json::to_string(s).expect("strings always serialize")
+}
+
+/// Property name, quoted unless it's a plain identifier
+fn js_key(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if plain {
+        name.to_string()
+    } else {
+        js_string(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_rust() {
+        let schema = Schema::parse(
+            "@schema{
+              Call struct{
+                tool: str [enum=[search fetch]]
+                query: str [len>=1] @k(q)
+                limit: int [1..50] @default(10) [optional]
+                since: time [optional]
+                tags: list<str>
+                meta: map<str,float> [optional]
+                at: Point
+                parent: Call [optional]
+              }
+              Point struct{ x: int y: int }
+              Shape sum{ Circle: float | Poly: list<Point> | Empty: null }
+            }",
+        )
+        .unwrap();
+        let expected = r#"// Generated by glyph-rs from a GLYPH schema. Do not edit.
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Call {
+    /// [enum=[search fetch]]
+    pub tool: String,
+    /// [len>=1]
+    #[serde(alias = "q")]
+    pub query: String,
+    /// [1..50] default 10
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<std::collections::BTreeMap<String, f64>>,
+    pub at: Point,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<Call>>,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Shape {
+    Circle(f64),
+    Poly(Vec<Point>),
+    Empty,
+}
+"#;
+        assert_eq!(generate_rust(&schema), expected);
+    }
+
+    #[test]
+    fn test_names_and_inline_records() {
+        let schema = Schema::parse(
+            "tool_args struct{ type: str, dryRun: bool, self: int, 2fa: str, pos: struct{x: float} } \
+             outcome sum{ ok_result: str }",
+        )
+        .unwrap();
+        let code = generate_rust(&schema);
+        for line in [
+            "#[serde(rename = \"outcome\")]\npub enum Outcome {",
+            "    #[serde(rename = \"ok_result\")]\n    OkResult(String),",
+            "#[serde(rename = \"tool_args\")]\npub struct ToolArgs {",
+            "    pub r#type: String,",
+            "    #[serde(rename = \"dryRun\")]\n    pub dry_run: bool,",
+            "    #[serde(rename = \"self\")]\n    pub self_: i64,",
+            "    #[serde(rename = \"2fa\")]\n    pub _2fa: String,",
+            "    pub pos: ToolArgsPos,",
+            "pub struct ToolArgsPos {\n    pub x: f64,\n}",
+        ] {
+            assert!(code.contains(line), "missing {:?} in\n{}", line, code);
+        }
+    }
+
+    fn web_schema() -> Schema {
+        Schema::parse(
+            "@schema{
+              Result struct{
+                title: str [len>=1]
+                status: str [enum=[todo done]]
+                score: float [0..1] [optional]
+                tags: list<str> [unique]
+                at: time
+                \"dry-run\": bool [optional]
+                pos: struct{x: int, y: int [optional]}
+                next: Result [optional]
+              }
+              Shape @open struct{ kind: str [regex=\"^[a-z]+$\"] }
+              Outcome sum{ Ok: list<Result> | Err: str | Pending: null }
+            }",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_generate_typescript() {
+        let expected = r#"// Generated by glyph-rs from a GLYPH schema. Do not edit.
+
+export type Outcome =
+  | { _tag: "Ok"; _value: Result[] }
+  | { _tag: "Err"; _value: string }
+  | { _tag: "Pending" };
+
+export interface Result {
+  /** [len>=1] */
+  title: string;
+  /** [enum=[todo done]] */
+  status: "todo" | "done";
+  /** [0..1] */
+  score?: number | null;
+  /** [unique] */
+  tags: string[];
+  at: string;
+  "dry-run"?: boolean | null;
+  pos: { x: number; y?: number | null };
+  next?: Result | null;
+  _type?: "Result";
+}
+
+export interface Shape {
+  /** [regex="^[a-z]+$"] */
+  kind: string;
+  _type?: "Shape";
+}
+"#;
+        assert_eq!(generate_typescript(&web_schema()), expected);
+    }
+
+    #[test]
+    fn test_generate_zod() {
+        let code = generate_zod(&web_schema());
+        assert!(code.starts_with(&format!("{}import {{ z }} from \"zod\";\n\nexport type Outcome =", HEADER)));
+        let expected = r#"
+export const OutcomeSchema: z.ZodType<Outcome> = z.discriminatedUnion("_tag", [
+  z.object({ _tag: z.literal("Ok"), _value: z.array(z.lazy(() => ResultSchema)) }),
+  z.object({ _tag: z.literal("Err"), _value: z.string() }),
+  z.object({ _tag: z.literal("Pending") }),
+]);
+
+export const ResultSchema: z.ZodType<Result> = z.object({
+  title: z.string().min(1),
+  status: z.enum(["todo", "done"]),
+  score: z.number().min(0).max(1).nullish(),
+  tags: z.array(z.string()).refine((xs) => new Set(xs.map((x) => JSON.stringify(x))).size === xs.length, "items must be unique"),
+  at: z.string().datetime({ offset: true }),
+  "dry-run": z.boolean().nullish(),
+  pos: z.object({
+    x: z.number().int(),
+    y: z.number().int().nullish(),
+  }).strict(),
+  next: z.lazy(() => ResultSchema).nullish(),
+  _type: z.literal("Result").optional(),
+}).strict();
+
+export const ShapeSchema: z.ZodType<Shape> = z.object({
+  kind: z.string().regex(new RegExp("^[a-z]+$")),
+  _type: z.literal("Shape").optional(),
+}).passthrough();
+"#;
+        assert!(code.ends_with(expected), "{}", code);
+    }
+
+    #[test]
+    fn test_generate_rust_file() {
+        let dir = std::env::temp_dir().join(format!("glyph-codegen-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let schema_path = dir.join("tools.glyph");
+        let out_path = dir.join("tools.rs");
+        fs::write(&schema_path, "@schema{ Point struct{ x: int y: int } }").unwrap();
+
+        assert!(generate_rust_file(&schema_path, &out_path).unwrap());
+        assert!(fs::read_to_string(&out_path).unwrap().contains("pub struct Point {"));
+        // Unchanged output isn't rewritten
+        assert!(!generate_rust_file(&schema_path, &out_path).unwrap());
+
+        fs::write(&schema_path, "@schema{ Point struct{ x: int }").unwrap();
+        assert!(matches!(generate_rust_file(&schema_path, &out_path), Err(GlyphError::Parse(_))));
+        assert!(matches!(generate_rust_file(dir.join("missing.glyph"), &out_path), Err(GlyphError::Io(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
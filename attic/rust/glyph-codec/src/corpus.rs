@@ -0,0 +1,553 @@
+//! Corpus measurements
+//!
+//! Load a directory of `.json` / `.glyph` fixtures, run encode, parse and hash
+//! over every payload, and report sizes, estimated tokens and timings as a
+//! `GValue`. Saved reports serve as baselines for catching size regressions
+//! across releases.
+//!
+//! `savings_report` does the same for a single payload, against pretty and
+//! compact JSON and with and without `@tab`, broken down by top-level key.
+
+use crate::document::parse_document;
+use crate::error::*;
+use crate::json_bridge::{parse_json, stringify_json, stringify_json_pretty};
+use crate::loose::*;
+use crate::parse::parse_loose;
+use crate::types::*;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Rough LLM token count: about 4 bytes per token (same heuristic as Go's `EstimateTokens`)
+pub fn estimate_tokens(s: &str) -> usize {
+    s.len().div_ceil(4)
+}
+
+/// One payload in a corpus
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusEntry {
+    pub name: String,
+    pub value: GValue,
+}
+
+/// A set of named payloads to measure
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    pub entries: Vec<CorpusEntry>,
+}
+
+impl Corpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a payload
+    pub fn add(&mut self, name: impl Into<String>, value: GValue) {
+        self.entries.push(CorpusEntry {
+            name: name.into(),
+            value,
+        });
+    }
+
+    /// Load every `.json` and `.glyph` file directly under `dir`, sorted by file name.
+    ///
+    /// `.glyph` files may carry a `!glyph/1` header. Other files are ignored.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::result::Result<_, _>>()?;
+        paths.sort();
+
+        let mut corpus = Self::new();
+        for path in paths {
+            let ext = path.extension().and_then(|e| e.to_str());
+            if !path.is_file() || !matches!(ext, Some("json") | Some("glyph")) {
+                continue;
+            }
+            let text = fs::read_to_string(&path)?;
+            let value = match ext {
+                Some("json") => parse_json(&text),
+                _ => parse_document(&text).map(|(_, v)| v),
+            }
+            .map_err(|e| GlyphError::Parse(format!("{}: {}", path.display(), e)))?;
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            corpus.add(name, value);
+        }
+        Ok(corpus)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encode, parse and hash every entry with `opts`
+    pub fn run(&self, opts: &LooseCanonOpts) -> Result<CorpusReport> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|e| measure(e, opts))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(CorpusReport { entries })
+    }
+}
+
+/// Measurements for one entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryStats {
+    pub name: String,
+    pub json_bytes: usize,
+    pub glyph_bytes: usize,
+    pub json_tokens: usize,
+    pub glyph_tokens: usize,
+    pub encode: Duration,
+    pub parse: Duration,
+    pub hash: Duration,
+    /// Parsing the GLYPH output gave back an equal value
+    pub roundtrip: bool,
+}
+
+fn measure(entry: &CorpusEntry, opts: &LooseCanonOpts) -> Result<EntryStats> {
+    let json = stringify_json(&entry.value);
+
+    let start = Instant::now();
+    let glyph = canonicalize_loose_with_opts(&entry.value, opts)?;
+    let encode = start.elapsed();
+
+    let start = Instant::now();
+    let parsed = parse_loose(&glyph);
+    let parse = start.elapsed();
+
+    let start = Instant::now();
+    hash_loose(&entry.value)?;
+    let hash = start.elapsed();
+
+    let roundtrip = match parsed {
+        Ok(parsed) => equal_loose(&parsed, &entry.value)?,
+        Err(_) => false,
+    };
+
+    Ok(EntryStats {
+        name: entry.name.clone(),
+        json_bytes: json.len(),
+        glyph_bytes: glyph.len(),
+        json_tokens: estimate_tokens(&json),
+        glyph_tokens: estimate_tokens(&glyph),
+        encode,
+        parse,
+        hash,
+        roundtrip,
+    })
+}
+
+/// An entry whose GLYPH output grew past the allowed tolerance
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeRegression {
+    pub name: String,
+    pub baseline_bytes: usize,
+    pub glyph_bytes: usize,
+}
+
+/// Result of `Corpus::run`
+#[derive(Debug, Clone, Default)]
+pub struct CorpusReport {
+    pub entries: Vec<EntryStats>,
+}
+
+impl CorpusReport {
+    pub fn json_bytes(&self) -> usize {
+        self.entries.iter().map(|e| e.json_bytes).sum()
+    }
+
+    pub fn glyph_bytes(&self) -> usize {
+        self.entries.iter().map(|e| e.glyph_bytes).sum()
+    }
+
+    pub fn json_tokens(&self) -> usize {
+        self.entries.iter().map(|e| e.json_tokens).sum()
+    }
+
+    pub fn glyph_tokens(&self) -> usize {
+        self.entries.iter().map(|e| e.glyph_tokens).sum()
+    }
+
+    /// Fraction of JSON bytes saved, e.g. `0.4` for 40%
+    pub fn byte_savings(&self) -> f64 {
+        savings(self.json_bytes(), self.glyph_bytes())
+    }
+
+    /// Fraction of estimated JSON tokens saved
+    pub fn token_savings(&self) -> f64 {
+        savings(self.json_tokens(), self.glyph_tokens())
+    }
+
+    /// The report as a GValue, suitable for emitting and saving as a baseline.
+    ///
+    /// Timings are in microseconds.
+    pub fn to_gvalue(&self) -> GValue {
+        let micros = |d: Duration| GValue::int(d.as_micros() as i64);
+        let total = |f: fn(&EntryStats) -> Duration| micros(self.entries.iter().map(f).sum());
+        let entries = self
+            .entries
+            .iter()
+            .map(|e| {
+                GValue::build_map()
+                    .str("name", e.name.as_str())
+                    .int("json_bytes", e.json_bytes as i64)
+                    .int("glyph_bytes", e.glyph_bytes as i64)
+                    .int("json_tokens", e.json_tokens as i64)
+                    .int("glyph_tokens", e.glyph_tokens as i64)
+                    .value("encode_us", micros(e.encode))
+                    .value("parse_us", micros(e.parse))
+                    .value("hash_us", micros(e.hash))
+                    .bool("roundtrip", e.roundtrip)
+                    .finish()
+            })
+            .collect::<Vec<_>>();
+        let totals = GValue::build_map()
+            .int("files", self.entries.len() as i64)
+            .int("json_bytes", self.json_bytes() as i64)
+            .int("glyph_bytes", self.glyph_bytes() as i64)
+            .int("json_tokens", self.json_tokens() as i64)
+            .int("glyph_tokens", self.glyph_tokens() as i64)
+            .float("byte_savings", round4(self.byte_savings()))
+            .float("token_savings", round4(self.token_savings()))
+            .value("encode_us", total(|e| e.encode))
+            .value("parse_us", total(|e| e.parse))
+            .value("hash_us", total(|e| e.hash))
+            .int("roundtrip_failures", self.entries.iter().filter(|e| !e.roundtrip).count() as i64);
+        GValue::build_map()
+            .str("spec", canon_spec_version())
+            .value("entries", entries)
+            .value("totals", totals)
+            .finish()
+    }
+
+    /// Entries whose GLYPH size exceeds the same-named entry in `baseline`
+    /// (a saved `to_gvalue` report) by more than `tolerance` (e.g. `0.05`).
+    ///
+    /// Entries missing from the baseline are not reported. The entries may be
+    /// a list or, for a baseline read back with `ParseOpts::tables`, a table.
+    pub fn size_regressions(&self, baseline: &GValue, tolerance: f64) -> Vec<SizeRegression> {
+        let entries = baseline.get("entries").map(|e| match e {
+            GValue::Table(t) => Cow::Owned(t.to_list()),
+            other => Cow::Borrowed(other),
+        });
+        let base = entries.as_deref().and_then(GValue::as_list).unwrap_or(&[]);
+        self.entries
+            .iter()
+            .filter_map(|e| {
+                let before = base
+                    .iter()
+                    .find(|b| b.get("name").and_then(|n| n.as_str()) == Some(e.name.as_str()))?
+                    .get("glyph_bytes")?
+                    .as_int()? as usize;
+                (e.glyph_bytes as f64 > before as f64 * (1.0 + tolerance)).then(|| SizeRegression {
+                    name: e.name.clone(),
+                    baseline_bytes: before,
+                    glyph_bytes: e.glyph_bytes,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Bytes and tokens of one encoding of a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodedSize {
+    pub bytes: usize,
+    pub tokens: usize,
+}
+
+/// One value's size in each encoding `savings_report` compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FormatSizes {
+    pub json_pretty: EncodedSize,
+    pub json_compact: EncodedSize,
+    /// Canonical GLYPH without `@tab` blocks
+    pub glyph: EncodedSize,
+    /// Canonical GLYPH with eligible lists as `@tab` (the default form)
+    pub glyph_tabular: EncodedSize,
+}
+
+impl FormatSizes {
+    fn measure(v: &GValue, count: &dyn Fn(&str) -> usize) -> Result<Self> {
+        let size = |text: &str| EncodedSize {
+            bytes: text.len(),
+            tokens: count(text),
+        };
+        Ok(Self {
+            json_pretty: size(&stringify_json_pretty(v)),
+            json_compact: size(&stringify_json(v)),
+            glyph: size(&canonicalize_loose_no_tabular(v)?),
+            glyph_tabular: size(&canonicalize_loose(v)?),
+        })
+    }
+
+    /// Fraction of compact JSON bytes tabular GLYPH saves
+    pub fn byte_savings(&self) -> f64 {
+        savings(self.json_compact.bytes, self.glyph_tabular.bytes)
+    }
+
+    /// Fraction of compact JSON tokens tabular GLYPH saves
+    pub fn token_savings(&self) -> f64 {
+        savings(self.json_compact.tokens, self.glyph_tabular.tokens)
+    }
+
+    fn to_gvalue(self) -> GValue {
+        let size = |s: EncodedSize| {
+            GValue::build_map()
+                .int("bytes", s.bytes as i64)
+                .int("tokens", s.tokens as i64)
+                .finish()
+        };
+        GValue::build_map()
+            .value("json_pretty", size(self.json_pretty))
+            .value("json_compact", size(self.json_compact))
+            .value("glyph", size(self.glyph))
+            .value("glyph_tabular", size(self.glyph_tabular))
+            .float("byte_savings", round4(self.byte_savings()))
+            .float("token_savings", round4(self.token_savings()))
+            .finish()
+    }
+}
+
+/// Sizes of one top-level field's value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySavings {
+    pub key: String,
+    pub sizes: FormatSizes,
+}
+
+/// Result of `savings_report`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SavingsReport {
+    pub total: FormatSizes,
+    /// Each field of a top-level map or struct, in its order; empty for
+    /// other values
+    pub keys: Vec<KeySavings>,
+}
+
+impl SavingsReport {
+    /// The report as a GValue, in the shape of `CorpusReport::to_gvalue`
+    pub fn to_gvalue(&self) -> GValue {
+        let keys = self
+            .keys
+            .iter()
+            .map(|k| {
+                let mut entry = k.sizes.to_gvalue();
+                let _ = entry.insert("key", GValue::str(k.key.as_str()));
+                entry
+            })
+            .collect::<Vec<_>>();
+        GValue::build_map()
+            .str("spec", canon_spec_version())
+            .value("keys", keys)
+            .value("totals", self.total.to_gvalue())
+            .finish()
+    }
+}
+
+/// A fixed-width table, one row per key and a total, for pasting into a
+/// write-up
+impl fmt::Display for SavingsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.keys.iter().map(|k| k.key.chars().count()).max().unwrap_or(0).max(5);
+        writeln!(
+            f,
+            "{:<width$}  {:>17}  {:>17}  {:>17}  {:>17}  {:>7}",
+            "key", "json pretty", "json compact", "glyph", "glyph @tab", "saved",
+        )?;
+        let row = |f: &mut fmt::Formatter<'_>, key: &str, s: &FormatSizes| {
+            let cell = |e: EncodedSize| format!("{}B/{}t", e.bytes, e.tokens);
+            writeln!(
+                f,
+                "{:<width$}  {:>17}  {:>17}  {:>17}  {:>17}  {:>6.1}%",
+                key,
+                cell(s.json_pretty),
+                cell(s.json_compact),
+                cell(s.glyph),
+                cell(s.glyph_tabular),
+                s.token_savings() * 100.0,
+            )
+        };
+        for k in &self.keys {
+            row(f, &k.key, &k.sizes)?;
+        }
+        row(f, "total", &self.total)
+    }
+}
+
+/// Bytes and estimated tokens of `v` as pretty JSON, compact JSON and GLYPH
+/// with and without `@tab`, overall and per top-level key
+pub fn savings_report(v: &GValue) -> Result<SavingsReport> {
+    savings_report_with(v, &estimate_tokens)
+}
+
+/// `savings_report` counting tokens with `count`, such as a real tokenizer
+pub fn savings_report_with(v: &GValue, count: &dyn Fn(&str) -> usize) -> Result<SavingsReport> {
+    let fields = match v {
+        GValue::Map(entries) => entries.as_slice(),
+        GValue::Struct(s) => s.fields.as_slice(),
+        _ => &[],
+    };
+    let keys = fields
+        .iter()
+        .map(|e| {
+            Ok(KeySavings {
+                key: e.key.to_string(),
+                sizes: FormatSizes::measure(&e.value, count)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(SavingsReport {
+        total: FormatSizes::measure(v, count)?,
+        keys,
+    })
+}
+
+fn savings(before: usize, after: usize) -> f64 {
+    if before == 0 {
+        return 0.0;
+    }
+    (before as f64 - after as f64) / before as f64
+}
+
+fn round4(x: f64) -> f64 {
+    (x * 10_000.0).round() / 10_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn rows(n: i64) -> GValue {
+        GValue::list(
+            (1..=n)
+                .map(|i| GValue::build_map().int("id", i).str("name", "item").finish())
+                .collect(),
+        )
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glyph-corpus-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("a"), 1);
+        assert_eq!(estimate_tokens("hello world"), 3);
+    }
+
+    #[test]
+    fn test_load_dir_and_report() {
+        let dir = scratch_dir("load");
+        fs::write(dir.join("b.json"), r#"[{"id":1,"name":"item"},{"id":2,"name":"item"},{"id":3,"name":"item"}]"#)
+            .unwrap();
+        fs::write(dir.join("a.glyph"), "!glyph/1 spec=glyph-loose-1.0.0\n{x=1}").unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let corpus = Corpus::load_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<_> = corpus.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.glyph", "b.json"]);
+
+        let report = corpus.run(&LooseCanonOpts::default()).unwrap();
+        assert!(report.entries.iter().all(|e| e.roundtrip));
+        assert!(report.glyph_bytes() < report.json_bytes());
+        assert!(report.byte_savings() > 0.0);
+
+        let g = report.to_gvalue();
+        assert_eq!(g.get("spec").and_then(|s| s.as_str()), Some(canon_spec_version()));
+        let totals = g.get("totals").unwrap();
+        assert_eq!(totals.get("files").and_then(|v| v.as_int()), Some(2));
+        assert_eq!(totals.get("roundtrip_failures").and_then(|v| v.as_int()), Some(0));
+        // The report itself canonicalizes and parses back
+        let text = canonicalize_loose(&g).unwrap();
+        assert!(equal_loose(&parse_loose(&text).unwrap(), &g).unwrap());
+    }
+
+    #[test]
+    fn test_load_dir_errors() {
+        assert!(matches!(Corpus::load_dir("/nonexistent/glyph-corpus"), Err(GlyphError::Io(_))));
+        let dir = scratch_dir("bad");
+        fs::write(dir.join("bad.json"), "{").unwrap();
+        let err = Corpus::load_dir(&dir).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(err.to_string().contains("bad.json"), "{}", err);
+    }
+
+    #[test]
+    fn test_size_regressions_against_baseline() {
+        let mut corpus = Corpus::new();
+        corpus.add("rows", rows(10));
+        let baseline = corpus.run(&LooseCanonOpts::default()).unwrap().to_gvalue();
+
+        // Same options: nothing regresses
+        let report = corpus.run(&LooseCanonOpts::default()).unwrap();
+        assert!(report.size_regressions(&baseline, 0.0).is_empty());
+
+        // Disabling tabular grows the output
+        let report = corpus.run(&LooseCanonOpts::no_tabular()).unwrap();
+        let regressions = report.size_regressions(&baseline, 0.0);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].name, "rows");
+        assert!(regressions[0].glyph_bytes > regressions[0].baseline_bytes);
+        assert!(report.size_regressions(&baseline, 10.0).is_empty());
+    }
+
+    #[test]
+    fn test_size_regressions_against_saved_baseline() {
+        let mut corpus = Corpus::new();
+        for name in ["a", "b", "c"] {
+            corpus.add(name, rows(10));
+        }
+        let saved = canonicalize_loose(&corpus.run(&LooseCanonOpts::default()).unwrap().to_gvalue()).unwrap();
+        assert!(saved.contains("entries=@tab"), "{}", saved);
+
+        let report = corpus.run(&LooseCanonOpts::no_tabular()).unwrap();
+        for tables in [false, true] {
+            let baseline = crate::parse::parse_loose_with(&saved, crate::parse::ParseOpts { tables }).unwrap();
+            assert_eq!(baseline.get("entries").unwrap().is_table(), tables);
+            assert_eq!(report.size_regressions(&baseline, 0.0).len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_savings_report() {
+        let v = GValue::build_map()
+            .str("query", "glyph codec")
+            .value("results", rows(20))
+            .finish();
+        let report = savings_report(&v).unwrap();
+        let keys: Vec<_> = report.keys.iter().map(|k| k.key.as_str()).collect();
+        assert_eq!(keys, ["query", "results"]);
+
+        let t = report.total;
+        assert!(t.json_pretty.bytes > t.json_compact.bytes);
+        assert!(t.json_compact.bytes > t.glyph.bytes);
+        assert!(t.glyph.bytes > t.glyph_tabular.bytes);
+        assert_eq!(t.glyph_tabular.tokens, estimate_tokens(&canonicalize_loose(&v).unwrap()));
+        assert!(report.keys[1].sizes.byte_savings() > report.keys[0].sizes.byte_savings());
+
+        let bytes = savings_report_with(&v, &|s: &str| s.len()).unwrap();
+        assert_eq!(bytes.total.json_compact.tokens, bytes.total.json_compact.bytes);
+
+        let g = report.to_gvalue();
+        assert_eq!(g["keys"][1]["key"], GValue::str("results"));
+        assert_eq!(g["totals"]["glyph_tabular"]["bytes"], GValue::int(t.glyph_tabular.bytes as i64));
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.lines().last().unwrap().starts_with("total"));
+    }
+}
@@ -0,0 +1,254 @@
+//! Borrowed value trees for one-shot encoding
+//!
+//! Turning an application struct into a `GValue` copies every string and
+//! byte buffer it holds, only for the copy to be written out and dropped.
+//! A `GValueCow<'a>` has the same shape but keeps text, keys and bytes as
+//! `Cow`s, so a conversion can borrow from the struct and only allocate for
+//! what it computes. An existing `GValue` can be embedded as is with
+//! `GValueCow::Value`.
+//!
+//! `canonicalize_cow` writes the same text as `canonicalize_loose_with_opts`
+//! on `to_value()`. As with `canonicalize_arena`, lists that may become
+//! `@tab` blocks are converted to owned values on the way, as are whole trees
+//! under `limits`, `max_value_bytes_per_type` or `tabular_paths`.
+
+use crate::error::GlyphError;
+use crate::loose::{CanonWriter, LooseCanonOpts};
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+
+/// A value whose text and bytes may be borrowed; see the module docs
+#[derive(Debug, Clone, PartialEq)]
+pub enum GValueCow<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(Cow<'a, str>),
+    Bytes(Cow<'a, [u8]>),
+    Time(DateTime<Utc>),
+    Id { prefix: Cow<'a, str>, value: Cow<'a, str> },
+    List(Vec<GValueCow<'a>>),
+    /// Entries in insertion order
+    Map(Vec<(Cow<'a, str>, GValueCow<'a>)>),
+    Struct {
+        type_name: Cow<'a, str>,
+        fields: Vec<(Cow<'a, str>, GValueCow<'a>)>,
+    },
+    Sum { tag: Cow<'a, str>, value: Option<Box<GValueCow<'a>>> },
+    /// An owned value, borrowed whole
+    Value(&'a GValue),
+}
+
+impl<'a> GValueCow<'a> {
+    pub fn str(s: impl Into<Cow<'a, str>>) -> Self {
+        GValueCow::Str(s.into())
+    }
+
+    pub fn bytes(data: impl Into<Cow<'a, [u8]>>) -> Self {
+        GValueCow::Bytes(data.into())
+    }
+
+    pub fn id(prefix: impl Into<Cow<'a, str>>, value: impl Into<Cow<'a, str>>) -> Self {
+        GValueCow::Id {
+            prefix: prefix.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn list(items: impl IntoIterator<Item = GValueCow<'a>>) -> Self {
+        GValueCow::List(items.into_iter().collect())
+    }
+
+    pub fn map<K: Into<Cow<'a, str>>>(entries: impl IntoIterator<Item = (K, GValueCow<'a>)>) -> Self {
+        GValueCow::Map(entries.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    pub fn struct_val<K: Into<Cow<'a, str>>>(
+        type_name: impl Into<Cow<'a, str>>,
+        fields: impl IntoIterator<Item = (K, GValueCow<'a>)>,
+    ) -> Self {
+        GValueCow::Struct {
+            type_name: type_name.into(),
+            fields: fields.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+        }
+    }
+
+    pub fn sum(tag: impl Into<Cow<'a, str>>, value: Option<GValueCow<'a>>) -> Self {
+        GValueCow::Sum {
+            tag: tag.into(),
+            value: value.map(Box::new),
+        }
+    }
+
+    /// Whether this is a map or struct, directly or through `Value`
+    pub(crate) fn is_record(&self) -> bool {
+        match self {
+            GValueCow::Map(_) | GValueCow::Struct { .. } => true,
+            GValueCow::Value(v) => v.is_map() || matches!(v, GValue::Struct(_)),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_list(&self) -> bool {
+        match self {
+            GValueCow::List(_) => true,
+            GValueCow::Value(v) => v.is_list(),
+            _ => false,
+        }
+    }
+
+    /// An owned copy
+    pub fn to_value(&self) -> GValue {
+        let entries = |fields: &[(Cow<'a, str>, GValueCow<'a>)]| -> Vec<MapEntry> {
+            fields.iter().map(|(k, v)| MapEntry::new(k.as_ref(), v.to_value())).collect()
+        };
+        match self {
+            GValueCow::Null => GValue::Null,
+            GValueCow::Bool(b) => GValue::Bool(*b),
+            GValueCow::Int(n) => GValue::Int(*n),
+            GValueCow::Float(f) => GValue::Float(*f),
+            GValueCow::Str(s) => GValue::str(s.as_ref()),
+            GValueCow::Bytes(b) => GValue::bytes(b.to_vec()),
+            GValueCow::Time(t) => GValue::Time(*t),
+            GValueCow::Id { prefix, value } => GValue::id(prefix.as_ref(), value.as_ref()),
+            GValueCow::List(items) => GValue::List(items.iter().map(GValueCow::to_value).collect()),
+            GValueCow::Map(fields) => GValue::Map(entries(fields)),
+            GValueCow::Struct { type_name, fields } => GValue::struct_val(type_name.as_ref(), entries(fields)),
+            GValueCow::Sum { tag, value } => GValue::sum(tag.as_ref(), value.as_ref().map(|v| v.to_value())),
+            GValueCow::Value(v) => (*v).clone(),
+        }
+    }
+
+    /// The owned form, reusing any owned strings and buffers
+    pub fn into_value(self) -> GValue {
+        let entries = |fields: Vec<(Cow<'a, str>, GValueCow<'a>)>| -> Vec<MapEntry> {
+            fields.into_iter().map(|(k, v)| MapEntry::new(k.into_owned(), v.into_value())).collect()
+        };
+        match self {
+            GValueCow::Str(s) => GValue::str(s.into_owned()),
+            GValueCow::Bytes(b) => GValue::bytes(b.into_owned()),
+            GValueCow::Id { prefix, value } => GValue::id(prefix.into_owned(), value.into_owned()),
+            GValueCow::List(items) => GValue::List(items.into_iter().map(GValueCow::into_value).collect()),
+            GValueCow::Map(fields) => GValue::Map(entries(fields)),
+            GValueCow::Struct { type_name, fields } => GValue::struct_val(type_name.into_owned(), entries(fields)),
+            GValueCow::Sum { tag, value } => GValue::sum(tag.into_owned(), value.map(|v| v.into_value())),
+            other => other.to_value(),
+        }
+    }
+}
+
+impl From<bool> for GValueCow<'_> {
+    fn from(b: bool) -> Self {
+        GValueCow::Bool(b)
+    }
+}
+
+impl From<i64> for GValueCow<'_> {
+    fn from(n: i64) -> Self {
+        GValueCow::Int(n)
+    }
+}
+
+impl From<f64> for GValueCow<'_> {
+    fn from(f: f64) -> Self {
+        GValueCow::Float(f)
+    }
+}
+
+impl<'a> From<&'a str> for GValueCow<'a> {
+    fn from(s: &'a str) -> Self {
+        GValueCow::Str(Cow::Borrowed(s))
+    }
+}
+
+impl From<String> for GValueCow<'_> {
+    fn from(s: String) -> Self {
+        GValueCow::Str(Cow::Owned(s))
+    }
+}
+
+impl<'a> From<&'a GValue> for GValueCow<'a> {
+    fn from(v: &'a GValue) -> Self {
+        GValueCow::Value(v)
+    }
+}
+
+impl<'a, T: Into<GValueCow<'a>>> From<Option<T>> for GValueCow<'a> {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(GValueCow::Null, Into::into)
+    }
+}
+
+/// Canonical text of `v`, as `canonicalize_loose_with_opts` would write
+/// `v.to_value()`
+pub fn canonicalize_cow(v: &GValueCow<'_>, opts: &LooseCanonOpts) -> Result<String, GlyphError> {
+    let mut buf = String::new();
+    CanonWriter::new(opts).write_cow(&mut buf, v)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::canonicalize_loose_with_opts;
+    use chrono::TimeZone;
+
+    struct User {
+        name: String,
+        email: Option<String>,
+        avatar: Vec<u8>,
+        tags: Vec<String>,
+    }
+
+    fn user_value(u: &User) -> GValueCow<'_> {
+        GValueCow::struct_val(
+            "User",
+            [
+                ("name", GValueCow::str(u.name.as_str())),
+                ("email", u.email.as_deref().into()),
+                ("avatar", GValueCow::bytes(u.avatar.as_slice())),
+                ("tags", GValueCow::list(u.tags.iter().map(|t| GValueCow::str(t.as_str())))),
+                ("initial", GValueCow::str(u.name.chars().take(1).collect::<String>())),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_canonicalize_cow_matches_owned() {
+        let users: Vec<User> = (0..3)
+            .map(|i| User {
+                name: format!("user {}", i),
+                email: (i != 1).then(|| format!("u{}@example.com", i)),
+                avatar: vec![i as u8; 4],
+                tags: vec!["a".to_string(), "true".to_string()],
+            })
+            .collect();
+        let embedded = GValue::map(vec![MapEntry::new("k", GValue::int(1))]);
+        let v = GValueCow::map([
+            ("users", GValueCow::list(users.iter().map(user_value))),
+            ("first", user_value(&users[0])),
+            ("owner", GValueCow::id("user", "42")),
+            ("at", GValueCow::Time(Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap())),
+            ("shape", GValueCow::sum("Circle", Some(1.5.into()))),
+            ("pairs", GValueCow::list([GValueCow::list([1.into(), 2.into()]), GValueCow::list([3.into(), 4.into()])])),
+            ("embedded", (&embedded).into()),
+        ]);
+        assert!(matches!(&v, GValueCow::Map(fields) if matches!(&fields[1].1, GValueCow::Struct { fields, .. }
+            if matches!(fields[0].1, GValueCow::Str(Cow::Borrowed(_))))));
+        let owned = v.to_value();
+        let tabular_lists = LooseCanonOpts { tabular_lists: true, min_rows: 2, ..LooseCanonOpts::default() };
+        for opts in [
+            LooseCanonOpts::default(),
+            LooseCanonOpts::no_tabular(),
+            LooseCanonOpts::llm(),
+            LooseCanonOpts::compact(),
+            tabular_lists,
+        ] {
+            assert_eq!(canonicalize_cow(&v, &opts).unwrap(), canonicalize_loose_with_opts(&owned, &opts).unwrap());
+        }
+        assert_eq!(v.into_value(), owned);
+        assert!(canonicalize_cow(&GValueCow::Float(f64::NAN), &LooseCanonOpts::default()).is_err());
+    }
+}
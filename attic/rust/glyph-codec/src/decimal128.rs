@@ -39,9 +39,7 @@ impl Decimal128 {
 
         // Sign extend for negative numbers
         let fill = if value < 0 { 0xFF } else { 0x00 };
-        for i in 0..8 {
-            coef[i] = fill;
-        }
+        coef[..8].fill(fill);
         coef[8..16].copy_from_slice(&bytes);
 
         Self { scale: 0, coef }
@@ -55,10 +53,9 @@ impl Decimal128 {
         let s = s.strip_suffix('m').unwrap_or(s);
 
         // Check for negative
-        let (negative, s) = if s.starts_with('-') {
-            (true, &s[1..])
-        } else {
-            (false, s)
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
         };
 
         // Split by decimal point
@@ -276,7 +273,7 @@ impl Mul for Decimal128 {
         let result = c1.checked_mul(c2).ok_or(DecimalError::Overflow)?;
 
         let new_scale = self.scale as i16 + other.scale as i16;
-        if new_scale > 127 || new_scale < -127 {
+        if !(-127..=127).contains(&new_scale) {
             return Err(DecimalError::ScaleOverflow);
         }
 
@@ -300,7 +297,7 @@ impl Div for Decimal128 {
         let result = c1.checked_div(c2).ok_or(DecimalError::Overflow)?;
 
         let new_scale = self.scale as i16 - other.scale as i16;
-        if new_scale > 127 || new_scale < -127 {
+        if !(-127..=127).contains(&new_scale) {
             return Err(DecimalError::ScaleOverflow);
         }
 
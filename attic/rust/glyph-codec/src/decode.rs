@@ -0,0 +1,383 @@
+//! Splitting a byte stream into values
+//!
+//! A socket or pipe carrying GLYPH delivers values in arbitrary pieces.
+//! `StreamDecoder` is the I/O-free core the stream readers build on: `push`
+//! bytes as they arrive and `next_value` returns each top-level value once
+//! all of it is buffered, parsed with `parse_loose`.
+//!
+//! `ReadDecoder` drives one from a blocking `io::Read`, such as a pipe or a
+//! subprocess's stdout, and yields the values as an iterator.
+//!
+//! Values are separated by whitespace, usually a newline. A value in
+//! brackets or quotes is complete at its closing byte, so a reader never
+//! waits on the next value to return this one; a bare word or number is
+//! complete at the whitespace after it, or at `finish`. A top-level `@tab`
+//! block runs through its `@end` line.
+//!
+//! A value that fails to parse is reported and skipped, and decoding carries
+//! on with the next. Running past `with_max_len` or ending mid-value leaves
+//! nothing to resume from; the buffer is dropped with the error.
+
+use crate::error::GlyphError;
+use crate::parse::parse_loose;
+use crate::types::GValue;
+use std::io::{self, Read};
+
+/// Consumed bytes are dropped from the front of the buffer once there are
+/// at least this many
+const COMPACT_AT: usize = 4096;
+
+/// Bytes asked of a reader per read
+pub(crate) const READ_CHUNK: usize = 8192;
+
+/// Splits pushed bytes into values; see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct StreamDecoder {
+    buf: Vec<u8>,
+    /// Start of the bytes not yet returned as a value
+    start: usize,
+    scan: Scan,
+    max_len: Option<usize>,
+    eof: bool,
+}
+
+/// Where the scan of the pending value has got to
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Scan {
+    /// Next byte to look at
+    pos: usize,
+    /// First byte of the value, once past leading whitespace
+    value: Option<usize>,
+    depth: usize,
+    quote: bool,
+    escape: bool,
+    /// Inside a top-level `@tab` block, which ends at an `@end` line
+    tab: bool,
+    line: usize,
+}
+
+impl Scan {
+    /// A scan starting at byte `pos`
+    pub(crate) fn at(pos: usize) -> Self {
+        Scan { pos, line: pos, ..Scan::default() }
+    }
+
+    /// Byte range in `buf` of the next complete value, `eof` meaning no
+    /// bytes will follow `buf`
+    pub(crate) fn next_span(&mut self, buf: &[u8], max_len: Option<usize>, eof: bool) -> Result<Option<(usize, usize)>, GlyphError> {
+        while self.pos < buf.len() {
+            let b = buf[self.pos];
+            self.pos += 1;
+            let Some(value) = self.value else {
+                if !b.is_ascii_whitespace() {
+                    self.value = Some(self.pos - 1);
+                    self.quote = b == b'"';
+                    self.escape = b == b'\\';
+                    self.depth = usize::from(matches!(b, b'{' | b'[' | b'('));
+                }
+                continue;
+            };
+            if let Some(max) = max_len.filter(|&max| self.pos - value > max) {
+                return Err(GlyphError::LimitExceeded(format!("value over max_len {} bytes", max)));
+            }
+            if self.escape {
+                self.escape = false;
+                continue;
+            }
+            if self.quote {
+                match b {
+                    b'\\' => self.escape = true,
+                    b'"' => {
+                        self.quote = false;
+                        if self.depth == 0 && !self.tab {
+                            return Ok(Some((value, self.pos)));
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'\\' => self.escape = true,
+                b'"' => self.quote = true,
+                b'{' | b'[' | b'(' => self.depth += 1,
+                b'}' | b']' | b')' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 && !self.tab {
+                        return Ok(Some((value, self.pos)));
+                    }
+                }
+                b'\n' if self.tab && self.depth == 0 => self.line = self.pos,
+                _ if b.is_ascii_whitespace() && self.depth == 0 && !self.tab => {
+                    if &buf[value..self.pos - 1] != b"@tab" {
+                        return Ok(Some((value, self.pos - 1)));
+                    }
+                    self.tab = true;
+                    self.line = self.pos;
+                }
+                _ => {}
+            }
+            if self.tab && self.depth == 0 && &buf[self.line..self.pos] == b"@end" {
+                return Ok(Some((value, self.pos)));
+            }
+        }
+        match self.value {
+            Some(value) if eof => {
+                if self.depth == 0 && !self.quote && !self.tab {
+                    return Ok(Some((value, self.pos)));
+                }
+                Err(GlyphError::Parse("stream ended inside a value".to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl StreamDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail on a value longer than `max` bytes instead of buffering it
+    pub fn with_max_len(mut self, max: usize) -> Self {
+        self.max_len = Some(max);
+        self
+    }
+
+    /// Add bytes read from the stream
+    pub fn push(&mut self, data: &[u8]) {
+        if self.start >= COMPACT_AT || self.start == self.buf.len() {
+            self.buf.drain(..self.start);
+            self.scan.pos -= self.start;
+            self.scan.line = self.scan.line.saturating_sub(self.start);
+            self.scan.value = self.scan.value.map(|v| v - self.start);
+            self.start = 0;
+        }
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Mark the end of the stream: a value still pending is completed or
+    /// reported as cut off by the following `next_value` calls
+    pub fn finish(&mut self) {
+        self.eof = true;
+    }
+
+    /// Whether `finish` has been called
+    pub fn is_finished(&self) -> bool {
+        self.eof
+    }
+
+    /// Bytes buffered but not yet returned as values
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// The next complete value, or `None` until more bytes are pushed (after
+    /// `finish`, once the stream is exhausted)
+    pub fn next_value(&mut self) -> Result<Option<GValue>, GlyphError> {
+        let Some((start, end)) = self.next_span()? else {
+            return Ok(None);
+        };
+        self.start = end;
+        self.scan = Scan::at(end);
+        let text = std::str::from_utf8(&self.buf[start..end]).map_err(|e| GlyphError::Parse(format!("invalid UTF-8 in value: {}", e)))?;
+        parse_loose(text).map(Some)
+    }
+
+    /// Byte range of the next complete value
+    fn next_span(&mut self) -> Result<Option<(usize, usize)>, GlyphError> {
+        let span = self.scan.next_span(&self.buf, self.max_len, self.eof);
+        if span.is_err() {
+            self.reset();
+        }
+        span
+    }
+
+    /// Drop everything buffered
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.start = 0;
+        self.scan = Scan::default();
+    }
+}
+
+/// Values read from an `io::Read` as they complete; see the module docs.
+///
+/// Iterating yields each value as soon as its last byte has been read,
+/// without waiting for the next one, and ends when the reader does. A read
+/// error ends the iteration after it is yielded; a value that fails to parse
+/// is yielded as an error and reading carries on.
+pub struct ReadDecoder<R> {
+    inner: R,
+    decoder: StreamDecoder,
+    chunk: Vec<u8>,
+    failed: bool,
+}
+
+impl<R: Read> ReadDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            decoder: StreamDecoder::new(),
+            chunk: vec![0; READ_CHUNK],
+            failed: false,
+        }
+    }
+
+    /// Fail on a value longer than `max` bytes instead of buffering it
+    pub fn with_max_len(mut self, max: usize) -> Self {
+        self.decoder = self.decoder.with_max_len(max);
+        self
+    }
+
+    /// The next value, reading until one is complete; `None` once the
+    /// reader is exhausted
+    pub fn next_value(&mut self) -> Result<Option<GValue>, GlyphError> {
+        loop {
+            if let Some(v) = self.decoder.next_value()? {
+                return Ok(Some(v));
+            }
+            if self.decoder.is_finished() {
+                return Ok(None);
+            }
+            match self.inner.read(&mut self.chunk) {
+                Ok(0) => self.decoder.finish(),
+                Ok(n) => self.decoder.push(&self.chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for ReadDecoder<R> {
+    type Item = Result<GValue, GlyphError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.next_value();
+        self.failed = matches!(result, Err(GlyphError::Io(_)));
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::canonicalize_loose;
+    use crate::types::*;
+
+    fn decode_in_pieces(text: &str, piece: usize) -> Vec<GValue> {
+        let mut d = StreamDecoder::new();
+        let mut out = Vec::new();
+        for chunk in text.as_bytes().chunks(piece) {
+            d.push(chunk);
+            while let Some(v) = d.next_value().unwrap() {
+                out.push(v);
+            }
+        }
+        d.finish();
+        while let Some(v) = d.next_value().unwrap() {
+            out.push(v);
+        }
+        out
+    }
+
+    #[test]
+    fn test_stream_decoder_splits_values() {
+        let rows = GValue::list((0..3).map(|i| GValue::build_map().int("id", i).str("note", "a | b\n").finish()).collect());
+        let values = vec![
+            GValue::build_map().str("q", "two words").value("tags", GValue::list(vec![GValue::str("x")])).finish(),
+            GValue::int(42),
+            GValue::str("quoted \"text\" with } and ]"),
+            rows.clone(),
+            GValue::map(vec![MapEntry::new("rows", rows)]),
+            GValue::struct_val("Point", vec![MapEntry::new("x", GValue::int(1))]),
+            GValue::sum("Done", None),
+            GValue::bytes(vec![1, 2, 3]),
+            GValue::id("user", "7"),
+            GValue::bool(true),
+        ];
+        let text: String = values.iter().map(|v| canonicalize_loose(v).unwrap() + "\n").collect();
+        assert!(text.contains("@tab"));
+        // Lists of records come back as tables
+        let expected: Vec<GValue> = values.iter().map(|v| parse_loose(&canonicalize_loose(v).unwrap()).unwrap()).collect();
+        for piece in [1, 3, 7, 64, text.len()] {
+            assert_eq!(decode_in_pieces(&text, piece), expected, "pieces of {}", piece);
+        }
+    }
+
+    #[test]
+    fn test_stream_decoder_completion_and_errors() {
+        let mut d = StreamDecoder::new();
+        // Closed brackets complete at once; a bare number waits for what follows
+        d.push(b"{a=1} 12");
+        assert_eq!(d.next_value().unwrap(), Some(GValue::map(vec![MapEntry::new("a", GValue::int(1))])));
+        assert_eq!(d.next_value().unwrap(), None);
+        d.push(b"3 {bad=}");
+        assert_eq!(d.next_value().unwrap(), Some(GValue::int(123)));
+        assert!(d.next_value().is_err());
+        d.push(b" [1 2");
+        d.finish();
+        assert!(d.next_value().unwrap_err().to_string().contains("ended inside a value"));
+        assert_eq!(d.next_value().unwrap(), None);
+
+        let mut d = StreamDecoder::new().with_max_len(8);
+        d.push(b"[1 2] [1 2 3 4 5 6]");
+        assert!(d.next_value().unwrap().is_some());
+        assert!(matches!(d.next_value(), Err(GlyphError::LimitExceeded(_))));
+        assert_eq!(d.buffered(), 0);
+    }
+
+    /// Hands out its bytes a few at a time, failing once in between
+    struct Trickle<'a> {
+        data: &'a [u8],
+        reads: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            if self.reads == 2 {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            let n = self.data.len().min(buf.len()).min(5);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_decoder() {
+        let text = "{q=\"a b\"} 12\n@tab _ rows=2 cols=1 [id]\n|1|\n|2|\n@end\n{bad=} [x]";
+        let mut values = ReadDecoder::new(Trickle { data: text.as_bytes(), reads: 0 });
+        assert_eq!(values.next().unwrap().unwrap(), GValue::map(vec![MapEntry::new("q", GValue::str("a b"))]));
+        assert_eq!(values.next().unwrap().unwrap(), GValue::int(12));
+        assert_eq!(values.next().unwrap().unwrap().as_list().map(<[_]>::len), Some(2));
+        assert!(values.next().unwrap().is_err());
+        assert_eq!(values.next().unwrap().unwrap(), GValue::list(vec![GValue::str("x")]));
+        assert!(values.next().is_none());
+        assert!(values.get_ref().data.is_empty());
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+        }
+        let mut values = ReadDecoder::new(Broken);
+        assert!(matches!(values.next(), Some(Err(GlyphError::Io(_)))));
+        assert!(values.next().is_none());
+    }
+}
@@ -0,0 +1,201 @@
+//! Session delta encoding
+//!
+//! In a long agent loop the same tool is called again and again with
+//! arguments or results that barely change. `DeltaEncoder` remembers the last
+//! value sent on each channel and sends the next one either as its full
+//! canonical form or as `Δ` followed by a patch from the previous value,
+//! whichever is shorter. `DeltaDecoder` keeps the same per-channel state on
+//! the other end and turns either kind of frame back into the value.
+//!
+//! A canonical form never starts with `Δ` (non-ASCII strings are quoted), so
+//! frames need no other marker.
+
+use crate::error::GlyphError;
+use crate::loose::canonicalize_loose;
+use crate::parse::parse_loose;
+use crate::patch::{apply_patch, diff_with, DiffOpts, GlyphPatch, PatchMode};
+use crate::types::*;
+use std::collections::HashMap;
+
+const DELTA_PREFIX: &str = "Δ";
+
+/// Copies cost a pass over each value but can replace whole subtrees
+const DELTA_DIFF: DiffOpts = DiffOpts {
+    detect_moves: true,
+    detect_copies: true,
+};
+
+/// Sending side of a delta session
+#[derive(Debug, Clone, Default)]
+pub struct DeltaEncoder {
+    last: HashMap<String, GValue>,
+}
+
+impl DeltaEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The frame for `v` on `channel`: its canonical form, or a delta from
+    /// the channel's previous value when that is shorter.
+    pub fn encode(&mut self, channel: &str, v: &GValue) -> Result<String, GlyphError> {
+        let full = canonicalize_loose(v)?;
+        let frame = match self.last.get(channel) {
+            Some(prev) => {
+                let patch = canonicalize_loose(&diff_with(prev, v, DELTA_DIFF).to_wire_value())?;
+                if DELTA_PREFIX.len() + patch.len() < full.len() {
+                    format!("{}{}", DELTA_PREFIX, patch)
+                } else {
+                    full
+                }
+            }
+            None => full,
+        };
+        self.last.insert(channel.to_string(), v.clone());
+        Ok(frame)
+    }
+
+    /// Forget `channel`, so its next frame is sent in full
+    pub fn reset(&mut self, channel: &str) {
+        self.last.remove(channel);
+    }
+
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+}
+
+/// Receiving side of a delta session
+#[derive(Debug, Clone, Default)]
+pub struct DeltaDecoder {
+    last: HashMap<String, GValue>,
+}
+
+impl DeltaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value carried by `frame` on `channel`.
+    ///
+    /// A delta on a channel with no previous value, or one that doesn't fit
+    /// it, is an error and leaves the channel unchanged.
+    pub fn decode(&mut self, channel: &str, frame: &str) -> Result<GValue, GlyphError> {
+        let value = match frame.strip_prefix(DELTA_PREFIX) {
+            Some(patch) => {
+                let prev = self.last.get(channel).ok_or_else(|| {
+                    GlyphError::InvalidValue(format!("delta on channel {} with no previous value", channel))
+                })?;
+                let patch = GlyphPatch::from_value(&parse_loose(patch)?)?;
+                let mut value = prev.clone();
+                apply_patch(&mut value, &patch, PatchMode::Strict)?;
+                value
+            }
+            None => parse_loose(frame)?,
+        };
+        self.last.insert(channel.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// The last value decoded on `channel`
+    pub fn last(&self, channel: &str) -> Option<&GValue> {
+        self.last.get(channel)
+    }
+
+    pub fn reset(&mut self, channel: &str) {
+        self.last.remove(channel);
+    }
+
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::equal_loose;
+
+    fn results(statuses: &[&str]) -> GValue {
+        let rows = statuses
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                GValue::build_map()
+                    .int("id", i as i64)
+                    .str("title", format!("result number {}", i))
+                    .str("status", *s)
+                    .finish()
+            })
+            .collect();
+        GValue::build_map().str("tool", "search").value("results", GValue::list(rows)).finish()
+    }
+
+    #[test]
+    fn test_delta_session() {
+        let mut enc = DeltaEncoder::new();
+        let mut dec = DeltaDecoder::new();
+        let turns = [
+            results(&["todo", "todo", "todo", "todo"]),
+            results(&["todo", "done", "todo", "todo"]),
+            results(&["todo", "done", "todo", "todo", "todo"]),
+            GValue::int(1),
+        ];
+        let mut frames = Vec::new();
+        for v in &turns {
+            let frame = enc.encode("search", v).unwrap();
+            let back = dec.decode("search", &frame).unwrap();
+            assert!(equal_loose(&back, v).unwrap(), "{} -> {}", frame, back);
+            frames.push(frame);
+        }
+        // The first value goes tabular, and later ones ride on it as deltas
+        assert!(frames[0].starts_with("{results=@tab"), "{}", frames[0]);
+        assert_eq!(frames[1], "Δ[{new=done op=change path=\"results[1].status\"}]");
+        assert!(frames[2].starts_with('Δ'));
+        // Replacing the whole value is cheaper in full
+        assert_eq!(frames[3], "1");
+
+        // New rows can arrive tabular inside a delta
+        let mut enc = DeltaEncoder::new();
+        let mut dec = DeltaDecoder::new();
+        let base = GValue::build_map().str("tool", "search").str("note", "a long note that dominates the size").finish();
+        let mut grown = base.clone();
+        grown.insert("results", results(&["a", "b", "c"]).get("results").unwrap().clone()).unwrap();
+        dec.decode("s", &enc.encode("s", &base).unwrap()).unwrap();
+        let frame = enc.encode("s", &grown).unwrap();
+        assert!(frame.starts_with("Δ") && frame.contains("@tab"), "{}", frame);
+        dec.decode("s", &frame).unwrap();
+        let mut more = grown.clone();
+        more.set_path("results[2].status", GValue::str("z")).unwrap();
+        let back = dec.decode("s", &enc.encode("s", &more).unwrap()).unwrap();
+        assert!(equal_loose(&back, &more).unwrap());
+        enc.encode("search", &turns[0]).unwrap();
+        assert_eq!(enc.encode("search", &turns[0]).unwrap(), "Δ[]");
+    }
+
+    #[test]
+    fn test_channels_and_errors() {
+        let mut enc = DeltaEncoder::new();
+        let mut dec = DeltaDecoder::new();
+        let a = results(&["todo"]);
+        let b = results(&["done"]);
+        let fa = enc.encode("a", &a).unwrap();
+        // Channels are independent
+        assert_eq!(enc.encode("b", &a).unwrap(), fa);
+
+        dec.decode("a", &fa).unwrap();
+        let fb = enc.encode("a", &b).unwrap();
+        assert!(fb.starts_with('Δ'));
+        let err = dec.decode("b", &fb).unwrap_err();
+        assert!(err.to_string().contains("no previous value"), "{}", err);
+        assert!(equal_loose(&dec.decode("a", &fb).unwrap(), &b).unwrap());
+
+        // A delta that doesn't fit leaves the channel as it was
+        dec.decode("c", "{x=1}").unwrap();
+        assert!(dec.decode("c", &fb).is_err());
+        assert_eq!(dec.last("c").unwrap().to_string(), "{x=1}");
+
+        enc.reset("a");
+        assert!(!enc.encode("a", &b).unwrap().starts_with('Δ'));
+    }
+}
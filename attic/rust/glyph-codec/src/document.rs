@@ -0,0 +1,419 @@
+//! Self-describing documents
+//!
+//! A document is an optional header line followed by one loose-mode value:
+//!
+//! ```text
+//! !glyph/1 spec=glyph-loose-1.0.0 null=_ bool=short tabular=t keys=sorted
+//! {a=1 b=2}
+//! ```
+//!
+//! The header records the spec version and the options the body was emitted
+//! with, so persisted files stay readable as the canonical rules evolve.
+
+use crate::error::*;
+use crate::loose::*;
+use crate::parse::parse_loose;
+use crate::types::GValue;
+use std::fmt;
+use std::time::Duration;
+
+/// Header format version written by this crate
+pub const DOCUMENT_VERSION: u32 = 1;
+
+const HEADER_PREFIX: &str = "!glyph/";
+
+/// Options for emitting a document
+#[derive(Debug, Clone)]
+pub struct DocumentOpts {
+    /// Write the `!glyph/1` header line
+    pub header: bool,
+    /// Options for the body
+    pub canon: LooseCanonOpts,
+}
+
+impl Default for DocumentOpts {
+    fn default() -> Self {
+        Self {
+            header: true,
+            canon: LooseCanonOpts::default(),
+        }
+    }
+}
+
+impl DocumentOpts {
+    /// Document with a header and the given body options
+    pub fn with_canon(canon: LooseCanonOpts) -> Self {
+        Self { header: true, canon }
+    }
+
+    /// Body only, no header line
+    pub fn bare() -> Self {
+        Self {
+            header: false,
+            ..Self::default()
+        }
+    }
+}
+
+/// Parsed `!glyph/N` header line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentHeader {
+    pub version: u32,
+    /// `key=value` attributes in header order, including unknown ones
+    pub attrs: Vec<(String, String)>,
+}
+
+impl DocumentHeader {
+    /// Header describing a body emitted with `opts`
+    pub fn from_opts(opts: &LooseCanonOpts) -> Self {
+        let mut attrs = vec![
+            ("spec".to_string(), canon_spec_version().to_string()),
+            ("null".to_string(), null_attr(opts.null_style).to_string()),
+            ("bool".to_string(), bool_attr(opts.bool_style).to_string()),
+            ("tabular".to_string(), tabular_attr(opts).to_string()),
+            ("keys".to_string(), key_order_attr(opts.key_order).to_string()),
+        ];
+        let mut push = |k: &str, v: String| attrs.push((k.to_string(), v));
+        match &opts.column_order {
+            ColumnOrder::Keys => {}
+            ColumnOrder::Pinned(cols) if cols.iter().all(|c| is_pin_safe(c)) => push("pin", cols.join(",")),
+            _ => push("columns", "custom".to_string()),
+        }
+        match &opts.sort_rows {
+            RowOrder::Source => {}
+            RowOrder::Canonical => push("sort", "canon".to_string()),
+            RowOrder::ByKey(key) if is_pin_safe(key) => push("sort", format!("key:{}", key)),
+            RowOrder::ByKey(_) => push("sort", "custom".to_string()),
+        }
+        if opts.tabular_flatten_depth > 0 {
+            push("flatten", opts.tabular_flatten_depth.to_string());
+        }
+        if opts.tabular_types {
+            push("tab_types", "t".to_string());
+        }
+        if opts.tabular_lists {
+            push("tab_lists", "t".to_string());
+        }
+        if opts.align_tabular {
+            push("align", "t".to_string());
+        }
+        if !opts.tabular_paths.is_empty() {
+            let rules: Vec<_> = opts
+                .tabular_paths
+                .iter()
+                .map(|r| format!("{}:{}", tabular_mode_attr(r.mode), r.pattern))
+                .collect();
+            if rules.iter().all(|r| is_pin_safe(r)) {
+                push("tab_paths", rules.join(","));
+            } else {
+                push("tab_paths", "custom".to_string());
+            }
+        }
+        if opts.unicode_norm == UnicodeNorm::Nfc {
+            push("norm", "nfc".to_string());
+        }
+        if let TimeStyle::RoundTo(bucket) = opts.time_style {
+            let bucket = if bucket.subsec_nanos() == 0 {
+                format!("{}s", bucket.as_secs())
+            } else {
+                format!("{}ms", bucket.as_millis())
+            };
+            push("time", bucket);
+        }
+        let caps = &opts.max_value_bytes_per_type;
+        for (key, cap) in [
+            ("max_str", caps.max_string_bytes),
+            ("max_bytes", caps.max_bytes_len),
+            ("max_list", caps.max_list_items),
+            ("max_map", caps.max_map_entries),
+        ] {
+            if let Some(n) = cap {
+                push(key, n.to_string());
+            }
+        }
+        Self {
+            version: DOCUMENT_VERSION,
+            attrs,
+        }
+    }
+
+    /// Parse a header line such as `!glyph/1 spec=glyph-loose-1.0.0`
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let rest = line
+            .strip_prefix(HEADER_PREFIX)
+            .ok_or_else(|| GlyphError::Parse(format!("expected {:?} header", HEADER_PREFIX)))?;
+        let mut tokens = rest.split_whitespace();
+        let version = tokens
+            .next()
+            .and_then(|v| v.parse::<u32>().ok())
+            .ok_or_else(|| GlyphError::Parse(format!("invalid document version in {:?}", line)))?;
+
+        let attrs = tokens
+            .map(|tok| {
+                tok.split_once('=')
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .ok_or_else(|| GlyphError::Parse(format!("invalid header attribute {:?}", tok)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { version, attrs })
+    }
+
+    /// Attribute value, if present
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Spec version the body was emitted under
+    pub fn spec(&self) -> Option<&str> {
+        self.get("spec")
+    }
+
+    /// Rebuild the options the body was emitted with.
+    ///
+    /// Fails for `keys=custom` and `columns=custom`, since a comparator
+    /// can't be recorded.
+    pub fn canon_opts(&self) -> Result<LooseCanonOpts> {
+        let mut opts = LooseCanonOpts::default();
+        for (k, v) in &self.attrs {
+            let bad = || GlyphError::InvalidValue(format!("header attribute {}={}", k, v));
+            let count = || v.parse::<usize>().map_err(|_| bad());
+            match k.as_str() {
+                "null" => {
+                    opts.null_style = match v.as_str() {
+                        "_" => NullStyle::Underscore,
+                        "∅" => NullStyle::Symbol,
+                        "null" => NullStyle::Word,
+                        _ => return Err(bad()),
+                    }
+                }
+                "bool" => {
+                    opts.bool_style = match v.as_str() {
+                        "short" => BoolStyle::Short,
+                        "word" => BoolStyle::Word,
+                        _ => return Err(bad()),
+                    }
+                }
+                "tabular" => {
+                    opts.auto_tabular = v == "t" || v == "size";
+                    opts.optimize_size = v == "size";
+                }
+                "keys" => {
+                    opts.key_order = match v.as_str() {
+                        "sorted" => KeyOrder::Sorted,
+                        "insertion" => KeyOrder::Insertion,
+                        _ => return Err(bad()),
+                    }
+                }
+                "pin" => opts.column_order = ColumnOrder::pinned(v.split(',')),
+                "columns" => return Err(bad()),
+                "sort" => {
+                    opts.sort_rows = match v.split_once(':') {
+                        _ if v == "canon" => RowOrder::Canonical,
+                        Some(("key", key)) => RowOrder::ByKey(key.to_string()),
+                        _ => return Err(bad()),
+                    }
+                }
+                "flatten" => opts.tabular_flatten_depth = count()?,
+                "tab_types" => opts.tabular_types = v == "t",
+                "tab_lists" => opts.tabular_lists = v == "t",
+                "align" => opts.align_tabular = v == "t",
+                "tab_paths" => {
+                    for rule in v.split(',') {
+                        let (mode, pattern) = rule.split_once(':').ok_or_else(bad)?;
+                        let mode = match mode {
+                            "auto" => TabularMode::Auto,
+                            "force" => TabularMode::Force,
+                            "off" => TabularMode::Off,
+                            _ => return Err(bad()),
+                        };
+                        opts.tabular_paths.push(TabularRule::new(pattern, mode).map_err(|_| bad())?);
+                    }
+                }
+                "norm" => opts.unicode_norm = if v == "nfc" { UnicodeNorm::Nfc } else { UnicodeNorm::None },
+                "time" => {
+                    let bucket = match v.strip_suffix("ms") {
+                        Some(ms) => ms.parse().ok().map(Duration::from_millis),
+                        None => v.strip_suffix('s').and_then(|s| s.parse().ok()).map(Duration::from_secs),
+                    };
+                    opts.time_style = TimeStyle::RoundTo(bucket.ok_or_else(bad)?);
+                }
+                "max_str" => opts.max_value_bytes_per_type.max_string_bytes = Some(count()?),
+                "max_bytes" => opts.max_value_bytes_per_type.max_bytes_len = Some(count()?),
+                "max_list" => opts.max_value_bytes_per_type.max_list_items = Some(count()?),
+                "max_map" => opts.max_value_bytes_per_type.max_map_entries = Some(count()?),
+                _ => {}
+            }
+        }
+        Ok(opts)
+    }
+}
+
+impl fmt::Display for DocumentHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", HEADER_PREFIX, self.version)?;
+        for (k, v) in &self.attrs {
+            write!(f, " {}={}", k, v)?;
+        }
+        Ok(())
+    }
+}
+
+fn null_attr(style: NullStyle) -> &'static str {
+    match style {
+        NullStyle::Underscore => "_",
+        NullStyle::Symbol => "∅",
+        NullStyle::Word => "null",
+    }
+}
+
+fn bool_attr(style: BoolStyle) -> &'static str {
+    match style {
+        BoolStyle::Short => "short",
+        BoolStyle::Word => "word",
+    }
+}
+
+fn tabular_attr(opts: &LooseCanonOpts) -> &'static str {
+    match (opts.auto_tabular, opts.optimize_size) {
+        (false, _) => "f",
+        (true, false) => "t",
+        (true, true) => "size",
+    }
+}
+
+/// Pinned column names that survive the comma-separated `pin=` attribute
+fn is_pin_safe(col: &str) -> bool {
+    !col.is_empty() && !col.contains(',') && !col.chars().any(char::is_whitespace)
+}
+
+fn tabular_mode_attr(mode: TabularMode) -> &'static str {
+    match mode {
+        TabularMode::Auto => "auto",
+        TabularMode::Force => "force",
+        TabularMode::Off => "off",
+    }
+}
+
+fn key_order_attr(order: KeyOrder) -> &'static str {
+    match order {
+        KeyOrder::Sorted => "sorted",
+        KeyOrder::Insertion => "insertion",
+        KeyOrder::Custom(_) => "custom",
+    }
+}
+
+/// Emit `v` as a document: optional header line, then the body
+pub fn emit_document(v: &GValue, opts: &DocumentOpts) -> Result<String> {
+    let body = canonicalize_loose_with_opts(v, &opts.canon)?;
+    if !opts.header {
+        return Ok(body);
+    }
+    Ok(format!("{}\n{}", DocumentHeader::from_opts(&opts.canon), body))
+}
+
+/// Parse a document with or without a header.
+///
+/// Rejects headers with a version newer than this crate understands.
+pub fn parse_document(input: &str) -> Result<(Option<DocumentHeader>, GValue)> {
+    let trimmed = input.trim_start();
+    if !trimmed.starts_with(HEADER_PREFIX) {
+        return Ok((None, parse_loose(input)?));
+    }
+    let (line, body) = trimmed.split_once('\n').unwrap_or((trimmed, ""));
+    let header = DocumentHeader::parse(line)?;
+    if header.version > DOCUMENT_VERSION {
+        return Err(GlyphError::Parse(format!(
+            "unsupported document version {} (max {})",
+            header.version, DOCUMENT_VERSION
+        )));
+    }
+    Ok((Some(header), parse_loose(body)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    fn sample() -> GValue {
+        GValue::map(vec![
+            field("b", GValue::null()),
+            field("a", GValue::bool(true)),
+            field("rows", GValue::list(vec![GValue::int(1), GValue::int(2)])),
+        ])
+    }
+
+    #[test]
+    fn test_document_roundtrip() {
+        let text = emit_document(&sample(), &DocumentOpts::default()).unwrap();
+        assert_eq!(
+            text,
+            "!glyph/1 spec=glyph-loose-1.0.0 null=_ bool=short tabular=t keys=sorted\n{a=t b=_ rows=[1 2]}"
+        );
+        let (header, value) = parse_document(&text).unwrap();
+        let header = header.unwrap();
+        assert_eq!(header.spec(), Some(canon_spec_version()));
+        assert!(equal_loose(&value, &sample()).unwrap());
+        assert_eq!(DocumentHeader::parse(&header.to_string()).unwrap(), header);
+    }
+
+    #[test]
+    fn test_header_records_options() {
+        let canon = LooseCanonOpts {
+            time_style: TimeStyle::minute(),
+            unicode_norm: UnicodeNorm::Nfc,
+            max_value_bytes_per_type: SizeCaps {
+                max_list_items: Some(10),
+                ..SizeCaps::default()
+            },
+            ..LooseCanonOpts::verbose()
+        };
+        let text = emit_document(&sample(), &DocumentOpts::with_canon(canon.clone())).unwrap();
+        let (header, value) = parse_document(&text).unwrap();
+        let restored = header.unwrap().canon_opts().unwrap();
+        assert_eq!(restored.null_style, NullStyle::Word);
+        assert_eq!(restored.time_style, canon.time_style);
+        assert_eq!(restored.max_value_bytes_per_type, canon.max_value_bytes_per_type);
+        // Re-emitting with the recorded options reproduces the body
+        assert_eq!(emit_document(&value, &DocumentOpts::with_canon(restored)).unwrap(), text);
+
+        let pinned = LooseCanonOpts {
+            column_order: ColumnOrder::pinned(["id", "user.name"]),
+            ..LooseCanonOpts::default()
+        };
+        let header = DocumentHeader::from_opts(&pinned);
+        assert_eq!(header.get("pin"), Some("id,user.name"));
+        assert_eq!(header.canon_opts().unwrap().column_order, pinned.column_order);
+        let spaced = LooseCanonOpts {
+            column_order: ColumnOrder::pinned(["a b"]),
+            ..LooseCanonOpts::default()
+        };
+        assert!(DocumentHeader::from_opts(&spaced).canon_opts().is_err());
+
+        let sorted = LooseCanonOpts {
+            sort_rows: RowOrder::ByKey("id".to_string()),
+            ..LooseCanonOpts::default()
+        };
+        assert_eq!(DocumentHeader::from_opts(&sorted).canon_opts().unwrap().sort_rows, sorted.sort_rows);
+
+        let compact = DocumentHeader::from_opts(&LooseCanonOpts::compact());
+        assert_eq!(compact.get("tabular"), Some("size"));
+        assert!(compact.canon_opts().unwrap().optimize_size);
+    }
+
+    #[test]
+    fn test_parse_document_variants() {
+        let (header, value) = parse_document("{a=1}").unwrap();
+        assert!(header.is_none());
+        assert_eq!(value, GValue::map(vec![field("a", GValue::int(1))]));
+        assert_eq!(emit_document(&value, &DocumentOpts::bare()).unwrap(), "{a=1}");
+
+        // Unknown attributes are kept, newer versions rejected
+        let (header, _) = parse_document("!glyph/1 spec=glyph-loose-1.0.0 future=x\n1").unwrap();
+        assert_eq!(header.unwrap().get("future"), Some("x"));
+        assert!(parse_document("!glyph/2\n1").is_err());
+        assert!(parse_document("!glyph/x\n1").is_err());
+        assert!(DocumentHeader::parse("!glyph/1 noequals").is_err());
+    }
+}
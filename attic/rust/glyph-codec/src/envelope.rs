@@ -0,0 +1,350 @@
+//! Tool call and result envelopes
+//!
+//! `ToolCall` and `ToolResult` are the shape every agent built on this crate
+//! uses for a call and its answer, whatever API carried them:
+//!
+//! ```text
+//! ToolCall{args={query=glyph} id=^call_abc name=search}
+//! ToolResult{content={hits=3} id=^call_abc latency_ms=42 status=ok}
+//! ```
+//!
+//! Ids differ between vendors and latency between runs, so `fingerprint`
+//! leaves both out: the same call made through OpenAI and Anthropic, or the
+//! same answer served twice, hashes the same.
+
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose, hash_loose};
+use crate::parse::parse_loose;
+use crate::types::*;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Type name of an encoded `ToolCall`
+pub const TOOL_CALL_TYPE: &str = "ToolCall";
+
+/// Type name of an encoded `ToolResult`
+pub const TOOL_RESULT_TYPE: &str = "ToolResult";
+
+/// A request to run a tool
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    /// The vendor's call id; legacy single-call APIs have none
+    pub id: Option<RefId>,
+    pub name: String,
+    pub args: GValue,
+}
+
+impl ToolCall {
+    pub fn new(name: impl Into<String>, args: GValue) -> Self {
+        Self {
+            id: None,
+            name: name.into(),
+            args,
+        }
+    }
+
+    pub fn with_id(mut self, id: RefId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// `ToolCall{args=... id=^... name=...}`, without `id` when there is none
+    pub fn to_value(&self) -> GValue {
+        let mut fields = vec![MapEntry::new("args", self.args.clone())];
+        if let Some(id) = &self.id {
+            fields.push(MapEntry::new("id", GValue::Id(id.clone())));
+        }
+        fields.push(MapEntry::new("name", GValue::str(self.name.as_str())));
+        GValue::struct_val(TOOL_CALL_TYPE, fields)
+    }
+
+    /// Read back a call written by `to_value`, or a plain map of the same
+    /// fields. A string id is taken as the ref's value; missing args read as
+    /// an empty map.
+    pub fn from_value(v: &GValue) -> Result<Self, GlyphError> {
+        expect_type(v, TOOL_CALL_TYPE)?;
+        Ok(Self {
+            id: id_field(v)?,
+            name: v.try_get("name")?.try_as_str()?.to_string(),
+            args: v.get("args").cloned().unwrap_or_else(|| GValue::map(vec![])),
+        })
+    }
+
+    /// Canonical text of `to_value`
+    pub fn to_text(&self) -> Result<String, GlyphError> {
+        canonicalize_loose(&self.to_value())
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, GlyphError> {
+        Self::from_value(&parse_loose(text)?)
+    }
+
+    /// `hash_loose` of the name and args, without the id
+    pub fn fingerprint(&self) -> Result<String, GlyphError> {
+        hash_loose(&Self { id: None, ..self.clone() }.to_value())
+    }
+}
+
+impl fmt::Display for ToolCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text().map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for ToolCall {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_text(s)
+    }
+}
+
+/// How a tool call ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ToolStatus {
+    #[default]
+    Ok,
+    Error,
+    Timeout,
+    Cancelled,
+}
+
+impl ToolStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ToolStatus::Ok => "ok",
+            ToolStatus::Error => "error",
+            ToolStatus::Timeout => "timeout",
+            ToolStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Parse a status as written by `as_str`
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "ok" => ToolStatus::Ok,
+            "error" => ToolStatus::Error,
+            "timeout" => ToolStatus::Timeout,
+            "cancelled" => ToolStatus::Cancelled,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for ToolStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The answer to a `ToolCall`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolResult {
+    /// Id of the call this answers
+    pub id: RefId,
+    pub status: ToolStatus,
+    /// What the tool returned; null when it returned nothing
+    pub content: GValue,
+    pub latency: Option<Duration>,
+    pub error: Option<String>,
+}
+
+impl ToolResult {
+    pub fn ok(id: RefId, content: GValue) -> Self {
+        Self {
+            id,
+            status: ToolStatus::Ok,
+            content,
+            latency: None,
+            error: None,
+        }
+    }
+
+    pub fn error(id: RefId, message: impl Into<String>) -> Self {
+        Self {
+            id,
+            status: ToolStatus::Error,
+            content: GValue::Null,
+            latency: None,
+            error: Some(message.into()),
+        }
+    }
+
+    pub fn with_status(mut self, status: ToolStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_content(mut self, content: GValue) -> Self {
+        self.content = content;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == ToolStatus::Ok
+    }
+
+    /// `ToolResult{content=... error=... id=^... latency_ms=... status=...}`;
+    /// null content, no error and no latency are left out
+    pub fn to_value(&self) -> GValue {
+        let mut fields = Vec::new();
+        if !self.content.is_null() {
+            fields.push(MapEntry::new("content", self.content.clone()));
+        }
+        if let Some(error) = &self.error {
+            fields.push(MapEntry::new("error", GValue::str(error.as_str())));
+        }
+        fields.push(MapEntry::new("id", GValue::Id(self.id.clone())));
+        if let Some(latency) = self.latency {
+            let ms = i64::try_from(latency.as_millis()).unwrap_or(i64::MAX);
+            fields.push(MapEntry::new("latency_ms", GValue::int(ms)));
+        }
+        fields.push(MapEntry::new("status", GValue::str(self.status.as_str())));
+        GValue::struct_val(TOOL_RESULT_TYPE, fields)
+    }
+
+    /// Read back a result written by `to_value`, or a plain map of the same
+    /// fields; a missing status reads as `ok`
+    pub fn from_value(v: &GValue) -> Result<Self, GlyphError> {
+        expect_type(v, TOOL_RESULT_TYPE)?;
+        let id = id_field(v)?.ok_or_else(|| GlyphError::MissingField("id".to_string()))?;
+        let status = match v.get("status") {
+            None | Some(GValue::Null) => ToolStatus::Ok,
+            Some(status) => {
+                let s = status.try_as_str()?;
+                ToolStatus::parse(s)
+                    .ok_or_else(|| GlyphError::InvalidValue(format!("unknown tool status {:?}", s)))?
+            }
+        };
+        let latency = match v.get("latency_ms") {
+            None | Some(GValue::Null) => None,
+            Some(ms) => {
+                let ms = u64::try_from(ms.try_as_int()?)
+                    .map_err(|_| GlyphError::InvalidValue("negative latency_ms".to_string()))?;
+                Some(Duration::from_millis(ms))
+            }
+        };
+        let error = match v.get("error") {
+            None | Some(GValue::Null) => None,
+            Some(error) => Some(error.try_as_str()?.to_string()),
+        };
+        Ok(Self {
+            id,
+            status,
+            content: v.get("content").cloned().unwrap_or(GValue::Null),
+            latency,
+            error,
+        })
+    }
+
+    /// Canonical text of `to_value`
+    pub fn to_text(&self) -> Result<String, GlyphError> {
+        canonicalize_loose(&self.to_value())
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, GlyphError> {
+        Self::from_value(&parse_loose(text)?)
+    }
+
+    /// `hash_loose` of the status, content and error, without the id or
+    /// latency
+    pub fn fingerprint(&self) -> Result<String, GlyphError> {
+        let mut v = self.to_value();
+        if let GValue::Struct(s) = &mut v {
+            s.fields.retain(|f| f.key != "id" && f.key != "latency_ms");
+        }
+        hash_loose(&v)
+    }
+}
+
+impl fmt::Display for ToolResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text().map_err(|_| fmt::Error)?)
+    }
+}
+
+impl FromStr for ToolResult {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_text(s)
+    }
+}
+
+fn expect_type(v: &GValue, type_name: &str) -> Result<(), GlyphError> {
+    match v {
+        GValue::Struct(s) if s.type_name == type_name => Ok(()),
+        GValue::Map(_) => Ok(()),
+        GValue::Struct(s) => Err(GlyphError::TypeMismatch {
+            expected: type_name.to_string(),
+            got: s.type_name.clone(),
+        }),
+        other => Err(GlyphError::TypeMismatch {
+            expected: type_name.to_string(),
+            got: other.gtype().to_string(),
+        }),
+    }
+}
+
+fn id_field(v: &GValue) -> Result<Option<RefId>, GlyphError> {
+    match v.get("id") {
+        None | Some(GValue::Null) => Ok(None),
+        Some(GValue::Id(id)) => Ok(Some(id.clone())),
+        Some(GValue::Str(s)) => Ok(Some(RefId::simple(s.as_str()))),
+        Some(other) => Err(GlyphError::TypeMismatch {
+            expected: "id".to_string(),
+            got: other.gtype().to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_call_roundtrip() {
+        let args = GValue::map(vec![MapEntry::new("query", GValue::str("glyph"))]);
+        let call = ToolCall::new("search", args).with_id(RefId::simple("call_abc"));
+        let text = call.to_text().unwrap();
+        assert_eq!(text, "ToolCall{args={query=glyph} id=^call_abc name=search}");
+        assert_eq!(text.parse::<ToolCall>().unwrap(), call);
+
+        let other = ToolCall { id: Some(RefId::simple("toolu_01A")), ..call.clone() };
+        assert_eq!(other.fingerprint().unwrap(), call.fingerprint().unwrap());
+        let changed = ToolCall::new("search", GValue::map(vec![]));
+        assert_ne!(changed.fingerprint().unwrap(), call.fingerprint().unwrap());
+    }
+
+    #[test]
+    fn test_tool_result_roundtrip() {
+        let hits = GValue::map(vec![MapEntry::new("hits", GValue::int(3))]);
+        let result = ToolResult::ok(RefId::simple("call_abc"), hits).with_latency(Duration::from_millis(42));
+        let text = result.to_text().unwrap();
+        assert_eq!(text, "ToolResult{content={hits=3} id=^call_abc latency_ms=42 status=ok}");
+        assert_eq!(text.parse::<ToolResult>().unwrap(), result);
+
+        let slower = result.clone().with_latency(Duration::from_secs(2));
+        assert_eq!(slower.fingerprint().unwrap(), result.fingerprint().unwrap());
+
+        let failed = ToolResult::error(RefId::simple("call_abc"), "rate limited").with_status(ToolStatus::Timeout);
+        assert_eq!(
+            failed.to_text().unwrap(),
+            r#"ToolResult{error="rate limited" id=^call_abc status=timeout}"#
+        );
+        assert!(matches!(
+            ToolResult::from_text("ToolResult{id=^x status=lost}"),
+            Err(GlyphError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            ToolResult::from_text("ToolCall{id=^x name=search}"),
+            Err(GlyphError::TypeMismatch { .. })
+        ));
+    }
+}
@@ -25,6 +25,12 @@ pub enum GlyphError {
 
     #[error("Recursion limit exceeded: {limit}")]
     RecursionLimitExceeded { limit: usize },
+
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, GlyphError>;
@@ -0,0 +1,462 @@
+//! Incremental re-canonicalization
+//!
+//! Keeps a canonical string together with its source map so that small edits
+//! to a large value only re-emit the affected spans instead of the whole
+//! document. The result is always byte-identical to a full
+//! `canonicalize_loose_with_opts` of the edited value. `EncodeLimits` depend
+//! on depth, sibling counts and total size, so with any limit set every edit
+//! re-emits the whole document.
+
+use crate::error::GlyphError;
+use crate::loose::*;
+use crate::path::{GlyphPath, PathSegment};
+use crate::types::*;
+use std::sync::OnceLock;
+
+/// Replace (or add) the value at a path
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathEdit {
+    pub path: GlyphPath,
+    pub value: GValue,
+}
+
+impl PathEdit {
+    pub fn new(path: GlyphPath, value: GValue) -> Self {
+        Self { path, value }
+    }
+}
+
+/// A value with its cached canonical form and source map
+#[derive(Debug, Clone)]
+pub struct CanonCache {
+    value: GValue,
+    opts: LooseCanonOpts,
+    text: String,
+    source_map: SourceMap,
+}
+
+impl CanonCache {
+    /// Canonicalize `value` once and keep the result for incremental updates
+    pub fn new(value: GValue, opts: LooseCanonOpts) -> Result<Self, GlyphError> {
+        let (text, source_map) = canonicalize_loose_with_source_map(&value, &opts)?;
+        Ok(Self {
+            value,
+            opts,
+            text,
+            source_map,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn value(&self) -> &GValue {
+        &self.value
+    }
+
+    pub fn opts(&self) -> &LooseCanonOpts {
+        &self.opts
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    pub fn into_parts(self) -> (GValue, String) {
+        (self.value, self.text)
+    }
+
+    /// Apply one edit, re-emitting only the smallest span it can affect.
+    ///
+    /// On error the cache is left unchanged.
+    pub fn apply(&mut self, edit: &PathEdit) -> Result<(), GlyphError> {
+        let (old, inserted) = replace_at(&mut self.value, &edit.path, edit.value.clone())?;
+        let root = self.reemit_root(&edit.path, inserted, old.as_ref());
+
+        match self.reemit(&root) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Roll the value back so it stays in sync with the text
+                match old {
+                    Some(old) => {
+                        replace_at(&mut self.value, &edit.path, old)?;
+                    }
+                    None => remove_last_entry(&mut self.value, &edit.path),
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Pick the node whose span must be re-emitted for an edit at `path`
+    fn reemit_root(&self, path: &GlyphPath, inserted: bool, old: Option<&GValue>) -> GlyphPath {
+        if self.opts.limits.is_set() {
+            return GlyphPath::root();
+        }
+        let mut root = if inserted {
+            path.parent().unwrap_or_default()
+        } else {
+            path.clone()
+        };
+
+        if self.opts.auto_tabular || !self.opts.tabular_paths.is_empty() {
+            // A row added, replaced, or given a new key can flip its list's tabular decision
+            let is_object = |v: Option<&GValue>| matches!(v, Some(GValue::Map(_)) | Some(GValue::Struct(_)));
+            let row_changed = inserted || is_object(old) || is_object(self.value.at(path));
+            if row_changed {
+                if let Some(parent) = root.parent() {
+                    if matches!(self.value.at(&parent), Some(GValue::List(_))) {
+                        root = parent;
+                    }
+                }
+            }
+
+            // Cells are not mapped individually: re-emit the outermost tabular block
+            for len in 0..root.len() {
+                let prefix = GlyphPath::from_segments(root.segments()[..len].to_vec());
+                if self.source_map.get(&prefix).is_some_and(|s| s.tabular) {
+                    root = prefix;
+                    break;
+                }
+            }
+        }
+
+        // Nodes under a size-cap placeholder have no span of their own
+        while self.source_map.get(&root).is_none() {
+            match root.parent() {
+                Some(parent) => root = parent,
+                None => break,
+            }
+        }
+        root
+    }
+
+    fn reemit(&mut self, root: &GlyphPath) -> Result<(), GlyphError> {
+        let old = self
+            .source_map
+            .get(root)
+            .ok_or_else(|| GlyphError::InvalidValue(format!("no source span for {}", root)))?;
+        let node = self.value.at(root)
+            .ok_or_else(|| GlyphError::InvalidValue(format!("no value at {}", root)))?;
+
+        let mut text = String::new();
+        let mut writer = CanonWriter::recording(&self.opts, root.clone());
+        writer.write(&mut text, node)?;
+        let fresh = writer.into_source_map();
+
+        let delta = text.len() as isize - (old.end - old.start) as isize;
+        let shift = |pos: usize| (pos as isize + delta) as usize;
+
+        self.source_map.spans.retain(|p, _| !root.is_prefix_of(p));
+        for span in self.source_map.spans.values_mut() {
+            if span.start >= old.end {
+                span.start = shift(span.start);
+                span.end = shift(span.end);
+            } else if span.end >= old.end {
+                // Ancestor enclosing the re-emitted node
+                span.end = shift(span.end);
+            }
+        }
+        for (p, mut span) in fresh.spans {
+            span.start += old.start;
+            span.end += old.start;
+            self.source_map.spans.insert(p, span);
+        }
+
+        self.text.replace_range(old.start..old.end, &text);
+        Ok(())
+    }
+}
+
+/// Apply `edits` in order and return the updated canonical string.
+///
+/// Each edit replaces the value at an existing path, or adds a new key to an
+/// existing map or struct. Only the spans the edit can affect are re-emitted.
+pub fn recanonicalize_incremental(cache: &mut CanonCache, edits: &[PathEdit]) -> Result<String, GlyphError> {
+    for edit in edits {
+        cache.apply(edit)?;
+    }
+    Ok(cache.text.clone())
+}
+
+// ============================================================
+// Cached fingerprints
+// ============================================================
+
+/// A value with its `hash_loose` fingerprint computed at most once.
+///
+/// Mutations go through `apply`, `update` or `replace`, which drop the cached
+/// fingerprint, so it never goes stale.
+#[derive(Debug, Clone)]
+pub struct FingerprintedValue {
+    value: GValue,
+    hash: OnceLock<String>,
+}
+
+impl FingerprintedValue {
+    pub fn new(value: GValue) -> Self {
+        Self {
+            value,
+            hash: OnceLock::new(),
+        }
+    }
+
+    pub fn value(&self) -> &GValue {
+        &self.value
+    }
+
+    pub fn into_inner(self) -> GValue {
+        self.value
+    }
+
+    /// The `hash_loose` fingerprint, computed on first use
+    pub fn fingerprint(&self) -> Result<&str, GlyphError> {
+        if let Some(hash) = self.hash.get() {
+            return Ok(hash);
+        }
+        let hash = hash_loose(&self.value)?;
+        Ok(self.hash.get_or_init(|| hash))
+    }
+
+    /// Whether the fingerprint is currently cached
+    pub fn is_cached(&self) -> bool {
+        self.hash.get().is_some()
+    }
+
+    /// Replace or add the value at `edit.path`, as `CanonCache::apply` does
+    pub fn apply(&mut self, edit: &PathEdit) -> Result<(), GlyphError> {
+        replace_at(&mut self.value, &edit.path, edit.value.clone())?;
+        self.hash = OnceLock::new();
+        Ok(())
+    }
+
+    /// Mutate the value in place
+    pub fn update<R>(&mut self, f: impl FnOnce(&mut GValue) -> R) -> R {
+        self.hash = OnceLock::new();
+        f(&mut self.value)
+    }
+
+    /// Swap in a new value, returning the old one
+    pub fn replace(&mut self, value: GValue) -> GValue {
+        self.hash = OnceLock::new();
+        std::mem::replace(&mut self.value, value)
+    }
+}
+
+impl From<GValue> for FingerprintedValue {
+    fn from(value: GValue) -> Self {
+        Self::new(value)
+    }
+}
+
+// ============================================================
+// Path helpers
+// ============================================================
+
+fn entries_mut(v: &mut GValue) -> Option<&mut Vec<MapEntry>> {
+    match v {
+        GValue::Map(entries) => Some(entries),
+        GValue::Struct(s) => Some(&mut s.fields),
+        _ => None,
+    }
+}
+
+/// Replace the node at `path`, or add a new key to an existing map/struct.
+/// Returns the old value (if any) and whether a new entry was inserted.
+pub(crate) fn replace_at(root: &mut GValue, path: &GlyphPath, value: GValue) -> Result<(Option<GValue>, bool), GlyphError> {
+    let (last, parent_path) = match (path.last(), path.parent()) {
+        (Some(last), Some(parent)) => (last, parent),
+        _ => return Ok((Some(std::mem::replace(root, value)), false)),
+    };
+    let not_found = || GlyphError::InvalidValue(format!("no value at {}", path));
+
+    let parent = root.at_mut(&parent_path).ok_or_else(not_found)?;
+
+    if let Some(slot) = parent.child_mut(last) {
+        return Ok((Some(std::mem::replace(slot, value)), false));
+    }
+    match (entries_mut(parent), last) {
+        (Some(entries), PathSegment::Key(k)) => {
+            entries.push(MapEntry::new(k.clone(), value));
+            Ok((None, true))
+        }
+        _ => Err(not_found()),
+    }
+}
+
+fn remove_last_entry(root: &mut GValue, path: &GlyphPath) {
+    let parent = path.parent().unwrap_or_default();
+    if let Some(entries) = root.at_mut(&parent).and_then(entries_mut) {
+        entries.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(s: &str) -> GlyphPath {
+        GlyphPath::parse(s).unwrap()
+    }
+
+    fn check(cache: &CanonCache) {
+        let full = canonicalize_loose_with_opts(cache.value(), cache.opts()).unwrap();
+        assert_eq!(cache.text(), full);
+        let (_, map) = canonicalize_loose_with_source_map(cache.value(), cache.opts()).unwrap();
+        for (p, span) in map.iter() {
+            assert_eq!(cache.source_map().get(p), Some(*span), "span mismatch at {}", p);
+        }
+        assert_eq!(cache.source_map().len(), map.len());
+    }
+
+    fn doc() -> GValue {
+        crate::from_json(&json!({
+            "name": "report",
+            "meta": {"owner": "ann", "tags": ["a", "b"]},
+            "rows": [
+                {"id": 1, "v": "x"},
+                {"id": 2, "v": "y"},
+                {"id": 3, "v": "z"}
+            ],
+            "mixed": [1, {"k": 2}, "s"]
+        }))
+    }
+
+    #[test]
+    fn test_source_map_spans() {
+        let v = doc();
+        let (text, map) = canonicalize_loose_with_source_map(&v, &LooseCanonOpts::default()).unwrap();
+        let owner = map.get(&path("meta.owner")).unwrap();
+        assert_eq!(&text[owner.start..owner.end], "ann");
+        let rows = map.get(&path("rows")).unwrap();
+        assert!(rows.tabular);
+        assert!(text[rows.start..rows.end].starts_with("@tab"));
+        assert!(map.get(&path("rows[0].id")).is_none());
+        assert_eq!(map.get(&GlyphPath::root()).unwrap().end, text.len());
+    }
+
+    #[test]
+    fn test_incremental_value_edit() {
+        let mut cache = CanonCache::new(doc(), LooseCanonOpts::default()).unwrap();
+        let out = recanonicalize_incremental(
+            &mut cache,
+            &[
+                PathEdit::new(path("meta.owner"), GValue::str("bob smith")),
+                PathEdit::new(path("meta.tags[1]"), GValue::int(7)),
+                PathEdit::new(path("name"), GValue::null()),
+            ],
+        )
+        .unwrap();
+        assert!(out.contains("owner=\"bob smith\""));
+        check(&cache);
+    }
+
+    #[test]
+    fn test_incremental_new_key_and_tabular() {
+        let mut cache = CanonCache::new(doc(), LooseCanonOpts::default()).unwrap();
+        cache.apply(&PathEdit::new(path("meta.added"), GValue::bool(true))).unwrap();
+        check(&cache);
+        // Editing a cell re-emits the whole tabular block
+        cache.apply(&PathEdit::new(path("rows[1].v"), GValue::str("a|b"))).unwrap();
+        check(&cache);
+        // Replacing a row with a scalar drops the tabular form
+        cache.apply(&PathEdit::new(path("rows[2]"), GValue::int(0))).unwrap();
+        assert!(!cache.text().contains("@tab"));
+        check(&cache);
+        cache.apply(&PathEdit::new(path("mixed[1].k"), GValue::list(vec![]))).unwrap();
+        check(&cache);
+    }
+
+    #[test]
+    fn test_incremental_under_size_cap() {
+        let opts = LooseCanonOpts {
+            max_value_bytes_per_type: SizeCaps {
+                max_list_items: Some(2),
+                ..SizeCaps::default()
+            },
+            ..LooseCanonOpts::default()
+        };
+        let mut cache = CanonCache::new(doc(), opts).unwrap();
+        assert!(cache.text().contains("Trunc{kind=\"list\" len=3"));
+        cache.apply(&PathEdit::new(path("rows[0].v"), GValue::str("w"))).unwrap();
+        check(&cache);
+        cache.apply(&PathEdit::new(path("meta.tags[0]"), GValue::str("c"))).unwrap();
+        check(&cache);
+    }
+
+    #[test]
+    fn test_incremental_errors_leave_cache_intact() {
+        let mut cache = CanonCache::new(doc(), LooseCanonOpts::default()).unwrap();
+        let before = cache.text().to_string();
+        assert!(cache.apply(&PathEdit::new(path("nope.deeper"), GValue::int(1))).is_err());
+        assert!(cache.apply(&PathEdit::new(path("meta.owner"), GValue::float(f64::NAN))).is_err());
+        assert!(cache.apply(&PathEdit::new(path("meta.fresh"), GValue::float(f64::NAN))).is_err());
+        assert_eq!(cache.text(), before);
+        assert_eq!(cache.value(), &doc());
+        check(&cache);
+    }
+
+    #[test]
+    fn test_incremental_under_encode_limits() {
+        let truncate = |limits: EncodeLimits| LooseCanonOpts {
+            limits: EncodeLimits {
+                policy: LimitPolicy::Truncate,
+                ..limits
+            },
+            ..LooseCanonOpts::default()
+        };
+
+        // Re-emitted nodes are as deep as in the full document
+        let deep = crate::from_json(&json!({"a": {"b": 1}}));
+        let mut cache = CanonCache::new(deep, truncate(EncodeLimits { max_depth: Some(2), ..EncodeLimits::default() })).unwrap();
+        cache.apply(&PathEdit::new(path("a.b"), crate::from_json(&json!({"c": {"d": 2}})))).unwrap();
+        assert!(cache.text().starts_with("{a={b=Trunc{"), "{}", cache.text());
+        check(&cache);
+
+        // Items past max_list_len stay behind the placeholder
+        let list = crate::from_json(&json!({"l": [1, 2, 3]}));
+        let mut cache = CanonCache::new(list, truncate(EncodeLimits { max_list_len: Some(2), ..EncodeLimits::default() })).unwrap();
+        cache.apply(&PathEdit::new(path("l[2]"), GValue::int(9))).unwrap();
+        assert_eq!(cache.text(), "{l=[1 2 \"…+1 more\"]}");
+        check(&cache);
+        cache.apply(&PathEdit::new(path("l[0]"), GValue::int(0))).unwrap();
+        check(&cache);
+
+        // Over a limit with the error policy, the edit is rolled back
+        let strict = LooseCanonOpts {
+            limits: EncodeLimits { max_output_bytes: Some(12), ..EncodeLimits::default() },
+            ..LooseCanonOpts::default()
+        };
+        let mut cache = CanonCache::new(crate::from_json(&json!({"k": "v"})), strict).unwrap();
+        assert!(cache.apply(&PathEdit::new(path("k"), GValue::str("a long value"))).is_err());
+        assert_eq!(cache.text(), "{k=v}");
+        check(&cache);
+    }
+
+    #[test]
+    fn test_fingerprinted_value() {
+        let mut fv = FingerprintedValue::new(doc());
+        assert!(!fv.is_cached());
+        let first = fv.fingerprint().unwrap().to_string();
+        assert!(fv.is_cached());
+        assert_eq!(first, hash_loose(&doc()).unwrap());
+
+        fv.apply(&PathEdit::new(path("meta.owner"), GValue::str("bo"))).unwrap();
+        assert!(!fv.is_cached());
+        let edited = fv.fingerprint().unwrap().to_string();
+        assert_ne!(edited, first);
+        assert_eq!(edited, hash_loose(fv.value()).unwrap());
+
+        // A failed edit changes nothing and keeps the cache
+        assert!(fv.apply(&PathEdit::new(path("nope.deeper"), GValue::int(1))).is_err());
+        assert_eq!(fv.fingerprint().unwrap(), edited);
+
+        fv.update(|v| *v = doc());
+        assert_eq!(fv.fingerprint().unwrap(), first);
+        fv.replace(GValue::float(f64::NAN));
+        assert!(fv.fingerprint().is_err());
+        assert!(!fv.is_cached());
+    }
+}
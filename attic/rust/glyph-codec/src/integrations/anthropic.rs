@@ -0,0 +1,233 @@
+//! Anthropic Messages content blocks
+//!
+//! Anthropic puts calls and results in a message's `content` array: a
+//! `tool_use` block `{id, name, input}` from the assistant and a
+//! `tool_result` block `{tool_use_id, content, is_error}` from the user.
+//! `from_anthropic` turns those blocks into `ToolCall` and `ToolResult`
+//! values, ids as `^toolu_...` refs and `is_error` as `status=error`, and
+//! skips text and other blocks; `to_anthropic_block` and
+//! `to_anthropic_message` go back.
+//!
+//! A result's `content` is either a string or a nested array of blocks.
+//! Text blocks become their text, other blocks (images, documents) are kept
+//! as maps. `to_anthropic_tool_result` answers a call with its result in
+//! canonical GLYPH.
+
+use super::{ref_id, ref_text};
+use crate::envelope::{ToolCall, ToolResult, ToolStatus, TOOL_CALL_TYPE, TOOL_RESULT_TYPE};
+use crate::error::GlyphError;
+use crate::json_bridge::{try_from_json, try_to_json};
+use crate::loose::canonicalize_loose;
+use crate::types::*;
+use serde_json::{json, Value as JsonValue};
+
+/// Every `tool_use` and `tool_result` block in a Messages response, a
+/// message, a `content` array or a single block, in order
+pub fn from_anthropic(json: &JsonValue) -> Result<Vec<GValue>, GlyphError> {
+    let blocks = match json.get("content").unwrap_or(json) {
+        JsonValue::Array(blocks) => blocks.as_slice(),
+        // A plain string message has no blocks
+        JsonValue::String(_) => &[],
+        block => std::slice::from_ref(block),
+    };
+    let mut out = Vec::new();
+    for block in blocks {
+        if let Some(v) = from_anthropic_block(block)? {
+            out.push(v);
+        }
+    }
+    Ok(out)
+}
+
+/// A `tool_use` block as a `ToolCall`, a `tool_result` block as a
+/// `ToolResult`; `None` for any other block
+pub fn from_anthropic_block(block: &JsonValue) -> Result<Option<GValue>, GlyphError> {
+    match block.get("type").and_then(JsonValue::as_str) {
+        Some("tool_use") => {
+            let id = block.get("id").and_then(JsonValue::as_str);
+            let name = block
+                .get("name")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| GlyphError::MissingField("tool_use.name".to_string()))?;
+            let args = match block.get("input") {
+                None | Some(JsonValue::Null) => GValue::map(vec![]),
+                Some(input) => try_from_json(input)?,
+            };
+            let mut call = ToolCall::new(name, args);
+            call.id = id.map(ref_id);
+            Ok(Some(call.to_value()))
+        }
+        Some("tool_result") => {
+            let id = block
+                .get("tool_use_id")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| GlyphError::MissingField("tool_result.tool_use_id".to_string()))?;
+            let content = match block.get("content") {
+                None | Some(JsonValue::Null) => GValue::Null,
+                Some(JsonValue::Array(blocks)) => GValue::list(
+                    blocks
+                        .iter()
+                        .map(content_from_block)
+                        .collect::<Result<_, _>>()?,
+                ),
+                Some(content) => try_from_json(content)?,
+            };
+            let mut result = ToolResult::ok(ref_id(id), content);
+            if block.get("is_error").and_then(JsonValue::as_bool).unwrap_or(false) {
+                result.status = ToolStatus::Error;
+            }
+            Ok(Some(result.to_value()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// A `ToolCall` as a `tool_use` block, a `ToolResult` as a `tool_result`
+/// block
+pub fn to_anthropic_block(v: &GValue) -> Result<JsonValue, GlyphError> {
+    match v.as_struct().map(|s| s.type_name.as_str()) {
+        Some(TOOL_CALL_TYPE) => {
+            let call = ToolCall::from_value(v)?;
+            let id = call.id.ok_or_else(|| GlyphError::MissingField("id".to_string()))?;
+            Ok(json!({
+                "type": "tool_use",
+                "id": ref_text(&id),
+                "name": call.name,
+                "input": try_to_json(&call.args)?,
+            }))
+        }
+        Some(TOOL_RESULT_TYPE) => {
+            let result = ToolResult::from_value(v)?;
+            // A failure with nothing but a message sends the message
+            let content = match (&result.content, &result.error) {
+                (GValue::Null, Some(error)) => JsonValue::String(error.clone()),
+                (content, _) => content_to_json(content)?,
+            };
+            let mut block = json!({
+                "type": "tool_result",
+                "tool_use_id": ref_text(&result.id),
+                "content": content,
+            });
+            if !result.is_ok() {
+                block["is_error"] = JsonValue::Bool(true);
+            }
+            Ok(block)
+        }
+        _ => Err(GlyphError::TypeMismatch {
+            expected: format!("{} or {}", TOOL_CALL_TYPE, TOOL_RESULT_TYPE),
+            got: v.gtype().to_string(),
+        }),
+    }
+}
+
+/// A message from `role` whose content is `values` as blocks
+pub fn to_anthropic_message(role: &str, values: &[GValue]) -> Result<JsonValue, GlyphError> {
+    let content = values
+        .iter()
+        .map(to_anthropic_block)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({"role": role, "content": content}))
+}
+
+/// A `tool_result` block answering `tool_use_id`, with `result` as its
+/// canonical GLYPH text
+pub fn to_anthropic_tool_result(tool_use_id: &str, result: &GValue) -> Result<JsonValue, GlyphError> {
+    Ok(json!({
+        "type": "tool_result",
+        "tool_use_id": tool_use_id,
+        "content": canonicalize_loose(result)?,
+    }))
+}
+
+fn content_from_block(block: &JsonValue) -> Result<GValue, GlyphError> {
+    match (block.get("type").and_then(JsonValue::as_str), block.get("text")) {
+        (Some("text"), Some(JsonValue::String(text))) => Ok(GValue::str(text.as_str())),
+        _ => try_from_json(block),
+    }
+}
+
+/// Strings stay strings; a list goes back to blocks, strings as text blocks;
+/// anything else is sent as its canonical GLYPH
+fn content_to_json(content: &GValue) -> Result<JsonValue, GlyphError> {
+    match content {
+        GValue::Null => Ok(JsonValue::Array(vec![])),
+        GValue::Str(s) => Ok(JsonValue::String(s.to_string())),
+        GValue::List(items) => items
+            .iter()
+            .map(|item| match item {
+                GValue::Str(s) => Ok(json!({"type": "text", "text": s})),
+                GValue::Map(_) if item.get("type").is_some_and(GValue::is_str) => try_to_json(item),
+                other => Ok(json!({"type": "text", "text": canonicalize_loose(other)?})),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(JsonValue::Array),
+        other => Ok(JsonValue::String(canonicalize_loose(other)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_anthropic_tool_use() {
+        let response = json!({
+            "role": "assistant",
+            "content": [
+                {"type": "text", "text": "Searching."},
+                {"type": "tool_use", "id": "toolu_01A", "name": "search", "input": {"query": "glyph", "limit": 5}}
+            ]
+        });
+        let values = from_anthropic(&response).unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(
+            canonicalize_loose(&values[0]).unwrap(),
+            "ToolCall{args={limit=5 query=glyph} id=^toolu_01A name=search}"
+        );
+
+        let message = to_anthropic_message("assistant", &values).unwrap();
+        assert_eq!(message["content"][0], response["content"][1]);
+        assert_eq!(from_anthropic(&message).unwrap(), values);
+    }
+
+    #[test]
+    fn test_from_anthropic_tool_result() {
+        let message = json!({
+            "role": "user",
+            "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_01A", "content": [
+                    {"type": "text", "text": "3 hits"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBO"}}
+                ]},
+                {"type": "tool_result", "tool_use_id": "toolu_01B", "content": "timeout", "is_error": true}
+            ]
+        });
+        let values = from_anthropic(&message).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            values[0].get("id"),
+            Some(&GValue::Id(RefId::simple("toolu_01A")))
+        );
+        assert_eq!(values[0]["content"][0], GValue::str("3 hits"));
+        assert_eq!(values[0]["content"][1]["source"]["media_type"], GValue::str("image/png"));
+        assert_eq!(
+            canonicalize_loose(&values[1]).unwrap(),
+            "ToolResult{content=timeout id=^toolu_01B status=error}"
+        );
+
+        assert_eq!(to_anthropic_message("user", &values).unwrap(), message);
+    }
+
+    #[test]
+    fn test_to_anthropic_tool_result() {
+        let result = GValue::map(vec![MapEntry::new("hits", GValue::int(3))]);
+        assert_eq!(
+            to_anthropic_tool_result("toolu_01A", &result).unwrap(),
+            json!({"type": "tool_result", "tool_use_id": "toolu_01A", "content": "{hits=3}"})
+        );
+        assert!(matches!(
+            to_anthropic_block(&GValue::int(1)),
+            Err(GlyphError::TypeMismatch { .. })
+        ));
+    }
+}
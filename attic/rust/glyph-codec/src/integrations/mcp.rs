@@ -0,0 +1,278 @@
+//! MCP (Model Context Protocol) JSON-RPC messages
+//!
+//! MCP speaks JSON-RPC 2.0. `from_mcp` turns an envelope into one of four
+//! structs, dropping the constant `jsonrpc` member:
+//!
+//! - `Request{id=1 method="tools/call" params={...}}`
+//! - `Notification{method="notifications/initialized"}`
+//! - `Response{id=1 result={...}}`
+//! - `Error{code=-32601 id=1 message="..."}`, with `data` when the error has it
+//!
+//! and `to_mcp` goes back. A batch is a list of them.
+//!
+//! `McpMode::Framed` is for injecting traffic into a model's context next to
+//! tooling that still expects JSON-RPC: the envelope stays JSON, and only
+//! `params` and `result`, which MCP always sends as objects, become a string
+//! of canonical GLYPH.
+
+use crate::error::GlyphError;
+use crate::json_bridge::{try_from_json, try_to_json};
+use crate::loose::canonicalize_loose;
+use crate::parse::parse_loose;
+use crate::types::*;
+use serde_json::{Map, Value as JsonValue};
+
+const JSONRPC_VERSION: &str = "2.0";
+
+/// How `encode_mcp` writes a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum McpMode {
+    /// The whole envelope as canonical GLYPH
+    #[default]
+    Glyph,
+    /// JSON-RPC framing kept, `params` and `result` as GLYPH strings
+    Framed,
+}
+
+/// A JSON-RPC message (or batch) as its GLYPH struct (or list of them)
+pub fn from_mcp(msg: &JsonValue) -> Result<GValue, GlyphError> {
+    let obj = match msg {
+        JsonValue::Array(batch) => {
+            return batch
+                .iter()
+                .map(from_mcp)
+                .collect::<Result<_, _>>()
+                .map(GValue::List)
+        }
+        JsonValue::Object(obj) => obj,
+        other => {
+            return Err(GlyphError::TypeMismatch {
+                expected: "JSON-RPC object".to_string(),
+                got: json_kind(other).to_string(),
+            })
+        }
+    };
+    let id = obj.get("id").map(try_from_json).transpose()?;
+    let mut fields = Vec::new();
+    let type_name = if let Some(method) = obj.get("method") {
+        let method = method.as_str().ok_or_else(|| GlyphError::TypeMismatch {
+            expected: "str".to_string(),
+            got: json_kind(method).to_string(),
+        })?;
+        if let Some(id) = id {
+            fields.push(MapEntry::new("id", id));
+        }
+        fields.push(MapEntry::new("method", GValue::str(method)));
+        if let Some(params) = obj.get("params") {
+            fields.push(MapEntry::new("params", try_from_json(params)?));
+        }
+        if obj.contains_key("id") {
+            "Request"
+        } else {
+            "Notification"
+        }
+    } else if let Some(error) = obj.get("error") {
+        let code = error
+            .get("code")
+            .and_then(JsonValue::as_i64)
+            .ok_or_else(|| GlyphError::MissingField("error.code".to_string()))?;
+        let message = error
+            .get("message")
+            .and_then(JsonValue::as_str)
+            .unwrap_or_default();
+        fields.push(MapEntry::new("code", GValue::int(code)));
+        if let Some(data) = error.get("data") {
+            fields.push(MapEntry::new("data", try_from_json(data)?));
+        }
+        fields.push(MapEntry::new("id", id.unwrap_or(GValue::Null)));
+        fields.push(MapEntry::new("message", GValue::str(message)));
+        "Error"
+    } else if let Some(result) = obj.get("result") {
+        fields.push(MapEntry::new("id", id.unwrap_or(GValue::Null)));
+        fields.push(MapEntry::new("result", try_from_json(result)?));
+        "Response"
+    } else {
+        return Err(GlyphError::MissingField(
+            "method, result or error".to_string(),
+        ));
+    };
+    Ok(GValue::struct_val(type_name, fields))
+}
+
+/// A struct from `from_mcp` (or a list of them) back to JSON-RPC
+pub fn to_mcp(v: &GValue) -> Result<JsonValue, GlyphError> {
+    if let GValue::List(batch) = v {
+        return batch
+            .iter()
+            .map(to_mcp)
+            .collect::<Result<_, _>>()
+            .map(JsonValue::Array);
+    }
+    let s = v.try_as_struct()?;
+    let mut obj = Map::new();
+    obj.insert(
+        "jsonrpc".to_string(),
+        JsonValue::String(JSONRPC_VERSION.to_string()),
+    );
+    match s.type_name.as_str() {
+        "Request" | "Notification" => {
+            if s.type_name == "Request" {
+                obj.insert("id".to_string(), try_to_json(v.try_get("id")?)?);
+            }
+            obj.insert(
+                "method".to_string(),
+                JsonValue::String(v.try_get("method")?.try_as_str()?.to_string()),
+            );
+            if let Some(params) = v.get("params") {
+                obj.insert("params".to_string(), try_to_json(params)?);
+            }
+        }
+        "Response" => {
+            obj.insert("id".to_string(), try_to_json(v.try_get("id")?)?);
+            obj.insert("result".to_string(), try_to_json(v.try_get("result")?)?);
+        }
+        "Error" => {
+            obj.insert(
+                "id".to_string(),
+                try_to_json(v.get("id").unwrap_or(&GValue::Null))?,
+            );
+            let mut error = Map::new();
+            error.insert(
+                "code".to_string(),
+                JsonValue::from(v.try_get("code")?.try_as_int()?),
+            );
+            error.insert(
+                "message".to_string(),
+                JsonValue::String(v.try_get("message")?.try_as_str()?.to_string()),
+            );
+            if let Some(data) = v.get("data") {
+                error.insert("data".to_string(), try_to_json(data)?);
+            }
+            obj.insert("error".to_string(), JsonValue::Object(error));
+        }
+        other => {
+            return Err(GlyphError::TypeMismatch {
+                expected: "Request, Notification, Response or Error".to_string(),
+                got: other.to_string(),
+            })
+        }
+    }
+    Ok(JsonValue::Object(obj))
+}
+
+/// `msg` as text in `mode`
+pub fn encode_mcp(msg: &JsonValue, mode: McpMode) -> Result<String, GlyphError> {
+    match mode {
+        McpMode::Glyph => canonicalize_loose(&from_mcp(msg)?),
+        McpMode::Framed => {
+            let framed = map_payloads(msg, &|payload| {
+                let glyph = canonicalize_loose(&try_from_json(payload)?)?;
+                Ok(JsonValue::String(glyph))
+            })?;
+            Ok(serde_json::to_string(&framed)?)
+        }
+    }
+}
+
+/// Text from `encode_mcp` in the same `mode` back to a JSON-RPC message
+pub fn decode_mcp(text: &str, mode: McpMode) -> Result<JsonValue, GlyphError> {
+    match mode {
+        McpMode::Glyph => to_mcp(&parse_loose(text)?),
+        McpMode::Framed => {
+            let msg: JsonValue = serde_json::from_str(text)?;
+            map_payloads(&msg, &|payload| match payload {
+                JsonValue::String(glyph) => try_to_json(&parse_loose(glyph)?),
+                other => Ok(other.clone()),
+            })
+        }
+    }
+}
+
+/// `msg` with `f` applied to each `params` and `result`
+fn map_payloads(
+    msg: &JsonValue,
+    f: &dyn Fn(&JsonValue) -> Result<JsonValue, GlyphError>,
+) -> Result<JsonValue, GlyphError> {
+    match msg {
+        JsonValue::Array(batch) => batch
+            .iter()
+            .map(|m| map_payloads(m, f))
+            .collect::<Result<_, _>>()
+            .map(JsonValue::Array),
+        JsonValue::Object(obj) => {
+            let mut out = obj.clone();
+            for key in ["params", "result"] {
+                if let Some(payload) = obj.get(key) {
+                    out.insert(key.to_string(), f(payload)?);
+                }
+            }
+            Ok(JsonValue::Object(out))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+fn json_kind(v: &JsonValue) -> &'static str {
+    match v {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "bool",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "str",
+        JsonValue::Array(_) => "list",
+        JsonValue::Object(_) => "map",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mcp_envelopes() {
+        let request = json!({
+            "jsonrpc": "2.0", "id": 1, "method": "tools/call",
+            "params": {"name": "search", "arguments": {"query": "glyph"}}
+        });
+        let v = from_mcp(&request).unwrap();
+        assert_eq!(
+            canonicalize_loose(&v).unwrap(),
+            r#"Request{id=1 method="tools/call" params={arguments={query=glyph} name=search}}"#
+        );
+        assert_eq!(to_mcp(&v).unwrap(), request);
+
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "notifications/initialized"},
+            {"jsonrpc": "2.0", "id": "a", "result": {"content": [{"type": "text", "text": "ok"}]}},
+            {"jsonrpc": "2.0", "id": 2, "error": {"code": -32601, "message": "Method not found"}}
+        ]);
+        let v = from_mcp(&batch).unwrap();
+        assert_eq!(v[0].as_struct().unwrap().type_name, "Notification");
+        assert_eq!(
+            canonicalize_loose(&v[2]).unwrap(),
+            r#"Error{code=-32601 id=2 message="Method not found"}"#
+        );
+        assert_eq!(to_mcp(&v).unwrap(), batch);
+
+        assert!(matches!(
+            from_mcp(&json!({"jsonrpc": "2.0", "id": 3})),
+            Err(GlyphError::MissingField(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_mcp_modes() {
+        let response =
+            json!({"jsonrpc": "2.0", "id": 7, "result": {"isError": false, "hits": [1, 2]}});
+
+        let glyph = encode_mcp(&response, McpMode::Glyph).unwrap();
+        assert_eq!(glyph, "Response{id=7 result={hits=[1 2] isError=f}}");
+        assert_eq!(decode_mcp(&glyph, McpMode::Glyph).unwrap(), response);
+
+        let framed = encode_mcp(&response, McpMode::Framed).unwrap();
+        let json: JsonValue = serde_json::from_str(&framed).unwrap();
+        assert_eq!(json["result"], "{hits=[1 2] isError=f}");
+        assert_eq!(json["id"], 7);
+        assert_eq!(decode_mcp(&framed, McpMode::Framed).unwrap(), response);
+    }
+}
@@ -0,0 +1,27 @@
+//! Converters for model vendors' tool-call formats and MCP
+//!
+//! Each vendor wraps a tool call in its own JSON envelope. The vendor
+//! modules turn those envelopes into the crate's `ToolCall` (and results
+//! into `ToolResult`) in their GLYPH form and back, so the rest of an agent
+//! works on one shape whichever API it talks to. `mcp` does the same for
+//! MCP's JSON-RPC envelopes.
+
+use crate::types::RefId;
+
+pub mod anthropic;
+pub mod mcp;
+pub mod openai;
+
+/// A vendor's call id as a ref; ids are opaque, so the whole text is the value
+pub(crate) fn ref_id(id: &str) -> RefId {
+    RefId::simple(id)
+}
+
+/// The id text a vendor expects back, `prefix:value` for prefixed refs
+pub(crate) fn ref_text(id: &RefId) -> String {
+    if id.prefix.is_empty() {
+        id.value.clone()
+    } else {
+        format!("{}:{}", id.prefix, id.value)
+    }
+}
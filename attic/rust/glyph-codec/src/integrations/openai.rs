@@ -0,0 +1,180 @@
+//! OpenAI chat-completions tool calls
+//!
+//! OpenAI sends a call as `{id, type: "function", function: {name,
+//! arguments}}` with the arguments as a JSON-encoded string. `from_openai`
+//! pulls every call out of a completion response, an assistant message or a
+//! bare `tool_calls` array and decodes the arguments; `to_openai_message`
+//! builds the assistant message back. `to_openai_tool_result` answers a call
+//! with its result in canonical GLYPH, which is where the tokens are saved.
+
+use super::{ref_id, ref_text};
+use crate::envelope::ToolCall;
+use crate::error::GlyphError;
+use crate::json_bridge::{try_from_json, try_to_json};
+use crate::loose::canonicalize_loose;
+use crate::types::*;
+use serde_json::{json, Value as JsonValue};
+
+/// Every tool call in a chat completion response, an assistant message, a
+/// `tool_calls` array or a single call. A response's calls come from its
+/// first choice; a message without calls yields none.
+pub fn from_openai(json: &JsonValue) -> Result<Vec<GValue>, GlyphError> {
+    if let Some(choices) = json.get("choices") {
+        return match choices.get(0).and_then(|c| c.get("message")) {
+            Some(message) => from_openai(message),
+            None => Ok(Vec::new()),
+        };
+    }
+    if let Some(calls) = json.get("tool_calls") {
+        return match calls {
+            JsonValue::Null => Ok(Vec::new()),
+            _ => from_openai(calls),
+        };
+    }
+    // Legacy single-call messages
+    if let Some(call) = json.get("function_call") {
+        return Ok(vec![from_openai_tool_call(call)?]);
+    }
+    if json.get("role").is_some() {
+        return Ok(Vec::new());
+    }
+    match json {
+        JsonValue::Array(calls) => calls.iter().map(from_openai_tool_call).collect(),
+        _ => Ok(vec![from_openai_tool_call(json)?]),
+    }
+}
+
+/// One `tool_calls` entry (or a legacy `function_call` object) as a
+/// `ToolCall` struct
+pub fn from_openai_tool_call(call: &JsonValue) -> Result<GValue, GlyphError> {
+    let id = call.get("id").and_then(JsonValue::as_str);
+    let function = call.get("function").unwrap_or(call);
+    let name = function
+        .get("name")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| GlyphError::MissingField("function.name".to_string()))?;
+    let args = match function.get("arguments") {
+        None | Some(JsonValue::Null) => GValue::map(vec![]),
+        Some(JsonValue::String(text)) if text.trim().is_empty() => GValue::map(vec![]),
+        Some(JsonValue::String(text)) => {
+            let json: JsonValue = serde_json::from_str(text).map_err(|e| {
+                GlyphError::Parse(format!("arguments of {} call: {}", name, e))
+            })?;
+            try_from_json(&json)?
+        }
+        // Some compatible servers send the arguments already decoded
+        Some(other) => try_from_json(other)?,
+    };
+    let mut call = ToolCall::new(name, args);
+    call.id = id.map(ref_id);
+    Ok(call.to_value())
+}
+
+/// A `ToolCall` as a `tool_calls` entry, arguments re-encoded as a JSON
+/// string
+pub fn to_openai_tool_call(call: &GValue) -> Result<JsonValue, GlyphError> {
+    let call = ToolCall::from_value(call)?;
+    let arguments = serde_json::to_string(&try_to_json(&call.args)?)?;
+    let mut json = json!({
+        "type": "function",
+        "function": {"name": call.name, "arguments": arguments},
+    });
+    if let Some(id) = &call.id {
+        json["id"] = JsonValue::String(ref_text(id));
+    }
+    Ok(json)
+}
+
+/// An assistant message carrying `calls`
+pub fn to_openai_message(calls: &[GValue]) -> Result<JsonValue, GlyphError> {
+    let tool_calls = calls
+        .iter()
+        .map(to_openai_tool_call)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({
+        "role": "assistant",
+        "content": null,
+        "tool_calls": tool_calls,
+    }))
+}
+
+/// A `role: "tool"` message answering call `call_id`, with `result` as its
+/// canonical GLYPH text
+pub fn to_openai_tool_result(call_id: &str, result: &GValue) -> Result<JsonValue, GlyphError> {
+    Ok(json!({
+        "role": "tool",
+        "tool_call_id": call_id,
+        "content": canonicalize_loose(result)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_openai_response() {
+        let response = json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [
+                        {
+                            "id": "call_abc",
+                            "type": "function",
+                            "function": {"name": "search", "arguments": "{\"query\":\"glyph\",\"limit\":5}"}
+                        },
+                        {
+                            "id": "call_def",
+                            "type": "function",
+                            "function": {"name": "now", "arguments": ""}
+                        }
+                    ]
+                }
+            }]
+        });
+        let calls = from_openai(&response).unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(
+            canonicalize_loose(&calls[0]).unwrap(),
+            "ToolCall{args={limit=5 query=glyph} id=^call_abc name=search}"
+        );
+        assert_eq!(calls[1].get("args"), Some(&GValue::map(vec![])));
+
+        let message = to_openai_message(&calls).unwrap();
+        assert_eq!(message["tool_calls"][0]["id"], "call_abc");
+        assert_eq!(
+            message["tool_calls"][0]["function"]["arguments"],
+            "{\"limit\":5,\"query\":\"glyph\"}"
+        );
+        assert_eq!(from_openai(&message).unwrap(), calls);
+    }
+
+    #[test]
+    fn test_openai_shapes() {
+        let legacy = json!({"role": "assistant", "function_call": {"name": "now", "arguments": "{}"}});
+        let calls = from_openai(&legacy).unwrap();
+        assert_eq!(canonicalize_loose(&calls[0]).unwrap(), "ToolCall{args={} name=now}");
+        assert!(to_openai_tool_call(&calls[0]).unwrap().get("id").is_none());
+
+        assert!(from_openai(&json!({"role": "assistant", "content": "hi"})).unwrap().is_empty());
+        assert!(from_openai(&json!({"role": "assistant", "tool_calls": null})).unwrap().is_empty());
+
+        let bad = json!({"id": "call_x", "function": {"name": "search", "arguments": "{query"}});
+        assert!(matches!(from_openai_tool_call(&bad), Err(GlyphError::Parse(_))));
+        assert!(matches!(
+            from_openai_tool_call(&json!({"id": "call_x"})),
+            Err(GlyphError::MissingField(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_openai_tool_result() {
+        let result = GValue::map(vec![MapEntry::new("hits", GValue::int(3))]);
+        assert_eq!(
+            to_openai_tool_result("call_abc", &result).unwrap(),
+            json!({"role": "tool", "tool_call_id": "call_abc", "content": "{hits=3}"})
+        );
+    }
+}
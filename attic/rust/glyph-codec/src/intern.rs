@@ -0,0 +1,283 @@
+//! Hash-consing of repeated subtrees
+//!
+//! Long agent sessions repeat the same sub-objects many times: identical tool
+//! schemas, identical error payloads. An `Interner` turns values into
+//! `SharedValue` trees where every distinct subtree (and map key) is stored
+//! once and shared through `Arc`, however many values contain it.
+//!
+//! Interning is exact: subtrees are shared only if they are identical, so
+//! `1` and `1.0` or two key orders of one map stay separate nodes.
+
+use crate::types::*;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// A value whose subtrees may be shared with other interned values
+#[derive(Debug, Clone, PartialEq)]
+pub enum SharedValue {
+    /// Anything without interned children: scalars and tables
+    Scalar(GValue),
+    List(Vec<Arc<SharedValue>>),
+    Map(Vec<(Arc<str>, Arc<SharedValue>)>),
+    Struct {
+        type_name: Arc<str>,
+        fields: Vec<(Arc<str>, Arc<SharedValue>)>,
+    },
+    Sum {
+        tag: Arc<str>,
+        value: Option<Arc<SharedValue>>,
+    },
+}
+
+impl SharedValue {
+    /// Rebuild the plain value
+    pub fn to_value(&self) -> GValue {
+        let entries = |fields: &[(Arc<str>, Arc<SharedValue>)]| {
+            fields.iter().map(|(k, v)| MapEntry::new(k.to_string(), v.to_value())).collect()
+        };
+        match self {
+            SharedValue::Scalar(v) => v.clone(),
+            SharedValue::List(items) => GValue::List(items.iter().map(|v| v.to_value()).collect()),
+            SharedValue::Map(fields) => GValue::Map(entries(fields)),
+            SharedValue::Struct { type_name, fields } => GValue::Struct(StructValue {
+                type_name: type_name.to_string(),
+                fields: entries(fields),
+            }),
+            SharedValue::Sum { tag, value } => GValue::Sum(SumValue {
+                tag: tag.to_string(),
+                value: value.as_ref().map(|v| Box::new(v.to_value())),
+            }),
+        }
+    }
+}
+
+/// Node stored in the interner, compared by content with children by identity
+struct Node(Arc<SharedValue>);
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        use SharedValue::*;
+        let same_fields = |a: &[(Arc<str>, Arc<SharedValue>)], b: &[(Arc<str>, Arc<SharedValue>)]| {
+            a.len() == b.len() && a.iter().zip(b).all(|((ka, va), (kb, vb))| ka == kb && Arc::ptr_eq(va, vb))
+        };
+        match (&*self.0, &*other.0) {
+            (Scalar(a), Scalar(b)) => same_scalar(a, b),
+            (List(a), List(b)) => a.len() == b.len() && a.iter().zip(b).all(|(x, y)| Arc::ptr_eq(x, y)),
+            (Map(a), Map(b)) => same_fields(a, b),
+            (Struct { type_name: ta, fields: a }, Struct { type_name: tb, fields: b }) => ta == tb && same_fields(a, b),
+            (Sum { tag: ta, value: a }, Sum { tag: tb, value: b }) => {
+                ta == tb
+                    && match (a, b) {
+                        (Some(x), Some(y)) => Arc::ptr_eq(x, y),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Node {}
+
+impl Hash for Node {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&*self.0).hash(state);
+        match &*self.0 {
+            SharedValue::Scalar(v) => hash_scalar(v, state),
+            SharedValue::List(items) => items.iter().for_each(|v| Arc::as_ptr(v).hash(state)),
+            SharedValue::Map(fields) => hash_fields(fields, state),
+            SharedValue::Struct { type_name, fields } => {
+                type_name.hash(state);
+                hash_fields(fields, state);
+            }
+            SharedValue::Sum { tag, value } => {
+                tag.hash(state);
+                value.as_ref().map(Arc::as_ptr).hash(state);
+            }
+        }
+    }
+}
+
+fn hash_fields<H: Hasher>(fields: &[(Arc<str>, Arc<SharedValue>)], state: &mut H) {
+    for (k, v) in fields {
+        k.hash(state);
+        Arc::as_ptr(v).hash(state);
+    }
+}
+
+/// Identical scalars; floats by bit pattern, so `0.0` and `-0.0` stay apart
+fn same_scalar(a: &GValue, b: &GValue) -> bool {
+    match (a, b) {
+        (GValue::Float(x), GValue::Float(y)) => x.to_bits() == y.to_bits(),
+        _ => a == b,
+    }
+}
+
+fn hash_scalar<H: Hasher>(v: &GValue, state: &mut H) {
+    std::mem::discriminant(v).hash(state);
+    match v {
+        GValue::Bool(b) => b.hash(state),
+        GValue::Int(n) => n.hash(state),
+        GValue::Float(f) => f.to_bits().hash(state),
+        GValue::Str(s) => s.hash(state),
+        GValue::Bytes(b) => b.hash(state),
+        GValue::Time(t) => t.hash(state),
+        GValue::Id(r) => r.hash(state),
+        // Cells are compared on a hash match
+        GValue::Table(t) => {
+            t.columns.hash(state);
+            t.rows.len().hash(state);
+        }
+        _ => {}
+    }
+}
+
+/// Shares identical subtrees across every value passed to `intern`
+#[derive(Default)]
+pub struct Interner {
+    nodes: HashSet<Node>,
+    keys: HashSet<Arc<str>>,
+    hits: usize,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intern `v`, reusing any subtree seen before
+    pub fn intern(&mut self, v: &GValue) -> Arc<SharedValue> {
+        let node = match v {
+            GValue::List(items) => SharedValue::List(items.iter().map(|item| self.intern(item)).collect()),
+            GValue::Map(entries) => SharedValue::Map(self.intern_entries(entries)),
+            GValue::Struct(s) => SharedValue::Struct {
+                type_name: self.key(&s.type_name),
+                fields: self.intern_entries(&s.fields),
+            },
+            GValue::Sum(s) => SharedValue::Sum {
+                tag: self.key(&s.tag),
+                value: s.value.as_deref().map(|inner| self.intern(inner)),
+            },
+            scalar => SharedValue::Scalar(scalar.clone()),
+        };
+        let node = Node(Arc::new(node));
+        if let Some(existing) = self.nodes.get(&node) {
+            self.hits += 1;
+            return existing.0.clone();
+        }
+        let shared = node.0.clone();
+        self.nodes.insert(node);
+        shared
+    }
+
+    fn intern_entries(&mut self, entries: &[MapEntry]) -> Vec<(Arc<str>, Arc<SharedValue>)> {
+        entries.iter().map(|e| (self.key(&e.key), self.intern(&e.value))).collect()
+    }
+
+    fn key(&mut self, k: &str) -> Arc<str> {
+        if let Some(existing) = self.keys.get(k) {
+            return existing.clone();
+        }
+        let key: Arc<str> = Arc::from(k);
+        self.keys.insert(key.clone());
+        key
+    }
+
+    /// Number of distinct subtrees held
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Subtrees found already interned, over the interner's lifetime
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Forget subtrees (and keys) no interned value still uses.
+    /// Returns how many subtrees were dropped.
+    pub fn collect(&mut self) -> usize {
+        let before = self.nodes.len();
+        // Dropping a parent releases its children, so repeat until stable
+        loop {
+            let len = self.nodes.len();
+            self.nodes.retain(|n| Arc::strong_count(&n.0) > 1);
+            if self.nodes.len() == len {
+                break;
+            }
+        }
+        self.keys.retain(|k| Arc::strong_count(k) > 1);
+        before - self.nodes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> GValue {
+        GValue::build_map()
+            .str("name", "search")
+            .value(
+                "params",
+                GValue::build_map().value("query", GValue::build_map().str("type", "string").finish()).finish(),
+            )
+            .finish()
+    }
+
+    fn turn(i: i64) -> GValue {
+        GValue::build_map().int("turn", i).value("tools", GValue::list(vec![schema(), schema()])).finish()
+    }
+
+    fn tools(v: &SharedValue) -> &Vec<Arc<SharedValue>> {
+        let SharedValue::Map(fields) = v else { panic!("not a map") };
+        match &*fields.iter().find(|(k, _)| &**k == "tools").unwrap().1 {
+            SharedValue::List(items) => items,
+            other => panic!("not a list: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_identical_subtrees_are_shared() {
+        let mut interner = Interner::new();
+        let a = interner.intern(&turn(1));
+        let b = interner.intern(&turn(2));
+        assert!(!Arc::ptr_eq(&a, &b));
+        assert!(Arc::ptr_eq(&tools(&a)[0], &tools(&a)[1]));
+        assert!(Arc::ptr_eq(&tools(&a)[0], &tools(&b)[1]));
+        assert!(Arc::ptr_eq(&interner.intern(&turn(1)), &a));
+        assert_eq!(a.to_value(), turn(1));
+        assert!(interner.hits() > 0);
+
+        // Exact identity only
+        let int = interner.intern(&GValue::int(1));
+        assert!(!Arc::ptr_eq(&int, &interner.intern(&GValue::float(1.0))));
+        assert!(!Arc::ptr_eq(&interner.intern(&GValue::float(0.0)), &interner.intern(&GValue::float(-0.0))));
+        let reordered = GValue::build_map().str("b", "x").str("a", "y").finish();
+        let ordered = GValue::build_map().str("a", "y").str("b", "x").finish();
+        assert!(!Arc::ptr_eq(&interner.intern(&reordered), &interner.intern(&ordered)));
+    }
+
+    #[test]
+    fn test_collect_drops_unused() {
+        let mut interner = Interner::new();
+        let kept = interner.intern(&turn(1));
+        let distinct = interner.len();
+        drop(interner.intern(&GValue::build_map().str("error", "timeout").finish()));
+        assert!(interner.len() > distinct);
+
+        assert!(interner.collect() > 0);
+        assert_eq!(interner.len(), distinct);
+        assert!(Arc::ptr_eq(&interner.intern(&turn(1)), &kept));
+        assert!(!interner.keys.iter().any(|k| &**k == "error"));
+
+        drop(kept);
+        interner.collect();
+        assert!(interner.is_empty());
+    }
+}
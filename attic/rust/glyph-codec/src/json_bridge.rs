@@ -34,7 +34,7 @@ fn from_json_with_depth(json: &JsonValue, depth: usize) -> Result<GValue> {
                 GValue::Float(0.0)
             }
         }
-        JsonValue::String(s) => GValue::Str(s.clone()),
+        JsonValue::String(s) => GValue::str(s.as_str()),
         JsonValue::Array(arr) => {
             let mut items = Vec::with_capacity(arr.len());
             for item in arr {
@@ -77,7 +77,7 @@ fn to_json_with_depth(gv: &GValue, depth: usize) -> Result<JsonValue> {
                 .map(JsonValue::Number)
                 .unwrap_or(JsonValue::Null)
         }
-        GValue::Str(s) => JsonValue::String(s.clone()),
+        GValue::Str(s) => JsonValue::String(s.to_string()),
         GValue::Bytes(data) => {
             use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
             JsonValue::String(BASE64.encode(data))
@@ -100,14 +100,14 @@ fn to_json_with_depth(gv: &GValue, depth: usize) -> Result<JsonValue> {
         GValue::Map(entries) => {
             let mut map = Map::new();
             for entry in entries {
-                map.insert(entry.key.clone(), to_json_with_depth(&entry.value, depth + 1)?);
+                map.insert(entry.key.to_string(), to_json_with_depth(&entry.value, depth + 1)?);
             }
             JsonValue::Object(map)
         }
         GValue::Struct(s) => {
             let mut map = Map::new();
             for field in &s.fields {
-                map.insert(field.key.clone(), to_json_with_depth(&field.value, depth + 1)?);
+                map.insert(field.key.to_string(), to_json_with_depth(&field.value, depth + 1)?);
             }
             // Include type name as special field
             map.insert("_type".to_string(), JsonValue::String(s.type_name.clone()));
@@ -121,6 +121,7 @@ fn to_json_with_depth(gv: &GValue, depth: usize) -> Result<JsonValue> {
             }
             JsonValue::Object(map)
         }
+        GValue::Table(t) => return to_json_with_depth(&t.to_list(), depth),
     })
 }
 
@@ -143,6 +144,7 @@ pub fn stringify_json_pretty(gv: &GValue) -> String {
 }
 
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     use super::*;
     use serde_json::json;
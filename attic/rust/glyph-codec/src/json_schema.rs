@@ -0,0 +1,488 @@
+//! JSON Schema import
+//!
+//! Tool definitions (OpenAI function `parameters`, Anthropic `input_schema`)
+//! are JSON Schema. `Schema::from_json_schema("Call", &json)` maps one onto
+//! GLYPH types so a single definition drives both `validate` and
+//! `canonicalize_strict`:
+//!
+//! - objects become records; properties outside `required` are optional, and
+//!   the record is `@open` unless `additionalProperties` is `false`
+//! - nested objects and sums get named types, `Call` + `Filter` -> `CallFilter`
+//! - `$defs` / `definitions` entries become named types that `$ref` points at
+//! - `enum` and `const` strings become `str [enum=[...]]`
+//! - `oneOf` / `anyOf` become sums, tagged by each branch's `title`, `$ref`
+//!   name or `const` discriminator; a `null` branch only makes the field
+//!   optional
+//! - `format: date-time` is `time`, `format: byte` is `bytes`
+//! - length, bound, pattern and `uniqueItems` keywords become constraints
+
+use crate::error::GlyphError;
+use crate::json_bridge::try_from_json;
+use crate::schema::*;
+use serde_json::{Map, Value as JsonValue};
+use std::collections::HashSet;
+
+impl Schema {
+    /// Convert a JSON Schema whose root is an object (or `oneOf`) into a
+    /// schema with that root as `name`
+    pub fn from_json_schema(name: &str, json: &JsonValue) -> Result<Schema, GlyphError> {
+        let mut importer = Importer {
+            defs: Map::new(),
+            schema: Schema::new(),
+            pending: HashSet::new(),
+        };
+        for key in ["definitions", "$defs"] {
+            if let Some(defs) = json.get(key).and_then(JsonValue::as_object) {
+                importer.defs.extend(defs.clone());
+            }
+        }
+        if !importer.is_named(json) {
+            return Err(GlyphError::InvalidValue(format!(
+                "JSON Schema root must be an object or oneOf, got {}",
+                json
+            )));
+        }
+        importer.define(name, json, "#")?;
+        let defs: Vec<_> = importer.defs.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        for (def, js) in defs {
+            if importer.is_named(&js) && importer.schema.get(&def).is_none() {
+                importer.define(&def, &js, &format!("#/$defs/{}", def))?;
+            }
+        }
+        Ok(importer.schema)
+    }
+}
+
+/// A converted JSON Schema node
+struct Imported {
+    ty: TypeSpec,
+    constraints: Vec<Constraint>,
+    nullable: bool,
+}
+
+impl Imported {
+    fn plain(ty: TypeSpec) -> Self {
+        Self {
+            ty,
+            constraints: Vec::new(),
+            nullable: false,
+        }
+    }
+}
+
+struct Importer {
+    defs: Map<String, JsonValue>,
+    schema: Schema,
+    /// Types being defined, so recursive `$ref`s stop at a name
+    pending: HashSet<String>,
+}
+
+impl Importer {
+    /// Whether `js` needs a named type: an object with fields, or a sum
+    fn is_named(&self, js: &JsonValue) -> bool {
+        if let Some(target) = ref_name(js) {
+            return self.defs.get(target).is_some_and(|d| self.is_named(d));
+        }
+        is_object(js) && (js.get("properties").is_some() || !has_value_schema(js)) || self.is_sum(js)
+    }
+
+    fn is_sum(&self, js: &JsonValue) -> bool {
+        branches(js).is_some_and(|b| b.iter().filter(|b| !is_null_type(b)).count() > 1)
+    }
+
+    fn define(&mut self, name: &str, js: &JsonValue, at: &str) -> Result<(), GlyphError> {
+        if self.schema.get(name).is_some() || !self.pending.insert(name.to_string()) {
+            return Ok(());
+        }
+        let def = if self.is_sum(js) {
+            let mut variants = Vec::new();
+            let mut tags = HashSet::new();
+            let live = branches(js).unwrap_or_default().into_iter().filter(|b| !is_null_type(b));
+            for (i, branch) in live.enumerate() {
+                let mut tag = variant_tag(branch, i);
+                if !tags.insert(tag.clone()) {
+                    tag = format!("{}{}", tag, i);
+                    tags.insert(tag.clone());
+                }
+                let ty = self.spec(branch, &format!("{}{}", name, tag), &format!("{}/oneOf/{}", at, i))?.ty;
+                variants.push(VariantDef::new(tag, ty));
+            }
+            TypeDef::sum(name, variants)
+        } else {
+            let required: HashSet<&str> = js
+                .get("required")
+                .and_then(JsonValue::as_array)
+                .map(|r| r.iter().filter_map(JsonValue::as_str).collect())
+                .unwrap_or_default();
+            let mut fields = Vec::new();
+            if let Some(props) = js.get("properties").and_then(JsonValue::as_object) {
+                for (key, prop) in props {
+                    let child = format!("{}{}", name, pascal_case(key));
+                    let imported = self.spec(prop, &child, &format!("{}/properties/{}", at, key))?;
+                    let mut field = FieldDef::new(key.as_str(), imported.ty);
+                    field.constraints = imported.constraints;
+                    field.optional = imported.nullable || !required.contains(key.as_str());
+                    if let Some(default) = prop.get("default") {
+                        field.default = Some(try_from_json(default)?);
+                    }
+                    fields.push(field);
+                }
+            }
+            let def = TypeDef::record(name, fields);
+            if js.get("additionalProperties") == Some(&JsonValue::Bool(false)) {
+                def
+            } else {
+                def.open()
+            }
+        };
+        self.pending.remove(name);
+        self.schema.add_type(def);
+        Ok(())
+    }
+
+    /// The GLYPH type of `js`; `name` is used if it needs a named type
+    fn spec(&mut self, js: &JsonValue, name: &str, at: &str) -> Result<Imported, GlyphError> {
+        let unsupported = |what: &str| GlyphError::InvalidValue(format!("JSON Schema at {}: {}", at, what));
+
+        if let Some(target) = ref_name(js) {
+            let def = self
+                .defs
+                .get(target)
+                .cloned()
+                .ok_or_else(|| unsupported(&format!("unresolved $ref {}", target)))?;
+            if !self.is_named(&def) {
+                return self.spec(&def, target, &format!("#/$defs/{}", target));
+            }
+            self.define(target, &def, &format!("#/$defs/{}", target))?;
+            return Ok(Imported::plain(TypeSpec::named(target)));
+        }
+
+        if let Some(all) = js.get("allOf").and_then(JsonValue::as_array) {
+            return match all.as_slice() {
+                [only] => self.spec(only, name, &format!("{}/allOf/0", at)),
+                _ => Err(unsupported("allOf with more than one schema")),
+            };
+        }
+
+        if let Some(branches) = branches(js) {
+            let nullable = branches.iter().any(|b| is_null_type(b));
+            let live: Vec<_> = branches.iter().filter(|b| !is_null_type(b)).collect();
+            let mut imported = match live.as_slice() {
+                [] => Imported::plain(TypeSpec::Null),
+                [only] => self.spec(only, name, &format!("{}/oneOf/0", at))?,
+                _ => {
+                    self.define(name, js, at)?;
+                    Imported::plain(TypeSpec::named(name))
+                }
+            };
+            imported.nullable |= nullable;
+            return Ok(imported);
+        }
+
+        if let Some(values) = js.get("enum").and_then(JsonValue::as_array) {
+            let nullable = values.iter().any(JsonValue::is_null);
+            let strings: Option<Vec<String>> = values
+                .iter()
+                .filter(|v| !v.is_null())
+                .map(|v| v.as_str().map(str::to_string))
+                .collect();
+            let strings = strings.ok_or_else(|| unsupported("enum values must be strings"))?;
+            return Ok(Imported {
+                ty: TypeSpec::Str,
+                constraints: vec![Constraint::Enum(strings)],
+                nullable,
+            });
+        }
+        if let Some(value) = js.get("const") {
+            let value = value.as_str().ok_or_else(|| unsupported("const must be a string"))?;
+            return Ok(Imported {
+                ty: TypeSpec::Str,
+                constraints: vec![Constraint::Enum(vec![value.to_string()])],
+                nullable: false,
+            });
+        }
+
+        let (type_name, nullable) = match js.get("type") {
+            Some(JsonValue::String(t)) => (t.as_str(), false),
+            Some(JsonValue::Array(ts)) => {
+                let live: Vec<_> = ts.iter().filter_map(JsonValue::as_str).filter(|t| *t != "null").collect();
+                match live.as_slice() {
+                    [only] => (*only, live.len() < ts.len()),
+                    _ => return Err(unsupported("a type list needs exactly one non-null type")),
+                }
+            }
+            _ if js.get("properties").is_some() => ("object", false),
+            _ => return Err(unsupported("no type")),
+        };
+
+        let mut constraints = Vec::new();
+        let ty = match type_name {
+            "string" => {
+                let number = |k: &str| js.get(k).and_then(JsonValue::as_u64).map(|n| n as usize);
+                if let Some(n) = number("minLength") {
+                    constraints.push(Constraint::MinLen(n));
+                }
+                if let Some(n) = number("maxLength") {
+                    constraints.push(Constraint::MaxLen(n));
+                }
+                if let Some(p) = js.get("pattern").and_then(JsonValue::as_str) {
+                    constraints.push(Constraint::Regex(p.to_string()));
+                }
+                let format = js.get("format").and_then(JsonValue::as_str);
+                let encoding = js.get("contentEncoding").and_then(JsonValue::as_str);
+                match (format, encoding) {
+                    (Some("date-time"), _) => TypeSpec::Time,
+                    (Some("byte"), _) | (_, Some("base64")) => TypeSpec::Bytes,
+                    _ => TypeSpec::Str,
+                }
+            }
+            "integer" | "number" => {
+                let bound = |k: &str| js.get(k).and_then(JsonValue::as_f64);
+                match (bound("minimum"), bound("maximum")) {
+                    (Some(lo), Some(hi)) => constraints.push(Constraint::Range(lo, hi)),
+                    (Some(lo), None) => constraints.push(Constraint::Min(lo)),
+                    (None, Some(hi)) => constraints.push(Constraint::Max(hi)),
+                    (None, None) => {}
+                }
+                if type_name == "integer" {
+                    TypeSpec::Int
+                } else {
+                    TypeSpec::Float
+                }
+            }
+            "boolean" => TypeSpec::Bool,
+            "null" => TypeSpec::Null,
+            "array" => {
+                let items = js.get("items").ok_or_else(|| unsupported("array without items"))?;
+                let elem = self.spec(items, &format!("{}Item", name), &format!("{}/items", at))?;
+                let number = |k: &str| js.get(k).and_then(JsonValue::as_u64).map(|n| n as usize);
+                if let Some(n) = number("minItems") {
+                    constraints.push(Constraint::MinLen(n));
+                }
+                if let Some(n) = number("maxItems") {
+                    constraints.push(Constraint::MaxLen(n));
+                }
+                if js.get("uniqueItems") == Some(&JsonValue::Bool(true)) {
+                    constraints.push(Constraint::Unique);
+                }
+                TypeSpec::list(elem.ty)
+            }
+            "object" => match js.get("additionalProperties") {
+                Some(values) if values.is_object() && js.get("properties").is_none() => {
+                    let value = self.spec(values, &format!("{}Value", name), &format!("{}/additionalProperties", at))?;
+                    TypeSpec::map(TypeSpec::Str, value.ty)
+                }
+                _ => {
+                    self.define(name, js, at)?;
+                    TypeSpec::named(name)
+                }
+            },
+            other => return Err(unsupported(&format!("unknown type {}", other))),
+        };
+        Ok(Imported {
+            ty,
+            constraints,
+            nullable,
+        })
+    }
+}
+
+fn ref_name(js: &JsonValue) -> Option<&str> {
+    let r = js.get("$ref")?.as_str()?;
+    r.strip_prefix("#/$defs/").or_else(|| r.strip_prefix("#/definitions/"))
+}
+
+fn branches(js: &JsonValue) -> Option<Vec<&JsonValue>> {
+    let list = js.get("oneOf").or_else(|| js.get("anyOf"))?.as_array()?;
+    Some(list.iter().collect())
+}
+
+fn is_object(js: &JsonValue) -> bool {
+    js.get("type").and_then(JsonValue::as_str) == Some("object") || (js.get("type").is_none() && js.get("properties").is_some())
+}
+
+/// A property-less object whose values share a schema is a map, not a record
+fn has_value_schema(js: &JsonValue) -> bool {
+    js.get("additionalProperties").is_some_and(JsonValue::is_object)
+}
+
+fn is_null_type(js: &JsonValue) -> bool {
+    js.get("type").and_then(JsonValue::as_str) == Some("null")
+}
+
+/// Tag for a `oneOf` branch: its title, `$ref` name, `const` discriminator
+/// property, or else its type
+fn variant_tag(branch: &JsonValue, index: usize) -> String {
+    if let Some(title) = branch.get("title").and_then(JsonValue::as_str) {
+        return pascal_case(title);
+    }
+    if let Some(target) = ref_name(branch) {
+        return target.to_string();
+    }
+    let discriminator = branch
+        .get("properties")
+        .and_then(JsonValue::as_object)
+        .and_then(|props| props.values().find_map(|p| p.get("const").and_then(JsonValue::as_str)));
+    if let Some(value) = discriminator {
+        return pascal_case(value);
+    }
+    match branch.get("type").and_then(JsonValue::as_str) {
+        Some("string") => "Str".to_string(),
+        Some("integer") => "Int".to_string(),
+        Some("number") => "Float".to_string(),
+        Some("boolean") => "Bool".to_string(),
+        Some("array") => "List".to_string(),
+        Some("object") => "Object".to_string(),
+        _ => format!("V{}", index),
+    }
+}
+
+/// `dry_run` / `dry-run` / `dry run` -> `DryRun`
+pub(crate) fn pascal_case(s: &str) -> String {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            let first = chars.next().map(|c| c.to_uppercase().collect::<String>()).unwrap_or_default();
+            first + chars.as_str()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::GValue;
+    use serde_json::json;
+
+    fn tool() -> JsonValue {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {"type": "string", "minLength": 1, "description": "what to look for"},
+                "limit": {"type": "integer", "minimum": 1, "maximum": 50, "default": 10},
+                "mode": {"enum": ["fast", "deep"]},
+                "since": {"type": ["string", "null"], "format": "date-time"},
+                "blob": {"type": "string", "contentEncoding": "base64"},
+                "tags": {"type": "array", "items": {"type": "string", "pattern": "^[a-z]+$"}, "uniqueItems": true},
+                "filter": {
+                    "type": "object",
+                    "properties": {"field": {"type": "string"}, "value": {"type": "number"}},
+                    "required": ["field"],
+                    "additionalProperties": false
+                },
+                "headers": {"type": "object", "additionalProperties": {"type": "string"}},
+                "target": {"oneOf": [{"$ref": "#/$defs/Url"}, {"$ref": "#/$defs/FilePath"}]}
+            },
+            "required": ["query", "since"],
+            "additionalProperties": false,
+            "$defs": {
+                "Url": {"type": "object", "properties": {"url": {"type": "string", "format": "uri"}}, "required": ["url"]},
+                "FilePath": {"type": "object", "properties": {"path": {"type": "string"}}},
+                "Unused": {"type": "string"}
+            }
+        })
+    }
+
+    #[test]
+    fn test_from_json_schema() {
+        let schema = Schema::from_json_schema("Search", &tool()).unwrap();
+        let names: Vec<_> = schema.types().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["FilePath", "Search", "SearchFilter", "SearchTarget", "Url"]);
+        assert_eq!(
+            schema.get("Search").unwrap().to_string(),
+            "Search struct{\n    \
+             blob: bytes [optional]\n    \
+             filter: SearchFilter [optional]\n    \
+             headers: map<str,str> [optional]\n    \
+             limit: int [1..50] @default(10) [optional]\n    \
+             mode: str [enum=[fast deep]] [optional]\n    \
+             query: str [len>=1]\n    \
+             since: time [optional]\n    \
+             tags: list<str> [unique] [optional]\n    \
+             target: SearchTarget [optional]\n  }"
+        );
+        assert_eq!(
+            schema.get("SearchFilter").unwrap().to_string(),
+            "SearchFilter struct{\n    field: str\n    value: float [optional]\n  }"
+        );
+        assert_eq!(
+            schema.get("SearchTarget").unwrap().to_string(),
+            "SearchTarget sum{\n    Url: Url |\n    FilePath: FilePath\n  }"
+        );
+        // Properties are open unless additionalProperties is false
+        assert!(schema.get("Url").unwrap().open);
+        assert!(!schema.get("Search").unwrap().open);
+        // Schema text round-trips
+        assert_eq!(Schema::parse(&schema.to_string()).unwrap(), schema);
+    }
+
+    #[test]
+    fn test_imported_schema_drives_validation() {
+        let schema = Schema::from_json_schema("Search", &tool()).unwrap();
+        let args = crate::parse_loose("{query=rain limit=80 mode=slow target=Url({url=\"x.io\"})}").unwrap();
+        let errors: Vec<_> = validate_as(&args, &schema, "Search").iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "limit: expected 1..50, got 80",
+                "mode: expected enum=[fast deep], got slow",
+            ]
+        );
+        let point = GValue::build_map().str("query", "rain").str("mode", "fast").finish();
+        assert_eq!(canonicalize_strict_as(&point, &schema, "Search").unwrap(), "Search@{bm=0b10000}(rain fast)");
+    }
+
+    #[test]
+    fn test_sums_nullables_and_refs() {
+        let js = json!({
+            "type": "object",
+            "properties": {
+                "shape": {"anyOf": [
+                    {"type": "object", "properties": {"kind": {"const": "circle"}, "r": {"type": "number"}}},
+                    {"type": "object", "properties": {"kind": {"const": "square"}, "side": {"type": "number"}}},
+                    {"type": "null"}
+                ]},
+                "id": {"anyOf": [{"type": "string"}, {"type": "integer"}]},
+                "next": {"$ref": "#/definitions/Node"},
+                "color": {"$ref": "#/definitions/Color"}
+            },
+            "required": ["shape"],
+            "definitions": {
+                "Node": {"type": "object", "properties": {"next": {"$ref": "#/definitions/Node"}}},
+                "Color": {"type": "string", "enum": ["red", "green", null]}
+            }
+        });
+        let schema = Schema::from_json_schema("Draw", &js).unwrap();
+        let draw = schema.get("Draw").unwrap();
+        let shape = draw.field("shape").unwrap();
+        assert!(shape.optional);
+        assert_eq!(shape.ty, TypeSpec::named("DrawShape"));
+        let tags: Vec<_> = schema.get("DrawShape").unwrap().variants().iter().map(|v| v.tag.as_str()).collect();
+        assert_eq!(tags, vec!["Circle", "Square"]);
+        assert_eq!(schema.get("DrawShapeCircle").unwrap().fields().len(), 2);
+        let ids: Vec<_> = schema.get("DrawId").unwrap().variants().iter().map(|v| v.ty.to_string()).collect();
+        assert_eq!(ids, vec!["str", "int"]);
+        // Recursive refs stop at the name; non-record defs are inlined
+        assert_eq!(schema.get("Node").unwrap().field("next").unwrap().ty, TypeSpec::named("Node"));
+        let color = draw.field("color").unwrap();
+        assert_eq!(color.ty, TypeSpec::Str);
+        assert!(color.optional);
+        assert_eq!(color.constraints, vec![Constraint::Enum(vec!["red".into(), "green".into()])]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        for (js, msg) in [
+            (json!({"type": "string"}), "root must be an object"),
+            (json!({"type": "object", "properties": {"x": {}}}), "#/properties/x: no type"),
+            (json!({"type": "object", "properties": {"x": {"$ref": "#/$defs/Nope"}}}), "unresolved $ref Nope"),
+            (json!({"type": "object", "properties": {"x": {"enum": [1, 2]}}}), "enum values must be strings"),
+            (json!({"type": "object", "properties": {"x": {"type": "array"}}}), "array without items"),
+        ] {
+            let err = Schema::from_json_schema("T", &js).unwrap_err().to_string();
+            assert!(err.contains(msg), "{}", err);
+        }
+        assert_eq!(pascal_case("dry_run-mode x"), "DryRunModeX");
+    }
+}
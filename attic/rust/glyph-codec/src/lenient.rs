@@ -0,0 +1,169 @@
+//! Lenient input normalization
+//!
+//! Helpers for accepting slightly-off input (as echoed by user-facing tools and
+//! models) in lenient parse modes. Every normalization is reported as a
+//! [`Repair`] so callers can audit what was changed.
+
+use std::fmt;
+
+/// Kind of normalization applied to an input value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairKind {
+    /// A localized number (thousands separators, decimal comma) was rewritten
+    LocaleNumber,
+}
+
+/// A normalization applied to one input value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    pub field: String,
+    pub kind: RepairKind,
+    pub original: String,
+    pub normalized: String,
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?} -> {:?} ({:?})", self.field, self.original, self.normalized, self.kind)
+    }
+}
+
+/// Characters accepted as thousands separators besides `.` and `,`
+const GROUP_SEPARATORS: [char; 4] = [' ', '\u{a0}', '\u{202f}', '\''];
+
+/// Rewrite a localized number into plain `-1234.5` form.
+///
+/// Accepts thousands separators (`,` `.` space, no-break space, `'`) and a
+/// decimal comma. When both `.` and `,` appear, the last one is the decimal
+/// separator. A single `,` followed by exactly three digits (`1,234`) is read
+/// as a thousands separator; any other single `,` is a decimal comma (`1,5`).
+/// Thousands groups must be exactly three digits.
+///
+/// Returns `None` if `s` is not a localized number or is already plain.
+pub fn normalize_locale_number(s: &str) -> Option<String> {
+    let trimmed = s.trim();
+    let (sign, body) = match trimmed.as_bytes().first()? {
+        b'-' => ("-", &trimmed[1..]),
+        b'+' => ("", &trimmed[1..]),
+        _ => ("", trimmed),
+    };
+
+    let is_sep = |c: char| c == '.' || c == ',' || GROUP_SEPARATORS.contains(&c);
+    if !body.starts_with(|c: char| c.is_ascii_digit())
+        || !body.ends_with(|c: char| c.is_ascii_digit())
+        || !body.chars().all(|c| c.is_ascii_digit() || is_sep(c))
+    {
+        return None;
+    }
+
+    let dots = body.matches('.').count();
+    let commas = body.matches(',').count();
+    let other_groups = body.contains(|c| GROUP_SEPARATORS.contains(&c));
+
+    let decimal = match (dots, commas) {
+        (0, 0) if !other_groups => return None,
+        (0, 0) => None,
+        (_, 0) if dots == 1 => {
+            if !other_groups {
+                // Plain decimal point: nothing to normalize
+                return None;
+            }
+            Some('.')
+        }
+        (_, 0) => None,
+        (0, 1) => {
+            let after = body.len() - body.find(',')? - 1;
+            if after == 3 && !other_groups {
+                None
+            } else {
+                Some(',')
+            }
+        }
+        (0, _) => None,
+        _ => {
+            let last_dot = body.rfind('.')?;
+            let last_comma = body.rfind(',')?;
+            Some(if last_dot > last_comma { '.' } else { ',' })
+        }
+    };
+
+    let (int_part, frac_part) = match decimal {
+        Some(d) => {
+            let (i, f) = body.split_at(body.rfind(d)?);
+            (i, Some(&f[1..]))
+        }
+        None => (body, None),
+    };
+
+    if let Some(frac) = frac_part {
+        if frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+    }
+
+    let digits = ungroup(int_part)?;
+    let mut out = format!("{}{}", sign, digits);
+    if let Some(frac) = frac_part {
+        out.push('.');
+        out.push_str(frac);
+    }
+    Some(out)
+}
+
+/// Strip a single consistent thousands separator, checking 3-digit groups
+fn ungroup(int_part: &str) -> Option<String> {
+    let mut sep = None;
+    let mut groups = Vec::new();
+    let mut current = String::new();
+    for c in int_part.chars() {
+        if c.is_ascii_digit() {
+            current.push(c);
+            continue;
+        }
+        if *sep.get_or_insert(c) != c {
+            return None;
+        }
+        groups.push(std::mem::take(&mut current));
+    }
+    groups.push(current);
+
+    let (first, rest) = groups.split_first()?;
+    if first.is_empty() || (first.len() > 3 && !rest.is_empty()) {
+        return None;
+    }
+    if rest.iter().any(|g| g.len() != 3) {
+        return None;
+    }
+    Some(groups.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thousands_separators() {
+        assert_eq!(normalize_locale_number("1,234").as_deref(), Some("1234"));
+        assert_eq!(normalize_locale_number("1,234,567").as_deref(), Some("1234567"));
+        assert_eq!(normalize_locale_number("1.234.567").as_deref(), Some("1234567"));
+        assert_eq!(normalize_locale_number("1 234 567").as_deref(), Some("1234567"));
+        assert_eq!(normalize_locale_number("1'234").as_deref(), Some("1234"));
+        assert_eq!(normalize_locale_number("-12\u{a0}345").as_deref(), Some("-12345"));
+    }
+
+    #[test]
+    fn test_decimal_separators() {
+        assert_eq!(normalize_locale_number("1,5").as_deref(), Some("1.5"));
+        assert_eq!(normalize_locale_number("1.234,56").as_deref(), Some("1234.56"));
+        assert_eq!(normalize_locale_number("1,234.56").as_deref(), Some("1234.56"));
+        assert_eq!(normalize_locale_number("+0,25").as_deref(), Some("0.25"));
+        assert_eq!(normalize_locale_number("1 234,5").as_deref(), Some("1234.5"));
+    }
+
+    #[test]
+    fn test_not_localized() {
+        for s in ["42", "3.14", "-7", "abc", "1,23,456", "12,34.5,6", "1,", ",5", "1..2", "1,2.3", "", "-"] {
+            assert_eq!(normalize_locale_number(s), None, "{:?}", s);
+        }
+    }
+}
@@ -15,6 +15,8 @@
 //! assert_eq!(glyph, "{action=search query=weather}");
 //! ```
 
+#[macro_use]
+mod macros;
 mod types;
 mod loose;
 mod json_bridge;
@@ -22,6 +24,60 @@ mod error;
 pub mod decimal128;
 pub mod schema_evolution;
 pub mod stream_validator;
+pub mod path;
+pub mod refs;
+pub mod incremental;
+pub mod lenient;
+pub mod unicode;
+pub mod pipeline;
+pub mod parse;
+pub mod document;
+pub mod ser;
+pub mod corpus;
+pub mod merkle;
+pub mod store;
+pub mod sign;
+pub mod intern;
+pub mod visit;
+pub mod patch;
+pub mod merge;
+pub mod delta;
+pub mod schema;
+pub mod json_schema;
+pub mod codegen;
+pub mod registry;
+pub mod envelope;
+pub mod integrations;
+pub mod prompt;
+pub mod transcript;
+pub mod watch;
+pub mod budget;
+pub mod page;
+pub mod redact;
+pub mod scrub;
+pub mod blob;
+pub mod preview;
+pub mod stats;
+pub mod summarize;
+pub mod arena;
+pub mod cow;
+pub mod writer;
+pub mod decode;
+pub mod lines;
+#[cfg(feature = "ansi")]
+pub mod ansi;
+#[cfg(feature = "tiktoken")]
+pub mod tokens;
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "codec")]
+pub mod codec;
+mod unicode_tables;
+mod ordering;
+mod blake3;
+mod scan;
 
 pub use types::*;
 pub use loose::*;
@@ -29,6 +85,53 @@ pub use json_bridge::*;
 pub use error::*;
 pub use decimal128::*;
 pub use schema_evolution::*;
+pub use path::*;
+pub use refs::*;
+pub use incremental::*;
+pub use lenient::*;
+pub use pipeline::*;
+pub use parse::*;
+pub use document::*;
+pub use ser::*;
+pub use corpus::*;
+pub use merkle::*;
+pub use store::*;
+pub use sign::*;
+pub use intern::*;
+pub use visit::*;
+pub use patch::*;
+pub use merge::*;
+pub use delta::*;
+pub use schema::*;
+pub use codegen::*;
+pub use registry::*;
+pub use envelope::*;
+pub use prompt::*;
+pub use transcript::*;
+pub use watch::*;
+pub use budget::*;
+pub use page::*;
+pub use redact::*;
+pub use scrub::*;
+pub use blob::*;
+pub use preview::*;
+pub use stats::*;
+pub use summarize::*;
+pub use arena::*;
+pub use cow::*;
+pub use writer::*;
+pub use decode::*;
+pub use lines::*;
+#[cfg(feature = "ansi")]
+pub use ansi::*;
+#[cfg(feature = "tiktoken")]
+pub use tokens::*;
+#[cfg(feature = "async")]
+pub use async_io::*;
+#[cfg(feature = "mmap")]
+pub use mmap::*;
+#[cfg(feature = "codec")]
+pub use codec::*;
 pub use stream_validator::{
     ArgSchema, ToolSchema, ToolRegistry, ErrorCode, ValidationError, ValidatorState, TimelineEvent,
     StreamingValidator, ValidationResult, default_tool_registry,
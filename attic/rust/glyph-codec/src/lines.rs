@@ -0,0 +1,236 @@
+//! GLYPH Lines: one value per line
+//!
+//! An append-friendly multi-document format for event logs, like JSON Lines.
+//! Each record is a single line holding one value's canonical text and
+//! ending in `\n`, so a log can be appended to with one write per record,
+//! split with any line tool, and a torn last record costs only that record.
+//!
+//! Canonical text has line breaks only between the lines of `@tab` blocks;
+//! quoted strings and cells carry theirs as `\n` escapes. A record whose text
+//! has line breaks is written escaped: a leading `\`, then the text with each
+//! `\` doubled and each line break as `\n`. No value starts with `\`, so
+//! readers tell the two kinds apart by the first byte, and records without
+//! tables are plain canonical text that `grep` and `parse_loose` read as is.
+//!
+//! Blank lines are skipped, and a `\r` before the `\n` is ignored.
+
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::parse::parse_loose;
+use crate::types::GValue;
+use std::io::{self, BufRead};
+
+/// The record line for `v` under `opts`, without its trailing `\n`
+pub fn encode_line(v: &GValue, opts: &LooseCanonOpts) -> Result<String, GlyphError> {
+    let text = canonicalize_loose_with_opts(v, opts)?;
+    if !text.contains('\n') {
+        return Ok(text);
+    }
+    let mut line = String::with_capacity(text.len() + 16);
+    line.push('\\');
+    for c in text.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            c => line.push(c),
+        }
+    }
+    Ok(line)
+}
+
+/// Parse one record line, given without its trailing `\n`
+pub fn decode_line(line: &str) -> Result<GValue, GlyphError> {
+    let Some(escaped) = line.strip_prefix('\\') else {
+        return parse_loose(line);
+    };
+    let mut text = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => text.push('\\'),
+            Some('n') => text.push('\n'),
+            other => {
+                return Err(GlyphError::Parse(format!(
+                    "invalid escape in record line: \\{}",
+                    other.map(String::from).unwrap_or_default()
+                )))
+            }
+        }
+    }
+    parse_loose(&text)
+}
+
+/// Appends values to an `io::Write` as GLYPH Lines records
+pub struct LinesWriter<W: io::Write> {
+    out: W,
+    opts: LooseCanonOpts,
+    records: usize,
+}
+
+impl<W: io::Write> LinesWriter<W> {
+    /// Records are encoded under `LooseCanonOpts::default()`
+    pub fn new(out: W) -> Self {
+        Self::with_opts(out, &LooseCanonOpts::default())
+    }
+
+    pub fn with_opts(out: W, opts: &LooseCanonOpts) -> Self {
+        Self {
+            out,
+            opts: opts.clone(),
+            records: 0,
+        }
+    }
+
+    /// Write `v` as one record, newline included, in a single `write_all`
+    pub fn write(&mut self, v: &GValue) -> Result<(), GlyphError> {
+        let mut line = encode_line(v, &self.opts)?;
+        line.push('\n');
+        self.out.write_all(line.as_bytes())?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Records written so far
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    pub fn flush(&mut self) -> Result<(), GlyphError> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads GLYPH Lines records from a `BufRead`.
+///
+/// Iterating yields each record's value. A record that fails to parse is
+/// yielded as a `Parse` error naming its line, and reading carries on with
+/// the next; a read error ends the iteration after it is yielded.
+pub struct LinesReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    line: usize,
+    failed: bool,
+}
+
+impl<R: BufRead> LinesReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            line: 0,
+            failed: false,
+        }
+    }
+
+    /// Line number of the last record read, from 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The next record, `None` at the end of the input
+    pub fn next_value(&mut self) -> Result<Option<GValue>, GlyphError> {
+        loop {
+            self.buf.clear();
+            if self.inner.read_until(b'\n', &mut self.buf)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            let mut record = self.buf.as_slice();
+            record = record.strip_suffix(b"\n").unwrap_or(record);
+            record = record.strip_suffix(b"\r").unwrap_or(record);
+            if record.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let line = self.line;
+            let at_line = |e: GlyphError| match e {
+                GlyphError::Parse(msg) => GlyphError::Parse(format!("line {}: {}", line, msg)),
+                other => other,
+            };
+            let text = std::str::from_utf8(record).map_err(|e| at_line(GlyphError::Parse(e.to_string())))?;
+            return decode_line(text).map(Some).map_err(at_line);
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Iterator for LinesReader<R> {
+    type Item = Result<GValue, GlyphError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.next_value();
+        self.failed = matches!(result, Err(GlyphError::Io(_)));
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn test_lines_round_trip() {
+        let table = GValue::Table(TableValue::new(
+            vec!["id".to_string(), "note".to_string()],
+            vec![vec![GValue::int(1), GValue::str("back\\slash\nand newline")], vec![GValue::int(2), GValue::Null]],
+        ));
+        let values = vec![
+            GValue::build_map().str("event", "start").str("path", "C:\\tmp").finish(),
+            table.clone(),
+            GValue::map(vec![MapEntry::new("rows", table)]),
+            GValue::str("multi\nline"),
+            GValue::int(3),
+        ];
+        let mut w = LinesWriter::new(Vec::new());
+        for v in &values {
+            w.write(v).unwrap();
+        }
+        assert_eq!(w.records(), values.len());
+        let out = String::from_utf8(w.into_inner()).unwrap();
+        assert_eq!(out.lines().count(), values.len());
+        let first = out.lines().next().unwrap();
+        assert!(!first.starts_with('\\'));
+        assert_eq!(parse_loose(first).unwrap(), values[0]);
+        assert!(out.lines().nth(1).unwrap().starts_with("\\@tab"));
+
+        // Tables read back as their row lists
+        let read: Vec<GValue> = LinesReader::new(out.as_bytes()).map(Result::unwrap).collect();
+        let rows = values[1].as_table().unwrap().to_list();
+        assert_eq!(read[1], rows);
+        assert_eq!(read[2], GValue::map(vec![MapEntry::new("rows", rows)]));
+        assert_eq!(read[0], values[0]);
+        assert_eq!(read[3..], values[3..]);
+    }
+
+    #[test]
+    fn test_lines_reader_errors() {
+        let input = "{a=1}\r\n\n{bad=}\n\\@tab\\q\n[1 2]";
+        let mut r = LinesReader::new(input.as_bytes());
+        assert_eq!(r.next().unwrap().unwrap(), GValue::map(vec![MapEntry::new("a", GValue::int(1))]));
+        assert!(r.next().unwrap().unwrap_err().to_string().starts_with("Parse error: line 3: "));
+        assert!(r.next().unwrap().unwrap_err().to_string().contains("invalid escape"));
+        // A last record without its newline is still read
+        assert_eq!(r.next().unwrap().unwrap(), GValue::list(vec![GValue::int(1), GValue::int(2)]));
+        assert_eq!(r.line(), 5);
+        assert!(r.next().is_none());
+    }
+}
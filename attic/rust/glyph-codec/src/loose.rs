@@ -3,11 +3,33 @@
 //! Provides deterministic canonical string representation for GValues
 //! in schema-optional mode. Used for hashing, comparison, and deduplication.
 
+use crate::arena::{ArenaEntries, ArenaRef, GArena, NodeId};
+use crate::cow::GValueCow;
+use crate::budget::{char_floor, cut_string, is_more_marker, more_marker};
 use crate::error::GlyphError;
 use crate::types::*;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use sha2::{Sha256, Digest};
-use std::collections::HashSet;
+use crate::path::{GlyphPath, PathPattern, PathSegment, PatternSegment};
+use crate::scan::{find_escape, word_prefix_len};
+use std::borrow::Cow;
+use crate::unicode::nfc;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+/// Version of the loose-mode canonical form emitted by default options
+pub const CANON_SPEC_VERSION: &str = "glyph-loose-1.0.0";
+
+/// Spec version of the canonical form (`docs/LOOSE_MODE_SPEC.md`).
+///
+/// Fingerprints are only comparable between implementations reporting the
+/// same version.
+pub fn canon_spec_version() -> &'static str {
+    CANON_SPEC_VERSION
+}
 
 /// Null style for canonicalization
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -17,6 +39,371 @@ pub enum NullStyle {
     Underscore,
     /// Use unicode symbol: ∅
     Symbol,
+    /// Use the word: null
+    Word,
+}
+
+/// Boolean style for canonicalization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoolStyle {
+    /// Use single letters: t / f
+    #[default]
+    Short,
+    /// Use words: true / false
+    Word,
+}
+
+/// Timestamp style for canonicalization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeStyle {
+    /// Emit the timestamp at second precision
+    #[default]
+    Exact,
+    /// Truncate to the start of a fixed-size bucket (aligned to the Unix epoch)
+    /// before emitting, so nearby timestamps canonicalize identically
+    RoundTo(Duration),
+}
+
+impl TimeStyle {
+    /// One-minute buckets
+    pub fn minute() -> Self {
+        TimeStyle::RoundTo(Duration::from_secs(60))
+    }
+
+    /// One-hour buckets
+    pub fn hour() -> Self {
+        TimeStyle::RoundTo(Duration::from_secs(3600))
+    }
+}
+
+/// Order of keys in maps, structs, and tabular columns
+#[derive(Debug, Clone, Copy, Default)]
+pub enum KeyOrder {
+    /// Bytewise order of the UTF-8 canonical key, quotes and escapes included
+    /// (the canonical form; see `docs/LOOSE_MODE_SPEC.md` "Key Ordering")
+    #[default]
+    Sorted,
+    /// Order in which keys appear in the value
+    Insertion,
+    /// Caller-defined comparison of raw keys (stable for equal keys)
+    Custom(fn(&str, &str) -> std::cmp::Ordering),
+}
+
+impl PartialEq for KeyOrder {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (KeyOrder::Sorted, KeyOrder::Sorted) | (KeyOrder::Insertion, KeyOrder::Insertion) => true,
+            (KeyOrder::Custom(a), KeyOrder::Custom(b)) => std::ptr::fn_addr_eq(*a, *b),
+            _ => false,
+        }
+    }
+}
+
+/// Order of columns in tabular blocks
+#[derive(Debug, Clone, Default)]
+pub enum ColumnOrder {
+    /// Same order as map keys (`key_order`)
+    #[default]
+    Keys,
+    /// These columns first, in this order; the rest follow `key_order`.
+    /// A name pins a top-level column (and its flattened columns) or one
+    /// flattened `a.b` column.
+    Pinned(Vec<String>),
+    /// Caller-defined comparison of raw column names
+    Custom(fn(&str, &str) -> std::cmp::Ordering),
+}
+
+impl ColumnOrder {
+    /// Pin `cols` to the front, e.g. `ColumnOrder::pinned(["id", "name"])`
+    pub fn pinned<S: Into<String>>(cols: impl IntoIterator<Item = S>) -> Self {
+        ColumnOrder::Pinned(cols.into_iter().map(Into::into).collect())
+    }
+}
+
+impl PartialEq for ColumnOrder {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ColumnOrder::Keys, ColumnOrder::Keys) => true,
+            (ColumnOrder::Pinned(a), ColumnOrder::Pinned(b)) => a == b,
+            (ColumnOrder::Custom(a), ColumnOrder::Custom(b)) => std::ptr::fn_addr_eq(*a, *b),
+            _ => false,
+        }
+    }
+}
+
+/// Tabular decision for lists covered by a `TabularRule`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TabularMode {
+    /// Follow `auto_tabular` and the other options
+    #[default]
+    Auto,
+    /// Emit eligible lists as `@tab` even when `auto_tabular` is off, below
+    /// `min_rows`, or longer than the plain list under `optimize_size`
+    Force,
+    /// Never emit `@tab`
+    Off,
+}
+
+/// Per-subtree tabular setting.
+///
+/// A rule covers a list when its pattern matches the list's path, one of its
+/// ancestors, or its rows: `results[*]` covers the `results` list and every
+/// list inside it. The last rule covering a list wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabularRule {
+    pub pattern: PathPattern,
+    pub mode: TabularMode,
+}
+
+impl TabularRule {
+    pub fn new(pattern: &str, mode: TabularMode) -> Result<Self, GlyphError> {
+        Ok(Self {
+            pattern: PathPattern::parse(pattern)?,
+            mode,
+        })
+    }
+
+    /// Rule forcing tabular under `pattern`
+    pub fn force(pattern: &str) -> Result<Self, GlyphError> {
+        Self::new(pattern, TabularMode::Force)
+    }
+
+    /// Rule disabling tabular under `pattern`
+    pub fn off(pattern: &str) -> Result<Self, GlyphError> {
+        Self::new(pattern, TabularMode::Off)
+    }
+}
+
+/// Order of rows in tabular blocks
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum RowOrder {
+    /// Rows in list order
+    #[default]
+    Source,
+    /// Bytewise order of each row's emitted cells
+    Canonical,
+    /// Bytewise order of the emitted cell in this column (a top-level name or
+    /// flattened `a.b` path), ties broken as `Canonical`. Without such a
+    /// column, rows sort as `Canonical`. Cells compare as text, so `10` sorts
+    /// before `9`.
+    ByKey(String),
+}
+
+/// Unicode normalization applied to strings and keys before emission
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnicodeNorm {
+    /// Emit strings exactly as given
+    #[default]
+    None,
+    /// Normalization Form C, so visually identical text emits identically
+    Nfc,
+}
+
+/// Per-type size limits. `None` means unlimited.
+///
+/// A value over its limit is emitted as a placeholder
+/// `Trunc{kind=str len=5000 ref=^trunc:<hash>}` (`kind` is quoted for the
+/// reserved words `list` and `map`), where `len` is the original
+/// length (bytes for strings and bytes, entries for lists and maps) and the
+/// hash is the first 16 hex digits of the value's `hash_loose`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeCaps {
+    /// Maximum UTF-8 length of a string
+    pub max_string_bytes: Option<usize>,
+    /// Maximum length of a byte string
+    pub max_bytes_len: Option<usize>,
+    /// Maximum number of list items
+    pub max_list_items: Option<usize>,
+    /// Maximum number of map entries or struct fields
+    pub max_map_entries: Option<usize>,
+}
+
+impl SizeCaps {
+    /// Kind and length of `v` if it exceeds its cap
+    pub fn overflow(&self, v: &GValue) -> Option<(&'static str, usize)> {
+        let (kind, len, cap) = match v {
+            GValue::Str(s) => ("str", s.len(), self.max_string_bytes),
+            GValue::Bytes(b) => ("bytes", b.len(), self.max_bytes_len),
+            GValue::List(items) => ("list", items.len(), self.max_list_items),
+            GValue::Map(entries) => ("map", entries.len(), self.max_map_entries),
+            GValue::Struct(s) => ("struct", s.fields.len(), self.max_map_entries),
+            GValue::Table(t) => ("table", t.rows.len(), self.max_list_items),
+            _ => return None,
+        };
+        match cap {
+            Some(cap) if len > cap => Some((kind, len)),
+            _ => None,
+        }
+    }
+}
+
+/// What encoding does with a value over an `EncodeLimits` limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    /// Fail with `GlyphError::LimitExceeded`, or `RecursionLimitExceeded`
+    /// for depth
+    #[default]
+    Error,
+    /// Cut strings to `…+N bytes` and lists to their first items and a
+    /// `"…+42 more"` item; containers at the depth limit become `Trunc{...}`
+    /// placeholders
+    Truncate,
+}
+
+/// Hard limits checked while encoding, so a misbehaving tool can't put
+/// megabytes into a prompt. `None` means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EncodeLimits {
+    /// Deepest nesting written; the root is depth 0, so a non-empty
+    /// container at this depth is over
+    pub max_depth: Option<usize>,
+    /// Most items in a list or rows in a table
+    pub max_list_len: Option<usize>,
+    /// Longest string, in UTF-8 bytes
+    pub max_string_len: Option<usize>,
+    /// Longest output, in bytes. Always an error, whatever the policy: there
+    /// is nothing sensible to cut once the text is written.
+    pub max_output_bytes: Option<usize>,
+    pub policy: LimitPolicy,
+}
+
+impl EncodeLimits {
+    /// Whether any limit is set
+    pub fn is_set(&self) -> bool {
+        self.max_depth.is_some()
+            || self.max_list_len.is_some()
+            || self.max_string_len.is_some()
+            || self.max_output_bytes.is_some()
+    }
+
+    /// `v` as the policy allows it to be written at `depth`
+    fn apply<'v>(&self, v: &'v GValue, depth: usize) -> Result<Cow<'v, GValue>, GlyphError> {
+        let truncate = self.policy == LimitPolicy::Truncate;
+        let (kind, len) = match v {
+            GValue::List(items) => ("list", items.len()),
+            GValue::Map(entries) => ("map", entries.len()),
+            GValue::Struct(s) => ("struct", s.fields.len()),
+            GValue::Sum(s) => ("sum", usize::from(s.value.is_some())),
+            GValue::Table(t) => ("table", t.rows.len()),
+            _ => ("", 0),
+        };
+        if let Some(max) = self.max_depth {
+            if depth >= max && len > 0 {
+                if !truncate {
+                    return Err(GlyphError::RecursionLimitExceeded { limit: max });
+                }
+                return Ok(Cow::Owned(trunc_placeholder(kind, len, v)?));
+            }
+        }
+        match v {
+            // The marker for cut items is always written whole
+            GValue::Str(s) if self.max_string_len.is_some_and(|max| s.len() > max) && !is_more_marker(s) => {
+                let max = self.max_string_len.unwrap_or_default();
+                if !truncate {
+                    return Err(GlyphError::LimitExceeded(format!(
+                        "string of {} bytes over max_string_len {}",
+                        s.len(),
+                        max
+                    )));
+                }
+                Ok(Cow::Owned(GValue::str(cut_string(s, char_floor(s, max)))))
+            }
+            GValue::List(_) | GValue::Table(_) if self.max_list_len.is_some_and(|max| len > max) => {
+                let max = self.max_list_len.unwrap_or_default();
+                if !truncate {
+                    return Err(GlyphError::LimitExceeded(format!(
+                        "{} of {} items over max_list_len {}",
+                        kind, len, max
+                    )));
+                }
+                // A table can't mark its elided rows; write its rows as maps
+                let mut items = match v {
+                    GValue::Table(t) => match t.to_list() {
+                        GValue::List(rows) => rows,
+                        _ => Vec::new(),
+                    },
+                    _ => v.as_list().map(|items| items[..max].to_vec()).unwrap_or_default(),
+                };
+                items.truncate(max);
+                items.push(more_marker(len - max));
+                Ok(Cow::Owned(GValue::List(items)))
+            }
+            _ => Ok(Cow::Borrowed(v)),
+        }
+    }
+}
+
+/// Words that are always quoted because a parser would read them as literals
+/// or keywords (D8, matching Go `isValidBareString`)
+const RESERVED_WORDS: [&str; 14] = [
+    "t", "f", "true", "false", "null", "_", "none", "nil", "NaN", "Inf", "struct", "sum", "list", "map",
+];
+
+/// Characters that delimit GLYPH syntax and can never appear in a bare string
+const STRUCTURAL_CHARS: [char; 12] = [' ', '=', '{', '}', '[', ']', '(', ')', '"', '|', '^', '\\'];
+
+/// Rules deciding which strings may be emitted bare (unquoted).
+///
+/// The default is the canonical D8 rule (`docs/CANONICAL_FORMS.md` §5):
+/// `[A-Za-z_][A-Za-z0-9_]*` and not a reserved word.
+/// Changing the rules changes canonical output, so fingerprints are only
+/// comparable between values canonicalized with the same rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BareRules {
+    /// Additional words that must always be quoted
+    pub extra_reserved: Vec<String>,
+    /// Extra characters permitted in bare strings (structural characters are ignored)
+    pub allow_chars: Vec<char>,
+    /// Characters that always force quoting, even if otherwise allowed
+    pub deny_chars: Vec<char>,
+    /// Permit non-ASCII Unicode letters and digits in bare strings
+    pub allow_unicode: bool,
+}
+
+impl BareRules {
+    /// Rules that quote everything outside plain ASCII (the default)
+    pub fn ascii_only() -> Self {
+        Self::default()
+    }
+
+    /// Rules that also allow Unicode letters and digits to stay bare
+    pub fn unicode() -> Self {
+        Self {
+            allow_unicode: true,
+            ..Self::default()
+        }
+    }
+
+    /// Check whether `s` is safe to emit without quotes under these rules
+    pub fn is_bare_safe(&self, s: &str) -> bool {
+        let first = match s.chars().next() {
+            Some(c) => c,
+            None => return false,
+        };
+
+        // Must start with a letter or underscore
+        if !(first == '_' || first.is_ascii_alphabetic() || (self.allow_unicode && first.is_alphabetic())) {
+            return false;
+        }
+
+        if RESERVED_WORDS.contains(&s) || self.extra_reserved.iter().any(|w| w == s) {
+            return false;
+        }
+
+        // Plain ASCII word bytes pass unless denied; check the rest by char
+        let word = if self.deny_chars.is_empty() { word_prefix_len(s.as_bytes()) } else { 0 };
+        s[word..].chars().all(|c| self.is_bare_char(c))
+    }
+
+    fn is_bare_char(&self, c: char) -> bool {
+        if STRUCTURAL_CHARS.contains(&c) || c.is_whitespace() || c.is_control() || self.deny_chars.contains(&c) {
+            return false;
+        }
+        c.is_ascii_alphanumeric()
+            || c == '_'
+            || (self.allow_unicode && c.is_alphanumeric())
+            || self.allow_chars.contains(&c)
+    }
 }
 
 /// Options for loose canonicalization
@@ -30,8 +417,46 @@ pub struct LooseCanonOpts {
     pub max_cols: usize,
     /// Allow missing keys in tabular (fill with null)
     pub allow_missing: bool,
+    /// Flatten nested maps in tabular rows into dot-path columns
+    /// (`user.id`) up to this many levels; 0 disables
+    pub tabular_flatten_depth: usize,
+    /// Annotate tabular columns with their cell type (`id:int`) when every
+    /// non-null cell has the same type; ints mixed with floats are `float`
+    pub tabular_types: bool,
+    /// Row order in tabular blocks. Sorting makes row sets that differ only
+    /// in order emit (and hash) identically.
+    pub sort_rows: RowOrder,
+    /// Also tabularize lists of equal-length lists, as `@tab _ cols=N []`
+    /// blocks with positional cells
+    pub tabular_lists: bool,
+    /// Pad tabular cells so the pipes line up. For logs and debugging only;
+    /// the padding is not part of the canonical form.
+    pub align_tabular: bool,
+    /// Per-subtree overrides of `auto_tabular`, by path pattern
+    pub tabular_paths: Vec<TabularRule>,
+    /// Emit a tabular block only when it is no longer than the plain list.
+    /// Output then depends on value lengths, so leave off for canonical forms.
+    pub optimize_size: bool,
     /// Null value style
     pub null_style: NullStyle,
+    /// Boolean value style
+    pub bool_style: BoolStyle,
+    /// Timestamp style
+    pub time_style: TimeStyle,
+    /// Rules for emitting strings and keys without quotes
+    pub bare_rules: BareRules,
+    /// Key ordering. Only `KeyOrder::Sorted` yields the canonical form; use the
+    /// others for display or prompt layout, never for fingerprints or equality.
+    pub key_order: KeyOrder,
+    /// Column order in tabular blocks. Anything but `ColumnOrder::Keys`
+    /// departs from the canonical form.
+    pub column_order: ColumnOrder,
+    /// Unicode normalization of strings and keys
+    pub unicode_norm: UnicodeNorm,
+    /// Size caps; oversized values become `^trunc:` placeholders
+    pub max_value_bytes_per_type: SizeCaps,
+    /// Limits that fail or cut the encoding; see `EncodeLimits`
+    pub limits: EncodeLimits,
 }
 
 impl Default for LooseCanonOpts {
@@ -41,7 +466,22 @@ impl Default for LooseCanonOpts {
             min_rows: 3,
             max_cols: 20,
             allow_missing: true,
+            tabular_flatten_depth: 0,
+            tabular_types: false,
+            sort_rows: RowOrder::Source,
+            tabular_lists: false,
+            align_tabular: false,
+            tabular_paths: Vec::new(),
+            optimize_size: false,
             null_style: NullStyle::Underscore,
+            bool_style: BoolStyle::Short,
+            time_style: TimeStyle::Exact,
+            bare_rules: BareRules::default(),
+            key_order: KeyOrder::Sorted,
+            column_order: ColumnOrder::Keys,
+            unicode_norm: UnicodeNorm::None,
+            max_value_bytes_per_type: SizeCaps::default(),
+            limits: EncodeLimits::default(),
         }
     }
 }
@@ -60,6 +500,35 @@ impl LooseCanonOpts {
         }
     }
 
+    /// Options spelling out `true` / `false` / `null`.
+    ///
+    /// Costs a few tokens but helps weaker models that misread `t`, `f` and `_`.
+    /// The output is not the canonical form, so don't use it for fingerprints.
+    pub fn verbose() -> Self {
+        Self {
+            null_style: NullStyle::Word,
+            bool_style: BoolStyle::Word,
+            ..Self::default()
+        }
+    }
+
+    /// Options picking whichever of tabular and list form is shorter
+    pub fn compact() -> Self {
+        Self {
+            optimize_size: true,
+            ..Self::default()
+        }
+    }
+
+    /// Options for human reading: tabular cells padded into aligned columns.
+    /// Parses back to the same value, but is not the canonical form.
+    pub fn aligned() -> Self {
+        Self {
+            align_tabular: true,
+            ..Self::default()
+        }
+    }
+
     /// Options with tabular disabled
     pub fn no_tabular() -> Self {
         Self {
@@ -88,370 +557,1880 @@ pub fn canonicalize_loose_no_tabular(v: &GValue) -> Result<String, GlyphError> {
 /// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
 pub fn canonicalize_loose_with_opts(v: &GValue, opts: &LooseCanonOpts) -> Result<String, GlyphError> {
     let mut buf = String::new();
-    write_canon_loose(&mut buf, v, opts)?;
+    CanonWriter::new(opts).write(&mut buf, v)?;
     Ok(buf)
 }
 
-/// Get the full 64-hex SHA-256 fingerprint of a GValue (no-tabular canonical form).
+/// Formats as `canonicalize_loose` output.
 ///
-/// Matches Go/Python/JS FingerprintLoose semantics: hashes the no-tabular canonical
-/// string and returns the full 64-character hex digest.
+/// A value with no canonical form still formats, so `to_string()` never
+/// panics: NaN and Inf are written as the strings `NaN`, `inf` and `-inf`,
+/// and anything else that fails (such as a ragged table) falls back to the
+/// `Debug` form. Neither is canonical; call `canonicalize_loose` to get the
+/// error instead.
+impl fmt::Display for GValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Ok(text) = canonicalize_loose(self) {
+            return f.write_str(&text);
+        }
+        let shown = self.clone().map_values(|_, v| match v {
+            GValue::Float(x) if !x.is_finite() => GValue::str(x.to_string()),
+            other => other,
+        });
+        match canonicalize_loose(&shown) {
+            Ok(text) => f.write_str(&text),
+            Err(_) => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Write the canonical form under `opts` to `out` as it is produced.
 ///
-/// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
-pub fn fingerprint_loose(v: &GValue) -> Result<String, GlyphError> {
-    hash_loose(v)
+/// Output is handed over in chunks of a few KiB, so memory stays flat however
+/// large `v` is. On error, `out` may hold a partial document.
+pub fn write_canon_loose<W: fmt::Write>(out: &mut W, v: &GValue, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
+    let mut buf = String::with_capacity(2 * STREAM_CHUNK);
+    let mut writer = CanonWriter::streaming(opts, out);
+    writer.write(&mut buf, v)?;
+    writer.flush(&mut buf)
 }
 
-/// Get full 64-hex SHA-256 digest of the no-tabular canonical form.
+/// `write_canon_loose` into an `io::Write`, such as a file or socket.
 ///
-/// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
-pub fn hash_loose(v: &GValue) -> Result<String, GlyphError> {
-    let canonical = canonicalize_loose_no_tabular(v)?;
-    let mut hasher = Sha256::new();
-    hasher.update(canonical.as_bytes());
-    let result = hasher.finalize();
-    Ok(hex_encode(&result))
+/// Output goes to `out` in chunks as it is produced; wrap unbuffered targets
+/// in a `BufWriter` if chunk-sized writes are too many. A failed write comes
+/// back as `GlyphError::Io` with the original error.
+pub fn write_canon_loose_io<W: std::io::Write>(out: W, v: &GValue, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
+    let mut out = IoWriter::new(out);
+    let result = write_canon_loose(&mut out, v, opts);
+    match out.take_error() {
+        Some(e) => Err(GlyphError::Io(e)),
+        None => result,
+    }
 }
 
-/// Check if two GValues are semantically equal.
+/// A `fmt::Write` over an `io::Write`, keeping the first I/O error.
 ///
-/// Returns `Err(GlyphError::InvalidFloat)` if either value tree contains NaN or Inf.
-pub fn equal_loose(a: &GValue, b: &GValue) -> Result<bool, GlyphError> {
-    Ok(canonicalize_loose(a)? == canonicalize_loose(b)?)
+/// `fmt::Error` carries no detail, so a failed write is stored here for
+/// `take_error` and reported to the formatter as a plain `fmt::Error`.
+pub struct IoWriter<W> {
+    inner: W,
+    error: Option<std::io::Error>,
 }
 
-// ============================================================
-// Internal canonicalization
-// ============================================================
+impl<W: std::io::Write> IoWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
 
-fn write_canon_loose(buf: &mut String, v: &GValue, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
-    match v {
-        GValue::Null => buf.push_str(canon_null(opts.null_style)),
-        GValue::Bool(b) => buf.push(if *b { 't' } else { 'f' }),
-        GValue::Int(n) => buf.push_str(&canon_int(*n)),
-        GValue::Float(f) => buf.push_str(&canon_float(*f)?),
-        GValue::Str(s) => buf.push_str(&canon_string(s)),
-        GValue::Bytes(data) => write_canon_bytes(buf, data),
-        GValue::Time(t) => buf.push_str(&t.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
-        GValue::Id(ref_id) => write_canon_ref(buf, ref_id),
-        GValue::List(items) => write_canon_list(buf, items, opts)?,
-        GValue::Map(entries) => write_canon_map(buf, entries, opts)?,
-        GValue::Struct(s) => write_canon_struct(buf, s, opts)?,
-        GValue::Sum(s) => write_canon_sum(buf, s, opts)?,
+    /// The error that stopped writing, if any
+    pub fn take_error(&mut self) -> Option<std::io::Error> {
+        self.error.take()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
     }
-    Ok(())
 }
 
-fn canon_null(style: NullStyle) -> &'static str {
-    match style {
-        NullStyle::Underscore => "_",
-        NullStyle::Symbol => "∅",
+impl<W: std::io::Write> fmt::Write for IoWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.error.is_some() {
+            return Err(fmt::Error);
+        }
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
     }
 }
 
-fn canon_int(n: i64) -> String {
-    n.to_string()
+/// Canonicalizer that keeps its buffers between calls.
+///
+/// `canonicalize_loose_with_opts` allocates its output and the scratch
+/// strings behind every `@tab` block and cell afresh. An `Encoder` holds on
+/// to them, so a server encoding many values per request stops allocating
+/// once its buffers have grown to fit. Output matches
+/// `canonicalize_loose_with_opts` under the same options.
+#[derive(Debug, Clone, Default)]
+pub struct Encoder {
+    opts: LooseCanonOpts,
+    out: String,
+    pool: Vec<String>,
 }
 
-fn canon_float(f: f64) -> Result<String, GlyphError> {
-    if f.is_nan() {
-        return Err(GlyphError::InvalidFloat("NaN is not allowed in glyph text canonicalization".to_string()));
+impl Encoder {
+    pub fn new(opts: LooseCanonOpts) -> Self {
+        Self {
+            opts,
+            out: String::new(),
+            pool: Vec::new(),
+        }
     }
-    if f.is_infinite() {
-        return Err(GlyphError::InvalidFloat(
-            format!("{}Inf is not allowed in glyph text canonicalization", if f > 0.0 { "" } else { "-" })
-        ));
+
+    pub fn opts(&self) -> &LooseCanonOpts {
+        &self.opts
     }
 
-    // Handle negative zero
-    let f = if f == 0.0 { 0.0 } else { f };
+    /// Canonical text of `v`, valid until the next call
+    pub fn encode(&mut self, v: &GValue) -> Result<&str, GlyphError> {
+        self.out.clear();
+        let mut writer = CanonWriter::new(&self.opts).with_pool(std::mem::take(&mut self.pool));
+        let result = writer.write(&mut self.out, v);
+        self.pool = writer.into_pool();
+        result.map(|()| self.out.as_str())
+    }
 
-    // Check if it's a whole number
-    if f.fract() == 0.0 && f.abs() < 1e15 {
-        return Ok(format!("{}", f as i64));
+    /// `encode`, into a new `String`
+    pub fn encode_to_string(&mut self, v: &GValue) -> Result<String, GlyphError> {
+        self.encode(v).map(str::to_string)
     }
 
-    // Use shortest representation
-    let abs = f.abs();
-    let exp = abs.log10().floor() as i32;
+    /// `write_canon_loose` with this encoder's options and buffers
+    pub fn write<W: fmt::Write>(&mut self, out: &mut W, v: &GValue) -> Result<(), GlyphError> {
+        self.out.clear();
+        let mut writer = CanonWriter::streaming(&self.opts, out).with_pool(std::mem::take(&mut self.pool));
+        let result = writer.write(&mut self.out, v).and_then(|()| writer.flush(&mut self.out));
+        self.pool = writer.into_pool();
+        result
+    }
+}
 
-    Ok(if exp < -4 || exp >= 15 {
-        // Use exponential notation
-        let mantissa = f / 10f64.powi(exp);
-        if exp >= 0 {
-            format!("{}e+{:02}", format_mantissa(mantissa), exp)
-        } else {
-            format!("{}e{:03}", format_mantissa(mantissa), exp)
+/// Byte length of the canonical form under `opts`, `@tab` decisions
+/// included, without building the output string.
+///
+/// The text is produced in chunks and counted, so memory stays flat however
+/// large `v` is. `max_output_bytes` is ignored so the full size comes back.
+/// A value the encoder rejects, such as one holding NaN, estimates at the
+/// length written before the error.
+pub fn estimate_size(v: &GValue, opts: &LooseCanonOpts) -> usize {
+    let opts = match opts.limits.max_output_bytes {
+        Some(_) => {
+            let mut opts = opts.clone();
+            opts.limits.max_output_bytes = None;
+            Cow::Owned(opts)
         }
-    } else {
-        // Use decimal notation
-        format_decimal(f)
-    })
+        None => Cow::Borrowed(opts),
+    };
+    let mut counter = ByteCounter(0);
+    let _ = write_canon_loose(&mut counter, v, &opts);
+    counter.0
 }
 
-fn format_mantissa(m: f64) -> String {
-    let s = format!("{:.15}", m);
-    s.trim_end_matches('0').trim_end_matches('.').to_string()
+/// Sink that keeps only the number of bytes written to it
+struct ByteCounter(usize);
+
+impl fmt::Write for ByteCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
 }
 
-fn format_decimal(f: f64) -> String {
-    let s = format!("{:.15}", f);
-    s.trim_end_matches('0').trim_end_matches('.').to_string()
+/// Canonicalize and record where every node landed in the output.
+///
+/// Nodes inside an `@tab` block are not mapped individually; the block is
+/// recorded as one span on the list with `tabular` set.
+pub fn canonicalize_loose_with_source_map(
+    v: &GValue,
+    opts: &LooseCanonOpts,
+) -> Result<(String, SourceMap), GlyphError> {
+    let mut buf = String::new();
+    let mut writer = CanonWriter::recording(opts, GlyphPath::root());
+    writer.write(&mut buf, v)?;
+    Ok((buf, writer.into_source_map()))
 }
 
-/// Check if a string is safe to emit without quotes
-fn is_bare_safe(s: &str) -> bool {
-    if s.is_empty() {
-        return false;
-    }
+/// Get the full 64-hex SHA-256 fingerprint of a GValue (no-tabular canonical form).
+///
+/// Matches Go/Python/JS FingerprintLoose semantics: hashes the no-tabular canonical
+/// string and returns the full 64-character hex digest.
+///
+/// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
+pub fn fingerprint_loose(v: &GValue) -> Result<String, GlyphError> {
+    hash_loose(v)
+}
 
-    // Must not start with a digit, quote, or special char
-    let first = s.chars().next().unwrap();
-    if first.is_ascii_digit() || first == '"' || first == '\'' || first == '-' {
-        return false;
-    }
+/// Get full 64-hex SHA-256 digest of the no-tabular canonical form.
+///
+/// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
+pub fn hash_loose(v: &GValue) -> Result<String, GlyphError> {
+    Ok(hash_loose_with(v, HashAlgo::Sha256, 32)?.to_hex())
+}
 
-    // Reserved words
-    let reserved = ["t", "f", "true", "false", "null", "_", "none", "nil"];
-    if reserved.contains(&s) {
-        return false;
-    }
+/// Get the 64-hex SHA-256 digest of the canonical form under `opts`.
+///
+/// Use with `UnicodeNorm::Nfc` so documents differing only in Unicode
+/// normalization hash identically. Hashes differ from `hash_loose` unless
+/// `opts` matches `LooseCanonOpts::no_tabular()`.
+pub fn hash_loose_with_opts(v: &GValue, opts: &LooseCanonOpts) -> Result<String, GlyphError> {
+    let mut hasher = HashWriter::new(HashAlgo::Sha256);
+    write_canon_loose(&mut hasher, v, opts)?;
+    Ok(hasher.finish(32)?.to_hex())
+}
 
-    // Must contain only safe characters: [a-zA-Z0-9._-]
-    s.chars().all(|c| {
-        c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.'
-    })
+/// Digest algorithm for `hash_loose_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashAlgo {
+    /// SHA-256; digests up to 32 bytes
+    #[default]
+    Sha256,
+    /// BLAKE3; digests of any length
+    Blake3,
 }
 
-pub fn canon_string(s: &str) -> String {
-    if is_bare_safe(s) {
-        s.to_string()
-    } else {
-        quote_string(s)
+impl HashAlgo {
+    /// Name used in prefixed fingerprints (`sha256`, `blake3`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HashAlgo::Sha256 => "sha256",
+            HashAlgo::Blake3 => "blake3",
+        }
     }
-}
 
-fn quote_string(s: &str) -> String {
-    let mut out = String::with_capacity(s.len() + 2);
-    out.push('"');
-    for c in s.chars() {
-        match c {
-            '\\' => out.push_str("\\\\"),
-            '"' => out.push_str("\\\""),
-            '\n' => out.push_str("\\n"),
-            '\r' => out.push_str("\\r"),
-            '\t' => out.push_str("\\t"),
-            c if (c as u32) < 0x20 => {
-                out.push_str(&format!("\\u{:04x}", c as u32));
-            }
-            c => out.push(c),
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "sha256" => Some(HashAlgo::Sha256),
+            "blake3" => Some(HashAlgo::Blake3),
+            _ => None,
         }
     }
-    out.push('"');
-    out
 }
 
-fn write_canon_bytes(buf: &mut String, data: &[u8]) {
-    buf.push_str("b64\"");
-    buf.push_str(&BASE64.encode(data));
-    buf.push('"');
+impl fmt::Display for HashAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-fn write_canon_ref(buf: &mut String, ref_id: &RefId) {
-    buf.push('^');
-    if !ref_id.prefix.is_empty() {
-        buf.push_str(&ref_id.prefix);
-        buf.push(':');
-    }
-    // Ref IDs allow more characters as bare (including starting with digits)
-    if is_ref_bare_safe(&ref_id.value) {
-        buf.push_str(&ref_id.value);
-    } else {
-        buf.push_str(&quote_string(&ref_id.value));
-    }
+/// A digest tagged with its algorithm.
+///
+/// Displays and parses as `algo:hex`, e.g. `blake3:6437b3ac…`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    pub algo: HashAlgo,
+    pub digest: Vec<u8>,
 }
 
-/// Check if a ref ID value is safe to emit without quotes
-/// (more permissive than regular strings - allows starting with digits)
-fn is_ref_bare_safe(s: &str) -> bool {
-    if s.is_empty() {
-        return false;
+impl Fingerprint {
+    /// Lowercase hex digest, without the algorithm prefix
+    pub fn to_hex(&self) -> String {
+        hex_encode(&self.digest)
     }
 
-    // Must contain only safe characters (no spaces, quotes, etc.)
-    s.chars().all(|c| {
-        c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
-            || (c as u32 > 127) // Allow unicode
-    })
-}
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.digest
+    }
 
-fn write_canon_list(buf: &mut String, items: &[GValue], opts: &LooseCanonOpts) -> Result<(), GlyphError> {
-    // Try tabular if enabled
-    if opts.auto_tabular {
-        if let Some(tabular) = try_emit_tabular(items, opts)? {
-            buf.push_str(&tabular);
-            return Ok(());
-        }
+    /// Digest length in bytes
+    pub fn len(&self) -> usize {
+        self.digest.len()
     }
 
-    buf.push('[');
-    for (i, item) in items.iter().enumerate() {
-        if i > 0 {
-            buf.push(' ');
+    pub fn is_empty(&self) -> bool {
+        self.digest.is_empty()
+    }
+
+    /// Parse `algo:hex`
+    pub fn parse(s: &str) -> Result<Self, GlyphError> {
+        let bad = || GlyphError::Parse(format!("invalid fingerprint {:?}", s));
+        let (algo, hex) = s.split_once(':').ok_or_else(bad)?;
+        let algo = HashAlgo::parse(algo).ok_or_else(bad)?;
+        if hex.is_empty() || hex.len() % 2 != 0 {
+            return Err(bad());
         }
-        write_canon_loose(buf, item, opts)?;
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(bad)?;
+        Ok(Self { algo, digest })
     }
-    buf.push(']');
-    Ok(())
 }
 
-fn write_canon_map(buf: &mut String, entries: &[MapEntry], opts: &LooseCanonOpts) -> Result<(), GlyphError> {
-    buf.push('{');
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algo, self.to_hex())
+    }
+}
 
-    // Sort entries by canonical key
-    let mut sorted: Vec<_> = entries.iter().collect();
-    sorted.sort_by(|a, b| canon_string(&a.key).cmp(&canon_string(&b.key)));
+impl std::str::FromStr for Fingerprint {
+    type Err = GlyphError;
 
-    for (i, entry) in sorted.iter().enumerate() {
-        if i > 0 {
-            buf.push(' ');
-        }
-        buf.push_str(&canon_string(&entry.key));
-        buf.push('=');
-        write_canon_loose(buf, &entry.value, opts)?;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Fingerprint::parse(s)
     }
-    buf.push('}');
-    Ok(())
 }
 
-fn write_canon_struct(buf: &mut String, s: &StructValue, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
-    buf.push_str(&s.type_name);
-    buf.push('{');
+/// A `fmt::Write` sink that hashes what is written to it.
+///
+/// Pair with `write_canon_loose` to hash without building the canonical string.
+pub struct HashWriter {
+    state: HashState,
+}
 
-    // Sort fields by canonical key
-    let mut sorted: Vec<_> = s.fields.iter().collect();
-    sorted.sort_by(|a, b| canon_string(&a.key).cmp(&canon_string(&b.key)));
+enum HashState {
+    Sha256(Sha256),
+    Blake3(Box<crate::blake3::Hasher>),
+}
 
-    for (i, field) in sorted.iter().enumerate() {
-        if i > 0 {
-            buf.push(' ');
+impl HashWriter {
+    pub fn new(algo: HashAlgo) -> Self {
+        let state = match algo {
+            HashAlgo::Sha256 => HashState::Sha256(Sha256::new()),
+            HashAlgo::Blake3 => HashState::Blake3(Box::new(crate::blake3::Hasher::new())),
+        };
+        Self { state }
+    }
+
+    pub fn algo(&self) -> HashAlgo {
+        match self.state {
+            HashState::Sha256(_) => HashAlgo::Sha256,
+            HashState::Blake3(_) => HashAlgo::Blake3,
         }
-        buf.push_str(&canon_string(&field.key));
-        buf.push('=');
-        write_canon_loose(buf, &field.value, opts)?;
     }
-    buf.push('}');
-    Ok(())
+
+    /// Digest of everything written, keeping `len` bytes (see `hash_loose_with`)
+    pub fn finish(self, len: usize) -> Result<Fingerprint, GlyphError> {
+        let algo = self.algo();
+        check_digest_len(algo, len)?;
+        let digest = match self.state {
+            HashState::Sha256(h) => h.finalize()[..len].to_vec(),
+            HashState::Blake3(h) => {
+                let mut out = vec![0; len];
+                h.finalize(&mut out);
+                out
+            }
+        };
+        Ok(Fingerprint { algo, digest })
+    }
+}
+
+impl fmt::Write for HashWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match &mut self.state {
+            HashState::Sha256(h) => h.update(s.as_bytes()),
+            HashState::Blake3(h) => h.update(s.as_bytes()),
+        }
+        Ok(())
+    }
 }
 
-fn write_canon_sum(buf: &mut String, s: &SumValue, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
-    buf.push_str(&s.tag);
-    buf.push('(');
-    if let Some(ref value) = s.value {
-        write_canon_loose(buf, value, opts)?;
+fn check_digest_len(algo: HashAlgo, len: usize) -> Result<(), GlyphError> {
+    let max = match algo {
+        HashAlgo::Sha256 => 32,
+        HashAlgo::Blake3 => usize::MAX,
+    };
+    if len == 0 || len > max {
+        return Err(GlyphError::InvalidValue(format!("{} digest length {}", algo, len)));
     }
-    buf.push(')');
     Ok(())
 }
 
+/// Hash the no-tabular canonical form with `algo`, keeping `len` bytes of digest.
+///
+/// `hash_loose_with(v, HashAlgo::Sha256, 32)` has the same digest as
+/// `hash_loose`. SHA-256 allows 1 to 32 bytes (shorter is a truncation);
+/// BLAKE3 any length from 1.
+pub fn hash_loose_with(v: &GValue, algo: HashAlgo, len: usize) -> Result<Fingerprint, GlyphError> {
+    check_digest_len(algo, len)?;
+    let mut hasher = HashWriter::new(algo);
+    write_canon_loose(&mut hasher, v, &LooseCanonOpts::no_tabular())?;
+    hasher.finish(len)
+}
+
+/// Get the `hash_loose` digest of the subtree at `path` (e.g. `"results[3].payload"`).
+///
+/// Only the addressed node is canonicalized, so polling loops can watch one
+/// field of a large value cheaply. Table rows are addressed by index, like the
+/// list of row maps they stand for. Returns `Err(GlyphError::InvalidValue)` if
+/// nothing is at `path`.
+pub fn hash_at(v: &GValue, path: &str) -> Result<String, GlyphError> {
+    let parsed = GlyphPath::parse(path)?;
+    let node = subtree_at(v, &parsed).ok_or_else(|| GlyphError::InvalidValue(format!("no value at {}", parsed)))?;
+    hash_loose(&node)
+}
+
+fn subtree_at<'a>(v: &'a GValue, path: &GlyphPath) -> Option<Cow<'a, GValue>> {
+    let mut node = Cow::Borrowed(v);
+    for seg in path.segments() {
+        node = match (&*node, seg) {
+            (GValue::Table(t), PathSegment::Index(i)) => Cow::Owned(t.row(*i)?),
+            _ => match node {
+                Cow::Borrowed(v) => Cow::Borrowed(v.child(seg)?),
+                Cow::Owned(v) => Cow::Owned(v.child(seg)?.clone()),
+            },
+        };
+    }
+    Some(node)
+}
+
+/// Check if two GValues are semantically equal: whether their default
+/// canonical forms match.
+///
+/// Compares the trees directly, without building either canonical string,
+/// and stops at the first difference. Lists that may be emitted as `@tab`
+/// blocks, and tables, are compared row by row; only rows that differ where
+/// an `@tab` block can't tell (a null cell against a missing key) or a table
+/// against a list with the same rows fall back to comparing the text.
+///
+/// Returns `Err(GlyphError::InvalidFloat)` if a compared float is NaN or Inf.
+pub fn equal_loose(a: &GValue, b: &GValue) -> Result<bool, GlyphError> {
+    equal_values(&LooseCanonOpts::default(), false, a, b)
+}
+
+/// Whether `a` and `b` print alike, at the top level or, with `cell`, inside
+/// an `@tab` cell, where lists are never tabular and tables print as their rows
+fn equal_values(opts: &LooseCanonOpts, cell: bool, a: &GValue, b: &GValue) -> Result<bool, GlyphError> {
+    use GValue::*;
+    Ok(match (a, b) {
+        (Null, Null) => true,
+        (Bool(x), Bool(y)) => x == y,
+        (Int(x), Int(y)) => x == y,
+        (Float(x), Float(y)) => finite(*x)? == finite(*y)?,
+        // Whole floats in the safe window are written as ints
+        (Int(n), Float(f)) | (Float(f), Int(n)) => {
+            let f = finite(*f)?;
+            f.fract() == 0.0 && f.abs() <= MAX_SAFE_INT && f as i64 == *n
+        }
+        (Str(x), Str(y)) => x == y,
+        (Bytes(x), Bytes(y)) => x == y,
+        // Written to the second
+        (Time(x), Time(y)) => x.timestamp() == y.timestamp(),
+        (Id(x), Id(y)) if x == y => true,
+        (Map(_) | Struct(_), Map(_) | Struct(_)) => {
+            let (name_a, xs) = entries_of(a);
+            let (name_b, ys) = entries_of(b);
+            name_a == name_b && equal_entries(opts, cell, xs, ys)?
+        }
+        (Sum(x), Sum(y)) => {
+            x.tag == y.tag
+                && match (&x.value, &y.value) {
+                    (None, None) => true,
+                    (Some(x), Some(y)) => equal_values(opts, cell, x, y)?,
+                    _ => false,
+                }
+        }
+        (List(_) | Table(_), List(_) | Table(_)) if cell || (!may_be_tab(opts, a) && !may_be_tab(opts, b)) => {
+            let (xs, ys) = (rows_of(a), rows_of(b));
+            if xs.len() != ys.len() {
+                return Ok(false);
+            }
+            for (x, y) in xs.iter().zip(ys.iter()) {
+                if !equal_values(opts, cell, x, y)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        // The tabular decision depends only on the rows
+        (List(_) | Table(_), List(_) | Table(_)) => equal_rows(opts, a, b)?,
+        // Differing refs may still print alike
+        (Id(_), Id(_)) => canonicalize_loose_with_opts(a, opts)? == canonicalize_loose_with_opts(b, opts)?,
+        // Other kinds never print alike
+        _ => false,
+    })
+}
+
+/// Items of a list, or the row maps of a table
+fn rows_of(v: &GValue) -> Cow<'_, [GValue]> {
+    match v {
+        GValue::List(items) => Cow::Borrowed(items),
+        GValue::Table(t) => Cow::Owned((0..t.rows.len()).filter_map(|i| t.row(i)).collect()),
+        _ => Cow::Borrowed(&[]),
+    }
+}
+
+/// Top-level lists and tables, at least one of which may be written as `@tab`.
+///
+/// Written as `[...]`, rows print alike when they are equal at the top level;
+/// as `@tab`, when they are equal as cells. When both hold, or neither, the
+/// answer is known without the text.
+fn equal_rows(opts: &LooseCanonOpts, a: &GValue, b: &GValue) -> Result<bool, GlyphError> {
+    let (xs, ys) = (rows_of(a), rows_of(b));
+    if xs.len() != ys.len() {
+        return Ok(false);
+    }
+    let (mut top, mut cells, mut tab_cells) = (true, true, true);
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        top = top && equal_values(opts, false, x, y)?;
+        cells = cells && equal_values(opts, true, x, y)?;
+        tab_cells = tab_cells && (cells || equal_tab_row(opts, x, y)?);
+        if !top && !tab_cells {
+            return Ok(false);
+        }
+    }
+    Ok(match (a, b) {
+        // Same rows make the same tabular decision
+        (GValue::List(_), GValue::List(_)) if top && cells => true,
+        (GValue::Table(x), GValue::Table(y)) if top && cells && opts.auto_tabular => {
+            x.columns.iter().enumerate().all(|(i, c)| Some(x.column_type(i)) == y.column_index(c).map(|j| y.column_type(j)))
+        }
+        // A null cell prints like a missing key, and a table is always `@tab`
+        _ => canonicalize_loose_with_opts(a, opts)? == canonicalize_loose_with_opts(b, opts)?,
+    })
+}
+
+/// Whether a list or table may be written as an `@tab` block under `opts`
+fn may_be_tab(opts: &LooseCanonOpts, v: &GValue) -> bool {
+    match v {
+        GValue::List(items) => may_tabularize(opts, items),
+        GValue::Table(_) => opts.auto_tabular,
+        _ => false,
+    }
+}
+
+/// Rows as an `@tab` block prints them: a null cell like a missing key, and
+/// struct rows without their type name. Rows that fail this never print alike
+/// in `@tab` blocks.
+fn equal_tab_row(opts: &LooseCanonOpts, x: &GValue, y: &GValue) -> Result<bool, GlyphError> {
+    if !matches!(x, GValue::Map(_) | GValue::Struct(_)) || !matches!(y, GValue::Map(_) | GValue::Struct(_)) {
+        return equal_values(opts, true, x, y);
+    }
+    let covered = |from: &[MapEntry], to: &[MapEntry]| -> Result<bool, GlyphError> {
+        for e in from.iter().filter(|e| !e.value.is_null()) {
+            match to.iter().find(|t| t.key == e.key) {
+                Some(t) if equal_values(opts, true, &e.value, &t.value)? => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    };
+    let ((_, xs), (_, ys)) = (entries_of(x), entries_of(y));
+    Ok(covered(xs, ys)? && covered(ys, xs)?)
+}
+
+/// `f`, or the error for NaN and infinities, which have no canonical form
+fn finite(f: f64) -> Result<f64, GlyphError> {
+    if f.is_nan() {
+        return Err(GlyphError::InvalidFloat("NaN is not allowed in glyph text canonicalization".to_string()));
+    }
+    if f.is_infinite() {
+        return Err(GlyphError::InvalidFloat(
+            format!("{}Inf is not allowed in glyph text canonicalization", if f > 0.0 { "" } else { "-" })
+        ));
+    }
+    Ok(f)
+}
+
+/// Type name (empty for maps) and entries of a map or struct
+fn entries_of(v: &GValue) -> (&str, &[MapEntry]) {
+    match v {
+        GValue::Struct(s) => (&s.type_name, &s.fields),
+        GValue::Map(entries) => ("", entries),
+        _ => ("", &[]),
+    }
+}
+
+/// Entries match as a multiset of key/value pairs; duplicate keys keep their
+/// relative order, as in the sorted output
+fn equal_entries(opts: &LooseCanonOpts, cell: bool, xs: &[MapEntry], ys: &[MapEntry]) -> Result<bool, GlyphError> {
+    if xs.len() != ys.len() {
+        return Ok(false);
+    }
+    // Same key order on both sides needs no sorting
+    if xs.iter().zip(ys).all(|(x, y)| x.key == y.key) {
+        for (x, y) in xs.iter().zip(ys) {
+            if !equal_values(opts, cell, &x.value, &y.value)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+    let mut xs: Vec<&MapEntry> = xs.iter().collect();
+    let mut ys: Vec<&MapEntry> = ys.iter().collect();
+    xs.sort_by(|a, b| a.key.cmp(&b.key));
+    ys.sort_by(|a, b| a.key.cmp(&b.key));
+    for (x, y) in xs.iter().zip(&ys) {
+        if x.key != y.key || !equal_values(opts, cell, &x.value, &y.value)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether `items` might be emitted as `@tab` under `opts`
+fn may_tabularize(opts: &LooseCanonOpts, items: &[GValue]) -> bool {
+    opts.auto_tabular
+        && !items.is_empty()
+        && items.len() >= opts.min_rows
+        && (items.iter().all(|v| matches!(v, GValue::Map(_) | GValue::Struct(_)))
+            || (opts.tabular_lists && items.iter().all(GValue::is_list)))
+}
+
 // ============================================================
-// Auto-tabular detection and emission
+// Source maps
 // ============================================================
 
-fn try_emit_tabular(items: &[GValue], opts: &LooseCanonOpts) -> Result<Option<String>, GlyphError> {
-    if items.len() < opts.min_rows {
-        return Ok(None);
+/// Byte range of one node in a canonical string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceSpan {
+    pub start: usize,
+    pub end: usize,
+    /// The node is a list emitted as an `@tab` block
+    pub tabular: bool,
+}
+
+/// Mapping from node paths to their spans in a canonical string
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub(crate) spans: HashMap<GlyphPath, SourceSpan>,
+}
+
+impl SourceMap {
+    /// Span of the node at `path`, if it was recorded
+    pub fn get(&self, path: &GlyphPath) -> Option<SourceSpan> {
+        self.spans.get(path).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spans.len()
     }
 
-    // Collect keys from all items
-    let mut all_keys: HashSet<String> = HashSet::new();
-    let mut row_keys: Vec<HashSet<String>> = Vec::new();
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&GlyphPath, &SourceSpan)> {
+        self.spans.iter()
+    }
+}
+
+// ============================================================
+// Internal canonicalization
+// ============================================================
+
+struct SpanRecorder {
+    spans: HashMap<GlyphPath, SourceSpan>,
+}
+
+/// Buffered bytes before a streaming writer flushes
+const STREAM_CHUNK: usize = 8 * 1024;
+
+/// Most scratch strings a writer keeps for reuse
+const SCRATCH_POOL: usize = 256;
+
+/// Lists and tables with at least this many items are split across the
+/// rayon pool, in chunks of `PARALLEL_CHUNK`
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_ITEMS: usize = 4096;
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK: usize = 1024;
+
+/// State for one canonicalization pass
+pub(crate) struct CanonWriter<'o> {
+    opts: &'o LooseCanonOpts,
+    recorder: Option<SpanRecorder>,
+    /// Path of the node being written; tracked only when recording spans or
+    /// matching `tabular_paths`
+    path: Option<GlyphPath>,
+    /// Nesting depth of tabular cells being emitted. Cells stay on one line,
+    /// and spans inside them are not recorded.
+    in_cell: usize,
+    /// Streaming target; output is flushed here in chunks instead of
+    /// accumulating in the buffer
+    sink: Option<&'o mut dyn fmt::Write>,
+    /// Depth of the node being written, for `EncodeLimits::max_depth`
+    depth: usize,
+    /// Bytes already handed to the sink
+    flushed: usize,
+    /// Cleared scratch strings for cells and `@tab` blocks, kept across
+    /// calls by `Encoder`
+    pool: Vec<String>,
+}
 
-    for item in items {
-        let keys = match get_object_keys(item) {
-            Some(k) => k,
-            None => return Ok(None),
+impl<'o> CanonWriter<'o> {
+    pub(crate) fn new(opts: &'o LooseCanonOpts) -> Self {
+        Self {
+            opts,
+            recorder: None,
+            path: (!opts.tabular_paths.is_empty()).then(GlyphPath::root),
+            in_cell: 0,
+            sink: None,
+            depth: 0,
+            flushed: 0,
+            pool: Vec::new(),
+        }
+    }
+
+    /// Writer that records a source map, with paths relative to `root`
+    pub(crate) fn recording(opts: &'o LooseCanonOpts, root: GlyphPath) -> Self {
+        Self {
+            opts,
+            recorder: Some(SpanRecorder { spans: HashMap::new() }),
+            path: Some(root),
+            in_cell: 0,
+            sink: None,
+            depth: 0,
+            flushed: 0,
+            pool: Vec::new(),
+        }
+    }
+
+    /// Writer that hands its output to `sink` in chunks of about `STREAM_CHUNK` bytes
+    fn streaming(opts: &'o LooseCanonOpts, sink: &'o mut dyn fmt::Write) -> Self {
+        Self {
+            sink: Some(sink),
+            ..Self::new(opts)
+        }
+    }
+
+    /// Take scratch strings from `pool` instead of allocating them
+    pub(crate) fn with_pool(mut self, pool: Vec<String>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    pub(crate) fn into_pool(self) -> Vec<String> {
+        self.pool
+    }
+
+    /// An empty scratch string
+    fn take_buf(&mut self) -> String {
+        self.pool.pop().unwrap_or_default()
+    }
+
+    /// Return a scratch string for reuse
+    fn give_buf(&mut self, mut s: String) {
+        if self.pool.len() < SCRATCH_POOL {
+            s.clear();
+            self.pool.push(s);
+        }
+    }
+
+    /// Pass the buffered output to the sink, if any, and clear the buffer
+    fn flush(&mut self, buf: &mut String) -> Result<(), GlyphError> {
+        self.check_output(buf)?;
+        if let Some(sink) = self.sink.as_mut() {
+            sink.write_str(buf).map_err(|e| GlyphError::Io(std::io::Error::other(e)))?;
+            self.flushed += buf.len();
+            buf.clear();
+        }
+        Ok(())
+    }
+
+    fn check_output(&self, buf: &str) -> Result<(), GlyphError> {
+        match self.opts.limits.max_output_bytes {
+            Some(max) if self.flushed + buf.len() > max => Err(GlyphError::LimitExceeded(format!(
+                "output over max_output_bytes {}",
+                max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn into_source_map(self) -> SourceMap {
+        SourceMap {
+            spans: self.recorder.map(|r| r.spans).unwrap_or_default(),
+        }
+    }
+
+    fn enter_key(&mut self, key: &str) {
+        if let Some(path) = self.path.as_mut() {
+            path.push_key(key);
+        }
+    }
+
+    fn enter_index(&mut self, idx: usize) {
+        if let Some(path) = self.path.as_mut() {
+            path.push_index(idx);
+        }
+    }
+
+    fn leave(&mut self) {
+        if let Some(path) = self.path.as_mut() {
+            path.pop();
+        }
+    }
+
+    pub(crate) fn write(&mut self, buf: &mut String, v: &GValue) -> Result<(), GlyphError> {
+        let start = buf.len();
+        let opts = self.opts;
+        let mut tabular = false;
+        // A cell can't hold a multi-line block, and without auto_tabular a
+        // table is written as its row list so it hashes like the list it
+        // parses from; limits then see the list at the same depth
+        let rows;
+        let v = match v {
+            GValue::Table(t) if self.in_cell > 0 || !opts.auto_tabular => {
+                rows = t.to_list();
+                &rows
+            }
+            _ => v,
+        };
+        let limited = if opts.limits.is_set() {
+            opts.limits.apply(v, self.depth)?
+        } else {
+            Cow::Borrowed(v)
         };
-        let key_set: HashSet<String> = keys.into_iter().collect();
-        all_keys.extend(key_set.clone());
-        row_keys.push(key_set);
+        let v = limited.as_ref();
+        if let Some((kind, len)) = opts.max_value_bytes_per_type.overflow(v) {
+            write_trunc(buf, kind, len, v)?;
+            self.record(start, buf.len(), false);
+            return Ok(());
+        }
+        self.depth += 1;
+        match v {
+            GValue::Null => buf.push_str(canon_null(opts.null_style)),
+            GValue::Bool(b) => buf.push_str(canon_bool(*b, opts.bool_style)),
+            GValue::Int(n) => buf.push_str(&canon_int(*n)),
+            GValue::Float(f) => buf.push_str(&canon_float(*f)?),
+            GValue::Str(s) => buf.push_str(&canon_text(opts, s)),
+            GValue::Bytes(data) => write_canon_bytes(buf, data),
+            GValue::Time(t) => buf.push_str(&canon_time(t, opts.time_style)),
+            GValue::Id(ref_id) => write_canon_ref(buf, ref_id),
+            GValue::List(items) => tabular = self.write_list(buf, items)?,
+            GValue::Map(entries) => self.write_entries(buf, entries)?,
+            GValue::Struct(s) => {
+                buf.push_str(&s.type_name);
+                self.write_entries(buf, &s.fields)?;
+            }
+            GValue::Sum(s) => self.write_sum(buf, s)?,
+            GValue::Table(t) => {
+                self.write_table(buf, t)?;
+                tabular = true;
+            }
+        }
+        self.depth -= 1;
+        if self.depth == 0 && self.in_cell == 0 {
+            self.check_output(buf)?;
+        }
+        self.record(start, buf.len(), tabular);
+        // Cells are rendered into their own buffers; only flush the output
+        if self.in_cell == 0 && buf.len() >= STREAM_CHUNK {
+            self.flush(buf)?;
+        }
+        Ok(())
     }
 
-    // Don't use tabular for empty objects or too many columns
-    if all_keys.is_empty() || all_keys.len() > opts.max_cols {
-        return Ok(None);
+    fn record(&mut self, start: usize, end: usize, tabular: bool) {
+        if let Some(rec) = self.recorder.as_mut() {
+            if self.in_cell == 0 {
+                rec.spans.insert(self.path.clone().unwrap_or_default(), SourceSpan { start, end, tabular });
+            }
+        }
     }
+}
 
-    // Check homogeneity
-    if !opts.allow_missing {
-        // Strict mode: all items must have identical keys
-        let first_keys = &row_keys[0];
-        for keys in &row_keys[1..] {
-            if keys != first_keys {
-                return Ok(None);
+fn canon_null(style: NullStyle) -> &'static str {
+    match style {
+        NullStyle::Underscore => "_",
+        NullStyle::Symbol => "∅",
+        NullStyle::Word => "null",
+    }
+}
+
+fn canon_time(t: &DateTime<Utc>, style: TimeStyle) -> String {
+    let t = match style {
+        TimeStyle::Exact => *t,
+        TimeStyle::RoundTo(bucket) => truncate_time(t, bucket),
+    };
+    t.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Truncate `t` to a multiple of `bucket` since the Unix epoch
+fn truncate_time(t: &DateTime<Utc>, bucket: Duration) -> DateTime<Utc> {
+    const NANOS: i128 = 1_000_000_000;
+    let bucket = bucket.as_nanos() as i128;
+    if bucket == 0 {
+        return *t;
+    }
+    let nanos = t.timestamp() as i128 * NANOS + t.timestamp_subsec_nanos() as i128;
+    let floored = nanos.div_euclid(bucket) * bucket;
+    DateTime::from_timestamp(floored.div_euclid(NANOS) as i64, floored.rem_euclid(NANOS) as u32).unwrap_or(*t)
+}
+
+fn canon_bool(b: bool, style: BoolStyle) -> &'static str {
+    match (style, b) {
+        (BoolStyle::Short, true) => "t",
+        (BoolStyle::Short, false) => "f",
+        (BoolStyle::Word, true) => "true",
+        (BoolStyle::Word, false) => "false",
+    }
+}
+
+fn canon_int(n: i64) -> String {
+    n.to_string()
+}
+
+/// Largest integer magnitude a double can represent exactly (2^53 - 1).
+pub(crate) const MAX_SAFE_INT: f64 = 9_007_199_254_740_991.0;
+
+/// Canonical float form (CANONICAL_FORMS.md §3, D4).
+///
+/// Integer-valued floats inside the safe-integer window collapse to integer
+/// literals (loose-mode typing). Everything else uses shortest round-trip
+/// digits from the std formatter (Grisu with Dragon4 fallback), switching to
+/// exponential form when the decimal exponent is `<= -5` or `>= 6`.
+fn canon_float(f: f64) -> Result<String, GlyphError> {
+    let f = finite(f)?;
+
+    // Whole numbers in the safe window (including -0.0) are ints in loose mode
+    if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INT {
+        return Ok(format!("{}", f as i64));
+    }
+
+    // `{:e}` yields the shortest round-trip mantissa plus a bare exponent, e.g. "1.5e-7"
+    let sci = format!("{:e}", f);
+    let (mantissa, exp) = sci.split_once('e').expect("`{:e}` always emits an exponent");
+    let exp: i32 = exp.parse().expect("`{:e}` exponent is an integer");
+
+    Ok(if (-4..6).contains(&exp) {
+        // `{}` yields the same shortest digits in plain decimal notation
+        format!("{}", f)
+    } else {
+        format!("{}e{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.unsigned_abs())
+    })
+}
+
+/// Canonical form of a string under the default bare rules
+pub fn canon_string(s: &str) -> String {
+    canon_string_with(s, &BareRules::default())
+}
+
+/// Canonical form of a string under custom bare rules
+pub fn canon_string_with(s: &str, rules: &BareRules) -> String {
+    if rules.is_bare_safe(s) {
+        s.to_string()
+    } else {
+        quote_string(s)
+    }
+}
+
+pub(crate) fn quote_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    push_quoted(&mut out, s);
+    out
+}
+
+/// Append `s` to `out` as a quoted string
+fn push_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    let mut rest = s;
+    // Copy the runs between escapes whole; escaped bytes are all ASCII
+    while let Some(i) = find_escape(rest.as_bytes()) {
+        out.push_str(&rest[..i]);
+        match rest.as_bytes()[i] {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b => {
+                let _ = write!(out, "\\u{:04x}", b);
+            }
+        }
+        rest = &rest[i + 1..];
+    }
+    out.push_str(rest);
+    out.push('"');
+}
+
+/// How `canon_string_with(a, rules)` and `canon_string_with(b, rules)`
+/// compare, without building either
+pub(crate) fn cmp_canon_text(rules: &BareRules, a: &str, b: &str) -> std::cmp::Ordering {
+    if a == b {
+        return std::cmp::Ordering::Equal;
+    }
+    match (rules.is_bare_safe(a), rules.is_bare_safe(b)) {
+        (true, true) => a.cmp(b),
+        (bare_a, bare_b) => canon_bytes(a, !bare_a).cmp(canon_bytes(b, !bare_b)),
+    }
+}
+
+/// The bytes `push_quoted` writes for `s`, or `s` itself when bare
+fn canon_bytes(s: &str, quoted: bool) -> impl Iterator<Item = u8> + '_ {
+    let quote = quoted.then_some(b'"');
+    quote
+        .into_iter()
+        .chain(s.bytes().flat_map(move |b| EscapedByte::new(b, quoted)))
+        .chain(quote)
+}
+
+/// One byte of a string as written, escaped when inside quotes
+struct EscapedByte {
+    bytes: [u8; 6],
+    pos: u8,
+    len: u8,
+}
+
+impl EscapedByte {
+    fn new(b: u8, quoted: bool) -> Self {
+        let (bytes, len) = match b {
+            _ if !quoted => ([b, 0, 0, 0, 0, 0], 1),
+            b'\\' | b'"' => ([b'\\', b, 0, 0, 0, 0], 2),
+            b'\n' => ([b'\\', b'n', 0, 0, 0, 0], 2),
+            b'\r' => ([b'\\', b'r', 0, 0, 0, 0], 2),
+            b'\t' => ([b'\\', b't', 0, 0, 0, 0], 2),
+            b if b < 0x20 => {
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                ([b'\\', b'u', b'0', b'0', HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]], 6)
             }
+            b => ([b, 0, 0, 0, 0, 0], 1),
+        };
+        Self { bytes, pos: 0, len }
+    }
+}
+
+impl Iterator for EscapedByte {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.pos == self.len {
+            return None;
         }
+        self.pos += 1;
+        Some(self.bytes[self.pos as usize - 1])
+    }
+}
+
+fn write_canon_bytes(buf: &mut String, data: &[u8]) {
+    buf.push_str("b64\"");
+    buf.push_str(&BASE64.encode(data));
+    buf.push('"');
+}
+
+fn write_canon_ref(buf: &mut String, ref_id: &RefId) {
+    write_canon_ref_parts(buf, &ref_id.prefix, &ref_id.value);
+}
+
+fn write_canon_ref_parts(buf: &mut String, prefix: &str, value: &str) {
+    buf.push('^');
+    if !prefix.is_empty() {
+        buf.push_str(prefix);
+        buf.push(':');
+    }
+    // Ref IDs allow more characters as bare (including starting with digits)
+    if is_ref_bare_safe(value) {
+        buf.push_str(value);
     } else {
-        // Allow missing, but check that at least 50% keys are common
-        let mut common_keys: HashSet<String> = row_keys[0].clone();
-        for keys in &row_keys[1..] {
-            common_keys = common_keys.intersection(keys).cloned().collect();
+        push_quoted(buf, value);
+    }
+}
+
+/// Placeholder standing in for a value over its size cap
+pub(crate) fn trunc_placeholder(kind: &str, len: usize, v: &GValue) -> Result<GValue, GlyphError> {
+    let hash = hash_loose(v)?;
+    Ok(GValue::struct_val(
+        "Trunc",
+        vec![
+            MapEntry::new("kind", GValue::str(kind)),
+            MapEntry::new("len", GValue::int(len as i64)),
+            MapEntry::new("ref", GValue::id("trunc", &hash[..16])),
+        ],
+    ))
+}
+
+fn write_trunc(buf: &mut String, kind: &str, len: usize, v: &GValue) -> Result<(), GlyphError> {
+    // Plain options: the placeholder itself must never be capped
+    let placeholder = trunc_placeholder(kind, len, v)?;
+    CanonWriter::new(&LooseCanonOpts::default()).write(buf, &placeholder)
+}
+
+/// Check if a ref ID value is safe to emit without quotes
+/// (more permissive than regular strings - allows starting with digits)
+fn is_ref_bare_safe(s: &str) -> bool {
+    if s.is_empty() {
+        return false;
+    }
+
+    // Must contain only safe characters (no spaces, quotes, etc.)
+    s.chars().all(|c| {
+        c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+            || (c as u32 > 127) // Allow unicode
+    })
+}
+
+impl CanonWriter<'_> {
+    /// Write a list, returning whether it was emitted as a tabular block
+    fn write_list(&mut self, buf: &mut String, items: &[GValue]) -> Result<bool, GlyphError> {
+        // Try tabular if enabled; never inside a cell
+        let mode = self.tabular_mode();
+        let enabled = match mode {
+            TabularMode::Auto => self.opts.auto_tabular,
+            TabularMode::Force => true,
+            TabularMode::Off => false,
+        };
+        if enabled && self.in_cell == 0 {
+            let forced = mode == TabularMode::Force;
+            let min_rows = if forced { 1 } else { self.opts.min_rows };
+            if let Some(tabular) = self.try_emit_tabular(items, min_rows)? {
+                let keep = forced || !self.opts.optimize_size || tabular.len() <= self.list_len(items)?;
+                if keep {
+                    buf.push_str(&tabular);
+                }
+                self.give_buf(tabular);
+                if keep {
+                    return Ok(true);
+                }
+            }
+        }
+
+        // Spans and streaming need the output in order as it is written
+        #[cfg(feature = "parallel")]
+        if items.len() >= PARALLEL_MIN_ITEMS && self.recorder.is_none() && self.sink.is_none() {
+            self.par_write_items(buf, items)?;
+            return Ok(false);
+        }
+
+        buf.push('[');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            self.enter_index(i);
+            self.write(buf, item)?;
+            self.leave();
+        }
+        buf.push(']');
+        Ok(false)
+    }
+
+    /// A writer starting at the given position, for a rayon task or an
+    /// incremental encoder
+    pub(crate) fn fork<'a>(opts: &'a LooseCanonOpts, path: &Option<GlyphPath>, in_cell: usize, depth: usize) -> CanonWriter<'a> {
+        CanonWriter {
+            path: path.clone(),
+            in_cell,
+            depth,
+            ..CanonWriter::new(opts)
+        }
+    }
+
+    /// `[item item ...]` with chunks of items written in parallel and joined
+    /// in order. On failure, the error is the first one a serial write would
+    /// have hit.
+    #[cfg(feature = "parallel")]
+    fn par_write_items(&mut self, buf: &mut String, items: &[GValue]) -> Result<(), GlyphError> {
+        use rayon::prelude::*;
+        let (opts, path, in_cell, depth) = (self.opts, &self.path, self.in_cell, self.depth);
+        let chunks: Vec<Result<String, GlyphError>> = items
+            .par_chunks(PARALLEL_CHUNK)
+            .enumerate()
+            .map(|(c, chunk)| {
+                let mut writer = CanonWriter::fork(opts, path, in_cell, depth);
+                let mut out = String::new();
+                for (j, item) in chunk.iter().enumerate() {
+                    if j > 0 {
+                        out.push(' ');
+                    }
+                    writer.enter_index(c * PARALLEL_CHUNK + j);
+                    writer.write(&mut out, item)?;
+                    writer.leave();
+                }
+                Ok(out)
+            })
+            .collect();
+        buf.push('[');
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            buf.push_str(&chunk?);
+        }
+        buf.push(']');
+        Ok(())
+    }
+
+    /// Mode from the last `tabular_paths` rule covering the current list
+    fn tabular_mode(&self) -> TabularMode {
+        let Some(path) = self.path.as_ref() else {
+            return TabularMode::Auto;
+        };
+        let rows = path.index(0);
+        let covers = |p: &PathPattern| {
+            p.matches_prefix_of(path) || (p.segments().last() == Some(&PatternSegment::AnyIndex) && p.matches(&rows))
+        };
+        self.opts
+            .tabular_paths
+            .iter()
+            .rev()
+            .find(|r| covers(&r.pattern))
+            .map_or(TabularMode::Auto, |r| r.mode)
+    }
+
+    /// Byte length of `items` emitted as a plain `[...]` list
+    fn list_len(&mut self, items: &[GValue]) -> Result<usize, GlyphError> {
+        let mut writer = CanonWriter {
+            opts: self.opts,
+            recorder: None,
+            path: self.path.clone(),
+            in_cell: self.in_cell,
+            sink: None,
+            depth: self.depth,
+            flushed: 0,
+            pool: std::mem::take(&mut self.pool),
+        };
+        let mut scratch = writer.take_buf();
+        let mut result = Ok(());
+        for (i, item) in items.iter().enumerate() {
+            writer.enter_index(i);
+            result = writer.write(&mut scratch, item);
+            writer.leave();
+            if result.is_err() {
+                break;
+            }
         }
+        let len = scratch.len() + items.len().saturating_sub(1) + 2;
+        writer.give_buf(scratch);
+        self.pool = writer.into_pool();
+        result.map(|()| len)
+    }
+
+    /// Write map entries or struct fields as `{k=v ...}`, sorted by canonical key
+    fn write_entries(&mut self, buf: &mut String, entries: &[MapEntry]) -> Result<(), GlyphError> {
+        buf.push('{');
+
+        let mut sorted: Vec<_> = entries.iter().collect();
+        order_keys(self.opts, &mut sorted, |e| &e.key);
+
+        for (i, entry) in sorted.iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            buf.push_str(&canon_text(self.opts, &entry.key));
+            buf.push('=');
+            self.enter_key(&entry.key);
+            self.write(buf, &entry.value)?;
+            self.leave();
+        }
+        buf.push('}');
+        Ok(())
+    }
+
+    fn write_sum(&mut self, buf: &mut String, s: &SumValue) -> Result<(), GlyphError> {
+        buf.push_str(&s.tag);
+        buf.push('(');
+        if let Some(ref value) = s.value {
+            self.enter_key(&s.tag);
+            self.write(buf, value)?;
+            self.leave();
+        }
+        buf.push(')');
+        Ok(())
+    }
+}
+
+// ============================================================
+// Arena trees
+// ============================================================
+
+impl CanonWriter<'_> {
+    /// Write the arena tree at `id`, as `write` would write its owned form
+    pub(crate) fn write_arena(&mut self, buf: &mut String, arena: &GArena, id: NodeId) -> Result<(), GlyphError> {
+        let opts = self.opts;
+        // Options that rewrite values or match paths work on the owned form
+        if opts.limits.is_set() || opts.max_value_bytes_per_type != SizeCaps::default() || !opts.tabular_paths.is_empty() {
+            return self.write(buf, &arena.to_value(id));
+        }
+        match arena.get(id) {
+            ArenaRef::Null => buf.push_str(canon_null(opts.null_style)),
+            ArenaRef::Bool(b) => buf.push_str(canon_bool(b, opts.bool_style)),
+            ArenaRef::Int(n) => {
+                let _ = write!(buf, "{}", n);
+            }
+            ArenaRef::Float(f) => buf.push_str(&canon_float(f)?),
+            ArenaRef::Str(s) => push_canon_text(buf, opts, s),
+            ArenaRef::Bytes(data) => write_canon_bytes(buf, data),
+            ArenaRef::Time(t) => buf.push_str(&canon_time(&t, opts.time_style)),
+            ArenaRef::Id { prefix, value } => write_canon_ref_parts(buf, prefix, value),
+            ArenaRef::List(items) => {
+                let rows = |pred: fn(&ArenaRef<'_>) -> bool| items.iter().all(|&item| pred(&arena.get(item)));
+                let may_tabularize = opts.auto_tabular
+                    && self.in_cell == 0
+                    && !items.is_empty()
+                    && items.len() >= opts.min_rows
+                    && (rows(|r| matches!(r, ArenaRef::Map(_) | ArenaRef::Struct { .. }))
+                        || (opts.tabular_lists && rows(|r| matches!(r, ArenaRef::List(_)))));
+                if may_tabularize {
+                    return self.write(buf, &arena.to_value(id));
+                }
+                buf.push('[');
+                for (i, &item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(' ');
+                    }
+                    self.write_arena(buf, arena, item)?;
+                }
+                buf.push(']');
+            }
+            ArenaRef::Map(fields) => self.write_arena_entries(buf, arena, fields)?,
+            ArenaRef::Struct { type_name, fields } => {
+                buf.push_str(type_name);
+                self.write_arena_entries(buf, arena, fields)?;
+            }
+            ArenaRef::Sum { tag, value } => {
+                buf.push_str(tag);
+                buf.push('(');
+                if let Some(value) = value {
+                    self.write_arena(buf, arena, value)?;
+                }
+                buf.push(')');
+            }
+            ArenaRef::Table(t) if self.in_cell > 0 || !opts.auto_tabular => self.write(buf, &t.to_list())?,
+            ArenaRef::Table(t) => self.write_table(buf, t)?,
+        }
+        Ok(())
+    }
 
-        // If less than half the keys are common, don't use tabular
-        if common_keys.len() * 2 < all_keys.len() {
+    fn write_arena_entries(&mut self, buf: &mut String, arena: &GArena, fields: ArenaEntries<'_>) -> Result<(), GlyphError> {
+        buf.push('{');
+        let mut sorted: Vec<(&str, NodeId)> = fields.iter().collect();
+        order_keys(self.opts, &mut sorted, |e| e.0);
+        for (i, (key, value)) in sorted.into_iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            push_canon_text(buf, self.opts, key);
+            buf.push('=');
+            self.write_arena(buf, arena, value)?;
+        }
+        buf.push('}');
+        Ok(())
+    }
+
+    /// Write a borrowed tree, as `write` would write `v.to_value()`
+    pub(crate) fn write_cow(&mut self, buf: &mut String, v: &GValueCow<'_>) -> Result<(), GlyphError> {
+        let opts = self.opts;
+        // Options that rewrite values or match paths work on the owned form
+        if opts.limits.is_set() || opts.max_value_bytes_per_type != SizeCaps::default() || !opts.tabular_paths.is_empty() {
+            return self.write(buf, &v.to_value());
+        }
+        match v {
+            GValueCow::Null => buf.push_str(canon_null(opts.null_style)),
+            GValueCow::Bool(b) => buf.push_str(canon_bool(*b, opts.bool_style)),
+            GValueCow::Int(n) => {
+                let _ = write!(buf, "{}", n);
+            }
+            GValueCow::Float(f) => buf.push_str(&canon_float(*f)?),
+            GValueCow::Str(s) => push_canon_text(buf, opts, s),
+            GValueCow::Bytes(data) => write_canon_bytes(buf, data),
+            GValueCow::Time(t) => buf.push_str(&canon_time(t, opts.time_style)),
+            GValueCow::Id { prefix, value } => write_canon_ref_parts(buf, prefix, value),
+            GValueCow::List(items) => {
+                let may_tabularize = opts.auto_tabular
+                    && self.in_cell == 0
+                    && !items.is_empty()
+                    && items.len() >= opts.min_rows
+                    && (items.iter().all(GValueCow::is_record) || (opts.tabular_lists && items.iter().all(GValueCow::is_list)));
+                if may_tabularize {
+                    return self.write(buf, &v.to_value());
+                }
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(' ');
+                    }
+                    self.write_cow(buf, item)?;
+                }
+                buf.push(']');
+            }
+            GValueCow::Map(fields) => self.write_cow_entries(buf, fields)?,
+            GValueCow::Struct { type_name, fields } => {
+                buf.push_str(type_name);
+                self.write_cow_entries(buf, fields)?;
+            }
+            GValueCow::Sum { tag, value } => {
+                buf.push_str(tag);
+                buf.push('(');
+                if let Some(value) = value {
+                    self.write_cow(buf, value)?;
+                }
+                buf.push(')');
+            }
+            GValueCow::Value(v) => self.write(buf, v)?,
+        }
+        Ok(())
+    }
+
+    fn write_cow_entries(&mut self, buf: &mut String, fields: &[(Cow<'_, str>, GValueCow<'_>)]) -> Result<(), GlyphError> {
+        buf.push('{');
+        let mut sorted: Vec<&(Cow<'_, str>, GValueCow<'_>)> = fields.iter().collect();
+        order_keys(self.opts, &mut sorted, |e| &e.0);
+        for (i, (key, value)) in sorted.into_iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            push_canon_text(buf, self.opts, key);
+            buf.push('=');
+            self.write_cow(buf, value)?;
+        }
+        buf.push('}');
+        Ok(())
+    }
+}
+
+// ============================================================
+// Auto-tabular detection and emission
+// ============================================================
+
+impl CanonWriter<'_> {
+    fn try_emit_tabular(&mut self, items: &[GValue], min_rows: usize) -> Result<Option<String>, GlyphError> {
+        let opts = self.opts;
+        if items.is_empty() || items.len() < min_rows {
             return Ok(None);
         }
+        if opts.tabular_lists && items.iter().all(GValue::is_list) {
+            return self.try_emit_list_rows(items);
+        }
+
+        // Collect keys from all items
+        let mut all_keys: HashSet<String> = HashSet::new();
+        let mut first_seen: Vec<String> = Vec::new();
+        let mut row_keys: Vec<HashSet<String>> = Vec::new();
+
+        for item in items {
+            if opts.max_value_bytes_per_type.overflow(item).is_some() {
+                return Ok(None);
+            }
+            let keys = match get_object_keys(item) {
+                Some(k) => k,
+                None => return Ok(None),
+            };
+            for k in &keys {
+                if all_keys.insert(k.clone()) {
+                    first_seen.push(k.clone());
+                }
+            }
+            row_keys.push(keys.into_iter().collect());
+        }
+
+        // Don't use tabular for empty objects or too many columns
+        if all_keys.is_empty() || all_keys.len() > opts.max_cols {
+            return Ok(None);
+        }
+
+        // Check homogeneity
+        if !opts.allow_missing {
+            // Strict mode: all items must have identical keys
+            let first_keys = &row_keys[0];
+            for keys in &row_keys[1..] {
+                if keys != first_keys {
+                    return Ok(None);
+                }
+            }
+        } else {
+            // Allow missing, but check that at least 50% keys are common
+            let mut common_keys: HashSet<String> = row_keys[0].clone();
+            for keys in &row_keys[1..] {
+                common_keys = common_keys.intersection(keys).cloned().collect();
+            }
+
+            // If less than half the keys are common, don't use tabular
+            if common_keys.len() * 2 < all_keys.len() {
+                return Ok(None);
+            }
+        }
+
+        // Order columns
+        let mut cols = first_seen;
+        order_columns(opts, &mut cols, |c| c);
+
+        let rows: Vec<HashMap<String, &GValue>> = items.iter().map(get_object_values).collect();
+        let mut paths: Vec<Vec<String>> = cols.iter().map(|c| vec![c.clone()]).collect();
+        let mut flat = false;
+        if opts.tabular_flatten_depth > 0 {
+            let flattened: Vec<Vec<String>> = cols
+                .iter()
+                .flat_map(|c| {
+                    let cells: Vec<Option<&GValue>> = rows.iter().map(|r| r.get(c).copied()).collect();
+                    flatten_column(opts, vec![c.clone()], &cells, opts.tabular_flatten_depth)
+                })
+                .collect();
+            if flattened.iter().any(|p| p.len() > 1) && flattened.len() <= opts.max_cols {
+                paths = flattened;
+                flat = true;
+            }
+        }
+
+        pin_columns(opts, &mut paths, |p| p);
+
+        let cells: Vec<Vec<Option<&GValue>>> = rows
+            .iter()
+            .map(|values| {
+                paths
+                    .iter()
+                    .map(|path| {
+                        path[1..]
+                            .iter()
+                            .try_fold(values.get(&path[0]).copied(), |v, seg| Some(v?.get(seg)))
+                            .flatten()
+                    })
+                    .collect()
+            })
+            .collect();
+        let types: Vec<Option<GType>> = (0..paths.len())
+            .map(|i| if opts.tabular_types { infer_column_type(cells.iter().map(|row| row[i])) } else { None })
+            .collect();
+
+        // Build tabular output
+        let names: Vec<String> = paths
+            .iter()
+            .map(|p| p.iter().map(|seg| canon_text(opts, seg)).collect::<Vec<_>>().join("."))
+            .collect();
+        let flat_attr = if flat { format!(" flat={}", opts.tabular_flatten_depth) } else { String::new() };
+        let mut buf = self.take_buf();
+        let _ = writeln!(
+            buf,
+            "@tab _ rows={} cols={}{} [{}]",
+            items.len(),
+            paths.len(),
+            flat_attr,
+            tab_header(&names, &types)
+        );
+
+        let keys: Vec<String> = paths.iter().map(|p| p.join(".")).collect();
+        self.write_tab_rows(&mut buf, cells, &keys)?;
+        Ok(Some(buf))
     }
 
-    // Sort columns
-    let mut cols: Vec<String> = all_keys.into_iter().collect();
-    cols.sort_by(|a, b| canon_string(a).cmp(&canon_string(b)));
+    /// Explicit table: an `@tab` block whenever `auto_tabular` is on, whatever
+    /// the other tabular options.
+    /// Declared column types are emitted and checked against the cells.
+    fn write_table(&mut self, buf: &mut String, t: &TableValue) -> Result<(), GlyphError> {
+        let opts = self.opts;
+        if let Some(row) = t.rows.iter().find(|r| r.len() != t.columns.len()) {
+            return Err(GlyphError::InvalidValue(format!(
+                "table row has {} cells, expected {}",
+                row.len(),
+                t.columns.len()
+            )));
+        }
+        let order: Vec<(&String, usize)> = tab_column_order(opts, &t.columns).into_iter().map(|i| (&t.columns[i], i)).collect();
 
-    // Build tabular output
-    let mut buf = String::new();
-    buf.push_str(&format!(
-        "@tab _ rows={} cols={} [{}]\n",
-        items.len(),
-        cols.len(),
-        cols.iter().map(|c| canon_string(c)).collect::<Vec<_>>().join(" ")
-    ));
-
-    for item in items {
-        buf.push('|');
-        let values = get_object_values(item);
-        for col in &cols {
-            let cell = match values.get(col) {
-                Some(v) => {
-                    let mut cell_buf = String::new();
-                    write_canon_loose(&mut cell_buf, v, opts)?;
-                    cell_buf.replace('|', "\\|")
+        let mut types = Vec::with_capacity(order.len());
+        for &(col, i) in &order {
+            let ty = match t.column_type(i) {
+                Some(ty) => {
+                    if let Some(bad) = t.rows.iter().map(|r| &r[i]).find(|v| !cell_matches(v, ty)) {
+                        return Err(GlyphError::TypeMismatch {
+                            expected: format!("{} in column {:?}", ty, col),
+                            got: bad.gtype().to_string(),
+                        });
+                    }
+                    Some(ty)
                 }
-                None => canon_null(opts.null_style).to_string(),
+                None if opts.tabular_types => infer_column_type(t.rows.iter().map(|r| Some(&r[i]))),
+                None => None,
             };
-            buf.push_str(&cell);
-            buf.push('|');
+            types.push(ty);
         }
-        buf.push('\n');
+
+        let names: Vec<&str> = order.iter().map(|(c, _)| c.as_str()).collect();
+        push_tab_header(buf, opts, &names, &types, t.rows.len());
+        let cells = t.rows.iter().map(|row| order.iter().map(|&(_, i)| Some(&row[i])).collect()).collect();
+        let keys: Vec<String> = order.iter().map(|(c, _)| c.to_string()).collect();
+        self.write_tab_rows(buf, cells, &keys)
     }
-    buf.push_str("@end");
 
-    Ok(Some(buf))
+    /// Rows of equal-length lists, with an empty column list
+    fn try_emit_list_rows(&mut self, items: &[GValue]) -> Result<Option<String>, GlyphError> {
+        let opts = self.opts;
+        let rows: Vec<&[GValue]> = items.iter().filter_map(GValue::as_list).collect();
+        let width = rows[0].len();
+        if width == 0 || width > opts.max_cols || rows.iter().any(|r| r.len() != width) {
+            return Ok(None);
+        }
+        if items.iter().any(|item| opts.max_value_bytes_per_type.overflow(item).is_some()) {
+            return Ok(None);
+        }
+
+        let mut buf = self.take_buf();
+        let _ = writeln!(buf, "@tab _ rows={} cols={} []", rows.len(), width);
+        let cells = rows.iter().map(|row| row.iter().map(Some).collect()).collect();
+        self.write_tab_rows(&mut buf, cells, &[])?;
+        Ok(Some(buf))
+    }
+
+    /// Write `|cell|cell|` rows, ordered per `opts.sort_rows`, then `@end`.
+    ///
+    /// `keys` names the columns for `RowOrder::ByKey`; missing cells are `_`.
+    fn write_tab_rows(&mut self, buf: &mut String, rows: Vec<Vec<Option<&GValue>>>, keys: &[String]) -> Result<(), GlyphError> {
+        #[cfg(feature = "parallel")]
+        let lines = if rows.len() >= PARALLEL_MIN_ITEMS {
+            self.par_render_rows(&rows)?
+        } else {
+            self.render_rows(&rows)?
+        };
+        #[cfg(not(feature = "parallel"))]
+        let lines = self.render_rows(&rows)?;
+        self.finish_tab_rows(buf, lines, keys);
+        Ok(())
+    }
+
+    /// Write rendered rows, ordered per `opts.sort_rows`, then `@end`
+    pub(crate) fn finish_tab_rows(&mut self, buf: &mut String, mut lines: Vec<Vec<String>>, keys: &[String]) {
+        match &self.opts.sort_rows {
+            RowOrder::Source => {}
+            RowOrder::Canonical => lines.sort(),
+            RowOrder::ByKey(key) => {
+                if let Some(col) = keys.iter().position(|k| k == key) {
+                    lines.sort_by(|a, b| a[col].cmp(&b[col]).then_with(|| a.cmp(b)));
+                } else {
+                    lines.sort();
+                }
+            }
+        }
+
+        let mut widths = vec![0; lines.first().map_or(0, Vec::len)];
+        if self.opts.align_tabular {
+            for cells in &lines {
+                for (w, cell) in widths.iter_mut().zip(cells) {
+                    *w = (*w).max(cell.chars().count());
+                }
+            }
+        }
+
+        for cells in lines {
+            push_tab_line(buf, &cells, &widths);
+            for cell in cells {
+                self.give_buf(cell);
+            }
+        }
+        buf.push_str("@end");
+    }
+
+    /// Rendered cells of each row
+    fn render_rows(&mut self, rows: &[Vec<Option<&GValue>>]) -> Result<Vec<Vec<String>>, GlyphError> {
+        let mut lines = Vec::with_capacity(rows.len());
+        for row in rows {
+            lines.push(self.render_row(row)?);
+        }
+        Ok(lines)
+    }
+
+    /// Rendered cells of one row; missing cells are `_`
+    pub(crate) fn render_row(&mut self, row: &[Option<&GValue>]) -> Result<Vec<String>, GlyphError> {
+        let mut cells = Vec::with_capacity(row.len());
+        for v in row {
+            let mut cell = self.take_buf();
+            match v {
+                Some(v) => self.write_cell(&mut cell, v)?,
+                None => cell.push_str(canon_null(self.opts.null_style)),
+            }
+            cells.push(cell);
+        }
+        Ok(cells)
+    }
+
+    /// `render_rows` over chunks of rows in parallel, joined in order
+    #[cfg(feature = "parallel")]
+    fn par_render_rows(&mut self, rows: &[Vec<Option<&GValue>>]) -> Result<Vec<Vec<String>>, GlyphError> {
+        use rayon::prelude::*;
+        let (opts, path, in_cell, depth) = (self.opts, &self.path, self.in_cell, self.depth);
+        let chunks: Vec<Result<Vec<Vec<String>>, GlyphError>> = rows
+            .par_chunks(PARALLEL_CHUNK)
+            .map(|chunk| CanonWriter::fork(opts, path, in_cell, depth).render_rows(chunk))
+            .collect();
+        let mut lines = Vec::with_capacity(rows.len());
+        for chunk in chunks {
+            lines.extend(chunk?);
+        }
+        Ok(lines)
+    }
+
+    /// Write a tabular cell on one line, escaping `|` as `\|`
+    fn write_cell(&mut self, buf: &mut String, v: &GValue) -> Result<(), GlyphError> {
+        let mut cell = self.take_buf();
+        self.in_cell += 1;
+        let result = self.write(&mut cell, v);
+        self.in_cell -= 1;
+        result?;
+        push_escaped_cell(buf, &cell);
+        self.give_buf(cell);
+        Ok(())
+    }
+}
+
+/// Append `|cell|cell|` and a newline, padding each cell to its width
+pub(crate) fn push_tab_line(buf: &mut String, cells: &[String], widths: &[usize]) {
+    buf.push('|');
+    for (i, cell) in cells.iter().enumerate() {
+        buf.push_str(cell);
+        for _ in cell.chars().count()..widths.get(i).copied().unwrap_or(0) {
+            buf.push(' ');
+        }
+        buf.push('|');
+    }
+    buf.push('\n');
+}
+
+/// Append a rendered cell to `buf`, escaped for a `|`-delimited row.
+///
+/// Rendered values hold no raw newline and no `\` outside quoted strings, so
+/// escaping `|` as `\|` is enough: readers take `\` plus the next character
+/// as a pair and split on any other `|`.
+fn push_escaped_cell(buf: &mut String, cell: &str) {
+    for (i, part) in cell.split('|').enumerate() {
+        if i > 0 {
+            buf.push_str("\\|");
+        }
+        buf.push_str(part);
+    }
+}
+
+/// Canonical string or key, normalized per `opts.unicode_norm`
+fn canon_text(opts: &LooseCanonOpts, s: &str) -> String {
+    match opts.unicode_norm {
+        UnicodeNorm::None => canon_string_with(s, &opts.bare_rules),
+        UnicodeNorm::Nfc => canon_string_with(&nfc(s), &opts.bare_rules),
+    }
+}
+
+/// Append the canonical string or key to `buf`, as `canon_text` without
+/// the intermediate string
+fn push_canon_text(buf: &mut String, opts: &LooseCanonOpts, s: &str) {
+    let s = match opts.unicode_norm {
+        UnicodeNorm::None => Cow::Borrowed(s),
+        UnicodeNorm::Nfc => nfc(s),
+    };
+    if opts.bare_rules.is_bare_safe(&s) {
+        buf.push_str(&s);
+    } else {
+        push_quoted(buf, &s);
+    }
+}
+
+/// Header column list, with `name:type` where a type is known
+fn tab_header(names: &[String], types: &[Option<GType>]) -> String {
+    names
+        .iter()
+        .zip(types)
+        .map(|(name, ty)| match ty {
+            Some(ty) => format!("{}:{}", name, ty),
+            None => name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The one type shared by every non-null cell, if any; ints mixed with floats
+/// count as floats
+fn infer_column_type<'a>(cells: impl Iterator<Item = Option<&'a GValue>>) -> Option<GType> {
+    let mut found = None;
+    for ty in cells.flatten().map(GValue::gtype).filter(|t| *t != GType::Null) {
+        match found {
+            None => found = Some(ty),
+            // Ints and floats together are floats; the parser widens the ints back
+            Some(GType::Int) if ty == GType::Float => found = Some(ty),
+            Some(GType::Float) if ty == GType::Int => {}
+            Some(prev) if prev != ty => return None,
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Whether a cell fits a declared column type (null always does)
+pub(crate) fn cell_matches(v: &GValue, ty: GType) -> bool {
+    let actual = v.gtype();
+    actual == ty || actual == GType::Null
+}
+
+/// Indexes of `columns` in the order a table writes them
+pub(crate) fn tab_column_order(opts: &LooseCanonOpts, columns: &[String]) -> Vec<usize> {
+    let mut order: Vec<(&String, usize)> = columns.iter().zip(0..).collect();
+    order_columns(opts, &mut order, |(c, _)| c);
+    pin_columns(opts, &mut order, |(c, _)| std::slice::from_ref(*c));
+    order.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Append the `@tab _ rows=N cols=K [...]` line of a table, `columns` in
+/// output order
+pub(crate) fn push_tab_header(buf: &mut String, opts: &LooseCanonOpts, columns: &[&str], types: &[Option<GType>], rows: usize) {
+    let names: Vec<String> = columns.iter().map(|c| canon_text(opts, c)).collect();
+    let _ = writeln!(buf, "@tab _ rows={} cols={} [{}]", rows, columns.len(), tab_header(&names, types));
+}
+
+/// Order tabular columns by `opts.column_order`, before pinning
+fn order_columns<T>(opts: &LooseCanonOpts, items: &mut [T], key_of: impl Fn(&T) -> &str) {
+    match opts.column_order {
+        ColumnOrder::Custom(cmp) => items.sort_by(|a, b| cmp(key_of(a), key_of(b))),
+        _ => order_keys(opts, items, key_of),
+    }
+}
+
+/// Move `ColumnOrder::Pinned` columns to the front, keeping the rest in order
+fn pin_columns<T>(opts: &LooseCanonOpts, items: &mut [T], path_of: impl Fn(&T) -> &[String]) {
+    if let ColumnOrder::Pinned(pinned) = &opts.column_order {
+        items.sort_by_cached_key(|item| {
+            let path = path_of(item);
+            let joined = path.join(".");
+            pinned
+                .iter()
+                .position(|p| *p == joined)
+                .or_else(|| pinned.iter().position(|p| *p == path[0]))
+                .unwrap_or(pinned.len())
+        });
+    }
+}
+
+/// Order keyed items according to `opts.key_order`.
+///
+/// Only the comparisons are allocation-free, and only under `KeyOrder::Sorted`
+/// without normalization; with NFC each key is normalized and quoted into a
+/// new string once. Writing still allocates around the sort, e.g. a table
+/// written as its row list is first cloned into one.
+fn order_keys<T>(opts: &LooseCanonOpts, items: &mut [T], key_of: impl Fn(&T) -> &str) {
+    match opts.key_order {
+        // Canonical forms compare as UTF-8 bytes, which is the spec's collation
+        KeyOrder::Sorted => match opts.unicode_norm {
+            UnicodeNorm::None => items.sort_by(|a, b| cmp_canon_text(&opts.bare_rules, key_of(a), key_of(b))),
+            // Normalizing may allocate, so do it once per key
+            UnicodeNorm::Nfc => items.sort_by_cached_key(|item| canon_text(opts, key_of(item))),
+        },
+        KeyOrder::Insertion => {}
+        KeyOrder::Custom(cmp) => items.sort_by(|a, b| cmp(key_of(a), key_of(b))),
+    }
 }
 
 fn get_object_keys(v: &GValue) -> Option<Vec<String>> {
     match v {
-        GValue::Map(entries) => Some(entries.iter().map(|e| e.key.clone()).collect()),
-        GValue::Struct(s) => Some(s.fields.iter().map(|f| f.key.clone()).collect()),
+        GValue::Map(entries) => Some(entries.iter().map(|e| e.key.to_string()).collect()),
+        GValue::Struct(s) => Some(s.fields.iter().map(|f| f.key.to_string()).collect()),
         _ => None,
     }
 }
 
-fn get_object_values(v: &GValue) -> std::collections::HashMap<String, &GValue> {
+/// Dot-path columns for `prefix`, given each row's value there.
+///
+/// A column is split only when every row holds a non-empty plain map whose
+/// keys are emitted bare and contain no `.`, so the header can be inverted.
+fn flatten_column(opts: &LooseCanonOpts, prefix: Vec<String>, cells: &[Option<&GValue>], depth: usize) -> Vec<Vec<String>> {
+    if depth == 0 {
+        return vec![prefix];
+    }
+    let mut keys: Vec<String> = Vec::new();
+    for cell in cells {
+        let entries = match cell {
+            Some(v @ GValue::Map(entries)) if !entries.is_empty() && opts.max_value_bytes_per_type.overflow(v).is_none() => entries,
+            _ => return vec![prefix],
+        };
+        for e in entries {
+            if e.key.contains('.') || canon_text(opts, &e.key) != e.key {
+                return vec![prefix];
+            }
+            if !keys.iter().any(|k| k.as_str() == e.key.as_str()) {
+                keys.push(e.key.to_string());
+            }
+        }
+    }
+    order_keys(opts, &mut keys, |k| k);
+    keys.into_iter()
+        .flat_map(|k| {
+            let sub: Vec<Option<&GValue>> = cells.iter().map(|c| c.and_then(|v| v.get(&k))).collect();
+            let mut path = prefix.clone();
+            path.push(k);
+            flatten_column(opts, path, &sub, depth - 1)
+        })
+        .collect()
+}
+
+fn get_object_values(v: &GValue) -> HashMap<String, &GValue> {
     match v {
-        GValue::Map(entries) => entries.iter().map(|e| (e.key.clone(), &e.value)).collect(),
-        GValue::Struct(s) => s.fields.iter().map(|f| (f.key.clone(), &f.value)).collect(),
-        _ => std::collections::HashMap::new(),
+        GValue::Map(entries) => entries.iter().map(|e| (e.key.to_string(), &e.value)).collect(),
+        GValue::Struct(s) => s.fields.iter().map(|f| (f.key.to_string(), &f.value)).collect(),
+        _ => HashMap::new(),
     }
 }
 
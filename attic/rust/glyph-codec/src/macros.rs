@@ -0,0 +1,120 @@
+//! `glyph!` value construction
+
+/// Build a `GValue` with literal syntax.
+///
+/// Maps are `{key = value, ...}` with identifier or string keys, lists are
+/// `[a, b]`, `null`/`true`/`false` are themselves, `@now` is the current
+/// time, and any other expression goes through `GValue::from`.
+///
+/// ```rust
+/// use glyph_rs::{canonicalize_loose, glyph};
+///
+/// let query = "weather";
+/// let v = glyph!({
+///     action = "search",
+///     query = query,
+///     tags = ["a", "b"],
+///     limit = 10,
+///     "content-type" = null,
+/// });
+/// assert_eq!(
+///     canonicalize_loose(&v).unwrap(),
+///     r#"{"content-type"=_ action=search limit=10 query=weather tags=[a b]}"#
+/// );
+/// ```
+#[macro_export]
+macro_rules! glyph {
+    (null) => {
+        $crate::GValue::Null
+    };
+    (true) => {
+        $crate::GValue::Bool(true)
+    };
+    (false) => {
+        $crate::GValue::Bool(false)
+    };
+    (@now) => {
+        $crate::GValue::now()
+    };
+    ([ $($tt:tt)* ]) => {
+        $crate::GValue::List($crate::glyph!(@list [] $($tt)*))
+    };
+    ({ $($tt:tt)* }) => {
+        $crate::GValue::Map($crate::glyph!(@map [] $($tt)*))
+    };
+
+    // List items, munched one at a time
+    (@list [$($out:expr,)*]) => {
+        vec![$($out,)*]
+    };
+    (@list [$($out:expr,)*] , $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)*] $($rest)*)
+    };
+    (@list [$($out:expr,)*] null $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!(null),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] true $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!(true),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] false $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!(false),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] @now $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!(@now),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] [ $($inner:tt)* ] $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!([$($inner)*]),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!({$($inner)*}),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] $next:expr , $($rest:tt)*) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!($next),] $($rest)*)
+    };
+    (@list [$($out:expr,)*] $last:expr) => {
+        $crate::glyph!(@list [$($out,)* $crate::glyph!($last),])
+    };
+
+    // Map entries, munched one at a time
+    (@map [$($out:expr,)*]) => {
+        vec![$($out,)*]
+    };
+    (@map [$($out:expr,)*] , $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)*] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = null $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!(null)),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = true $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!(true)),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = false $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!(false)),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = @now $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!(@now)),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = [ $($inner:tt)* ] $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!([$($inner)*])),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = { $($inner:tt)* } $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!({$($inner)*})),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = $value:expr , $($rest:tt)*) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!($value)),] $($rest)*)
+    };
+    (@map [$($out:expr,)*] $key:tt = $value:expr) => {
+        $crate::glyph!(@map [$($out,)* $crate::glyph!(@entry $key $crate::glyph!($value)),])
+    };
+
+    (@entry $key:ident $value:expr) => {
+        $crate::MapEntry::new(stringify!($key), $value)
+    };
+    (@entry $key:literal $value:expr) => {
+        $crate::MapEntry::new($key, $value)
+    };
+
+    ($other:expr) => {
+        $crate::GValue::from($other)
+    };
+}
@@ -0,0 +1,438 @@
+//! Deep merge
+//!
+//! `merge(&base, &overlay, opts)` lays one value over another: maps (and
+//! structs of the same type) combine key by key, recursively, and everything
+//! else is taken from the overlay. `MergeOpts` picks what happens to lists
+//! present in both and whether an overlay `null` deletes a key, so layered
+//! tool configs and partial updates can be folded together before
+//! canonicalization.
+//!
+//! `merge3(&ancestor, &ours, &theirs)` merges two concurrent edits of one
+//! value, reporting the paths both sides changed differently as conflicts.
+
+use crate::patch::same;
+use crate::path::GlyphPath;
+use crate::types::*;
+use std::borrow::Cow;
+use std::fmt;
+
+/// How `merge` combines two lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListMerge {
+    /// The overlay's list replaces the base's
+    #[default]
+    Replace,
+    /// The overlay's items follow the base's
+    Append,
+}
+
+/// Options for `merge`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeOpts {
+    /// Lists present in both values
+    pub lists: ListMerge,
+    /// An overlay `null` removes the key instead of setting it to null
+    pub null_deletes: bool,
+}
+
+impl MergeOpts {
+    /// JSON Merge Patch (RFC 7386): lists replace, `null` deletes
+    pub fn merge_patch() -> Self {
+        Self {
+            lists: ListMerge::Replace,
+            null_deletes: true,
+        }
+    }
+
+    /// Lists append, `null` is kept as a value
+    pub fn append() -> Self {
+        Self {
+            lists: ListMerge::Append,
+            null_deletes: false,
+        }
+    }
+}
+
+/// `overlay` laid over `base`.
+///
+/// Keys keep the base's order, with new overlay keys after them. A value that
+/// is a map on one side and anything else on the other is replaced whole.
+pub fn merge(base: &GValue, overlay: &GValue, opts: MergeOpts) -> GValue {
+    match (base, overlay) {
+        (GValue::Map(xs), GValue::Map(ys)) => GValue::Map(merge_entries(xs, ys, opts)),
+        (GValue::Struct(x), GValue::Struct(y)) if x.type_name == y.type_name => {
+            GValue::struct_val(x.type_name.clone(), merge_entries(&x.fields, &y.fields, opts))
+        }
+        // Tables append as their rows
+        (GValue::List(_) | GValue::Table(_), GValue::List(_) | GValue::Table(_)) if opts.lists == ListMerge::Append => {
+            GValue::List(items(base).iter().chain(items(overlay).iter()).cloned().collect())
+        }
+        // New maps can still carry nulls to strip
+        (_, GValue::Map(ys)) if opts.null_deletes => GValue::Map(merge_entries(&[], ys, opts)),
+        _ => overlay.clone(),
+    }
+}
+
+/// A list's items, or a table's row maps
+fn items(v: &GValue) -> Cow<'_, [GValue]> {
+    match v {
+        GValue::List(items) => Cow::Borrowed(items),
+        GValue::Table(t) => Cow::Owned((0..t.rows.len()).filter_map(|i| t.row(i)).collect()),
+        _ => Cow::Borrowed(&[]),
+    }
+}
+
+/// Fold `layers` left to right, later layers winning
+pub fn merge_all<'a>(layers: impl IntoIterator<Item = &'a GValue>, opts: MergeOpts) -> GValue {
+    let mut layers = layers.into_iter();
+    let Some(first) = layers.next() else {
+        return GValue::Null;
+    };
+    layers.fold(merge(&GValue::Null, first, opts), |acc, layer| merge(&acc, layer, opts))
+}
+
+fn merge_entries(base: &[MapEntry], overlay: &[MapEntry], opts: MergeOpts) -> Vec<MapEntry> {
+    let mut out = base.to_vec();
+    for e in overlay {
+        if opts.null_deletes && e.value.is_null() {
+            out.retain(|x| x.key != e.key);
+            continue;
+        }
+        match out.iter_mut().find(|x| x.key == e.key) {
+            Some(x) => x.value = merge(&x.value, &e.value, opts),
+            None => out.push(MapEntry::new(e.key.clone(), merge(&GValue::Null, &e.value, opts))),
+        }
+    }
+    out
+}
+
+// ============================================================
+// Three-way merge
+// ============================================================
+
+/// A path both sides changed differently. `None` means the side deleted it
+/// (or, for the ancestor, that both sides added it).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    pub path: GlyphPath,
+    pub ancestor: Option<GValue>,
+    pub ours: Option<GValue>,
+    pub theirs: Option<GValue>,
+}
+
+/// Result of `merge3`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreeWayMerge {
+    /// The merged value, holding our side at each conflict
+    pub value: GValue,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl ThreeWayMerge {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Merge `ours` and `theirs`, two edits of `ancestor`.
+///
+/// A change made on one side only is taken; identical changes on both sides
+/// are taken once. Where both sides changed the same node differently, maps
+/// and same-typed structs merge key by key, lists of unchanged length (tables
+/// by their rows) and same-tag sums merge item by item, and anything else is
+/// a conflict that keeps our side. Values compare with `equal_loose`.
+pub fn merge3(ancestor: &GValue, ours: &GValue, theirs: &GValue) -> ThreeWayMerge {
+    let mut conflicts = Vec::new();
+    let value = merge3_node(Some(ancestor), Some(ours), Some(theirs), &mut GlyphPath::root(), &mut conflicts);
+    ThreeWayMerge {
+        value: value.unwrap_or(GValue::Null),
+        conflicts,
+    }
+}
+
+fn same_opt(a: Option<&GValue>, b: Option<&GValue>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => same(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn merge3_node(
+    base: Option<&GValue>,
+    ours: Option<&GValue>,
+    theirs: Option<&GValue>,
+    path: &mut GlyphPath,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Option<GValue> {
+    if same_opt(ours, theirs) || same_opt(base, theirs) {
+        return ours.cloned();
+    }
+    if same_opt(base, ours) {
+        return theirs.cloned();
+    }
+    match (base, ours, theirs) {
+        (_, Some(GValue::Map(o)), Some(GValue::Map(t))) => {
+            let b = match base {
+                Some(GValue::Map(b)) => b.as_slice(),
+                _ => &[],
+            };
+            return Some(GValue::Map(merge3_entries(b, o, t, path, conflicts)));
+        }
+        (_, Some(GValue::Struct(o)), Some(GValue::Struct(t))) if o.type_name == t.type_name => {
+            let b = match base {
+                Some(GValue::Struct(b)) if b.type_name == o.type_name => b.fields.as_slice(),
+                _ => &[],
+            };
+            let fields = merge3_entries(b, &o.fields, &t.fields, path, conflicts);
+            return Some(GValue::struct_val(o.type_name.clone(), fields));
+        }
+        // Tables merge as their row lists
+        (
+            Some(b @ (GValue::List(_) | GValue::Table(_))),
+            Some(o @ (GValue::List(_) | GValue::Table(_))),
+            Some(t @ (GValue::List(_) | GValue::Table(_))),
+        ) => {
+            let (b, o, t) = (items(b), items(o), items(t));
+            if b.len() == o.len() && o.len() == t.len() {
+                let mut merged = Vec::with_capacity(o.len());
+                for i in 0..o.len() {
+                    path.push_index(i);
+                    merged.extend(merge3_node(Some(&b[i]), Some(&o[i]), Some(&t[i]), path, conflicts));
+                    path.pop();
+                }
+                return Some(GValue::List(merged));
+            }
+        }
+        (Some(GValue::Sum(b)), Some(GValue::Sum(o)), Some(GValue::Sum(t))) if b.tag == o.tag && o.tag == t.tag => {
+            path.push_key(o.tag.clone());
+            let inner = merge3_node(b.value.as_deref(), o.value.as_deref(), t.value.as_deref(), path, conflicts);
+            path.pop();
+            return Some(GValue::sum(o.tag.clone(), inner));
+        }
+        _ => {}
+    }
+    conflicts.push(MergeConflict {
+        path: path.clone(),
+        ancestor: base.cloned(),
+        ours: ours.cloned(),
+        theirs: theirs.cloned(),
+    });
+    ours.cloned()
+}
+
+fn merge3_entries(
+    base: &[MapEntry],
+    ours: &[MapEntry],
+    theirs: &[MapEntry],
+    path: &mut GlyphPath,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Vec<MapEntry> {
+    let lookup = |entries: &'_ [MapEntry], key: &str| -> Option<GValue> {
+        entries.iter().find(|e| e.key == key).map(|e| e.value.clone())
+    };
+    let mut keys: Vec<&str> = Vec::new();
+    for e in ours.iter().chain(theirs) {
+        if !keys.contains(&e.key.as_str()) {
+            keys.push(&e.key);
+        }
+    }
+    let mut out = Vec::with_capacity(keys.len());
+    for key in keys {
+        let (b, o, t) = (lookup(base, key), lookup(ours, key), lookup(theirs, key));
+        path.push_key(key);
+        if let Some(value) = merge3_node(b.as_ref(), o.as_ref(), t.as_ref(), path, conflicts) {
+            out.push(MapEntry::new(key, value));
+        }
+        path.pop();
+    }
+    out
+}
+
+fn show(v: &Option<GValue>) -> String {
+    match v {
+        Some(v) => crate::loose::canonicalize_loose(v).unwrap_or_else(|_| format!("{:?}", v)),
+        None => "(absent)".to_string(),
+    }
+}
+
+/// `path: ours <value>, theirs <value>`
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ours {}, theirs {}", self.path, show(&self.ours), show(&self.theirs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> GValue {
+        GValue::build_map()
+            .str("model", "small")
+            .value("tools", GValue::list(vec![GValue::str("search")]))
+            .value(
+                "limits",
+                GValue::build_map().int("tokens", 1000).int("calls", 5).finish(),
+            )
+            .finish()
+    }
+
+    #[test]
+    fn test_merge_maps_deeply() {
+        let overlay = GValue::build_map()
+            .value("limits", GValue::build_map().int("calls", 10).finish())
+            .value("tools", GValue::list(vec![GValue::str("fetch")]))
+            .str("user", "al")
+            .finish();
+
+        let replaced = merge(&defaults(), &overlay, MergeOpts::default());
+        assert_eq!(
+            replaced.to_string(),
+            "{limits={calls=10 tokens=1000} model=small tools=[fetch] user=al}"
+        );
+        // Base keys first, in base order
+        let keys: Vec<&str> = replaced.as_map().unwrap().iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["model", "tools", "limits", "user"]);
+
+        let appended = merge(&defaults(), &overlay, MergeOpts::append());
+        assert_eq!(appended.get("tools").unwrap().to_string(), "[search fetch]");
+    }
+
+    #[test]
+    fn test_null_semantics() {
+        let overlay = GValue::build_map()
+            .value("model", GValue::Null)
+            .value("limits", GValue::build_map().value("calls", GValue::Null).finish())
+            .value("extra", GValue::build_map().int("a", 1).value("b", GValue::Null).finish())
+            .finish();
+
+        let kept = merge(&defaults(), &overlay, MergeOpts::default());
+        assert_eq!(kept.get("model"), Some(&GValue::Null));
+        assert_eq!(kept.get_path("extra.b"), Some(&GValue::Null));
+
+        let patched = merge(&defaults(), &overlay, MergeOpts::merge_patch());
+        assert_eq!(
+            patched.to_string(),
+            "{extra={a=1} limits={tokens=1000} tools=[search]}"
+        );
+        assert_eq!(merge(&defaults(), &GValue::Null, MergeOpts::merge_patch()), GValue::Null);
+    }
+
+    #[test]
+    fn test_merge_kinds_and_layers() {
+        // Mismatched kinds and differently typed structs are replaced whole
+        let a = GValue::struct_val("Cfg", vec![field("x", GValue::int(1)), field("y", GValue::int(2))]);
+        let b = GValue::struct_val("Cfg", vec![field("y", GValue::int(3))]);
+        assert_eq!(merge(&a, &b, MergeOpts::default()).to_string(), "Cfg{x=1 y=3}");
+        let other = GValue::struct_val("Other", vec![field("y", GValue::int(3))]);
+        assert_eq!(merge(&a, &other, MergeOpts::default()), other);
+        assert_eq!(merge(&defaults(), &GValue::int(1), MergeOpts::default()), GValue::int(1));
+
+        let layers = [
+            defaults(),
+            GValue::build_map().str("model", "large").finish(),
+            GValue::build_map().value("limits", GValue::build_map().int("tokens", 8000).finish()).finish(),
+        ];
+        let cfg = merge_all(&layers, MergeOpts::default());
+        assert_eq!(cfg.get("model"), Some(&GValue::str("large")));
+        assert_eq!(cfg.get_path("limits.tokens"), Some(&GValue::int(8000)));
+        assert_eq!(cfg.get_path("limits.calls"), Some(&GValue::int(5)));
+        assert_eq!(merge_all([], MergeOpts::default()), GValue::Null);
+    }
+
+    #[test]
+    fn test_append_parsed_tables() {
+        let rows = |n: i64| {
+            let body: String = (0..n).map(|i| format!("|{}|\n", i)).collect();
+            let text = format!("{{rows=@tab _ rows={} cols=1 [id]\n{}@end}}", n, body);
+            crate::parse::parse_loose_with(&text, crate::parse::ParseOpts { tables: true }).unwrap()
+        };
+        let (a, b) = (rows(3), rows(4));
+        assert!(a.get("rows").unwrap().is_table());
+        let merged = merge(&a, &b, MergeOpts::append());
+        assert_eq!(merged.get("rows").unwrap().as_list().unwrap().len(), 7);
+        assert_eq!(merged.get_path("rows[3].id"), Some(&GValue::int(0)));
+
+        // A table and a plain list append too
+        let list = GValue::build_map().value("rows", GValue::list(vec![GValue::int(9)])).finish();
+        assert_eq!(merge(&a, &list, MergeOpts::append()).get_path("rows[3]"), Some(&GValue::int(9)));
+        assert_eq!(merge(&a, &b, MergeOpts::default()), b);
+    }
+
+    #[test]
+    fn test_merge3_parsed_tables() {
+        let doc = |a: &str, b: &str| {
+            let text = format!("{{rows=@tab _ rows=3 cols=2 [id s]\n|1|{}|\n|2|x|\n|3|{}|\n@end}}", a, b);
+            crate::parse::parse_loose_with(&text, crate::parse::ParseOpts { tables: true }).unwrap()
+        };
+        let (base, ours, theirs) = (doc("x", "x"), doc("ours", "x"), doc("x", "theirs"));
+        assert!(base.get("rows").unwrap().is_table());
+        let m = merge3(&base, &ours, &theirs);
+        assert!(m.is_clean(), "{:?}", m.conflicts);
+        assert_eq!(m.value.get_path("rows[0].s"), Some(&GValue::str("ours")));
+        assert_eq!(m.value.get_path("rows[2].s"), Some(&GValue::str("theirs")));
+
+        // Both sides editing one cell conflict at that cell
+        let m = merge3(&base, &ours, &doc("theirs", "x"));
+        let paths: Vec<String> = m.conflicts.iter().map(|c| c.path.to_string()).collect();
+        assert_eq!(paths, ["rows[0].s"]);
+    }
+
+    fn pad(notes: &[&str], status: &str, owner: &str) -> GValue {
+        GValue::build_map()
+            .value("notes", GValue::list(notes.iter().map(|n| GValue::str(*n)).collect()))
+            .value("task", GValue::build_map().str("status", status).str("owner", owner).finish())
+            .finish()
+    }
+
+    #[test]
+    fn test_merge3_clean() {
+        let base = pad(&["a", "b"], "open", "al");
+        // Ours closes the task, theirs reassigns it and edits a note
+        let ours = pad(&["a", "b"], "done", "al");
+        let mut theirs = pad(&["a", "B"], "open", "bo");
+        theirs.insert("seen", GValue::bool(true)).unwrap();
+
+        let m = merge3(&base, &ours, &theirs);
+        assert!(m.is_clean(), "{:?}", m.conflicts);
+        assert_eq!(m.value.to_string(), "{notes=[a B] seen=t task={owner=bo status=done}}");
+
+        // The same edit on both sides isn't a conflict
+        assert!(merge3(&base, &ours, &ours).is_clean());
+        assert_eq!(merge3(&base, &base, &base).value, base);
+    }
+
+    #[test]
+    fn test_merge3_conflicts() {
+        let base = pad(&["a"], "open", "al");
+        let ours = pad(&["a", "x"], "done", "al");
+        let mut theirs = pad(&["a", "y"], "wontfix", "al");
+        theirs.remove("notes").unwrap();
+
+        let m = merge3(&base, &ours, &theirs);
+        let conflicts: Vec<String> = m.conflicts.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            conflicts,
+            ["notes: ours [a x], theirs (absent)", "task.status: ours done, theirs wontfix"]
+        );
+        assert_eq!(m.conflicts[1].ancestor, Some(GValue::str("open")));
+        // Conflicts keep our side
+        assert_eq!(m.value, ours);
+
+        // Lists that changed length on both sides conflict whole
+        let theirs = pad(&["a", "y"], "open", "al");
+        let m = merge3(&base, &ours, &theirs);
+        assert_eq!(m.conflicts.len(), 1);
+        assert_eq!(m.conflicts[0].path.to_string(), "notes");
+
+        // Both sides adding the same key differently
+        let mut o = base.clone();
+        o.insert("tag", GValue::int(1)).unwrap();
+        let mut t = base.clone();
+        t.insert("tag", GValue::int(2)).unwrap();
+        let m = merge3(&base, &o, &t);
+        assert_eq!(m.conflicts[0].ancestor, None);
+        assert_eq!(m.conflicts[0].to_string(), "tag: ours 1, theirs 2");
+    }
+}
@@ -0,0 +1,432 @@
+//! Merkle subtree hashing
+//!
+//! `merkle_hash` gives every node of a value its own SHA-256 digest, with
+//! parents hashing their children's digests. Two large values can then be
+//! compared subtree by subtree (`MerkleNode::diff`), and a `MerkleProof`
+//! shows that one field has a given value under a known root digest without
+//! the rest of the tree. `MerkleValue` keeps a value with its tree and
+//! re-hashes only the path to an edited field.
+//!
+//! Digests follow loose equality: values that are `equal_loose` outside
+//! tabular blocks hash alike (`1` and `1.0`, maps in any key order). Tables
+//! hash as their lists of row maps.
+
+use crate::error::GlyphError;
+use crate::incremental::{replace_at, PathEdit};
+use crate::loose::{canonicalize_loose_no_tabular, Fingerprint, HashAlgo};
+use crate::path::{GlyphPath, PathSegment};
+use crate::types::*;
+use sha2::{Digest as _, Sha256};
+
+/// A SHA-256 digest
+pub type NodeDigest = [u8; 32];
+
+const LEAF: u8 = 0;
+const MAP: u8 = 1;
+const LIST: u8 = 2;
+const SUM: u8 = 3;
+
+/// Digest of one node, with the digests of its children
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleNode {
+    pub digest: NodeDigest,
+    /// Map entries in key order, list items in list order, or a sum's value
+    pub children: Vec<(PathSegment, MerkleNode)>,
+    kind: u8,
+    label: String,
+}
+
+/// Hash `v` and every subtree in it.
+///
+/// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
+pub fn merkle_hash(v: &GValue) -> Result<MerkleNode, GlyphError> {
+    let (kind, label, children) = match v {
+        GValue::Map(entries) => (MAP, String::new(), hash_entries(entries)?),
+        GValue::Struct(s) => (MAP, s.type_name.clone(), hash_entries(&s.fields)?),
+        GValue::List(items) => (LIST, String::new(), hash_items(items)?),
+        GValue::Table(t) => return merkle_hash(&t.to_list()),
+        GValue::Sum(s) => {
+            let children = match &s.value {
+                Some(inner) => vec![(PathSegment::Key(s.tag.clone()), merkle_hash(inner)?)],
+                None => Vec::new(),
+            };
+            (SUM, s.tag.clone(), children)
+        }
+        scalar => {
+            let text = canonicalize_loose_no_tabular(scalar)?;
+            let mut h = Sha256::new();
+            h.update([LEAF]);
+            h.update(text.as_bytes());
+            return Ok(MerkleNode {
+                digest: h.finalize().into(),
+                children: Vec::new(),
+                kind: LEAF,
+                label: String::new(),
+            });
+        }
+    };
+    let digest = node_digest(kind, &label, children.iter().map(|(seg, c)| (seg, &c.digest)));
+    Ok(MerkleNode {
+        digest,
+        children,
+        kind,
+        label,
+    })
+}
+
+fn hash_entries(entries: &[MapEntry]) -> Result<Vec<(PathSegment, MerkleNode)>, GlyphError> {
+    // Stable, so duplicate keys keep their order as in the canonical form
+    let mut sorted: Vec<&MapEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.key.cmp(&b.key));
+    sorted
+        .into_iter()
+        .map(|e| Ok((PathSegment::Key(e.key.to_string()), merkle_hash(&e.value)?)))
+        .collect()
+}
+
+fn hash_items(items: &[GValue]) -> Result<Vec<(PathSegment, MerkleNode)>, GlyphError> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| Ok((PathSegment::Index(i), merkle_hash(item)?)))
+        .collect()
+}
+
+/// Parent digest over its kind, label and length-prefixed children
+fn node_digest<'a>(kind: u8, label: &str, children: impl ExactSizeIterator<Item = (&'a PathSegment, &'a NodeDigest)>) -> NodeDigest {
+    let mut h = Sha256::new();
+    h.update([kind]);
+    h.update((label.len() as u64).to_be_bytes());
+    h.update(label.as_bytes());
+    h.update((children.len() as u64).to_be_bytes());
+    for (seg, digest) in children {
+        let key = match seg {
+            PathSegment::Key(k) if kind != LIST => k.as_str(),
+            _ => "",
+        };
+        h.update((key.len() as u64).to_be_bytes());
+        h.update(key.as_bytes());
+        h.update(digest);
+    }
+    h.finalize().into()
+}
+
+impl MerkleNode {
+    /// Lowercase hex digest
+    pub fn hex(&self) -> String {
+        self.digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// The digest as a `Fingerprint`
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint {
+            algo: HashAlgo::Sha256,
+            digest: self.digest.to_vec(),
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.kind == LEAF
+    }
+
+    fn child(&self, seg: &PathSegment) -> Option<&MerkleNode> {
+        self.children.iter().find(|(s, _)| s == seg).map(|(_, c)| c)
+    }
+
+    /// Node at `path`. With duplicate keys, the first in key order.
+    pub fn get(&self, path: &GlyphPath) -> Option<&MerkleNode> {
+        path.segments().iter().try_fold(self, |node, seg| node.child(seg))
+    }
+
+    /// Paths of the outermost subtrees that differ between `self` and `other`.
+    ///
+    /// Equal digests prune the walk, so unchanged parts cost nothing. A node
+    /// whose kind or set of children changed is reported as a whole; the
+    /// root path means the values differ at the top.
+    pub fn diff(&self, other: &MerkleNode) -> Vec<GlyphPath> {
+        let mut out = Vec::new();
+        self.diff_into(other, &mut GlyphPath::root(), &mut out);
+        out
+    }
+
+    fn diff_into(&self, other: &MerkleNode, path: &mut GlyphPath, out: &mut Vec<GlyphPath>) {
+        if self.digest == other.digest {
+            return;
+        }
+        let same_shape = self.kind == other.kind
+            && self.kind != LEAF
+            && self.label == other.label
+            && self.children.len() == other.children.len()
+            && self.children.iter().zip(&other.children).all(|((a, _), (b, _))| a == b);
+        if !same_shape {
+            out.push(path.clone());
+            return;
+        }
+        for ((seg, a), (_, b)) in self.children.iter().zip(&other.children) {
+            match seg {
+                PathSegment::Key(k) => path.push_key(k.clone()),
+                PathSegment::Index(i) => path.push_index(*i),
+            }
+            a.diff_into(b, path, out);
+            path.pop();
+        }
+    }
+
+    /// Proof that the node at `path` is part of this tree
+    pub fn proof(&self, path: &GlyphPath) -> Option<MerkleProof> {
+        let mut steps = Vec::with_capacity(path.len());
+        let mut node = self;
+        for seg in path.segments() {
+            let position = node.children.iter().position(|(s, _)| s == seg)?;
+            steps.push(ProofStep {
+                kind: node.kind,
+                label: node.label.clone(),
+                children: node.children.iter().map(|(s, c)| (s.clone(), c.digest)).collect(),
+                position,
+            });
+            node = &node.children[position].1;
+        }
+        Some(MerkleProof {
+            path: path.clone(),
+            leaf: node.digest,
+            steps,
+        })
+    }
+}
+
+impl MerkleNode {
+    /// Put `node` at `segs`, adding a new map key if needed, and re-hash
+    /// each parent on the way back up
+    fn splice(&mut self, segs: &[PathSegment], node: MerkleNode) {
+        let Some((seg, rest)) = segs.split_first() else {
+            *self = node;
+            return;
+        };
+        // The first match in key order is the first in source order, as edits pick
+        match self.children.iter().position(|(s, _)| s == seg) {
+            Some(i) => self.children[i].1.splice(rest, node),
+            None => {
+                let at = match seg {
+                    PathSegment::Key(key) => self
+                        .children
+                        .partition_point(|(s, _)| matches!(s, PathSegment::Key(k) if k <= key)),
+                    PathSegment::Index(_) => self.children.len(),
+                };
+                self.children.insert(at, (seg.clone(), node));
+            }
+        }
+        self.digest = node_digest(self.kind, &self.label, self.children.iter().map(|(s, c)| (s, &c.digest)));
+    }
+}
+
+// ============================================================
+// Tracked values
+// ============================================================
+
+/// A value with its Merkle tree kept current across edits.
+///
+/// `apply` hashes only the new subtree and the digests of its ancestors, so
+/// one changed field in a large value costs a walk down its path rather than
+/// a full re-hash.
+#[derive(Debug, Clone)]
+pub struct MerkleValue {
+    value: GValue,
+    tree: MerkleNode,
+}
+
+impl MerkleValue {
+    /// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
+    pub fn new(value: GValue) -> Result<Self, GlyphError> {
+        let tree = merkle_hash(&value)?;
+        Ok(Self { value, tree })
+    }
+
+    pub fn value(&self) -> &GValue {
+        &self.value
+    }
+
+    pub fn tree(&self) -> &MerkleNode {
+        &self.tree
+    }
+
+    /// Root digest
+    pub fn digest(&self) -> &NodeDigest {
+        &self.tree.digest
+    }
+
+    pub fn into_inner(self) -> GValue {
+        self.value
+    }
+
+    /// Replace or add the value at `edit.path`, as `CanonCache::apply` does.
+    ///
+    /// A failed edit (missing parent, NaN or Inf) changes nothing.
+    pub fn apply(&mut self, edit: &PathEdit) -> Result<(), GlyphError> {
+        let node = merkle_hash(&edit.value)?;
+        replace_at(&mut self.value, &edit.path, edit.value.clone())?;
+        self.tree.splice(edit.path.segments(), node);
+        Ok(())
+    }
+
+    /// Swap in a new value, returning the old one
+    pub fn replace(&mut self, value: GValue) -> Result<GValue, GlyphError> {
+        self.tree = merkle_hash(&value)?;
+        Ok(std::mem::replace(&mut self.value, value))
+    }
+}
+
+/// One parent on the way from a proven node to the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProofStep {
+    kind: u8,
+    label: String,
+    children: Vec<(PathSegment, NodeDigest)>,
+    position: usize,
+}
+
+/// Evidence that the node at `path` has digest `leaf` in a tree with a given root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub path: GlyphPath,
+    pub leaf: NodeDigest,
+    steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /// Root digest implied by the proof
+    pub fn root(&self) -> NodeDigest {
+        self.steps.iter().rev().fold(self.leaf, |digest, step| {
+            let children = step
+                .children
+                .iter()
+                .enumerate()
+                .map(|(i, (seg, d))| (seg, if i == step.position { &digest } else { d }));
+            node_digest(step.kind, &step.label, children)
+        })
+    }
+
+    /// Check that `value` sits at `self.path` in the tree with digest `root`
+    pub fn verify(&self, value: &GValue, root: &NodeDigest) -> Result<bool, GlyphError> {
+        Ok(merkle_hash(value)?.digest == self.leaf && self.root() == *root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(status: &str, rows: i64) -> GValue {
+        GValue::build_map()
+            .str("status", status)
+            .value(
+                "items",
+                GValue::list((0..rows).map(|i| GValue::build_map().int("id", i).finish()).collect()),
+            )
+            .value("meta", GValue::build_map().int("took_ms", 12).finish())
+            .finish()
+    }
+
+    #[test]
+    fn test_digests_follow_loose_equality() {
+        let a = GValue::build_map().int("a", 1).str("b", "x").finish();
+        let b = GValue::build_map().str("b", "x").float("a", 1.0).finish();
+        assert_eq!(merkle_hash(&a).unwrap().digest, merkle_hash(&b).unwrap().digest);
+        assert_ne!(
+            merkle_hash(&GValue::list(vec![a.clone()])).unwrap().digest,
+            merkle_hash(&a).unwrap().digest
+        );
+
+        let t = GValue::table(["id"], vec![vec![GValue::int(1)]]);
+        let rows = GValue::list(vec![GValue::build_map().int("id", 1).finish()]);
+        assert_eq!(merkle_hash(&t).unwrap(), merkle_hash(&rows).unwrap());
+        assert!(merkle_hash(&GValue::float(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_get_and_diff() {
+        let before = merkle_hash(&result("ok", 3)).unwrap();
+        let mut changed = result("ok", 3);
+        if let GValue::Map(entries) = &mut changed {
+            entries[2].value = GValue::build_map().int("took_ms", 40).finish();
+        }
+        let after = merkle_hash(&changed).unwrap();
+
+        let items = GlyphPath::parse("items").unwrap();
+        assert_eq!(before.get(&items), after.get(&items));
+        assert!(before.get(&GlyphPath::parse("items[1].id").unwrap()).unwrap().is_leaf());
+        assert!(before.get(&GlyphPath::parse("nope").unwrap()).is_none());
+
+        let paths: Vec<String> = before.diff(&after).iter().map(|p| p.to_string()).collect();
+        assert_eq!(paths, ["meta.took_ms"]);
+
+        // A changed child set is reported at its parent
+        let longer = merkle_hash(&result("ok", 4)).unwrap();
+        let paths: Vec<String> = before.diff(&longer).iter().map(|p| p.to_string()).collect();
+        assert_eq!(paths, ["items"]);
+        assert!(before.diff(&before).is_empty());
+    }
+
+    #[test]
+    fn test_merkle_value_edits() {
+        let path = |s: &str| GlyphPath::parse(s).unwrap();
+        let mut mv = MerkleValue::new(result("ok", 20)).unwrap();
+        let before = mv.tree().clone();
+
+        mv.apply(&PathEdit::new(path("items[7].id"), GValue::int(70))).unwrap();
+        assert_eq!(mv.tree(), &merkle_hash(mv.value()).unwrap());
+        let paths: Vec<String> = before.diff(mv.tree()).iter().map(|p| p.to_string()).collect();
+        assert_eq!(paths, ["items[7].id"]);
+
+        // New keys land in key order
+        mv.apply(&PathEdit::new(path("meta.cached"), GValue::bool(true))).unwrap();
+        mv.apply(&PathEdit::new(path("error"), GValue::null())).unwrap();
+        assert_eq!(mv.tree(), &merkle_hash(mv.value()).unwrap());
+
+        mv.apply(&PathEdit::new(path("items"), GValue::table(["id"], vec![vec![GValue::int(1)]])))
+            .unwrap();
+        assert_eq!(mv.tree(), &merkle_hash(mv.value()).unwrap());
+        // Table rows are edited like list items
+        mv.apply(&PathEdit::new(path("items[0].id"), GValue::int(2))).unwrap();
+        assert_eq!(mv.tree(), &merkle_hash(mv.value()).unwrap());
+
+        // Failed edits change nothing
+        let digest = *mv.digest();
+        assert!(mv.apply(&PathEdit::new(path("status"), GValue::float(f64::NAN))).is_err());
+        assert!(mv.apply(&PathEdit::new(path("nope.deeper"), GValue::int(1))).is_err());
+        assert!(mv.apply(&PathEdit::new(path("items[5]"), GValue::int(1))).is_err());
+        assert_eq!(*mv.digest(), digest);
+        assert_eq!(mv.tree(), &merkle_hash(mv.value()).unwrap());
+
+        // Duplicate keys: the first occurrence is edited and re-hashed
+        let mut dup = MerkleValue::new(GValue::map(vec![
+            MapEntry::new("b", GValue::int(1)),
+            MapEntry::new("a", GValue::int(2)),
+            MapEntry::new("b", GValue::int(3)),
+        ]))
+        .unwrap();
+        dup.apply(&PathEdit::new(path("b"), GValue::int(9))).unwrap();
+        assert_eq!(dup.tree(), &merkle_hash(dup.value()).unwrap());
+
+        let old = mv.replace(result("ok", 3)).unwrap();
+        assert_eq!(old.as_map().map(|m| m.len()), Some(4));
+        assert_eq!(*mv.digest(), merkle_hash(&result("ok", 3)).unwrap().digest);
+    }
+
+    #[test]
+    fn test_proofs() {
+        let tree = merkle_hash(&result("ok", 50)).unwrap();
+        let path = GlyphPath::parse("status").unwrap();
+        let proof = tree.proof(&path).unwrap();
+        assert_eq!(proof.root(), tree.digest);
+        assert!(proof.verify(&GValue::str("ok"), &tree.digest).unwrap());
+        assert!(!proof.verify(&GValue::str("error"), &tree.digest).unwrap());
+
+        // Same field, different tree
+        let other = merkle_hash(&result("ok", 49)).unwrap();
+        assert!(!proof.verify(&GValue::str("ok"), &other.digest).unwrap());
+
+        let deep = tree.proof(&GlyphPath::parse("items[7]").unwrap()).unwrap();
+        assert!(deep.verify(&GValue::build_map().int("id", 7).finish(), &tree.digest).unwrap());
+        assert!(tree.proof(&GlyphPath::parse("items[50]").unwrap()).is_none());
+    }
+}
@@ -0,0 +1,157 @@
+//! Parsing files through a memory map (feature `mmap`)
+//!
+//! `parse_file_mmap` maps a file and parses the mapping in place, so a
+//! large document is never copied into a `String` first; the OS pages it in
+//! as the parser reaches it and can drop those pages again under pressure.
+//!
+//! A log of many values written one after another, as `write_value_async`
+//! leaves them, can be queried without parsing it whole: `MappedFile::values`
+//! finds each top-level value with the `StreamDecoder`'s scan and parses only
+//! that one, so memory holds a single value at a time.
+
+use crate::decode::Scan;
+use crate::error::GlyphError;
+use crate::parse::parse_loose;
+use crate::types::GValue;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// Parse the file at `path` as one document, reading it through a memory
+/// map; see `MappedFile::open` for the caveat on files changed meanwhile
+pub fn parse_file_mmap(path: impl AsRef<Path>) -> Result<GValue, GlyphError> {
+    MappedFile::open(path)?.parse()
+}
+
+/// A file mapped into memory for parsing; see the module docs
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// Map the file at `path`.
+    ///
+    /// The mapping reads the file as it is on disk, so the file must not be
+    /// truncated or rewritten while it is open: a truncation shows up as a
+    /// bus error rather than a `GlyphError`. Appending is fine, though the
+    /// appended bytes are not seen.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, GlyphError> {
+        let file = File::open(path)?;
+        // Safety: the caller keeps the file unchanged while it is mapped, as
+        // documented above
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self { map })
+    }
+
+    /// Size of the file in bytes
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// The whole file as text
+    pub fn as_str(&self) -> Result<&str, GlyphError> {
+        utf8(&self.map)
+    }
+
+    /// Parse the whole file as one document
+    pub fn parse(&self) -> Result<GValue, GlyphError> {
+        parse_loose(self.as_str()?)
+    }
+
+    /// The top-level values of the file, parsed one at a time.
+    ///
+    /// A value that fails to parse is yielded as an error and iteration
+    /// carries on; a file that ends inside a value ends with an error.
+    pub fn values(&self) -> MappedValues<'_> {
+        MappedValues {
+            data: &self.map,
+            scan: Scan::default(),
+            done: false,
+        }
+    }
+}
+
+/// Iterator over the values of a `MappedFile`
+pub struct MappedValues<'a> {
+    data: &'a [u8],
+    scan: Scan,
+    done: bool,
+}
+
+impl Iterator for MappedValues<'_> {
+    type Item = Result<GValue, GlyphError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (start, end) = match self.scan.next_span(self.data, None, true) {
+            Ok(Some(span)) => span,
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) => {
+                self.done = true;
+                return Some(Err(e));
+            }
+        };
+        self.scan = Scan::at(end);
+        Some(utf8(&self.data[start..end]).and_then(parse_loose))
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str, GlyphError> {
+    std::str::from_utf8(bytes).map_err(|e| GlyphError::Parse(format!("invalid UTF-8 at byte {}", e.valid_up_to())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::canonicalize_loose;
+    use crate::types::*;
+
+    fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("glyph-mmap-{}-{}", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_file_mmap() {
+        let rows = GValue::list((0..50).map(|i| GValue::build_map().int("id", i).str("msg", "hello world").finish()).collect());
+        let doc = GValue::build_map().str("source", "agent").value("events", rows).finish();
+        let text = canonicalize_loose(&doc).unwrap();
+        let path = temp_file("doc", &text);
+        assert_eq!(parse_file_mmap(&path).unwrap(), parse_loose(&text).unwrap());
+
+        std::fs::write(&path, "").unwrap();
+        let empty = MappedFile::open(&path).unwrap();
+        assert!(empty.is_empty());
+        assert!(empty.parse().is_err());
+        assert_eq!(empty.values().count(), 0);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(parse_file_mmap(&path), Err(GlyphError::Io(_))));
+    }
+
+    #[test]
+    fn test_mapped_values() {
+        let path = temp_file("log", "{event=start n=1}\n{event=\"tool call\" n=2}\n{bad=}\n{event=stop n=3}\n[1 2");
+        let file = MappedFile::open(&path).unwrap();
+        let results: Vec<_> = file.values().collect();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[1].as_ref().unwrap().get("event"), Some(&GValue::str("tool call")));
+        assert!(results[2].is_err());
+        assert_eq!(results[3].as_ref().unwrap().get("n"), Some(&GValue::int(3)));
+        assert!(results[4].as_ref().unwrap_err().to_string().contains("ended inside a value"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,351 @@
+//! Equality, ordering and hashing for GValue
+//!
+//! Equality is exact and structural: `1` and `1.0` differ, as do two key
+//! orders of one map. Floats compare by value, so `-0.0 == 0.0`; a NaN equals
+//! a NaN with the same bits, which keeps `Eq` reflexive.
+//!
+//! The total order agrees with that equality. Kinds rank as
+//! null < bool < numbers < str < bytes < time < id < list < map < struct < sum < table.
+//! Ints and floats order together by numeric value, an int first when they
+//! are equal, with NaNs after every number (by bit pattern among themselves).
+//! Maps order by their entries sorted by key, as in canonical form, then by
+//! source order; lists, structs and sums order element by element.
+//!
+//! Hashing follows `equal_loose`: whole floats hash as the int they print as,
+//! maps hash in key order, tables hash as their lists of rows, and times hash
+//! to the second. Values that are `equal_loose` therefore hash alike, which is
+//! also enough for the stricter `Eq`.
+
+use crate::loose::{cmp_canon_text, BareRules, MAX_SAFE_INT};
+use crate::types::*;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+// ============================================================
+// Equality
+// ============================================================
+
+fn float_eq(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && a.to_bits() == b.to_bits())
+}
+
+impl PartialEq for GValue {
+    fn eq(&self, other: &Self) -> bool {
+        use GValue::*;
+        match (self, other) {
+            (Null, Null) => true,
+            (Bool(a), Bool(b)) => a == b,
+            (Int(a), Int(b)) => a == b,
+            (Float(a), Float(b)) => float_eq(*a, *b),
+            (Str(a), Str(b)) => a == b,
+            (Bytes(a), Bytes(b)) => a == b,
+            (Time(a), Time(b)) => a == b,
+            (Id(a), Id(b)) => a == b,
+            (List(a), List(b)) => a == b,
+            (Map(a), Map(b)) => a == b,
+            (Struct(a), Struct(b)) => a == b,
+            (Sum(a), Sum(b)) => a == b,
+            (Table(a), Table(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for GValue {}
+
+// ============================================================
+// Ordering
+// ============================================================
+
+fn rank(v: &GValue) -> u8 {
+    match v {
+        GValue::Null => 0,
+        GValue::Bool(_) => 1,
+        GValue::Int(_) | GValue::Float(_) => 2,
+        GValue::Str(_) => 3,
+        GValue::Bytes(_) => 4,
+        GValue::Time(_) => 5,
+        GValue::Id(_) => 6,
+        GValue::List(_) => 7,
+        GValue::Map(_) => 8,
+        GValue::Struct(_) => 9,
+        GValue::Sum(_) => 10,
+        GValue::Table(_) => 11,
+    }
+}
+
+fn cmp_floats(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(&b).expect("non-NaN floats are ordered"),
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (true, true) => a.to_bits().cmp(&b.to_bits()),
+    }
+}
+
+/// Exact numeric comparison; an int sorts before an equal float
+fn cmp_int_float(n: i64, f: f64) -> Ordering {
+    // 2^63: every float at or beyond it exceeds any i64
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if f.is_nan() || f >= LIMIT {
+        return Ordering::Less;
+    }
+    if f < -LIMIT {
+        return Ordering::Greater;
+    }
+    // `f` is within i64 range, so its integer part converts exactly
+    n.cmp(&(f.trunc() as i64)).then(if f.fract() < 0.0 { Ordering::Greater } else { Ordering::Less })
+}
+
+/// Keys as the default canonical form orders them
+fn cmp_keys(a: &str, b: &str) -> Ordering {
+    cmp_canon_text(&BareRules::default(), a, b)
+}
+
+fn sorted_entries(entries: &[MapEntry]) -> Vec<&MapEntry> {
+    // Stable, so duplicate keys keep their order as in the canonical form
+    let mut sorted: Vec<&MapEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| cmp_keys(&a.key, &b.key));
+    sorted
+}
+
+fn cmp_entries(a: &[MapEntry], b: &[MapEntry]) -> Ordering {
+    let by_key = |a: &[&MapEntry], b: &[&MapEntry]| {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| cmp_keys(&x.key, &y.key).then_with(|| x.value.cmp(&y.value)))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    };
+    by_key(&sorted_entries(a), &sorted_entries(b))
+        .then_with(|| by_key(&a.iter().collect::<Vec<_>>(), &b.iter().collect::<Vec<_>>()))
+}
+
+impl Ord for GValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use GValue::*;
+        match (self, other) {
+            (Null, Null) => Ordering::Equal,
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Int(a), Int(b)) => a.cmp(b),
+            (Float(a), Float(b)) => cmp_floats(*a, *b),
+            (Int(n), Float(f)) => cmp_int_float(*n, *f),
+            (Float(f), Int(n)) => cmp_int_float(*n, *f).reverse(),
+            (Str(a), Str(b)) => a.cmp(b),
+            (Bytes(a), Bytes(b)) => a.cmp(b),
+            (Time(a), Time(b)) => a.cmp(b),
+            (Id(a), Id(b)) => a.cmp(b),
+            (List(a), List(b)) => a.cmp(b),
+            (Map(a), Map(b)) => cmp_entries(a, b),
+            (Struct(a), Struct(b)) => a.type_name.cmp(&b.type_name).then_with(|| cmp_entries(&a.fields, &b.fields)),
+            (Sum(a), Sum(b)) => a.tag.cmp(&b.tag).then_with(|| a.value.cmp(&b.value)),
+            (Table(a), Table(b)) => a
+                .columns
+                .cmp(&b.columns)
+                .then_with(|| a.rows.cmp(&b.rows))
+                .then_with(|| a.types.cmp(&b.types)),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for GValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// ============================================================
+// Hashing
+// ============================================================
+
+impl Hash for GValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            GValue::Null => state.write_u8(0),
+            GValue::Bool(b) => {
+                state.write_u8(1);
+                b.hash(state);
+            }
+            GValue::Int(n) => {
+                state.write_u8(2);
+                n.hash(state);
+            }
+            // Whole floats in the safe window print as ints
+            GValue::Float(f) if f.fract() == 0.0 && f.abs() <= MAX_SAFE_INT => {
+                state.write_u8(2);
+                (*f as i64).hash(state);
+            }
+            GValue::Float(f) => {
+                state.write_u8(3);
+                f.to_bits().hash(state);
+            }
+            GValue::Str(s) => {
+                state.write_u8(4);
+                s.hash(state);
+            }
+            GValue::Bytes(b) => {
+                state.write_u8(5);
+                b.hash(state);
+            }
+            GValue::Time(t) => {
+                state.write_u8(6);
+                t.timestamp().hash(state);
+            }
+            GValue::Id(r) => {
+                state.write_u8(7);
+                r.hash(state);
+            }
+            GValue::List(items) => {
+                state.write_u8(8);
+                state.write_usize(items.len());
+                items.iter().for_each(|item| item.hash(state));
+            }
+            GValue::Map(entries) => hash_row(sorted_entries(entries).into_iter().map(|e| (e.key.as_str(), &e.value)), state),
+            // Rows in `@tab` blocks drop type names, so structs hash as maps
+            GValue::Struct(s) => hash_row(sorted_entries(&s.fields).into_iter().map(|e| (e.key.as_str(), &e.value)), state),
+            GValue::Sum(s) => {
+                state.write_u8(10);
+                s.tag.hash(state);
+                s.value.hash(state);
+            }
+            GValue::Table(t) => {
+                let mut order: Vec<usize> = (0..t.columns.len()).collect();
+                order.sort_by(|&a, &b| t.columns[a].cmp(&t.columns[b]));
+                state.write_u8(8);
+                state.write_usize(t.rows.len());
+                for row in &t.rows {
+                    hash_row(order.iter().map(|&i| (t.columns[i].as_str(), &row[i])), state);
+                }
+            }
+        }
+    }
+}
+
+/// Hash map entries given in key order. Null entries are skipped: a missing
+/// key and a null cell print alike in `@tab` blocks.
+fn hash_row<'a, H: Hasher>(entries: impl Iterator<Item = (&'a str, &'a GValue)>, state: &mut H) {
+    state.write_u8(9);
+    for (key, value) in entries.filter(|(_, v)| !v.is_null()) {
+        key.hash(state);
+        value.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::equal_loose;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeSet, HashSet};
+
+    fn hash_of(v: &GValue) -> u64 {
+        let mut h = DefaultHasher::new();
+        v.hash(&mut h);
+        h.finish()
+    }
+
+    fn samples() -> Vec<GValue> {
+        let map = |pairs: &[(&str, GValue)]| GValue::map(pairs.iter().map(|(k, v)| field(*k, v.clone())).collect());
+        vec![
+            GValue::Null,
+            GValue::bool(false),
+            GValue::bool(true),
+            GValue::int(-3),
+            GValue::float(-2.5),
+            GValue::int(0),
+            GValue::float(0.0),
+            GValue::float(-0.0),
+            GValue::int(1),
+            GValue::float(1.0),
+            GValue::float(1.5),
+            GValue::int(i64::MAX),
+            GValue::float(1e300),
+            GValue::float(f64::INFINITY),
+            GValue::float(f64::NAN),
+            GValue::str(""),
+            GValue::str("a"),
+            GValue::bytes(vec![1]),
+            GValue::simple_id("x"),
+            GValue::list(vec![GValue::int(1)]),
+            GValue::list(vec![GValue::float(1.0)]),
+            map(&[("a", GValue::int(1)), ("b", GValue::int(2))]),
+            map(&[("b", GValue::int(2)), ("a", GValue::int(1))]),
+            map(&[("a", GValue::int(1)), ("b", GValue::Null)]),
+            map(&[("a", GValue::int(2))]),
+            GValue::struct_val("T", vec![field("a", GValue::int(1))]),
+            GValue::sum("Ok", None),
+            GValue::sum("Ok", Some(GValue::int(1))),
+            GValue::table(["a"], vec![vec![GValue::int(1)]]),
+        ]
+    }
+
+    #[test]
+    fn test_order_is_total_and_matches_eq() {
+        let values = samples();
+        for a in &values {
+            assert_eq!(a.cmp(a), Ordering::Equal, "{:?}", a);
+            for b in &values {
+                assert_eq!(a.cmp(b) == Ordering::Equal, a == b, "{:?} vs {:?}", a, b);
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "{:?} vs {:?}", a, b);
+                for c in &values {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{:?} <= {:?} <= {:?}", a, b, c);
+                    }
+                }
+            }
+        }
+
+        assert!(GValue::int(1) < GValue::float(1.0));
+        assert!(GValue::float(1.0) < GValue::float(1.5));
+        assert!(GValue::float(0.5) < GValue::int(1));
+        assert!(GValue::float(-0.5) > GValue::int(-1));
+        assert!(GValue::int(i64::MAX) < GValue::float(9.3e18));
+        assert!(GValue::float(f64::INFINITY) < GValue::float(f64::NAN));
+        assert_eq!(GValue::float(0.0), GValue::float(-0.0));
+        assert_eq!(GValue::float(f64::NAN), GValue::float(f64::NAN));
+
+        // Maps compare key by key in canonical order, where quoted keys sort first
+        let map = |a: i64, ab: i64| GValue::build_map().int("a", a).int("a b", ab).finish();
+        assert_eq!(map(0, 1).to_string(), r#"{"a b"=1 a=0}"#);
+        assert!(map(0, 1) > map(1, 0));
+    }
+
+    #[test]
+    fn test_hash_follows_loose_equality() {
+        let values = samples();
+        for a in &values {
+            for b in &values {
+                if a == b || equal_loose(a, b).unwrap_or(false) {
+                    assert_eq!(hash_of(a), hash_of(b), "{:?} vs {:?}", a, b);
+                }
+            }
+        }
+
+        // In an `@tab` block a missing key and a null cell print alike
+        let rows = |null_at: i64| {
+            GValue::list(
+                (0..3)
+                    .map(|i| match i {
+                        0 => GValue::map(vec![field("id", GValue::int(i)), field("x", GValue::int(1))]),
+                        i if i == null_at => GValue::map(vec![field("id", GValue::int(i)), field("x", GValue::Null)]),
+                        _ => GValue::map(vec![field("id", GValue::int(i))]),
+                    })
+                    .collect(),
+            )
+        };
+        assert!(equal_loose(&rows(1), &rows(2)).unwrap());
+        assert_ne!(rows(1), rows(2));
+        assert_eq!(hash_of(&rows(1)), hash_of(&rows(2)));
+    }
+
+    #[test]
+    fn test_collections() {
+        let dedup: HashSet<GValue> = samples().into_iter().chain(samples()).collect();
+        assert_eq!(dedup.len(), samples().len() - 1, "-0.0 and 0.0 are one value");
+
+        let sorted: Vec<GValue> = samples().into_iter().collect::<BTreeSet<_>>().into_iter().collect();
+        assert_eq!(sorted.first(), Some(&GValue::Null));
+        assert!(matches!(sorted.last(), Some(GValue::Table(_))));
+    }
+}
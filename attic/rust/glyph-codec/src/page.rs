@@ -0,0 +1,236 @@
+//! Paging through large lists
+//!
+//! Rather than put a 500-row tool result into a prompt, send the first page
+//! and a cursor. `paginate` and `first_page` cut the list at a path into
+//! pages; each page is the whole value with that list sliced, plus a `next`
+//! cursor the model can hand back to ask for more:
+//!
+//! ```text
+//! Page{next=^page:"3f9a12c0/20/20/results" offset=0 total=120 value={query=glyph results=[...]}}
+//! ```
+//!
+//! A cursor carries the list's path, the page's offset and size, and a short
+//! hash of the list, so `page_at` refuses a cursor minted for different data
+//! instead of serving the wrong rows.
+
+use crate::error::GlyphError;
+use crate::loose::hash_loose;
+use crate::path::GlyphPath;
+use crate::types::*;
+
+/// Prefix of page cursor refs
+pub const PAGE_PREFIX: &str = "page";
+
+/// Type name of `Page::to_value`
+pub const PAGE_TYPE: &str = "Page";
+
+/// Hex digits of the list hash kept in a cursor
+const CURSOR_HASH_LEN: usize = 8;
+
+/// Position of a page, encoded as `^page:"<hash>/<offset>/<size>/<path>"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageCursor {
+    pub path: GlyphPath,
+    pub offset: usize,
+    pub page_size: usize,
+    /// Leading hex digits of the list's `hash_loose`
+    pub list_hash: String,
+}
+
+impl PageCursor {
+    pub fn to_ref(&self) -> RefId {
+        RefId::new(
+            PAGE_PREFIX,
+            format!("{}/{}/{}/{}", self.list_hash, self.offset, self.page_size, self.path),
+        )
+    }
+
+    pub fn from_ref(id: &RefId) -> Result<Self, GlyphError> {
+        let bad = || GlyphError::InvalidValue(format!("not a page cursor: {}:{}", id.prefix, id.value));
+        if id.prefix != PAGE_PREFIX {
+            return Err(bad());
+        }
+        let mut parts = id.value.splitn(4, '/');
+        let mut next = || parts.next().ok_or_else(bad);
+        let list_hash = next()?.to_string();
+        let offset = next()?.parse().map_err(|_| bad())?;
+        let page_size = next()?.parse().map_err(|_| bad())?;
+        let path = GlyphPath::parse(next()?)?;
+        if page_size == 0 {
+            return Err(bad());
+        }
+        Ok(Self {
+            path,
+            offset,
+            page_size,
+            list_hash,
+        })
+    }
+}
+
+/// One page of a list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// The paged value with its list cut to this page
+    pub value: GValue,
+    /// Index of the page's first item in the full list
+    pub offset: usize,
+    /// Items in the full list
+    pub total: usize,
+    /// Cursor for the following page; `None` on the last
+    pub next: Option<RefId>,
+}
+
+impl Page {
+    /// `Page{next=^page:... offset=0 total=120 value=...}`, without `next` on
+    /// the last page
+    pub fn to_value(&self) -> GValue {
+        let mut fields = Vec::with_capacity(4);
+        if let Some(next) = &self.next {
+            fields.push(MapEntry::new("next", GValue::Id(next.clone())));
+        }
+        fields.push(MapEntry::new("offset", GValue::int(self.offset as i64)));
+        fields.push(MapEntry::new("total", GValue::int(self.total as i64)));
+        fields.push(MapEntry::new("value", self.value.clone()));
+        GValue::struct_val(PAGE_TYPE, fields)
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.next.is_none()
+    }
+}
+
+/// Every page of the list at `path`, `page_size` items each; a single page
+/// for an empty list
+pub fn paginate(v: &GValue, path: &str, page_size: usize) -> Result<Vec<Page>, GlyphError> {
+    let mut pages = vec![first_page(v, path, page_size)?];
+    while let Some(next) = pages.last().and_then(|p| p.next.clone()) {
+        pages.push(page_at(v, &next)?);
+    }
+    Ok(pages)
+}
+
+/// The first `page_size` items of the list at `path`
+pub fn first_page(v: &GValue, path: &str, page_size: usize) -> Result<Page, GlyphError> {
+    if page_size == 0 {
+        return Err(GlyphError::InvalidValue("page size must be at least 1".to_string()));
+    }
+    let path = GlyphPath::parse(path)?;
+    let list = list_at(v, &path)?;
+    let cursor = PageCursor {
+        list_hash: list_hash(list)?,
+        path,
+        offset: 0,
+        page_size,
+    };
+    Ok(slice(v, &cursor))
+}
+
+/// The page a cursor from `Page::next` points at, in the same value.
+/// Returns `Err(GlyphError::InvalidValue)` if the list has changed since.
+pub fn page_at(v: &GValue, cursor: &RefId) -> Result<Page, GlyphError> {
+    let cursor = PageCursor::from_ref(cursor)?;
+    let list = list_at(v, &cursor.path)?;
+    if list_hash(list)? != cursor.list_hash {
+        return Err(GlyphError::InvalidValue(format!("stale page cursor for {}", cursor.path)));
+    }
+    Ok(slice(v, &cursor))
+}
+
+fn list_at<'a>(v: &'a GValue, path: &GlyphPath) -> Result<&'a GValue, GlyphError> {
+    match v.at(path) {
+        Some(list @ (GValue::List(_) | GValue::Table(_))) => Ok(list),
+        Some(other) => Err(GlyphError::TypeMismatch {
+            expected: "list".to_string(),
+            got: other.gtype().to_string(),
+        }),
+        None => Err(GlyphError::InvalidValue(format!("no value at {}", path))),
+    }
+}
+
+fn list_hash(list: &GValue) -> Result<String, GlyphError> {
+    let mut hash = hash_loose(list)?;
+    hash.truncate(CURSOR_HASH_LEN);
+    Ok(hash)
+}
+
+/// `v` with the list at the cursor's path cut to the cursor's page; the list
+/// is known to be there
+fn slice(v: &GValue, cursor: &PageCursor) -> Page {
+    let mut value = v.clone();
+    let list = value.at_mut(&cursor.path).expect("list checked by caller");
+    let total = match list {
+        GValue::List(items) => items.len(),
+        GValue::Table(t) => t.rows.len(),
+        _ => 0,
+    };
+    let start = cursor.offset.min(total);
+    let end = cursor.offset.saturating_add(cursor.page_size).min(total);
+    match list {
+        GValue::List(items) => *items = items.drain(start..end).collect(),
+        GValue::Table(t) => t.rows = t.rows.drain(start..end).collect(),
+        _ => {}
+    }
+    let next = (end < total).then(|| {
+        PageCursor {
+            offset: end,
+            ..cursor.clone()
+        }
+        .to_ref()
+    });
+    Page {
+        value,
+        offset: start,
+        total,
+        next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::canonicalize_loose;
+
+    fn result() -> GValue {
+        GValue::map(vec![
+            MapEntry::new("query", GValue::str("glyph")),
+            MapEntry::new("results", GValue::list((0..5).map(GValue::int).collect())),
+        ])
+    }
+
+    #[test]
+    fn test_paginate() {
+        let pages = paginate(&result(), "results", 2).unwrap();
+        let items: Vec<_> = pages.iter().map(|p| canonicalize_loose(&p.value["results"]).unwrap()).collect();
+        assert_eq!(items, ["[0 1]", "[2 3]", "[4]"]);
+        assert_eq!(pages.iter().map(|p| p.offset).collect::<Vec<_>>(), [0, 2, 4]);
+        assert!(pages[2].is_last());
+        assert_eq!(pages[0].value["query"], GValue::str("glyph"));
+
+        let text = canonicalize_loose(&pages[0].to_value()).unwrap();
+        let hash = &hash_loose(&result()["results"]).unwrap()[..8];
+        assert_eq!(
+            text,
+            format!("Page{{next=^page:\"{}/2/2/results\" offset=0 total=5 value={{query=glyph results=[0 1]}}}}", hash)
+        );
+
+        let empty = GValue::list(vec![]);
+        let pages = paginate(&empty, "$", 10).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].total, 0);
+    }
+
+    #[test]
+    fn test_page_at_checks_cursor() {
+        let first = first_page(&result(), "results", 3).unwrap();
+        let next = first.next.clone().unwrap();
+        assert_eq!(page_at(&result(), &next).unwrap().value["results"], GValue::list(vec![GValue::int(3), GValue::int(4)]));
+
+        let mut changed = result();
+        changed.set_path("results[0]", GValue::int(9)).unwrap();
+        assert!(matches!(page_at(&changed, &next), Err(GlyphError::InvalidValue(_))));
+        assert!(page_at(&result(), &RefId::new("user", "1")).is_err());
+        assert!(matches!(first_page(&result(), "query", 3), Err(GlyphError::TypeMismatch { .. })));
+        assert!(first_page(&result(), "results", 0).is_err());
+    }
+}
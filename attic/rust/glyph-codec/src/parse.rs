@@ -0,0 +1,810 @@
+//! GLYPH loose-mode text parser
+//!
+//! Parses the text produced by `canonicalize_loose*` back into a `GValue`,
+//! including quoted keys, refs, bytes, structs, sums and `@tab` blocks. Also
+//! accepts the usual input variants: `null`/`∅`/`none`/`nil`, `true`/`false`,
+//! `:` as a key separator, and commas between items.
+
+use crate::error::*;
+use crate::loose::cell_matches;
+use crate::types::*;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::collections::HashMap;
+
+pub const MAX_PARSE_DEPTH: usize = 128;
+
+/// Characters that end a bare token
+pub(crate) const DELIMITERS: [char; 15] = [' ', '=', '{', '}', '[', ']', '(', ')', '"', '|', '^', '\\', ',', ':', '@'];
+
+/// Options for `parse_loose_with`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOpts {
+    /// Read `@tab` blocks with plain named columns as `GValue::Table`,
+    /// keeping their column types. Off by default: rows come back as a list
+    /// of maps, which paths, indexing and visitors read like any other list.
+    pub tables: bool,
+}
+
+/// Parse a GLYPH loose-mode document into a value
+pub fn parse_loose(input: &str) -> Result<GValue> {
+    parse_loose_with(input, ParseOpts::default())
+}
+
+/// Parse a GLYPH loose-mode document with explicit options
+pub fn parse_loose_with(input: &str, opts: ParseOpts) -> Result<GValue> {
+    let mut p = Parser::new(input);
+    p.tables = opts.tables;
+    p.skip_ws();
+    let v = p.parse_value(0)?;
+    p.skip_ws();
+    if !p.at_end() {
+        return Err(p.err("unexpected trailing input"));
+    }
+    Ok(v)
+}
+
+/// Parses as `parse_loose`
+impl std::str::FromStr for GValue {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_loose(s)
+    }
+}
+
+pub(crate) struct Parser<'a> {
+    src: &'a str,
+    pub(crate) pos: usize,
+    tables: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(src: &'a str) -> Self {
+        Self { src, pos: 0, tables: false }
+    }
+
+    pub(crate) fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    pub(crate) fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    pub(crate) fn at_end(&self) -> bool {
+        self.pos >= self.src.len()
+    }
+
+    pub(crate) fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// Skip whitespace and optional commas between items
+    pub(crate) fn skip_separators(&mut self) {
+        loop {
+            self.skip_ws();
+            if !self.eat(',') {
+                break;
+            }
+        }
+    }
+
+    pub(crate) fn err(&self, msg: &str) -> GlyphError {
+        let before = &self.src[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        GlyphError::Parse(format!("{} at {}:{}", msg, line, col))
+    }
+
+    pub(crate) fn parse_value(&mut self, depth: usize) -> Result<GValue> {
+        if depth > MAX_PARSE_DEPTH {
+            return Err(GlyphError::RecursionLimitExceeded { limit: MAX_PARSE_DEPTH });
+        }
+        match self.peek() {
+            None => Err(self.err("expected value")),
+            Some('[') => self.parse_list(depth),
+            Some('{') => Ok(GValue::Map(self.parse_entries(depth)?)),
+            Some('"') => Ok(GValue::str(self.parse_quoted()?)),
+            Some('^') => self.parse_ref(),
+            Some('@') => self.parse_tabular(depth),
+            Some('∅') => {
+                self.bump();
+                Ok(GValue::Null)
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => self.parse_number_or_time(),
+            Some(_) if self.rest().starts_with("b64\"") => {
+                self.pos += 3;
+                let encoded = self.parse_quoted()?;
+                BASE64
+                    .decode(encoded.as_bytes())
+                    .map(GValue::Bytes)
+                    .map_err(|e| self.err(&format!("invalid base64: {}", e)))
+            }
+            Some(_) => self.parse_word(depth),
+        }
+    }
+
+    /// Read a bare token up to the next delimiter or whitespace
+    pub(crate) fn bare_token(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c.is_control() || DELIMITERS.contains(&c) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        &self.src[start..self.pos]
+    }
+
+    /// Literal, bare string, `Type{...}` struct, or `Tag(...)` sum
+    fn parse_word(&mut self, depth: usize) -> Result<GValue> {
+        let word = self.bare_token();
+        if word.is_empty() {
+            return Err(self.err(&format!("unexpected {:?}", self.peek().unwrap_or(' '))));
+        }
+        match self.peek() {
+            Some('{') => {
+                let fields = self.parse_entries(depth)?;
+                return Ok(GValue::Struct(StructValue::new(word, fields)));
+            }
+            Some('(') => {
+                self.bump();
+                self.skip_ws();
+                let value = if self.eat(')') {
+                    None
+                } else {
+                    let v = self.parse_value(depth + 1)?;
+                    self.skip_ws();
+                    if !self.eat(')') {
+                        return Err(self.err("expected ')'"));
+                    }
+                    Some(v)
+                };
+                return Ok(GValue::Sum(SumValue::new(word, value)));
+            }
+            _ => {}
+        }
+        Ok(match word {
+            "_" | "null" | "none" | "nil" => GValue::Null,
+            "t" | "true" => GValue::Bool(true),
+            "f" | "false" => GValue::Bool(false),
+            "NaN" => GValue::Float(f64::NAN),
+            "Inf" => GValue::Float(f64::INFINITY),
+            _ => GValue::str(word),
+        })
+    }
+
+    fn parse_number_or_time(&mut self) -> Result<GValue> {
+        let start = self.pos;
+        let token = self.bare_token();
+        // Times contain ':' (a delimiter elsewhere), so take the full run
+        if looks_like_date(token) {
+            while let Some(c) = self.peek() {
+                if !(c.is_ascii_alphanumeric() || matches!(c, ':' | '.' | '-' | '+')) {
+                    break;
+                }
+                self.pos += 1;
+            }
+            let text = &self.src[start..self.pos];
+            return parse_time(text).map(GValue::Time).ok_or_else(|| self.err(&format!("invalid time {:?}", text)));
+        }
+        if token == "-Inf" {
+            return Ok(GValue::Float(f64::NEG_INFINITY));
+        }
+        if let Ok(n) = token.parse::<i64>() {
+            return Ok(GValue::Int(n));
+        }
+        match token.parse::<f64>() {
+            Ok(f) if token.bytes().all(|b| b.is_ascii_digit() || matches!(b, b'-' | b'+' | b'.' | b'e' | b'E')) => {
+                Ok(GValue::Float(f))
+            }
+            _ => Err(self.err(&format!("invalid number {:?}", token))),
+        }
+    }
+
+    fn parse_quoted(&mut self) -> Result<String> {
+        if !self.eat('"') {
+            return Err(self.err("expected '\"'"));
+        }
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.err("unterminated string")),
+                Some('"') => return Ok(out),
+                Some('\\') => match self.bump() {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => out.push(self.parse_unicode_escape()?),
+                    _ => return Err(self.err("invalid escape")),
+                },
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char> {
+        let hi = self.hex4()?;
+        if (0xD800..0xDC00).contains(&hi) {
+            // Surrogate pair
+            if self.rest().starts_with("\\u") {
+                self.pos += 2;
+                let lo = self.hex4()?;
+                if (0xDC00..0xE000).contains(&lo) {
+                    let cp = 0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00);
+                    return char::from_u32(cp).ok_or_else(|| self.err("invalid surrogate pair"));
+                }
+            }
+            return Err(self.err("unpaired surrogate"));
+        }
+        char::from_u32(hi).ok_or_else(|| self.err("invalid \\u escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self.rest().get(..4).ok_or_else(|| self.err("short \\u escape"))?;
+        let n = u32::from_str_radix(digits, 16).map_err(|_| self.err("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(n)
+    }
+
+    /// Key: quoted string, or bare token (keys are separated by `=` or `:`)
+    pub(crate) fn parse_key(&mut self) -> Result<String> {
+        if self.peek() == Some('"') {
+            return self.parse_quoted();
+        }
+        let key = self.bare_token();
+        if key.is_empty() {
+            return Err(self.err("expected key"));
+        }
+        Ok(key.to_string())
+    }
+
+    fn parse_ref(&mut self) -> Result<GValue> {
+        self.bump(); // ^
+        if self.peek() == Some('"') {
+            return Ok(GValue::Id(RefId::new("", self.parse_quoted()?)));
+        }
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || (DELIMITERS.contains(&c) && c != ':') {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        let token = &self.src[start..self.pos];
+        match token.split_once(':') {
+            Some((prefix, "")) if self.peek() == Some('"') => Ok(GValue::Id(RefId::new(prefix, self.parse_quoted()?))),
+            Some((prefix, value)) if !prefix.is_empty() => Ok(GValue::Id(RefId::new(prefix, value))),
+            _ if !token.is_empty() => Ok(GValue::Id(RefId::new("", token))),
+            _ => Err(self.err("empty reference")),
+        }
+    }
+
+    fn parse_list(&mut self, depth: usize) -> Result<GValue> {
+        self.bump(); // [
+        let mut items = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.eat(']') {
+                return Ok(GValue::List(items));
+            }
+            if self.at_end() {
+                return Err(self.err("unterminated list"));
+            }
+            items.push(self.parse_value(depth + 1)?);
+        }
+    }
+
+    /// `{k=v ...}` with last-wins duplicate keys
+    fn parse_entries(&mut self, depth: usize) -> Result<Vec<MapEntry>> {
+        self.bump(); // {
+        let mut entries: Vec<MapEntry> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        loop {
+            self.skip_separators();
+            if self.eat('}') {
+                return Ok(entries);
+            }
+            if self.at_end() {
+                return Err(self.err("unterminated map"));
+            }
+            let key = self.parse_key()?;
+            self.skip_ws();
+            if !self.eat('=') && !self.eat(':') {
+                return Err(self.err(&format!("expected '=' after key {:?}", key)));
+            }
+            self.skip_ws();
+            let value = self.parse_value(depth + 1)?;
+            match index.get(&key) {
+                Some(&i) => entries[i].value = value,
+                None => {
+                    index.insert(key.clone(), entries.len());
+                    entries.push(MapEntry::new(key, value));
+                }
+            }
+        }
+    }
+
+    // ============================================================
+    // Tabular blocks
+    // ============================================================
+
+    /// `@tab _ [rows=N] [cols=M] [k1 k2]` then `|v|v|` rows, then `@end`.
+    /// An empty column list with `cols=M` gives rows of M-item lists; with
+    /// `flat=N`, bare `a.b` columns rebuild nested maps.
+    fn parse_tabular(&mut self, depth: usize) -> Result<GValue> {
+        if !self.rest().starts_with("@tab _") {
+            return Err(self.err("expected '@tab _'"));
+        }
+        self.pos += "@tab _".len();
+
+        let mut expected_rows = None;
+        let mut expected_cols = None;
+        let mut flat = false;
+        loop {
+            self.skip_inline_ws();
+            if self.peek() == Some('[') {
+                break;
+            }
+            let attr = self.bare_token();
+            if attr.is_empty() || !self.eat('=') {
+                return Err(self.err("expected '[' in tabular header"));
+            }
+            let n: usize = self.bare_token().parse().map_err(|_| self.err("invalid tabular header count"))?;
+            match attr {
+                "rows" => expected_rows = Some(n),
+                "cols" => expected_cols = Some(n),
+                "flat" => flat = n > 0,
+                _ => {}
+            }
+        }
+
+        self.bump(); // [
+        let mut cols = Vec::new();
+        let mut types: Vec<Option<GType>> = Vec::new();
+        loop {
+            self.skip_inline_ws();
+            if self.eat(']') {
+                break;
+            }
+            if self.at_end() || self.peek() == Some('\n') {
+                return Err(self.err("missing ']' in tabular header"));
+            }
+            // In a flat block, bare keys with dots are paths into nested maps
+            let quoted = self.peek() == Some('"');
+            let key = self.parse_key()?;
+            cols.push(if flat && !quoted {
+                key.split('.').map(str::to_string).collect()
+            } else {
+                vec![key]
+            });
+            types.push(if self.eat(':') {
+                let name = self.bare_token();
+                Some(GType::parse(name).ok_or_else(|| self.err(&format!("unknown column type {:?}", name)))?)
+            } else {
+                None
+            });
+        }
+        // An empty column list with cols=N means positional rows of lists
+        let positional = cols.is_empty() && expected_cols.is_some_and(|n| n > 0);
+        if !positional && expected_cols.is_some_and(|n| n != cols.len()) {
+            return Err(self.err("tabular column count does not match header"));
+        }
+
+        // Plain named columns give a Table when asked; otherwise rows are a List
+        let table = self.tables && !positional && cols.iter().all(|p| p.len() == 1);
+        let mut rows = Vec::new();
+        let mut table_rows = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.rest().starts_with("@end") {
+                self.pos += "@end".len();
+                break;
+            }
+            if self.at_end() {
+                return Err(self.err("missing @end"));
+            }
+            let line_end = self.pos + row_len(self.rest());
+            let line = self.src[self.pos..line_end].trim_end();
+            match expected_cols {
+                Some(width) if positional => rows.push(self.parse_list_row(line, width, depth)?),
+                _ if table => table_rows.push(self.parse_cells(line, &cols, &types, depth)?),
+                _ => rows.push(self.parse_row(line, &cols, &types, depth)?),
+            }
+            self.pos = line_end;
+        }
+        if expected_rows.is_some_and(|n| n != rows.len() + table_rows.len()) {
+            return Err(self.err("tabular row count does not match header"));
+        }
+        if table {
+            let columns = cols.into_iter().flatten().collect();
+            let mut t = TableValue::new(columns, table_rows);
+            if types.iter().any(Option::is_some) {
+                t = t.with_types(types);
+            }
+            return Ok(GValue::Table(t));
+        }
+        Ok(GValue::List(rows))
+    }
+
+    fn skip_inline_ws(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_row(&self, line: &str, cols: &[Vec<String>], types: &[Option<GType>], depth: usize) -> Result<GValue> {
+        let mut entries = Vec::with_capacity(cols.len());
+        for (path, value) in cols.iter().zip(self.parse_cells(line, cols, types, depth)?) {
+            insert_path(&mut entries, path, value);
+        }
+        Ok(GValue::Map(entries))
+    }
+
+    /// Cell values of one row, in column order, checked against column types
+    fn parse_cells(&self, line: &str, cols: &[Vec<String>], types: &[Option<GType>], depth: usize) -> Result<Vec<GValue>> {
+        let cells = self.row_cells(line, cols.len())?;
+        cols.iter()
+            .zip(cells)
+            .zip(types)
+            .map(|((path, cell), ty)| {
+                let col = path.join(".");
+                let value = self.parse_cell(cell, &col, depth)?;
+                match (*ty, value) {
+                    // Whole floats are written like ints
+                    (Some(GType::Float), GValue::Int(n)) => Ok(GValue::Float(n as f64)),
+                    (Some(ty), value) if !cell_matches(&value, ty) => {
+                        Err(self.err(&format!("cell {:?}: expected {}, got {}", col, ty, value.gtype())))
+                    }
+                    (_, value) => Ok(value),
+                }
+            })
+            .collect()
+    }
+
+    fn parse_list_row(&self, line: &str, width: usize, depth: usize) -> Result<GValue> {
+        let cells = self.row_cells(line, width)?;
+        let items = cells
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| self.parse_cell(cell, &i.to_string(), depth))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(GValue::List(items))
+    }
+
+    /// Split `|a|b|` into exactly `width` raw cells
+    fn row_cells<'l>(&self, line: &'l str, width: usize) -> Result<Vec<&'l str>> {
+        let inner = line
+            .strip_prefix('|')
+            .and_then(|l| l.strip_suffix('|'))
+            .ok_or_else(|| self.err("tabular row must start and end with '|'"))?;
+        let cells = if width == 0 { Vec::new() } else { split_cells(inner) };
+        if cells.len() != width {
+            return Err(self.err(&format!("expected {} cells, got {}", width, cells.len())));
+        }
+        Ok(cells)
+    }
+
+    fn parse_cell(&self, cell: &str, col: &str, depth: usize) -> Result<GValue> {
+        let text = unescape_cell(cell);
+        let mut p = Parser::new(&text);
+        p.tables = self.tables;
+        p.skip_ws();
+        let value = p.parse_value(depth + 1).map_err(|e| self.err(&format!("cell {:?}: {}", col, e)))?;
+        p.skip_ws();
+        if !p.at_end() {
+            return Err(self.err(&format!("trailing input in cell {:?}", col)));
+        }
+        Ok(value)
+    }
+}
+
+/// Insert `value` at `path`, creating nested maps as needed
+fn insert_path(entries: &mut Vec<MapEntry>, path: &[String], value: GValue) {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    if rest.is_empty() {
+        entries.push(MapEntry::new(key.clone(), value));
+        return;
+    }
+    let idx = match entries.iter().position(|e| e.key == *key && e.value.is_map()) {
+        Some(i) => i,
+        None => {
+            entries.push(MapEntry::new(key.clone(), GValue::Map(Vec::new())));
+            entries.len() - 1
+        }
+    };
+    if let GValue::Map(inner) = &mut entries[idx].value {
+        insert_path(inner, rest, value);
+    }
+}
+
+/// Split a row on `|`, taking `\` and the character after it as a pair
+fn split_cells(inner: &str) -> Vec<&str> {
+    let mut cells = Vec::new();
+    let mut start = 0;
+    let bytes = inner.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'|' => {
+                cells.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    cells.push(&inner[start..]);
+    cells
+}
+
+/// Undo cell escaping: `\|` becomes `|`, other `\` pairs are kept for the
+/// value parser
+fn unescape_cell(cell: &str) -> String {
+    let mut out = String::with_capacity(cell.len());
+    let mut chars = cell.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('|') => out.push('|'),
+            Some(next) => {
+                out.push('\\');
+                out.push(next);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Byte length of the tabular row at the start of `rest`: up to the first
+/// newline outside a quoted string, so quoted cells may span lines
+fn row_len(rest: &str) -> usize {
+    let bytes = rest.as_bytes();
+    let mut quoted = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => quoted = !quoted,
+            b'\n' if !quoted => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+fn looks_like_date(token: &str) -> bool {
+    let b = token.as_bytes();
+    b.len() >= 10 && b[..4].iter().all(u8::is_ascii_digit) && b[4] == b'-' && b[7] == b'-'
+}
+
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(text) {
+        return Some(t.with_timezone(&Utc));
+    }
+    for fmt in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%MZ"] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(text, fmt) {
+            return Some(t.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|t| t.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::*;
+
+    fn roundtrip(v: &GValue) {
+        for opts in [LooseCanonOpts::default(), LooseCanonOpts::no_tabular()] {
+            let text = canonicalize_loose_with_opts(v, &opts).unwrap();
+            let parsed = parse_loose(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+            assert_eq!(canonicalize_loose_with_opts(&parsed, &opts).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_parse_scalars() {
+        assert_eq!(parse_loose("_").unwrap(), GValue::Null);
+        assert_eq!(parse_loose("∅").unwrap(), GValue::Null);
+        assert_eq!(parse_loose("true").unwrap(), GValue::Bool(true));
+        assert_eq!(parse_loose("-42").unwrap(), GValue::Int(-42));
+        assert_eq!(parse_loose("1e-06").unwrap(), GValue::Float(1e-6));
+        assert_eq!(parse_loose("hello").unwrap(), GValue::str("hello"));
+        assert_eq!(parse_loose("\"a\\nb\\u00e9\"").unwrap(), GValue::str("a\nbé"));
+        assert_eq!(parse_loose("b64\"SGk=\"").unwrap(), GValue::bytes(b"Hi".to_vec()));
+        assert_eq!(parse_loose("^user:1").unwrap(), GValue::id("user", "1"));
+        assert_eq!(parse_loose("^doc:\"a b\"").unwrap(), GValue::id("doc", "a b"));
+        assert!(parse_loose("2024-01-02T03:04:05Z").unwrap().is_time());
+    }
+
+    #[test]
+    fn test_parse_containers() {
+        let v = parse_loose("{b=[1, 2] a:Point{x=1 y=2} s=Some(t) n=None()}").unwrap();
+        assert_eq!(canonicalize_loose(&v).unwrap(), "{a=Point{x=1 y=2} b=[1 2] n=None() s=Some(t)}");
+        // Last wins
+        assert_eq!(parse_loose("{k=1 k=2}").unwrap(), GValue::map(vec![field("k", GValue::int(2))]));
+    }
+
+    #[test]
+    fn test_keys_roundtrip() {
+        let keys = [
+            "a=b", "x y", "{", "}", "[a]", "(p)", "quote\"d", "back\\slash", "pipe|key", "tab\tkey",
+            "new\nline", "", "_", "t", "null", "1", "-x", "a:b", "^ref", "@tab", "é", "😀", "a,b",
+        ];
+        let entries: Vec<MapEntry> = keys.iter().map(|k| field(*k, GValue::str(*k))).collect();
+        let v = GValue::map(entries);
+        roundtrip(&v);
+        let parsed = parse_loose(&canonicalize_loose(&v).unwrap()).unwrap();
+        for k in keys {
+            assert_eq!(parsed.get(k), Some(&GValue::str(k)), "{:?}", k);
+        }
+    }
+
+    #[test]
+    fn test_tabular_roundtrip() {
+        let row = |id: i64, note: &str| {
+            GValue::map(vec![
+                field("id", GValue::int(id)),
+                field("a b", GValue::str(note)),
+                field("x|y", GValue::list(vec![GValue::str("p|q")])),
+            ])
+        };
+        let v = GValue::map(vec![field(
+            "rows",
+            GValue::list(vec![row(1, "one"), row(2, "t|wo"), row(3, "th\"ree")]),
+        )]);
+        let text = canonicalize_loose(&v).unwrap();
+        assert!(text.contains("@tab _ rows=3 cols=3 [\"a b\" \"x|y\" id]"), "{}", text);
+        assert!(equal_loose(&parse_loose(&text).unwrap(), &v).unwrap());
+        roundtrip(&v);
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let text = "@tab _ rows=2 cols=2 [id name]\n|1|a|\n|2|_|\n@end";
+        let v = parse_loose_with(text, ParseOpts { tables: true }).unwrap();
+        let t = v.as_table().unwrap();
+        assert_eq!(t.columns, ["id", "name"]);
+        assert_eq!(t.rows, vec![vec![GValue::int(1), GValue::str("a")], vec![GValue::int(2), GValue::Null]]);
+        assert_eq!(canonicalize_loose(&v).unwrap(), text);
+        // By default the rows are the list of maps the table stands for
+        assert_eq!(parse_loose(text).unwrap(), t.to_list());
+        assert_eq!(parse_loose(text).unwrap().get_path("[0].name"), Some(&GValue::str("a")));
+        assert_eq!(parse_loose(text).unwrap().walk().count(), 7);
+    }
+
+    #[test]
+    fn test_cell_escaping() {
+        let row = |k: i64, v: GValue| GValue::build_map().int("k", k).value("v", v).finish();
+        let rows = GValue::list((1..=3).map(|i| row(i, GValue::str("x"))).collect());
+        let v = GValue::list(vec![
+            row(1, rows.clone()),
+            row(2, GValue::table(["a"], vec![vec![GValue::str("p|q")]])),
+            row(3, GValue::str("ends\\")),
+            row(4, GValue::str("a\\|b\nc")),
+        ]);
+        let text = canonicalize_loose(&v).unwrap();
+        // Nested lists and tables stay inline, so every row is one line
+        assert_eq!(text.lines().count(), 6, "{}", text);
+        assert!(text.contains(r#"|2|[{a="p\|q"}]|"#), "{}", text);
+        let parsed = parse_loose(&text).unwrap();
+        assert_eq!(parsed[0]["v"], rows);
+        assert!(equal_loose(&parsed, &v).unwrap());
+
+        // `\\` pairs before a delimiter, and quoted cells spanning lines
+        let text = "@tab _ rows=2 cols=2 [a b]\n|\"x\\\\\"|\"two\nlines\"|\n|\"\\|\"|_|\n@end";
+        let t = parse_loose_with(text, ParseOpts { tables: true }).unwrap();
+        let t = t.as_table().unwrap();
+        assert_eq!(t.rows[0], vec![GValue::str("x\\"), GValue::str("two\nlines")]);
+        assert_eq!(t.rows[1][0], GValue::str("|"));
+    }
+
+    #[test]
+    fn test_typed_columns() {
+        let text = "@tab _ rows=3 cols=3 [id:int score:float ts:time]\n|1|2|2024-01-02T03:04:05Z|\n|2|2.5|_|\n|3|_|_|\n@end";
+        let v = parse_loose_with(text, ParseOpts { tables: true }).unwrap();
+        let t = v.as_table().unwrap();
+        assert_eq!(t.types, [Some(GType::Int), Some(GType::Float), Some(GType::Time)]);
+        // Whole floats are written like ints; the annotation restores the type
+        assert_eq!(t.rows[0][1], GValue::Float(2.0));
+        assert!(t.rows[0][2].is_time());
+        // Declared types are emitted back
+        assert_eq!(canonicalize_loose(&v).unwrap(), text);
+        // Rows read as maps still get the declared types
+        assert_eq!(parse_loose(text).unwrap()[0]["score"], GValue::Float(2.0));
+
+        assert!(parse_loose("@tab _ [id:int]\n|x|\n@end").is_err());
+        assert!(parse_loose("@tab _ [id:integer]\n|1|\n@end").is_err());
+        // Untyped columns accept anything
+        assert!(parse_loose("@tab _ [id:int v]\n|1|x|\n|2|3|\n@end").is_ok());
+    }
+
+    #[test]
+    fn test_list_rows_roundtrip() {
+        let row = |a: i64, s: &str| GValue::list(vec![GValue::int(a), GValue::str(s), GValue::list(vec![])]);
+        let v = GValue::list(vec![row(1, "x"), row(2, "a|b"), row(3, "z")]);
+        let opts = LooseCanonOpts {
+            tabular_lists: true,
+            ..LooseCanonOpts::default()
+        };
+        let text = canonicalize_loose_with_opts(&v, &opts).unwrap();
+        assert_eq!(text, "@tab _ rows=3 cols=3 []\n|1|x|[]|\n|2|\"a\\|b\"|[]|\n|3|z|[]|\n@end");
+        assert_eq!(parse_loose(&text).unwrap(), v);
+        assert!(parse_loose("@tab _ cols=2 []\n|1|\n@end").is_err());
+    }
+
+    #[test]
+    fn test_flat_columns_roundtrip() {
+        let row = |id: i64, name: &str| {
+            GValue::build_map()
+                .value("user", GValue::build_map().int("id", id).str("name", name))
+                .int("score", id * 10)
+                .finish()
+        };
+        let v = GValue::list(vec![row(1, "a"), row(2, "b"), row(3, "c")]);
+        let opts = LooseCanonOpts {
+            tabular_flatten_depth: 1,
+            ..LooseCanonOpts::default()
+        };
+        let text = canonicalize_loose_with_opts(&v, &opts).unwrap();
+        assert!(text.starts_with("@tab _ rows=3 cols=3 flat=1 [score user.id user.name]\n|10|1|a|"), "{}", text);
+        assert!(equal_loose(&parse_loose(&text).unwrap(), &v).unwrap());
+
+        // Without flat=, a dotted key is a plain column
+        let plain = parse_loose("@tab _ [a.b]\n|1|\n@end").unwrap();
+        assert_eq!(plain[0]["a.b"], GValue::int(1));
+        // Quoted keys stay literal in a flat block
+        let quoted = parse_loose("@tab _ flat=1 [\"a.b\" c.d]\n|1|2|\n@end").unwrap();
+        let row = quoted.index(0).unwrap();
+        assert_eq!(row.get("a.b"), Some(&GValue::int(1)));
+        assert_eq!(row.get("c").and_then(|c| c.get("d")), Some(&GValue::int(2)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in ["{a=1", "[1 2", "\"open", "{=1}", "{a 1}", "1 2", "b64\"!!\"", "@tab _ [a]\n|1|\n", "@tab _ rows=2 [a]\n|1|\n@end"] {
+            assert!(parse_loose(bad).is_err(), "{:?} should fail", bad);
+        }
+        let deep = "[".repeat(MAX_PARSE_DEPTH + 2);
+        assert!(matches!(parse_loose(&deep), Err(GlyphError::RecursionLimitExceeded { .. })));
+    }
+}
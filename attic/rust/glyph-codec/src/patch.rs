@@ -0,0 +1,1345 @@
+//! Structural diffs
+//!
+//! `diff(&before, &after)` lists what changed between two values as a
+//! `GlyphPatch`: added and removed map keys and list items, and changed
+//! values, each at its path. Leaves compare with `equal_loose`, so `1` and
+//! `1.0` or a re-ordered map are not changes. Lists are aligned on equal
+//! items, so reordered items and subtrees moved to a new key come out as
+//! moves rather than remove/add pairs; `DiffOpts` turns that off, or also
+//! turns repeated subtrees into copies.
+//!
+//! A patch prints one change per line (`~ args.query: rust -> go`) for
+//! "what changed since last call" prompts, converts to a GValue with
+//! `to_value` (and back with `from_value`), and replays onto the old value
+//! with `apply_patch`, strictly or skipping ops that no longer fit.
+
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose, canonicalize_loose_no_tabular, equal_loose, hash_loose};
+use crate::parse::parse_loose;
+use crate::path::{GlyphPath, PathSegment};
+use crate::types::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// One change in a `GlyphPatch`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOp {
+    /// A map key or list item present only in the new value
+    Add { path: GlyphPath, value: GValue },
+    /// A map key or list item present only in the old value
+    Remove { path: GlyphPath, old: GValue },
+    /// A node whose value differs
+    Change { path: GlyphPath, old: GValue, new: GValue },
+    /// A node taken out at `from` and put in at `path`. In lists the item is
+    /// removed first, so `path` counts positions without it.
+    Move { from: GlyphPath, path: GlyphPath },
+    /// A new node equal to the one at `from`
+    Copy { from: GlyphPath, path: GlyphPath },
+}
+
+impl PatchOp {
+    /// The node the op writes (for moves and copies, the destination)
+    pub fn path(&self) -> &GlyphPath {
+        match self {
+            PatchOp::Add { path, .. }
+            | PatchOp::Remove { path, .. }
+            | PatchOp::Change { path, .. }
+            | PatchOp::Move { path, .. }
+            | PatchOp::Copy { path, .. } => path,
+        }
+    }
+
+    /// Whether the op shifts the items of a list
+    fn shifts_list(&self) -> bool {
+        let is_index = |p: &GlyphPath| matches!(p.last(), Some(PathSegment::Index(_)));
+        match self {
+            PatchOp::Add { path, .. } | PatchOp::Remove { path, .. } | PatchOp::Copy { path, .. } => is_index(path),
+            PatchOp::Move { from, path } => is_index(from) || is_index(path),
+            PatchOp::Change { .. } => false,
+        }
+    }
+}
+
+/// The changes between two values, in an order `apply` can replay
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlyphPatch {
+    pub ops: Vec<PatchOp>,
+}
+
+/// Options for `diff_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOpts {
+    /// Align lists on equal items, emitting moves for reordered ones, and pair
+    /// a removed key with an added key of the same value into a move
+    pub detect_moves: bool,
+    /// Turn an added key whose value already exists elsewhere into a copy.
+    /// Costs a canonical form per node of `after`.
+    pub detect_copies: bool,
+}
+
+impl Default for DiffOpts {
+    fn default() -> Self {
+        Self {
+            detect_moves: true,
+            detect_copies: false,
+        }
+    }
+}
+
+impl DiffOpts {
+    /// Lists compared index by index, no moves or copies
+    pub fn positional() -> Self {
+        Self {
+            detect_moves: false,
+            detect_copies: false,
+        }
+    }
+}
+
+/// Changes that turn `before` into `after`, with `DiffOpts::default()`
+pub fn diff(before: &GValue, after: &GValue) -> GlyphPatch {
+    diff_with(before, after, DiffOpts::default())
+}
+
+/// Changes that turn `before` into `after`
+pub fn diff_with(before: &GValue, after: &GValue, opts: DiffOpts) -> GlyphPatch {
+    let mut ops = Vec::new();
+    diff_node(before, after, &mut GlyphPath::root(), &mut ops, opts);
+    if opts.detect_moves {
+        pair_moves(&mut ops);
+    }
+    if opts.detect_copies {
+        find_copies(after, &mut ops);
+    }
+    GlyphPatch { ops }
+}
+
+/// Loose equality; values without a canonical form (NaN, Inf) compare exactly
+pub(crate) fn same(a: &GValue, b: &GValue) -> bool {
+    equal_loose(a, b).unwrap_or_else(|_| a == b)
+}
+
+/// Text that is equal exactly when values are loosely equal, for comparing
+/// many items against each other
+fn canon_key(v: &GValue) -> String {
+    canonicalize_loose_no_tabular(v).unwrap_or_else(|_| format!("{:?}", v))
+}
+
+fn has_duplicate_keys(entries: &[MapEntry]) -> bool {
+    entries.iter().enumerate().any(|(i, e)| entries[..i].iter().any(|prev| prev.key == e.key))
+}
+
+fn diff_node(a: &GValue, b: &GValue, path: &mut GlyphPath, ops: &mut Vec<PatchOp>, opts: DiffOpts) {
+    match (a, b) {
+        // Tables diff as their row lists, so a one-cell edit stays one op
+        (GValue::Table(_), GValue::Table(_) | GValue::List(_)) | (GValue::List(_), GValue::Table(_)) => {
+            diff_node(&as_rows(a), &as_rows(b), path, ops, opts)
+        }
+        (GValue::Map(xs), GValue::Map(ys)) => diff_entries(a, b, xs, ys, path, ops, opts),
+        (GValue::Struct(x), GValue::Struct(y)) if x.type_name == y.type_name => {
+            diff_entries(a, b, &x.fields, &y.fields, path, ops, opts)
+        }
+        (GValue::List(xs), GValue::List(ys)) if opts.detect_moves => diff_aligned(xs, ys, path, ops, opts),
+        (GValue::List(xs), GValue::List(ys)) => {
+            for (i, (x, y)) in xs.iter().zip(ys).enumerate() {
+                path.push_index(i);
+                diff_node(x, y, path, ops, opts);
+                path.pop();
+            }
+            // Removals from the end first, so earlier indices stay valid
+            for i in (ys.len()..xs.len()).rev() {
+                path.push_index(i);
+                ops.push(PatchOp::Remove {
+                    path: path.clone(),
+                    old: xs[i].clone(),
+                });
+                path.pop();
+            }
+            for (i, y) in ys.iter().enumerate().skip(xs.len()) {
+                path.push_index(i);
+                ops.push(PatchOp::Add {
+                    path: path.clone(),
+                    value: y.clone(),
+                });
+                path.pop();
+            }
+        }
+        (GValue::Sum(x), GValue::Sum(y)) if x.tag == y.tag => match (&x.value, &y.value) {
+            (Some(xv), Some(yv)) => {
+                path.push_key(x.tag.clone());
+                diff_node(xv, yv, path, ops, opts);
+                path.pop();
+            }
+            (None, None) => {}
+            _ => change(a, b, path, ops),
+        },
+        _ => {
+            if !same(a, b) {
+                change(a, b, path, ops);
+            }
+        }
+    }
+}
+
+/// A table's list of row maps; anything else as it is
+fn as_rows(v: &GValue) -> Cow<'_, GValue> {
+    match v {
+        GValue::Table(t) => Cow::Owned(t.to_list()),
+        other => Cow::Borrowed(other),
+    }
+}
+
+fn change(a: &GValue, b: &GValue, path: &GlyphPath, ops: &mut Vec<PatchOp>) {
+    ops.push(PatchOp::Change {
+        path: path.clone(),
+        old: a.clone(),
+        new: b.clone(),
+    });
+}
+
+fn diff_entries(
+    a: &GValue,
+    b: &GValue,
+    xs: &[MapEntry],
+    ys: &[MapEntry],
+    path: &mut GlyphPath,
+    ops: &mut Vec<PatchOp>,
+    opts: DiffOpts,
+) {
+    // Keys alone can't address a repeated key's entries
+    if has_duplicate_keys(xs) || has_duplicate_keys(ys) {
+        if !same(a, b) {
+            change(a, b, path, ops);
+        }
+        return;
+    }
+    for x in xs {
+        path.push_key(x.key.clone());
+        match ys.iter().find(|y| y.key == x.key) {
+            Some(y) => diff_node(&x.value, &y.value, path, ops, opts),
+            None => ops.push(PatchOp::Remove {
+                path: path.clone(),
+                old: x.value.clone(),
+            }),
+        }
+        path.pop();
+    }
+    for y in ys.iter().filter(|y| !xs.iter().any(|x| x.key == y.key)) {
+        path.push_key(y.key.clone());
+        ops.push(PatchOp::Add {
+            path: path.clone(),
+            value: y.value.clone(),
+        });
+        path.pop();
+    }
+}
+
+// ============================================================
+// Moves and copies
+// ============================================================
+
+/// Edit `xs` into `ys` left to right. At each position the current item is
+/// kept if it matches, edited in place if neither it nor the wanted item
+/// appears elsewhere, dropped if nothing later wants it, and otherwise the
+/// wanted item is moved up from further on or added.
+fn diff_aligned(xs: &[GValue], ys: &[GValue], path: &mut GlyphPath, ops: &mut Vec<PatchOp>, opts: DiffOpts) {
+    let mut cur: Vec<(String, &GValue)> = xs.iter().map(|x| (canon_key(x), x)).collect();
+    let want: Vec<String> = ys.iter().map(canon_key).collect();
+    let mut j = 0;
+    while j < ys.len() {
+        let wanted_later = |key: &str| want[j..].iter().any(|w| w == key);
+        if j < cur.len() && cur[j].0 == want[j] {
+            j += 1;
+            continue;
+        }
+        let found = (j + 1..cur.len()).find(|&k| cur[k].0 == want[j]);
+        if j < cur.len() && found.is_none() && !wanted_later(&cur[j].0) {
+            path.push_index(j);
+            diff_node(cur[j].1, &ys[j], path, ops, opts);
+            path.pop();
+            cur[j] = (want[j].clone(), &ys[j]);
+            j += 1;
+        } else if j < cur.len() && !wanted_later(&cur[j].0) {
+            path.push_index(j);
+            ops.push(PatchOp::Remove {
+                path: path.clone(),
+                old: cur.remove(j).1.clone(),
+            });
+            path.pop();
+        } else if let Some(k) = found {
+            ops.push(PatchOp::Move {
+                from: path.index(k),
+                path: path.index(j),
+            });
+            let item = cur.remove(k);
+            cur.insert(j, item);
+            j += 1;
+        } else {
+            ops.push(PatchOp::Add {
+                path: path.index(j),
+                value: ys[j].clone(),
+            });
+            cur.insert(j, (want[j].clone(), &ys[j]));
+            j += 1;
+        }
+    }
+    for i in (ys.len()..cur.len()).rev() {
+        ops.push(PatchOp::Remove {
+            path: path.index(i),
+            old: cur[i].1.clone(),
+        });
+    }
+}
+
+fn ends_in_key(path: &GlyphPath) -> bool {
+    matches!(path.last(), Some(PathSegment::Key(_)))
+}
+
+/// Whether naming `from` is shorter than writing out `value`
+fn worth_referencing(from: &GlyphPath, value_key: &str) -> bool {
+    value_key.len() > from.to_string().len()
+}
+
+/// Merge a removed key and an added key of the same value into one move.
+///
+/// The move lands at the later of the two ops, and only when no op between
+/// them shifts a list, so both paths still name the same nodes there.
+fn pair_moves(ops: &mut Vec<PatchOp>) {
+    let mut i = 0;
+    while i < ops.len() {
+        let (PatchOp::Remove { path: from, old }, true) = (&ops[i], ends_in_key(ops[i].path())) else {
+            i += 1;
+            continue;
+        };
+        let key = canon_key(old);
+        let partner = ops.iter().enumerate().position(|(j, op)| {
+            let (lo, hi) = (i.min(j), i.max(j));
+            matches!(op, PatchOp::Add { path, value } if ends_in_key(path) && canon_key(value) == key)
+                && !ops[lo + 1..hi].iter().any(PatchOp::shifts_list)
+        });
+        match partner {
+            Some(j) if worth_referencing(from, &key) => {
+                let mv = PatchOp::Move {
+                    from: from.clone(),
+                    path: ops[j].path().clone(),
+                };
+                let (lo, hi) = (i.min(j), i.max(j));
+                ops[hi] = mv;
+                ops.remove(lo);
+                // The op now at `i` hasn't been looked at yet, unless the
+                // move itself landed there
+                if hi == i {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Replace added keys whose value also sits, unchanged, at another path of
+/// `after` with copies from there. Copies run last, reading the finished
+/// value, and only for adds that no later op can shift.
+fn find_copies(after: &GValue, ops: &mut Vec<PatchOp>) {
+    let last_shift = ops.iter().rposition(PatchOp::shifts_list);
+    let added: Vec<GlyphPath> = ops.iter().filter(|op| matches!(op, PatchOp::Add { .. })).map(|op| op.path().clone()).collect();
+    let mut sources: Option<HashMap<String, GlyphPath>> = None;
+    let mut copies = Vec::new();
+    let mut i = 0;
+    while i < ops.len() {
+        let candidate = match &ops[i] {
+            PatchOp::Add { path, value }
+                if ends_in_key(path) && last_shift.is_none_or(|s| s < i) && !matches!(value, GValue::Null | GValue::Bool(_)) =>
+            {
+                Some((path.clone(), canon_key(value)))
+            }
+            _ => None,
+        };
+        let Some((path, key)) = candidate else {
+            i += 1;
+            continue;
+        };
+        let sources = sources.get_or_insert_with(|| {
+            let mut map = HashMap::new();
+            for (p, node) in after.walk() {
+                if !p.is_root() && !added.iter().any(|a| a.is_prefix_of(&p)) {
+                    map.entry(canon_key(node)).or_insert(p);
+                }
+            }
+            map
+        });
+        match sources.get(&key) {
+            Some(from) if worth_referencing(from, &key) => {
+                copies.push(PatchOp::Copy {
+                    from: from.clone(),
+                    path,
+                });
+                ops.remove(i);
+            }
+            _ => i += 1,
+        }
+    }
+    ops.extend(copies);
+}
+
+// ============================================================
+// Patch
+// ============================================================
+
+impl GlyphPatch {
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PatchOp> {
+        self.ops.iter()
+    }
+
+    /// The patch as a list of `{op=add|remove|change|move|copy path=... ...}`
+    /// maps
+    pub fn to_value(&self) -> GValue {
+        GValue::list(self.ops.iter().map(|op| op_value(op, true)).collect())
+    }
+
+    /// The patch as one line of GLYPH-like text for a model:
+    /// `Δ{results[2].status=done +results[5]={...} -stale_field}`, with
+    /// `>from→to` for moves and `&from→to` for copies.
+    ///
+    /// Changes carry only the new value and removals only the path, so this
+    /// is for reading, not for `apply`; send `to_value` when the patch has to
+    /// come back.
+    pub fn render_delta(&self) -> String {
+        let mut out = String::from("Δ{");
+        for (i, op) in self.ops.iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            match op {
+                PatchOp::Change { path, new, .. } => out.push_str(&format!("{}={}", path, show(new))),
+                PatchOp::Add { path, value } => out.push_str(&format!("+{}={}", path, show(value))),
+                PatchOp::Remove { path, .. } => out.push_str(&format!("-{}", path)),
+                PatchOp::Move { from, path } => out.push_str(&format!(">{}→{}", from, path)),
+                PatchOp::Copy { from, path } => out.push_str(&format!("&{}→{}", from, path)),
+            }
+        }
+        out.push('}');
+        out
+    }
+
+    /// Replay the changes onto `target`, normally the `before` value.
+    ///
+    /// Same as `apply_patch(target, self, PatchMode::Strict)`.
+    pub fn apply(&self, target: &mut GValue) -> Result<(), GlyphError> {
+        apply_patch(target, self, PatchMode::Strict).map(|_| ())
+    }
+
+    /// `to_value` without the `old` fields, for receivers that only apply
+    pub(crate) fn to_wire_value(&self) -> GValue {
+        GValue::list(self.ops.iter().map(|op| op_value(op, false)).collect())
+    }
+
+    /// Read back a patch written by `to_value`, including one that went
+    /// through text and came back as an `@tab` table. Missing `old` fields
+    /// read as null.
+    pub fn from_value(v: &GValue) -> Result<Self, GlyphError> {
+        let rows;
+        let items = match v {
+            GValue::Table(t) => {
+                rows = t.to_list();
+                rows.try_as_list()?
+            }
+            other => other.try_as_list()?,
+        };
+        let ops = items
+            .iter()
+            .map(|item| {
+                let path_at = |key: &str| path_field(item, key);
+                let path = path_at("path")?;
+                let value = |key: &str| item.try_get(key).cloned();
+                let old = || item.get("old").cloned().unwrap_or(GValue::Null);
+                match item.try_get("op")?.try_as_str()? {
+                    "add" => Ok(PatchOp::Add { path, value: value("value")? }),
+                    "remove" => Ok(PatchOp::Remove { path, old: old() }),
+                    "change" => Ok(PatchOp::Change {
+                        path,
+                        old: old(),
+                        new: value("new")?,
+                    }),
+                    "move" => Ok(PatchOp::Move { from: path_at("from")?, path }),
+                    "copy" => Ok(PatchOp::Copy { from: path_at("from")?, path }),
+                    other => Err(GlyphError::InvalidValue(format!("unknown patch op: {}", other))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { ops })
+    }
+}
+
+// ============================================================
+// Application
+// ============================================================
+
+/// How `apply_patch` treats ops that don't fit the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PatchMode {
+    /// Any op whose path (or, for adds, parent) is missing is an error
+    #[default]
+    Strict,
+    /// Best effort: a change to a missing key inserts it, an add past the end
+    /// of a list appends, and ops that still can't land are skipped
+    Lenient,
+}
+
+/// Apply `patch` to `target`, returning the ops that were skipped.
+///
+/// In `Strict` mode the first op that doesn't fit stops the patch with an
+/// error (earlier ops stay applied) and nothing is ever skipped. In
+/// `Lenient` mode removing something already gone counts as skipped, as does
+/// any op whose parent is missing or the wrong kind.
+pub fn apply_patch(target: &mut GValue, patch: &GlyphPatch, mode: PatchMode) -> Result<GlyphPatch, GlyphError> {
+    let mut skipped = Vec::new();
+    for op in &patch.ops {
+        match apply_op(target, op, mode) {
+            Ok(true) => {}
+            Ok(false) => skipped.push(op.clone()),
+            Err(_) if mode == PatchMode::Lenient => skipped.push(op.clone()),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(GlyphPatch { ops: skipped })
+}
+
+/// `Ok(false)` when a lenient op had nothing to act on
+fn apply_op(target: &mut GValue, op: &PatchOp, mode: PatchMode) -> Result<bool, GlyphError> {
+    let lenient = mode == PatchMode::Lenient;
+    match op {
+        PatchOp::Change { path, new, .. } => {
+            let (Some(last), Some(parent)) = (path.last(), path.parent()) else {
+                *target = new.clone();
+                return Ok(true);
+            };
+            let parent = target.at_mut(&parent).ok_or_else(|| no_value(path))?;
+            match parent.child_mut(last) {
+                Some(slot) => *slot = new.clone(),
+                None => match last {
+                    PathSegment::Key(k) if lenient => {
+                        parent.insert(k.clone(), new.clone())?;
+                    }
+                    _ => return Err(no_value(path)),
+                },
+            }
+            Ok(true)
+        }
+        PatchOp::Add { path, value } => insert_at(target, path, value.clone(), lenient),
+        PatchOp::Remove { path, .. } => Ok(remove_at(target, path, lenient)?.is_some()),
+        PatchOp::Move { from, path } => {
+            let Some(value) = remove_at(target, from, lenient)? else {
+                return Ok(false);
+            };
+            let backup = value.clone();
+            insert_at(target, path, value, lenient).inspect_err(|_| {
+                // Put it back, so a failed move changes nothing
+                let _ = insert_at(target, from, backup, true);
+            })
+        }
+        // at_mut, so a copied table row is reachable
+        PatchOp::Copy { from, path } => match target.at_mut(from).cloned() {
+            Some(value) => insert_at(target, path, value, lenient),
+            None if lenient => Ok(false),
+            None => Err(no_value(from)),
+        },
+    }
+}
+
+fn no_value(path: &GlyphPath) -> GlyphError {
+    GlyphError::InvalidValue(format!("no value at {}", path))
+}
+
+/// Add `value` as a new map key or list item
+fn insert_at(target: &mut GValue, path: &GlyphPath, value: GValue, lenient: bool) -> Result<bool, GlyphError> {
+    let (Some(last), Some(parent)) = (path.last(), path.parent()) else {
+        *target = value;
+        return Ok(true);
+    };
+    let parent = target.at_mut(&parent).ok_or_else(|| no_value(path))?;
+    if let PathSegment::Index(_) = last {
+        parent.expand_table();
+    }
+    match (parent, last) {
+        (GValue::List(items), PathSegment::Index(i)) => {
+            if *i <= items.len() {
+                items.insert(*i, value);
+            } else if lenient {
+                items.push(value);
+            } else {
+                return Err(no_value(path));
+            }
+        }
+        (parent, PathSegment::Key(k)) => {
+            parent.insert(k.clone(), value)?;
+        }
+        (parent, PathSegment::Index(_)) => {
+            return Err(GlyphError::TypeMismatch {
+                expected: "list".into(),
+                got: parent.gtype().to_string(),
+            })
+        }
+    }
+    Ok(true)
+}
+
+/// Take out the node at `path`; `Ok(None)` when a lenient remove finds nothing
+fn remove_at(target: &mut GValue, path: &GlyphPath, lenient: bool) -> Result<Option<GValue>, GlyphError> {
+    let (Some(last), Some(parent)) = (path.last(), path.parent()) else {
+        return Ok(Some(std::mem::replace(target, GValue::Null)));
+    };
+    let parent = target.at_mut(&parent).ok_or_else(|| no_value(path))?;
+    if let PathSegment::Index(_) = last {
+        parent.expand_table();
+    }
+    let removed = match (parent, last) {
+        (GValue::List(items), PathSegment::Index(i)) if *i < items.len() => Some(items.remove(*i)),
+        (parent, PathSegment::Key(k)) => parent.remove(k)?,
+        _ => None,
+    };
+    match removed {
+        None if !lenient => Err(no_value(path)),
+        removed => Ok(removed),
+    }
+}
+
+fn op_value(op: &PatchOp, with_old: bool) -> GValue {
+    let b = GValue::build_map();
+    match op {
+        PatchOp::Add { path, value } => b.str("op", "add").str("path", path.to_string()).value("value", value.clone()),
+        PatchOp::Remove { path, old } => {
+            let b = b.str("op", "remove").str("path", path.to_string());
+            if with_old {
+                b.value("old", old.clone())
+            } else {
+                b
+            }
+        }
+        PatchOp::Change { path, old, new } => {
+            let b = b.str("op", "change").str("path", path.to_string());
+            let b = if with_old { b.value("old", old.clone()) } else { b };
+            b.value("new", new.clone())
+        }
+        PatchOp::Move { from, path } => b.str("op", "move").str("from", from.to_string()).str("path", path.to_string()),
+        PatchOp::Copy { from, path } => b.str("op", "copy").str("from", from.to_string()).str("path", path.to_string()),
+    }
+    .finish()
+}
+
+impl<'a> IntoIterator for &'a GlyphPatch {
+    type Item = &'a PatchOp;
+    type IntoIter = std::slice::Iter<'a, PatchOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.iter()
+    }
+}
+
+/// Canonical text, or `NaN`/`Inf` for floats that have none
+pub(crate) fn show(v: &GValue) -> String {
+    match v {
+        GValue::Float(f) if !f.is_finite() => f.to_string(),
+        other => canonicalize_loose(other).unwrap_or_else(|_| format!("{:?}", other)),
+    }
+}
+
+/// One line per op: `+ path: value`, `- path: old`, `~ path: old -> new`,
+/// `> from -> to` (move) and `& from -> to` (copy)
+impl fmt::Display for GlyphPatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, op) in self.ops.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            match op {
+                PatchOp::Add { path, value } => write!(f, "+ {}: {}", path, show(value))?,
+                PatchOp::Remove { path, old } => write!(f, "- {}: {}", path, show(old))?,
+                PatchOp::Change { path, old, new } => write!(f, "~ {}: {} -> {}", path, show(old), show(new))?,
+                PatchOp::Move { from, path } => write!(f, "> {} -> {}", from, path)?,
+                PatchOp::Copy { from, path } => write!(f, "& {} -> {}", from, path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A path stored as a string field, `$` for the root
+fn path_field(item: &GValue, key: &str) -> Result<GlyphPath, GlyphError> {
+    match item.try_get(key)?.try_as_str()? {
+        "$" => Ok(GlyphPath::root()),
+        p => GlyphPath::parse(p),
+    }
+}
+
+// ============================================================
+// GLYPH form
+// ============================================================
+
+impl GlyphPatch {
+    /// The patch as a GLYPH list of op sums:
+    ///
+    /// ```text
+    /// [set({old=todo path="results[2].status" value=done})
+    ///  ins({path="results[5]" value={...}}) del({old=t path=stale})
+    ///  mov({from=a path=b}) cpy({from=a path=c})]
+    /// ```
+    ///
+    /// `to_text` is its canonical form, so equal patches emit, hash and
+    /// compare identically.
+    pub fn to_glyph(&self) -> GValue {
+        let op = |tag: &str, b: GMapBuilder| GValue::sum(tag, Some(b.finish()));
+        let path = |b: GMapBuilder, p: &GlyphPath| b.str("path", p.to_string());
+        GValue::list(
+            self.ops
+                .iter()
+                .map(|o| {
+                    let b = GValue::build_map();
+                    match o {
+                        PatchOp::Change { path: p, old, new } => {
+                            op("set", path(b, p).value("old", old.clone()).value("value", new.clone()))
+                        }
+                        PatchOp::Add { path: p, value } => op("ins", path(b, p).value("value", value.clone())),
+                        PatchOp::Remove { path: p, old } => op("del", path(b, p).value("old", old.clone())),
+                        PatchOp::Move { from, path: p } => op("mov", path(b, p).str("from", from.to_string())),
+                        PatchOp::Copy { from, path: p } => op("cpy", path(b, p).str("from", from.to_string())),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Read a patch in the `to_glyph` form. `old` may be left out (it reads as
+    /// null), so hand-written patches need only `path` and `value`.
+    pub fn from_glyph(v: &GValue) -> Result<Self, GlyphError> {
+        let ops = v
+            .try_as_list()?
+            .iter()
+            .map(|item| {
+                let sum = item.try_as_sum()?;
+                let body = sum.value.as_deref().ok_or_else(|| GlyphError::MissingField("path".into()))?;
+                let path = path_field(body, "path")?;
+                let old = || body.get("old").cloned().unwrap_or(GValue::Null);
+                let value = || body.try_get("value").cloned();
+                match sum.tag.as_str() {
+                    "set" => Ok(PatchOp::Change { path, old: old(), new: value()? }),
+                    "ins" => Ok(PatchOp::Add { path, value: value()? }),
+                    "del" => Ok(PatchOp::Remove { path, old: old() }),
+                    "mov" => Ok(PatchOp::Move { from: path_field(body, "from")?, path }),
+                    "cpy" => Ok(PatchOp::Copy { from: path_field(body, "from")?, path }),
+                    other => Err(GlyphError::InvalidValue(format!("unknown patch op: {}", other))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { ops })
+    }
+
+    /// Canonical text of `to_glyph`
+    pub fn to_text(&self) -> Result<String, GlyphError> {
+        canonicalize_loose(&self.to_glyph())
+    }
+
+    /// Parse text written by `to_text` (or by hand, or by a model)
+    pub fn from_text(text: &str) -> Result<Self, GlyphError> {
+        Self::from_glyph(&parse_loose(text)?)
+    }
+
+    /// `hash_loose` of the GLYPH form, for storing and deduplicating patches
+    pub fn fingerprint(&self) -> Result<String, GlyphError> {
+        hash_loose(&self.to_glyph())
+    }
+}
+
+impl FromStr for GlyphPatch {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_text(s)
+    }
+}
+
+// ============================================================
+// Row diffs
+// ============================================================
+
+/// Rows of two tables matched up by a key column
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowDiff {
+    pub key_column: String,
+    /// Rows only in the new table, in its order
+    pub added: Vec<GValue>,
+    /// Rows only in the old table, in its order
+    pub removed: Vec<GValue>,
+    /// Rows in both whose cells differ, in the new table's order
+    pub changed: Vec<RowChange>,
+}
+
+/// The changed cells of one row
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowChange {
+    pub key: GValue,
+    pub cells: Vec<CellChange>,
+}
+
+/// One changed cell; a missing cell reads as null
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellChange {
+    pub column: String,
+    pub old: GValue,
+    pub new: GValue,
+}
+
+impl RowDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Diff two lists of maps (or structs, or `@tab` tables) row by row, matching
+/// rows on `key_column` instead of position.
+///
+/// Every row must have a distinct, non-null key.
+pub fn diff_rows(before: &GValue, after: &GValue, key_column: &str) -> Result<RowDiff, GlyphError> {
+    let old = keyed_rows(before, key_column)?;
+    let new = keyed_rows(after, key_column)?;
+    let mut diff = RowDiff {
+        key_column: key_column.to_string(),
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+    };
+    for (key, row) in &new {
+        match old.iter().find(|(k, _)| k == key) {
+            None => diff.added.push(row.clone()),
+            Some((_, prev)) => {
+                let cells = diff_cells(row_entries(prev)?, row_entries(row)?);
+                if !cells.is_empty() {
+                    diff.changed.push(RowChange {
+                        key: row.try_get(key_column)?.clone(),
+                        cells,
+                    });
+                }
+            }
+        }
+    }
+    for (key, row) in &old {
+        if !new.iter().any(|(k, _)| k == key) {
+            diff.removed.push(row.clone());
+        }
+    }
+    Ok(diff)
+}
+
+/// Each row with the canonical text of its key
+fn keyed_rows(v: &GValue, key_column: &str) -> Result<Vec<(String, GValue)>, GlyphError> {
+    let rows = match v {
+        GValue::Table(t) => (0..t.rows.len()).filter_map(|i| t.row(i)).collect(),
+        other => other.try_as_list()?.to_vec(),
+    };
+    let mut out: Vec<(String, GValue)> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let key = match row.try_get(key_column)? {
+            GValue::Null => return Err(GlyphError::MissingField(key_column.to_string())),
+            key => canon_key(key),
+        };
+        if out.iter().any(|(k, _)| *k == key) {
+            return Err(GlyphError::InvalidValue(format!("duplicate {} {}", key_column, key)));
+        }
+        out.push((key, row));
+    }
+    Ok(out)
+}
+
+fn row_entries(row: &GValue) -> Result<&[MapEntry], GlyphError> {
+    match row {
+        GValue::Map(entries) => Ok(entries),
+        GValue::Struct(s) => Ok(&s.fields),
+        other => Err(GlyphError::TypeMismatch {
+            expected: "map or struct".into(),
+            got: other.gtype().to_string(),
+        }),
+    }
+}
+
+fn diff_cells(old: &[MapEntry], new: &[MapEntry]) -> Vec<CellChange> {
+    let cell = |entries: &[MapEntry], column: &str| {
+        entries.iter().find(|e| e.key == column).map_or(GValue::Null, |e| e.value.clone())
+    };
+    let mut columns: Vec<&str> = Vec::new();
+    for e in old.iter().chain(new) {
+        if !columns.contains(&e.key.as_str()) {
+            columns.push(&e.key);
+        }
+    }
+    columns
+        .into_iter()
+        .filter_map(|column| {
+            let (a, b) = (cell(old, column), cell(new, column));
+            (!same(&a, &b)).then(|| CellChange {
+                column: column.to_string(),
+                old: a,
+                new: b,
+            })
+        })
+        .collect()
+}
+
+/// One line per row: `~ id=2: status todo -> done`, `+ id=7: {...}`, `- id=3`
+impl fmt::Display for RowDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key_of = |row: &GValue| row.get(&self.key_column).map_or_else(|| "_".to_string(), show);
+        let mut lines = Vec::new();
+        for change in &self.changed {
+            let cells: Vec<String> = change
+                .cells
+                .iter()
+                .map(|c| format!("{} {} -> {}", c.column, show(&c.old), show(&c.new)))
+                .collect();
+            lines.push(format!("~ {}={}: {}", self.key_column, show(&change.key), cells.join(", ")));
+        }
+        for row in &self.added {
+            lines.push(format!("+ {}={}: {}", self.key_column, key_of(row), show(row)));
+        }
+        for row in &self.removed {
+            lines.push(format!("- {}={}", self.key_column, key_of(row)));
+        }
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{parse_loose_with, ParseOpts};
+
+    fn call(query: &str, tags: &[&str], extra: Option<(&str, GValue)>) -> GValue {
+        let mut b = GValue::build_map()
+            .str("tool", "search")
+            .value(
+                "args",
+                GValue::build_map()
+                    .str("query", query)
+                    .value("tags", GValue::list(tags.iter().map(|t| GValue::str(*t)).collect()))
+                    .finish(),
+            );
+        if let Some((k, v)) = extra {
+            b = b.value(k, v);
+        }
+        b.finish()
+    }
+
+    #[test]
+    fn test_diff_reports_paths() {
+        let before = call("rust", &["a", "b", "c"], Some(("stale", GValue::bool(true))));
+        let after = call("go", &["a", "x"], Some(("limit", GValue::int(5))));
+        let patch = diff(&before, &after);
+        assert_eq!(
+            patch.to_string(),
+            "~ args.query: rust -> go\n\
+             ~ args.tags[1]: b -> x\n\
+             - args.tags[2]: c\n\
+             - stale: t\n\
+             + limit: 5"
+        );
+        let paths: Vec<String> = patch.iter().map(|op| op.path().to_string()).collect();
+        assert_eq!(paths, ["args.query", "args.tags[1]", "args.tags[2]", "stale", "limit"]);
+
+        let mut replayed = before.clone();
+        patch.apply(&mut replayed).unwrap();
+        assert!(equal_loose(&replayed, &after).unwrap());
+        assert!(diff(&replayed, &after).is_empty());
+    }
+
+    #[test]
+    fn test_loose_leaves_and_whole_changes() {
+        let a = GValue::build_map().int("n", 1).str("s", "x").finish();
+        let b = GValue::build_map().str("s", "x").float("n", 1.0).finish();
+        assert!(diff(&a, &b).is_empty());
+
+        // Kind changes and differing sums replace the node whole
+        let patch = diff(&GValue::list(vec![GValue::int(1)]), &GValue::str("x"));
+        assert_eq!(patch.to_string(), "~ $: [1] -> x");
+        let ok = GValue::sum("Ok", Some(GValue::int(1)));
+        assert_eq!(diff(&ok, &GValue::sum("Ok", Some(GValue::int(2)))).to_string(), "~ Ok: 1 -> 2");
+        assert_eq!(diff(&ok, &GValue::sum("Err", None)).len(), 1);
+
+        let mut root = GValue::int(1);
+        diff(&root, &GValue::str("x")).apply(&mut root).unwrap();
+        assert_eq!(root, GValue::str("x"));
+    }
+
+    #[test]
+    fn test_diff_parsed_tables() {
+        let text = |status: &str| {
+            format!("{{rows=@tab _ rows=3 cols=2 [id status]\n|1|todo|\n|2|{}|\n|3|todo|\n@end}}", status)
+        };
+        let before = parse_loose_with(&text("todo"), ParseOpts { tables: true }).unwrap();
+        let after = parse_loose_with(&text("done"), ParseOpts { tables: true }).unwrap();
+        assert!(before.get("rows").unwrap().is_table());
+        assert_eq!(diff(&before, &after).to_string(), "~ rows[1].status: todo -> done");
+
+        // A table against a list of the same rows
+        let list = GValue::build_map().value("rows", after.get("rows").unwrap().as_table().unwrap().to_list()).finish();
+        assert_eq!(diff(&before, &list).to_string(), "~ rows[1].status: todo -> done");
+        assert!(diff(&list, &after).is_empty());
+    }
+
+    #[test]
+    fn test_apply_to_parsed_tables() {
+        let row = |id: i64, s: &str| GValue::build_map().int("id", id).str("s", s).finish();
+        let before = GValue::build_map().value("rows", GValue::list(vec![row(1, "a"), row(2, "b"), row(3, "c")])).finish();
+        let mut after = before.clone();
+        after.set_path("rows[0].s", GValue::str("z")).unwrap();
+        after.get_path_mut("rows").unwrap().push(row(4, "d")).unwrap();
+
+        let parsed = || parse_loose_with(&canonicalize_loose(&before).unwrap(), ParseOpts { tables: true }).unwrap();
+        assert!(parsed().get("rows").unwrap().is_table());
+        for opts in [DiffOpts::default(), DiffOpts::positional()] {
+            let mut target = parsed();
+            diff_with(&before, &after, opts).apply(&mut target).unwrap();
+            assert!(equal_loose(&target, &after).unwrap());
+        }
+
+        // Removing a row and copying one
+        let mut target = parsed();
+        let patch = GlyphPatch {
+            ops: vec![
+                PatchOp::Remove {
+                    path: GlyphPath::parse("rows[2]").unwrap(),
+                    old: row(3, "c"),
+                },
+                PatchOp::Copy {
+                    from: GlyphPath::parse("rows[0]").unwrap(),
+                    path: GlyphPath::parse("first").unwrap(),
+                },
+            ],
+        };
+        patch.apply(&mut target).unwrap();
+        assert_eq!(target.get_path("rows[1].s"), Some(&GValue::str("b")));
+        assert_eq!(target.get_path("rows[2]"), None);
+        assert!(equal_loose(target.get("first").unwrap(), &row(1, "a")).unwrap());
+    }
+
+    #[test]
+    fn test_patch_value_and_apply_errors() {
+        let before = call("rust", &[], None);
+        let after = call("rust", &["a"], None);
+        let patch = diff(&before, &after);
+        assert_eq!(
+            patch.to_value().to_string(),
+            "[{op=add path=\"args.tags[0]\" value=a}]"
+        );
+
+        assert_eq!(GlyphPatch::from_value(&patch.to_value()).unwrap(), patch);
+
+        // Replaying onto something else fails at the missing path
+        let mut other = GValue::build_map().str("tool", "search").finish();
+        let err = patch.apply(&mut other).unwrap_err();
+        assert!(err.to_string().contains("no value at args.tags[0]"), "{}", err);
+    }
+
+    #[test]
+    fn test_render_delta() {
+        let before = GValue::build_map()
+            .value(
+                "results",
+                GValue::list(vec![
+                    GValue::build_map().str("status", "todo").finish(),
+                    GValue::build_map().str("status", "todo").finish(),
+                ]),
+            )
+            .bool("stale_field", true)
+            .finish();
+        let mut after = before.clone();
+        after.set_path("results[1].status", GValue::str("done")).unwrap();
+        after.get_path_mut("results").unwrap().push(GValue::build_map().int("id", 7).finish()).unwrap();
+        after.remove("stale_field").unwrap();
+
+        let patch = diff(&before, &after);
+        assert_eq!(patch.render_delta(), "Δ{results[1].status=done +results[2]={id=7} -stale_field}");
+        assert_eq!(GlyphPatch::default().render_delta(), "Δ{}");
+    }
+
+    #[test]
+    fn test_patch_survives_text() {
+        let mixed = diff(
+            &call("rust", &["a", "b"], Some(("stale", GValue::bool(true)))),
+            &call("go", &["a"], Some(("limit", GValue::int(5)))),
+        );
+        let text = mixed.to_value().to_string();
+        assert_eq!(GlyphPatch::from_value(&text.parse().unwrap()).unwrap(), mixed);
+
+        // Three ops of one shape go tabular on the way out
+        let removals = diff(&call("rust", &["a", "b", "c", "d"], None), &call("rust", &["a"], None));
+        let text = removals.to_value().to_string();
+        assert!(text.starts_with("@tab"), "{}", text);
+        assert_eq!(GlyphPatch::from_value(&text.parse().unwrap()).unwrap(), removals);
+
+        let root = GlyphPatch::from_value(&diff(&GValue::int(1), &GValue::int(2)).to_value()).unwrap();
+        assert!(root.ops[0].path().is_root());
+        assert!(GlyphPatch::from_value(&GValue::int(1)).is_err());
+    }
+
+    #[test]
+    fn test_apply_patch_modes() {
+        let before = call("rust", &["a"], Some(("stale", GValue::bool(true))));
+        let after = call("go", &["a", "b"], None);
+        let patch = diff(&before, &after);
+
+        // The receiver's copy has drifted: no tags, and `stale` already gone
+        let drifted = || {
+            GValue::build_map()
+                .str("tool", "search")
+                .value("args", GValue::build_map().str("query", "rust").finish())
+                .finish()
+        };
+
+        let mut strict = drifted();
+        let err = apply_patch(&mut strict, &patch, PatchMode::Strict).unwrap_err();
+        assert!(err.to_string().contains("no value at args.tags[1]"), "{}", err);
+        // Ops before the failure stay applied
+        assert_eq!(strict.get_path("args.query"), Some(&GValue::str("go")));
+
+        let mut lenient = drifted();
+        let skipped = apply_patch(&mut lenient, &patch, PatchMode::Lenient).unwrap();
+        assert_eq!(skipped.to_string(), "+ args.tags[1]: b\n- stale: t");
+        assert_eq!(lenient.to_string(), "{args={query=go} tool=search}");
+
+        // Lenient changes insert missing keys and adds past the end append
+        let mut v = GValue::build_map().value("xs", GValue::list(vec![])).finish();
+        let patch = GlyphPatch {
+            ops: vec![
+                PatchOp::Change {
+                    path: GlyphPath::parse("n").unwrap(),
+                    old: GValue::int(1),
+                    new: GValue::int(2),
+                },
+                PatchOp::Add {
+                    path: GlyphPath::parse("xs[3]").unwrap(),
+                    value: GValue::int(7),
+                },
+            ],
+        };
+        assert!(patch.apply(&mut v.clone()).is_err());
+        assert!(apply_patch(&mut v, &patch, PatchMode::Lenient).unwrap().is_empty());
+        assert_eq!(v.to_string(), "{n=2 xs=[7]}");
+    }
+
+    fn ints(xs: &[i64]) -> GValue {
+        GValue::list(xs.iter().map(|x| GValue::int(*x)).collect())
+    }
+
+    fn doc(title: &str) -> GValue {
+        GValue::build_map().str("title", title).str("body", format!("{} in some detail", title)).finish()
+    }
+
+    #[test]
+    fn test_list_moves() {
+        let before = GValue::list(vec![doc("a"), doc("b"), doc("c"), doc("d")]);
+        let after = GValue::list(vec![doc("d"), doc("a"), doc("b"), doc("c")]);
+        let patch = diff(&before, &after);
+        assert_eq!(patch.to_string(), "> [3] -> [0]");
+        assert_eq!(patch.render_delta(), "Δ{>[3]→[0]}");
+        let mut v = before.clone();
+        patch.apply(&mut v).unwrap();
+        assert_eq!(v, after);
+
+        // Inserting or dropping at the front no longer touches every item
+        assert_eq!(diff(&ints(&[1, 2, 3]), &ints(&[9, 1, 2, 3])).to_string(), "+ [0]: 9");
+        assert_eq!(diff(&ints(&[1, 2, 3]), &ints(&[2, 3])).to_string(), "- [0]: 1");
+        // Edited items are still diffed in place
+        let edited = GValue::list(vec![doc("a"), doc("B"), doc("c"), doc("d")]);
+        let patch = diff(&before, &edited);
+        assert_eq!(patch.len(), 2);
+        assert!(patch.iter().all(|op| matches!(op, PatchOp::Change { .. })));
+
+        let positional = diff_with(&before, &after, DiffOpts::positional());
+        assert_eq!(positional.len(), 8);
+    }
+
+    #[test]
+    fn test_key_moves_and_copies() {
+        let cfg = GValue::build_map().str("model", "large").int("tokens", 8000).finish();
+        let before = GValue::build_map().value("draft_config", cfg.clone()).int("a", 1).finish();
+        let after = GValue::build_map().value("config", cfg.clone()).int("b", 1).finish();
+        let patch = diff(&before, &after);
+        // Small values aren't worth a reference
+        assert_eq!(patch.to_string(), "- a: 1\n> draft_config -> config\n+ b: 1");
+        let mut v = before.clone();
+        patch.apply(&mut v).unwrap();
+        assert!(equal_loose(&v, &after).unwrap());
+
+        let before = GValue::build_map().value("config", cfg.clone()).finish();
+        let after = GValue::build_map().value("config", cfg.clone()).value("backup", cfg.clone()).finish();
+        assert_eq!(diff(&before, &after).to_string(), "+ backup: {model=large tokens=8000}");
+        let opts = DiffOpts {
+            detect_copies: true,
+            ..DiffOpts::default()
+        };
+        let patch = diff_with(&before, &after, opts);
+        assert_eq!(patch.to_string(), "& config -> backup");
+        let mut v = before.clone();
+        patch.apply(&mut v).unwrap();
+        assert!(equal_loose(&v, &after).unwrap());
+
+        let back = GlyphPatch::from_value(&patch.to_value().to_string().parse().unwrap()).unwrap();
+        assert_eq!(back, patch);
+    }
+
+    #[test]
+    fn test_move_failures() {
+        let mut v = GValue::build_map().int("a", 1).finish();
+        let mv = GlyphPatch {
+            ops: vec![PatchOp::Move {
+                from: GlyphPath::parse("a").unwrap(),
+                path: GlyphPath::parse("missing.b").unwrap(),
+            }],
+        };
+        assert!(mv.apply(&mut v).is_err());
+        // A failed move leaves the source in place
+        assert_eq!(v.to_string(), "{a=1}");
+        let skipped = apply_patch(&mut v, &mv, PatchMode::Lenient).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(v.to_string(), "{a=1}");
+    }
+
+    #[test]
+    fn test_diff_replays_over_many_edits() {
+        let lists: [&[i64]; 9] = [
+            &[],
+            &[1],
+            &[1, 2, 3, 4],
+            &[4, 3, 2, 1],
+            &[2, 3, 4],
+            &[1, 1, 2, 2],
+            &[5, 1, 6, 3],
+            &[3, 1, 4, 1, 5],
+            &[9, 8, 7],
+        ];
+        for a in lists {
+            for b in lists {
+                for opts in [DiffOpts::default(), DiffOpts::positional()] {
+                    let (x, y) = (ints(a), ints(b));
+                    let mut v = x.clone();
+                    diff_with(&x, &y, opts).apply(&mut v).unwrap();
+                    assert_eq!(v, y, "{:?} -> {:?}", a, b);
+                }
+            }
+        }
+
+        // Key moves inside lists that are themselves reordered
+        let item = |k: &str, n: i64| {
+            GValue::build_map().value(k, GValue::build_map().int("n", n).str("pad", "xxxxxxxxxxxx").finish()).finish()
+        };
+        let before = GValue::list(vec![item("a", 1), item("b", 2), item("c", 3)]);
+        let after = GValue::list(vec![item("c", 3), item("x", 1), item("b", 2)]);
+        let opts = DiffOpts {
+            detect_copies: true,
+            ..DiffOpts::default()
+        };
+        let patch = diff_with(&before, &after, opts);
+        let mut v = before.clone();
+        patch.apply(&mut v).unwrap();
+        assert!(equal_loose(&v, &after).unwrap(), "{}", patch);
+    }
+
+    fn task(id: i64, status: &str, owner: &str) -> GValue {
+        GValue::build_map().int("id", id).str("status", status).str("owner", owner).finish()
+    }
+
+    #[test]
+    fn test_diff_rows() {
+        let before = GValue::list(vec![task(1, "todo", "al"), task(2, "todo", "al"), task(3, "done", "bo")]);
+        let after = GValue::list(vec![task(2, "done", "bo"), task(1, "todo", "al"), task(7, "todo", "cy")]);
+        let rows = diff_rows(&before, &after, "id").unwrap();
+        assert_eq!(
+            rows.to_string(),
+            "~ id=2: status todo -> done, owner al -> bo\n\
+             + id=7: {id=7 owner=cy status=todo}\n\
+             - id=3"
+        );
+        assert_eq!(rows.changed[0].key, GValue::int(2));
+        assert_eq!(rows.changed[0].cells[0].old, GValue::str("todo"));
+        assert!(diff_rows(&before, &before, "id").unwrap().is_empty());
+
+        // Tables from parsed text diff the same way, and missing cells are null
+        let text = GValue::list(vec![task(1, "todo", "al"), task(2, "done", "bo"), task(3, "done", "bo")]).to_string();
+        let table = parse_loose_with(&text, ParseOpts { tables: true }).unwrap();
+        assert!(matches!(table, GValue::Table(_)));
+        let mut sparse = task(3, "done", "bo");
+        sparse.remove("owner").unwrap();
+        let after = GValue::list(vec![task(1, "todo", "al"), task(2, "done", "bo"), sparse]);
+        assert_eq!(diff_rows(&table, &after, "id").unwrap().to_string(), "~ id=3: owner bo -> _");
+    }
+
+    #[test]
+    fn test_diff_rows_errors() {
+        let rows = GValue::list(vec![task(1, "todo", "al"), task(1, "done", "al")]);
+        let err = diff_rows(&rows, &GValue::list(vec![]), "id").unwrap_err();
+        assert!(err.to_string().contains("duplicate id 1"), "{}", err);
+        assert!(matches!(
+            diff_rows(&GValue::list(vec![task(1, "a", "b")]), &GValue::list(vec![]), "name"),
+            Err(GlyphError::MissingField(_))
+        ));
+        assert!(matches!(
+            diff_rows(&GValue::int(1), &GValue::list(vec![]), "id"),
+            Err(GlyphError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_glyph_text_round_trip() {
+        let before = call("rust", &["a", "b", "c"], Some(("stale", GValue::bool(true))));
+        let mut after = call("go", &["c", "a"], Some(("limit", GValue::int(5))));
+        after.insert("rows", GValue::list(vec![task(1, "a", "b"), task(2, "a", "b"), task(3, "a", "b")])).unwrap();
+        let cfg = GValue::build_map().str("model", "large").int("tokens", 8000).finish();
+        let mut before = before;
+        before.insert("cfg", cfg.clone()).unwrap();
+        after.insert("cfg", cfg.clone()).unwrap();
+        after.insert("copy", cfg).unwrap();
+        let opts = DiffOpts {
+            detect_copies: true,
+            ..DiffOpts::default()
+        };
+        let patch = diff_with(&before, &after, opts);
+        let glyph = patch.to_glyph();
+        let tags: Vec<&str> = glyph.as_list().unwrap().iter().map(|op| op.as_sum().unwrap().tag.as_str()).collect();
+        assert_eq!(tags, ["set", "mov", "del", "del", "ins", "ins", "cpy"]);
+
+        // Tabular values inside ops come back as lists
+        let text = patch.to_text().unwrap();
+        assert!(text.contains("@tab"), "{}", text);
+        let back: GlyphPatch = text.parse().unwrap();
+        // Equal up to key order inside the rows
+        assert!(equal_loose(&back.to_glyph(), &glyph).unwrap());
+        assert_eq!(back.to_text().unwrap(), text);
+        assert_eq!(back.fingerprint().unwrap(), patch.fingerprint().unwrap());
+
+        let mut v = before.clone();
+        back.apply(&mut v).unwrap();
+        assert!(equal_loose(&v, &after).unwrap());
+    }
+
+    #[test]
+    fn test_glyph_text_by_hand() {
+        let patch = GlyphPatch::from_text(r#"[set({path="args.query" value=go}) del({path=stale}) ins({path="args.tags[0]" value=x})]"#).unwrap();
+        assert_eq!(patch.to_string(), "~ args.query: _ -> go\n- stale: _\n+ args.tags[0]: x");
+        let mut v = call("rust", &["a"], Some(("stale", GValue::bool(true))));
+        patch.apply(&mut v).unwrap();
+        assert_eq!(v.to_string(), "{args={query=go tags=[x a]} tool=search}");
+
+        for bad in ["[frob({path=a})]", "[set({value=1})]", "[set({path=a})]", "[del()]", "{x=1}"] {
+            assert!(GlyphPatch::from_text(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,716 @@
+//! Paths into GValue trees
+//!
+//! A path addresses a node by a sequence of map/struct keys and list indices,
+//! written as `results[3].payload`. Keys that are not plain identifiers are
+//! quoted: `meta."content-type"` or `meta["a.b"]`. The root path is `$`, and a
+//! leading `$` / `$.` is accepted when parsing.
+
+use crate::error::GlyphError;
+use crate::types::{GValue, TableValue};
+use std::fmt;
+use std::str::FromStr;
+
+/// A single step in a path
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathSegment {
+    /// Map key, struct field, or sum tag
+    Key(String),
+    /// List index
+    Index(usize),
+}
+
+/// Location of a node inside a GValue tree
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GlyphPath {
+    segments: Vec<PathSegment>,
+}
+
+impl GlyphPath {
+    /// The root path (`$`)
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Build a path from segments
+    pub fn from_segments(segments: Vec<PathSegment>) -> Self {
+        Self { segments }
+    }
+
+    /// Parse a path such as `a.b[0]."c.d"`
+    pub fn parse(s: &str) -> Result<Self, GlyphError> {
+        let segments = PathParser::new(s, false).parse()?;
+        Ok(Self {
+            segments: segments
+                .into_iter()
+                .filter_map(|seg| match seg {
+                    PatternSegment::Key(k) => Some(PathSegment::Key(k)),
+                    PatternSegment::Index(i) => Some(PathSegment::Index(i)),
+                    // Not produced without wildcards
+                    PatternSegment::AnyKey | PatternSegment::AnyIndex => None,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Append a key segment in place
+    pub fn push_key(&mut self, key: impl Into<String>) {
+        self.segments.push(PathSegment::Key(key.into()));
+    }
+
+    /// Append an index segment in place
+    pub fn push_index(&mut self, idx: usize) {
+        self.segments.push(PathSegment::Index(idx));
+    }
+
+    /// Remove and return the last segment
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+
+    /// New path with a key segment appended
+    pub fn key(&self, key: impl Into<String>) -> Self {
+        let mut p = self.clone();
+        p.push_key(key);
+        p
+    }
+
+    /// New path with an index segment appended
+    pub fn index(&self, idx: usize) -> Self {
+        let mut p = self.clone();
+        p.push_index(idx);
+        p
+    }
+
+    /// Path without its last segment (`None` for the root)
+    pub fn parent(&self) -> Option<Self> {
+        if self.segments.is_empty() {
+            return None;
+        }
+        Some(Self {
+            segments: self.segments[..self.segments.len() - 1].to_vec(),
+        })
+    }
+
+    /// Last segment, if any
+    pub fn last(&self) -> Option<&PathSegment> {
+        self.segments.last()
+    }
+
+    /// Check whether `self` is `other` or one of its ancestors
+    pub fn is_prefix_of(&self, other: &GlyphPath) -> bool {
+        other.segments.starts_with(&self.segments)
+    }
+}
+
+impl From<Vec<PathSegment>> for GlyphPath {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Self { segments }
+    }
+}
+
+impl FromStr for GlyphPath {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GlyphPath::parse(s)
+    }
+}
+
+impl fmt::Display for GlyphPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str("$");
+        }
+        for (i, seg) in self.segments.iter().enumerate() {
+            match seg {
+                PathSegment::Key(k) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    write_path_key(f, k)?;
+                }
+                PathSegment::Index(idx) => write!(f, "[{}]", idx)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check if a key can appear unquoted in a path
+fn is_plain_key(k: &str) -> bool {
+    !k.is_empty()
+        && k != "$"
+        && k.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+fn write_path_key(f: &mut fmt::Formatter<'_>, k: &str) -> fmt::Result {
+    if is_plain_key(k) {
+        return f.write_str(k);
+    }
+    f.write_str("\"")?;
+    for c in k.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+// ============================================================
+// Path patterns
+// ============================================================
+
+/// A single step in a path pattern
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PatternSegment {
+    Key(String),
+    Index(usize),
+    /// `*`: any map key, struct field, or sum tag
+    AnyKey,
+    /// `[*]`: any list index
+    AnyIndex,
+}
+
+impl PatternSegment {
+    fn matches(&self, seg: &PathSegment) -> bool {
+        match (self, seg) {
+            (Self::Key(a), PathSegment::Key(b)) => a == b,
+            (Self::Index(a), PathSegment::Index(b)) => a == b,
+            (Self::AnyKey, PathSegment::Key(_)) | (Self::AnyIndex, PathSegment::Index(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A path with wildcards, such as `results[*].tags` or `$.*.items`.
+///
+/// An unquoted `*` matches any key and `[*]` any index; `"*"` is a literal key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PathPattern {
+    segments: Vec<PatternSegment>,
+}
+
+impl PathPattern {
+    /// Parse a pattern
+    pub fn parse(s: &str) -> Result<Self, GlyphError> {
+        Ok(Self {
+            segments: PathParser::new(s, true).parse()?,
+        })
+    }
+
+    pub fn segments(&self) -> &[PatternSegment] {
+        &self.segments
+    }
+
+    /// Check whether the pattern matches `path` exactly
+    pub fn matches(&self, path: &GlyphPath) -> bool {
+        self.segments.len() == path.len() && self.matches_prefix_of(path)
+    }
+
+    /// Check whether the pattern matches `path` or one of its ancestors
+    pub fn matches_prefix_of(&self, path: &GlyphPath) -> bool {
+        self.segments.len() <= path.len() && self.segments.iter().zip(path.segments()).all(|(p, s)| p.matches(s))
+    }
+
+    /// Check whether the pattern could match something below `path`
+    pub fn matches_below(&self, path: &GlyphPath) -> bool {
+        self.segments.len() > path.len() && self.segments.iter().zip(path.segments()).all(|(p, s)| p.matches(s))
+    }
+}
+
+impl From<GlyphPath> for PathPattern {
+    fn from(path: GlyphPath) -> Self {
+        Self {
+            segments: path
+                .segments
+                .into_iter()
+                .map(|seg| match seg {
+                    PathSegment::Key(k) => PatternSegment::Key(k),
+                    PathSegment::Index(i) => PatternSegment::Index(i),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PathPattern::parse(s)
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str("$");
+        }
+        for (i, seg) in self.segments.iter().enumerate() {
+            match seg {
+                PatternSegment::Key(_) | PatternSegment::AnyKey if i > 0 => f.write_str(".")?,
+                _ => {}
+            }
+            match seg {
+                PatternSegment::Key(k) if k == "*" => f.write_str("\"*\"")?,
+                PatternSegment::Key(k) => write_path_key(f, k)?,
+                PatternSegment::Index(idx) => write!(f, "[{}]", idx)?,
+                PatternSegment::AnyKey => f.write_str("*")?,
+                PatternSegment::AnyIndex => f.write_str("[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================
+// Path parsing
+// ============================================================
+
+struct PathParser<'a> {
+    src: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    /// Accept `*` and `[*]` wildcards
+    wildcards: bool,
+}
+
+impl<'a> PathParser<'a> {
+    fn new(src: &'a str, wildcards: bool) -> Self {
+        Self {
+            src,
+            chars: src.char_indices().peekable(),
+            wildcards,
+        }
+    }
+
+    fn err(&self, msg: &str) -> GlyphError {
+        GlyphError::Parse(format!("invalid path {:?}: {}", self.src, msg))
+    }
+
+    fn parse(mut self) -> Result<Vec<PatternSegment>, GlyphError> {
+        let mut path = Vec::new();
+
+        if let Some(&(_, '$')) = self.chars.peek() {
+            self.chars.next();
+            if let Some(&(_, '.')) = self.chars.peek() {
+                self.chars.next();
+            }
+        }
+
+        let mut expect_key = true;
+        while let Some(&(_, c)) = self.chars.peek() {
+            match c {
+                '[' => {
+                    self.chars.next();
+                    path.push(self.parse_bracket()?);
+                    expect_key = false;
+                }
+                '.' if !expect_key => {
+                    self.chars.next();
+                    expect_key = true;
+                }
+                '"' if expect_key => {
+                    self.chars.next();
+                    path.push(PatternSegment::Key(self.parse_quoted()?));
+                    expect_key = false;
+                }
+                _ if expect_key => {
+                    let key = self.parse_plain()?;
+                    path.push(if self.wildcards && key == "*" {
+                        PatternSegment::AnyKey
+                    } else {
+                        PatternSegment::Key(key)
+                    });
+                    expect_key = false;
+                }
+                _ => return Err(self.err(&format!("unexpected {:?}", c))),
+            }
+        }
+
+        if expect_key && !path.is_empty() {
+            return Err(self.err("trailing '.'"));
+        }
+        Ok(path)
+    }
+
+    fn parse_plain(&mut self) -> Result<String, GlyphError> {
+        let mut key = String::new();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c == '.' || c == '[' {
+                break;
+            }
+            if c == '"' || c == ']' {
+                return Err(self.err(&format!("unexpected {:?}", c)));
+            }
+            key.push(c);
+            self.chars.next();
+        }
+        if key.is_empty() {
+            return Err(self.err("empty key"));
+        }
+        Ok(key)
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, GlyphError> {
+        let mut key = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(key),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) => key.push(c),
+                    None => return Err(self.err("unterminated escape")),
+                },
+                Some((_, c)) => key.push(c),
+                None => return Err(self.err("unterminated quoted key")),
+            }
+        }
+    }
+
+    fn parse_bracket(&mut self) -> Result<PatternSegment, GlyphError> {
+        let seg = if let Some(&(_, '"')) = self.chars.peek() {
+            self.chars.next();
+            PatternSegment::Key(self.parse_quoted()?)
+        } else if self.wildcards && matches!(self.chars.peek(), Some(&(_, '*'))) {
+            self.chars.next();
+            PatternSegment::AnyIndex
+        } else {
+            let mut digits = String::new();
+            while let Some(&(_, c)) = self.chars.peek() {
+                if c == ']' {
+                    break;
+                }
+                digits.push(c);
+                self.chars.next();
+            }
+            let idx = digits
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| self.err(&format!("bad index {:?}", digits)))?;
+            PatternSegment::Index(idx)
+        };
+        match self.chars.next() {
+            Some((_, ']')) => Ok(seg),
+            _ => Err(self.err("missing ']'")),
+        }
+    }
+}
+
+// ============================================================
+// Access by path
+// ============================================================
+
+impl GValue {
+    /// Child one step down: a map/struct key (first match), list index, or
+    /// the value of a sum with that tag. A table row has no node, so an
+    /// index into a table finds nothing here; `at` steps through to the cell.
+    pub fn child(&self, seg: &PathSegment) -> Option<&GValue> {
+        match (self, seg) {
+            (GValue::List(items), PathSegment::Index(i)) => items.get(*i),
+            (GValue::Map(entries), PathSegment::Key(k)) => entries.iter().find(|e| e.key == *k).map(|e| &e.value),
+            (GValue::Struct(s), PathSegment::Key(k)) => s.fields.iter().find(|e| e.key == *k).map(|e| &e.value),
+            (GValue::Sum(s), PathSegment::Key(k)) if &s.tag == k => s.value.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// `child` for editing. Indexing into a table turns it into its list of
+    /// row maps first, so rows can be reached like list items.
+    pub fn child_mut(&mut self, seg: &PathSegment) -> Option<&mut GValue> {
+        if let (GValue::Table(t), PathSegment::Index(i)) = (&*self, seg) {
+            if *i < t.rows.len() {
+                self.expand_table();
+            }
+        }
+        match (self, seg) {
+            (GValue::List(items), PathSegment::Index(i)) => items.get_mut(*i),
+            (GValue::Map(entries), PathSegment::Key(k)) => {
+                entries.iter_mut().find(|e| e.key == *k).map(|e| &mut e.value)
+            }
+            (GValue::Struct(s), PathSegment::Key(k)) => {
+                s.fields.iter_mut().find(|e| e.key == *k).map(|e| &mut e.value)
+            }
+            (GValue::Sum(s), PathSegment::Key(k)) if &s.tag == k => s.value.as_deref_mut(),
+            _ => None,
+        }
+    }
+
+    /// Node at `path`. A path through a table row reaches its cells, as
+    /// `rows[i].column`; the row itself has no node, so a path ending there
+    /// finds nothing (`TableValue::row` builds it).
+    pub fn at(&self, path: &GlyphPath) -> Option<&GValue> {
+        let mut node = self;
+        let mut segs = path.segments().iter();
+        while let Some(seg) = segs.next() {
+            node = match (node, seg) {
+                (GValue::Table(t), PathSegment::Index(i)) => match segs.next()? {
+                    PathSegment::Key(col) => table_cell(t, *i, col)?,
+                    PathSegment::Index(_) => return None,
+                },
+                _ => node.child(seg)?,
+            };
+        }
+        Some(node)
+    }
+
+    /// `at` for editing; tables on the way are expanded as by `child_mut`
+    pub fn at_mut(&mut self, path: &GlyphPath) -> Option<&mut GValue> {
+        path.segments().iter().try_fold(self, |node, seg| node.child_mut(seg))
+    }
+
+    /// Node at a path such as `a.b[0].c` or `meta."content-type"`.
+    ///
+    /// `None` if nothing is there or `path` does not parse; use
+    /// `GlyphPath::parse` with `at` to tell the two apart.
+    pub fn get_path(&self, path: &str) -> Option<&GValue> {
+        self.at(&GlyphPath::parse(path).ok()?)
+    }
+
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut GValue> {
+        self.at_mut(&GlyphPath::parse(path).ok()?)
+    }
+
+    /// `get_path`, with errors naming the path: `MissingField("args.query")`,
+    /// or `TypeMismatch` with the path of the node that could not be entered
+    pub fn try_get_path(&self, path: &str) -> Result<&GValue, GlyphError> {
+        let path = GlyphPath::parse(path)?;
+        let missing = |end: usize| GlyphError::MissingField(GlyphPath::from_segments(path.segments()[..end].to_vec()).to_string());
+        let mut node = self;
+        let mut segs = path.segments().iter().enumerate();
+        while let Some((i, seg)) = segs.next() {
+            let here = || GlyphPath::from_segments(path.segments()[..i].to_vec());
+            // A row has no node: step through it to the cell, as `at` does
+            if let (GValue::Table(t), PathSegment::Index(row)) = (node, seg) {
+                node = match segs.next() {
+                    Some((_, PathSegment::Key(col))) => table_cell(t, *row, col).ok_or_else(|| missing(i + 2))?,
+                    _ => {
+                        return Err(GlyphError::TypeMismatch {
+                            expected: format!("column of table row at {}", GlyphPath::from_segments(path.segments()[..=i].to_vec())),
+                            got: node.gtype().to_string(),
+                        })
+                    }
+                };
+                continue;
+            }
+            let container = matches!(
+                (node, seg),
+                (GValue::List(_), PathSegment::Index(_))
+                    | (GValue::Map(_) | GValue::Struct(_) | GValue::Sum(_), PathSegment::Key(_))
+            );
+            if !container {
+                let expected = match seg {
+                    PathSegment::Key(_) => "map or struct",
+                    PathSegment::Index(_) => "list",
+                };
+                return Err(GlyphError::TypeMismatch {
+                    expected: format!("{} at {}", expected, here()),
+                    got: node.gtype().to_string(),
+                });
+            }
+            node = node.child(seg).ok_or_else(|| missing(i + 1))?;
+        }
+        Ok(node)
+    }
+
+    /// Set the node at `path`, returning the old value.
+    ///
+    /// The parent must exist. A missing last key is added to its map or
+    /// struct; list indices must be in range (use `push` to append). Tables
+    /// along the path, or holding the last index, become their row lists.
+    pub fn set_path(&mut self, path: &str, value: GValue) -> Result<Option<GValue>, GlyphError> {
+        let path = GlyphPath::parse(path)?;
+        let (Some(last), Some(parent_path)) = (path.last(), path.parent()) else {
+            return Ok(Some(std::mem::replace(self, value)));
+        };
+        let parent = self
+            .at_mut(&parent_path)
+            .ok_or_else(|| GlyphError::InvalidValue(format!("no value at {}", parent_path)))?;
+        if let PathSegment::Index(_) = last {
+            parent.expand_table();
+        }
+        match (parent, last) {
+            (GValue::Sum(s), PathSegment::Key(k)) if &s.tag == k => {
+                Ok(s.value.replace(Box::new(value)).map(|old| *old))
+            }
+            (parent, PathSegment::Key(k)) => parent.insert(k.clone(), value),
+            (GValue::List(items), PathSegment::Index(i)) => match items.get_mut(*i) {
+                Some(slot) => Ok(Some(std::mem::replace(slot, value))),
+                None => Err(GlyphError::InvalidValue(format!(
+                    "index {} out of range for list of {} at {}",
+                    i,
+                    items.len(),
+                    parent_path
+                ))),
+            },
+            (other, PathSegment::Index(_)) => Err(GlyphError::TypeMismatch {
+                expected: "list".to_string(),
+                got: other.gtype().to_string(),
+            }),
+        }
+    }
+}
+
+/// Cell `column` of row `row`
+fn table_cell<'a>(t: &'a TableValue, row: usize, column: &str) -> Option<&'a GValue> {
+    t.rows.get(row)?.get(t.column_index(column)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        let p = GlyphPath::parse("results[3].payload").unwrap();
+        assert_eq!(
+            p.segments(),
+            &[
+                PathSegment::Key("results".into()),
+                PathSegment::Index(3),
+                PathSegment::Key("payload".into()),
+            ]
+        );
+        assert_eq!(p.to_string(), "results[3].payload");
+    }
+
+    #[test]
+    fn test_root_forms() {
+        assert!(GlyphPath::parse("").unwrap().is_root());
+        assert!(GlyphPath::parse("$").unwrap().is_root());
+        assert_eq!(GlyphPath::parse("$.a[0]").unwrap().to_string(), "a[0]");
+        assert_eq!(GlyphPath::root().to_string(), "$");
+        assert_eq!(GlyphPath::parse("[1][2]").unwrap().to_string(), "[1][2]");
+    }
+
+    #[test]
+    fn test_get_path() {
+        let mut v = GValue::build_map()
+            .value(
+                "a",
+                GValue::build_map()
+                    .value("b", GValue::list(vec![GValue::build_map().int("c", 7).finish()]))
+                    .str("x.y", "dotted")
+                    .finish(),
+            )
+            .value("ok", GValue::Sum(crate::types::SumValue::new("Ok", Some(GValue::int(1)))))
+            .finish();
+        assert_eq!(v.get_path("a.b[0].c"), Some(&GValue::int(7)));
+        assert_eq!(v.get_path(r#"a."x.y""#), Some(&GValue::str("dotted")));
+        assert_eq!(v.get_path(r#"$.a["x.y"]"#), Some(&GValue::str("dotted")));
+        assert_eq!(v.get_path("ok.Ok"), Some(&GValue::int(1)));
+        assert_eq!(v.get_path(""), Some(&v.clone()));
+        assert_eq!(v.get_path("a.b[1]"), None);
+        assert_eq!(v.get_path("a.x.y"), None);
+        assert_eq!(v.get_path("a.b[0].c.d"), None);
+        assert_eq!(v.get_path("a.b["), None);
+
+        *v.get_path_mut("a.b[0].c").unwrap() = GValue::int(8);
+        assert_eq!(v.get_path("a.b[0].c"), Some(&GValue::int(8)));
+        assert!(v.get_path_mut("a.nope").is_none());
+    }
+
+    #[test]
+    fn test_paths_read_table_cells() {
+        let t = GValue::table(["id", "tags"], vec![vec![GValue::int(1), GValue::list(vec![GValue::str("x")])]]);
+        let mut v = GValue::build_map().value("rows", t).finish();
+        assert_eq!(v.get_path("rows[0].id"), Some(&GValue::int(1)));
+        assert_eq!(v.get_path("rows[0].tags[0]"), Some(&GValue::str("x")));
+        assert_eq!(v.try_get_path("rows[0].id").unwrap(), &GValue::int(1));
+        assert_eq!(v.get_path("rows[0].nope"), None);
+        assert_eq!(v.get_path("rows[1].id"), None);
+        assert!(matches!(v.try_get_path("rows[1].id"), Err(GlyphError::MissingField(p)) if p == "rows[1].id"));
+
+        // A row has no node to borrow; editing through one expands the table
+        assert_eq!(v.get_path("rows[0]"), None);
+        assert!(matches!(v.try_get_path("rows[0]"), Err(GlyphError::TypeMismatch { .. })));
+        for path in ["rows[0].id", "rows[0].tags[0]"] {
+            let read = v.get_path(path).cloned();
+            assert_eq!(v.get_path_mut(path).map(|n| n.clone()), read);
+        }
+        assert_eq!(v.get_path("rows[0]"), Some(&GValue::build_map().int("id", 1).value("tags", GValue::list(vec![GValue::str("x")])).finish()));
+    }
+
+    #[test]
+    fn test_mutation_reaches_into_tables() {
+        let text = "{rows=@tab _ rows=3 cols=2 [id s]\n|1|a|\n|2|b|\n|3|c|\n@end}";
+        let mut v = crate::parse::parse_loose_with(text, crate::parse::ParseOpts { tables: true }).unwrap();
+        assert!(v.get("rows").unwrap().is_table());
+
+        assert_eq!(v.set_path("rows[0].s", GValue::str("z")).unwrap(), Some(GValue::str("a")));
+        assert!(v.get("rows").unwrap().is_list());
+        assert_eq!(v.get_path("rows[0].s"), Some(&GValue::str("z")));
+
+        let mut v = crate::parse::parse_loose_with(text, crate::parse::ParseOpts { tables: true }).unwrap();
+        v.set_path("rows[2]", GValue::int(0)).unwrap();
+        assert_eq!(v.get_path("rows[2]"), Some(&GValue::int(0)));
+
+        let mut v = crate::parse::parse_loose_with(text, crate::parse::ParseOpts { tables: true }).unwrap();
+        v.get_path_mut("rows").unwrap().push(GValue::build_map().int("id", 4).str("s", "d").finish()).unwrap();
+        assert_eq!(v.get_path("rows[3].id"), Some(&GValue::int(4)));
+
+        // A miss leaves the table as it is
+        let mut v = crate::parse::parse_loose_with(text, crate::parse::ParseOpts { tables: true }).unwrap();
+        assert!(v.get_path_mut("rows[9]").is_none());
+        assert!(v.get("rows").unwrap().is_table());
+    }
+
+    #[test]
+    fn test_quoted_keys() {
+        let p = GlyphPath::parse(r#"meta."a.b"["c d"].e"#).unwrap();
+        assert_eq!(p.segments()[1], PathSegment::Key("a.b".into()));
+        assert_eq!(p.segments()[2], PathSegment::Key("c d".into()));
+        assert_eq!(p.to_string(), r#"meta."a.b"."c d".e"#);
+        assert_eq!(GlyphPath::parse(&p.to_string()).unwrap(), p);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in ["a.", "a..b", "a[x]", "a[1", "\"open", "a]"] {
+            assert!(GlyphPath::parse(bad).is_err(), "{:?} should fail", bad);
+        }
+    }
+
+    #[test]
+    fn test_prefix_and_parent() {
+        let p = GlyphPath::parse("a.b[1]").unwrap();
+        assert_eq!(p.parent().unwrap().to_string(), "a.b");
+        assert!(GlyphPath::parse("a").unwrap().is_prefix_of(&p));
+        assert!(!GlyphPath::parse("b").unwrap().is_prefix_of(&p));
+        assert!(GlyphPath::root().parent().is_none());
+    }
+
+    #[test]
+    fn test_patterns() {
+        let p = PathPattern::parse("$.results[*].*").unwrap();
+        assert_eq!(p.segments()[1], PatternSegment::AnyIndex);
+        assert_eq!(p.to_string(), "results[*].*");
+        assert!(p.matches(&GlyphPath::parse("results[4].tags").unwrap()));
+        assert!(!p.matches(&GlyphPath::parse("results[4]").unwrap()));
+        assert!(p.matches_prefix_of(&GlyphPath::parse("results[4].tags[0]").unwrap()));
+        assert!(!p.matches_prefix_of(&GlyphPath::parse("metadata.tags").unwrap()));
+
+        // Quoted "*" is a literal key; plain paths reject wildcards
+        let lit = PathPattern::parse(r#"a."*""#).unwrap();
+        assert!(!lit.matches(&GlyphPath::parse("a.b").unwrap()));
+        assert_eq!(PathPattern::parse(&lit.to_string()).unwrap(), lit);
+        assert!(GlyphPath::parse("a[*]").is_err());
+        assert_eq!(PathPattern::from(GlyphPath::parse("a[0]").unwrap()).to_string(), "a[0]");
+    }
+}
@@ -0,0 +1,620 @@
+//! Declarative transformation pipelines
+//!
+//! A `Pipeline` bundles the usual pre-emission transforms (project, redact,
+//! normalize keys, truncate) with canonicalization. Steps always run in that
+//! order, whatever order the builder methods were called in, so every service
+//! using the same pipeline produces the same output.
+
+use crate::error::GlyphError;
+use crate::loose::*;
+use crate::path::{GlyphPath, PathPattern, PathSegment};
+use crate::redact::RedactStyle;
+use crate::types::*;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A pipeline step, in execution order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PipelineStep {
+    Project,
+    Redact,
+    NormalizeKeys,
+    Truncate,
+    Canonicalize,
+}
+
+impl fmt::Display for PipelineStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PipelineStep::Project => "project",
+            PipelineStep::Redact => "redact",
+            PipelineStep::NormalizeKeys => "normalize_keys",
+            PipelineStep::Truncate => "truncate",
+            PipelineStep::Canonicalize => "canonicalize",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Key spelling produced by the normalize-keys step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCase {
+    /// `userid`
+    Lower,
+    /// `user_id`
+    Snake,
+    /// `userId`
+    Camel,
+}
+
+/// Metrics for one executed step
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepMetrics {
+    pub step: PipelineStep,
+    pub elapsed: Duration,
+    /// Nodes in the value after the step
+    pub nodes: usize,
+    /// Nodes removed, redacted, renamed, or truncated by the step
+    pub changed: usize,
+}
+
+/// Metrics for one pipeline run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineMetrics {
+    pub steps: Vec<StepMetrics>,
+    pub output_bytes: usize,
+}
+
+impl PipelineMetrics {
+    /// Total time across all steps
+    pub fn total(&self) -> Duration {
+        self.steps.iter().map(|s| s.elapsed).sum()
+    }
+
+    /// Metrics for one step, if it ran
+    pub fn step(&self, step: PipelineStep) -> Option<&StepMetrics> {
+        self.steps.iter().find(|s| s.step == step)
+    }
+}
+
+/// Reusable project → redact → normalize-keys → truncate → canonicalize chain
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    project: Option<Vec<GlyphPath>>,
+    redact: Option<(Vec<String>, GValue)>,
+    redact_paths: Option<(Vec<PathPattern>, RedactStyle)>,
+    key_case: Option<KeyCase>,
+    truncate: Option<SizeCaps>,
+    opts: LooseCanonOpts,
+}
+
+impl Pipeline {
+    /// Create a pipeline that only canonicalizes, with default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep only the given paths (and their ancestors).
+    ///
+    /// A key segment applied to a list selects that key in every item, so
+    /// `results.id` keeps the `id` of each result.
+    pub fn project<P: Into<GlyphPath>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.project = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Replace the value of every entry named in `keys`, at any depth, with `"[redacted]"`
+    pub fn redact<S: Into<String>>(self, keys: impl IntoIterator<Item = S>) -> Self {
+        self.redact_with(keys, GValue::str("[redacted]"))
+    }
+
+    /// Replace the value of every entry named in `keys`, at any depth, with `replacement`
+    pub fn redact_with<S: Into<String>>(mut self, keys: impl IntoIterator<Item = S>, replacement: GValue) -> Self {
+        self.redact = Some((keys.into_iter().map(Into::into).collect(), replacement));
+        self
+    }
+
+    /// Replace every value whose path matches one of `patterns` as `style`
+    /// says; runs with the key-based redaction
+    pub fn redact_paths(mut self, patterns: impl IntoIterator<Item = PathPattern>, style: RedactStyle) -> Self {
+        self.redact_paths = Some((patterns.into_iter().collect(), style));
+        self
+    }
+
+    /// Rewrite map and struct keys to `case`. On collisions the last entry wins.
+    pub fn normalize_keys(mut self, case: KeyCase) -> Self {
+        self.key_case = Some(case);
+        self
+    }
+
+    /// Replace values over `caps` with `Trunc{...}` placeholders
+    pub fn truncate(mut self, caps: SizeCaps) -> Self {
+        self.truncate = Some(caps);
+        self
+    }
+
+    /// Canonicalization options for the final step
+    pub fn canonicalize(mut self, opts: LooseCanonOpts) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Run the pipeline and return the canonical string
+    pub fn run(&self, v: &GValue) -> Result<String, GlyphError> {
+        self.run_with_metrics(v).map(|(out, _)| out)
+    }
+
+    /// Run the pipeline and return the canonical string with per-step metrics
+    pub fn run_with_metrics(&self, v: &GValue) -> Result<(String, PipelineMetrics), GlyphError> {
+        let mut metrics = PipelineMetrics::default();
+        let mut value = v.clone();
+
+        if let Some(ref paths) = self.project {
+            let start = Instant::now();
+            let before = count_nodes(&value);
+            value = project(&value, paths);
+            let nodes = count_nodes(&value);
+            metrics.push(PipelineStep::Project, start, nodes, before - nodes);
+        }
+        if self.redact.is_some() || self.redact_paths.is_some() {
+            let start = Instant::now();
+            let mut changed = 0;
+            if let Some((ref keys, ref replacement)) = self.redact {
+                changed += redact(&mut value, keys, replacement);
+            }
+            if let Some((ref patterns, ref style)) = self.redact_paths {
+                changed += crate::redact::redact(&mut value, patterns, style)?;
+            }
+            metrics.push(PipelineStep::Redact, start, count_nodes(&value), changed);
+        }
+        if let Some(case) = self.key_case {
+            let start = Instant::now();
+            let changed = normalize_keys(&mut value, case);
+            metrics.push(PipelineStep::NormalizeKeys, start, count_nodes(&value), changed);
+        }
+        if let Some(ref caps) = self.truncate {
+            let start = Instant::now();
+            let changed = truncate(&mut value, caps)?;
+            metrics.push(PipelineStep::Truncate, start, count_nodes(&value), changed);
+        }
+
+        let start = Instant::now();
+        let out = canonicalize_loose_with_opts(&value, &self.opts)?;
+        metrics.push(PipelineStep::Canonicalize, start, count_nodes(&value), 0);
+        metrics.output_bytes = out.len();
+        Ok((out, metrics))
+    }
+}
+
+impl PipelineMetrics {
+    fn push(&mut self, step: PipelineStep, start: Instant, nodes: usize, changed: usize) {
+        self.steps.push(StepMetrics {
+            step,
+            elapsed: start.elapsed(),
+            nodes,
+            changed,
+        });
+    }
+}
+
+// ============================================================
+// Steps
+// ============================================================
+
+fn count_nodes(v: &GValue) -> usize {
+    1 + match v {
+        GValue::List(items) => items.iter().map(count_nodes).sum(),
+        GValue::Map(entries) => entries.iter().map(|e| count_nodes(&e.value)).sum(),
+        GValue::Struct(s) => s.fields.iter().map(|e| count_nodes(&e.value)).sum(),
+        GValue::Sum(s) => s.value.as_deref().map_or(0, count_nodes),
+        GValue::Table(t) => t.rows.iter().flatten().map(count_nodes).sum(),
+        _ => 0,
+    }
+}
+
+fn project(v: &GValue, paths: &[GlyphPath]) -> GValue {
+    let selectors: Vec<&[PathSegment]> = paths.iter().map(|p| p.segments()).collect();
+    project_node(v, &selectors)
+}
+
+/// Keep the parts of `v` selected by the remaining path segments
+fn project_node(v: &GValue, selectors: &[&[PathSegment]]) -> GValue {
+    // A selector that ends here keeps the whole subtree
+    if selectors.iter().any(|s| s.is_empty()) {
+        return v.clone();
+    }
+
+    let keep_entries = |entries: &[MapEntry]| -> Vec<MapEntry> {
+        entries
+            .iter()
+            .filter_map(|e| {
+                let rest = rest_for_key(selectors, &e.key);
+                (!rest.is_empty()).then(|| MapEntry::new(e.key.clone(), project_node(&e.value, &rest)))
+            })
+            .collect()
+    };
+
+    match v {
+        GValue::Map(entries) => GValue::Map(keep_entries(entries)),
+        GValue::Struct(s) => GValue::struct_val(s.type_name.clone(), keep_entries(&s.fields)),
+        GValue::List(items) => {
+            let mut out = Vec::new();
+            for (i, item) in items.iter().enumerate() {
+                let by_index: Vec<&[PathSegment]> = selectors
+                    .iter()
+                    .filter_map(|s| match s.split_first() {
+                        Some((PathSegment::Index(n), rest)) if *n == i => Some(rest),
+                        _ => None,
+                    })
+                    .collect();
+                // Key selectors pass through lists to every item
+                let by_key: Vec<&[PathSegment]> = selectors
+                    .iter()
+                    .filter(|s| matches!(s.first(), Some(PathSegment::Key(_))))
+                    .copied()
+                    .collect();
+                if by_index.is_empty() && by_key.is_empty() {
+                    continue;
+                }
+                let mut item_selectors = by_index;
+                item_selectors.extend(by_key);
+                out.push(project_node(item, &item_selectors));
+            }
+            GValue::List(out)
+        }
+        GValue::Sum(s) => {
+            let rest = rest_for_key(selectors, &s.tag);
+            match s.value {
+                Some(ref inner) if !rest.is_empty() => GValue::sum(s.tag.clone(), Some(project_node(inner, &rest))),
+                _ => GValue::sum(s.tag.clone(), None),
+            }
+        }
+        GValue::Table(t) => {
+            // Per-row selections can't keep a table's shape
+            if selectors.iter().any(|s| matches!(s.first(), Some(PathSegment::Index(_)))) {
+                return project_node(&t.to_list(), selectors);
+            }
+            let kept: Vec<(usize, Vec<&[PathSegment]>)> = t
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i, rest_for_key(selectors, c)))
+                .filter(|(_, rest)| !rest.is_empty())
+                .collect();
+            let columns = kept.iter().map(|(i, _)| t.columns[*i].clone()).collect();
+            let rows = t
+                .rows
+                .iter()
+                .map(|row| kept.iter().map(|(i, rest)| project_node(&row[*i], rest)).collect())
+                .collect();
+            GValue::Table(TableValue::new(columns, rows))
+        }
+        _ => v.clone(),
+    }
+}
+
+fn rest_for_key<'a>(selectors: &[&'a [PathSegment]], key: &str) -> Vec<&'a [PathSegment]> {
+    selectors
+        .iter()
+        .filter_map(|s| match s.split_first() {
+            Some((PathSegment::Key(k), rest)) if k == key => Some(rest),
+            _ => None,
+        })
+        .collect()
+}
+
+fn redact(v: &mut GValue, keys: &[String], replacement: &GValue) -> usize {
+    let mut changed = 0;
+    let mut redact_entries = |entries: &mut Vec<MapEntry>| {
+        for e in entries.iter_mut() {
+            if keys.iter().any(|k| k.as_str() == e.key.as_str()) {
+                e.value = replacement.clone();
+                changed += 1;
+            } else {
+                changed += redact(&mut e.value, keys, replacement);
+            }
+        }
+    };
+    match v {
+        GValue::Map(entries) => redact_entries(entries),
+        GValue::Struct(s) => redact_entries(&mut s.fields),
+        GValue::List(items) => {
+            for item in items {
+                changed += redact(item, keys, replacement);
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(ref mut inner) = s.value {
+                changed += redact(inner, keys, replacement);
+            }
+        }
+        GValue::Table(t) => {
+            for (i, col) in t.columns.iter().enumerate() {
+                for row in t.rows.iter_mut() {
+                    if keys.contains(col) {
+                        row[i] = replacement.clone();
+                        changed += 1;
+                    } else {
+                        changed += redact(&mut row[i], keys, replacement);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+fn normalize_keys(v: &mut GValue, case: KeyCase) -> usize {
+    let mut changed = 0;
+    let mut normalize_entries = |entries: &mut Vec<MapEntry>| {
+        for e in entries.iter_mut() {
+            let key = convert_key(&e.key, case);
+            if key != e.key {
+                e.key.clear();
+                e.key.push_str(&key);
+                changed += 1;
+            }
+            changed += normalize_keys(&mut e.value, case);
+        }
+        // Last wins, keeping the position of the last occurrence
+        let mut seen = std::collections::HashSet::new();
+        let mut kept: Vec<MapEntry> = entries.drain(..).rev().filter(|e| seen.insert(e.key.clone())).collect();
+        kept.reverse();
+        *entries = kept;
+    };
+    match v {
+        GValue::Map(entries) => normalize_entries(entries),
+        GValue::Struct(s) => normalize_entries(&mut s.fields),
+        GValue::List(items) => {
+            for item in items {
+                changed += normalize_keys(item, case);
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(ref mut inner) = s.value {
+                changed += normalize_keys(inner, case);
+            }
+        }
+        GValue::Table(t) => {
+            for col in t.columns.iter_mut() {
+                let key = convert_key(col, case);
+                if key != *col {
+                    *col = key;
+                    changed += 1;
+                }
+            }
+            for cell in t.rows.iter_mut().flatten() {
+                changed += normalize_keys(cell, case);
+            }
+            // Last wins, as for map entries
+            let keep: Vec<bool> = (0..t.columns.len())
+                .map(|i| !t.columns[i + 1..].contains(&t.columns[i]))
+                .collect();
+            let mut flags = keep.iter();
+            t.columns.retain(|_| *flags.next().unwrap_or(&true));
+            for row in t.rows.iter_mut() {
+                let mut flags = keep.iter();
+                row.retain(|_| *flags.next().unwrap_or(&true));
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+/// Split a key into lowercase words at `_`, `-`, spaces, `.`, and lower→upper boundaries
+fn key_words(key: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in key.chars() {
+        if c == '_' || c == '-' || c == ' ' || c == '.' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn convert_key(key: &str, case: KeyCase) -> String {
+    let words = key_words(key);
+    if words.is_empty() {
+        return key.to_string();
+    }
+    match case {
+        KeyCase::Lower => words.concat(),
+        KeyCase::Snake => words.join("_"),
+        KeyCase::Camel => {
+            let mut out = words[0].clone();
+            for w in &words[1..] {
+                let mut chars = w.chars();
+                if let Some(first) = chars.next() {
+                    out.extend(first.to_uppercase());
+                    out.push_str(chars.as_str());
+                }
+            }
+            out
+        }
+    }
+}
+
+fn truncate(v: &mut GValue, caps: &SizeCaps) -> Result<usize, GlyphError> {
+    if let Some((kind, len)) = caps.overflow(v) {
+        *v = trunc_placeholder(kind, len, v)?;
+        return Ok(1);
+    }
+    let mut changed = 0;
+    match v {
+        GValue::Map(entries) => {
+            for e in entries {
+                changed += truncate(&mut e.value, caps)?;
+            }
+        }
+        GValue::Struct(s) => {
+            for e in &mut s.fields {
+                changed += truncate(&mut e.value, caps)?;
+            }
+        }
+        GValue::List(items) => {
+            for item in items {
+                changed += truncate(item, caps)?;
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(ref mut inner) = s.value {
+                changed += truncate(inner, caps)?;
+            }
+        }
+        GValue::Table(t) => {
+            for cell in t.rows.iter_mut().flatten() {
+                changed += truncate(cell, caps)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event() -> GValue {
+        crate::from_json(&json!({
+            "userId": 7,
+            "User Name": "ann",
+            "auth": {"apiKey": "sk-123", "scope": "read"},
+            "results": [
+                {"docId": "a", "body": "x".repeat(40), "score": 1},
+                {"docId": "b", "body": "short", "score": 2}
+            ],
+            "debug": {"trace": [1, 2, 3]}
+        }))
+    }
+
+    fn path(s: &str) -> GlyphPath {
+        GlyphPath::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_pipeline_runs_steps_in_fixed_order() {
+        let caps = SizeCaps {
+            max_string_bytes: Some(16),
+            ..SizeCaps::default()
+        };
+        // Builder call order does not matter
+        let a = Pipeline::new()
+            .truncate(caps)
+            .normalize_keys(KeyCase::Snake)
+            .redact(["apiKey"])
+            .project([path("userId"), path("auth"), path("results.docId"), path("results.body")]);
+        let b = Pipeline::new()
+            .project([path("userId"), path("auth"), path("results.docId"), path("results.body")])
+            .redact(["apiKey"])
+            .normalize_keys(KeyCase::Snake)
+            .truncate(caps);
+
+        let out = a.run(&event()).unwrap();
+        assert_eq!(out, b.run(&event()).unwrap());
+        assert!(out.contains("api_key=\"[redacted]\""), "{}", out);
+        assert!(out.contains("user_id=7"));
+        assert!(out.contains("Trunc{kind=str len=40"));
+        assert!(!out.contains("debug") && !out.contains("score"));
+    }
+
+    #[test]
+    fn test_pipeline_metrics() {
+        let pipeline = Pipeline::new()
+            .redact(["apiKey", "scope"])
+            .normalize_keys(KeyCase::Camel)
+            .canonicalize(LooseCanonOpts::no_tabular());
+        let (out, metrics) = pipeline.run_with_metrics(&event()).unwrap();
+
+        let steps: Vec<_> = metrics.steps.iter().map(|s| s.step).collect();
+        assert_eq!(steps, vec![PipelineStep::Redact, PipelineStep::NormalizeKeys, PipelineStep::Canonicalize]);
+        assert_eq!(metrics.step(PipelineStep::Redact).unwrap().changed, 2);
+        // "User Name" -> "userName"
+        assert_eq!(metrics.step(PipelineStep::NormalizeKeys).unwrap().changed, 1);
+        assert_eq!(metrics.output_bytes, out.len());
+        assert!(metrics.step(PipelineStep::Project).is_none());
+        assert_eq!(out, canonicalize_loose_with_opts(&pipeline_value(), &LooseCanonOpts::no_tabular()).unwrap());
+    }
+
+    fn pipeline_value() -> GValue {
+        let mut v = event();
+        redact(&mut v, &["apiKey".to_string(), "scope".to_string()], &GValue::str("[redacted]"));
+        normalize_keys(&mut v, KeyCase::Camel);
+        v
+    }
+
+    #[test]
+    fn test_pipeline_redact_paths() {
+        let pattern = PathPattern::parse("results[*].body").unwrap();
+        let (out, metrics) = Pipeline::new()
+            .redact(["apiKey"])
+            .redact_paths([pattern], RedactStyle::Null)
+            .run_with_metrics(&event())
+            .unwrap();
+        assert!(out.contains("apiKey=\"[redacted]\""));
+        assert!(out.contains("{body=_ docId=a score=1}"), "{}", out);
+        assert_eq!(metrics.step(PipelineStep::Redact).unwrap().changed, 3);
+    }
+
+    #[test]
+    fn test_project_paths() {
+        let v = event();
+        let out = project(&v, &[path("results[1].docId"), path("auth.scope")]);
+        assert_eq!(
+            canonicalize_loose(&out).unwrap(),
+            "{auth={scope=read} results=[{docId=b}]}"
+        );
+    }
+
+    #[test]
+    fn test_steps_on_tables() {
+        let table = || {
+            GValue::table(
+                ["docId", "apiKey", "doc_id"],
+                vec![
+                    vec![GValue::str("a"), GValue::str("k1"), GValue::int(1)],
+                    vec![GValue::str("b"), GValue::str("k2"), GValue::int(2)],
+                ],
+            )
+        };
+        let out = Pipeline::new().redact(["apiKey"]).normalize_keys(KeyCase::Snake).run(&table()).unwrap();
+        // docId -> doc_id collides with the later doc_id column, which wins
+        assert_eq!(out, "@tab _ rows=2 cols=2 [api_key doc_id]\n|\"[redacted]\"|1|\n|\"[redacted]\"|2|\n@end");
+
+        let projected = project(&table(), &[path("docId")]);
+        assert_eq!(projected, GValue::table(["docId"], vec![vec![GValue::str("a")], vec![GValue::str("b")]]));
+        // Row selectors fall back to row maps
+        let row = project(&table(), &[path("[1].docId")]);
+        assert_eq!(canonicalize_loose(&row).unwrap(), "[{docId=b}]");
+    }
+
+    #[test]
+    fn test_convert_key() {
+        assert_eq!(convert_key("userId", KeyCase::Snake), "user_id");
+        assert_eq!(convert_key("content-type", KeyCase::Camel), "contentType");
+        assert_eq!(convert_key("User Name", KeyCase::Lower), "username");
+        assert_eq!(convert_key("HTTPCode", KeyCase::Snake), "httpcode");
+        assert_eq!(convert_key("__", KeyCase::Snake), "__");
+
+        let mut v = GValue::map(vec![field("a_b", GValue::int(1)), field("aB", GValue::int(2))]);
+        normalize_keys(&mut v, KeyCase::Snake);
+        assert_eq!(canonicalize_loose(&v).unwrap(), "{a_b=2}");
+    }
+}
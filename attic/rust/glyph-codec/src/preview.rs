@@ -0,0 +1,152 @@
+//! Previews of large values
+//!
+//! "Roughly what did that tool return?" doesn't need every row. `preview`
+//! keeps the shape of a value and a representative sample of its content:
+//! the first and last items of long lists with a `"…+42 more"` item between,
+//! the first entries of wide maps with a `"…"` entry counting the rest
+//! (quoted, so it sorts first), strings cut with a `…+N bytes` badge, and
+//! containers below a depth cap summarized as `"{…5 keys}"` or
+//! `"[…12 items]"`.
+//!
+//! A preview is for reading, not round-tripping: markers are plain strings
+//! in the places they stand in for.
+
+use crate::budget::{char_floor, cut_string, more_marker};
+use crate::error::GlyphError;
+use crate::loose::canonicalize_loose;
+use crate::types::*;
+
+/// Key of the map entry counting elided entries
+pub const PREVIEW_MORE_KEY: &str = "…";
+
+/// What `preview` keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreviewOpts {
+    /// Leading list items kept
+    pub head: usize,
+    /// Trailing list items kept
+    pub tail: usize,
+    /// Map entries and struct fields kept, in their original order
+    pub max_entries: usize,
+    /// Bytes of a string, or of a byte string, kept
+    pub max_string: usize,
+    /// Containers this deep are summarized; the root is depth 0
+    pub max_depth: usize,
+}
+
+impl Default for PreviewOpts {
+    fn default() -> Self {
+        Self {
+            head: 3,
+            tail: 1,
+            max_entries: 20,
+            max_string: 80,
+            max_depth: 4,
+        }
+    }
+}
+
+/// A sample of `v` with the same shape; see the module docs
+pub fn preview(v: &GValue, opts: PreviewOpts) -> GValue {
+    preview_node(v, 0, &opts)
+}
+
+/// Canonical text of `preview`
+pub fn preview_text(v: &GValue, opts: PreviewOpts) -> Result<String, GlyphError> {
+    canonicalize_loose(&preview(v, opts))
+}
+
+fn preview_node(v: &GValue, depth: usize, opts: &PreviewOpts) -> GValue {
+    match v {
+        GValue::Str(s) if s.len() > opts.max_string => GValue::str(cut_string(s, char_floor(s, opts.max_string))),
+        // Bytes can't carry a text badge, so a cut byte string becomes
+        // `[b64"..." "…+N bytes"]`
+        GValue::Bytes(b) if b.len() > opts.max_string => GValue::list(vec![
+            GValue::bytes(b[..opts.max_string].to_vec()),
+            GValue::str(format!("…+{} bytes", b.len() - opts.max_string)),
+        ]),
+        GValue::List(items) if depth >= opts.max_depth && !items.is_empty() => {
+            GValue::str(format!("[…{} items]", items.len()))
+        }
+        GValue::Map(entries) if depth >= opts.max_depth && !entries.is_empty() => {
+            GValue::str(format!("{{…{} keys}}", entries.len()))
+        }
+        GValue::Struct(s) if depth >= opts.max_depth && !s.fields.is_empty() => {
+            GValue::str(format!("{}{{…{} fields}}", s.type_name, s.fields.len()))
+        }
+        GValue::Table(t) if depth >= opts.max_depth => GValue::str(format!("[…{} items]", t.rows.len())),
+        GValue::List(items) => GValue::List(sample(items, depth, opts)),
+        GValue::Map(entries) => GValue::Map(sample_entries(entries, depth, opts)),
+        GValue::Struct(s) => GValue::struct_val(s.type_name.clone(), sample_entries(&s.fields, depth, opts)),
+        GValue::Sum(s) => GValue::sum(
+            s.tag.clone(),
+            s.value.as_deref().map(|inner| preview_node(inner, depth + 1, opts)),
+        ),
+        // Elided rows can't be marked inside a table
+        GValue::Table(t) if t.rows.len() > opts.head + opts.tail + 1 => preview_node(&t.to_list(), depth, opts),
+        other => other.clone(),
+    }
+}
+
+fn sample(items: &[GValue], depth: usize, opts: &PreviewOpts) -> Vec<GValue> {
+    let each = |item: &GValue| preview_node(item, depth + 1, opts);
+    // Eliding a single item would save nothing
+    if items.len() <= opts.head + opts.tail + 1 {
+        return items.iter().map(each).collect();
+    }
+    let mut out: Vec<GValue> = items[..opts.head].iter().map(each).collect();
+    out.push(more_marker(items.len() - opts.head - opts.tail));
+    out.extend(items[items.len() - opts.tail..].iter().map(each));
+    out
+}
+
+fn sample_entries(entries: &[MapEntry], depth: usize, opts: &PreviewOpts) -> Vec<MapEntry> {
+    let kept = entries.len().min(opts.max_entries);
+    let mut out: Vec<MapEntry> = entries[..kept]
+        .iter()
+        .map(|e| MapEntry::new(e.key.clone(), preview_node(&e.value, depth + 1, opts)))
+        .collect();
+    if kept < entries.len() {
+        out.push(MapEntry::new(PREVIEW_MORE_KEY, GValue::str(format!("+{} more", entries.len() - kept))));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use serde_json::json;
+
+    #[test]
+    fn test_preview_lists_and_strings() {
+        let v = from_json(&json!({
+            "hits": (0..10).collect::<Vec<_>>(),
+            "log": "x".repeat(100),
+            "few": [1, 2, 3, 4, 5]
+        }));
+        let text = preview_text(&v, PreviewOpts::default()).unwrap();
+        assert_eq!(
+            text,
+            format!("{{few=[1 2 3 4 5] hits=[0 1 2 \"…+6 more\" 9] log=\"{}…+20 bytes\"}}", "x".repeat(80))
+        );
+    }
+
+    #[test]
+    fn test_preview_depth_and_width() {
+        let v = from_json(&json!({"a": {"b": {"c": [1, 2], "d": {}}}, "w": {"k1": 1, "k2": 2, "k3": 3}}));
+        let opts = PreviewOpts {
+            max_depth: 2,
+            max_entries: 2,
+            ..PreviewOpts::default()
+        };
+        assert_eq!(
+            preview_text(&v, opts).unwrap(),
+            "{a={b=\"{…2 keys}\"} w={\"…\"=\"+1 more\" k1=1 k2=2}}"
+        );
+        let rows = GValue::table(["id"], (0..6).map(|i| vec![GValue::int(i)]).collect());
+        assert_eq!(preview_text(&rows, PreviewOpts::default()).unwrap(), "[{id=0} {id=1} {id=2} \"…+2 more\" {id=5}]");
+        let bytes = preview(&GValue::bytes(vec![0; 100]), PreviewOpts::default());
+        assert_eq!(bytes[1], GValue::str("…+20 bytes"));
+    }
+}
@@ -0,0 +1,324 @@
+//! System-prompt snippets that teach a model to write GLYPH
+//!
+//! `FormatPrompt` turns example values, a schema or both into a short
+//! description of the syntax followed by rendered examples. The description
+//! only covers what the examples use: a prompt for flat maps of strings and
+//! numbers doesn't spend tokens on refs, sums or `@tab` blocks.
+//!
+//! ```text
+//! Write values in GLYPH, a compact alternative to JSON:
+//! - Maps: {key=value other=value}, entries separated by spaces, no commas or colons.
+//! ...
+//!
+//! Examples:
+//! search: {limit=5 query="glyph codec"}
+//! ```
+
+use crate::error::GlyphError;
+use crate::loose::canonicalize_loose;
+use crate::schema::*;
+use crate::types::*;
+use chrono::{TimeZone, Utc};
+use std::fmt::Write as _;
+
+/// How deep schema examples follow type references before writing `_`
+const MAX_SAMPLE_DEPTH: usize = 6;
+
+/// Builder for a format description with examples
+#[derive(Debug, Clone, Default)]
+pub struct FormatPrompt {
+    examples: Vec<(Option<String>, GValue)>,
+    schema: Option<Schema>,
+}
+
+impl FormatPrompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an example value
+    pub fn example(mut self, v: GValue) -> Self {
+        self.examples.push((None, v));
+        self
+    }
+
+    /// Add an example shown after `label:`, such as a tool name
+    pub fn labeled(mut self, label: impl Into<String>, v: GValue) -> Self {
+        self.examples.push((Some(label.into()), v));
+        self
+    }
+
+    /// Include the schema's text and one synthesized example per type.
+    /// Examples fill required fields only, from declared defaults, the first
+    /// `enum` value or `min` where there is one.
+    pub fn schema(mut self, schema: &Schema) -> Self {
+        self.schema = Some(schema.clone());
+        self
+    }
+
+    /// Every example, labeled ones with their label, schema examples by type
+    /// name
+    fn all_examples(&self) -> Vec<(Option<String>, GValue)> {
+        let mut out = self.examples.clone();
+        if let Some(schema) = &self.schema {
+            for def in schema.types() {
+                let v = sample_type(schema, def, 0);
+                out.push((Some(def.name.clone()), v));
+            }
+        }
+        out
+    }
+
+    /// The prompt snippet
+    pub fn render(&self) -> Result<String, GlyphError> {
+        let examples = self.all_examples();
+        let mut rendered = Vec::with_capacity(examples.len());
+        let mut used = Used::default();
+        for (label, v) in &examples {
+            used.scan(v);
+            let text = canonicalize_loose(v)?;
+            used.table |= text.contains("@tab ");
+            rendered.push((label, text));
+        }
+
+        let mut out = String::from("Write values in GLYPH, a compact alternative to JSON:\n");
+        for line in used.rules() {
+            out.push_str("- ");
+            out.push_str(line);
+            out.push('\n');
+        }
+        if let Some(schema) = &self.schema {
+            out.push_str("\nTypes:\n");
+            out.push_str(&schema.canonical());
+            out.push('\n');
+        }
+        if !rendered.is_empty() {
+            out.push_str("\nExamples:\n");
+            for (label, text) in rendered {
+                if let Some(label) = label {
+                    let _ = write!(out, "{}: ", label);
+                    // Keep a multi-line @tab block under its label
+                    if text.contains('\n') {
+                        out.push('\n');
+                    }
+                }
+                out.push_str(&text);
+                out.push('\n');
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Prompt snippet for `examples`, unlabeled
+pub fn format_prompt(examples: &[GValue]) -> Result<String, GlyphError> {
+    examples
+        .iter()
+        .fold(FormatPrompt::new(), |p, v| p.example(v.clone()))
+        .render()
+}
+
+/// Prompt snippet for `schema`, with an example per type
+pub fn format_prompt_for_schema(schema: &Schema) -> Result<String, GlyphError> {
+    FormatPrompt::new().schema(schema).render()
+}
+
+/// Which syntax the examples use
+#[derive(Debug, Default)]
+struct Used {
+    null: bool,
+    bool: bool,
+    number: bool,
+    string: bool,
+    bytes: bool,
+    time: bool,
+    id: bool,
+    list: bool,
+    record: bool,
+    sum: bool,
+    table: bool,
+}
+
+impl Used {
+    fn scan(&mut self, v: &GValue) {
+        match v {
+            GValue::Null => self.null = true,
+            GValue::Bool(_) => self.bool = true,
+            GValue::Int(_) | GValue::Float(_) => self.number = true,
+            GValue::Str(_) => self.string = true,
+            GValue::Bytes(_) => self.bytes = true,
+            GValue::Time(_) => self.time = true,
+            GValue::Id(_) => self.id = true,
+            GValue::List(items) => {
+                self.list = true;
+                items.iter().for_each(|item| self.scan(item));
+            }
+            GValue::Map(entries) => entries.iter().for_each(|e| self.scan(&e.value)),
+            GValue::Struct(s) => {
+                self.record = true;
+                s.fields.iter().for_each(|e| self.scan(&e.value));
+            }
+            GValue::Sum(s) => {
+                self.sum = true;
+                if let Some(value) = &s.value {
+                    self.scan(value);
+                }
+            }
+            GValue::Table(t) => {
+                self.table = true;
+                t.rows.iter().flatten().for_each(|cell| self.scan(cell));
+            }
+        }
+    }
+
+    fn rules(&self) -> Vec<&'static str> {
+        let mut rules = vec!["Maps: {key=value other=value}, entries separated by spaces, no commas or \
+             colons."];
+        if self.list {
+            rules.push("Lists: [1 2 3], items separated by spaces.");
+        }
+        if self.string {
+            rules.push(
+                "Strings: bare when a single word of letters, digits and _ not starting with a \
+                 digit (status=done), otherwise double-quoted (query=\"two words\"). Always quote \
+                 strings that read as literals: \"t\", \"f\", \"true\", \"null\", \"none\", \"_\".",
+            );
+        }
+        if self.bool || self.null {
+            rules.push("t and f are true and false; _ is null.");
+        }
+        if self.number {
+            rules.push("Numbers are written as in JSON: 42, -1.5, 1e-7.");
+        }
+        if self.time {
+            rules.push("Times are unquoted RFC 3339 in UTC: 2025-01-01T00:00:00Z.");
+        }
+        if self.id {
+            rules.push("References start with ^: ^user:42.");
+        }
+        if self.bytes {
+            rules.push("Bytes are base64 inside b64\"...\".");
+        }
+        if self.record {
+            rules.push("Typed records put the type name before the braces: Point{x=1 y=2}.");
+        }
+        if self.sum {
+            rules.push("Variants are Tag(value), or Tag() with no value.");
+        }
+        if self.table {
+            rules.push(
+                "A list of maps with the same keys may be a table: @tab _ rows=N cols=K [key1 key2], \
+                 then one |value|value| line per row, then @end.",
+            );
+        }
+        rules
+    }
+}
+
+fn sample_type(schema: &Schema, def: &TypeDef, depth: usize) -> GValue {
+    match &def.kind {
+        TypeKind::Struct(fields) => GValue::struct_val(def.name.as_str(), sample_fields(schema, fields, depth)),
+        TypeKind::Sum(variants) => match variants.first() {
+            Some(VariantDef { tag, ty: TypeSpec::Null }) => GValue::sum(tag.as_str(), None),
+            Some(v) => GValue::sum(v.tag.as_str(), Some(sample_spec(schema, &v.ty, &[], depth + 1))),
+            None => GValue::Null,
+        },
+    }
+}
+
+fn sample_fields(schema: &Schema, fields: &[FieldDef], depth: usize) -> Vec<MapEntry> {
+    fields
+        .iter()
+        .filter(|f| !f.optional || f.default.is_some())
+        .map(|f| {
+            let v = match &f.default {
+                Some(default) => default.clone(),
+                None => sample_spec(schema, &f.ty, &f.constraints, depth + 1),
+            };
+            MapEntry::new(f.name.as_str(), v)
+        })
+        .collect()
+}
+
+fn sample_spec(schema: &Schema, ty: &TypeSpec, constraints: &[Constraint], depth: usize) -> GValue {
+    if depth > MAX_SAMPLE_DEPTH {
+        return GValue::Null;
+    }
+    let min = constraints.iter().find_map(|c| match c {
+        Constraint::Min(n) | Constraint::Range(n, _) => Some(*n),
+        _ => None,
+    });
+    match ty {
+        TypeSpec::Null => GValue::Null,
+        TypeSpec::Bool => GValue::bool(true),
+        TypeSpec::Int => GValue::int(min.map_or(1, |n| n.ceil() as i64)),
+        TypeSpec::Float => GValue::float(min.unwrap_or(1.5)),
+        TypeSpec::Str => {
+            let first = constraints.iter().find_map(|c| match c {
+                Constraint::Enum(values) => values.first(),
+                _ => None,
+            });
+            GValue::str(first.map_or("text", String::as_str))
+        }
+        TypeSpec::Bytes => GValue::bytes(b"glyph".to_vec()),
+        TypeSpec::Time => GValue::time(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap()),
+        TypeSpec::Id => GValue::id("id", "1"),
+        TypeSpec::List(elem) => GValue::list(vec![sample_spec(schema, elem, &[], depth + 1)]),
+        TypeSpec::Map(_, value) => GValue::map(vec![MapEntry::new("key", sample_spec(schema, value, &[], depth + 1))]),
+        TypeSpec::Ref(name) => match schema.get(name) {
+            Some(def) => sample_type(schema, def, depth),
+            None => GValue::Null,
+        },
+        TypeSpec::Struct(fields) => GValue::map(sample_fields(schema, fields, depth)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_prompt_covers_what_examples_use() {
+        let search = GValue::map(vec![
+            MapEntry::new("limit", GValue::int(5)),
+            MapEntry::new("query", GValue::str("glyph codec")),
+        ]);
+        let prompt = FormatPrompt::new().labeled("search", search.clone()).render().unwrap();
+        assert!(prompt.starts_with("Write values in GLYPH"));
+        assert!(prompt.contains("Strings: bare"));
+        assert!(prompt.contains("Numbers"));
+        assert!(!prompt.contains("References"));
+        assert!(!prompt.contains("@tab"));
+        assert!(prompt.ends_with("Examples:\nsearch: {limit=5 query=\"glyph codec\"}\n"));
+
+        let rows = GValue::list(
+            (1..=3)
+                .map(|i| GValue::map(vec![MapEntry::new("id", GValue::int(i)), MapEntry::new("ok", GValue::bool(true))]))
+                .collect(),
+        );
+        let prompt = format_prompt(&[search, rows]).unwrap();
+        assert!(prompt.contains("@tab _ rows=N"));
+        assert!(prompt.contains("t and f are true and false"));
+    }
+
+    #[test]
+    fn test_format_prompt_for_schema() {
+        let schema = Schema::parse(
+            r#"@schema{
+                Search struct{
+                    query: str
+                    mode: str [enum=[fast deep]]
+                    limit: int [min=1] @default(10) [optional]
+                    cursor: str [optional]
+                }
+                Shape sum{ Circle: float | None: null }
+            }"#,
+        )
+        .unwrap();
+        let prompt = format_prompt_for_schema(&schema).unwrap();
+        assert!(prompt.contains("\nTypes:\n@schema{"));
+        assert!(prompt.contains("Search: Search{limit=10 mode=fast query=text}\n"), "{}", prompt);
+        assert!(prompt.contains("Shape: Circle(1.5)\n"));
+        assert!(prompt.contains("Variants are Tag(value)"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Redaction by path
+//!
+//! `redact` replaces every value whose path matches one of a set of patterns
+//! before the tree reaches a prompt, a log line or a trace. Patterns are the
+//! usual `PathPattern`s, so `users[*].email` or `*.password` reach into
+//! lists and every key at a level; a match replaces the whole subtree.
+//!
+//! The replacement depends on `RedactStyle`. Hashes let two redacted values
+//! still be compared for equality; for guessable values such as phone
+//! numbers, use `Keyed` so the hash can't be reversed by trying them all.
+
+use crate::error::GlyphError;
+use crate::loose::hash_loose;
+use crate::path::{GlyphPath, PathPattern};
+use crate::sign::sign_loose;
+use crate::types::*;
+use std::fmt;
+
+/// Text of `RedactStyle::Marker`
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Hex digits kept by the hash styles
+const REDACT_HASH_LEN: usize = 16;
+
+/// What a redacted value becomes
+#[derive(Clone, PartialEq, Eq, Default)]
+pub enum RedactStyle {
+    /// `"[REDACTED]"`
+    #[default]
+    Marker,
+    /// `"sha256:<16 hex>"` of the value's `hash_loose`
+    Hash,
+    /// `"hmac:<16 hex>"` of the value's `sign_loose` under this key
+    Keyed(Vec<u8>),
+    Null,
+}
+
+impl fmt::Debug for RedactStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RedactStyle::Marker => f.write_str("Marker"),
+            RedactStyle::Hash => f.write_str("Hash"),
+            // Keep the key out of logs
+            RedactStyle::Keyed(_) => f.write_str("Keyed(..)"),
+            RedactStyle::Null => f.write_str("Null"),
+        }
+    }
+}
+
+impl RedactStyle {
+    /// The replacement for `v`
+    pub fn replace(&self, v: &GValue) -> Result<GValue, GlyphError> {
+        Ok(match self {
+            RedactStyle::Marker => GValue::str(REDACTED),
+            RedactStyle::Hash => GValue::str(format!("sha256:{}", &hash_loose(v)?[..REDACT_HASH_LEN])),
+            RedactStyle::Keyed(key) => GValue::str(format!("hmac:{}", &sign_loose(v, key)?[..REDACT_HASH_LEN])),
+            RedactStyle::Null => GValue::Null,
+        })
+    }
+}
+
+/// Replace every value matching one of `patterns` as `style` says; returns
+/// how many were replaced. Table rows and cells are matched as `rows[i]` and
+/// `rows[i].column`; a table with a matching row is turned into its row list.
+///
+/// Returns `Err(GlyphError::InvalidFloat)` if a hash style meets NaN or Inf.
+pub fn redact(v: &mut GValue, patterns: &[PathPattern], style: &RedactStyle) -> Result<usize, GlyphError> {
+    if patterns.is_empty() {
+        return Ok(0);
+    }
+    redact_node(v, &mut GlyphPath::root(), patterns, style)
+}
+
+/// `redact` on a copy
+pub fn redacted(v: &GValue, patterns: &[PathPattern], style: &RedactStyle) -> Result<GValue, GlyphError> {
+    let mut out = v.clone();
+    redact(&mut out, patterns, style)?;
+    Ok(out)
+}
+
+fn redact_node(
+    v: &mut GValue,
+    path: &mut GlyphPath,
+    patterns: &[PathPattern],
+    style: &RedactStyle,
+) -> Result<usize, GlyphError> {
+    if patterns.iter().any(|p| p.matches(path)) {
+        *v = style.replace(v)?;
+        return Ok(1);
+    }
+    // Nothing below can match
+    if !patterns.iter().any(|p| p.matches_below(path)) {
+        return Ok(0);
+    }
+    // A whole row can only be replaced as a list item
+    if let GValue::Table(t) = v {
+        let row_matches = (0..t.rows.len()).any(|i| {
+            path.push_index(i);
+            let hit = patterns.iter().any(|p| p.matches(path));
+            path.pop();
+            hit
+        });
+        if row_matches {
+            v.expand_table();
+        }
+    }
+    let mut changed = 0;
+    match v {
+        GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+            for e in entries {
+                path.push_key(e.key.clone());
+                changed += redact_node(&mut e.value, path, patterns, style)?;
+                path.pop();
+            }
+        }
+        GValue::List(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push_index(i);
+                changed += redact_node(item, path, patterns, style)?;
+                path.pop();
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(inner) = &mut s.value {
+                path.push_key(s.tag.clone());
+                changed += redact_node(inner, path, patterns, style)?;
+                path.pop();
+            }
+        }
+        GValue::Table(t) => {
+            for (i, row) in t.rows.iter_mut().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    changed += redact_node(cell, path, patterns, style)?;
+                    path.pop();
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use crate::loose::canonicalize_loose;
+    use crate::parse::{parse_loose_with, ParseOpts};
+    use serde_json::json;
+
+    fn patterns(ps: &[&str]) -> Vec<PathPattern> {
+        ps.iter().map(|p| PathPattern::parse(p).unwrap()).collect()
+    }
+
+    fn users() -> GValue {
+        from_json(&json!({
+            "users": [
+                {"name": "ann", "email": "ann@example.com", "auth": {"token": "t1"}},
+                {"name": "bob", "email": "bob@example.com", "auth": {"token": "t2"}}
+            ]
+        }))
+    }
+
+    #[test]
+    fn test_redact_styles() {
+        let ps = patterns(&["users[*].email", "users[*].auth"]);
+        let mut v = users();
+        assert_eq!(redact(&mut v, &ps, &RedactStyle::Marker).unwrap(), 4);
+        assert_eq!(
+            canonicalize_loose(&v).unwrap(),
+            "{users=[{auth=\"[REDACTED]\" email=\"[REDACTED]\" name=ann} {auth=\"[REDACTED]\" email=\"[REDACTED]\" name=bob}]}"
+        );
+
+        let v = redacted(&users(), &patterns(&["users[0].email"]), &RedactStyle::Null).unwrap();
+        assert_eq!(v["users"][0]["email"], GValue::Null);
+        assert_eq!(v["users"][1]["email"], GValue::str("bob@example.com"));
+    }
+
+    #[test]
+    fn test_redact_hashes() {
+        let ps = patterns(&["*.*"]);
+        let v = from_json(&json!({"a": {"x": "same"}, "b": {"y": "same", "z": "other"}}));
+        let hashed = redacted(&v, &ps, &RedactStyle::Hash).unwrap();
+        assert_eq!(hashed["a"]["x"], hashed["b"]["y"]);
+        assert_ne!(hashed["a"]["x"], hashed["b"]["z"]);
+        assert!(hashed["a"]["x"].as_str().unwrap().starts_with("sha256:"));
+
+        let keyed = redacted(&v, &ps, &RedactStyle::Keyed(b"k1".to_vec())).unwrap();
+        let other = redacted(&v, &ps, &RedactStyle::Keyed(b"k2".to_vec())).unwrap();
+        assert_eq!(keyed["a"]["x"].as_str().unwrap().len(), "hmac:".len() + 16);
+        assert_ne!(keyed["a"]["x"], other["a"]["x"]);
+        assert_eq!(format!("{:?}", RedactStyle::Keyed(b"k1".to_vec())), "Keyed(..)");
+    }
+
+    #[test]
+    fn test_redact_table_cells() {
+        let mut t = GValue::table(
+            ["id", "ssn"],
+            vec![vec![GValue::int(1), GValue::str("123")], vec![GValue::int(2), GValue::str("456")]],
+        );
+        assert_eq!(redact(&mut t, &patterns(&["[*].ssn"]), &RedactStyle::Null).unwrap(), 2);
+        assert_eq!(canonicalize_loose(&t).unwrap(), "@tab _ rows=2 cols=2 [id ssn]\n|1|_|\n|2|_|\n@end");
+    }
+
+    #[test]
+    fn test_redact_table_rows() {
+        let mut v = users();
+        v["users"].push(from_json(&json!({"name": "cy", "email": "cy@example.com", "auth": {"token": "t3"}}))).unwrap();
+        let text = canonicalize_loose(&v).unwrap();
+        assert!(text.contains("@tab"), "{}", text);
+        for tables in [false, true] {
+            let mut parsed = parse_loose_with(&text, ParseOpts { tables }).unwrap();
+            assert_eq!(parsed["users"].is_table(), tables);
+            assert_eq!(redact(&mut parsed, &patterns(&["users[*]"]), &RedactStyle::Marker).unwrap(), 3);
+            assert_eq!(canonicalize_loose(&parsed).unwrap(), format!("{{users=[{0} {0} {0}]}}", "\"[REDACTED]\""));
+        }
+
+        let mut t = GValue::table(["id"], vec![vec![GValue::int(1)], vec![GValue::int(2)]]);
+        assert_eq!(redact(&mut t, &patterns(&["[1]"]), &RedactStyle::Null).unwrap(), 1);
+        assert_eq!(t, GValue::list(vec![GValue::build_map().int("id", 1).finish(), GValue::Null]));
+    }
+}
@@ -0,0 +1,223 @@
+//! Reference index and dangling-reference validation
+//!
+//! Collects every `^prefix:value` reference in a value tree together with the
+//! path where it occurs, and checks them against a caller-supplied resolver so
+//! agents don't act on references to entities that no longer exist.
+
+use crate::path::GlyphPath;
+use crate::types::*;
+use std::collections::{HashMap, HashSet};
+
+/// A reference and the path where it occurs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefOccurrence {
+    pub path: GlyphPath,
+    pub reference: RefId,
+}
+
+/// Index of all references in a value, in depth-first document order
+#[derive(Debug, Clone, Default)]
+pub struct RefIndex {
+    occurrences: Vec<RefOccurrence>,
+}
+
+impl RefIndex {
+    /// Build the index for a value tree
+    pub fn build(v: &GValue) -> Self {
+        let mut index = Self::default();
+        let mut path = GlyphPath::root();
+        collect_refs(v, &mut path, &mut index.occurrences);
+        index
+    }
+
+    /// All occurrences in document order
+    pub fn occurrences(&self) -> &[RefOccurrence] {
+        &self.occurrences
+    }
+
+    /// Distinct references
+    pub fn unique(&self) -> Vec<&RefId> {
+        let mut seen = HashSet::new();
+        self.occurrences
+            .iter()
+            .map(|o| &o.reference)
+            .filter(|r| seen.insert(*r))
+            .collect()
+    }
+
+    /// Occurrences grouped by prefix (empty string for unprefixed refs)
+    pub fn by_prefix(&self) -> HashMap<&str, Vec<&RefOccurrence>> {
+        let mut out: HashMap<&str, Vec<&RefOccurrence>> = HashMap::new();
+        for occ in &self.occurrences {
+            out.entry(occ.reference.prefix.as_str()).or_default().push(occ);
+        }
+        out
+    }
+
+    pub fn len(&self) -> usize {
+        self.occurrences.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.occurrences.is_empty()
+    }
+}
+
+fn collect_refs(v: &GValue, path: &mut GlyphPath, out: &mut Vec<RefOccurrence>) {
+    match v {
+        GValue::Id(r) => out.push(RefOccurrence {
+            path: path.clone(),
+            reference: r.clone(),
+        }),
+        GValue::List(items) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push_index(i);
+                collect_refs(item, path, out);
+                path.pop();
+            }
+        }
+        GValue::Map(entries) => collect_entry_refs(entries, path, out),
+        GValue::Struct(s) => collect_entry_refs(&s.fields, path, out),
+        GValue::Sum(s) => {
+            if let Some(ref inner) = s.value {
+                path.push_key(s.tag.clone());
+                collect_refs(inner, path, out);
+                path.pop();
+            }
+        }
+        GValue::Table(t) => {
+            for (i, row) in t.rows.iter().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    collect_refs(cell, path, out);
+                    path.pop();
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_entry_refs(entries: &[MapEntry], path: &mut GlyphPath, out: &mut Vec<RefOccurrence>) {
+    for entry in entries {
+        path.push_key(entry.key.clone());
+        collect_refs(&entry.value, path, out);
+        path.pop();
+    }
+}
+
+// ============================================================
+// Validation
+// ============================================================
+
+/// Resolves references to known entities
+pub trait RefResolver {
+    /// Return true if the reference points at something that exists
+    fn resolve(&self, reference: &RefId) -> bool;
+}
+
+impl<F: Fn(&RefId) -> bool> RefResolver for F {
+    fn resolve(&self, reference: &RefId) -> bool {
+        self(reference)
+    }
+}
+
+impl RefResolver for HashSet<RefId> {
+    fn resolve(&self, reference: &RefId) -> bool {
+        self.contains(reference)
+    }
+}
+
+/// A reference that resolved to nothing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingRef {
+    pub path: GlyphPath,
+    pub reference: RefId,
+}
+
+impl std::fmt::Display for DanglingRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.reference.prefix.is_empty() {
+            write!(f, "{}: dangling reference ^{}", self.path, self.reference.value)
+        } else {
+            write!(
+                f,
+                "{}: dangling reference ^{}:{}",
+                self.path, self.reference.prefix, self.reference.value
+            )
+        }
+    }
+}
+
+/// Report every reference in `v` that the resolver cannot resolve.
+///
+/// Each occurrence is reported separately, in document order. The resolver is
+/// consulted once per distinct reference.
+pub fn validate_refs(v: &GValue, resolver: &dyn RefResolver) -> Vec<DanglingRef> {
+    RefIndex::build(v).dangling(resolver)
+}
+
+impl RefIndex {
+    /// Occurrences whose reference the resolver cannot resolve
+    pub fn dangling(&self, resolver: &dyn RefResolver) -> Vec<DanglingRef> {
+        let mut cache: HashMap<&RefId, bool> = HashMap::new();
+        self.occurrences
+            .iter()
+            .filter(|occ| !*cache.entry(&occ.reference).or_insert_with(|| resolver.resolve(&occ.reference)))
+            .map(|occ| DanglingRef {
+                path: occ.path.clone(),
+                reference: occ.reference.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> GValue {
+        GValue::map(vec![
+            field("owner", GValue::id("user", "1")),
+            field(
+                "items",
+                GValue::list(vec![GValue::id("doc", "a"), GValue::id("doc", "gone"), GValue::Int(3)]),
+            ),
+            field("status", GValue::sum("moved", Some(GValue::id("doc", "gone")))),
+        ])
+    }
+
+    #[test]
+    fn test_index_paths() {
+        let index = RefIndex::build(&sample());
+        let paths: Vec<String> = index.occurrences().iter().map(|o| o.path.to_string()).collect();
+        assert_eq!(paths, vec!["owner", "items[0]", "items[1]", "status.moved"]);
+        assert_eq!(index.unique().len(), 3);
+        assert_eq!(index.by_prefix()["doc"].len(), 3);
+    }
+
+    #[test]
+    fn test_validate_refs_with_set() {
+        let known: HashSet<RefId> = [RefId::new("user", "1"), RefId::new("doc", "a")].into_iter().collect();
+        let dangling = validate_refs(&sample(), &known);
+        assert_eq!(dangling.len(), 2);
+        assert_eq!(dangling[0].path.to_string(), "items[1]");
+        assert_eq!(dangling[1].to_string(), "status.moved: dangling reference ^doc:gone");
+    }
+
+    #[test]
+    fn test_validate_refs_with_closure() {
+        let calls = std::cell::Cell::new(0);
+        let resolver = |r: &RefId| {
+            calls.set(calls.get() + 1);
+            r.prefix == "user"
+        };
+        let dangling = validate_refs(&sample(), &resolver);
+        assert_eq!(dangling.len(), 3);
+        // "doc:gone" appears twice but is resolved once
+        assert_eq!(calls.get(), 3);
+        assert!(validate_refs(&GValue::Int(1), &resolver).is_empty());
+    }
+}
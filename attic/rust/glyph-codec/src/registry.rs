@@ -0,0 +1,382 @@
+//! Versioned schema registry
+//!
+//! Long-lived agents keep values written under old tool shapes.
+//! `SchemaRegistry` holds every version of each named schema, refuses a new
+//! version that breaks the registry's `Compatibility` with its neighbours,
+//! and finds the schema a stored value was written under by its `hash()`.
+//!
+//! Compatibility is about reading: a schema reads data written under another
+//! when every value valid there is valid under it too. Backward
+//! compatibility means the newer version reads data the older one wrote;
+//! forward means the older reads the newer's.
+
+use crate::error::GlyphError;
+use crate::loose::{HashAlgo, HashWriter};
+use crate::schema::*;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+
+impl Schema {
+    /// 32-hex SHA-256 prefix of the canonical schema text; any change to a
+    /// type, field, constraint or flag changes it
+    pub fn hash(&self) -> String {
+        let mut hasher = HashWriter::new(HashAlgo::Sha256);
+        let _ = write!(hasher, "{}", self);
+        hasher.finish(16).expect("16 bytes fits SHA-256").to_hex()
+    }
+}
+
+/// Which versions a registry keeps compatible
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compatibility {
+    /// Any change is accepted
+    None,
+    /// A newer version reads what the older one wrote
+    #[default]
+    Backward,
+    /// An older version reads what the newer one writes
+    Forward,
+    /// Both
+    Full,
+}
+
+/// Why one version can't read another's data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatIssue {
+    /// `Backward` if the newer version can't read the older's data,
+    /// `Forward` for the other way round
+    pub direction: Compatibility,
+    /// `Type` or `Type.field`
+    pub at: String,
+    pub problem: String,
+}
+
+impl fmt::Display for CompatIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Compatibility::Forward => "forward",
+            _ => "backward",
+        };
+        write!(f, "{}: {}: {}", direction, self.at, self.problem)
+    }
+}
+
+/// Issues with `new` reading data written under `old`
+pub fn check_backward(old: &Schema, new: &Schema) -> Vec<CompatIssue> {
+    read_issues(new, old, Compatibility::Backward)
+}
+
+/// Issues with `old` reading data written under `new`
+pub fn check_forward(old: &Schema, new: &Schema) -> Vec<CompatIssue> {
+    read_issues(old, new, Compatibility::Forward)
+}
+
+/// Issues between two versions under `mode`
+pub fn check_compatibility(old: &Schema, new: &Schema, mode: Compatibility) -> Vec<CompatIssue> {
+    let mut issues = Vec::new();
+    if matches!(mode, Compatibility::Backward | Compatibility::Full) {
+        issues.extend(check_backward(old, new));
+    }
+    if matches!(mode, Compatibility::Forward | Compatibility::Full) {
+        issues.extend(check_forward(old, new));
+    }
+    issues
+}
+
+/// Every schema version, by name
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    compatibility: Compatibility,
+    /// Versions of each name, oldest first
+    schemas: BTreeMap<String, Vec<(String, Schema)>>,
+}
+
+impl SchemaRegistry {
+    /// A registry that keeps versions backward compatible
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_compatibility(compatibility: Compatibility) -> Self {
+        Self {
+            compatibility,
+            ..Self::default()
+        }
+    }
+
+    pub fn compatibility(&self) -> Compatibility {
+        self.compatibility
+    }
+
+    /// Issues `schema` would have as `version` of `name`, against the
+    /// versions just before and after it
+    pub fn check(&self, name: &str, version: &str, schema: &Schema) -> Vec<CompatIssue> {
+        let Some(versions) = self.schemas.get(name) else {
+            return Vec::new();
+        };
+        let at = versions.partition_point(|(v, _)| version_cmp(v, version).is_lt());
+        let mut issues = Vec::new();
+        if let Some((_, older)) = at.checked_sub(1).map(|i| &versions[i]) {
+            issues.extend(check_compatibility(older, schema, self.compatibility));
+        }
+        if let Some((_, newer)) = versions[at..].iter().find(|(v, _)| v != version) {
+            issues.extend(check_compatibility(schema, newer, self.compatibility));
+        }
+        issues
+    }
+
+    /// Add `schema` as `version` of `name` and return its hash.
+    ///
+    /// Re-registering the same schema is a no-op; a different schema under a
+    /// taken version, or one that breaks compatibility, is an error.
+    pub fn register(&mut self, name: &str, version: &str, schema: Schema) -> Result<String, GlyphError> {
+        let hash = schema.hash();
+        if let Some(existing) = self.get(name, version) {
+            if existing.hash() == hash {
+                return Ok(hash);
+            }
+            return Err(GlyphError::InvalidValue(format!(
+                "{} {} is already registered with a different schema",
+                name, version
+            )));
+        }
+        let issues = self.check(name, version, &schema);
+        if !issues.is_empty() {
+            let issues: Vec<_> = issues.iter().map(|i| i.to_string()).collect();
+            return Err(GlyphError::InvalidValue(format!(
+                "{} {} is incompatible: {}",
+                name,
+                version,
+                issues.join("; ")
+            )));
+        }
+        let versions = self.schemas.entry(name.to_string()).or_default();
+        let at = versions.partition_point(|(v, _)| version_cmp(v, version).is_lt());
+        versions.insert(at, (version.to_string(), schema));
+        Ok(hash)
+    }
+
+    pub fn get(&self, name: &str, version: &str) -> Option<&Schema> {
+        self.schemas
+            .get(name)?
+            .iter()
+            .find(|(v, _)| v == version)
+            .map(|(_, s)| s)
+    }
+
+    /// The newest version of `name`
+    pub fn latest(&self, name: &str) -> Option<(&str, &Schema)> {
+        self.schemas.get(name)?.last().map(|(v, s)| (v.as_str(), s))
+    }
+
+    /// Versions of `name`, oldest first
+    pub fn versions(&self, name: &str) -> Vec<&str> {
+        self.schemas
+            .get(name)
+            .map(|vs| vs.iter().map(|(v, _)| v.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Registered names, sorted
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.schemas.keys().map(String::as_str)
+    }
+
+    /// The name, version and schema with this `hash()`
+    pub fn by_hash(&self, hash: &str) -> Option<(&str, &str, &Schema)> {
+        self.schemas.iter().find_map(|(name, versions)| {
+            versions
+                .iter()
+                .find(|(_, s)| s.hash() == hash)
+                .map(|(v, s)| (name.as_str(), v.as_str(), s))
+        })
+    }
+}
+
+/// `1.10` after `1.9`, `v2` after `v1`; versions that aren't dotted numbers
+/// sort as text
+fn version_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    fn parts(v: &str) -> Option<Vec<u64>> {
+        v.trim_start_matches(['v', 'V']).split('.').map(|p| p.parse().ok()).collect()
+    }
+    match (parts(a), parts(b)) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        _ => a.cmp(b),
+    }
+}
+
+// ============================================================
+// Compatibility rules
+// ============================================================
+
+/// What keeps `reader` from reading every value valid under `writer`
+fn read_issues(reader: &Schema, writer: &Schema, direction: Compatibility) -> Vec<CompatIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |at: String, problem: String| issues.push(CompatIssue { direction, at, problem });
+    for w in writer.types() {
+        let Some(r) = reader.get(&w.name) else {
+            issue(w.name.clone(), "type is not defined".to_string());
+            continue;
+        };
+        match (&r.kind, &w.kind) {
+            (TypeKind::Struct(rf), TypeKind::Struct(wf)) => {
+                record_issues(&w.name, rf, r.open, wf, &mut issue);
+            }
+            (TypeKind::Sum(rv), TypeKind::Sum(wv)) => {
+                for v in wv {
+                    let at = format!("{}.{}", w.name, v.tag);
+                    match rv.iter().find(|r| r.tag == v.tag) {
+                        None => issue(at, "variant is not defined".to_string()),
+                        Some(r) if !reads(&r.ty, &v.ty) => {
+                            issue(at, format!("{} payload read as {}", v.ty, r.ty))
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            _ => issue(w.name.clone(), "changed between struct and sum".to_string()),
+        }
+    }
+    issues
+}
+
+fn record_issues(
+    name: &str,
+    reader: &[FieldDef],
+    open: bool,
+    writer: &[FieldDef],
+    issue: &mut impl FnMut(String, String),
+) {
+    for r in reader {
+        let at = format!("{}.{}", name, r.name);
+        let Some(w) = writer.iter().find(|w| w.name == r.name) else {
+            if !r.optional {
+                issue(at, "required but never written".to_string());
+            }
+            continue;
+        };
+        if w.optional && !r.optional {
+            issue(at.clone(), "required but may be absent".to_string());
+        }
+        if !reads(&r.ty, &w.ty) {
+            issue(at.clone(), format!("{} read as {}", w.ty, r.ty));
+        }
+        for c in r.constraints.iter().filter(|c| !w.constraints.contains(c)) {
+            issue(at.clone(), format!("adds constraint [{}]", c));
+        }
+        if r.fid != 0 && w.fid != 0 && r.fid != w.fid {
+            issue(at, format!("@fid {} read as @fid {}", w.fid, r.fid));
+        }
+    }
+    if !open {
+        for w in writer.iter().filter(|w| !reader.iter().any(|r| r.name == w.name)) {
+            issue(format!("{}.{}", name, w.name), "unknown field".to_string());
+        }
+    }
+}
+
+/// Whether a value of type `writer` is also a valid `reader`
+fn reads(reader: &TypeSpec, writer: &TypeSpec) -> bool {
+    match (reader, writer) {
+        (TypeSpec::Float, TypeSpec::Int) | (TypeSpec::Str, TypeSpec::Id) | (TypeSpec::Id, TypeSpec::Str) => true,
+        (TypeSpec::List(r), TypeSpec::List(w)) => reads(r, w),
+        (TypeSpec::Map(rk, rv), TypeSpec::Map(wk, wv)) => reads(rk, wk) && reads(rv, wv),
+        (TypeSpec::Struct(r), TypeSpec::Struct(w)) => {
+            let mut ok = true;
+            record_issues("", r, false, w, &mut |_, _| ok = false);
+            ok
+        }
+        (r, w) => r == w,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v1() -> Schema {
+        Schema::parse("Call struct{ query: str, limit: int [optional] } Mode sum{ Fast: null | Deep: int }").unwrap()
+    }
+
+    #[test]
+    fn test_compatibility_rules() {
+        // Adding an optional field and widening int to float is backward compatible
+        let v2 = Schema::parse(
+            "Call struct{ query: str, limit: float [optional], lang: str [optional] } Mode sum{ Fast: null | Deep: int | Auto: null }",
+        )
+        .unwrap();
+        assert_eq!(check_backward(&v1(), &v2), vec![]);
+        // ...but the old version can't read the new field, variant or float
+        let forward: Vec<_> = check_forward(&v1(), &v2).iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            forward,
+            vec![
+                "forward: Call.limit: float read as int",
+                "forward: Call.lang: unknown field",
+                "forward: Mode.Auto: variant is not defined",
+            ]
+        );
+
+        let breaking = Schema::parse(
+            "Call struct{ query: str [len>=1], limit: int, user: str } Mode struct{ x: int }",
+        )
+        .unwrap();
+        let issues: Vec<_> = check_backward(&v1(), &breaking).iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            issues,
+            vec![
+                "backward: Call.query: adds constraint [len>=1]",
+                "backward: Call.limit: required but may be absent",
+                "backward: Call.user: required but never written",
+                "backward: Mode: changed between struct and sum",
+            ]
+        );
+        let open = Schema::parse("Call @open struct{ query: str } Mode sum{ Fast: null | Deep: int }").unwrap();
+        assert_eq!(check_backward(&v1(), &open), vec![]);
+        assert_eq!(check_compatibility(&v1(), &open, Compatibility::None), vec![]);
+        assert_eq!(check_compatibility(&v1(), &open, Compatibility::Full).len(), 0);
+        let dropped = Schema::parse("Call struct{ query: str, limit: int [optional] }").unwrap();
+        assert_eq!(check_backward(&v1(), &dropped)[0].to_string(), "backward: Mode: type is not defined");
+        let refid = Schema::parse("Call struct{ query: str @fid(2), limit: int [optional] @fid(1) }").unwrap();
+        let moved = Schema::parse("Call struct{ query: str @fid(1), limit: int [optional] @fid(2) }").unwrap();
+        assert_eq!(check_backward(&refid, &moved).len(), 2);
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut reg = SchemaRegistry::new();
+        let h1 = reg.register("search", "1.0", v1()).unwrap();
+        assert_eq!(h1.len(), 32);
+        assert_eq!(h1, v1().hash());
+        // Registering the same schema again is fine; a different one isn't
+        assert_eq!(reg.register("search", "1.0", v1()).unwrap(), h1);
+        let v2 = Schema::parse("Call struct{ query: str, limit: int [optional], lang: str [optional] } Mode sum{ Fast: null | Deep: int }").unwrap();
+        assert!(reg.register("search", "1.0", v2.clone()).is_err());
+
+        let h2 = reg.register("search", "1.10", v2.clone()).unwrap();
+        assert_ne!(h1, h2);
+        let err = reg
+            .register("search", "2.0", Schema::parse("Call struct{ query: int }").unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("search 2.0 is incompatible: backward: Call.query: str read as int"), "{}", err);
+
+        // Versions order numerically, and a new one is checked against both neighbours
+        let v1_5 = Schema::parse("Call struct{ query: str, limit: int [optional], lang: str [optional], x: int [optional] } Mode sum{ Fast: null | Deep: int }").unwrap();
+        assert!(!reg.check("search", "1.5", &v1_5).is_empty());
+        reg.register("search", "1.2", v2.clone()).unwrap();
+        assert_eq!(reg.versions("search"), vec!["1.0", "1.2", "1.10"]);
+        assert_eq!(reg.latest("search").unwrap().0, "1.10");
+        assert_eq!(reg.by_hash(&h1).map(|(n, v, _)| (n, v)), Some(("search", "1.0")));
+        assert!(reg.by_hash("nope").is_none());
+        assert_eq!(reg.names().collect::<Vec<_>>(), vec!["search"]);
+
+        // A forward-only registry takes what a backward one refuses, and vice versa
+        let mut fwd = SchemaRegistry::with_compatibility(Compatibility::Forward);
+        fwd.register("search", "v1", v2).unwrap();
+        fwd.register("search", "v2", v1()).unwrap();
+        assert!(fwd.register("search", "v3", Schema::parse("Call struct{ query: str, extra: int [optional] } Mode sum{ Fast: null | Deep: int }").unwrap()).is_err());
+        assert_eq!(version_cmp("v2", "v10"), std::cmp::Ordering::Less);
+        assert_eq!(version_cmp("beta", "alpha"), std::cmp::Ordering::Greater);
+    }
+}
@@ -0,0 +1,99 @@
+//! Bulk byte scanning for the string encoder
+//!
+//! Quoting copies a string through unchanged except for `"`, `\` and control
+//! characters, and most strings have none. Instead of matching every char,
+//! `find_escape` tests eight bytes at a time as one `u64` (SWAR: SIMD within
+//! a register), so `quote_string` can copy the runs in between in one go.
+//! `word_prefix_len` does the same job for `is_bare_safe` with a byte table.
+//!
+//! Every byte these look for is ASCII, so any index they return is a char
+//! boundary.
+
+const LO: u64 = 0x0101_0101_0101_0101;
+const HI: u64 = 0x8080_8080_8080_8080;
+
+/// Whether any byte of `x` is zero
+#[inline]
+fn has_zero(x: u64) -> bool {
+    x.wrapping_sub(LO) & !x & HI != 0
+}
+
+/// Whether any byte of `x` is below `n`, for `n` up to 128
+#[inline]
+fn has_below(x: u64, n: u8) -> bool {
+    x.wrapping_sub(LO * n as u64) & !x & HI != 0
+}
+
+/// Whether `b` must be escaped inside a quoted string
+#[inline]
+fn needs_escape(b: u8) -> bool {
+    b < 0x20 || b == b'"' || b == b'\\'
+}
+
+/// Index of the first byte of `s` that must be escaped inside quotes
+pub(crate) fn find_escape(s: &[u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut chunks = s.chunks_exact(8);
+    for chunk in &mut chunks {
+        let x = u64::from_le_bytes(chunk.try_into().expect("8-byte chunk"));
+        if has_below(x, 0x20) || has_zero(x ^ (LO * b'"' as u64)) || has_zero(x ^ (LO * b'\\' as u64)) {
+            // Some byte in this word matches; find which
+            return chunk.iter().position(|&b| needs_escape(b)).map(|j| i + j);
+        }
+        i += 8;
+    }
+    chunks.remainder().iter().position(|&b| needs_escape(b)).map(|j| i + j)
+}
+
+/// `[A-Za-z0-9_]` by byte value
+static WORD: [bool; 256] = {
+    let mut table = [false; 256];
+    let mut b = 0;
+    while b < 256 {
+        let c = b as u8;
+        table[b] = c.is_ascii_alphanumeric() || c == b'_';
+        b += 1;
+    }
+    table
+};
+
+/// Length of the leading run of `[A-Za-z0-9_]` bytes in `s`
+pub(crate) fn word_prefix_len(s: &[u8]) -> usize {
+    s.iter().position(|&b| !WORD[b as usize]).unwrap_or(s.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_escape_matches_bytewise() {
+        let naive = |s: &[u8]| s.iter().position(|&b| needs_escape(b));
+        let mut cases: Vec<String> = vec![
+            String::new(),
+            "plain text with no escapes at all, quite long".to_string(),
+            "é日本語 and ünïcödé over many words".to_string(),
+            "\u{7f}\u{80}".repeat(9),
+        ];
+        for pos in 0..20 {
+            for special in ['"', '\\', '\n', '\t', '\0', '\u{1f}'] {
+                let mut s = "abcdefghijklmnopqrs".to_string();
+                s.insert(pos, special);
+                cases.push(s);
+            }
+        }
+        for s in &cases {
+            assert_eq!(find_escape(s.as_bytes()), naive(s.as_bytes()), "{:?}", s);
+        }
+        // Space and everything above it pass
+        assert_eq!(find_escape(&(0x20..=0xff).filter(|&b| b != b'"' && b != b'\\').collect::<Vec<u8>>()), None);
+    }
+
+    #[test]
+    fn test_word_prefix_len() {
+        assert_eq!(word_prefix_len(b"user_id42 rest"), 9);
+        assert_eq!(word_prefix_len(b"all_word"), 8);
+        assert_eq!(word_prefix_len("é".as_bytes()), 0);
+        assert_eq!(word_prefix_len(b""), 0);
+    }
+}
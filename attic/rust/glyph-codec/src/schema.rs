@@ -0,0 +1,2181 @@
+//! GLYPH schemas and strict-mode encoding
+//!
+//! A `Schema` names record (`struct`) and sum types in the `@schema{...}`
+//! language of GLYPH_T_SPEC §3:
+//!
+//! ```text
+//! @schema{
+//!   Point struct{
+//!     x: int
+//!     y: int
+//!   }
+//!   Shape sum{
+//!     Circle: float |
+//!     Poly: list<Point>
+//!   }
+//! }
+//! ```
+//!
+//! With a schema both sides already agree on field order, so
+//! `canonicalize_strict` writes records positionally and drops the keys:
+//! `Point@(3 4)`. When an optional field is absent, a presence bitmap over
+//! the optional fields (least significant bit first) leads the record and
+//! only the present fields follow: `User@{bm=0b10}(7 ada admin)`.
+//!
+//! `validate` checks a value against the schema and reports every mismatch
+//! with its path, so model-produced tool arguments can be rejected before
+//! they run; `normalize` fills declared defaults first, and
+//! `validate_coerced` can repair strings such as `"5"` in an `int` slot.
+
+use crate::error::GlyphError;
+use crate::loose::{canon_string, canonicalize_loose_no_tabular, quote_string};
+use crate::parse::{parse_loose, Parser, MAX_PARSE_DEPTH};
+use crate::path::GlyphPath;
+use crate::types::*;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+// ============================================================
+// Schema model
+// ============================================================
+
+/// Type of a field, list element, map value or sum payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSpec {
+    Null,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Bytes,
+    Time,
+    Id,
+    List(Box<TypeSpec>),
+    Map(Box<TypeSpec>, Box<TypeSpec>),
+    /// A type named in the schema
+    Ref(String),
+    /// An anonymous record
+    Struct(Vec<FieldDef>),
+}
+
+impl TypeSpec {
+    pub fn list(elem: TypeSpec) -> Self {
+        TypeSpec::List(Box::new(elem))
+    }
+
+    pub fn map(key: TypeSpec, value: TypeSpec) -> Self {
+        TypeSpec::Map(Box::new(key), Box::new(value))
+    }
+
+    pub fn named(name: impl Into<String>) -> Self {
+        TypeSpec::Ref(name.into())
+    }
+}
+
+impl fmt::Display for TypeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSpec::Null => f.write_str("null"),
+            TypeSpec::Bool => f.write_str("bool"),
+            TypeSpec::Int => f.write_str("int"),
+            TypeSpec::Float => f.write_str("float"),
+            TypeSpec::Str => f.write_str("str"),
+            TypeSpec::Bytes => f.write_str("bytes"),
+            TypeSpec::Time => f.write_str("time"),
+            TypeSpec::Id => f.write_str("id"),
+            TypeSpec::List(elem) => write!(f, "list<{}>", elem),
+            TypeSpec::Map(k, v) => write!(f, "map<{},{}>", k, v),
+            TypeSpec::Ref(name) => f.write_str(name),
+            TypeSpec::Struct(fields) => {
+                f.write_str("struct{")?;
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", field)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Validation constraint on a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraint {
+    /// `min=N`
+    Min(f64),
+    /// `max=N`
+    Max(f64),
+    /// `len>=N`
+    MinLen(usize),
+    /// `len<=N`
+    MaxLen(usize),
+    /// `len=N`
+    Len(usize),
+    /// `regex="..."`
+    Regex(String),
+    /// `enum=[a b c]`
+    Enum(Vec<String>),
+    /// `nonempty`
+    NonEmpty,
+    /// `unique` (list items)
+    Unique,
+    /// `min..max`, inclusive
+    Range(f64, f64),
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Min(n) => write!(f, "min={}", num(*n)),
+            Constraint::Max(n) => write!(f, "max={}", num(*n)),
+            Constraint::MinLen(n) => write!(f, "len>={}", n),
+            Constraint::MaxLen(n) => write!(f, "len<={}", n),
+            Constraint::Len(n) => write!(f, "len={}", n),
+            Constraint::Regex(re) => write!(f, "regex={}", quote_string(re)),
+            Constraint::Enum(values) => {
+                let values: Vec<_> = values.iter().map(|v| canon_string(v)).collect();
+                write!(f, "enum=[{}]", values.join(" "))
+            }
+            Constraint::NonEmpty => f.write_str("nonempty"),
+            Constraint::Unique => f.write_str("unique"),
+            Constraint::Range(lo, hi) => write!(f, "{}..{}", num(*lo), num(*hi)),
+        }
+    }
+}
+
+/// Whole bounds print as integers
+fn num(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+/// A record field
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDef {
+    pub name: String,
+    pub ty: TypeSpec,
+    pub constraints: Vec<Constraint>,
+    /// Short key accepted in place of the name (`@k(x)`)
+    pub wire_key: Option<String>,
+    pub optional: bool,
+    pub default: Option<GValue>,
+    /// Stable position in strict form (`@fid(N)`); 0 if unassigned
+    pub fid: u32,
+    /// An explicit null counts as present (`@keepnull`)
+    pub keep_null: bool,
+    /// Encoding hint (`@codec(dict)`)
+    pub codec: Option<String>,
+}
+
+impl FieldDef {
+    pub fn new(name: impl Into<String>, ty: TypeSpec) -> Self {
+        Self {
+            name: name.into(),
+            ty,
+            constraints: Vec::new(),
+            wire_key: None,
+            optional: false,
+            default: None,
+            fid: 0,
+            keep_null: false,
+            codec: None,
+        }
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    pub fn keep_null(mut self) -> Self {
+        self.keep_null = true;
+        self
+    }
+
+    pub fn with_constraint(mut self, c: Constraint) -> Self {
+        self.constraints.push(c);
+        self
+    }
+
+    pub fn with_wire_key(mut self, key: impl Into<String>) -> Self {
+        self.wire_key = Some(key.into());
+        self
+    }
+
+    pub fn with_default(mut self, value: GValue) -> Self {
+        self.default = Some(value);
+        self
+    }
+
+    pub fn with_fid(mut self, fid: u32) -> Self {
+        self.fid = fid;
+        self
+    }
+
+    pub fn with_codec(mut self, codec: impl Into<String>) -> Self {
+        self.codec = Some(codec.into());
+        self
+    }
+
+    /// Whether `key` names this field, by name or wire key
+    pub fn matches(&self, key: &str) -> bool {
+        self.name == key || self.wire_key.as_deref() == Some(key)
+    }
+}
+
+impl fmt::Display for FieldDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_schema_ident(&self.name) {
+            write!(f, "{}: {}", self.name, self.ty)?;
+        } else {
+            write!(f, "{}: {}", quote_string(&self.name), self.ty)?;
+        }
+        for c in &self.constraints {
+            write!(f, " [{}]", c)?;
+        }
+        if self.fid > 0 {
+            write!(f, " @fid({})", self.fid)?;
+        }
+        if let Some(ref k) = self.wire_key {
+            write!(f, " @k({})", k)?;
+        }
+        if let Some(ref codec) = self.codec {
+            write!(f, " @codec({})", codec)?;
+        }
+        if self.keep_null {
+            f.write_str(" @keepnull")?;
+        }
+        if let Some(ref v) = self.default {
+            write!(f, " @default({})", v)?;
+        }
+        if self.optional {
+            f.write_str(" [optional]")?;
+        }
+        Ok(())
+    }
+}
+
+fn is_schema_ident(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// A sum variant
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantDef {
+    pub tag: String,
+    pub ty: TypeSpec,
+}
+
+impl VariantDef {
+    pub fn new(tag: impl Into<String>, ty: TypeSpec) -> Self {
+        Self { tag: tag.into(), ty }
+    }
+}
+
+/// Body of a named type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    Struct(Vec<FieldDef>),
+    Sum(Vec<VariantDef>),
+}
+
+/// A named type
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDef {
+    pub name: String,
+    pub version: Option<String>,
+    /// `@pack`: prefer the strict form on the wire
+    pub pack: bool,
+    /// `@tab`: lists of this type may go tabular
+    pub tab: bool,
+    /// `@open`: values may carry fields the schema doesn't name
+    pub open: bool,
+    pub kind: TypeKind,
+}
+
+impl TypeDef {
+    pub fn record(name: impl Into<String>, fields: Vec<FieldDef>) -> Self {
+        Self::with_kind(name, TypeKind::Struct(fields))
+    }
+
+    pub fn sum(name: impl Into<String>, variants: Vec<VariantDef>) -> Self {
+        Self::with_kind(name, TypeKind::Sum(variants))
+    }
+
+    fn with_kind(name: impl Into<String>, kind: TypeKind) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            pack: false,
+            tab: false,
+            open: false,
+            kind,
+        }
+    }
+
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn packed(mut self) -> Self {
+        self.pack = true;
+        self
+    }
+
+    pub fn tabular(mut self) -> Self {
+        self.tab = true;
+        self
+    }
+
+    pub fn open(mut self) -> Self {
+        self.open = true;
+        self
+    }
+
+    /// Declared fields; empty for a sum
+    pub fn fields(&self) -> &[FieldDef] {
+        match self.kind {
+            TypeKind::Struct(ref fields) => fields,
+            TypeKind::Sum(_) => &[],
+        }
+    }
+
+    /// Declared variants; empty for a record
+    pub fn variants(&self) -> &[VariantDef] {
+        match self.kind {
+            TypeKind::Sum(ref variants) => variants,
+            TypeKind::Struct(_) => &[],
+        }
+    }
+
+    /// The field `key` names, by name or wire key
+    pub fn field(&self, key: &str) -> Option<&FieldDef> {
+        self.fields().iter().find(|f| f.matches(key))
+    }
+
+    pub fn variant(&self, tag: &str) -> Option<&VariantDef> {
+        self.variants().iter().find(|v| v.tag == tag)
+    }
+
+    /// Fields in strict-form order: ascending `@fid` first, then the rest
+    /// by name
+    pub fn fields_by_fid(&self) -> Vec<&FieldDef> {
+        fields_by_fid(self.fields())
+    }
+}
+
+fn fields_by_fid(fields: &[FieldDef]) -> Vec<&FieldDef> {
+    let mut out: Vec<_> = fields.iter().collect();
+    out.sort_by(|a, b| match (a.fid, b.fid) {
+        (0, 0) => a.name.cmp(&b.name),
+        (0, _) => std::cmp::Ordering::Greater,
+        (_, 0) => std::cmp::Ordering::Less,
+        (x, y) => x.cmp(&y),
+    });
+    out
+}
+
+impl fmt::Display for TypeDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(ref v) = self.version {
+            write!(f, ":{}", v)?;
+        }
+        f.write_str(" ")?;
+        match self.kind {
+            TypeKind::Struct(ref fields) => {
+                for (on, flag) in [(self.pack, "@pack "), (self.tab, "@tab "), (self.open, "@open ")] {
+                    if on {
+                        f.write_str(flag)?;
+                    }
+                }
+                f.write_str("struct{\n")?;
+                for field in fields {
+                    writeln!(f, "    {}", field)?;
+                }
+            }
+            TypeKind::Sum(ref variants) => {
+                f.write_str("sum{\n")?;
+                for (i, v) in variants.iter().enumerate() {
+                    write!(f, "    {}: {}", v.tag, v.ty)?;
+                    if i + 1 < variants.len() {
+                        f.write_str(" |")?;
+                    }
+                    f.write_str("\n")?;
+                }
+            }
+        }
+        f.write_str("  }")
+    }
+}
+
+/// A set of named types
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    types: BTreeMap<String, TypeDef>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a type, replacing any of the same name
+    pub fn add_type(&mut self, def: TypeDef) {
+        self.types.insert(def.name.clone(), def);
+    }
+
+    pub fn with_type(mut self, def: TypeDef) -> Self {
+        self.add_type(def);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeDef> {
+        self.types.get(name)
+    }
+
+    /// Types in name order
+    pub fn types(&self) -> impl Iterator<Item = &TypeDef> {
+        self.types.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Parse schema text, with or without the `@schema{...}` wrapper
+    pub fn parse(text: &str) -> Result<Self, GlyphError> {
+        SchemaParser::new(text).schema()
+    }
+
+    /// Canonical schema text: types by name, one field per line
+    pub fn canonical(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("@schema{\n")?;
+        for def in self.types.values() {
+            writeln!(f, "  {}", def)?;
+        }
+        f.write_str("}")
+    }
+}
+
+impl FromStr for Schema {
+    type Err = GlyphError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Schema::parse(s)
+    }
+}
+
+// ============================================================
+// Schema text parser
+// ============================================================
+
+struct SchemaParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl SchemaParser {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn error(&self, msg: &str) -> GlyphError {
+        GlyphError::Parse(format!("schema: {} at offset {}", msg, self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Skip whitespace and commas
+    fn skip(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace() || c == ',') {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), GlyphError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, GlyphError> {
+        self.skip();
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_' || c == '.') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("expected a name"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// A quoted name, unescaped
+    fn quoted(&mut self) -> Result<String, GlyphError> {
+        let start = self.pos;
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => self.pos += 1,
+                '"' => {
+                    let text: String = self.chars[start..self.pos].iter().collect();
+                    return match parse_loose(&text)? {
+                        GValue::Str(s) => Ok(s.to_string()),
+                        _ => Err(self.error("bad quoted name")),
+                    };
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("unclosed '\"'"))
+    }
+
+    /// Text between a bracket at the cursor and its match, honouring nesting
+    /// and quoted strings
+    fn balanced(&mut self, open: char, close: char) -> Result<String, GlyphError> {
+        self.expect(open)?;
+        let start = self.pos;
+        let mut depth = 1;
+        let mut quoted = false;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if quoted {
+                match c {
+                    '\\' => self.pos += 1,
+                    '"' => quoted = false,
+                    _ => {}
+                }
+            } else if c == '"' {
+                quoted = true;
+            } else if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(self.chars[start..self.pos - 1].iter().collect());
+                }
+            }
+        }
+        Err(self.error(&format!("unclosed '{}'", open)))
+    }
+
+    fn schema(&mut self) -> Result<Schema, GlyphError> {
+        let mut schema = Schema::new();
+        self.skip();
+        let wrapped = self.chars[self.pos..].starts_with(&['@', 's', 'c', 'h', 'e', 'm', 'a']);
+        if wrapped {
+            self.pos += "@schema".len();
+            self.expect('{')?;
+        }
+        loop {
+            self.skip();
+            match self.peek() {
+                None if wrapped => return Err(self.error("unclosed '@schema{'")),
+                None => break,
+                Some('}') if wrapped => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => schema.add_type(self.type_def()?),
+            }
+        }
+        self.skip();
+        if self.peek().is_some() {
+            return Err(self.error("unexpected text after schema"));
+        }
+        Ok(schema)
+    }
+
+    fn type_def(&mut self) -> Result<TypeDef, GlyphError> {
+        let name = self.ident()?;
+        let version = if self.eat(':') { Some(self.ident()?) } else { None };
+        let (mut pack, mut tab, mut open) = (false, false, false);
+        while self.eat('@') {
+            match self.ident()?.as_str() {
+                "pack" => pack = true,
+                "tab" => tab = true,
+                "open" => open = true,
+                other => return Err(self.error(&format!("unknown type flag @{}", other))),
+            }
+        }
+        let kind = match self.ident()?.as_str() {
+            "struct" => TypeKind::Struct(self.struct_body()?),
+            "sum" => TypeKind::Sum(self.sum_body()?),
+            other => return Err(self.error(&format!("expected struct or sum, got {}", other))),
+        };
+        Ok(TypeDef {
+            name,
+            version,
+            pack,
+            tab,
+            open,
+            kind,
+        })
+    }
+
+    fn struct_body(&mut self) -> Result<Vec<FieldDef>, GlyphError> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        while !self.eat('}') {
+            if self.peek().is_none() {
+                return Err(self.error("unclosed struct"));
+            }
+            fields.push(self.field()?);
+        }
+        Ok(fields)
+    }
+
+    fn sum_body(&mut self) -> Result<Vec<VariantDef>, GlyphError> {
+        self.expect('{')?;
+        let mut variants = Vec::new();
+        while !self.eat('}') {
+            if self.peek().is_none() {
+                return Err(self.error("unclosed sum"));
+            }
+            let tag = self.ident()?;
+            self.expect(':')?;
+            variants.push(VariantDef::new(tag, self.type_spec()?));
+            self.eat('|');
+        }
+        Ok(variants)
+    }
+
+    fn field(&mut self) -> Result<FieldDef, GlyphError> {
+        self.skip();
+        let name = if self.peek() == Some('"') {
+            self.quoted()?
+        } else {
+            self.ident()?
+        };
+        self.expect(':')?;
+        let mut field = FieldDef::new(name, self.type_spec()?);
+        loop {
+            self.skip();
+            match self.peek() {
+                Some('[') => {
+                    let text = self.balanced('[', ']')?;
+                    match text.trim() {
+                        "optional" => field.optional = true,
+                        c => field.constraints.push(self.constraint(c)?),
+                    }
+                }
+                Some('@') => {
+                    self.pos += 1;
+                    match self.ident()?.as_str() {
+                        "keepnull" => field.keep_null = true,
+                        "fid" => {
+                            let n = self.balanced('(', ')')?;
+                            field.fid = n.trim().parse().map_err(|_| self.error("bad @fid"))?;
+                        }
+                        "k" => field.wire_key = Some(self.balanced('(', ')')?.trim().to_string()),
+                        "codec" => field.codec = Some(self.balanced('(', ')')?.trim().to_string()),
+                        "default" => field.default = Some(parse_loose(&self.balanced('(', ')')?)?),
+                        other => return Err(self.error(&format!("unknown field annotation @{}", other))),
+                    }
+                }
+                _ => return Ok(field),
+            }
+        }
+    }
+
+    fn type_spec(&mut self) -> Result<TypeSpec, GlyphError> {
+        Ok(match self.ident()?.as_str() {
+            "null" => TypeSpec::Null,
+            "bool" => TypeSpec::Bool,
+            "int" => TypeSpec::Int,
+            "float" => TypeSpec::Float,
+            "str" => TypeSpec::Str,
+            "bytes" => TypeSpec::Bytes,
+            "time" => TypeSpec::Time,
+            "id" => TypeSpec::Id,
+            "list" => {
+                self.expect('<')?;
+                let elem = self.type_spec()?;
+                self.expect('>')?;
+                TypeSpec::list(elem)
+            }
+            "map" => {
+                self.expect('<')?;
+                let key = self.type_spec()?;
+                let value = self.type_spec()?;
+                self.expect('>')?;
+                TypeSpec::map(key, value)
+            }
+            "struct" => TypeSpec::Struct(self.struct_body()?),
+            name => TypeSpec::Ref(name.to_string()),
+        })
+    }
+
+    fn constraint(&self, text: &str) -> Result<Constraint, GlyphError> {
+        let bad = || self.error(&format!("bad constraint [{}]", text));
+        let float = |s: &str| s.trim().parse::<f64>().map_err(|_| bad());
+        let size = |s: &str| s.trim().parse::<usize>().map_err(|_| bad());
+        Ok(if text == "nonempty" {
+            Constraint::NonEmpty
+        } else if text == "unique" {
+            Constraint::Unique
+        } else if let Some(n) = text.strip_prefix("len>=") {
+            Constraint::MinLen(size(n)?)
+        } else if let Some(n) = text.strip_prefix("len<=") {
+            Constraint::MaxLen(size(n)?)
+        } else if let Some(n) = text.strip_prefix("len=") {
+            Constraint::Len(size(n)?)
+        } else if let Some(n) = text.strip_prefix("min=") {
+            Constraint::Min(float(n)?)
+        } else if let Some(n) = text.strip_prefix("max=") {
+            Constraint::Max(float(n)?)
+        } else if let Some(re) = text.strip_prefix("regex=") {
+            match parse_loose(re)? {
+                GValue::Str(re) => Constraint::Regex(re.to_string()),
+                _ => return Err(bad()),
+            }
+        } else if let Some(values) = text.strip_prefix("enum=") {
+            let values = match parse_loose(values)? {
+                GValue::List(items) => items,
+                _ => return Err(bad()),
+            };
+            Constraint::Enum(
+                values
+                    .iter()
+                    .map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string()))
+                    .collect(),
+            )
+        } else if let Some((lo, hi)) = text.split_once("..") {
+            Constraint::Range(float(lo)?, float(hi)?)
+        } else {
+            return Err(bad());
+        })
+    }
+}
+
+// ============================================================
+// Strict-mode encoding
+// ============================================================
+
+const STRICT_NULL: &str = "∅";
+
+/// Canonical text of `v` with every record the schema knows written
+/// positionally.
+///
+/// Structs are matched by type name; anything under a typed field follows
+/// that field's type, so plain maps nested in a record pack too.
+pub fn canonicalize_strict(v: &GValue, schema: &Schema) -> Result<String, GlyphError> {
+    let mut buf = String::new();
+    StrictWriter { schema }.write(&mut buf, v, None)?;
+    Ok(buf)
+}
+
+/// `canonicalize_strict` with `v` read as the named type, for a top-level
+/// map that carries no type name of its own
+pub fn canonicalize_strict_as(v: &GValue, schema: &Schema, type_name: &str) -> Result<String, GlyphError> {
+    if schema.get(type_name).is_none() {
+        return Err(GlyphError::InvalidValue(format!("unknown type {}", type_name)));
+    }
+    let mut buf = String::new();
+    StrictWriter { schema }.write(&mut buf, v, Some(&TypeSpec::named(type_name)))?;
+    Ok(buf)
+}
+
+struct StrictWriter<'s> {
+    schema: &'s Schema,
+}
+
+impl StrictWriter<'_> {
+    fn write(&self, buf: &mut String, v: &GValue, ty: Option<&TypeSpec>) -> Result<(), GlyphError> {
+        // A struct's own type name wins over the slot it sits in
+        let own = match v {
+            GValue::Struct(s) => self.schema.get(&s.type_name),
+            _ => None,
+        };
+        let named = own.or_else(|| match ty {
+            Some(TypeSpec::Ref(name)) => self.schema.get(name),
+            _ => None,
+        });
+
+        match (v, named, ty) {
+            (GValue::Null, _, _) => buf.push_str(STRICT_NULL),
+            (GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }), Some(def), _) => {
+                let TypeKind::Struct(ref fields) = def.kind else {
+                    return Err(mismatch(&def.name, v));
+                };
+                self.write_record(buf, &def.name, fields, def.open, entries)?;
+            }
+            (GValue::Sum(s), Some(def), _) => {
+                let TypeKind::Sum(_) = def.kind else {
+                    return Err(mismatch(&def.name, v));
+                };
+                let variant = def.variant(&s.tag).ok_or_else(|| {
+                    GlyphError::InvalidValue(format!("{} has no variant {}", def.name, s.tag))
+                })?;
+                self.write_sum(buf, s, Some(&variant.ty))?;
+            }
+            (_, Some(def), Some(TypeSpec::Ref(_))) => return Err(mismatch(&def.name, v)),
+            (
+                GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }),
+                None,
+                Some(TypeSpec::Struct(fields)),
+            ) => {
+                self.write_record(buf, "", fields, false, entries)?;
+            }
+            (GValue::List(items), _, _) => {
+                let elem = match ty {
+                    Some(TypeSpec::List(elem)) => Some(&**elem),
+                    _ => None,
+                };
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(' ');
+                    }
+                    self.write(buf, item, elem)?;
+                }
+                buf.push(']');
+            }
+            (GValue::Map(entries), _, _) => {
+                let value_ty = match ty {
+                    Some(TypeSpec::Map(_, value)) => Some(&**value),
+                    _ => None,
+                };
+                self.write_keyed(buf, "", entries, |_| value_ty)?;
+            }
+            (GValue::Struct(s), _, _) => self.write_keyed(buf, &s.type_name, &s.fields, |_| None)?,
+            (GValue::Sum(s), _, _) => self.write_sum(buf, s, None)?,
+            _ => buf.push_str(&canonicalize_loose_no_tabular(v)?),
+        }
+        Ok(())
+    }
+
+    fn write_record(
+        &self,
+        buf: &mut String,
+        name: &str,
+        fields: &[FieldDef],
+        open: bool,
+        entries: &[MapEntry],
+    ) -> Result<(), GlyphError> {
+        if let Some(extra) = entries.iter().find(|e| !fields.iter().any(|f| f.matches(&e.key))) {
+            if !open {
+                return Err(GlyphError::InvalidValue(format!("{} has no field {}", type_label(name), extra.key)));
+            }
+            // Positions can't carry unnamed fields, so open records with
+            // extras keep their keys
+            return self.write_keyed(buf, name, entries, |key| {
+                fields.iter().find(|f| f.matches(key)).map(|f| &f.ty)
+            });
+        }
+
+        let value_of = |f: &FieldDef| entries.iter().find(|e| f.matches(&e.key)).map(|e| &e.value);
+        let present = |f: &FieldDef| value_of(f).is_some_and(|v| !v.is_null() || f.keep_null);
+        let ordered = fields_by_fid(fields);
+        let optionals: Vec<_> = ordered.iter().filter(|f| f.optional).collect();
+        let mask: Vec<bool> = optionals.iter().map(|f| present(f)).collect();
+
+        buf.push_str(name);
+        let slots: Vec<_> = if mask.iter().all(|&p| p) {
+            buf.push_str("@(");
+            ordered
+        } else {
+            buf.push_str("@{bm=");
+            buf.push_str(&mask_to_binary(&mask));
+            buf.push_str("}(");
+            // Required fields first, then the optionals the bitmap marks
+            let (optional, required): (Vec<_>, Vec<_>) = ordered.into_iter().partition(|f| f.optional);
+            required.into_iter().chain(optional.into_iter().filter(|f| present(f))).collect()
+        };
+        for (i, f) in slots.iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            // Optionals only get a slot when present
+            match value_of(f) {
+                Some(v) => self.write(buf, v, Some(&f.ty))?,
+                None => return Err(GlyphError::MissingField(format!("{}.{}", type_label(name), f.name))),
+            }
+        }
+        buf.push(')');
+        Ok(())
+    }
+
+    /// `Name{k=v ...}` with sorted keys, each value under its own type
+    fn write_keyed<'t>(
+        &self,
+        buf: &mut String,
+        name: &str,
+        entries: &[MapEntry],
+        ty_of: impl Fn(&str) -> Option<&'t TypeSpec>,
+    ) -> Result<(), GlyphError> {
+        let mut sorted: Vec<_> = entries.iter().collect();
+        sorted.sort_by(|a, b| a.key.cmp(&b.key));
+        buf.push_str(name);
+        buf.push('{');
+        for (i, e) in sorted.iter().enumerate() {
+            if i > 0 {
+                buf.push(' ');
+            }
+            buf.push_str(&canon_string(&e.key));
+            buf.push('=');
+            self.write(buf, &e.value, ty_of(&e.key))?;
+        }
+        buf.push('}');
+        Ok(())
+    }
+
+    fn write_sum(&self, buf: &mut String, s: &SumValue, ty: Option<&TypeSpec>) -> Result<(), GlyphError> {
+        buf.push_str(&s.tag);
+        buf.push('(');
+        if let Some(ref value) = s.value {
+            self.write(buf, value, ty)?;
+        }
+        buf.push(')');
+        Ok(())
+    }
+}
+
+fn type_label(name: &str) -> &str {
+    if name.is_empty() {
+        "struct"
+    } else {
+        name
+    }
+}
+
+fn mismatch(expected: &str, v: &GValue) -> GlyphError {
+    GlyphError::TypeMismatch {
+        expected: expected.to_string(),
+        got: v.gtype().to_string(),
+    }
+}
+
+/// Presence bits, first optional field least significant, minimal digits
+fn mask_to_binary(mask: &[bool]) -> String {
+    let digits: String = match mask.iter().rposition(|&p| p) {
+        Some(top) => mask[..=top].iter().rev().map(|&p| if p { '1' } else { '0' }).collect(),
+        None => "0".to_string(),
+    };
+    format!("0b{}", digits)
+}
+
+// ============================================================
+// Strict-mode decoding
+// ============================================================
+
+/// Decode text written by `canonicalize_strict`.
+///
+/// Positional records come back as structs named by their type, keyed by
+/// field name, with absent optionals left out; inline struct fields come back
+/// as maps. Ints in float slots become floats. Everything else reads as in
+/// `parse_loose`.
+pub fn parse_strict(text: &str, schema: &Schema) -> Result<GValue, GlyphError> {
+    let mut reader = StrictReader {
+        schema,
+        p: Parser::new(text),
+    };
+    let v = reader.read(None, 0)?;
+    reader.p.skip_ws();
+    if !reader.p.at_end() {
+        return Err(reader.p.err("unexpected trailing input"));
+    }
+    Ok(v)
+}
+
+struct StrictReader<'s, 'a> {
+    schema: &'s Schema,
+    p: Parser<'a>,
+}
+
+impl<'s> StrictReader<'s, '_> {
+    fn read(&mut self, ty: Option<&'s TypeSpec>, depth: usize) -> Result<GValue, GlyphError> {
+        if depth > MAX_PARSE_DEPTH {
+            return Err(GlyphError::RecursionLimitExceeded { limit: MAX_PARSE_DEPTH });
+        }
+        self.p.skip_ws();
+        let v = match self.p.peek() {
+            Some('[') => {
+                let elem = match ty {
+                    Some(TypeSpec::List(elem)) => Some(&**elem),
+                    _ => None,
+                };
+                self.p.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.p.skip_separators();
+                    if self.p.eat(']') {
+                        break GValue::List(items);
+                    }
+                    if self.p.at_end() {
+                        return Err(self.p.err("unterminated list"));
+                    }
+                    items.push(self.read(elem, depth + 1)?);
+                }
+            }
+            Some('{') => {
+                let value_ty = match ty {
+                    Some(TypeSpec::Map(_, value)) => Some(&**value),
+                    _ => None,
+                };
+                GValue::Map(self.read_keyed(|_| value_ty, depth)?)
+            }
+            Some('@') if !self.p.rest().starts_with("@tab") => {
+                let Some(TypeSpec::Struct(fields)) = ty else {
+                    return Err(self.p.err("positional record without a type name"));
+                };
+                GValue::Map(self.read_positional("", fields, depth)?)
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let start = self.p.pos;
+                let word = self.p.bare_token();
+                match self.p.peek() {
+                    Some('@') => {
+                        let fields = self.record_fields(word)?;
+                        GValue::Struct(StructValue::new(word, self.read_positional(word, fields, depth)?))
+                    }
+                    Some('{') => {
+                        // Open records with extra keys, or types the schema lacks
+                        let def = self.schema.get(word);
+                        let fields = def.map_or(&[][..], |d| d.fields());
+                        let entries = self.read_keyed(
+                            |key| fields.iter().find(|f| f.matches(key)).map(|f| &f.ty),
+                            depth,
+                        )?;
+                        GValue::Struct(StructValue::new(word, entries))
+                    }
+                    Some('(') => {
+                        let payload = match ty {
+                            Some(TypeSpec::Ref(name)) => {
+                                self.schema.get(name).and_then(|d| d.variant(word)).map(|v| &v.ty)
+                            }
+                            _ => None,
+                        };
+                        self.p.bump();
+                        self.p.skip_ws();
+                        let value = if self.p.eat(')') {
+                            None
+                        } else {
+                            let v = self.read(payload, depth + 1)?;
+                            self.p.skip_ws();
+                            if !self.p.eat(')') {
+                                return Err(self.p.err("expected ')'"));
+                            }
+                            Some(v)
+                        };
+                        GValue::Sum(SumValue::new(word, value))
+                    }
+                    _ => {
+                        self.p.pos = start;
+                        self.p.parse_value(depth)?
+                    }
+                }
+            }
+            _ => self.p.parse_value(depth)?,
+        };
+        Ok(match (v, ty) {
+            (GValue::Int(n), Some(TypeSpec::Float)) => GValue::Float(n as f64),
+            (v, _) => v,
+        })
+    }
+
+    fn record_fields(&self, name: &str) -> Result<&'s [FieldDef], GlyphError> {
+        match self.schema.get(name) {
+            Some(TypeDef { kind: TypeKind::Struct(fields), .. }) => Ok(fields),
+            Some(_) => Err(self.p.err(&format!("{} is not a struct", name))),
+            None => Err(self.p.err(&format!("unknown type {}", name))),
+        }
+    }
+
+    /// `@(v ...)` or `@{bm=0b...}(v ...)` after the type name
+    fn read_positional(&mut self, name: &str, fields: &'s [FieldDef], depth: usize) -> Result<Vec<MapEntry>, GlyphError> {
+        self.p.bump(); // @
+        let ordered = fields_by_fid(fields);
+        let slots: Vec<&FieldDef> = if self.p.rest().starts_with("{bm=") {
+            self.p.pos += "{bm=".len();
+            let bits = self.p.bare_token();
+            let digits = bits
+                .strip_prefix("0b")
+                .filter(|d| !d.is_empty() && d.bytes().all(|b| b == b'0' || b == b'1'))
+                .ok_or_else(|| self.p.err(&format!("invalid bitmap {:?}", bits)))?;
+            if !self.p.eat('}') {
+                return Err(self.p.err("expected '}' after bitmap"));
+            }
+            let mask: Vec<bool> = digits.bytes().rev().map(|b| b == b'1').collect();
+            let (optional, required): (Vec<_>, Vec<_>) = ordered.iter().copied().partition(|f| f.optional);
+            if mask.iter().skip(optional.len()).any(|&p| p) {
+                return Err(self.p.err(&format!(
+                    "bitmap {} marks more than the {} optional fields of {}",
+                    bits,
+                    optional.len(),
+                    type_label(name)
+                )));
+            }
+            let present = optional.into_iter().enumerate().filter(|(i, _)| mask.get(*i) == Some(&true));
+            required.into_iter().chain(present.map(|(_, f)| f)).collect()
+        } else {
+            ordered.clone()
+        };
+        if !self.p.eat('(') {
+            return Err(self.p.err("expected '('"));
+        }
+
+        let mut entries = Vec::new();
+        for f in slots {
+            self.p.skip_separators();
+            if self.p.peek() == Some(')') {
+                return Err(GlyphError::MissingField(format!("{}.{}", type_label(name), f.name)));
+            }
+            let v = self.read(Some(&f.ty), depth + 1)?;
+            // Dense records from other writers mark absent optionals with ∅
+            if !(v.is_null() && f.optional && !f.keep_null) {
+                entries.push(MapEntry::new(f.name.clone(), v));
+            }
+        }
+        self.p.skip_separators();
+        if !self.p.eat(')') {
+            return Err(self.p.err(&format!("too many values for {}", type_label(name))));
+        }
+        entries.sort_by_key(|e| ordered.iter().position(|f| f.name == e.key));
+        Ok(entries)
+    }
+
+    /// `{k=v ...}`, each value under its own type
+    fn read_keyed(
+        &mut self,
+        ty_of: impl Fn(&str) -> Option<&'s TypeSpec>,
+        depth: usize,
+    ) -> Result<Vec<MapEntry>, GlyphError> {
+        self.p.bump(); // {
+        let mut entries = Vec::new();
+        loop {
+            self.p.skip_separators();
+            if self.p.eat('}') {
+                return Ok(entries);
+            }
+            if self.p.at_end() {
+                return Err(self.p.err("unterminated map"));
+            }
+            let key = self.p.parse_key()?;
+            self.p.skip_ws();
+            if !self.p.eat('=') && !self.p.eat(':') {
+                return Err(self.p.err(&format!("expected '=' after key {:?}", key)));
+            }
+            let value = self.read(ty_of(&key), depth + 1)?;
+            entries.push(MapEntry::new(key, value));
+        }
+    }
+}
+
+// ============================================================
+// Validation
+// ============================================================
+
+/// Longest value snippet carried by a `ValidationError`, in chars
+const SNIPPET_CHARS: usize = 40;
+
+/// One place where a value doesn't fit its schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub path: GlyphPath,
+    /// A type (`int`, `Point`) or the constraint that failed (`len>=1`)
+    pub expected: String,
+    /// Canonical form of what was found, shortened; `(absent)` for a
+    /// missing field
+    pub actual: String,
+}
+
+impl ValidationError {
+    fn new(path: &GlyphPath, expected: impl Into<String>, actual: &GValue) -> Self {
+        Self {
+            path: path.clone(),
+            expected: expected.into(),
+            actual: snippet(actual),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: expected {}, got {}", self.path, self.expected, self.actual)
+    }
+}
+
+fn snippet(v: &GValue) -> String {
+    let text = canonicalize_loose_no_tabular(v).unwrap_or_else(|_| v.gtype().to_string());
+    if text.chars().count() <= SNIPPET_CHARS {
+        return text;
+    }
+    let mut short: String = text.chars().take(SNIPPET_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+/// Every place `v` breaks the schema, in document order.
+///
+/// Structs are checked against the type of the same name, and everything
+/// under a typed field against that field's type. Nulls pass anywhere but in
+/// a required field.
+pub fn validate(v: &GValue, schema: &Schema) -> Vec<ValidationError> {
+    let mut checker = Checker::new(schema);
+    checker.check(v, None, &GlyphPath::root());
+    checker.errors
+}
+
+/// `validate` with `v` read as the named type
+pub fn validate_as(v: &GValue, schema: &Schema, type_name: &str) -> Vec<ValidationError> {
+    let mut checker = Checker::new(schema);
+    checker.check(v, Some(&TypeSpec::named(type_name)), &GlyphPath::root());
+    checker.errors
+}
+
+struct Checker<'s> {
+    schema: &'s Schema,
+    errors: Vec<ValidationError>,
+    patterns: HashMap<String, Option<Regex>>,
+}
+
+impl<'s> Checker<'s> {
+    fn new(schema: &'s Schema) -> Self {
+        Self {
+            schema,
+            errors: Vec::new(),
+            patterns: HashMap::new(),
+        }
+    }
+
+    fn fail(&mut self, path: &GlyphPath, expected: impl Into<String>, actual: &GValue) {
+        self.errors.push(ValidationError::new(path, expected, actual));
+    }
+
+    /// Check `v` against `ty`, or only look inside it for known structs
+    fn check(&mut self, v: &GValue, ty: Option<&TypeSpec>, path: &GlyphPath) {
+        if let GValue::Table(t) = v {
+            return self.check(&t.to_list(), ty, path);
+        }
+        let Some(ty) = ty else {
+            return self.walk(v, path);
+        };
+        if v.is_null() {
+            return;
+        }
+        let ok = match ty {
+            TypeSpec::Null => false,
+            TypeSpec::Bool => v.is_bool(),
+            TypeSpec::Int => match v {
+                GValue::Int(_) => true,
+                GValue::Float(f) => f.fract() == 0.0,
+                _ => false,
+            },
+            TypeSpec::Float => matches!(v, GValue::Int(_) | GValue::Float(_)),
+            TypeSpec::Str | TypeSpec::Id => matches!(v, GValue::Str(_) | GValue::Id(_)),
+            TypeSpec::Bytes => v.is_bytes(),
+            TypeSpec::Time => v.is_time(),
+            TypeSpec::List(elem) => match v {
+                GValue::List(items) => {
+                    for (i, item) in items.iter().enumerate() {
+                        self.check(item, Some(elem), &path.index(i));
+                    }
+                    true
+                }
+                _ => false,
+            },
+            TypeSpec::Map(_, value) => match v {
+                GValue::Map(entries) => {
+                    for e in entries {
+                        self.check(&e.value, Some(value), &path.key(e.key.as_str()));
+                    }
+                    true
+                }
+                _ => false,
+            },
+            TypeSpec::Struct(fields) => match v {
+                GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+                    self.check_record("struct", fields, false, entries, path);
+                    true
+                }
+                _ => false,
+            },
+            TypeSpec::Ref(name) => return self.check_named(v, name, path),
+        };
+        if !ok {
+            self.fail(path, ty.to_string(), v);
+        }
+    }
+
+    fn check_named(&mut self, v: &GValue, name: &str, path: &GlyphPath) {
+        let schema = self.schema;
+        let Some(def) = schema.get(name) else {
+            self.errors.push(ValidationError {
+                path: path.clone(),
+                expected: name.to_string(),
+                actual: "(undefined type)".to_string(),
+            });
+            return;
+        };
+        match (&def.kind, v) {
+            (TypeKind::Struct(fields), GValue::Map(entries)) => {
+                self.check_record(name, fields, def.open, entries, path)
+            }
+            (TypeKind::Struct(fields), GValue::Struct(s)) if s.type_name == name => {
+                self.check_record(name, fields, def.open, &s.fields, path)
+            }
+            (TypeKind::Sum(_), GValue::Sum(s)) => match def.variant(&s.tag) {
+                Some(variant) => match s.value {
+                    Some(ref payload) => self.check(payload, Some(&variant.ty), &path.key(s.tag.as_str())),
+                    None if variant.ty == TypeSpec::Null => {}
+                    None => self.fail(&path.key(s.tag.as_str()), variant.ty.to_string(), &GValue::Null),
+                },
+                None => {
+                    let tags: Vec<_> = def.variants().iter().map(|v| v.tag.as_str()).collect();
+                    self.fail(path, format!("one of {}", tags.join("|")), v);
+                }
+            },
+            _ => self.fail(path, name, v),
+        }
+    }
+
+    fn check_record(&mut self, name: &str, fields: &[FieldDef], open: bool, entries: &[MapEntry], path: &GlyphPath) {
+        for f in fields {
+            let at = path.key(f.name.as_str());
+            match entries.iter().find(|e| f.matches(&e.key)) {
+                Some(e) if e.value.is_null() && !f.optional && f.ty != TypeSpec::Null => {
+                    self.fail(&at, f.ty.to_string(), &e.value);
+                }
+                Some(e) => {
+                    self.check(&e.value, Some(&f.ty), &at);
+                    if !e.value.is_null() {
+                        self.check_constraints(&e.value, &f.constraints, &at);
+                    }
+                }
+                None if f.optional => {}
+                None => self.errors.push(ValidationError {
+                    path: at,
+                    expected: f.ty.to_string(),
+                    actual: "(absent)".to_string(),
+                }),
+            }
+        }
+        if !open {
+            for e in entries.iter().filter(|e| !fields.iter().any(|f| f.matches(&e.key))) {
+                self.fail(&path.key(e.key.as_str()), format!("no such field in {}", name), &e.value);
+            }
+        }
+    }
+
+    fn check_constraints(&mut self, v: &GValue, constraints: &[Constraint], path: &GlyphPath) {
+        let number = match v {
+            GValue::Int(n) => Some(*n as f64),
+            GValue::Float(f) => Some(*f),
+            _ => None,
+        };
+        let len = match v {
+            GValue::Str(s) => Some(s.chars().count()),
+            GValue::Bytes(b) => Some(b.len()),
+            GValue::List(items) => Some(items.len()),
+            GValue::Map(entries) => Some(entries.len()),
+            _ => None,
+        };
+        for c in constraints {
+            let ok = match c {
+                Constraint::Min(min) => number.is_none_or(|n| n >= *min),
+                Constraint::Max(max) => number.is_none_or(|n| n <= *max),
+                Constraint::Range(lo, hi) => number.is_none_or(|n| *lo <= n && n <= *hi),
+                Constraint::MinLen(min) => len.is_none_or(|n| n >= *min),
+                Constraint::MaxLen(max) => len.is_none_or(|n| n <= *max),
+                Constraint::Len(want) => len.is_none_or(|n| n == *want),
+                Constraint::NonEmpty => len.is_none_or(|n| n > 0),
+                Constraint::Enum(values) => v.as_str().is_none_or(|s| values.iter().any(|e| e == s)),
+                Constraint::Regex(pattern) => match v.as_str() {
+                    Some(s) => self.pattern(pattern).is_some_and(|re| re.is_match(s)),
+                    None => true,
+                },
+                Constraint::Unique => {
+                    if let Some(items) = v.as_list() {
+                        let mut seen = HashSet::new();
+                        for (i, item) in items.iter().enumerate() {
+                            let key = canonicalize_loose_no_tabular(item).unwrap_or_default();
+                            if !seen.insert(key) {
+                                self.fail(&path.index(i), "unique", item);
+                            }
+                        }
+                    }
+                    true
+                }
+            };
+            if !ok {
+                self.fail(path, c.to_string(), v);
+            }
+        }
+    }
+
+    /// Compiled pattern, or `None` when it doesn't compile (nothing matches)
+    fn pattern(&mut self, pattern: &str) -> Option<&Regex> {
+        self.patterns
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(pattern).ok())
+            .as_ref()
+    }
+
+    /// Look for known structs in an untyped value
+    fn walk(&mut self, v: &GValue, path: &GlyphPath) {
+        match v {
+            GValue::Struct(s) if self.schema.get(&s.type_name).is_some() => {
+                self.check_named(v, &s.type_name, path)
+            }
+            GValue::Struct(StructValue { fields: entries, .. }) | GValue::Map(entries) => {
+                for e in entries {
+                    self.check(&e.value, None, &path.key(e.key.as_str()));
+                }
+            }
+            GValue::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.check(item, None, &path.index(i));
+                }
+            }
+            GValue::Sum(SumValue { tag, value: Some(payload) }) => self.check(payload, None, &path.key(tag.as_str())),
+            _ => {}
+        }
+    }
+}
+
+// ============================================================
+// Normalization and coercion
+// ============================================================
+
+/// Fill the declared default of every absent optional field, and fail with
+/// `MissingField` (naming its path) on the first absent required one.
+///
+/// Values are matched to types as in `validate`. An explicit null is not
+/// absent and is left alone. On error, fields visited before the failure
+/// are already filled.
+pub fn normalize(v: &mut GValue, schema: &Schema) -> Result<(), GlyphError> {
+    walk_typed(v, None, schema, &GlyphPath::root(), &mut Defaults)
+}
+
+/// `normalize` with `v` read as the named type
+pub fn normalize_as(v: &mut GValue, schema: &Schema, type_name: &str) -> Result<(), GlyphError> {
+    if schema.get(type_name).is_none() {
+        return Err(GlyphError::InvalidValue(format!("unknown type {}", type_name)));
+    }
+    walk_typed(v, Some(&TypeSpec::named(type_name)), schema, &GlyphPath::root(), &mut Defaults)
+}
+
+/// Which strings `validate_coerced` may turn into the type their slot
+/// expects. Nothing is coerced by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CoerceOpts {
+    /// `"5"` -> `5`
+    pub ints: bool,
+    /// `"2.5"` -> `2.5`
+    pub floats: bool,
+    /// `"true"` / `"false"`, any case
+    pub bools: bool,
+    /// RFC 3339 timestamps
+    pub times: bool,
+}
+
+impl CoerceOpts {
+    pub fn all() -> Self {
+        Self {
+            ints: true,
+            floats: true,
+            bools: true,
+            times: true,
+        }
+    }
+}
+
+/// A string replaced by the value its slot expects
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coercion {
+    pub path: GlyphPath,
+    pub from: GValue,
+    pub to: GValue,
+}
+
+impl fmt::Display for Coercion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.path, snippet(&self.from), snippet(&self.to))
+    }
+}
+
+/// What `validate_coerced` changed and what was still wrong after
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CoercionReport {
+    pub coercions: Vec<Coercion>,
+    pub errors: Vec<ValidationError>,
+}
+
+impl CoercionReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Coerce strings in typed slots as `opts` allows, then `validate` the
+/// result
+pub fn validate_coerced(v: &mut GValue, schema: &Schema, opts: CoerceOpts) -> CoercionReport {
+    let mut coerce = Coerce { opts, done: Vec::new() };
+    let _ = walk_typed(v, None, schema, &GlyphPath::root(), &mut coerce);
+    CoercionReport {
+        coercions: coerce.done,
+        errors: validate(v, schema),
+    }
+}
+
+/// `validate_coerced` with `v` read as the named type
+pub fn validate_coerced_as(v: &mut GValue, schema: &Schema, type_name: &str, opts: CoerceOpts) -> CoercionReport {
+    let mut coerce = Coerce { opts, done: Vec::new() };
+    let _ = walk_typed(v, Some(&TypeSpec::named(type_name)), schema, &GlyphPath::root(), &mut coerce);
+    CoercionReport {
+        coercions: coerce.done,
+        errors: validate_as(v, schema, type_name),
+    }
+}
+
+/// Hooks for a mutable walk that follows the schema's types
+trait TypedVisit {
+    /// `v` sits where `ty` is expected; called before its children
+    fn value(&mut self, _v: &mut GValue, _ty: &TypeSpec, _path: &GlyphPath) -> Result<(), GlyphError> {
+        Ok(())
+    }
+
+    /// A record, after its present fields were walked
+    fn record(&mut self, _entries: &mut Vec<MapEntry>, _fields: &[FieldDef], _path: &GlyphPath) -> Result<(), GlyphError> {
+        Ok(())
+    }
+}
+
+/// Walk `v` as `validate` does: typed slots follow their type, and untyped
+/// values are searched for structs the schema knows
+fn walk_typed<V: TypedVisit>(
+    v: &mut GValue,
+    ty: Option<&TypeSpec>,
+    schema: &Schema,
+    path: &GlyphPath,
+    visit: &mut V,
+) -> Result<(), GlyphError> {
+    if let (GValue::Table(t), Some(TypeSpec::List(_))) = (&*v, ty) {
+        *v = t.to_list();
+    }
+    if let Some(ty) = ty {
+        visit.value(v, ty, path)?;
+    }
+    let ty = match (ty, &*v) {
+        (None, GValue::Struct(s)) if schema.get(&s.type_name).is_some() => Some(TypeSpec::named(s.type_name.as_str())),
+        _ => ty.cloned(),
+    };
+    match (ty.as_ref(), v) {
+        (None, GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. })) => {
+            for e in entries {
+                walk_typed(&mut e.value, None, schema, &path.key(e.key.as_str()), visit)?;
+            }
+        }
+        (None, GValue::List(items)) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                walk_typed(item, None, schema, &path.index(i), visit)?;
+            }
+        }
+        (None, GValue::Sum(SumValue { tag, value: Some(payload) })) => {
+            walk_typed(payload, None, schema, &path.key(tag.as_str()), visit)?;
+        }
+        (Some(TypeSpec::List(elem)), GValue::List(items)) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                walk_typed(item, Some(elem), schema, &path.index(i), visit)?;
+            }
+        }
+        (Some(TypeSpec::Map(_, value)), GValue::Map(entries)) => {
+            for e in entries {
+                walk_typed(&mut e.value, Some(value), schema, &path.key(e.key.as_str()), visit)?;
+            }
+        }
+        (Some(TypeSpec::Struct(fields)), GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. })) => {
+            walk_record(entries, fields, schema, path, visit)?;
+        }
+        (Some(TypeSpec::Ref(name)), v) => match (schema.get(name).map(|d| &d.kind), v) {
+            (
+                Some(TypeKind::Struct(fields)),
+                GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }),
+            ) => walk_record(entries, fields, schema, path, visit)?,
+            (Some(TypeKind::Sum(variants)), GValue::Sum(s)) => {
+                let variant = variants.iter().find(|v| v.tag == s.tag);
+                if let (Some(variant), Some(payload)) = (variant, s.value.as_deref_mut()) {
+                    walk_typed(payload, Some(&variant.ty), schema, &path.key(s.tag.as_str()), visit)?;
+                }
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+fn walk_record<V: TypedVisit>(
+    entries: &mut Vec<MapEntry>,
+    fields: &[FieldDef],
+    schema: &Schema,
+    path: &GlyphPath,
+    visit: &mut V,
+) -> Result<(), GlyphError> {
+    for f in fields {
+        if let Some(e) = entries.iter_mut().find(|e| f.matches(&e.key)) {
+            walk_typed(&mut e.value, Some(&f.ty), schema, &path.key(f.name.as_str()), visit)?;
+        }
+    }
+    visit.record(entries, fields, path)
+}
+
+struct Defaults;
+
+impl TypedVisit for Defaults {
+    fn record(&mut self, entries: &mut Vec<MapEntry>, fields: &[FieldDef], path: &GlyphPath) -> Result<(), GlyphError> {
+        for f in fields {
+            if entries.iter().any(|e| f.matches(&e.key)) {
+                continue;
+            }
+            if !f.optional {
+                return Err(GlyphError::MissingField(path.key(f.name.as_str()).to_string()));
+            }
+            if let Some(ref default) = f.default {
+                entries.push(MapEntry::new(f.name.as_str(), default.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Coerce {
+    opts: CoerceOpts,
+    done: Vec<Coercion>,
+}
+
+impl TypedVisit for Coerce {
+    fn value(&mut self, v: &mut GValue, ty: &TypeSpec, path: &GlyphPath) -> Result<(), GlyphError> {
+        let GValue::Str(s) = &*v else {
+            return Ok(());
+        };
+        let text = s.trim();
+        let to = match ty {
+            TypeSpec::Int if self.opts.ints => text.parse().ok().map(GValue::Int),
+            TypeSpec::Float if self.opts.floats => {
+                text.parse::<f64>().ok().filter(|f| f.is_finite()).map(GValue::Float)
+            }
+            TypeSpec::Bool if self.opts.bools => match text.to_ascii_lowercase().as_str() {
+                "true" => Some(GValue::Bool(true)),
+                "false" => Some(GValue::Bool(false)),
+                _ => None,
+            },
+            TypeSpec::Time if self.opts.times => DateTime::parse_from_rfc3339(text)
+                .ok()
+                .map(|t| GValue::Time(t.with_timezone(&Utc))),
+            _ => None,
+        };
+        if let Some(to) = to {
+            let from = std::mem::replace(v, to.clone());
+            self.done.push(Coercion {
+                path: path.clone(),
+                from,
+                to,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHAPES: &str = "@schema{
+      Point struct{ x: int y: int }
+      User:v2 @pack struct{
+        id: int @fid(1)
+        name: str [len>=1] @fid(2) @k(n)
+        email: str [optional] @fid(3)
+        role: str [enum=[admin user]] @fid(4) @default(user) [optional]
+      }
+      Shape sum{ Circle: float | Poly: list<Point> }
+    }";
+
+    fn point(x: i64, y: i64) -> GValue {
+        GValue::build_map().int("x", x).int("y", y).finish()
+    }
+
+    #[test]
+    fn test_parse_and_canonical() {
+        let schema = Schema::parse(SHAPES).unwrap();
+        assert_eq!(schema.len(), 3);
+        let user = schema.get("User").unwrap();
+        assert_eq!(user.version.as_deref(), Some("v2"));
+        assert!(user.pack && !user.open);
+        let name = user.field("n").unwrap();
+        assert_eq!(name.name, "name");
+        assert_eq!(name.constraints, vec![Constraint::MinLen(1)]);
+        let role = user.field("role").unwrap();
+        assert!(role.optional);
+        assert_eq!(role.default, Some(GValue::str("user")));
+        assert_eq!(role.constraints, vec![Constraint::Enum(vec!["admin".into(), "user".into()])]);
+        assert_eq!(
+            schema.get("Shape").unwrap().variant("Poly").unwrap().ty,
+            TypeSpec::list(TypeSpec::named("Point"))
+        );
+
+        let text = schema.canonical();
+        assert_eq!(
+            text,
+            "@schema{\n  Point struct{\n    x: int\n    y: int\n  }\n  Shape sum{\n    Circle: float |\n    Poly: list<Point>\n  }\n  User:v2 @pack struct{\n    id: int @fid(1)\n    name: str [len>=1] @fid(2) @k(n)\n    email: str @fid(3) [optional]\n    role: str [enum=[admin user]] @fid(4) @default(user) [optional]\n  }\n}"
+        );
+        assert_eq!(text.parse::<Schema>().unwrap(), schema);
+    }
+
+    #[test]
+    fn test_parse_round_trips_every_feature() {
+        let schema = Schema::new()
+            .with_type(
+                TypeDef::record(
+                    "Cfg",
+                    vec![
+                        FieldDef::new("tags", TypeSpec::list(TypeSpec::Str))
+                            .with_constraint(Constraint::Unique)
+                            .with_constraint(Constraint::NonEmpty),
+                        FieldDef::new("rank", TypeSpec::Int).with_constraint(Constraint::Range(0.0, 100.0)),
+                        FieldDef::new("ratio", TypeSpec::Float)
+                            .with_constraint(Constraint::Min(0.5))
+                            .with_constraint(Constraint::Max(2.0)),
+                        FieldDef::new("code", TypeSpec::Str)
+                            .with_constraint(Constraint::Regex("^[a-z]+ \"x\"$".into()))
+                            .with_constraint(Constraint::Len(3))
+                            .with_constraint(Constraint::MaxLen(9)),
+                        FieldDef::new("meta", TypeSpec::map(TypeSpec::Str, TypeSpec::Time)),
+                        FieldDef::new(
+                            "pos",
+                            TypeSpec::Struct(vec![
+                                FieldDef::new("x", TypeSpec::Float),
+                                FieldDef::new("y", TypeSpec::Float).optional(),
+                            ]),
+                        ),
+                        FieldDef::new("tier", TypeSpec::Str).with_codec("dict").keep_null().optional(),
+                        FieldDef::new("owner", TypeSpec::Id).with_default(GValue::Null),
+                    ],
+                )
+                .open()
+                .tabular(),
+            )
+            .with_type(TypeDef::sum("Blob", vec![VariantDef::new("Raw", TypeSpec::Bytes)]));
+        let text = schema.to_string();
+        assert!(text.contains("[regex=\"^[a-z]+ \\\"x\\\"$\"]"), "{}", text);
+        assert!(text.contains("pos: struct{x: float, y: float [optional]}"), "{}", text);
+        assert_eq!(Schema::parse(&text).unwrap(), schema);
+
+        // Names that aren't identifiers are quoted
+        let odd = Schema::new().with_type(TypeDef::record("Flags", vec![FieldDef::new("dry-run", TypeSpec::Bool)]));
+        assert!(odd.to_string().contains("\"dry-run\": bool"));
+        assert_eq!(Schema::parse(&odd.to_string()).unwrap(), odd);
+
+        // The wrapper is optional
+        let bare = Schema::parse("Point struct{ x: int, y: int }").unwrap();
+        assert_eq!(bare.get("Point").unwrap().fields().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "@schema{ Point struct{ x: int }",
+            "Point struct{ x int }",
+            "Point record{ x: int }",
+            "Point struct{ x: int [min=a] }",
+            "Point struct{ x: int @frobnicate }",
+            "Point @sealed struct{ x: int }",
+            "@schema{ Point struct{ x: int } } trailing",
+        ] {
+            assert!(matches!(Schema::parse(bad), Err(GlyphError::Parse(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_strict() {
+        let schema = Schema::parse(SHAPES).unwrap();
+        assert_eq!(
+            canonicalize_strict(&GValue::struct_val("Point", vec![MapEntry::new("y", GValue::int(4)), MapEntry::new("x", GValue::int(3))]), &schema)
+                .unwrap(),
+            "Point@(3 4)"
+        );
+        assert_eq!(canonicalize_strict_as(&point(3, 4), &schema, "Point").unwrap(), "Point@(3 4)");
+
+        // Every optional present: dense
+        let full = GValue::build_map()
+            .int("id", 7)
+            .str("n", "ada")
+            .str("email", "a@x.io")
+            .str("role", "admin")
+            .finish();
+        assert_eq!(canonicalize_strict_as(&full, &schema, "User").unwrap(), "User@(7 ada \"a@x.io\" admin)");
+
+        // A missing optional switches to the bitmap form
+        let sparse = GValue::build_map().int("id", 7).str("name", "ada").str("role", "admin").finish();
+        assert_eq!(canonicalize_strict_as(&sparse, &schema, "User").unwrap(), "User@{bm=0b10}(7 ada admin)");
+        let bare = GValue::build_map().int("id", 7).str("name", "ada").value("email", GValue::Null).finish();
+        assert_eq!(canonicalize_strict_as(&bare, &schema, "User").unwrap(), "User@{bm=0b0}(7 ada)");
+
+        // Typed slots pack nested maps and sums; untyped ones stay loose
+        let s = schema.clone().with_type(TypeDef::record(
+            "Doc",
+            vec![
+                FieldDef::new("shape", TypeSpec::named("Shape")),
+                FieldDef::new("at", TypeSpec::list(TypeSpec::named("Point"))),
+                FieldDef::new("meta", TypeSpec::map(TypeSpec::Str, TypeSpec::named("Point"))),
+            ],
+        ));
+        let doc = GValue::build_map()
+            .value("shape", GValue::sum("Poly", Some(GValue::list(vec![point(0, 0), point(1, 2)]))))
+            .value("at", GValue::list(vec![point(5, 6), GValue::Null]))
+            .value("meta", GValue::build_map().value("o", point(0, 0)).finish())
+            .finish();
+        assert_eq!(
+            canonicalize_strict_as(&doc, &s, "Doc").unwrap(),
+            "Doc@([Point@(5 6) ∅] {o=Point@(0 0)} Poly([Point@(0 0) Point@(1 2)]))"
+        );
+        let loose = GValue::build_map()
+            .str("note", "a-b")
+            .value("items", GValue::list(vec![point(1, 2), point(3, 4), point(5, 6)]))
+            .finish();
+        assert_eq!(
+            canonicalize_strict(&loose, &schema).unwrap(),
+            "{items=[{x=1 y=2} {x=3 y=4} {x=5 y=6}] note=\"a-b\"}"
+        );
+
+        // Values that don't fit their slot
+        let mut bad = doc.clone();
+        bad.insert("shape", GValue::sum("Square", Some(GValue::int(2)))).unwrap();
+        assert!(matches!(canonicalize_strict_as(&bad, &s, "Doc"), Err(GlyphError::InvalidValue(_))));
+        bad.insert("shape", GValue::int(2)).unwrap();
+        assert_eq!(
+            canonicalize_strict_as(&bad, &s, "Doc").unwrap_err().to_string(),
+            "Type mismatch: expected Shape, got int"
+        );
+    }
+
+    #[test]
+    fn test_strict_field_order_and_errors() {
+        let schema = Schema::parse(
+            "Row struct{ b: int, a: int @fid(2), c: str @fid(1) } Open @open struct{ a: int, b: int [optional] }",
+        )
+        .unwrap();
+        let row = GValue::build_map().int("a", 1).int("b", 2).str("c", "x").finish();
+        // @fid fields lead in fid order, the rest follow by name
+        assert_eq!(canonicalize_strict_as(&row, &schema, "Row").unwrap(), "Row@(x 1 2)");
+
+        let missing = GValue::build_map().int("a", 1).str("c", "x").finish();
+        let err = canonicalize_strict_as(&missing, &schema, "Row").unwrap_err();
+        assert_eq!(err.to_string(), "Missing required field: Row.b");
+
+        let mut extra = row.clone();
+        extra.insert("d", GValue::int(4)).unwrap();
+        assert!(matches!(canonicalize_strict_as(&extra, &schema, "Row"), Err(GlyphError::InvalidValue(_))));
+
+        // Open records fall back to keys when they carry extras
+        let open = GValue::build_map().int("a", 1).int("z", 9).finish();
+        assert_eq!(canonicalize_strict_as(&open, &schema, "Open").unwrap(), "Open{a=1 z=9}");
+        assert_eq!(
+            canonicalize_strict_as(&GValue::build_map().int("a", 1).finish(), &schema, "Open").unwrap(),
+            "Open@{bm=0b0}(1)"
+        );
+        assert!(canonicalize_strict_as(&row, &schema, "Nope").is_err());
+
+        // The bitmap form lists required fields before present optionals
+        let mix = Schema::parse("Mix struct{ a: int [optional] @fid(1), b: int @fid(2), c: int [optional] @fid(3) }").unwrap();
+        let v = GValue::build_map().int("a", 1).int("b", 2).finish();
+        assert_eq!(canonicalize_strict_as(&v, &mix, "Mix").unwrap(), "Mix@{bm=0b1}(2 1)");
+        let mut all = v.clone();
+        all.insert("c", GValue::int(3)).unwrap();
+        assert_eq!(canonicalize_strict_as(&all, &mix, "Mix").unwrap(), "Mix@(1 2 3)");
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        let schema = Schema::parse(SHAPES).unwrap().with_type(TypeDef::record(
+            "Doc",
+            vec![
+                FieldDef::new("shape", TypeSpec::named("Shape")),
+                FieldDef::new("at", TypeSpec::list(TypeSpec::named("Point"))),
+                FieldDef::new("meta", TypeSpec::map(TypeSpec::Str, TypeSpec::named("Point"))),
+                FieldDef::new("box", TypeSpec::Struct(vec![FieldDef::new("w", TypeSpec::Float)])).optional(),
+            ],
+        ));
+        let point = |x, y| GValue::struct_val("Point", vec![MapEntry::new("x", GValue::int(x)), MapEntry::new("y", GValue::int(y))]);
+        assert_eq!(parse_strict("Point@(3 4)", &schema).unwrap(), point(3, 4));
+
+        // Records decode by position, then re-encode to the same text
+        for text in [
+            "User@(7 ada \"a@x.io\" admin)",
+            "User@{bm=0b10}(7 ada admin)",
+            "Doc@{bm=0b0}([Point@(5 6) ∅] {o=Point@(0 0)} Poly([Point@(0 0) Point@(1 2)]))",
+            "Doc@([] @(2.5) {} Circle(2.5))",
+        ] {
+            let v = parse_strict(text, &schema).unwrap();
+            assert_eq!(canonicalize_strict(&v, &schema).unwrap(), text);
+        }
+        let user = parse_strict("User@{bm=0b10}(7 ada admin)", &schema).unwrap();
+        assert_eq!(user.get("name").and_then(|v| v.as_str()), Some("ada"));
+        assert!(user.get("email").is_none());
+
+        // Slot types steer the scalars: ints read into float slots become floats
+        let doc = parse_strict("Doc@([] @(2) {} Circle(1))", &schema).unwrap();
+        assert_eq!(doc.get("shape"), Some(&GValue::sum("Circle", Some(GValue::float(1.0)))));
+        assert_eq!(doc.get("box"), Some(&GValue::build_map().value("w", GValue::float(2.0)).finish()));
+
+        // Dense records from other writers may mark absent optionals with ∅
+        let dense = parse_strict("User@(7 ada ∅ admin)", &schema).unwrap();
+        assert_eq!(dense, parse_strict("User@{bm=0b10}(7 ada admin)", &schema).unwrap());
+        // Open records with extras keep their keys
+        let open = Schema::parse("Open @open struct{ a: float }").unwrap();
+        assert_eq!(
+            parse_strict("Open{a=1 z=9}", &open).unwrap(),
+            GValue::struct_val("Open", vec![MapEntry::new("a", GValue::float(1.0)), MapEntry::new("z", GValue::int(9))])
+        );
+
+        let err = |text: &str| parse_strict(text, &schema).unwrap_err().to_string();
+        assert_eq!(err("User@(7)"), "Missing required field: User.name");
+        assert!(err("Point@(1 2 3)").contains("too many values for Point"));
+        assert!(err("Nope@(1)").contains("unknown type Nope"));
+        assert!(err("Shape@(1)").contains("Shape is not a struct"));
+        assert!(err("User@{bm=0b100}(7 ada)").contains("marks more than the 2 optional fields"));
+        assert!(err("User@{bm=2}(7 ada)").contains("invalid bitmap"));
+        assert!(err("[@(1)]").contains("positional record without a type name"));
+        assert!(err("Point@(1 2) x").contains("unexpected trailing input"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let schema = Schema::parse(
+            "@schema{
+              Call struct{
+                tool: str [enum=[search fetch]]
+                query: str [len>=1]
+                limit: int [1..50] [optional]
+                tags: list<str> [unique] [optional]
+                at: Point [optional]
+                shape: Shape [optional]
+              }
+              Point struct{ x: int y: int }
+              Shape sum{ Circle: float | Poly: list<Point> }
+            }",
+        )
+        .unwrap();
+        let ok = GValue::build_map()
+            .str("tool", "search")
+            .str("query", "weather")
+            .int("limit", 5)
+            .value("at", GValue::build_map().int("x", 1).float("y", 2.0).finish())
+            .value("shape", GValue::sum("Circle", Some(GValue::int(2))))
+            .finish();
+        assert_eq!(validate_as(&ok, &schema, "Call"), vec![]);
+
+        let bad = GValue::build_map()
+            .str("tool", "delete")
+            .str("query", "")
+            .float("limit", 99.5)
+            .value("tags", GValue::list(vec![GValue::str("a"), GValue::str("b"), GValue::str("a")]))
+            .value("at", GValue::build_map().str("x", "one").finish())
+            .value("shape", GValue::sum("Poly", Some(GValue::list(vec![GValue::int(1)]))))
+            .bool("dry_run", true)
+            .finish();
+        let errors: Vec<_> = validate_as(&bad, &schema, "Call").iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "tool: expected enum=[search fetch], got delete",
+                "query: expected len>=1, got \"\"",
+                "limit: expected int, got 99.5",
+                "limit: expected 1..50, got 99.5",
+                "tags[2]: expected unique, got a",
+                "at.x: expected int, got one",
+                "at.y: expected int, got (absent)",
+                "shape.Poly[0]: expected Point, got 1",
+                "dry_run: expected no such field in Call, got t",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_structs_and_snippets() {
+        let schema = Schema::parse("Point struct{ x: int y: int } Tag struct{ name: str [regex=\"^[a-z]+$\"] }").unwrap();
+        // Untyped values are searched for structs the schema knows
+        let doc = GValue::build_map()
+            .value(
+                "points",
+                GValue::list(vec![
+                    GValue::struct_val("Point", vec![MapEntry::new("x", GValue::int(1)), MapEntry::new("y", GValue::Null)]),
+                    GValue::struct_val("Other", vec![MapEntry::new("x", GValue::str("free"))]),
+                ]),
+            )
+            .value("tag", GValue::struct_val("Tag", vec![MapEntry::new("name", GValue::str("Hello World"))]))
+            .finish();
+        let errors = validate(&doc, &schema);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(errors[0].path.to_string(), "points[0].y");
+        assert_eq!(errors[0].expected, "int");
+        assert_eq!(errors[0].actual, "_");
+        assert_eq!(errors[1].to_string(), "tag.name: expected regex=\"^[a-z]+$\", got \"Hello World\"");
+
+        // Long values are cut short
+        let long = GValue::list((0..40).map(GValue::int).collect());
+        let errors = validate_as(&long, &schema, "Point");
+        assert_eq!(errors[0].path.to_string(), "$");
+        assert_eq!(errors[0].actual.chars().count(), SNIPPET_CHARS);
+        assert!(errors[0].actual.ends_with('…'));
+
+        // Inline records take maps or structs of any name
+        let inline = Schema::parse("Call struct{ pos: struct{x: float} }").unwrap();
+        let call = GValue::build_map()
+            .value("pos", GValue::struct_val("CallPos", vec![MapEntry::new("x", GValue::float(1.5))]))
+            .finish();
+        assert_eq!(validate_as(&call, &inline, "Call"), vec![]);
+        assert_eq!(canonicalize_strict_as(&call, &inline, "Call").unwrap(), "Call@(@(1.5))");
+
+        let errors = validate_as(&GValue::int(1), &schema, "Nope");
+        assert_eq!(errors[0].to_string(), "$: expected Nope, got (undefined type)");
+    }
+
+    #[test]
+    fn test_normalize() {
+        let schema = Schema::parse(
+            "@schema{
+              Call struct{
+                query: str
+                limit: int @default(10) [optional]
+                mode: str @default(fast) [optional] @k(m)
+                note: str [optional]
+                items: list<Item>
+                shape: Shape [optional]
+              }
+              Item struct{ name: str, done: bool @default(f) [optional] }
+              Shape sum{ Box: Item | Dot: null }
+            }",
+        )
+        .unwrap();
+        let mut v = parse_loose("{query=rain m=deep items=[{name=a} {name=b done=t}] shape=Box({name=c})}").unwrap();
+        normalize_as(&mut v, &schema, "Call").unwrap();
+        assert_eq!(
+            v.to_string(),
+            "{items=[{done=f name=a} {done=t name=b}] limit=10 m=deep query=rain shape=Box({done=f name=c})}"
+        );
+        // Already normal values don't change
+        let before = v.clone();
+        normalize_as(&mut v, &schema, "Call").unwrap();
+        assert_eq!(v, before);
+
+        // An explicit null stays null
+        let mut v = parse_loose("{query=rain limit=null items=[]}").unwrap();
+        normalize_as(&mut v, &schema, "Call").unwrap();
+        assert_eq!(v.to_string(), "{items=[] limit=_ mode=fast query=rain}");
+
+        // Missing required fields name their path
+        let mut v = parse_loose("{query=rain items=[{name=a} {done=t} {name=c}]}").unwrap();
+        let err = normalize_as(&mut v, &schema, "Call").unwrap_err();
+        assert!(matches!(err, GlyphError::MissingField(ref p) if p == "items[1].name"), "{}", err);
+        let mut v = GValue::build_map().value("items", GValue::list(vec![])).finish();
+        assert_eq!(normalize_as(&mut v, &schema, "Call").unwrap_err().to_string(), "Missing required field: query");
+
+        // Untyped values are searched for known structs; tabular lists work too
+        let mut doc = parse_loose("{calls=[Item{name=a} Item{name=b}]}").unwrap();
+        normalize(&mut doc, &schema).unwrap();
+        assert_eq!(doc.get("calls").unwrap().to_string(), "[Item{done=f name=a} Item{done=f name=b}]");
+        let table = GValue::table(["name"], vec![vec![GValue::str("x")], vec![GValue::str("y")], vec![GValue::str("z")]]);
+        let mut rows = GValue::build_map().str("query", "q").value("items", table).finish();
+        normalize_as(&mut rows, &schema, "Call").unwrap();
+        assert_eq!(rows.get("items").unwrap().index(2).unwrap().get("done"), Some(&GValue::bool(false)));
+        assert!(normalize_as(&mut rows, &schema, "Nope").is_err());
+    }
+
+    #[test]
+    fn test_validate_coerced() {
+        let schema = Schema::parse(
+            "Call struct{ limit: int, ratio: float, dry: bool, since: time [optional], tags: list<int> [optional], q: str }",
+        )
+        .unwrap();
+        let args = || {
+            parse_loose(
+                "{limit=\"5\" ratio=\" 2.5\" dry=TRUE since=\"2026-01-02T03:04:05+01:00\" tags=[\"1\" 2 \"x\"] q=\"7\"}",
+            )
+            .unwrap()
+        };
+
+        // Off by default: the strings are reported as they are
+        let mut v = args();
+        let report = validate_coerced_as(&mut v, &schema, "Call", CoerceOpts::default());
+        assert!(report.coercions.is_empty());
+        assert_eq!(report.errors.len(), 6);
+        assert_eq!(v, args());
+
+        let mut v = args();
+        let report = validate_coerced_as(&mut v, &schema, "Call", CoerceOpts::all());
+        let done: Vec<_> = report.coercions.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            done,
+            vec![
+                "limit: \"5\" -> 5",
+                "ratio: \" 2.5\" -> 2.5",
+                "dry: TRUE -> t",
+                "since: \"2026-01-02T03:04:05+01:00\" -> 2026-01-02T02:04:05Z",
+                "tags[0]: \"1\" -> 1",
+            ]
+        );
+        // Strings expected as strings stay, and unparseable ones are still errors
+        assert_eq!(v.get("q"), Some(&GValue::str("7")));
+        let errors: Vec<_> = report.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, vec!["tags[2]: expected int, got x"]);
+        assert!(!report.is_valid());
+
+        // Each kind is opt-in on its own
+        let mut v = args();
+        let report = validate_coerced(&mut v, &schema, CoerceOpts { bools: true, ..CoerceOpts::default() });
+        assert_eq!(report.coercions.len(), 0, "untyped maps aren't coerced");
+        let mut v = GValue::struct_val("Call", v.as_map().unwrap().to_vec());
+        let report = validate_coerced(&mut v, &schema, CoerceOpts { bools: true, ..CoerceOpts::default() });
+        assert_eq!(report.coercions.len(), 1);
+        assert_eq!(v.get("dry"), Some(&GValue::bool(true)));
+    }
+
+    #[test]
+    fn test_mask_to_binary() {
+        assert_eq!(mask_to_binary(&[]), "0b0");
+        assert_eq!(mask_to_binary(&[false, false]), "0b0");
+        assert_eq!(mask_to_binary(&[true, false, true]), "0b101");
+        assert_eq!(mask_to_binary(&[false, true, false]), "0b10");
+    }
+}
@@ -15,22 +15,17 @@ use regex::Regex;
 // ============================================================
 
 /// Schema evolution mode.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum EvolutionMode {
     /// Fail on unknown fields
     Strict,
     /// Ignore unknown fields (default)
+    #[default]
     Tolerant,
     /// Auto-migrate between versions
     Migrate,
 }
 
-impl Default for EvolutionMode {
-    fn default() -> Self {
-        EvolutionMode::Tolerant
-    }
-}
-
 // ============================================================
 // Field Types
 // ============================================================
@@ -0,0 +1,494 @@
+//! serde serialization into GValue
+//!
+//! `to_gvalue` converts any `Serialize` type into a `GValue`. By default Rust
+//! structs become anonymous maps, as with `serde_json`; `StructMode::Typed`
+//! keeps the type name (`SearchArgs{...}`) and turns enum variants into sums,
+//! so downstream schema validation can key off type names.
+
+use crate::error::*;
+use crate::types::*;
+use serde::ser::{self, Serialize};
+
+/// How Rust structs and enum variants are represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StructMode {
+    /// Structs become maps, variants become `"Name"` or `{Name=...}` (serde_json layout)
+    #[default]
+    Map,
+    /// Structs become `GValue::Struct` with their type name, variants become sums
+    Typed,
+}
+
+/// Options for `to_gvalue_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SerOpts {
+    pub struct_mode: StructMode,
+}
+
+impl SerOpts {
+    /// Options that keep Rust type names
+    pub fn typed() -> Self {
+        Self {
+            struct_mode: StructMode::Typed,
+        }
+    }
+}
+
+impl ser::Error for GlyphError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        GlyphError::InvalidValue(msg.to_string())
+    }
+}
+
+/// Serialize `value` into a GValue with structs as maps
+pub fn to_gvalue<T: Serialize + ?Sized>(value: &T) -> Result<GValue> {
+    to_gvalue_with(value, SerOpts::default())
+}
+
+/// Serialize `value` into a GValue with the given options
+pub fn to_gvalue_with<T: Serialize + ?Sized>(value: &T, opts: SerOpts) -> Result<GValue> {
+    value.serialize(Serializer { opts })
+}
+
+/// Serializer producing a `GValue`
+#[derive(Debug, Clone, Copy)]
+pub struct Serializer {
+    opts: SerOpts,
+}
+
+impl Serializer {
+    pub fn new(opts: SerOpts) -> Self {
+        Self { opts }
+    }
+
+    fn typed(&self) -> bool {
+        self.opts.struct_mode == StructMode::Typed
+    }
+
+    /// Wrap a variant's payload as a sum (typed) or a one-entry map
+    fn variant(&self, variant: &str, value: GValue) -> GValue {
+        if self.typed() {
+            GValue::sum(variant, Some(value))
+        } else {
+            GValue::map(vec![MapEntry::new(variant, value)])
+        }
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<GValue> {
+        Ok(GValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<GValue> {
+        Ok(GValue::Int(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<GValue> {
+        Ok(GValue::Int(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<GValue> {
+        Ok(GValue::Int(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<GValue> {
+        Ok(GValue::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<GValue> {
+        Ok(GValue::Int(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<GValue> {
+        Ok(GValue::Int(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<GValue> {
+        Ok(GValue::Int(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<GValue> {
+        // Same as the JSON bridge: out-of-range integers become floats
+        Ok(i64::try_from(v).map_or(GValue::Float(v as f64), GValue::Int))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<GValue> {
+        Ok(GValue::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<GValue> {
+        Ok(GValue::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<GValue> {
+        Ok(GValue::str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<GValue> {
+        Ok(GValue::str(v))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<GValue> {
+        Ok(GValue::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<GValue> {
+        Ok(GValue::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<GValue> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<GValue> {
+        Ok(GValue::Null)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<GValue> {
+        if self.typed() {
+            Ok(GValue::struct_val(name, vec![]))
+        } else {
+            Ok(GValue::Null)
+        }
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<GValue> {
+        if self.typed() {
+            Ok(GValue::sum(variant, None))
+        } else {
+            Ok(GValue::str(variant))
+        }
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<GValue> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<GValue> {
+        let inner = value.serialize(self)?;
+        Ok(self.variant(variant, inner))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer::new(self, len, None))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+        Ok(SeqSerializer::new(self, Some(len), None))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer> {
+        Ok(SeqSerializer::new(self, Some(len), None))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer> {
+        Ok(SeqSerializer::new(self, Some(len), Some(variant)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer::new(self, len.unwrap_or(0), MapKind::Map))
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<MapSerializer> {
+        let kind = if self.typed() { MapKind::Struct(name) } else { MapKind::Map };
+        Ok(MapSerializer::new(self, len, kind))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer> {
+        Ok(MapSerializer::new(self, len, MapKind::Variant(variant)))
+    }
+}
+
+/// Collects sequence, tuple, and tuple-variant elements
+pub struct SeqSerializer {
+    ser: Serializer,
+    items: Vec<GValue>,
+    variant: Option<&'static str>,
+}
+
+impl SeqSerializer {
+    fn new(ser: Serializer, len: Option<usize>, variant: Option<&'static str>) -> Self {
+        Self {
+            ser,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.items.push(value.serialize(self.ser)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<GValue> {
+        let list = GValue::List(self.items);
+        Ok(match self.variant {
+            Some(variant) => self.ser.variant(variant, list),
+            None => list,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+enum MapKind {
+    Map,
+    Struct(&'static str),
+    Variant(&'static str),
+}
+
+/// Collects map entries and struct fields
+pub struct MapSerializer {
+    ser: Serializer,
+    entries: Vec<MapEntry>,
+    pending_key: Option<String>,
+    kind: MapKind,
+}
+
+impl MapSerializer {
+    fn new(ser: Serializer, len: usize, kind: MapKind) -> Self {
+        Self {
+            ser,
+            entries: Vec::with_capacity(len),
+            pending_key: None,
+            kind,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<()> {
+        let value = value.serialize(self.ser)?;
+        self.entries.push(MapEntry::new(key, value));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<GValue> {
+        Ok(match self.kind {
+            MapKind::Map => GValue::Map(self.entries),
+            MapKind::Struct(name) => GValue::struct_val(name, self.entries),
+            MapKind::Variant(variant) => self.ser.variant(variant, GValue::Map(self.entries)),
+        })
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        let key = match key.serialize(self.ser)? {
+            GValue::Str(s) => s.to_string(),
+            GValue::Int(n) => n.to_string(),
+            GValue::Bool(b) => b.to_string(),
+            GValue::Sum(s) if s.value.is_none() => s.tag,
+            other => {
+                return Err(GlyphError::TypeMismatch {
+                    expected: "string map key".to_string(),
+                    got: other.gtype().to_string(),
+                })
+            }
+        };
+        self.pending_key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .pending_key
+            .take()
+            .ok_or_else(|| GlyphError::InvalidValue("map value without key".to_string()))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = GValue;
+    type Error = GlyphError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<GValue> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::canonicalize_loose;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct SearchArgs {
+        query: String,
+        limit: u32,
+        filter: Option<Filter>,
+        mode: Mode,
+    }
+
+    #[derive(Serialize)]
+    struct Filter {
+        tags: Vec<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    enum Mode {
+        Fast,
+        Near(f64),
+        Range { lo: i32, hi: i32 },
+    }
+
+    fn args(mode: Mode) -> SearchArgs {
+        SearchArgs {
+            query: "rust".to_string(),
+            limit: 10,
+            filter: Some(Filter { tags: vec!["a"] }),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_struct_mode_map() {
+        let v = to_gvalue(&args(Mode::Fast)).unwrap();
+        assert_eq!(
+            canonicalize_loose(&v).unwrap(),
+            "{filter={tags=[a]} limit=10 mode=Fast query=rust}"
+        );
+        let v = to_gvalue(&args(Mode::Range { lo: 1, hi: 2 })).unwrap();
+        assert!(canonicalize_loose(&v).unwrap().contains("mode={Range={hi=2 lo=1}}"));
+        // Same layout as serde_json
+        let json = serde_json::to_value(args(Mode::Near(0.5))).unwrap();
+        assert_eq!(v.is_map(), json.is_object());
+        assert_eq!(
+            canonicalize_loose(&to_gvalue(&args(Mode::Near(0.5))).unwrap()).unwrap(),
+            canonicalize_loose(&crate::from_json(&json)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_struct_mode_typed() {
+        let v = to_gvalue_with(&args(Mode::Near(0.5)), SerOpts::typed()).unwrap();
+        assert_eq!(v.as_struct().unwrap().type_name, "SearchArgs");
+        assert_eq!(
+            canonicalize_loose(&v).unwrap(),
+            "SearchArgs{filter=Filter{tags=[a]} limit=10 mode=Near(0.5) query=rust}"
+        );
+        let v = to_gvalue_with(&Mode::Range { lo: 1, hi: 2 }, SerOpts::typed()).unwrap();
+        assert_eq!(canonicalize_loose(&v).unwrap(), "Range({hi=2 lo=1})");
+        let v = to_gvalue_with(&Mode::Fast, SerOpts::typed()).unwrap();
+        assert_eq!(canonicalize_loose(&v).unwrap(), "Fast()");
+    }
+
+    #[test]
+    fn test_map_keys_and_scalars() {
+        let mut m = BTreeMap::new();
+        m.insert(2u8, "two");
+        m.insert(10u8, "ten");
+        let v = to_gvalue(&m).unwrap();
+        assert_eq!(canonicalize_loose(&v).unwrap(), "{\"10\"=ten \"2\"=two}");
+        assert_eq!(to_gvalue(&u64::MAX).unwrap(), GValue::Float(u64::MAX as f64));
+        assert_eq!(to_gvalue(&(1, "x")).unwrap(), GValue::list(vec![GValue::int(1), GValue::str("x")]));
+
+        let mut bad = BTreeMap::new();
+        bad.insert(vec![1], 1);
+        assert!(to_gvalue(&bad).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+//! HMAC-signed canonical payloads
+//!
+//! `sign_loose` computes HMAC-SHA256 over the no-tabular canonical form, so a
+//! signature survives re-encoding, key reordering and `1` vs `1.0`: anything
+//! `hash_loose` treats as the same value, including a `Table` and its row
+//! list. `SignedEnvelope` carries a payload and its signature together as
+//! `{payload=... sig=...}`.
+
+use crate::error::*;
+use crate::loose::{write_canon_loose, LooseCanonOpts};
+use crate::types::GValue;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const BLOCK_LEN: usize = 64;
+
+/// HMAC-SHA256 that takes its message as canonical text
+struct HmacWriter {
+    inner: Sha256,
+    outer_key: [u8; BLOCK_LEN],
+}
+
+impl HmacWriter {
+    fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            block[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let mut inner = Sha256::new();
+        inner.update(block.map(|b| b ^ 0x36));
+        Self {
+            inner,
+            outer_key: block.map(|b| b ^ 0x5c),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finish(self) -> [u8; 32] {
+        let mut outer = Sha256::new();
+        outer.update(self.outer_key);
+        outer.update(self.inner.finalize());
+        outer.finalize().into()
+    }
+}
+
+impl fmt::Write for HmacWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.update(s.as_bytes());
+        Ok(())
+    }
+}
+
+fn mac(v: &GValue, key: &[u8]) -> Result<[u8; 32]> {
+    let mut mac = HmacWriter::new(key);
+    write_canon_loose(&mut mac, v, &LooseCanonOpts::no_tabular())?;
+    Ok(mac.finish())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Get the 64-hex HMAC-SHA256 of `v`'s no-tabular canonical form under `key`.
+///
+/// Returns `Err(GlyphError::InvalidFloat)` if the value tree contains NaN or Inf.
+pub fn sign_loose(v: &GValue, key: &[u8]) -> Result<String> {
+    Ok(to_hex(&mac(v, key)?))
+}
+
+/// Check a `sign_loose` signature. Malformed signatures are `Ok(false)`.
+///
+/// The comparison takes the same time wherever the digests differ.
+pub fn verify_loose(v: &GValue, key: &[u8], sig: &str) -> Result<bool> {
+    let expected = mac(v, key)?;
+    let Some(given) = from_hex(&sig.to_ascii_lowercase()) else {
+        return Ok(false);
+    };
+    if given.len() != expected.len() {
+        return Ok(false);
+    }
+    Ok(given.iter().zip(&expected).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0)
+}
+
+// ============================================================
+// Envelope
+// ============================================================
+
+/// A payload with its `sign_loose` signature, written as `{payload=... sig=...}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedEnvelope {
+    pub payload: GValue,
+    pub sig: String,
+}
+
+impl SignedEnvelope {
+    /// Sign `payload` with `key`
+    pub fn sign(payload: GValue, key: &[u8]) -> Result<Self> {
+        let sig = sign_loose(&payload, key)?;
+        Ok(Self { payload, sig })
+    }
+
+    pub fn verify(&self, key: &[u8]) -> Result<bool> {
+        verify_loose(&self.payload, key, &self.sig)
+    }
+
+    /// The payload, if the signature checks out under `key`
+    pub fn open(self, key: &[u8]) -> Result<GValue> {
+        if !self.verify(key)? {
+            return Err(GlyphError::InvalidValue("envelope signature does not match payload".to_string()));
+        }
+        Ok(self.payload)
+    }
+
+    pub fn to_value(&self) -> GValue {
+        GValue::build_map()
+            .value("payload", self.payload.clone())
+            .str("sig", &self.sig)
+            .finish()
+    }
+
+    /// Read an envelope from `{payload=... sig=...}`; the signature is not checked
+    pub fn from_value(v: &GValue) -> Result<Self> {
+        let GValue::Map(entries) = v else {
+            return Err(GlyphError::TypeMismatch {
+                expected: "map".to_string(),
+                got: v.gtype().to_string(),
+            });
+        };
+        let field = |name: &str| {
+            entries
+                .iter()
+                .find(|e| e.key == name)
+                .map(|e| &e.value)
+                .ok_or_else(|| GlyphError::MissingField(name.to_string()))
+        };
+        let payload = field("payload")?.clone();
+        let sig = match field("sig")? {
+            GValue::Str(s) => s.to_string(),
+            other => {
+                return Err(GlyphError::TypeMismatch {
+                    expected: "str".to_string(),
+                    got: other.gtype().to_string(),
+                })
+            }
+        };
+        Ok(Self { payload, sig })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::{canonicalize_loose, equal_loose};
+    use crate::parse::{parse_loose, parse_loose_with, ParseOpts};
+
+    fn hmac_hex(key: &[u8], data: &[u8]) -> String {
+        let mut mac = HmacWriter::new(key);
+        mac.update(data);
+        to_hex(&mac.finish())
+    }
+
+    #[test]
+    fn test_hmac_rfc4231() {
+        assert_eq!(
+            hmac_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first
+        assert_eq!(
+            hmac_hex(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First"),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let a = GValue::build_map().int("n", 1).str("op", "transfer").finish();
+        let b = GValue::build_map().str("op", "transfer").float("n", 1.0).finish();
+        let sig = sign_loose(&a, b"k1").unwrap();
+        assert_eq!(sig.len(), 64);
+        assert!(verify_loose(&b, b"k1", &sig).unwrap());
+        assert!(verify_loose(&b, b"k1", &sig.to_uppercase()).unwrap());
+        assert!(!verify_loose(&b, b"k2", &sig).unwrap());
+        assert!(!verify_loose(&GValue::int(1), b"k1", &sig).unwrap());
+        assert!(!verify_loose(&a, b"k1", &sig[..62]).unwrap());
+        assert!(!verify_loose(&a, b"k1", "not hex").unwrap());
+        assert!(sign_loose(&GValue::float(f64::NAN), b"k1").is_err());
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let payload = GValue::build_map().str("op", "transfer").int("amount", 10).finish();
+        let env = SignedEnvelope::sign(payload.clone(), b"secret").unwrap();
+        let text = canonicalize_loose(&env.to_value()).unwrap();
+        assert!(text.starts_with("{payload={amount=10 op=transfer} sig="), "{}", text);
+
+        let read = SignedEnvelope::from_value(&parse_loose(&text).unwrap()).unwrap();
+        assert!(read.verify(b"secret").unwrap());
+        assert!(equal_loose(&read.clone().open(b"secret").unwrap(), &payload).unwrap());
+        assert!(read.open(b"other").is_err());
+
+        // A list of 3+ maps is sent as @tab; it verifies read back as a list or a Table
+        let row = |id: i64| GValue::build_map().int("id", id).str("s", "ok").finish();
+        let rows = GValue::build_map().value("rows", GValue::list(vec![row(1), row(2), row(3)])).finish();
+        let env = SignedEnvelope::sign(rows, b"secret").unwrap();
+        let text = canonicalize_loose(&env.to_value()).unwrap();
+        let read = SignedEnvelope::from_value(&parse_loose(&text).unwrap()).unwrap();
+        assert!(read.verify(b"secret").unwrap());
+        let read = SignedEnvelope::from_value(&parse_loose_with(&text, ParseOpts { tables: true }).unwrap()).unwrap();
+        assert!(read.payload.get("rows").unwrap().is_table(), "{}", text);
+        assert!(read.verify(b"secret").unwrap());
+
+        let mut tampered = env.clone();
+        tampered.payload = GValue::build_map().str("op", "transfer").int("amount", 1000).finish();
+        assert!(!tampered.verify(b"secret").unwrap());
+
+        assert!(SignedEnvelope::from_value(&GValue::int(1)).is_err());
+        let no_sig = GValue::build_map().value("payload", payload).finish();
+        assert!(matches!(SignedEnvelope::from_value(&no_sig), Err(GlyphError::MissingField(_))));
+    }
+}
@@ -0,0 +1,179 @@
+//! Value statistics
+//!
+//! When a tool result eats the context window, `stats` says where the bytes
+//! went: node counts by type, nesting depth, total string and byte-string
+//! payload, the encoded size, and the largest subtrees by path. Subtree
+//! sizes are each subtree's own canonical length, so they nest: a big field
+//! shows up with its ancestors above it.
+
+use crate::corpus::estimate_tokens;
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose_with_opts, estimate_size, LooseCanonOpts};
+use crate::path::GlyphPath;
+use crate::types::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Subtrees listed in `ValueStats::largest`
+pub const LARGEST_SUBTREES: usize = 10;
+
+/// Encoded size of one subtree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtreeSize {
+    pub path: GlyphPath,
+    pub bytes: usize,
+}
+
+/// What `stats` measures
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValueStats {
+    /// Every node, table cells included
+    pub nodes: usize,
+    pub by_type: BTreeMap<GType, usize>,
+    /// Deepest nesting; a scalar root is depth 0
+    pub max_depth: usize,
+    /// UTF-8 length of all strings, keys not included
+    pub string_bytes: usize,
+    /// Length of all byte strings, before base64
+    pub bytes_len: usize,
+    /// Canonical text length of the whole value
+    pub encoded_bytes: usize,
+    /// `estimate_tokens` of the canonical text
+    pub estimated_tokens: usize,
+    /// Largest subtrees below the root, biggest first
+    pub largest: Vec<SubtreeSize>,
+}
+
+/// Statistics of `v` under the default canonical options
+pub fn stats(v: &GValue) -> Result<ValueStats, GlyphError> {
+    stats_with(v, &LooseCanonOpts::default())
+}
+
+/// Statistics of `v`, sizes as canonicalized with `opts`
+pub fn stats_with(v: &GValue, opts: &LooseCanonOpts) -> Result<ValueStats, GlyphError> {
+    let text = canonicalize_loose_with_opts(v, opts)?;
+    let mut out = ValueStats {
+        encoded_bytes: text.len(),
+        estimated_tokens: estimate_tokens(&text),
+        ..ValueStats::default()
+    };
+    let mut subtrees = Vec::new();
+    measure(v, &mut GlyphPath::root(), 0, opts, &mut out, &mut subtrees)?;
+    subtrees.sort_by_key(|s| std::cmp::Reverse(s.bytes));
+    subtrees.truncate(LARGEST_SUBTREES);
+    out.largest = subtrees;
+    Ok(out)
+}
+
+fn measure(
+    v: &GValue,
+    path: &mut GlyphPath,
+    depth: usize,
+    opts: &LooseCanonOpts,
+    out: &mut ValueStats,
+    subtrees: &mut Vec<SubtreeSize>,
+) -> Result<(), GlyphError> {
+    out.nodes += 1;
+    *out.by_type.entry(v.gtype()).or_default() += 1;
+    out.max_depth = out.max_depth.max(depth);
+    if !path.is_root() {
+        subtrees.push(SubtreeSize {
+            path: path.clone(),
+            bytes: estimate_size(v, opts),
+        });
+    }
+    let mut child = |v: &GValue, path: &mut GlyphPath| measure(v, path, depth + 1, opts, out, subtrees);
+    match v {
+        GValue::Str(s) => out.string_bytes += s.len(),
+        GValue::Bytes(b) => out.bytes_len += b.len(),
+        GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+            for e in entries {
+                path.push_key(e.key.clone());
+                child(&e.value, path)?;
+                path.pop();
+            }
+        }
+        GValue::List(items) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push_index(i);
+                child(item, path)?;
+                path.pop();
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(inner) = &s.value {
+                path.push_key(s.tag.clone());
+                child(inner, path)?;
+                path.pop();
+            }
+        }
+        // Cells count a level deeper than their table, as the rows of the
+        // list it stands for would; the rows themselves are not nodes
+        GValue::Table(t) => {
+            for (i, row) in t.rows.iter().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    measure(cell, path, depth + 2, opts, out, subtrees)?;
+                    path.pop();
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+impl fmt::Display for ValueStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} nodes, depth {}, {}B encoded (~{} tokens), {}B in strings, {}B in bytes",
+            self.nodes, self.max_depth, self.encoded_bytes, self.estimated_tokens, self.string_bytes, self.bytes_len,
+        )?;
+        let types: Vec<String> = self.by_type.iter().map(|(t, n)| format!("{}={}", t.as_str(), n)).collect();
+        writeln!(f, "types: {}", types.join(" "))?;
+        for s in &self.largest {
+            let share = s.bytes as f64 * 100.0 / self.encoded_bytes.max(1) as f64;
+            writeln!(f, "{:>10}B {:>5.1}%  {}", s.bytes, share, s.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use serde_json::json;
+
+    #[test]
+    fn test_stats() {
+        let v = from_json(&json!({
+            "id": 7,
+            "doc": {"body": "x".repeat(200), "tags": ["a", "b"]},
+            "ok": true
+        }));
+        let s = stats(&v).unwrap();
+        assert_eq!(s.nodes, 8);
+        assert_eq!(s.by_type[&GType::Str], 3);
+        assert_eq!(s.by_type[&GType::Map], 2);
+        assert_eq!(s.max_depth, 3);
+        assert_eq!(s.string_bytes, 202);
+        assert_eq!(s.encoded_bytes, crate::canonicalize_loose(&v).unwrap().len());
+        let top: Vec<_> = s.largest.iter().take(2).map(|t| t.path.to_string()).collect();
+        assert_eq!(top, ["doc", "doc.body"]);
+        assert_eq!(s.largest[1].bytes, 200);
+        assert!(s.to_string().starts_with("8 nodes, depth 3,"));
+    }
+
+    #[test]
+    fn test_stats_tables() {
+        let t = GValue::table(["id", "name"], vec![vec![GValue::int(1), GValue::str("ann")]]);
+        let s = stats(&t).unwrap();
+        assert_eq!(s.nodes, 3);
+        assert_eq!(s.max_depth, 2);
+        assert_eq!(s.largest[0].path.to_string(), "[0].name");
+    }
+}
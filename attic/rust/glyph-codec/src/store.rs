@@ -0,0 +1,291 @@
+//! Content-addressed value store
+//!
+//! `GlyphStore` keeps values under their canonical fingerprint, so storing
+//! the same value twice is free and agents can refer to earlier tool results
+//! by hash. Entries are the no-tabular canonical text, the exact bytes the
+//! fingerprint covers, so reads are checked against their key.
+
+use crate::error::*;
+use crate::loose::*;
+use crate::parse::parse_loose;
+use crate::types::GValue;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Where a `GlyphStore` keeps its entries, keyed by fingerprint
+pub trait StoreBackend {
+    /// Entry text for `key`, if present
+    fn read(&self, key: &Fingerprint) -> Result<Option<String>>;
+    /// Store `text` under `key`; entries are immutable, so an existing key may be kept as is
+    fn write(&mut self, key: &Fingerprint, text: &str) -> Result<()>;
+    fn contains(&self, key: &Fingerprint) -> Result<bool> {
+        Ok(self.read(key)?.is_some())
+    }
+    /// Delete `key`, returning whether it was present
+    fn remove(&mut self, key: &Fingerprint) -> Result<bool>;
+}
+
+// ============================================================
+// Backends
+// ============================================================
+
+/// Entries held in a `HashMap`
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    entries: HashMap<Fingerprint, String>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl StoreBackend for MemoryBackend {
+    fn read(&self, key: &Fingerprint) -> Result<Option<String>> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &Fingerprint, text: &str) -> Result<()> {
+        self.entries.entry(key.clone()).or_insert_with(|| text.to_string());
+        Ok(())
+    }
+
+    fn contains(&self, key: &Fingerprint) -> Result<bool> {
+        Ok(self.entries.contains_key(key))
+    }
+
+    fn remove(&mut self, key: &Fingerprint) -> Result<bool> {
+        Ok(self.entries.remove(key).is_some())
+    }
+}
+
+/// One file per entry at `<root>/<algo>/<first 2 hex>/<hex>.glyph`
+#[derive(Debug, Clone)]
+pub struct FsBackend {
+    root: PathBuf,
+}
+
+impl FsBackend {
+    /// Use `root`, creating it if needed
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(&root)?;
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn entry_path(&self, key: &Fingerprint) -> PathBuf {
+        let hex = key.to_hex();
+        self.root
+            .join(key.algo.as_str())
+            .join(&hex[..2.min(hex.len())])
+            .join(format!("{}.glyph", hex))
+    }
+}
+
+impl StoreBackend for FsBackend {
+    fn read(&self, key: &Fingerprint) -> Result<Option<String>> {
+        match fs::read_to_string(self.entry_path(key)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&mut self, key: &Fingerprint, text: &str) -> Result<()> {
+        let path = self.entry_path(key);
+        if path.is_file() {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Write then rename, so readers never see a partial entry
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn contains(&self, key: &Fingerprint) -> Result<bool> {
+        Ok(self.entry_path(key).is_file())
+    }
+
+    fn remove(&mut self, key: &Fingerprint) -> Result<bool> {
+        match fs::remove_file(self.entry_path(key)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// ============================================================
+// Store
+// ============================================================
+
+/// Values stored under their `hash_loose_with` fingerprint
+#[derive(Debug, Clone)]
+pub struct GlyphStore<B: StoreBackend = MemoryBackend> {
+    backend: B,
+    algo: HashAlgo,
+}
+
+impl GlyphStore<MemoryBackend> {
+    /// Store kept in memory
+    pub fn in_memory() -> Self {
+        Self::new(MemoryBackend::new())
+    }
+}
+
+impl GlyphStore<FsBackend> {
+    /// Store kept in files under `root`
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(FsBackend::open(root)?))
+    }
+}
+
+impl<B: StoreBackend> GlyphStore<B> {
+    /// Store over `backend`, keyed by full SHA-256
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            algo: HashAlgo::Sha256,
+        }
+    }
+
+    /// Key new entries with `algo` instead
+    pub fn with_algo(mut self, algo: HashAlgo) -> Self {
+        self.algo = algo;
+        self
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Store `v` and return its key. Values equal under `equal_loose`
+    /// (outside tabular blocks) share one entry.
+    pub fn put(&mut self, v: &GValue) -> Result<Fingerprint> {
+        let text = canonicalize_loose_no_tabular(v)?;
+        let key = digest_text(&text, self.algo)?;
+        if !self.backend.contains(&key)? {
+            self.backend.write(&key, &text)?;
+        }
+        Ok(key)
+    }
+
+    /// The value stored under `key`.
+    ///
+    /// Fails if the entry no longer matches its key.
+    pub fn get(&self, key: &Fingerprint) -> Result<Option<GValue>> {
+        match self.get_text(key)? {
+            Some(text) => Ok(Some(parse_loose(&text)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The canonical text stored under `key`, checked against it
+    pub fn get_text(&self, key: &Fingerprint) -> Result<Option<String>> {
+        let Some(text) = self.backend.read(key)? else {
+            return Ok(None);
+        };
+        if digest_text(&text, key.algo)? != *key {
+            return Err(GlyphError::InvalidValue(format!("store entry {} does not match its key", key)));
+        }
+        Ok(Some(text))
+    }
+
+    pub fn contains(&self, key: &Fingerprint) -> Result<bool> {
+        self.backend.contains(key)
+    }
+
+    pub fn remove(&mut self, key: &Fingerprint) -> Result<bool> {
+        self.backend.remove(key)
+    }
+}
+
+/// 32-byte digest of canonical text, as `hash_loose_with(.., 32)` computes it
+fn digest_text(text: &str, algo: HashAlgo) -> Result<Fingerprint> {
+    let mut hasher = HashWriter::new(algo);
+    hasher.write_str(text).map_err(|e| GlyphError::Io(std::io::Error::other(e)))?;
+    hasher.finish(32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(n: i64) -> GValue {
+        GValue::build_map()
+            .str("tool", "search")
+            .value("hits", GValue::list((0..n).map(|i| GValue::build_map().int("id", i).finish()).collect()))
+            .finish()
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("glyph-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_memory_store_dedupes() {
+        let mut store = GlyphStore::in_memory();
+        let a = store.put(&result(3)).unwrap();
+        assert_eq!(a, hash_loose_with(&result(3), HashAlgo::Sha256, 32).unwrap());
+        assert_eq!(store.put(&result(3)).unwrap(), a);
+        let b = store.put(&result(4)).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(store.backend().len(), 2);
+
+        let got = store.get(&a).unwrap().unwrap();
+        assert!(equal_loose(&got, &result(3)).unwrap());
+        assert!(store.remove(&a).unwrap());
+        assert_eq!(store.get(&a).unwrap(), None);
+        assert!(!store.remove(&a).unwrap());
+
+        let mut b3 = GlyphStore::in_memory().with_algo(HashAlgo::Blake3);
+        let key = b3.put(&result(3)).unwrap();
+        assert_eq!(key.algo, HashAlgo::Blake3);
+        assert!(b3.contains(&key).unwrap());
+        assert!(!b3.contains(&a).unwrap());
+    }
+
+    #[test]
+    fn test_fs_store_roundtrip_and_integrity() {
+        let dir = scratch_dir("fs");
+        let mut store = GlyphStore::open(&dir).unwrap();
+        let key = store.put(&result(5)).unwrap();
+        let path = store.backend().entry_path(&key);
+        assert!(path.starts_with(dir.join("sha256")), "{}", path.display());
+
+        // A second store over the same directory sees the entry
+        let reopened = GlyphStore::open(&dir).unwrap();
+        assert!(equal_loose(&reopened.get(&key).unwrap().unwrap(), &result(5)).unwrap());
+
+        fs::write(&path, "{tampered=t}").unwrap();
+        let err = reopened.get(&key).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{}", err);
+
+        let missing = hash_loose_with(&GValue::int(1), HashAlgo::Sha256, 32).unwrap();
+        assert_eq!(reopened.get(&missing).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -12,6 +12,7 @@ use std::collections::HashMap;
 use std::fmt;
 use std::time::{Duration, Instant};
 use regex::Regex;
+use crate::lenient::{normalize_locale_number, Repair, RepairKind};
 
 // ============================================================
 // Tool Registry
@@ -303,6 +304,10 @@ pub struct StreamingValidator {
     max_buffer_size: usize,
     max_field_count: usize,
     max_error_count: usize,
+
+    // Lenient parsing
+    lenient_numbers: bool,
+    repairs: Vec<Repair>,
 }
 
 pub use crate::schema_evolution::FieldValue;
@@ -336,6 +341,8 @@ impl StreamingValidator {
             max_buffer_size: DEFAULT_MAX_BUFFER,
             max_field_count: DEFAULT_MAX_FIELDS,
             max_error_count: DEFAULT_MAX_ERRORS,
+            lenient_numbers: false,
+            repairs: Vec::new(),
         }
     }
 
@@ -357,6 +364,13 @@ impl StreamingValidator {
         self
     }
 
+    /// Accept localized numbers (`1,234.5`, `1.234,5`) and report each
+    /// normalization in `ValidationResult::repairs`.
+    pub fn with_lenient_numbers(mut self) -> Self {
+        self.lenient_numbers = true;
+        self
+    }
+
     fn add_error(&mut self, error: ValidationError) {
         if self.errors.len() >= self.max_error_count {
             return;
@@ -387,6 +401,7 @@ impl StreamingValidator {
         self.complete_at_token = 0;
         self.complete_at_time = Duration::ZERO;
         self.timeline.clear();
+        self.repairs.clear();
     }
 
     /// Start timing.
@@ -413,11 +428,10 @@ impl StreamingValidator {
         let elapsed = self.start_time.map(|t| t.elapsed()).unwrap_or(Duration::ZERO);
 
         // Record tool detection
-        if self.tool_name.is_some() && self.tool_detected_at_token == 0 {
+        if let (Some(tool_name), 0) = (self.tool_name.as_ref(), self.tool_detected_at_token) {
             self.tool_detected_at_token = self.token_count;
             self.tool_detected_at_time = elapsed;
 
-            let tool_name = self.tool_name.as_ref().unwrap();
             let allowed = self.registry.is_allowed(tool_name);
             self.timeline.push(TimelineEvent {
                 event: "TOOL_DETECTED".to_string(),
@@ -552,14 +566,10 @@ impl StreamingValidator {
                 self.depth -= 1;
                 self.current_val.push(c);
             }
-            '=' => {
-                if self.depth == 1 && !self.has_key {
-                    self.current_key = self.current_val.trim().to_string();
-                    self.current_val.clear();
-                    self.has_key = true;
-                } else {
-                    self.current_val.push(c);
-                }
+            '=' if self.depth == 1 && !self.has_key => {
+                self.current_key = self.current_val.trim().to_string();
+                self.current_val.clear();
+                self.has_key = true;
             }
             ' ' | '\n' | '\t' | '\r' => {
                 if self.depth == 1 && self.has_key && !self.current_val.is_empty() {
@@ -591,7 +601,7 @@ impl StreamingValidator {
             return;
         }
 
-        let value = self.parse_value(&val_str);
+        let value = self.parse_field_value(&key, &val_str);
 
         // Check for tool/action field
         if key == "action" || key == "tool" {
@@ -621,6 +631,26 @@ impl StreamingValidator {
         self.fields.insert(key, value);
     }
 
+    fn parse_field_value(&mut self, key: &str, s: &str) -> FieldValue {
+        let value = self.parse_value(s);
+        if !self.lenient_numbers || !matches!(value, FieldValue::Str(_)) {
+            return value;
+        }
+        match normalize_locale_number(s) {
+            Some(normalized) => {
+                let value = self.parse_value(&normalized);
+                self.repairs.push(Repair {
+                    field: key.to_string(),
+                    kind: RepairKind::LocaleNumber,
+                    original: s.to_string(),
+                    normalized,
+                });
+                value
+            }
+            None => value,
+        }
+    }
+
     fn parse_value(&self, s: &str) -> FieldValue {
         // Boolean
         if s == "t" || s == "true" {
@@ -631,7 +661,7 @@ impl StreamingValidator {
         }
 
         // Null
-        if s == "_" || s == "null" || s.is_empty() {
+        if s == "_" || s == "∅" || s == "null" || s.is_empty() {
             return FieldValue::Null;
         }
 
@@ -835,6 +865,7 @@ impl StreamingValidator {
             first_error_at_time: self.first_error_at_time,
             complete_at_token: self.complete_at_token,
             complete_at_time: self.complete_at_time,
+            repairs: self.repairs.clone(),
         }
     }
 
@@ -868,6 +899,7 @@ pub struct ValidationResult {
     pub first_error_at_time: Duration,
     pub complete_at_token: usize,
     pub complete_at_time: Duration,
+    pub repairs: Vec<Repair>,
 }
 
 // ============================================================
@@ -966,6 +998,28 @@ mod tests {
         assert!(result.errors.iter().any(|e| e.code == ErrorCode::ConstraintMax));
     }
 
+    #[test]
+    fn test_streaming_validator_lenient_numbers() {
+        let mut v = StreamingValidator::new(default_tool_registry()).with_lenient_numbers();
+        v.push_token("{action=search query=x max_results=1,000 price=1.234,5}");
+        let result = v.get_result();
+
+        assert_eq!(result.fields.get("max_results"), Some(&FieldValue::Int(1000)));
+        assert_eq!(result.fields.get("price"), Some(&FieldValue::Float(1234.5)));
+        assert_eq!(result.repairs.len(), 2);
+        assert_eq!(result.repairs[0].field, "max_results");
+        assert_eq!(result.repairs[0].original, "1,000");
+        assert_eq!(result.repairs[0].normalized, "1000");
+        assert!(result.errors.iter().any(|e| e.code == ErrorCode::ConstraintMax));
+
+        // Strict by default
+        let mut strict = StreamingValidator::new(default_tool_registry());
+        strict.push_token("{action=search query=x price=1,5}");
+        let result = strict.get_result();
+        assert_eq!(result.fields.get("price"), Some(&FieldValue::Str("1,5".to_string())));
+        assert!(result.repairs.is_empty());
+    }
+
     #[test]
     fn test_default_registry() {
         let registry = default_tool_registry();
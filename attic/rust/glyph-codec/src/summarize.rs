@@ -0,0 +1,234 @@
+//! Encode-time summaries of long strings
+//!
+//! A stack trace or a scraped HTML page can be most of a tool result and
+//! little of its meaning. A `Summarizer` is handed every string longer than
+//! `min_chars` characters, with its path, and may return shorter text to put
+//! in its place: a rule that keeps a trace's first and last frames, or a call
+//! to a small model. `summarize` runs one over a tree in place and
+//! `canonicalize_summarized` over a copy on its way to text.
+//!
+//! Model calls are usually async, so `AsyncSummarizer` returns a boxed future
+//! and `summarize_async` awaits each long string in turn. It needs no runtime
+//! of its own; every `Summarizer` is also an `AsyncSummarizer`.
+//!
+//! `LineSummarizer` is the built-in rule: the first and last lines of the
+//! text, with a count of those left out between them.
+
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::path::GlyphPath;
+use crate::types::*;
+use std::future::Future;
+use std::pin::Pin;
+
+/// A `min_chars` that leaves ordinary text alone and catches traces and pages
+pub const DEFAULT_SUMMARY_MIN_CHARS: usize = 2000;
+
+/// Rewrites long strings as summaries
+pub trait Summarizer {
+    /// A summary of `s` at `path`, or `None` to keep it as is
+    fn summarize(&self, path: &GlyphPath, s: &str) -> Option<String>;
+}
+
+impl<F> Summarizer for F
+where
+    F: Fn(&GlyphPath, &str) -> Option<String>,
+{
+    fn summarize(&self, path: &GlyphPath, s: &str) -> Option<String> {
+        self(path, s)
+    }
+}
+
+/// Future returned by `AsyncSummarizer::summarize`
+pub type SummaryFuture<'a> = Pin<Box<dyn Future<Output = Option<String>> + Send + 'a>>;
+
+/// A `Summarizer` that waits on something, such as a model call
+pub trait AsyncSummarizer {
+    /// A summary of `s` at `path`, or `None` to keep it as is
+    fn summarize<'a>(&'a self, path: &'a GlyphPath, s: &'a str) -> SummaryFuture<'a>;
+}
+
+impl<T: Summarizer + ?Sized> AsyncSummarizer for T {
+    fn summarize<'a>(&'a self, path: &'a GlyphPath, s: &'a str) -> SummaryFuture<'a> {
+        Box::pin(std::future::ready(Summarizer::summarize(self, path, s)))
+    }
+}
+
+/// Keeps the first `head` and last `tail` lines of text with more than
+/// `head + tail` lines, joined by `… N lines omitted …`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineSummarizer {
+    pub head: usize,
+    pub tail: usize,
+}
+
+impl Default for LineSummarizer {
+    fn default() -> Self {
+        Self { head: 10, tail: 5 }
+    }
+}
+
+impl Summarizer for LineSummarizer {
+    fn summarize(&self, _path: &GlyphPath, s: &str) -> Option<String> {
+        let lines: Vec<&str> = s.lines().collect();
+        if lines.len() <= self.head + self.tail {
+            return None;
+        }
+        let omitted = lines.len() - self.head - self.tail;
+        let mut out: Vec<String> = lines[..self.head].iter().map(|l| l.to_string()).collect();
+        out.push(format!("… {} lines omitted …", omitted));
+        out.extend(lines[lines.len() - self.tail..].iter().map(|l| l.to_string()));
+        Some(out.join("\n"))
+    }
+}
+
+/// Run `summarizer` on every string in `v` longer than `min_chars`
+/// characters, table cells as `rows[i].column`; returns how many it replaced.
+/// Keys are left alone.
+pub fn summarize<S: Summarizer + ?Sized>(v: &mut GValue, min_chars: usize, summarizer: &S) -> usize {
+    let mut changed = 0;
+    for (path, s) in long_strings(v, min_chars) {
+        if let Some(summary) = summarizer.summarize(&path, s) {
+            s.clear();
+            s.push_str(&summary);
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// `summarize` with an `AsyncSummarizer`, one string at a time
+pub async fn summarize_async<S: AsyncSummarizer + ?Sized>(v: &mut GValue, min_chars: usize, summarizer: &S) -> usize {
+    let mut changed = 0;
+    for (path, s) in long_strings(v, min_chars) {
+        if let Some(summary) = summarizer.summarize(&path, s).await {
+            s.clear();
+            s.push_str(&summary);
+            changed += 1;
+        }
+    }
+    changed
+}
+
+/// Canonical text of `v` with every string longer than `min_chars`
+/// characters passed through `summarizer`
+pub fn canonicalize_summarized<S: Summarizer + ?Sized>(
+    v: &GValue,
+    opts: &LooseCanonOpts,
+    min_chars: usize,
+    summarizer: &S,
+) -> Result<String, GlyphError> {
+    let mut v = v.clone();
+    summarize(&mut v, min_chars, summarizer);
+    canonicalize_loose_with_opts(&v, opts)
+}
+
+/// `canonicalize_summarized` with an `AsyncSummarizer`
+pub async fn canonicalize_summarized_async<S: AsyncSummarizer + ?Sized>(
+    v: &GValue,
+    opts: &LooseCanonOpts,
+    min_chars: usize,
+    summarizer: &S,
+) -> Result<String, GlyphError> {
+    let mut v = v.clone();
+    summarize_async(&mut v, min_chars, summarizer).await;
+    canonicalize_loose_with_opts(&v, opts)
+}
+
+/// Every string in `v` over `min_chars` characters, with its path
+fn long_strings(v: &mut GValue, min_chars: usize) -> Vec<(GlyphPath, &mut GStr)> {
+    let mut out = Vec::new();
+    collect(v, &mut GlyphPath::root(), min_chars, &mut out);
+    out
+}
+
+fn collect<'v>(v: &'v mut GValue, path: &mut GlyphPath, min_chars: usize, out: &mut Vec<(GlyphPath, &'v mut GStr)>) {
+    match v {
+        // A string has at least as many bytes as characters
+        GValue::Str(s) if s.len() > min_chars && s.chars().count() > min_chars => out.push((path.clone(), s)),
+        GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+            for e in entries {
+                path.push_key(e.key.clone());
+                collect(&mut e.value, path, min_chars, out);
+                path.pop();
+            }
+        }
+        GValue::List(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push_index(i);
+                collect(item, path, min_chars, out);
+                path.pop();
+            }
+        }
+        GValue::Sum(s) => {
+            if let Some(inner) = &mut s.value {
+                path.push_key(s.tag.clone());
+                collect(inner, path, min_chars, out);
+                path.pop();
+            }
+        }
+        GValue::Table(t) => {
+            for (i, row) in t.rows.iter_mut().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    collect(cell, path, min_chars, out);
+                    path.pop();
+                }
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use serde_json::json;
+    use std::task::{Context, Poll, Waker};
+
+    fn trace(frames: usize) -> String {
+        (0..frames).map(|i| format!("  at frame{}", i)).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn test_line_summarizer() {
+        let mut v = from_json(&json!({"error": trace(40), "short": trace(20), "tags": ["ok"]}));
+        let lines = LineSummarizer { head: 2, tail: 1 };
+        assert_eq!(summarize(&mut v, 300, &lines), 1);
+        assert_eq!(
+            v["error"],
+            GValue::str("  at frame0\n  at frame1\n… 37 lines omitted …\n  at frame39")
+        );
+        // Under the threshold
+        assert_eq!(v["short"], GValue::str(trace(20)));
+    }
+
+    struct Model;
+
+    impl AsyncSummarizer for Model {
+        fn summarize<'a>(&'a self, path: &'a GlyphPath, s: &'a str) -> SummaryFuture<'a> {
+            Box::pin(async move { Some(format!("{}: {} chars", path, s.chars().count())) })
+        }
+    }
+
+    #[test]
+    fn test_async_summarizer() {
+        let v = from_json(&json!({"page": "é".repeat(50), "rows": [{"html": "x".repeat(60)}]}));
+        let opts = LooseCanonOpts::default();
+        let mut fut = std::pin::pin!(canonicalize_summarized_async(&v, &opts, 40, &Model));
+        // Nothing here actually waits, so one poll finishes it
+        let Poll::Ready(text) = fut.as_mut().poll(&mut Context::from_waker(Waker::noop())) else {
+            panic!("summary still pending");
+        };
+        assert_eq!(text.unwrap(), "{page=\"page: 50 chars\" rows=[{html=\"rows[0].html: 60 chars\"}]}");
+
+        // 50 characters but 100 bytes: counted in characters
+        let mut v = v.clone();
+        let closure = |_: &GlyphPath, _: &str| Some("long".to_string());
+        assert_eq!(summarize(&mut v, 55, &closure), 1);
+        assert_eq!(v["rows"][0]["html"], GValue::str("long"));
+    }
+}
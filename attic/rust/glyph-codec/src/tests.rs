@@ -1,5 +1,7 @@
 //! Tests for GLYPH codec
 
+#![allow(clippy::approx_constant)]
+
 use crate::*;
 use serde_json::json;
 
@@ -139,6 +141,83 @@ fn test_equality() {
     assert!(equal_loose(&a, &b).unwrap(), "Same data, different order should be equal");
 }
 
+#[test]
+fn test_equality_matches_canonical_form() {
+    use chrono::TimeZone;
+    let m = |pairs: &[(&str, GValue)]| GValue::map(pairs.iter().map(|(k, v)| MapEntry::new(*k, v.clone())).collect());
+    let t = chrono::Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    let row = |id: i64| m(&[("id", GValue::int(id))]);
+    let values = vec![
+        GValue::null(),
+        GValue::bool(false),
+        GValue::int(0),
+        GValue::int(1),
+        GValue::int(1_000_000),
+        GValue::float(-0.0),
+        GValue::float(1.0),
+        GValue::float(1.5),
+        GValue::float(1e6),
+        GValue::float(1e20),
+        GValue::str("a"),
+        GValue::str("1"),
+        GValue::str("t"),
+        GValue::bytes(vec![1, 2]),
+        GValue::time(t),
+        GValue::time(t + chrono::Duration::milliseconds(500)),
+        GValue::id("a", "b"),
+        GValue::id("", "a:b"),
+        GValue::id("", "b"),
+        m(&[("a", GValue::int(1)), ("b", GValue::int(2))]),
+        m(&[("b", GValue::int(2)), ("a", GValue::int(1))]),
+        m(&[("a", GValue::int(1)), ("a", GValue::int(2))]),
+        m(&[("a", GValue::int(2)), ("a", GValue::int(1))]),
+        GValue::struct_val("", vec![MapEntry::new("a", GValue::int(1)), MapEntry::new("b", GValue::int(2))]),
+        GValue::struct_val("P", vec![MapEntry::new("a", GValue::int(1))]),
+        GValue::sum("Ok", Some(GValue::int(1))),
+        GValue::sum("Ok", Some(GValue::float(1.0))),
+        GValue::sum("Ok", None),
+        GValue::list(vec![GValue::int(1), GValue::float(1.0)]),
+        GValue::list(vec![GValue::float(1.0), GValue::int(1)]),
+        GValue::list(vec![row(1), row(2)]),
+        GValue::list(vec![row(1), row(2), row(3)]),
+        GValue::list(vec![row(1), m(&[("id", GValue::int(2)), ("x", GValue::null())]), row(3)]),
+        GValue::list(vec![
+            m(&[("id", GValue::int(1)), ("x", GValue::null())]),
+            m(&[("id", GValue::int(2)), ("x", GValue::null())]),
+            m(&[("id", GValue::int(3)), ("x", GValue::null())]),
+        ]),
+        GValue::table(["id"], vec![vec![GValue::int(1)], vec![GValue::int(2)], vec![GValue::int(3)]]),
+        GValue::table(["id"], vec![vec![GValue::int(1)], vec![GValue::int(2)]]),
+        GValue::Table(
+            TableValue::new(vec!["id".to_string()], vec![vec![GValue::int(1)], vec![GValue::int(2)], vec![GValue::int(3)]])
+                .with_types(vec![Some(GType::Int)]),
+        ),
+        GValue::table(["id", "x"], vec![vec![GValue::int(1), GValue::null()], vec![GValue::int(2), GValue::null()], vec![GValue::int(3), GValue::null()]]),
+        GValue::list(vec![row(1), row(2), row(4)]),
+        GValue::list((1..=3).map(|id| GValue::struct_val("P", vec![MapEntry::new("id", GValue::int(id))])).collect()),
+        // Inside cells a table prints as its rows
+        GValue::list((1..=3).map(|id| m(&[("id", GValue::int(id)), ("v", GValue::table(["a"], vec![vec![GValue::int(1)]]))])).collect()),
+        GValue::list((1..=3).map(|id| m(&[("id", GValue::int(id)), ("v", GValue::list(vec![m(&[("a", GValue::int(1))])]))])).collect()),
+    ];
+    for a in &values {
+        for b in &values {
+            let canonical = canonicalize_loose(a).unwrap() == canonicalize_loose(b).unwrap();
+            assert_eq!(equal_loose(a, b).unwrap(), canonical, "{:?} vs {:?}", a, b);
+        }
+    }
+
+    assert!(equal_loose(&GValue::float(f64::NAN), &GValue::int(1)).is_err());
+    // Different kinds and differing rows are told apart without the text,
+    // so a NaN past the difference is never reached
+    let rows_nan = GValue::list(vec![row(1), row(2), m(&[("id", GValue::float(f64::NAN))])]);
+    assert!(!equal_loose(&GValue::map(vec![MapEntry::new("id", GValue::int(1))]), &rows_nan).unwrap());
+    assert!(!equal_loose(&GValue::list(vec![row(1), row(3), row(3)]), &rows_nan).unwrap());
+    assert!(equal_loose(&GValue::list(vec![row(1), row(2), row(3)]), &rows_nan).is_err());
+    // Stops at the first difference
+    let late_nan = GValue::list(vec![GValue::int(1), GValue::float(f64::NAN)]);
+    assert!(!equal_loose(&GValue::list(vec![GValue::int(2), GValue::int(0)]), &late_nan).unwrap());
+}
+
 #[test]
 fn test_fingerprint_deterministic() {
     let data = json!({"a": 1, "b": [2, 3]});
@@ -160,6 +239,384 @@ fn test_hash_loose() {
     assert!(h.chars().all(|c| c.is_ascii_hexdigit()), "hash must be hex: {}", h);
 }
 
+#[test]
+fn test_hash_loose_with() {
+    let gv = from_json(&json!({"test": "value", "n": [1, 2, 3]}));
+    let sha = hash_loose_with(&gv, HashAlgo::Sha256, 32).unwrap();
+    assert_eq!(sha.to_hex(), hash_loose(&gv).unwrap());
+    assert_eq!(sha.to_string(), format!("sha256:{}", sha.to_hex()));
+
+    let short = hash_loose_with(&gv, HashAlgo::Sha256, 8).unwrap();
+    assert_eq!(short.digest, sha.digest[..8]);
+
+    let b3 = hash_loose_with(&gv, HashAlgo::Blake3, 32).unwrap();
+    assert_eq!(b3.len(), 32);
+    assert_ne!(b3.digest, sha.digest);
+    let b3_long = hash_loose_with(&gv, HashAlgo::Blake3, 64).unwrap();
+    assert_eq!(b3_long.digest[..32], b3.digest[..]);
+    assert_eq!(b3_long.to_string().parse::<Fingerprint>().unwrap(), b3_long);
+
+    assert!(hash_loose_with(&gv, HashAlgo::Sha256, 33).is_err());
+    assert!(hash_loose_with(&gv, HashAlgo::Blake3, 0).is_err());
+    for bad in ["abcd", "md5:abcd", "sha256:abc", "sha256:zz", "sha256:"] {
+        assert!(Fingerprint::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_mutation_api() {
+    let mut gv = from_json(&json!({"args": {"query": "rust"}, "tags": ["a"]}));
+    assert_eq!(gv.insert("limit", GValue::int(10)).unwrap(), None);
+    assert_eq!(gv.insert("limit", GValue::int(20)).unwrap(), Some(GValue::int(10)));
+    assert_eq!(gv.get_path("tags").unwrap().as_list().map(|l| l.len()), Some(1));
+    gv.get_path_mut("tags").unwrap().push(GValue::str("b")).unwrap();
+
+    assert_eq!(gv.set_path("args.query", GValue::str("go")).unwrap(), Some(GValue::str("rust")));
+    assert_eq!(gv.set_path("args.lang", GValue::str("en")).unwrap(), None);
+    assert_eq!(gv.set_path("tags[1]", GValue::str("c")).unwrap(), Some(GValue::str("b")));
+    assert_eq!(
+        canonicalize_loose(&gv).unwrap(),
+        "{args={lang=en query=go} limit=20 tags=[a c]}"
+    );
+
+    assert_eq!(gv.remove("limit").unwrap(), Some(GValue::int(20)));
+    assert_eq!(gv.remove("limit").unwrap(), None);
+
+    // Errors leave the value alone
+    let before = gv.clone();
+    assert!(matches!(gv.push(GValue::null()), Err(GlyphError::TypeMismatch { .. })));
+    assert!(matches!(GValue::int(1).insert("k", GValue::null()), Err(GlyphError::TypeMismatch { .. })));
+    assert!(matches!(GValue::str("s").remove("k"), Err(GlyphError::TypeMismatch { .. })));
+    assert!(matches!(gv.set_path("args.query.x", GValue::null()), Err(GlyphError::TypeMismatch { .. })));
+    assert!(matches!(gv.set_path("args[0]", GValue::null()), Err(GlyphError::TypeMismatch { .. })));
+    assert!(matches!(gv.set_path("tags[5]", GValue::null()), Err(GlyphError::InvalidValue(_))));
+    assert!(matches!(gv.set_path("nope.x", GValue::null()), Err(GlyphError::InvalidValue(_))));
+    assert!(matches!(gv.set_path("a[", GValue::null()), Err(GlyphError::Parse(_))));
+    assert_eq!(gv, before);
+
+    let old = gv.set_path("$", GValue::int(1)).unwrap();
+    assert_eq!(old, Some(before));
+    assert_eq!(gv, GValue::int(1));
+}
+
+#[test]
+fn test_index_operators() {
+    let mut gv = from_json(&json!({"args": {"query": "rust"}, "hits": [1, 2]}));
+    assert_eq!(gv["args"]["query"], GValue::str("rust"));
+    assert_eq!(gv["hits"][1], GValue::int(2));
+    assert_eq!(gv["hits"][9], GValue::Null);
+    assert_eq!(gv["nope"]["deeper"][0], GValue::Null);
+    assert_eq!(gv["args"][0], GValue::Null);
+
+    gv["args"]["query"] = GValue::str("go");
+    gv["args"]["limit"] = GValue::int(5);
+    gv["hits"][0] = GValue::int(10);
+    gv["meta"]["source"] = GValue::str("web");
+    assert_eq!(
+        canonicalize_loose(&gv).unwrap(),
+        "{args={limit=5 query=go} hits=[10 2] meta={source=web}}"
+    );
+
+    // Panic-free alternatives
+    assert!(gv.get_mut("nope").is_none());
+    assert!(gv["hits"].index_mut(5).is_none());
+    *gv.get_mut("hits").and_then(|h| h.index_mut(1)).unwrap() = GValue::int(20);
+    assert_eq!(gv.get_path("hits[1]"), Some(&GValue::int(20)));
+}
+
+#[test]
+#[should_panic(expected = "cannot index int")]
+fn test_index_mut_panics_on_scalar() {
+    let mut gv = GValue::int(1);
+    gv["k"] = GValue::Null;
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn test_index_mut_panics_out_of_range() {
+    let mut gv = GValue::list(vec![]);
+    gv[0] = GValue::Null;
+}
+
+#[test]
+fn test_display_without_canonical_form() {
+    let v = GValue::build_map().float("x", f64::NAN).float("y", f64::NEG_INFINITY).int("z", 1).finish();
+    assert!(canonicalize_loose(&v).is_err());
+    assert_eq!(v.to_string(), r#"{x="NaN" y="-inf" z=1}"#);
+    assert_eq!(format!("{}", GValue::float(f64::INFINITY)), "inf");
+
+    let ragged = GValue::table(["a", "b"], vec![vec![GValue::int(1)]]);
+    assert_eq!(ragged.to_string(), format!("{:?}", ragged));
+}
+
+#[test]
+#[should_panic(expected = "cannot index table")]
+fn test_index_mut_panics_on_table() {
+    let mut gv = GValue::table(["id"], vec![vec![GValue::int(1)]]);
+    assert_eq!(gv[0], GValue::Null);
+    gv[0] = GValue::Null;
+}
+
+#[test]
+fn test_index_mut_after_expand_table() {
+    let mut gv = GValue::table(["id"], vec![vec![GValue::int(1)]]);
+    gv.expand_table()[0]["id"] = GValue::int(2);
+    assert_eq!(gv[0]["id"], GValue::int(2));
+}
+
+#[test]
+fn test_glyph_macro() {
+    let query = String::from("weather");
+    let v = crate::glyph!({
+        action = "search",
+        query = query,
+        tags = ["a", "b"],
+        nested = {deep = [1, -2, 2.5, {x = true}], none = null, off = false},
+        "content-type" = "json",
+        ts = @now,
+        empty = [],
+    });
+    assert!(matches!(v["ts"], GValue::Time(_)));
+    let expected = GValue::build_map()
+        .str("action", "search")
+        .str("query", "weather")
+        .value("tags", GValue::list(vec![GValue::str("a"), GValue::str("b")]))
+        .value(
+            "nested",
+            GValue::build_map()
+                .value(
+                    "deep",
+                    GValue::list(vec![
+                        GValue::int(1),
+                        GValue::int(-2),
+                        GValue::float(2.5),
+                        GValue::build_map().bool("x", true).finish(),
+                    ]),
+                )
+                .value("none", GValue::Null)
+                .bool("off", false)
+                .finish(),
+        )
+        .str("content-type", "json")
+        .value("ts", v["ts"].clone())
+        .value("empty", GValue::list(vec![]))
+        .finish();
+    assert_eq!(v, expected);
+
+    assert_eq!(crate::glyph!(null), GValue::Null);
+    assert_eq!(crate::glyph!([]), GValue::list(vec![]));
+    assert_eq!(crate::glyph!({}), GValue::map(vec![]));
+    assert_eq!(crate::glyph!(1 + 2), GValue::int(3));
+    assert_eq!(crate::glyph!([@now, "x"]).as_list().map(|l| l.len()), Some(2));
+}
+
+#[test]
+fn test_display_and_from_str() {
+    let gv = from_json(&json!({"action": "search", "tags": ["a", "b"], "n": 1.5}));
+    assert_eq!(gv.to_string(), canonicalize_loose(&gv).unwrap());
+    assert_eq!(format!("call {}", GValue::list(vec![GValue::int(1)])), "call [1]");
+
+    let parsed: GValue = gv.to_string().parse().unwrap();
+    assert!(equal_loose(&parsed, &gv).unwrap());
+    assert!("{a=".parse::<GValue>().is_err());
+
+    use std::fmt::Write as _;
+    let mut out = String::new();
+    assert!(write!(out, "{}", GValue::float(f64::NAN)).is_ok());
+    assert_eq!(out, r#""NaN""#);
+}
+
+#[test]
+fn test_from_conversions() {
+    use chrono::TimeZone;
+    use std::collections::BTreeMap;
+
+    assert_eq!(GValue::from(7i64), GValue::int(7));
+    assert_eq!(GValue::from(true), GValue::bool(true));
+    let name = String::from("x");
+    assert_eq!(GValue::from(&name), GValue::str("x"));
+    assert_eq!(GValue::from(vec![1u8, 2]), GValue::bytes(vec![1, 2]));
+    let ts = chrono::Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    assert_eq!(GValue::from(ts), GValue::time(ts));
+    assert_eq!(GValue::from(vec!["a", "b"]), GValue::list(vec![GValue::str("a"), GValue::str("b")]));
+    assert_eq!(GValue::from(vec![vec![1i64], vec![]]).to_string(), "[[1] []]");
+    assert_eq!(GValue::from(None::<i64>), GValue::Null);
+    assert_eq!(GValue::from(Some(2.5)), GValue::float(2.5));
+
+    let mut scores = BTreeMap::new();
+    scores.insert("bo", 2i64);
+    scores.insert("al", 1i64);
+    let gv: GValue = scores.into();
+    assert_eq!(gv.as_map().unwrap()[0].key, "al");
+    assert_eq!(gv.to_string(), "{al=1 bo=2}");
+}
+
+#[test]
+fn test_fallible_extraction() {
+    let gv = from_json(&json!({"args": {"query": "rust", "limit": 5}, "tags": ["a"]}));
+    assert_eq!(gv.try_get("args").unwrap().try_get("limit").unwrap().try_as_int().unwrap(), 5);
+    assert_eq!(gv.try_get("tags").unwrap().try_index(0).unwrap().try_as_str().unwrap(), "a");
+    assert_eq!(gv.try_get_path("args.query").unwrap().try_as_str().unwrap(), "rust");
+
+    let msg = |r: Result<&GValue>| r.unwrap_err().to_string();
+    assert_eq!(
+        gv.try_get("args").unwrap().try_get("query").unwrap().try_as_int().unwrap_err().to_string(),
+        "Type mismatch: expected int, got str"
+    );
+    assert_eq!(msg(gv.try_get("nope")), "Missing required field: nope");
+    assert_eq!(msg(gv.try_get("tags").unwrap().try_index(3)), "Missing required field: [3]");
+    assert_eq!(msg(gv.try_get("tags").unwrap().try_get("x")), "Type mismatch: expected map or struct, got list");
+    assert_eq!(msg(gv.try_index(0)), "Type mismatch: expected list, got map");
+
+    assert_eq!(msg(gv.try_get_path("args.missing")), "Missing required field: args.missing");
+    assert_eq!(msg(gv.try_get_path("tags[2]")), "Missing required field: tags[2]");
+    assert_eq!(
+        msg(gv.try_get_path("args.query.x")),
+        "Type mismatch: expected map or struct at args.query, got str"
+    );
+    assert_eq!(msg(gv.try_get_path("args[0]")), "Type mismatch: expected list at args, got map");
+    assert!(matches!(gv.try_get_path("a["), Err(GlyphError::Parse(_))));
+
+    // A table has no row values to borrow; its cells are reached by path
+    let table = GValue::table(["id"], vec![vec![GValue::int(7)]]);
+    assert_eq!(table.try_as_list().unwrap_err().to_string(), "Type mismatch: expected list, got table");
+    assert_eq!(msg(table.try_index(0)), "Type mismatch: expected list, got table");
+    assert_eq!(table.try_get_path("[0].id").unwrap(), &GValue::int(7));
+    assert_eq!(table.clone().expand_table().try_index(0).unwrap().try_get("id").unwrap(), &GValue::int(7));
+}
+
+#[test]
+fn test_hash_at() {
+    let gv = from_json(&json!({
+        "status": "ok",
+        "results": [{"id": 1, "payload": {"a": 1}}, {"id": 2, "payload": {"a": 2}}, {"id": 3, "payload": {"a": 3}}]
+    }));
+    let payload = from_json(&json!({"a": 2}));
+    assert_eq!(hash_at(&gv, "results[1].payload").unwrap(), hash_loose(&payload).unwrap());
+    assert_eq!(hash_at(&gv, "").unwrap(), hash_loose(&gv).unwrap());
+    assert_ne!(hash_at(&gv, "results[0].payload").unwrap(), hash_at(&gv, "results[1].payload").unwrap());
+
+    // Tables address rows the same way
+    let text = canonicalize_loose(&gv).unwrap();
+    assert!(text.contains("@tab"), "{}", text);
+    let parsed = parse_loose_with(&text, ParseOpts { tables: true }).unwrap();
+    assert!(parsed.get("results").unwrap().is_table());
+    assert_eq!(hash_at(&parsed, "results[1].payload").unwrap(), hash_loose(&payload).unwrap());
+    assert_eq!(hash_at(&parsed, "results[1].id").unwrap(), hash_loose(&GValue::int(2)).unwrap());
+
+    assert!(hash_at(&gv, "results[3]").is_err());
+    assert!(hash_at(&gv, "status.x").is_err());
+    assert!(hash_at(&gv, "results[").is_err());
+}
+
+#[test]
+fn test_write_canon_loose_streams() {
+    // Chunked sink that records how much it was handed at once
+    #[derive(Default)]
+    struct Chunks {
+        text: String,
+        writes: usize,
+        largest: usize,
+    }
+    impl std::fmt::Write for Chunks {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            self.text.push_str(s);
+            self.writes += 1;
+            self.largest = self.largest.max(s.len());
+            Ok(())
+        }
+    }
+
+    let rows: Vec<GValue> = (0..2000)
+        .map(|i| GValue::build_map().int("id", i).str("name", "x|y").value("tags", GValue::list(vec![GValue::int(i)])).finish())
+        .collect();
+    let v = GValue::build_map().value("rows", GValue::list(rows.clone())).value("more", GValue::list(rows)).finish();
+
+    for opts in [LooseCanonOpts::default(), LooseCanonOpts::no_tabular()] {
+        let mut out = Chunks::default();
+        write_canon_loose(&mut out, &v, &opts).unwrap();
+        assert_eq!(out.text, canonicalize_loose_with_opts(&v, &opts).unwrap());
+        if !opts.auto_tabular {
+            // Plain output is flushed in bounded pieces
+            assert!(out.writes > 5 && out.largest < 16 * 1024, "{} writes, largest {}", out.writes, out.largest);
+        }
+    }
+
+    let mut hasher = HashWriter::new(HashAlgo::Sha256);
+    write_canon_loose(&mut hasher, &v, &LooseCanonOpts::no_tabular()).unwrap();
+    assert_eq!(hasher.finish(32).unwrap().to_hex(), hash_loose(&v).unwrap());
+    assert!(write_canon_loose(&mut String::new(), &GValue::float(f64::NAN), &LooseCanonOpts::default()).is_err());
+}
+
+#[test]
+fn test_write_canon_loose_io() {
+    let v = GValue::build_map().int("id", 7).str("name", "glyph codec").finish();
+    let mut out = Vec::new();
+    write_canon_loose_io(&mut out, &v, &LooseCanonOpts::default()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), canonicalize_loose(&v).unwrap());
+
+    struct Closed;
+    impl std::io::Write for Closed {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "closed"))
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    match write_canon_loose_io(Closed, &v, &LooseCanonOpts::default()) {
+        Err(GlyphError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe),
+        other => panic!("expected the pipe error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_encoder_reuse_matches_canonicalize() {
+    let row = |i: i64| GValue::build_map().int("id", i).str("name", "a|b").value("tags", GValue::list(vec![GValue::int(i)])).finish();
+    let values = [
+        GValue::list((0..20).map(row).collect()),
+        GValue::build_map().value("rows", GValue::list((0..3).map(row).collect())).int("total", 3).finish(),
+        GValue::str("plain"),
+        GValue::list(vec![GValue::list(vec![GValue::int(1), GValue::Null]), GValue::list(vec![GValue::int(2), GValue::int(3)])]),
+    ];
+    for opts in [LooseCanonOpts::default(), LooseCanonOpts::compact(), LooseCanonOpts::llm()] {
+        let mut encoder = Encoder::new(opts.clone());
+        // Twice over, so the second pass runs on reused buffers
+        for v in values.iter().chain(&values) {
+            let expected = canonicalize_loose_with_opts(v, &opts).unwrap();
+            assert_eq!(encoder.encode(v).unwrap(), expected);
+            let mut streamed = String::new();
+            encoder.write(&mut streamed, v).unwrap();
+            assert_eq!(streamed, expected);
+        }
+    }
+
+    let mut encoder = Encoder::default();
+    assert!(encoder.encode(&GValue::float(f64::NAN)).is_err());
+    assert_eq!(encoder.encode_to_string(&values[2]).unwrap(), "plain");
+}
+
+#[test]
+fn test_estimate_size_matches_output() {
+    let rows: Vec<GValue> = (0..50)
+        .map(|i| GValue::build_map().int("id", i).str("name", "héllo world").finish())
+        .collect();
+    let v = GValue::build_map()
+        .value("rows", GValue::list(rows))
+        .value("mixed", GValue::list(vec![GValue::int(1), GValue::str("two")]))
+        .finish();
+    for opts in [LooseCanonOpts::default(), LooseCanonOpts::no_tabular(), LooseCanonOpts::compact()] {
+        assert_eq!(estimate_size(&v, &opts), canonicalize_loose_with_opts(&v, &opts).unwrap().len());
+    }
+
+    // The output limit doesn't cap the estimate
+    let capped = LooseCanonOpts {
+        limits: EncodeLimits { max_output_bytes: Some(10), ..EncodeLimits::default() },
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(estimate_size(&v, &capped), canonicalize_loose(&v).unwrap().len());
+}
+
 #[test]
 fn test_unicode() {
     // Per spec, bare-safe is [a-zA-Z0-9._-] only; unicode is not bare-safe and must be quoted.
@@ -215,6 +672,34 @@ fn test_opts_llm() {
     assert_eq!(opts.null_style, NullStyle::Underscore);
 }
 
+#[test]
+fn test_opts_verbose() {
+    let opts = LooseCanonOpts::verbose();
+    let gv = GValue::list(vec![GValue::bool(true), GValue::bool(false), GValue::null(), GValue::str("null")]);
+    assert_eq!(canonicalize_loose_with_opts(&gv, &opts).unwrap(), "[true false null \"null\"]");
+    // Default stays compact
+    assert_eq!(canonicalize_loose(&gv).unwrap(), "[t f _ \"null\"]");
+}
+
+#[test]
+fn test_opts_verbose_tabular_missing_cells() {
+    let data = json!([{"a": 1, "b": true}, {"a": 2}, {"a": 3, "b": false}]);
+    let out = canonicalize_loose_with_opts(&from_json(&data), &LooseCanonOpts::verbose()).unwrap();
+    assert!(out.contains("|1|true|"), "{}", out);
+    assert!(out.contains("|2|null|"), "{}", out);
+}
+
+#[test]
+fn test_stream_validator_accepts_both_literal_forms() {
+    let mut v = StreamingValidator::new(default_tool_registry());
+    v.push_token("{action=search query=x limit=5 flag=true other=∅ more=null}");
+    let result = v.get_result();
+    assert!(result.complete, "{:?}", result.errors);
+    assert_eq!(result.fields.get("flag"), Some(&FieldValue::Bool(true)));
+    assert_eq!(result.fields.get("other"), Some(&FieldValue::Null));
+    assert_eq!(result.fields.get("more"), Some(&FieldValue::Null));
+}
+
 #[test]
 fn test_opts_no_tabular() {
     let opts = LooseCanonOpts::no_tabular();
@@ -284,6 +769,50 @@ fn test_canon_float_exponential_large() {
     let _ = result; // use it
 }
 
+// ============================================================
+// Float formatting: shortest round-trip (D4) vectors
+// ============================================================
+
+#[test]
+fn test_canon_float_shortest_roundtrip_vectors() {
+    // Vectors shared with go/glyph/testdata/loose_json/golden
+    let cases: &[(f64, &str)] = &[
+        (3.14159, "3.14159"),
+        (-0.02, "-0.02"),
+        (0.1, "0.1"),
+        (0.30000000000000004, "0.30000000000000004"),
+        (3.141592653589793, "3.141592653589793"),
+        (2.718281828459045, "2.718281828459045"),
+        (0.0001, "0.0001"),
+        (0.00001, "1e-05"),
+        (0.000001, "1e-06"),
+        (1e-7, "1e-07"),
+        (1e-100, "1e-100"),
+        (999999.9, "999999.9"),
+        (1234567.5, "1.2345675e+06"),
+        (1e20, "1e+20"),
+        (1e21, "1e+21"),
+        (1e100, "1e+100"),
+        (9007199254740991.0, "9007199254740991"),
+        (9007199254740992.0, "9.007199254740992e+15"),
+        (-9007199254740991.0, "-9007199254740991"),
+        (5e-324, "5e-324"),
+        (f64::MAX, "1.7976931348623157e+308"),
+    ];
+    for (f, want) in cases {
+        assert_eq!(&canonicalize_loose(&GValue::float(*f)).unwrap(), want, "float {:e}", f);
+    }
+}
+
+#[test]
+fn test_canon_float_roundtrips_exactly() {
+    // Values needing 17 significant digits must survive emit -> parse
+    for f in [0.1 + 0.2, 1.0 / 3.0, 2.0f64.sqrt(), 123456.789e-12, -7.0e-300, 4.35e15 + 0.5] {
+        let s = canonicalize_loose(&GValue::float(f)).unwrap();
+        assert_eq!(s.parse::<f64>().unwrap(), f, "{} did not round-trip", s);
+    }
+}
+
 // ============================================================
 // NEW: String escape coverage (lines 221-227)
 // ============================================================
@@ -363,6 +892,338 @@ fn test_canon_string_starts_with_quote() {
     assert!(result.starts_with('"'), "Should be quoted: {}", result);
 }
 
+// ============================================================
+// Bare-string rules
+// ============================================================
+
+#[test]
+fn test_bare_rules_default_matches_canon_string() {
+    let rules = BareRules::default();
+    for s in ["hello", "a.b-c_d", "true", "_", "9lives", "-x", "héllo", "a b", ""] {
+        assert_eq!(canon_string_with(s, &rules), canon_string(s), "{:?}", s);
+    }
+}
+
+#[test]
+fn test_bare_rules_extra_reserved() {
+    let opts = LooseCanonOpts {
+        bare_rules: BareRules {
+            extra_reserved: vec!["yes".to_string(), "no".to_string()],
+            ..BareRules::default()
+        },
+        ..LooseCanonOpts::default()
+    };
+    let gv = GValue::map(vec![field("no", GValue::str("yes")), field("ok", GValue::str("maybe"))]);
+    assert_eq!(canonicalize_loose_with_opts(&gv, &opts).unwrap(), "{\"no\"=\"yes\" ok=maybe}");
+}
+
+#[test]
+fn test_bare_rules_allow_and_deny_chars() {
+    let rules = BareRules {
+        allow_chars: vec!['/', ':', '=', '.', '-'],
+        deny_chars: vec!['.'],
+        ..BareRules::default()
+    };
+    assert!(rules.is_bare_safe("src/main"));
+    assert!(rules.is_bare_safe("ns:key"));
+    assert!(rules.is_bare_safe("has-dash"));
+    // Structural characters can never be allowed
+    assert!(!rules.is_bare_safe("a=b"));
+    // Deny wins over allow
+    assert!(!rules.is_bare_safe("v1.2"));
+    assert!(!BareRules::default().is_bare_safe("v1.2"));
+    // Allowed characters never start a bare string
+    assert!(!rules.is_bare_safe("-x"));
+}
+
+#[test]
+fn test_bare_rules_default_is_d8() {
+    let rules = BareRules::default();
+    for s in ["hello", "MixedCase", "_123", "a_b9"] {
+        assert!(rules.is_bare_safe(s), "{:?}", s);
+    }
+    for s in ["has-dash", "has.dot", "has/slash", "9lives", "map", "NaN", "Inf", "struct", "sum", "list"] {
+        assert!(!rules.is_bare_safe(s), "{:?}", s);
+    }
+}
+
+#[test]
+fn test_bare_rules_unicode() {
+    let gv = GValue::str("café");
+    assert_eq!(canonicalize_loose(&gv).unwrap(), "\"café\"");
+
+    let opts = LooseCanonOpts {
+        bare_rules: BareRules::unicode(),
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(canonicalize_loose_with_opts(&gv, &opts).unwrap(), "café");
+    assert_eq!(canonicalize_loose_with_opts(&GValue::str("日本 語"), &opts).unwrap(), "\"日本 語\"");
+    assert!(!BareRules::unicode().is_bare_safe("a\u{00a0}b"));
+}
+
+// ============================================================
+// Key ordering
+// ============================================================
+
+fn unordered_map() -> GValue {
+    GValue::map(vec![
+        field("name", GValue::str("x")),
+        field("id", GValue::int(1)),
+        field("zeta", GValue::int(2)),
+        field("alpha", GValue::int(3)),
+    ])
+}
+
+#[test]
+fn test_key_order_sorted_is_default() {
+    assert_eq!(LooseCanonOpts::default().key_order, KeyOrder::Sorted);
+    assert_eq!(canonicalize_loose(&unordered_map()).unwrap(), "{alpha=3 id=1 name=x zeta=2}");
+}
+
+#[test]
+fn test_key_order_insertion() {
+    let opts = LooseCanonOpts {
+        key_order: KeyOrder::Insertion,
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(
+        canonicalize_loose_with_opts(&unordered_map(), &opts).unwrap(),
+        "{name=x id=1 zeta=2 alpha=3}"
+    );
+
+    let row = |extra: bool| {
+        let mut fields = vec![field("b", GValue::int(1)), field("a", GValue::int(2))];
+        if extra {
+            fields.push(field("c", GValue::int(3)));
+        }
+        GValue::map(fields)
+    };
+    let rows = GValue::list(vec![row(false), row(true), row(false)]);
+    let out = canonicalize_loose_with_opts(&rows, &opts).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=3 [b a c]"), "{}", out);
+}
+
+#[test]
+fn test_key_order_custom() {
+    fn id_first(a: &str, b: &str) -> std::cmp::Ordering {
+        (a != "id").cmp(&(b != "id")).then_with(|| a.cmp(b))
+    }
+    let opts = LooseCanonOpts {
+        key_order: KeyOrder::Custom(id_first),
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(
+        canonicalize_loose_with_opts(&unordered_map(), &opts).unwrap(),
+        "{id=1 alpha=3 name=x zeta=2}"
+    );
+}
+
+#[test]
+fn test_sorted_keys_follow_canonical_bytes() {
+    let keys = [
+        "zz", "a", "B", "_x", "a b", "a\"", "a\\", "a\n", "a\u{1}", "a\u{1f}", "a\u{7f}", "a\tb", "t", "", "é", "1x", "!bang",
+        "a\"b", "a\\b", "a#",
+    ];
+    let v = GValue::map(keys.iter().map(|k| MapEntry::new(*k, GValue::int(1))).collect());
+    let mut canon: Vec<String> = keys.iter().map(|k| canon_string(k)).collect();
+    canon.sort();
+    let expected = format!("{{{}}}", canon.iter().map(|k| format!("{}=1", k)).collect::<Vec<_>>().join(" "));
+    assert_eq!(canonicalize_loose(&v).unwrap(), expected);
+}
+
+// ============================================================
+// Unicode normalization
+// ============================================================
+
+#[test]
+fn test_unicode_nfc_strings_and_keys() {
+    let composed = GValue::map(vec![field("caf\u{e9}", GValue::str("r\u{e9}sum\u{e9}"))]);
+    let decomposed = GValue::map(vec![field("cafe\u{301}", GValue::str("re\u{301}sume\u{301}"))]);
+
+    // Off by default: different bytes, different hashes
+    assert_ne!(hash_loose(&composed).unwrap(), hash_loose(&decomposed).unwrap());
+
+    let opts = LooseCanonOpts {
+        unicode_norm: UnicodeNorm::Nfc,
+        ..LooseCanonOpts::no_tabular()
+    };
+    assert_eq!(
+        canonicalize_loose_with_opts(&decomposed, &opts).unwrap(),
+        "{\"caf\u{e9}\"=\"r\u{e9}sum\u{e9}\"}"
+    );
+    assert_eq!(
+        hash_loose_with_opts(&composed, &opts).unwrap(),
+        hash_loose_with_opts(&decomposed, &opts).unwrap()
+    );
+    assert_eq!(hash_loose_with_opts(&composed, &LooseCanonOpts::no_tabular()).unwrap(), hash_loose(&composed).unwrap());
+}
+
+#[test]
+fn test_unicode_nfc_tabular_columns() {
+    let row = |k: &str| GValue::map(vec![field(k, GValue::int(1)), field("id", GValue::int(2))]);
+    let rows = GValue::list(vec![row("e\u{301}"), row("e\u{301}"), row("e\u{301}")]);
+    let opts = LooseCanonOpts {
+        unicode_norm: UnicodeNorm::Nfc,
+        ..LooseCanonOpts::default()
+    };
+    let out = canonicalize_loose_with_opts(&rows, &opts).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=2 [\"\u{e9}\" id]"), "{}", out);
+}
+
+// ============================================================
+// Size caps
+// ============================================================
+
+#[test]
+fn test_size_caps_replace_oversized_values() {
+    let long = "x".repeat(50);
+    let v = GValue::map(vec![
+        field("body", GValue::str(&long)),
+        field("short", GValue::str("ok")),
+        field("items", GValue::list((0..5).map(GValue::int).collect())),
+        field("blob", GValue::bytes(vec![0; 8])),
+    ]);
+    let opts = LooseCanonOpts {
+        max_value_bytes_per_type: SizeCaps {
+            max_string_bytes: Some(16),
+            max_list_items: Some(3),
+            ..SizeCaps::default()
+        },
+        ..LooseCanonOpts::default()
+    };
+    let out = canonicalize_loose_with_opts(&v, &opts).unwrap();
+
+    let body_hash = &hash_loose(&GValue::str(&long)).unwrap()[..16];
+    assert!(out.contains(&format!("body=Trunc{{kind=str len=50 ref=^trunc:{}}}", body_hash)), "{}", out);
+    assert!(out.contains("items=Trunc{kind=\"list\" len=5 ref=^trunc:"), "{}", out);
+    assert!(out.contains("short=ok"));
+    assert!(out.contains("blob=b64"));
+
+    // Values at the limit are kept
+    let exact = GValue::str("x".repeat(16));
+    assert_eq!(canonicalize_loose_with_opts(&exact, &opts).unwrap(), "x".repeat(16));
+    assert_eq!(SizeCaps::default().overflow(&GValue::str(&long)), None);
+}
+
+#[test]
+fn test_size_caps_rows_disable_tabular() {
+    let row = |n: i64| GValue::map(vec![field("id", GValue::int(n)), field("tags", GValue::list(vec![]))]);
+    let rows = GValue::list(vec![row(1), row(2), row(3)]);
+    let opts = LooseCanonOpts {
+        max_value_bytes_per_type: SizeCaps {
+            max_map_entries: Some(1),
+            ..SizeCaps::default()
+        },
+        ..LooseCanonOpts::default()
+    };
+    let out = canonicalize_loose_with_opts(&rows, &opts).unwrap();
+    assert!(out.starts_with("[Trunc{kind=\"map\" len=2"), "{}", out);
+    assert!(!out.contains("@tab"));
+}
+
+// ============================================================
+// Encode limits
+// ============================================================
+
+#[test]
+fn test_encode_limits_error() {
+    let nested = GValue::list(vec![GValue::list(vec![GValue::list(vec![GValue::int(1)])])]);
+    let opts = |limits: EncodeLimits| LooseCanonOpts { limits, ..LooseCanonOpts::default() };
+
+    let depth = opts(EncodeLimits { max_depth: Some(2), ..EncodeLimits::default() });
+    assert!(matches!(
+        canonicalize_loose_with_opts(&nested, &depth),
+        Err(GlyphError::RecursionLimitExceeded { limit: 2 })
+    ));
+    let depth = opts(EncodeLimits { max_depth: Some(3), ..EncodeLimits::default() });
+    assert_eq!(canonicalize_loose_with_opts(&nested, &depth).unwrap(), "[[[1]]]");
+
+    let strings = opts(EncodeLimits { max_string_len: Some(4), ..EncodeLimits::default() });
+    assert!(canonicalize_loose_with_opts(&GValue::str("abcd"), &strings).is_ok());
+    assert!(matches!(
+        canonicalize_loose_with_opts(&GValue::str("abcde"), &strings),
+        Err(GlyphError::LimitExceeded(_))
+    ));
+
+    let items = GValue::list((0..10).map(GValue::int).collect());
+    let lists = opts(EncodeLimits { max_list_len: Some(5), ..EncodeLimits::default() });
+    assert!(matches!(canonicalize_loose_with_opts(&items, &lists), Err(GlyphError::LimitExceeded(_))));
+
+    // The output limit applies whatever the policy, streaming included
+    let output = opts(EncodeLimits {
+        max_output_bytes: Some(10),
+        policy: LimitPolicy::Truncate,
+        ..EncodeLimits::default()
+    });
+    assert!(matches!(canonicalize_loose_with_opts(&items, &output), Err(GlyphError::LimitExceeded(_))));
+    let mut sink = String::new();
+    assert!(write_canon_loose(&mut sink, &items, &output).is_err());
+}
+
+#[test]
+fn test_encode_limits_truncate() {
+    let limits = EncodeLimits {
+        max_depth: Some(2),
+        max_list_len: Some(3),
+        max_string_len: Some(4),
+        policy: LimitPolicy::Truncate,
+        ..EncodeLimits::default()
+    };
+    let opts = LooseCanonOpts { limits, ..LooseCanonOpts::default() };
+    let v = GValue::map(vec![
+        field("body", GValue::str("abcdefgh")),
+        field("deep", GValue::map(vec![field("inner", GValue::list(vec![GValue::int(1)]))])),
+        field("items", GValue::list((0..10).map(GValue::int).collect())),
+    ]);
+    let out = canonicalize_loose_with_opts(&v, &opts).unwrap();
+    assert!(out.contains("body=\"abcd…+4 bytes\""), "{}", out);
+    assert!(out.contains("deep={inner=Trunc{kind=\"list\" len=1 ref=^trunc:"), "{}", out);
+    assert!(out.contains("items=[0 1 2 \"…+7 more\"]"), "{}", out);
+
+    // Table rows are cut the same way
+    let table = TableValue::new(vec!["id".to_string()], (0..5).map(|n| vec![GValue::int(n)]).collect());
+    let out = canonicalize_loose_with_opts(&GValue::Table(table), &opts).unwrap();
+    assert!(out.ends_with("\"…+2 more\"]"), "{}", out);
+}
+
+// ============================================================
+// Size-optimized tabular
+// ============================================================
+
+#[test]
+fn test_optimize_size_prefers_shorter_form() {
+    // Three one-key rows: the @tab header costs more than it saves
+    let short = GValue::list((1..=3).map(|n| GValue::map(vec![field("a", GValue::int(n))])).collect());
+    assert!(canonicalize_loose(&short).unwrap().starts_with("@tab"));
+    assert_eq!(
+        canonicalize_loose_with_opts(&short, &LooseCanonOpts::compact()).unwrap(),
+        "[{a=1} {a=2} {a=3}]"
+    );
+
+    // Long repeated keys: tabular wins and is kept
+    let row = |n: i64| {
+        GValue::map(vec![
+            field("identifier", GValue::int(n)),
+            field("description", GValue::str("x")),
+        ])
+    };
+    let wide = GValue::list((1..=5).map(row).collect());
+    let out = canonicalize_loose_with_opts(&wide, &LooseCanonOpts::compact()).unwrap();
+    assert_eq!(out, canonicalize_loose(&wide).unwrap());
+    assert!(out.starts_with("@tab"));
+}
+
+#[test]
+fn test_optimize_size_never_longer() {
+    let row = |n: i64, k: &str| GValue::map(vec![field(k, GValue::int(n)), field("b", GValue::null())]);
+    for key in ["a", "k_long_column_name_here"] {
+        let v = GValue::list((1..=4).map(|n| row(n, key)).collect());
+        let compact = canonicalize_loose_with_opts(&v, &LooseCanonOpts::compact()).unwrap();
+        assert!(compact.len() <= canonicalize_loose(&v).unwrap().len());
+        assert!(compact.len() <= canonicalize_loose_no_tabular(&v).unwrap().len());
+    }
+}
+
 // ============================================================
 // NEW: Bytes canonicalization (lines 236-239)
 // ============================================================
@@ -521,6 +1382,331 @@ fn test_tabular_with_structs() {
     assert!(result.contains("@tab"), "Struct arrays should be tabular-eligible");
 }
 
+#[test]
+fn test_tabular_flatten_nested_rows() {
+    let row = |id: i64, city: &str| {
+        GValue::build_map()
+            .int("id", id)
+            .value("user", GValue::build_map().str("name", "x").value("addr", GValue::build_map().str("city", city)))
+            .finish()
+    };
+    let rows = GValue::list(vec![row(1, "a"), row(2, "b"), row(3, "c")]);
+    let with_depth = |d| LooseCanonOpts {
+        tabular_flatten_depth: d,
+        ..LooseCanonOpts::default()
+    };
+
+    assert!(canonicalize_loose(&rows).unwrap().starts_with("@tab _ rows=3 cols=2 [id user]"));
+    let one = canonicalize_loose_with_opts(&rows, &with_depth(1)).unwrap();
+    assert!(one.starts_with("@tab _ rows=3 cols=3 flat=1 [id user.addr user.name]\n|1|{city=a}|x|"), "{}", one);
+    let two = canonicalize_loose_with_opts(&rows, &with_depth(2)).unwrap();
+    assert!(two.starts_with("@tab _ rows=3 cols=3 flat=2 [id user.addr.city user.name]\n|1|a|x|"), "{}", two);
+}
+
+#[test]
+fn test_tabular_flatten_skips_ambiguous_columns() {
+    let opts = LooseCanonOpts {
+        tabular_flatten_depth: 1,
+        ..LooseCanonOpts::default()
+    };
+    // A non-map in one row, or a key that would need quoting, keeps the column whole
+    let rows = GValue::list(vec![
+        GValue::build_map().value("u", GValue::build_map().int("id", 1)).value("m", GValue::build_map().int("a b", 1)).finish(),
+        GValue::build_map().value("u", GValue::build_map().int("id", 2)).value("m", GValue::build_map().int("a b", 2)).finish(),
+        GValue::build_map().null("u").value("m", GValue::build_map().int("a b", 3)).finish(),
+    ]);
+    let out = canonicalize_loose_with_opts(&rows, &opts).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=2 [m u]"), "{}", out);
+}
+
+fn people() -> GValue {
+    let row = |id: i64, name: &str, age: i64| {
+        GValue::build_map().int("age", age).int("id", id).str("name", name).value("meta", GValue::build_map().int("x", id)).finish()
+    };
+    GValue::list(vec![row(1, "a", 30), row(2, "b", 40), row(3, "c", 50)])
+}
+
+#[test]
+fn test_column_order_pinned() {
+    assert!(canonicalize_loose(&people()).unwrap().starts_with("@tab _ rows=3 cols=4 [age id meta name]"));
+
+    let opts = LooseCanonOpts {
+        column_order: ColumnOrder::pinned(["name", "id", "missing"]),
+        ..LooseCanonOpts::default()
+    };
+    let out = canonicalize_loose_with_opts(&people(), &opts).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=4 [name id age meta]\n|a|1|30|{x=1}|"), "{}", out);
+    // Map keys outside tabular blocks are unaffected
+    let row = people().index(0).cloned().unwrap();
+    assert_eq!(canonicalize_loose_with_opts(&row, &opts).unwrap(), canonicalize_loose(&row).unwrap());
+
+    // Flattened columns can be pinned individually or by parent
+    let flat = LooseCanonOpts {
+        column_order: ColumnOrder::pinned(["meta.x", "name"]),
+        tabular_flatten_depth: 1,
+        ..LooseCanonOpts::default()
+    };
+    let out = canonicalize_loose_with_opts(&people(), &flat).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=4 flat=1 [meta.x name age id]"), "{}", out);
+}
+
+#[test]
+fn test_column_order_custom() {
+    fn by_len(a: &str, b: &str) -> std::cmp::Ordering {
+        a.len().cmp(&b.len()).then_with(|| b.cmp(a))
+    }
+    let opts = LooseCanonOpts {
+        column_order: ColumnOrder::Custom(by_len),
+        ..LooseCanonOpts::default()
+    };
+    let out = canonicalize_loose_with_opts(&people(), &opts).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=4 [id age name meta]"), "{}", out);
+}
+
+#[test]
+fn test_table_always_tabular() {
+    let t = GValue::table(["name", "id"], vec![vec![GValue::str("a"), GValue::int(1)]]);
+    // One row: still an @tab block, columns in key order
+    let expected = "@tab _ rows=1 cols=2 [id name]\n|1|a|\n@end";
+    assert_eq!(canonicalize_loose(&t).unwrap(), expected);
+    // Tabular disabled: the row list, as for the equivalent list
+    assert_eq!(canonicalize_loose_no_tabular(&t).unwrap(), "[{id=1 name=a}]");
+    // ...and it is no deeper than that list under max_depth
+    let shallow = LooseCanonOpts {
+        limits: EncodeLimits { max_depth: Some(2), ..EncodeLimits::default() },
+        ..LooseCanonOpts::no_tabular()
+    };
+    let nested = GValue::map(vec![MapEntry::new("rows", t.clone())]);
+    assert!(canonicalize_loose_with_opts(&nested, &shallow).is_err());
+    let deep = LooseCanonOpts {
+        limits: EncodeLimits { max_depth: Some(3), ..EncodeLimits::default() },
+        ..LooseCanonOpts::no_tabular()
+    };
+    assert_eq!(canonicalize_loose_with_opts(&nested, &deep).unwrap(), "{rows=[{id=1 name=a}]}");
+    assert_eq!(
+        canonicalize_loose(&GValue::table(["a"], vec![])).unwrap(),
+        "@tab _ rows=0 cols=1 [a]\n@end"
+    );
+
+    let pinned = LooseCanonOpts {
+        column_order: ColumnOrder::pinned(["name"]),
+        ..LooseCanonOpts::default()
+    };
+    assert!(canonicalize_loose_with_opts(&t, &pinned).unwrap().starts_with("@tab _ rows=1 cols=2 [name id]\n|a|1|"));
+
+    assert_eq!(to_json(&t), serde_json::json!([{"name": "a", "id": 1}]));
+    let ragged = GValue::table(["a", "b"], vec![vec![GValue::int(1)]]);
+    assert!(matches!(canonicalize_loose(&ragged), Err(GlyphError::InvalidValue(_))));
+}
+
+#[test]
+fn test_table_hash_matches_parsed_list() {
+    let row = |id: i64, name: &str| GValue::build_map().int("id", id).str("name", name).finish();
+    let v = GValue::map(vec![MapEntry::new("rows", GValue::list(vec![row(1, "a"), row(2, "b"), row(3, "c")]))]);
+    let parsed = parse_loose_with(&canonicalize_loose(&v).unwrap(), ParseOpts { tables: true }).unwrap();
+    assert!(parsed.get("rows").unwrap().is_table());
+    assert_eq!(hash_loose(&v).unwrap(), hash_loose(&parsed).unwrap());
+    assert_eq!(
+        hash_loose_with(&v, HashAlgo::Blake3, 32).unwrap(),
+        hash_loose_with(&parsed, HashAlgo::Blake3, 32).unwrap()
+    );
+    assert_eq!(canonicalize_loose_no_tabular(&parsed).unwrap(), canonicalize_loose_no_tabular(&v).unwrap());
+}
+
+#[test]
+fn test_tabular_types_inferred() {
+    let row = |id: i64, name: Option<&str>, v: GValue| {
+        GValue::build_map().int("id", id).maybe("name", name).value("v", v).finish()
+    };
+    let rows = GValue::list(vec![
+        row(1, Some("a"), GValue::int(1)),
+        row(2, None, GValue::str("x")),
+        row(3, Some("c"), GValue::Null),
+    ]);
+    assert!(canonicalize_loose(&rows).unwrap().starts_with("@tab _ rows=3 cols=3 [id name v]"));
+
+    let opts = LooseCanonOpts {
+        tabular_types: true,
+        ..LooseCanonOpts::default()
+    };
+    // Missing and null cells don't count; mixed columns stay unannotated
+    let out = canonicalize_loose_with_opts(&rows, &opts).unwrap();
+    assert!(out.starts_with("@tab _ rows=3 cols=3 [id:int name:str v]"), "{}", out);
+    let parsed = parse_loose_with(&out, ParseOpts { tables: true }).unwrap();
+    assert_eq!(parsed.as_table().unwrap().types, [Some(GType::Int), Some(GType::Str), None]);
+}
+
+#[test]
+fn test_tabular_types_mixed_numbers_idempotent() {
+    let row = |id: i64, v: GValue| GValue::build_map().int("id", id).value("v", v).finish();
+    let rows = GValue::list(vec![row(1, GValue::int(1)), row(2, GValue::float(2.0)), row(3, GValue::float(2.5))]);
+    let opts = LooseCanonOpts {
+        tabular_types: true,
+        ..LooseCanonOpts::default()
+    };
+    let once = canonicalize_loose_with_opts(&rows, &opts).unwrap();
+    assert!(once.starts_with("@tab _ rows=3 cols=2 [id:int v:float]"), "{}", once);
+    let parsed = parse_loose(&once).unwrap();
+    assert_eq!(parsed[0]["v"], GValue::Float(1.0));
+    assert_eq!(canonicalize_loose_with_opts(&parsed, &opts).unwrap(), once);
+
+    // Whole floats only: the ints they print as come back as floats
+    let whole = GValue::list(vec![row(1, GValue::int(1)), row(2, GValue::float(2.0)), row(3, GValue::int(3))]);
+    let once = canonicalize_loose_with_opts(&whole, &opts).unwrap();
+    assert_eq!(canonicalize_loose_with_opts(&parse_loose(&once).unwrap(), &opts).unwrap(), once);
+}
+
+#[test]
+fn test_table_declared_types_checked() {
+    let t = TableValue::new(vec!["id".to_string()], vec![vec![GValue::str("x")]]).with_types(vec![Some(GType::Int)]);
+    let err = canonicalize_loose(&GValue::Table(t)).unwrap_err();
+    assert!(matches!(err, GlyphError::TypeMismatch { .. }), "{}", err);
+    assert_eq!(GType::parse("time"), Some(GType::Time));
+    assert_eq!(GValue::table(["a"], vec![]).gtype().to_string(), "table");
+}
+
+#[test]
+fn test_sort_rows_makes_sets_hash_equal() {
+    let row = |id: i64, name: &str| GValue::build_map().int("id", id).str("name", name).finish();
+    let a = GValue::list(vec![row(2, "b"), row(10, "c"), row(1, "a")]);
+    let b = GValue::list(vec![row(1, "a"), row(2, "b"), row(10, "c")]);
+    assert_ne!(canonicalize_loose(&a).unwrap(), canonicalize_loose(&b).unwrap());
+
+    let canon = LooseCanonOpts {
+        sort_rows: RowOrder::Canonical,
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(hash_loose_with_opts(&a, &canon).unwrap(), hash_loose_with_opts(&b, &canon).unwrap());
+    assert_eq!(
+        canonicalize_loose_with_opts(&a, &canon).unwrap(),
+        "@tab _ rows=3 cols=2 [id name]\n|1|a|\n|10|c|\n|2|b|\n@end"
+    );
+
+    // By key, compared as emitted text
+    let by_name = LooseCanonOpts {
+        sort_rows: RowOrder::ByKey("name".to_string()),
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(
+        canonicalize_loose_with_opts(&a, &by_name).unwrap(),
+        "@tab _ rows=3 cols=2 [id name]\n|1|a|\n|2|b|\n|10|c|\n@end"
+    );
+}
+
+#[test]
+fn test_sort_rows_tables_and_list_rows() {
+    let opts = LooseCanonOpts {
+        sort_rows: RowOrder::ByKey("k".to_string()),
+        tabular_lists: true,
+        ..LooseCanonOpts::default()
+    };
+    let t = GValue::table(["k", "v"], vec![vec![GValue::str("z"), GValue::int(1)], vec![GValue::str("a"), GValue::int(2)]]);
+    assert_eq!(canonicalize_loose_with_opts(&t, &opts).unwrap(), "@tab _ rows=2 cols=2 [k v]\n|a|2|\n|z|1|\n@end");
+
+    // Positional rows have no key column and sort canonically
+    let m = GValue::list(vec![
+        GValue::list(vec![GValue::int(3), GValue::int(0)]),
+        GValue::list(vec![GValue::int(1), GValue::int(9)]),
+        GValue::list(vec![GValue::int(2), GValue::int(5)]),
+    ]);
+    assert_eq!(
+        canonicalize_loose_with_opts(&m, &opts).unwrap(),
+        "@tab _ rows=3 cols=2 []\n|1|9|\n|2|5|\n|3|0|\n@end"
+    );
+}
+
+#[test]
+fn test_align_tabular() {
+    let row = |id: i64, name: &str| GValue::build_map().int("id", id).str("name", name).finish();
+    let v = GValue::list(vec![row(1, "Al"), row(200, "Bea Smith"), row(30, "Cy")]);
+    let aligned = canonicalize_loose_with_opts(&v, &LooseCanonOpts::aligned()).unwrap();
+    assert_eq!(
+        aligned,
+        "@tab _ rows=3 cols=2 [id name]\n|1  |Al         |\n|200|\"Bea Smith\"|\n|30 |Cy         |\n@end"
+    );
+    // Same value, but the canonical form stays unpadded
+    assert!(equal_loose(&parse_loose(&aligned).unwrap(), &v).unwrap());
+    assert!(!canonicalize_loose(&v).unwrap().contains("  "));
+
+    let doc = emit_document(&v, &DocumentOpts::with_canon(LooseCanonOpts::aligned())).unwrap();
+    assert!(doc.lines().next().unwrap().contains("align=t"));
+    let (header, parsed) = parse_document(&doc).unwrap();
+    assert!(header.unwrap().canon_opts().unwrap().align_tabular);
+    assert!(equal_loose(&parsed, &v).unwrap());
+}
+
+#[test]
+fn test_tabular_paths() {
+    let row = |id: i64| GValue::build_map().int("id", id).finish();
+    let rows = |n: i64| GValue::list((1..=n).map(row).collect());
+    let v = GValue::build_map()
+        .value("results", rows(2))
+        .value("metadata", GValue::build_map().value("audit", rows(3)).finish())
+        .value("other", rows(3))
+        .finish();
+
+    let opts = LooseCanonOpts {
+        auto_tabular: false,
+        tabular_paths: vec![TabularRule::force("results[*]").unwrap()],
+        ..LooseCanonOpts::default()
+    };
+    let text = canonicalize_loose_with_opts(&v, &opts).unwrap();
+    // Forced below min_rows; everything else follows auto_tabular=false
+    assert!(text.contains("results=@tab _ rows=2 cols=1 [id]"), "{}", text);
+    assert_eq!(text.matches("@tab").count(), 1, "{}", text);
+
+    let opts = LooseCanonOpts {
+        tabular_paths: vec![TabularRule::off("metadata").unwrap()],
+        ..LooseCanonOpts::default()
+    };
+    let text = canonicalize_loose_with_opts(&v, &opts).unwrap();
+    assert!(text.contains("audit=[{id=1}"), "{}", text);
+    assert!(text.contains("other=@tab"), "{}", text);
+
+    // The last covering rule wins
+    let opts = LooseCanonOpts {
+        tabular_paths: vec![TabularRule::off("$").unwrap(), TabularRule::force("*.audit").unwrap()],
+        ..LooseCanonOpts::default()
+    };
+    let text = canonicalize_loose_with_opts(&v, &opts).unwrap();
+    assert!(text.contains("audit=@tab"), "{}", text);
+    assert_eq!(text.matches("@tab").count(), 1, "{}", text);
+    assert!(equal_loose(&parse_loose(&text).unwrap(), &v).unwrap());
+
+    let doc = emit_document(&v, &DocumentOpts::with_canon(opts.clone())).unwrap();
+    assert!(doc.lines().next().unwrap().contains("tab_paths=off:$,force:*.audit"), "{}", doc);
+    let (header, _) = parse_document(&doc).unwrap();
+    assert_eq!(header.unwrap().canon_opts().unwrap().tabular_paths, opts.tabular_paths);
+}
+
+#[test]
+fn test_tabular_lists_of_lists() {
+    let matrix = GValue::list((0..3).map(|r| GValue::list((0..2).map(|c| GValue::int(r * 2 + c)).collect())).collect());
+    // Off by default
+    assert_eq!(canonicalize_loose(&matrix).unwrap(), "[[0 1] [2 3] [4 5]]");
+
+    let opts = LooseCanonOpts {
+        tabular_lists: true,
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(
+        canonicalize_loose_with_opts(&matrix, &opts).unwrap(),
+        "@tab _ rows=3 cols=2 []\n|0|1|\n|2|3|\n|4|5|\n@end"
+    );
+
+    // Ragged, empty and too-short lists stay plain
+    let ragged = GValue::list(vec![
+        GValue::list(vec![GValue::int(1)]),
+        GValue::list(vec![GValue::int(1), GValue::int(2)]),
+        GValue::list(vec![GValue::int(1)]),
+    ]);
+    assert_eq!(canonicalize_loose_with_opts(&ragged, &opts).unwrap(), "[[1] [1 2] [1]]");
+    let empty = GValue::list(vec![GValue::list(vec![]); 3]);
+    assert_eq!(canonicalize_loose_with_opts(&empty, &opts).unwrap(), "[[] [] []]");
+    let two = GValue::list(vec![GValue::list(vec![GValue::int(1)]); 2]);
+    assert_eq!(canonicalize_loose_with_opts(&two, &opts).unwrap(), "[[1] [1]]");
+}
+
 #[test]
 fn test_tabular_non_object_items() {
     // List of non-objects should not be tabular
@@ -1100,6 +2286,45 @@ fn test_canon_time() {
     assert_eq!(result, "2026-03-09T12:00:00Z");
 }
 
+#[test]
+fn test_canon_time_round_to_bucket() {
+    use chrono::TimeZone;
+    let t1 = chrono::Utc.with_ymd_and_hms(2024, 3, 5, 14, 29, 59).unwrap();
+    let t2 = chrono::Utc.with_ymd_and_hms(2024, 3, 5, 14, 1, 0).unwrap();
+    let hourly = LooseCanonOpts {
+        time_style: TimeStyle::hour(),
+        ..LooseCanonOpts::default()
+    };
+    let a = canonicalize_loose_with_opts(&GValue::time(t1), &hourly).unwrap();
+    let b = canonicalize_loose_with_opts(&GValue::time(t2), &hourly).unwrap();
+    assert_eq!(a, "2024-03-05T14:00:00Z");
+    assert_eq!(a, b);
+
+    let minute = LooseCanonOpts {
+        time_style: TimeStyle::minute(),
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(canonicalize_loose_with_opts(&GValue::time(t1), &minute).unwrap(), "2024-03-05T14:29:00Z");
+    // Exact is the default
+    assert_eq!(canonicalize_loose(&GValue::time(t1)).unwrap(), "2024-03-05T14:29:59Z");
+}
+
+#[test]
+fn test_canon_time_round_before_epoch() {
+    use chrono::TimeZone;
+    let t = chrono::Utc.with_ymd_and_hms(1969, 12, 31, 23, 59, 30).unwrap();
+    let opts = LooseCanonOpts {
+        time_style: TimeStyle::minute(),
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(canonicalize_loose_with_opts(&GValue::time(t), &opts).unwrap(), "1969-12-31T23:59:00Z");
+    let zero = LooseCanonOpts {
+        time_style: TimeStyle::RoundTo(std::time::Duration::ZERO),
+        ..LooseCanonOpts::default()
+    };
+    assert_eq!(canonicalize_loose_with_opts(&GValue::time(t), &zero).unwrap(), "1969-12-31T23:59:30Z");
+}
+
 // ============================================================
 // NEW: Empty list and map
 // ============================================================
@@ -1159,6 +2384,12 @@ fn test_glyph_error_display() {
 
     let e6 = GlyphError::RecursionLimitExceeded { limit: 128 };
     assert_eq!(format!("{}", e6), "Recursion limit exceeded: 128");
+
+    let e7 = GlyphError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "gone"));
+    assert_eq!(format!("{}", e7), "I/O error: gone");
+
+    let e8 = GlyphError::LimitExceeded("output over max_output_bytes 10".to_string());
+    assert_eq!(format!("{}", e8), "Limit exceeded: output over max_output_bytes 10");
 }
 
 // ============================================================
@@ -1203,3 +2434,85 @@ fn test_ref_id_new() {
     assert_eq!(r2.value, "abc");
 }
 
+
+// ============================================================
+// Fluent builders
+// ============================================================
+
+#[test]
+fn test_build_map_chained() {
+    let cursor: Option<&str> = None;
+    let v = GValue::build_map()
+        .str("action", "search")
+        .int("limit", 10)
+        .bool("exact", false)
+        .maybe("cursor", cursor)
+        .maybe("score", Some(0.5))
+        .when(false, "debug", true)
+        .value("tags", GValue::build_list().str("a").str("b"))
+        .finish();
+    assert_eq!(
+        canonicalize_loose(&v).unwrap(),
+        "{action=search exact=f limit=10 score=0.5 tags=[a b]}"
+    );
+    assert!(v.get("cursor").is_none());
+}
+
+#[test]
+fn test_build_struct_and_list() {
+    let v = GValue::build_map().int("x", 1).null("y").finish_struct("Point");
+    assert_eq!(canonicalize_loose(&v).unwrap(), "Point{x=1 y=_}");
+
+    let list = GValue::build_list()
+        .int(1)
+        .maybe(None::<i64>)
+        .extend(["p", "q"])
+        .push(GValue::build_map().int("k", 2))
+        .finish();
+    assert_eq!(canonicalize_loose(&list).unwrap(), "[1 p q {k=2}]");
+    assert!(GValue::build_list().is_empty());
+    assert_eq!(GValue::build_map().null("a").len(), 1);
+}
+
+#[test]
+#[cfg(feature = "compact-str")]
+fn test_compact_str_keeps_short_text_inline() {
+    let v = GValue::map(vec![MapEntry::new("user_id", GValue::str("ada"))]);
+    let GValue::Map(entries) = &v else { unreachable!() };
+    assert!(!entries[0].key.is_heap_allocated());
+    assert!(matches!(&entries[0].value, GValue::Str(s) if !s.is_heap_allocated()));
+    assert_eq!(canonicalize_loose(&v).unwrap(), "{user_id=ada}");
+    assert_eq!(parse_loose("{user_id=ada}").unwrap(), v);
+}
+
+// ============================================================
+// Large lists and tables (split across threads with `parallel`)
+// ============================================================
+
+#[test]
+fn test_large_list_and_table_output_is_ordered() {
+    let n = 10_000;
+    let rows = GValue::list(
+        (0..n)
+            .map(|i| GValue::build_map().int("id", i).str("name", format!("n {}", i)).finish())
+            .collect(),
+    );
+    let mut expected = format!("@tab _ rows={} cols=2 [id name]\n", n);
+    for i in 0..n {
+        expected.push_str(&format!("|{}|\"n {}\"|\n", i, i));
+    }
+    expected.push_str("@end");
+    assert_eq!(canonicalize_loose(&rows).unwrap(), expected);
+
+    let items = GValue::list((0..n).map(|i| GValue::list(vec![GValue::int(i), GValue::str("x")])).collect());
+    let expected = format!("[{}]", (0..n).map(|i| format!("[{} x]", i)).collect::<Vec<_>>().join(" "));
+    assert_eq!(canonicalize_loose_with_opts(&items, &LooseCanonOpts::no_tabular()).unwrap(), expected);
+
+    // The error reported is the first in order, as when encoding serially
+    let mut bad: Vec<GValue> = (0..n).map(GValue::int).collect();
+    bad[9_000] = GValue::str("fine");
+    bad[9_500] = GValue::float(f64::INFINITY);
+    bad[2_000] = GValue::float(f64::NAN);
+    let err = canonicalize_loose(&GValue::list(bad)).unwrap_err();
+    assert!(err.to_string().contains("NaN"), "{}", err);
+}
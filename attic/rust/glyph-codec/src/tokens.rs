@@ -0,0 +1,74 @@
+//! Exact token counts with OpenAI's BPE tokenizers
+//!
+//! `estimate_tokens` guesses from byte length, which is close for English
+//! prose and off for the punctuation-heavy text both JSON and GLYPH are made
+//! of. With the `tiktoken` feature, `token_count` runs the real tokenizer so
+//! budget checks and savings figures match what the model is billed for.
+//! Each tokenizer's tables are loaded once, on first use.
+
+use crate::error::GlyphError;
+use crate::loose::{canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::types::GValue;
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, CoreBPE};
+
+/// BPE vocabulary to count with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Tokenizer {
+    /// GPT-4 and GPT-3.5
+    Cl100k,
+    /// GPT-4o and later
+    #[default]
+    O200k,
+}
+
+impl Tokenizer {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Tokenizer::Cl100k => "cl100k_base",
+            Tokenizer::O200k => "o200k_base",
+        }
+    }
+
+    fn bpe(self) -> &'static CoreBPE {
+        match self {
+            Tokenizer::Cl100k => cl100k_base_singleton(),
+            Tokenizer::O200k => o200k_base_singleton(),
+        }
+    }
+}
+
+/// Tokens in `text`; special-token markers such as `<|endoftext|>` count as
+/// ordinary text
+pub fn token_count(text: &str, tokenizer: Tokenizer) -> usize {
+    tokenizer.bpe().encode_ordinary(text).len()
+}
+
+/// Tokens in the canonical form of `v` under `opts`
+pub fn token_count_value(v: &GValue, opts: &LooseCanonOpts, tokenizer: Tokenizer) -> Result<usize, GlyphError> {
+    Ok(token_count(&canonicalize_loose_with_opts(v, opts)?, tokenizer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_bridge::from_json;
+    use crate::loose::canonicalize_loose;
+    use serde_json::json;
+
+    #[test]
+    fn test_token_count() {
+        assert_eq!(token_count("", Tokenizer::Cl100k), 0);
+        assert_eq!(token_count("hello world", Tokenizer::Cl100k), 2);
+        assert_eq!(token_count("hello world", Tokenizer::O200k), 2);
+        assert_eq!(token_count("<|endoftext|>", Tokenizer::Cl100k), 7);
+    }
+
+    #[test]
+    fn test_token_count_value() {
+        let data = json!({"action": "search", "query": "glyph codec", "limit": 5});
+        let v = from_json(&data);
+        let glyph = token_count_value(&v, &LooseCanonOpts::default(), Tokenizer::Cl100k).unwrap();
+        assert_eq!(glyph, token_count(&canonicalize_loose(&v).unwrap(), Tokenizer::Cl100k));
+        assert!(glyph < token_count(&data.to_string(), Tokenizer::Cl100k));
+    }
+}
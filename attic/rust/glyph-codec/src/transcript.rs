@@ -0,0 +1,246 @@
+//! Chat transcript compression
+//!
+//! Agent transcripts repeat the same JSON over and over: tool results,
+//! pasted documents, the same lookup answered twice. `compress_transcript`
+//! takes a message list in OpenAI or Anthropic shape and returns the same
+//! list with every JSON object or array found in message text, tool results
+//! and (optionally) tool arguments rewritten as canonical GLYPH. Prose is
+//! left alone, and a payload is only rewritten when that makes it shorter.
+//!
+//! With `dedup` on, a payload seen earlier in the transcript is replaced by
+//! a ref to where it first appeared: the tool call id for a tool result
+//! (`^call_abc`), otherwise the message index (`^msg:3`).
+
+use crate::error::GlyphError;
+use crate::json_bridge::try_from_json;
+use crate::loose::{canonicalize_loose, canonicalize_loose_with_opts, LooseCanonOpts};
+use crate::types::*;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+
+/// Options for `compress_transcript`
+#[derive(Debug, Clone)]
+pub struct CompressOpts {
+    /// Replace repeated payloads with a ref to their first appearance
+    pub dedup: bool,
+    /// Payloads shorter than this, as GLYPH, are repeated rather than
+    /// referenced
+    pub min_dedup_bytes: usize,
+    /// Also rewrite OpenAI `function.arguments`. Off by default: the API
+    /// expects JSON there, though models read GLYPH back fine.
+    pub tool_args: bool,
+    pub canon: LooseCanonOpts,
+}
+
+impl Default for CompressOpts {
+    fn default() -> Self {
+        Self {
+            dedup: true,
+            min_dedup_bytes: 64,
+            tool_args: false,
+            canon: LooseCanonOpts::default(),
+        }
+    }
+}
+
+/// Sizes before and after `compress_transcript`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressStats {
+    /// Payloads rewritten as GLYPH
+    pub encoded: usize,
+    /// Payloads replaced by a ref
+    pub deduped: usize,
+    /// Bytes of the rewritten payloads before and after
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// `messages` with their structured parts as GLYPH; see the module docs
+pub fn compress_transcript(messages: &[JsonValue], opts: &CompressOpts) -> Result<Vec<JsonValue>, GlyphError> {
+    compress_transcript_with_stats(messages, opts).map(|(messages, _)| messages)
+}
+
+/// `compress_transcript`, also reporting what it changed
+pub fn compress_transcript_with_stats(
+    messages: &[JsonValue],
+    opts: &CompressOpts,
+) -> Result<(Vec<JsonValue>, CompressStats), GlyphError> {
+    let mut c = Compressor {
+        opts,
+        seen: HashMap::new(),
+        stats: CompressStats::default(),
+    };
+    let mut out = Vec::with_capacity(messages.len());
+    for (i, msg) in messages.iter().enumerate() {
+        let mut msg = msg.clone();
+        c.message(i, &mut msg)?;
+        out.push(msg);
+    }
+    Ok((out, c.stats))
+}
+
+struct Compressor<'a> {
+    opts: &'a CompressOpts,
+    /// Canonical text of each payload seen, to the ref for its first copy
+    seen: HashMap<String, String>,
+    stats: CompressStats,
+}
+
+impl Compressor<'_> {
+    fn message(&mut self, index: usize, msg: &mut JsonValue) -> Result<(), GlyphError> {
+        let here = format_ref(&GValue::id("msg", index.to_string()))?;
+        // OpenAI tool results are answered by call id
+        let owner = match msg.get("tool_call_id").and_then(JsonValue::as_str) {
+            Some(id) => format_ref(&GValue::Id(RefId::simple(id)))?,
+            None => here.clone(),
+        };
+        if let Some(content) = msg.get_mut("content") {
+            self.content(content, &owner)?;
+        }
+        if self.opts.tool_args {
+            if let Some(JsonValue::Array(calls)) = msg.get_mut("tool_calls") {
+                for call in calls {
+                    if let Some(args) = call.pointer_mut("/function/arguments") {
+                        self.text(args, &here, false)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A message's `content`: a string or an array of blocks
+    fn content(&mut self, content: &mut JsonValue, owner: &str) -> Result<(), GlyphError> {
+        match content {
+            JsonValue::String(_) => self.text(content, owner, true),
+            JsonValue::Array(blocks) => {
+                for block in blocks {
+                    match block.get("type").and_then(JsonValue::as_str) {
+                        Some("text") => {
+                            if let Some(text) = block.get_mut("text") {
+                                self.text(text, owner, true)?;
+                            }
+                        }
+                        Some("tool_result") => {
+                            let owner = match block.get("tool_use_id").and_then(JsonValue::as_str) {
+                                Some(id) => format_ref(&GValue::Id(RefId::simple(id)))?,
+                                None => owner.to_string(),
+                            };
+                            if let Some(inner) = block.get_mut("content") {
+                                self.content(inner, &owner)?;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Rewrite a string holding a JSON object or array; `dedup` is off where
+    /// a ref would leave the field unusable
+    fn text(&mut self, slot: &mut JsonValue, owner: &str, dedup: bool) -> Result<(), GlyphError> {
+        let Some(text) = slot.as_str() else {
+            return Ok(());
+        };
+        let trimmed = text.trim_start();
+        if !trimmed.starts_with('{') && !trimmed.starts_with('[') {
+            return Ok(());
+        }
+        let Ok(json) = serde_json::from_str::<JsonValue>(text) else {
+            return Ok(());
+        };
+        let glyph = canonicalize_loose_with_opts(&try_from_json(&json)?, &self.opts.canon)?;
+        let before = text.len();
+        let dedup = dedup && self.opts.dedup && glyph.len() >= self.opts.min_dedup_bytes;
+        let replacement = match self.seen.get(&glyph) {
+            Some(first) if dedup => {
+                self.stats.deduped += 1;
+                first.clone()
+            }
+            _ => {
+                if glyph.len() >= before {
+                    return Ok(());
+                }
+                if dedup {
+                    self.seen.insert(glyph.clone(), owner.to_string());
+                }
+                self.stats.encoded += 1;
+                glyph
+            }
+        };
+        self.stats.bytes_before += before;
+        self.stats.bytes_after += replacement.len();
+        *slot = JsonValue::String(replacement);
+        Ok(())
+    }
+}
+
+fn format_ref(id: &GValue) -> Result<String, GlyphError> {
+    canonicalize_loose(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result_json() -> String {
+        json!({"results": [
+            {"id": 1, "title": "GLYPH codec", "score": 0.9},
+            {"id": 2, "title": "JSON alternatives", "score": 0.7}
+        ]})
+        .to_string()
+    }
+
+    #[test]
+    fn test_compress_openai_transcript() {
+        let messages = vec![
+            json!({"role": "user", "content": "Search twice, please."}),
+            json!({"role": "assistant", "content": null, "tool_calls": [
+                {"id": "call_a", "type": "function", "function": {"name": "search", "arguments": "{\"query\": \"glyph\"}"}},
+                {"id": "call_b", "type": "function", "function": {"name": "search", "arguments": "{\"query\": \"glyph\"}"}}
+            ]}),
+            json!({"role": "tool", "tool_call_id": "call_a", "content": result_json()}),
+            json!({"role": "tool", "tool_call_id": "call_b", "content": result_json()}),
+        ];
+        let (out, stats) = compress_transcript_with_stats(&messages, &CompressOpts::default()).unwrap();
+        assert_eq!(out[0], messages[0]);
+        assert_eq!(out[1], messages[1]);
+        assert!(out[2]["content"].as_str().unwrap().starts_with("{results="));
+        assert_eq!(out[3]["content"], "^call_a");
+        assert_eq!(stats.encoded, 1);
+        assert_eq!(stats.deduped, 1);
+        assert!(stats.bytes_after < stats.bytes_before);
+
+        let opts = CompressOpts {
+            dedup: false,
+            tool_args: true,
+            ..CompressOpts::default()
+        };
+        let out = compress_transcript(&messages, &opts).unwrap();
+        assert_eq!(out[1]["tool_calls"][0]["function"]["arguments"], "{query=glyph}");
+        assert_eq!(out[3]["content"], out[2]["content"]);
+    }
+
+    #[test]
+    fn test_compress_anthropic_blocks() {
+        let messages = vec![
+            json!({"role": "user", "content": [
+                {"type": "text", "text": result_json()},
+                {"type": "text", "text": "Not JSON {at all}"}
+            ]}),
+            json!({"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_01", "content": [{"type": "text", "text": result_json()}]},
+                {"type": "tool_result", "tool_use_id": "toolu_02", "content": "[1, 2]"}
+            ]}),
+        ];
+        let out = compress_transcript(&messages, &CompressOpts::default()).unwrap();
+        assert!(out[0]["content"][0]["text"].as_str().unwrap().starts_with("{results="));
+        assert_eq!(out[0]["content"][1]["text"], "Not JSON {at all}");
+        assert_eq!(out[1]["content"][0]["content"][0]["text"], "^msg:0");
+        assert_eq!(out[1]["content"][1]["content"], "[1 2]");
+    }
+}
@@ -1,9 +1,24 @@
 //! Core GLYPH types
 
+use crate::error::GlyphError;
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Text of `GValue::Str` and map keys: `String`, or with the `compact-str`
+/// feature a `CompactString` that keeps up to 24 bytes inline. Both deref to
+/// `str`, and both convert from `String` and `&str`.
+#[cfg(not(feature = "compact-str"))]
+pub type GStr = String;
+
+/// Text of `GValue::Str` and map keys: `String`, or with the `compact-str`
+/// feature a `CompactString` that keeps up to 24 bytes inline. Both deref to
+/// `str`, and both convert from `String` and `&str`.
+#[cfg(feature = "compact-str")]
+pub type GStr = compact_str::CompactString;
 
 /// GLYPH value type enumeration
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum GValue {
     /// Null value
     Null,
@@ -14,7 +29,7 @@ pub enum GValue {
     /// Floating point value (f64)
     Float(f64),
     /// String value
-    Str(String),
+    Str(GStr),
     /// Binary data (bytes)
     Bytes(Vec<u8>),
     /// Timestamp (UTC)
@@ -29,10 +44,76 @@ pub enum GValue {
     Struct(StructValue),
     /// Sum type (tagged union)
     Sum(SumValue),
+    /// Explicit table, always emitted as an `@tab` block
+    Table(TableValue),
+}
+
+/// Kind of a GValue, named as in the Go port (`int`, `str`, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GType {
+    Null,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Bytes,
+    Time,
+    Id,
+    List,
+    Map,
+    Struct,
+    Sum,
+    Table,
+}
+
+impl GType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GType::Null => "null",
+            GType::Bool => "bool",
+            GType::Int => "int",
+            GType::Float => "float",
+            GType::Str => "str",
+            GType::Bytes => "bytes",
+            GType::Time => "time",
+            GType::Id => "id",
+            GType::List => "list",
+            GType::Map => "map",
+            GType::Struct => "struct",
+            GType::Sum => "sum",
+            GType::Table => "table",
+        }
+    }
+
+    /// Parse a type name as written by `as_str`
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "null" => GType::Null,
+            "bool" => GType::Bool,
+            "int" => GType::Int,
+            "float" => GType::Float,
+            "str" => GType::Str,
+            "bytes" => GType::Bytes,
+            "time" => GType::Time,
+            "id" => GType::Id,
+            "list" => GType::List,
+            "map" => GType::Map,
+            "struct" => GType::Struct,
+            "sum" => GType::Sum,
+            "table" => GType::Table,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for GType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Reference ID with optional prefix
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct RefId {
     pub prefix: String,
     pub value: String,
@@ -55,14 +136,14 @@ impl RefId {
 }
 
 /// Map entry (key-value pair)
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MapEntry {
-    pub key: String,
+    pub key: GStr,
     pub value: GValue,
 }
 
 impl MapEntry {
-    pub fn new(key: impl Into<String>, value: GValue) -> Self {
+    pub fn new(key: impl Into<GStr>, value: GValue) -> Self {
         Self {
             key: key.into(),
             value,
@@ -71,7 +152,7 @@ impl MapEntry {
 }
 
 /// Typed struct value
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StructValue {
     pub type_name: String,
     pub fields: Vec<MapEntry>,
@@ -87,7 +168,7 @@ impl StructValue {
 }
 
 /// Sum type (tagged union)
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SumValue {
     pub tag: String,
     pub value: Option<Box<GValue>>,
@@ -102,6 +183,67 @@ impl SumValue {
     }
 }
 
+/// Table of named columns and positional rows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableValue {
+    pub columns: Vec<String>,
+    /// Each row holds one cell per column, in column order
+    pub rows: Vec<Vec<GValue>>,
+    /// Declared cell type per column (`None` = unannotated); empty when the
+    /// table has no annotations
+    pub types: Vec<Option<GType>>,
+}
+
+impl TableValue {
+    pub fn new(columns: Vec<String>, rows: Vec<Vec<GValue>>) -> Self {
+        Self {
+            columns,
+            rows,
+            types: Vec::new(),
+        }
+    }
+
+    /// Declare per-column cell types, emitted as `name:type` in the header
+    pub fn with_types(mut self, types: Vec<Option<GType>>) -> Self {
+        self.types = types;
+        self
+    }
+
+    /// Declared type of column `idx`
+    pub fn column_type(&self, idx: usize) -> Option<GType> {
+        self.types.get(idx).copied().flatten()
+    }
+
+    /// Index of `column`, if present
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == column)
+    }
+
+    /// Row `idx` as a map of column to cell
+    pub fn row(&self, idx: usize) -> Option<GValue> {
+        let row = self.rows.get(idx)?;
+        Some(GValue::Map(
+            self.columns.iter().zip(row).map(|(c, v)| MapEntry::new(c.clone(), v.clone())).collect(),
+        ))
+    }
+
+    /// The equivalent list of row maps
+    pub fn to_list(&self) -> GValue {
+        GValue::List((0..self.rows.len()).filter_map(|i| self.row(i)).collect())
+    }
+
+    /// `to_list`, moving the cells instead of cloning them
+    pub fn into_list(self) -> GValue {
+        let columns = self.columns;
+        GValue::List(
+            self.rows
+                .into_iter()
+                .map(|row| GValue::Map(columns.iter().zip(row).map(|(c, v)| MapEntry::new(c.clone(), v)).collect()))
+                .collect(),
+        )
+    }
+}
+
 // ============================================================
 // Builder functions
 // ============================================================
@@ -128,7 +270,7 @@ impl GValue {
     }
 
     /// Create a string value
-    pub fn str(v: impl Into<String>) -> Self {
+    pub fn str(v: impl Into<GStr>) -> Self {
         GValue::Str(v.into())
     }
 
@@ -142,6 +284,11 @@ impl GValue {
         GValue::Time(v)
     }
 
+    /// Create a timestamp value for the current time
+    pub fn now() -> Self {
+        GValue::Time(Utc::now())
+    }
+
     /// Create a reference ID
     pub fn id(prefix: impl Into<String>, value: impl Into<String>) -> Self {
         GValue::Id(RefId::new(prefix, value))
@@ -172,10 +319,33 @@ impl GValue {
         GValue::Sum(SumValue::new(tag, value))
     }
 
+    /// Create a table value
+    pub fn table<S: Into<String>>(columns: impl IntoIterator<Item = S>, rows: Vec<Vec<GValue>>) -> Self {
+        GValue::Table(TableValue::new(columns.into_iter().map(Into::into).collect(), rows))
+    }
+
     // ============================================================
     // Type checking
     // ============================================================
 
+    pub fn gtype(&self) -> GType {
+        match self {
+            GValue::Null => GType::Null,
+            GValue::Bool(_) => GType::Bool,
+            GValue::Int(_) => GType::Int,
+            GValue::Float(_) => GType::Float,
+            GValue::Str(_) => GType::Str,
+            GValue::Bytes(_) => GType::Bytes,
+            GValue::Time(_) => GType::Time,
+            GValue::Id(_) => GType::Id,
+            GValue::List(_) => GType::List,
+            GValue::Map(_) => GType::Map,
+            GValue::Struct(_) => GType::Struct,
+            GValue::Sum(_) => GType::Sum,
+            GValue::Table(_) => GType::Table,
+        }
+    }
+
     pub fn is_null(&self) -> bool {
         matches!(self, GValue::Null)
     }
@@ -224,6 +394,10 @@ impl GValue {
         matches!(self, GValue::Sum(_))
     }
 
+    pub fn is_table(&self) -> bool {
+        matches!(self, GValue::Table(_))
+    }
+
     // ============================================================
     // Value extraction
     // ============================================================
@@ -305,6 +479,13 @@ impl GValue {
         }
     }
 
+    pub fn as_table(&self) -> Option<&TableValue> {
+        match self {
+            GValue::Table(v) => Some(v),
+            _ => None,
+        }
+    }
+
     /// Get a value from a map or struct by key
     pub fn get(&self, key: &str) -> Option<&GValue> {
         match self {
@@ -314,6 +495,15 @@ impl GValue {
         }
     }
 
+    /// Get a mutable value from a map or struct by key
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut GValue> {
+        match self {
+            GValue::Map(entries) => entries.iter_mut().find(|e| e.key == key).map(|e| &mut e.value),
+            GValue::Struct(s) => s.fields.iter_mut().find(|e| e.key == key).map(|e| &mut e.value),
+            _ => None,
+        }
+    }
+
     /// Get a value from a list by index
     pub fn index(&self, idx: usize) -> Option<&GValue> {
         match self {
@@ -321,9 +511,469 @@ impl GValue {
             _ => None,
         }
     }
+
+    /// Get a mutable value from a list by index
+    pub fn index_mut(&mut self, idx: usize) -> Option<&mut GValue> {
+        match self {
+            GValue::List(items) => items.get_mut(idx),
+            _ => None,
+        }
+    }
+}
+
+// ============================================================
+// Fallible extraction
+// ============================================================
+
+/// `Result` forms of the accessors above, for `?` in tool handlers
+impl GValue {
+    fn mismatch(&self, expected: &str) -> GlyphError {
+        GlyphError::TypeMismatch {
+            expected: expected.to_string(),
+            got: self.gtype().to_string(),
+        }
+    }
+
+    fn typed<T>(&self, expected: GType, v: Option<T>) -> Result<T, GlyphError> {
+        v.ok_or_else(|| self.mismatch(expected.as_str()))
+    }
+
+    pub fn try_as_bool(&self) -> Result<bool, GlyphError> {
+        self.typed(GType::Bool, self.as_bool())
+    }
+
+    pub fn try_as_int(&self) -> Result<i64, GlyphError> {
+        self.typed(GType::Int, self.as_int())
+    }
+
+    pub fn try_as_float(&self) -> Result<f64, GlyphError> {
+        self.typed(GType::Float, self.as_float())
+    }
+
+    pub fn try_as_str(&self) -> Result<&str, GlyphError> {
+        self.typed(GType::Str, self.as_str())
+    }
+
+    pub fn try_as_bytes(&self) -> Result<&[u8], GlyphError> {
+        self.typed(GType::Bytes, self.as_bytes())
+    }
+
+    pub fn try_as_time(&self) -> Result<&DateTime<Utc>, GlyphError> {
+        self.typed(GType::Time, self.as_time())
+    }
+
+    pub fn try_as_id(&self) -> Result<&RefId, GlyphError> {
+        self.typed(GType::Id, self.as_id())
+    }
+
+    /// The items of a list. A table is rejected with `TypeMismatch`: its rows
+    /// are not stored as values, so there is no slice to borrow. Use
+    /// `try_as_table`, or `expand_table` to turn it into a list.
+    pub fn try_as_list(&self) -> Result<&[GValue], GlyphError> {
+        self.typed(GType::List, self.as_list())
+    }
+
+    pub fn try_as_map(&self) -> Result<&[MapEntry], GlyphError> {
+        self.typed(GType::Map, self.as_map())
+    }
+
+    pub fn try_as_struct(&self) -> Result<&StructValue, GlyphError> {
+        self.typed(GType::Struct, self.as_struct())
+    }
+
+    pub fn try_as_sum(&self) -> Result<&SumValue, GlyphError> {
+        self.typed(GType::Sum, self.as_sum())
+    }
+
+    pub fn try_as_table(&self) -> Result<&TableValue, GlyphError> {
+        self.typed(GType::Table, self.as_table())
+    }
+
+    /// `get`, failing with `MissingField(key)` or `TypeMismatch` if not a map or struct
+    pub fn try_get(&self, key: &str) -> Result<&GValue, GlyphError> {
+        match self {
+            GValue::Map(_) | GValue::Struct(_) => self.get(key).ok_or_else(|| GlyphError::MissingField(key.to_string())),
+            _ => Err(self.mismatch("map or struct")),
+        }
+    }
+
+    /// `index`, failing with `MissingField("[i]")` or `TypeMismatch` if not a
+    /// list. A table is a `TypeMismatch`, as in `try_as_list`; `try_get_path`
+    /// reads its cells as `[i].column`.
+    pub fn try_index(&self, idx: usize) -> Result<&GValue, GlyphError> {
+        match self {
+            GValue::List(items) => items.get(idx).ok_or_else(|| GlyphError::MissingField(format!("[{}]", idx))),
+            _ => Err(self.mismatch("list")),
+        }
+    }
+}
+
+// ============================================================
+// Index operators
+// ============================================================
+
+static NULL: GValue = GValue::Null;
+
+/// `value["key"]`: the first entry with `key`, or `Null` if there is none or
+/// the value is not a map or struct. Use `get` to tell those apart.
+impl std::ops::Index<&str> for GValue {
+    type Output = GValue;
+
+    fn index(&self, key: &str) -> &GValue {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// `value[i]`: the list item, or `Null` if out of range or not a list (a
+/// table's rows have no node to return)
+impl std::ops::Index<usize> for GValue {
+    type Output = GValue;
+
+    fn index(&self, idx: usize) -> &GValue {
+        GValue::index(self, idx).unwrap_or(&NULL)
+    }
+}
+
+/// `value["key"] = v`: a missing key is added as `Null` first, and a `Null`
+/// value becomes an empty map.
+///
+/// Panics if the value is neither a map, a struct nor `Null`.
+impl std::ops::IndexMut<&str> for GValue {
+    fn index_mut(&mut self, key: &str) -> &mut GValue {
+        if matches!(self, GValue::Null) {
+            *self = GValue::Map(Vec::new());
+        }
+        let entries = match self {
+            GValue::Map(entries) => entries,
+            GValue::Struct(s) => &mut s.fields,
+            other => panic!("cannot index {} with key {:?}", other.gtype(), key),
+        };
+        let i = match entries.iter().position(|e| e.key == key) {
+            Some(i) => i,
+            None => {
+                entries.push(MapEntry::new(key, GValue::Null));
+                entries.len() - 1
+            }
+        };
+        &mut entries[i].value
+    }
+}
+
+/// `value[i] = v`.
+///
+/// Panics if the value is not a list or `i` is out of range. A table reads as
+/// `Null` through `value[i]`, so writing one panics too: call `expand_table`
+/// first to edit its rows.
+impl std::ops::IndexMut<usize> for GValue {
+    fn index_mut(&mut self, idx: usize) -> &mut GValue {
+        match self {
+            GValue::List(items) => {
+                let len = items.len();
+                items
+                    .get_mut(idx)
+                    .unwrap_or_else(|| panic!("index {} out of range for list of {}", idx, len))
+            }
+            other => panic!("cannot index {} with {}", other.gtype(), idx),
+        }
+    }
+}
+
+// ============================================================
+// Mutation
+// ============================================================
+
+impl GValue {
+    fn entries_mut(&mut self) -> Result<&mut Vec<MapEntry>, GlyphError> {
+        match self {
+            GValue::Map(entries) => Ok(entries),
+            GValue::Struct(s) => Ok(&mut s.fields),
+            other => Err(GlyphError::TypeMismatch {
+                expected: "map or struct".to_string(),
+                got: other.gtype().to_string(),
+            }),
+        }
+    }
+
+    /// Set `key` in a map or struct, returning the old value.
+    ///
+    /// Replaces the first entry with that key, or appends a new one.
+    pub fn insert(&mut self, key: impl Into<GStr>, value: GValue) -> Result<Option<GValue>, GlyphError> {
+        let key = key.into();
+        let entries = self.entries_mut()?;
+        match entries.iter_mut().find(|e| e.key == key) {
+            Some(entry) => Ok(Some(std::mem::replace(&mut entry.value, value))),
+            None => {
+                entries.push(MapEntry::new(key, value));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the first entry with `key` from a map or struct
+    pub fn remove(&mut self, key: &str) -> Result<Option<GValue>, GlyphError> {
+        let entries = self.entries_mut()?;
+        Ok(entries.iter().position(|e| e.key == key).map(|i| entries.remove(i).value))
+    }
+
+    /// Turn a `Table` into its list of row maps, in place, so its rows can be
+    /// edited like list items. Other values are left as they are.
+    pub fn expand_table(&mut self) -> &mut Self {
+        if self.is_table() {
+            if let GValue::Table(t) = std::mem::replace(self, GValue::Null) {
+                *self = t.into_list();
+            }
+        }
+        self
+    }
+
+    /// Append to a list; a table becomes its row list first
+    pub fn push(&mut self, value: GValue) -> Result<(), GlyphError> {
+        match self.expand_table() {
+            GValue::List(items) => {
+                items.push(value);
+                Ok(())
+            }
+            other => Err(GlyphError::TypeMismatch {
+                expected: "list".to_string(),
+                got: other.gtype().to_string(),
+            }),
+        }
+    }
 }
 
 /// Helper to create a map entry
-pub fn field(key: impl Into<String>, value: GValue) -> MapEntry {
+pub fn field(key: impl Into<GStr>, value: GValue) -> MapEntry {
     MapEntry::new(key, value)
 }
+
+impl From<bool> for GValue {
+    fn from(v: bool) -> Self {
+        GValue::Bool(v)
+    }
+}
+
+impl From<i64> for GValue {
+    fn from(v: i64) -> Self {
+        GValue::Int(v)
+    }
+}
+
+impl From<f64> for GValue {
+    fn from(v: f64) -> Self {
+        GValue::Float(v)
+    }
+}
+
+impl From<&str> for GValue {
+    fn from(v: &str) -> Self {
+        GValue::str(v)
+    }
+}
+
+impl From<String> for GValue {
+    fn from(v: String) -> Self {
+        GValue::str(v)
+    }
+}
+
+impl From<&String> for GValue {
+    fn from(v: &String) -> Self {
+        GValue::str(v.as_str())
+    }
+}
+
+/// Bytes, not a list of ints
+impl From<Vec<u8>> for GValue {
+    fn from(v: Vec<u8>) -> Self {
+        GValue::Bytes(v)
+    }
+}
+
+impl From<DateTime<Utc>> for GValue {
+    fn from(v: DateTime<Utc>) -> Self {
+        GValue::Time(v)
+    }
+}
+
+impl From<RefId> for GValue {
+    fn from(v: RefId) -> Self {
+        GValue::Id(v)
+    }
+}
+
+impl<T: Into<GValue>> From<Vec<T>> for GValue {
+    fn from(v: Vec<T>) -> Self {
+        GValue::List(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// A map in key order
+impl<K: Into<GStr>, T: Into<GValue>> From<BTreeMap<K, T>> for GValue {
+    fn from(v: BTreeMap<K, T>) -> Self {
+        GValue::Map(v.into_iter().map(|(k, v)| MapEntry::new(k, v.into())).collect())
+    }
+}
+
+/// `None` is `Null`
+impl<T: Into<GValue>> From<Option<T>> for GValue {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(GValue::Null, Into::into)
+    }
+}
+
+// ============================================================
+// Fluent builders
+// ============================================================
+
+impl GValue {
+    /// Start building a map field by field
+    pub fn build_map() -> GMapBuilder {
+        GMapBuilder::default()
+    }
+
+    /// Start building a list item by item
+    pub fn build_list() -> GListBuilder {
+        GListBuilder::default()
+    }
+}
+
+/// Chained map construction: `GValue::build_map().str("action", "search").finish()`
+#[derive(Debug, Clone, Default)]
+pub struct GMapBuilder {
+    entries: Vec<MapEntry>,
+}
+
+impl GMapBuilder {
+    /// Add an entry with any value convertible to `GValue`
+    pub fn value(mut self, key: impl Into<GStr>, value: impl Into<GValue>) -> Self {
+        self.entries.push(MapEntry::new(key, value.into()));
+        self
+    }
+
+    pub fn null(self, key: impl Into<GStr>) -> Self {
+        self.value(key, GValue::Null)
+    }
+
+    pub fn bool(self, key: impl Into<GStr>, v: bool) -> Self {
+        self.value(key, v)
+    }
+
+    pub fn int(self, key: impl Into<GStr>, v: i64) -> Self {
+        self.value(key, v)
+    }
+
+    pub fn float(self, key: impl Into<GStr>, v: f64) -> Self {
+        self.value(key, v)
+    }
+
+    pub fn str(self, key: impl Into<GStr>, v: impl Into<GStr>) -> Self {
+        self.value(key, GValue::str(v))
+    }
+
+    /// Add the entry only when `value` is `Some`
+    pub fn maybe<V: Into<GValue>>(self, key: impl Into<GStr>, value: Option<V>) -> Self {
+        match value {
+            Some(v) => self.value(key, v),
+            None => self,
+        }
+    }
+
+    /// Add the entry only when `cond` holds
+    pub fn when(self, cond: bool, key: impl Into<GStr>, value: impl Into<GValue>) -> Self {
+        if cond {
+            self.value(key, value)
+        } else {
+            self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Finish as a map
+    pub fn finish(self) -> GValue {
+        GValue::Map(self.entries)
+    }
+
+    /// Finish as a struct named `type_name`
+    pub fn finish_struct(self, type_name: impl Into<String>) -> GValue {
+        GValue::struct_val(type_name, self.entries)
+    }
+}
+
+impl From<GMapBuilder> for GValue {
+    fn from(b: GMapBuilder) -> Self {
+        b.finish()
+    }
+}
+
+/// Chained list construction: `GValue::build_list().int(1).str("x").finish()`
+#[derive(Debug, Clone, Default)]
+pub struct GListBuilder {
+    items: Vec<GValue>,
+}
+
+impl GListBuilder {
+    /// Append any value convertible to `GValue`
+    pub fn push(mut self, value: impl Into<GValue>) -> Self {
+        self.items.push(value.into());
+        self
+    }
+
+    pub fn null(self) -> Self {
+        self.push(GValue::Null)
+    }
+
+    pub fn bool(self, v: bool) -> Self {
+        self.push(v)
+    }
+
+    pub fn int(self, v: i64) -> Self {
+        self.push(v)
+    }
+
+    pub fn float(self, v: f64) -> Self {
+        self.push(v)
+    }
+
+    pub fn str(self, v: impl Into<GStr>) -> Self {
+        self.push(GValue::str(v))
+    }
+
+    /// Append only when `value` is `Some`
+    pub fn maybe<V: Into<GValue>>(self, value: Option<V>) -> Self {
+        match value {
+            Some(v) => self.push(v),
+            None => self,
+        }
+    }
+
+    /// Append every item
+    pub fn extend<V: Into<GValue>>(mut self, values: impl IntoIterator<Item = V>) -> Self {
+        self.items.extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn finish(self) -> GValue {
+        GValue::List(self.items)
+    }
+}
+
+impl From<GListBuilder> for GValue {
+    fn from(b: GListBuilder) -> Self {
+        b.finish()
+    }
+}
@@ -0,0 +1,180 @@
+//! Unicode canonical normalization (NFC)
+//!
+//! Self-contained implementation of canonical decomposition, canonical
+//! ordering and canonical composition (UAX #15), driven by the tables in
+//! `unicode_tables.rs`.
+
+use crate::unicode_tables::{CCC, COMPOSE, DECOMP, DECOMP_CHARS};
+use std::borrow::Cow;
+
+// Hangul syllables are composed/decomposed algorithmically
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Lowest code point that can change under NFC
+const FIRST_AFFECTED: char = '\u{300}';
+
+/// Normalize `s` to Unicode Normalization Form C.
+///
+/// Returns the input unchanged (borrowed) when no character can be affected.
+pub fn nfc(s: &str) -> Cow<'_, str> {
+    if s.chars().all(|c| c < FIRST_AFFECTED) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut chars: Vec<char> = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        decompose(c, &mut chars);
+    }
+    reorder(&mut chars);
+    compose(&mut chars);
+
+    let out: String = chars.into_iter().collect();
+    if out == s {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(out)
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    let cp = c as u32;
+    CCC.binary_search_by(|&(lo, hi, _)| {
+        if hi < cp {
+            std::cmp::Ordering::Less
+        } else if lo > cp {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    })
+    .map(|i| CCC[i].2)
+    .unwrap_or(0)
+}
+
+fn decompose(c: char, out: &mut Vec<char>) {
+    let cp = c as u32;
+    if (S_BASE..S_BASE + S_COUNT).contains(&cp) {
+        let s = cp - S_BASE;
+        out.push(char_from(L_BASE + s / N_COUNT));
+        out.push(char_from(V_BASE + (s % N_COUNT) / T_COUNT));
+        if !s.is_multiple_of(T_COUNT) {
+            out.push(char_from(T_BASE + s % T_COUNT));
+        }
+        return;
+    }
+    match DECOMP.binary_search_by_key(&cp, |&(k, _, _)| k) {
+        Ok(i) => {
+            let (_, offset, len) = DECOMP[i];
+            let start = offset as usize;
+            out.extend(DECOMP_CHARS[start..start + len as usize].iter().map(|&d| char_from(d)));
+        }
+        Err(_) => out.push(c),
+    }
+}
+
+/// Stable-sort each run of non-starters by combining class
+fn reorder(chars: &mut [char]) {
+    let mut i = 0;
+    while i < chars.len() {
+        if combining_class(chars[i]) == 0 {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && combining_class(chars[i]) != 0 {
+            i += 1;
+        }
+        chars[start..i].sort_by_key(|&c| combining_class(c));
+    }
+}
+
+fn compose(chars: &mut Vec<char>) {
+    let mut out: Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter: Option<usize> = None;
+    let mut last_class: i16 = -1;
+
+    for &c in chars.iter() {
+        let class = combining_class(c) as i16;
+        if let Some(si) = starter {
+            // Blocked if an intervening character has the same or higher class
+            let blocked = last_class != -1 && (last_class >= class || last_class == 0);
+            if !blocked {
+                if let Some(composite) = compose_pair(out[si], c) {
+                    out[si] = composite;
+                    continue;
+                }
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+            last_class = -1;
+        } else {
+            last_class = class;
+        }
+        out.push(c);
+    }
+    *chars = out;
+}
+
+fn compose_pair(a: char, b: char) -> Option<char> {
+    let (a, b) = (a as u32, b as u32);
+
+    // Hangul LV and LVT
+    if (L_BASE..L_BASE + L_COUNT).contains(&a) && (V_BASE..V_BASE + V_COUNT).contains(&b) {
+        return Some(char_from(S_BASE + ((a - L_BASE) * V_COUNT + (b - V_BASE)) * T_COUNT));
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&a)
+        && (a - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&b)
+    {
+        return Some(char_from(a + (b - T_BASE)));
+    }
+
+    COMPOSE
+        .binary_search_by_key(&(a, b), |&(x, y, _)| (x, y))
+        .ok()
+        .map(|i| char_from(COMPOSE[i].2))
+}
+
+fn char_from(cp: u32) -> char {
+    char::from_u32(cp).expect("unicode table holds valid scalar values")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfc_composes() {
+        assert_eq!(nfc("e\u{301}"), "\u{e9}");
+        assert_eq!(nfc("A\u{30a}ngstr\u{f6}m"), "\u{c5}ngstr\u{f6}m");
+        // Ohm sign is a singleton decomposition to Omega
+        assert_eq!(nfc("\u{2126}"), "\u{3a9}");
+        // Hangul jamo
+        assert_eq!(nfc("\u{1112}\u{1161}\u{11ab}"), "\u{d55c}");
+    }
+
+    #[test]
+    fn test_nfc_reorders_marks() {
+        // dot below (ccc 220) sorts before dot above (ccc 230)
+        assert_eq!(nfc("q\u{307}\u{323}"), "q\u{323}\u{307}");
+        assert_eq!(nfc("s\u{307}\u{323}"), "\u{1e69}");
+        assert_eq!(nfc("s\u{323}\u{307}"), "\u{1e69}");
+    }
+
+    #[test]
+    fn test_nfc_borrows_when_unchanged() {
+        assert!(matches!(nfc("plain ascii"), Cow::Borrowed(_)));
+        assert!(matches!(nfc("caf\u{e9} \u{d55c}"), Cow::Borrowed(_)));
+        // Composition exclusions stay decomposed
+        assert_eq!(nfc("\u{915}\u{93c}"), "\u{915}\u{93c}");
+    }
+}
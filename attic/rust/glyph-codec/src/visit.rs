@@ -0,0 +1,407 @@
+//! Deep iteration and transformation of GValue trees
+//!
+//! `GValue::walk` yields every node with its path, depth-first; `Visitor`
+//! adds enter/exit hooks around maps, lists, structs and sums for analyses
+//! that need to know where a container ends. `transform` and `map_values`
+//! rebuild a tree through a closure. Table rows have no node of their own, so
+//! a walk or transform goes from a table straight to its cells, at
+//! `path[i].column`.
+
+use crate::path::{GlyphPath, PathPattern, PathSegment};
+use crate::types::*;
+
+/// Depth-first, pre-order iterator over `(path, node)` pairs
+pub struct Walk<'a> {
+    stack: Vec<(GlyphPath, &'a GValue)>,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (GlyphPath, &'a GValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+        let child = |seg: PathSegment| GlyphPath::from_segments(path.segments().iter().cloned().chain([seg]).collect());
+        // Pushed in reverse so children come out in order
+        match node {
+            GValue::List(items) => {
+                for (i, item) in items.iter().enumerate().rev() {
+                    self.stack.push((child(PathSegment::Index(i)), item));
+                }
+            }
+            GValue::Map(entries) | GValue::Struct(StructValue { fields: entries, .. }) => {
+                for e in entries.iter().rev() {
+                    self.stack.push((child(PathSegment::Key(e.key.to_string())), &e.value));
+                }
+            }
+            GValue::Sum(s) => {
+                if let Some(inner) = &s.value {
+                    self.stack.push((child(PathSegment::Key(s.tag.clone())), inner));
+                }
+            }
+            GValue::Table(t) => {
+                for (i, row) in t.rows.iter().enumerate().rev() {
+                    for (col, cell) in t.columns.iter().zip(row).rev() {
+                        let mut p = child(PathSegment::Index(i));
+                        p.push_key(col.clone());
+                        self.stack.push((p, cell));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Some((path, node))
+    }
+}
+
+/// Hooks called by `GValue::visit`. Every method defaults to doing nothing.
+///
+/// Containers get `enter_*` before their children and `exit_*` after; all
+/// other nodes get `visit_leaf`. A table's children are its cells.
+#[allow(unused_variables)]
+pub trait Visitor {
+    fn visit_leaf(&mut self, path: &GlyphPath, v: &GValue) {}
+    fn enter_map(&mut self, path: &GlyphPath, entries: &[MapEntry]) {}
+    fn exit_map(&mut self, path: &GlyphPath, entries: &[MapEntry]) {}
+    fn enter_list(&mut self, path: &GlyphPath, items: &[GValue]) {}
+    fn exit_list(&mut self, path: &GlyphPath, items: &[GValue]) {}
+    fn enter_struct(&mut self, path: &GlyphPath, s: &StructValue) {}
+    fn exit_struct(&mut self, path: &GlyphPath, s: &StructValue) {}
+    fn enter_sum(&mut self, path: &GlyphPath, s: &SumValue) {}
+    fn exit_sum(&mut self, path: &GlyphPath, s: &SumValue) {}
+    fn enter_table(&mut self, path: &GlyphPath, t: &TableValue) {}
+    fn exit_table(&mut self, path: &GlyphPath, t: &TableValue) {}
+}
+
+impl GValue {
+    /// Every node with its path, depth-first, starting with `self` at the root
+    pub fn walk(&self) -> Walk<'_> {
+        Walk {
+            stack: vec![(GlyphPath::root(), self)],
+        }
+    }
+
+    /// Drive `visitor` over the tree, depth-first
+    pub fn visit<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        visit_node(self, &mut GlyphPath::root(), visitor);
+    }
+}
+
+// ============================================================
+// Transformation
+// ============================================================
+
+impl GValue {
+    /// Rebuild the tree bottom-up, passing every node to `f` after its
+    /// children. Returning `None` drops the node from its map, list or sum;
+    /// dropping the root leaves `Null`.
+    ///
+    /// Paths are those of the input tree, so indices after a dropped list
+    /// item still count it. Table cells are passed as `path[i].column`, and a
+    /// dropped cell becomes null; rows have no node, so `f` never sees one.
+    pub fn transform(self, mut f: impl FnMut(&GlyphPath, GValue) -> Option<GValue>) -> GValue {
+        transform_node(self, &mut GlyphPath::root(), &mut f).unwrap_or(GValue::Null)
+    }
+
+    /// `transform`, calling `f` only on nodes whose path matches `pattern`
+    pub fn transform_matching(
+        self,
+        pattern: &PathPattern,
+        mut f: impl FnMut(&GlyphPath, GValue) -> Option<GValue>,
+    ) -> GValue {
+        self.transform(|path, v| if pattern.matches(path) { f(path, v) } else { Some(v) })
+    }
+
+    /// Replace every leaf (anything but a map, list, struct, sum or table)
+    /// with `f`'s result
+    pub fn map_values(self, mut f: impl FnMut(&GlyphPath, GValue) -> GValue) -> GValue {
+        self.transform(|path, v| match v {
+            GValue::Map(_) | GValue::List(_) | GValue::Struct(_) | GValue::Sum(_) | GValue::Table(_) => Some(v),
+            leaf => Some(f(path, leaf)),
+        })
+    }
+}
+
+type TransformFn<'f> = dyn FnMut(&GlyphPath, GValue) -> Option<GValue> + 'f;
+
+fn transform_entries(entries: Vec<MapEntry>, path: &mut GlyphPath, f: &mut TransformFn<'_>) -> Vec<MapEntry> {
+    let mut out = Vec::with_capacity(entries.len());
+    for e in entries {
+        path.push_key(e.key.clone());
+        if let Some(value) = transform_node(e.value, path, f) {
+            out.push(MapEntry::new(e.key, value));
+        }
+        path.pop();
+    }
+    out
+}
+
+fn transform_node(v: GValue, path: &mut GlyphPath, f: &mut TransformFn<'_>) -> Option<GValue> {
+    let v = match v {
+        GValue::Map(entries) => GValue::Map(transform_entries(entries, path, f)),
+        GValue::Struct(s) => GValue::Struct(StructValue {
+            fields: transform_entries(s.fields, path, f),
+            type_name: s.type_name,
+        }),
+        GValue::List(items) => {
+            let mut out = Vec::with_capacity(items.len());
+            for (i, item) in items.into_iter().enumerate() {
+                path.push_index(i);
+                out.extend(transform_node(item, path, f));
+                path.pop();
+            }
+            GValue::List(out)
+        }
+        GValue::Sum(s) => {
+            let value = s.value.and_then(|inner| {
+                path.push_key(s.tag.clone());
+                let out = transform_node(*inner, path, f);
+                path.pop();
+                out
+            });
+            GValue::Sum(SumValue::new(s.tag, value))
+        }
+        GValue::Table(mut t) => {
+            for (i, row) in t.rows.iter_mut().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    *cell = transform_node(std::mem::replace(cell, GValue::Null), path, f).unwrap_or(GValue::Null);
+                    path.pop();
+                }
+                path.pop();
+            }
+            GValue::Table(t)
+        }
+        leaf => leaf,
+    };
+    f(path, v)
+}
+
+// ============================================================
+// Visiting
+// ============================================================
+
+fn visit_entries<V: Visitor + ?Sized>(entries: &[MapEntry], path: &mut GlyphPath, visitor: &mut V) {
+    for e in entries {
+        path.push_key(e.key.clone());
+        visit_node(&e.value, path, visitor);
+        path.pop();
+    }
+}
+
+fn visit_node<V: Visitor + ?Sized>(v: &GValue, path: &mut GlyphPath, visitor: &mut V) {
+    match v {
+        GValue::Map(entries) => {
+            visitor.enter_map(path, entries);
+            visit_entries(entries, path, visitor);
+            visitor.exit_map(path, entries);
+        }
+        GValue::Struct(s) => {
+            visitor.enter_struct(path, s);
+            visit_entries(&s.fields, path, visitor);
+            visitor.exit_struct(path, s);
+        }
+        GValue::List(items) => {
+            visitor.enter_list(path, items);
+            for (i, item) in items.iter().enumerate() {
+                path.push_index(i);
+                visit_node(item, path, visitor);
+                path.pop();
+            }
+            visitor.exit_list(path, items);
+        }
+        GValue::Sum(s) => {
+            visitor.enter_sum(path, s);
+            if let Some(inner) = &s.value {
+                path.push_key(s.tag.clone());
+                visit_node(inner, path, visitor);
+                path.pop();
+            }
+            visitor.exit_sum(path, s);
+        }
+        GValue::Table(t) => {
+            visitor.enter_table(path, t);
+            for (i, row) in t.rows.iter().enumerate() {
+                path.push_index(i);
+                for (col, cell) in t.columns.iter().zip(row) {
+                    path.push_key(col.clone());
+                    visit_node(cell, path, visitor);
+                    path.pop();
+                }
+                path.pop();
+            }
+            visitor.exit_table(path, t);
+        }
+        leaf => visitor.visit_leaf(path, leaf),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> GValue {
+        GValue::build_map()
+            .str("tool", "search")
+            .value("args", GValue::list(vec![GValue::int(1), GValue::build_map().str("token", "secret").finish()]))
+            .value("result", GValue::sum("Ok", Some(GValue::struct_val("Hit", vec![field("score", GValue::float(0.5))]))))
+            .finish()
+    }
+
+    #[test]
+    fn test_walk_order() {
+        let v = sample();
+        let paths: Vec<String> = v.walk().map(|(p, _)| p.to_string()).collect();
+        assert_eq!(
+            paths,
+            ["$", "tool", "args", "args[0]", "args[1]", "args[1].token", "result", "result.Ok", "result.Ok.score"]
+        );
+        for (path, node) in v.walk() {
+            assert_eq!(v.at(&path), Some(node));
+        }
+        assert_eq!(GValue::int(1).walk().count(), 1);
+    }
+
+    #[test]
+    fn test_walk_tables() {
+        let t = GValue::table(["id", "tags"], vec![vec![GValue::int(1), GValue::list(vec![GValue::str("a")])]]);
+        let v = GValue::build_map().value("rows", t).finish();
+        let paths: Vec<String> = v.walk().map(|(p, _)| p.to_string()).collect();
+        assert_eq!(paths, ["$", "rows", "rows[0].id", "rows[0].tags", "rows[0].tags[0]"]);
+        assert_eq!(v.walk().last().map(|(_, node)| node), Some(&GValue::str("a")));
+
+        #[derive(Default)]
+        struct Leaves(Vec<String>);
+        impl Visitor for Leaves {
+            fn visit_leaf(&mut self, path: &GlyphPath, _: &GValue) {
+                self.0.push(path.to_string());
+            }
+            fn enter_table(&mut self, path: &GlyphPath, t: &TableValue) {
+                self.0.push(format!("table {} {}", path, t.rows.len()));
+            }
+        }
+        let mut leaves = Leaves::default();
+        v.visit(&mut leaves);
+        assert_eq!(leaves.0, ["table rows 1", "rows[0].id", "rows[0].tags[0]"]);
+    }
+
+    #[test]
+    fn test_transform() {
+        let v = GValue::build_map()
+            .value("Name", GValue::str("x"))
+            .value("Gone", GValue::Null)
+            .value("Scores", GValue::list(vec![GValue::float(0.123), GValue::Null, GValue::float(2.0 / 3.0)]))
+            .finish();
+
+        // Strip nulls, round floats and lowercase keys in one pass
+        let mut seen = Vec::new();
+        let out = v.clone().transform(|path, node| {
+            seen.push(path.to_string());
+            match node {
+                GValue::Null => None,
+                GValue::Float(f) => Some(GValue::float((f * 100.0).round() / 100.0)),
+                GValue::Map(entries) => Some(GValue::map(
+                    entries.into_iter().map(|e| MapEntry::new(e.key.to_lowercase(), e.value)).collect(),
+                )),
+                other => Some(other),
+            }
+        });
+        assert_eq!(out.to_string(), "{name=x scores=[0.12 0.67]}");
+        assert_eq!(seen, ["Name", "Gone", "Scores[0]", "Scores[1]", "Scores[2]", "Scores", "$"]);
+        assert_eq!(GValue::Null.transform(|_, _| None), GValue::Null);
+
+        let leaves = v.clone().map_values(|_, leaf| match leaf {
+            GValue::Null => GValue::str("-"),
+            other => other,
+        });
+        assert_eq!(leaves.to_string(), r#"{Gone="-" Name=x Scores=[0.123 "-" 0.6666666666666666]}"#);
+    }
+
+    #[test]
+    fn test_transform_tables() {
+        let t = GValue::table(
+            ["email", "id"],
+            vec![vec![GValue::str("al@x.io"), GValue::int(1)], vec![GValue::str("bo@x.io"), GValue::int(2)]],
+        );
+        let v = GValue::build_map().value("users", t).finish();
+        let pattern = PathPattern::parse("users[*].email").unwrap();
+        let masked = v.clone().transform_matching(&pattern, |_, _| Some(GValue::str("***")));
+        assert!(masked["users"].is_table());
+        assert_eq!(masked.to_string(), "{users=@tab _ rows=2 cols=2 [email id]\n|\"***\"|1|\n|\"***\"|2|\n@end}");
+
+        // Dropped cells become null; map_values reaches every cell
+        let dropped = v.clone().transform_matching(&pattern, |_, _| None);
+        assert_eq!(dropped.get_path("users").and_then(GValue::as_table).unwrap().rows[1][0], GValue::Null);
+        let mut seen = Vec::new();
+        v.map_values(|path, leaf| {
+            seen.push(path.to_string());
+            leaf
+        });
+        assert_eq!(seen, ["users[0].email", "users[0].id", "users[1].email", "users[1].id"]);
+    }
+
+    #[test]
+    fn test_transform_matching() {
+        let users = GValue::list(vec![
+            GValue::build_map().str("name", "al").str("email", "al@x.io").finish(),
+            GValue::build_map().str("name", "bo").str("email", "bo@x.io").finish(),
+        ]);
+        let v = GValue::build_map().value("users", users).str("email", "ops@x.io").finish();
+        let pattern = PathPattern::parse("users[*].email").unwrap();
+        let redacted = v.transform_matching(&pattern, |_, _| Some(GValue::str("***")));
+        assert_eq!(
+            redacted.to_string(),
+            r#"{email="ops@x.io" users=[{email="***" name=al} {email="***" name=bo}]}"#
+        );
+
+        let sum = GValue::sum("Err", Some(GValue::str("boom")));
+        let cleared = sum.transform_matching(&PathPattern::parse("Err").unwrap(), |_, _| None);
+        assert_eq!(cleared, GValue::sum("Err", None));
+    }
+
+    #[test]
+    fn test_visitor_hooks() {
+        #[derive(Default)]
+        struct Audit {
+            events: Vec<String>,
+            depth: usize,
+            max_depth: usize,
+            secrets: Vec<String>,
+        }
+
+        impl Visitor for Audit {
+            fn visit_leaf(&mut self, path: &GlyphPath, v: &GValue) {
+                if v.as_str() == Some("secret") {
+                    self.secrets.push(path.to_string());
+                }
+            }
+            fn enter_map(&mut self, path: &GlyphPath, _: &[MapEntry]) {
+                self.events.push(format!("map {}", path));
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+            fn exit_map(&mut self, path: &GlyphPath, _: &[MapEntry]) {
+                self.events.push(format!("/map {}", path));
+                self.depth -= 1;
+            }
+            fn enter_list(&mut self, path: &GlyphPath, items: &[GValue]) {
+                self.events.push(format!("list {} {}", path, items.len()));
+            }
+            fn enter_struct(&mut self, _: &GlyphPath, s: &StructValue) {
+                self.events.push(format!("struct {}", s.type_name));
+            }
+            fn enter_sum(&mut self, _: &GlyphPath, s: &SumValue) {
+                self.events.push(format!("sum {}", s.tag));
+            }
+        }
+
+        let mut audit = Audit::default();
+        sample().visit(&mut audit);
+        assert_eq!(
+            audit.events,
+            ["map $", "list args 2", "map args[1]", "/map args[1]", "sum Ok", "struct Hit", "/map $"]
+        );
+        assert_eq!(audit.max_depth, 2);
+        assert_eq!(audit.secrets, ["args[1].token"]);
+    }
+}
@@ -0,0 +1,372 @@
+//! Argument watchers over streamed GLYPH
+//!
+//! Models write tool calls a few tokens at a time. `ArgWatcher` reads the
+//! text as it arrives and calls back as soon as the value at a watched path
+//! is complete, so a tool can start on `args.query` while the model is still
+//! writing the rest of the call:
+//!
+//! ```
+//! use glyph_rs::{ArgWatcher, GValue};
+//!
+//! let mut query = None;
+//! let mut w = ArgWatcher::new()
+//!     .on_path("args.query", |_, v| query = Some(v.clone()))
+//!     .unwrap();
+//! assert_eq!(w.push("ToolCall{args={query=\"glyph co").unwrap(), 0);
+//! assert_eq!(w.push("dec\" limit=5} name=").unwrap(), 1);
+//! w.push("search}").unwrap();
+//! assert!(w.is_complete());
+//! drop(w);
+//! assert_eq!(query, Some(GValue::str("glyph codec")));
+//! ```
+//!
+//! A value is complete at its closing quote or bracket, or, for a bare word
+//! or number, at the character after it; `finish` completes one cut off by
+//! the end of the stream. Each complete value is parsed with `parse_loose`,
+//! so callbacks see exactly what parsing the whole text would give. Only the
+//! first top-level value is watched; anything after it is ignored.
+
+use crate::error::GlyphError;
+use crate::parse::{parse_loose, DELIMITERS};
+use crate::path::{GlyphPath, PathPattern};
+use crate::types::GValue;
+
+type Callback<'a> = Box<dyn FnMut(&GlyphPath, &GValue) + 'a>;
+
+/// Fires callbacks for watched paths as streamed GLYPH text completes them
+#[derive(Default)]
+pub struct ArgWatcher<'a> {
+    watches: Vec<(PathPattern, Callback<'a>)>,
+    buf: String,
+    pos: usize,
+    /// Open containers, outermost first
+    stack: Vec<Frame>,
+    /// Set once the top-level value is complete
+    done: bool,
+    /// How far the quoted string at `pos` was scanned without finding its
+    /// closing quote
+    scanned: usize,
+}
+
+#[derive(Debug)]
+enum MapState {
+    Key,
+    Sep(String),
+    Value(String),
+}
+
+/// An open map, struct, list or sum: its path, where its text starts, and
+/// what it expects next
+#[derive(Debug)]
+enum Frame {
+    Map { path: GlyphPath, start: usize, state: MapState },
+    List { path: GlyphPath, start: usize, next: usize },
+    Sum { path: GlyphPath, start: usize, tag: String },
+}
+
+impl Frame {
+    fn path_and_start(&self) -> (&GlyphPath, usize) {
+        match self {
+            Frame::Map { path, start, .. } | Frame::List { path, start, .. } | Frame::Sum { path, start, .. } => {
+                (path, *start)
+            }
+        }
+    }
+}
+
+impl<'a> ArgWatcher<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` with the path and value of every complete value matching
+    /// `pattern`, such as `args.query` or `args.items[*]`
+    pub fn on_path(mut self, pattern: &str, f: impl FnMut(&GlyphPath, &GValue) + 'a) -> Result<Self, GlyphError> {
+        self.watches.push((PathPattern::parse(pattern)?, Box::new(f)));
+        Ok(self)
+    }
+
+    /// Feed the next chunk of text; returns how many callbacks fired
+    pub fn push(&mut self, chunk: &str) -> Result<usize, GlyphError> {
+        self.buf.push_str(chunk);
+        self.scan(false)
+    }
+
+    /// End of stream: complete a trailing bare value and fire for it
+    pub fn finish(&mut self) -> Result<usize, GlyphError> {
+        let fired = self.scan(true)?;
+        if !self.done {
+            return Err(self.err("unexpected end of input"));
+        }
+        Ok(fired)
+    }
+
+    /// Whether the top-level value is complete
+    pub fn is_complete(&self) -> bool {
+        self.done
+    }
+
+    /// Everything pushed so far
+    pub fn text(&self) -> &str {
+        &self.buf
+    }
+
+    fn scan(&mut self, last: bool) -> Result<usize, GlyphError> {
+        let mut fired = 0;
+        while !self.done {
+            let Some(c) = self.buf[self.pos..].chars().next() else {
+                break;
+            };
+            if c.is_whitespace() || c == ',' {
+                self.pos += c.len_utf8();
+                continue;
+            }
+            match self.stack.last_mut() {
+                Some(Frame::Map { state: MapState::Key, .. }) if c == '}' => fired += self.close()?,
+                Some(Frame::Map { state: MapState::Key, .. }) => {
+                    let Some((key, end)) = self.key(c, last)? else {
+                        break;
+                    };
+                    self.pos = end;
+                    if let Some(Frame::Map { state, .. }) = self.stack.last_mut() {
+                        *state = MapState::Sep(key);
+                    }
+                }
+                Some(Frame::Map { state, .. }) if matches!(state, MapState::Sep(_)) => {
+                    if c != '=' && c != ':' {
+                        return Err(self.err("expected '=' after key"));
+                    }
+                    if let MapState::Sep(key) = std::mem::replace(state, MapState::Key) {
+                        *state = MapState::Value(key);
+                    }
+                    self.pos += 1;
+                }
+                Some(Frame::List { .. }) if c == ']' => fired += self.close()?,
+                Some(Frame::Sum { .. }) if c == ')' => fired += self.close()?,
+                _ => match self.value(c, last)? {
+                    Some(n) => fired += n,
+                    None => break,
+                },
+            }
+        }
+        Ok(fired)
+    }
+
+    /// A map key at `pos`, and where it ends; `None` until it is complete
+    fn key(&mut self, c: char, last: bool) -> Result<Option<(String, usize)>, GlyphError> {
+        if c == '"' {
+            let Some(end) = self.quoted_end(self.pos) else {
+                return Ok(None);
+            };
+            return match parse_loose(&self.buf[self.pos..end])? {
+                GValue::Str(key) => Ok(Some((key.to_string(), end))),
+                _ => Err(self.err("expected key")),
+            };
+        }
+        let end = self.bare_end(self.pos, false);
+        if end == self.pos {
+            return Err(self.err(&format!("unexpected {:?}", c)));
+        }
+        if end == self.buf.len() && !last {
+            return Ok(None);
+        }
+        Ok(Some((self.buf[self.pos..end].to_string(), end)))
+    }
+
+    /// Start or complete the value at `pos`; `None` when it needs more input
+    fn value(&mut self, c: char, last: bool) -> Result<Option<usize>, GlyphError> {
+        let start = self.pos;
+        match c {
+            '{' | '[' => {
+                let path = self.child_path();
+                self.stack.push(match c {
+                    '{' => Frame::Map { path, start, state: MapState::Key },
+                    _ => Frame::List { path, start, next: 0 },
+                });
+                self.pos += 1;
+                Ok(Some(0))
+            }
+            '"' => match self.quoted_end(start) {
+                Some(end) => self.complete(start, end).map(Some),
+                None => Ok(None),
+            },
+            '@' => match self.buf[start..].find("\n@end") {
+                Some(i) => self.complete(start, start + i + "\n@end".len()).map(Some),
+                None if last => self.complete(start, self.buf.len()).map(Some),
+                None => Ok(None),
+            },
+            _ => {
+                // Refs keep their `prefix:` and times their clock, so only
+                // bare words stop at ':'
+                let colons = c == '^' || c == '+' || c == '-' || c.is_ascii_digit();
+                let from = if c == '^' { start + 1 } else { start };
+                let end = self.bare_end(from, colons);
+                if end == self.buf.len() && !last {
+                    return Ok(None);
+                }
+                let word = &self.buf[start..end];
+                match self.buf[end..].chars().next() {
+                    Some('"') if word == "b64" || word == "^" || (c == '^' && word.ends_with(':')) => {
+                        match self.quoted_end(end) {
+                            Some(end) => self.complete(start, end).map(Some),
+                            None => Ok(None),
+                        }
+                    }
+                    Some('{') if !word.is_empty() && c != '^' => {
+                        let path = self.child_path();
+                        self.stack.push(Frame::Map { path, start, state: MapState::Key });
+                        self.pos = end + 1;
+                        Ok(Some(0))
+                    }
+                    Some('(') if !word.is_empty() && c != '^' => {
+                        let path = self.child_path();
+                        let tag = word.to_string();
+                        self.stack.push(Frame::Sum { path, start, tag });
+                        self.pos = end + 1;
+                        Ok(Some(0))
+                    }
+                    _ if word.is_empty() => Err(self.err(&format!("unexpected {:?}", c))),
+                    _ => self.complete(start, end).map(Some),
+                }
+            }
+        }
+    }
+
+    /// End of a bare token starting at `from`
+    fn bare_end(&self, from: usize, colons: bool) -> usize {
+        self.buf[from..]
+            .char_indices()
+            .find(|&(_, c)| c.is_whitespace() || c.is_control() || (DELIMITERS.contains(&c) && !(colons && c == ':')))
+            .map_or(self.buf.len(), |(i, _)| from + i)
+    }
+
+    /// End of the quoted string opening at `open`, just past its closing quote
+    fn quoted_end(&mut self, open: usize) -> Option<usize> {
+        let bytes = self.buf.as_bytes();
+        let mut i = self.scanned.max(open + 1);
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if i + 1 == bytes.len() => break,
+                b'\\' => i += 2,
+                b'"' => {
+                    self.scanned = 0;
+                    return Some(i + 1);
+                }
+                _ => i += 1,
+            }
+        }
+        self.scanned = i;
+        None
+    }
+
+    /// Path of a value starting now, from what the innermost container expects
+    fn child_path(&self) -> GlyphPath {
+        match self.stack.last() {
+            None => GlyphPath::root(),
+            Some(Frame::Map { path, state: MapState::Value(key), .. }) => path.key(key.as_str()),
+            Some(Frame::Map { path, .. }) => path.clone(),
+            Some(Frame::List { path, next, .. }) => path.index(*next),
+            Some(Frame::Sum { path, tag, .. }) => path.key(tag.as_str()),
+        }
+    }
+
+    /// The scalar at `start..end` is complete
+    fn complete(&mut self, start: usize, end: usize) -> Result<usize, GlyphError> {
+        let path = self.child_path();
+        self.pos = end;
+        let fired = self.fire(&path, start, end)?;
+        self.advance();
+        Ok(fired)
+    }
+
+    /// The innermost container closes at `pos`
+    fn close(&mut self) -> Result<usize, GlyphError> {
+        let frame = self.stack.pop().expect("close with an open container");
+        self.pos += 1;
+        let (path, start) = frame.path_and_start();
+        let fired = self.fire(path, start, self.pos)?;
+        self.advance();
+        Ok(fired)
+    }
+
+    /// Move the innermost container past a value just completed
+    fn advance(&mut self) {
+        match self.stack.last_mut() {
+            None => self.done = true,
+            Some(Frame::Map { state, .. }) => *state = MapState::Key,
+            Some(Frame::List { next, .. }) => *next += 1,
+            Some(Frame::Sum { .. }) => {}
+        }
+    }
+
+    fn fire(&mut self, path: &GlyphPath, start: usize, end: usize) -> Result<usize, GlyphError> {
+        if !self.watches.iter().any(|(pattern, _)| pattern.matches(path)) {
+            return Ok(0);
+        }
+        let v = parse_loose(&self.buf[start..end])?;
+        let mut fired = 0;
+        for (pattern, f) in &mut self.watches {
+            if pattern.matches(path) {
+                f(path, &v);
+                fired += 1;
+            }
+        }
+        Ok(fired)
+    }
+
+    fn err(&self, msg: &str) -> GlyphError {
+        GlyphError::Parse(format!("{} at offset {}", msg, self.pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_fires_before_stream_ends() {
+        let seen = RefCell::new(Vec::new());
+        let record = |path: &GlyphPath, v: &GValue| seen.borrow_mut().push((path.to_string(), v.clone()));
+        let mut w = ArgWatcher::new()
+            .on_path("args.query", record)
+            .unwrap()
+            .on_path("args.tags[*]", record)
+            .unwrap();
+        let text = r#"ToolCall{args={query="say \"hi\"" tags=[a b] limit=5} id=^call_1 name=search}"#;
+        let mut fired_at = Vec::new();
+        for (i, c) in text.char_indices() {
+            if w.push(&c.to_string()).unwrap() > 0 {
+                fired_at.push(i);
+            }
+        }
+        assert!(w.is_complete());
+        assert_eq!(fired_at, vec![text.find("\" tags").unwrap(), text.find(" b]").unwrap(), text.find("] limit").unwrap()]);
+        drop(w);
+        let seen = seen.into_inner();
+        assert_eq!(seen[0].1, GValue::str("say \"hi\""));
+        assert_eq!(seen[1].1, GValue::str("a"));
+        assert_eq!(seen[2].1, GValue::str("b"));
+    }
+
+    #[test]
+    fn test_nested_values_and_finish() {
+        let mut got = Vec::new();
+        let mut w = ArgWatcher::new()
+            .on_path("*", |path, v| got.push((path.to_string(), v.clone())))
+            .unwrap();
+        w.push("{at: 2025-01-01T00:00:00Z, by=^user:42 shape=Circle(1.5) ").unwrap();
+        w.push("data=b64\"aGk=\" rows=@tab _ rows=1 cols=1 [id]\n|1|\n@end n=1}").unwrap();
+        assert_eq!(w.finish().unwrap(), 0);
+        drop(w);
+        let keys: Vec<_> = got.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(keys, ["at", "by", "shape", "data", "rows", "n"]);
+        assert_eq!(got[1].1, GValue::id("user", "42"));
+        assert_eq!(got[5].1, GValue::int(1));
+
+        let mut w = ArgWatcher::new().on_path("", |_, _| {}).unwrap();
+        assert_eq!(w.push("42").unwrap(), 0);
+        assert_eq!(w.finish().unwrap(), 1);
+        assert!(ArgWatcher::new().push("{a=}").is_err());
+    }
+}
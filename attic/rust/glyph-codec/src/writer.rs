@@ -0,0 +1,304 @@
+//! Incremental list and table encoders
+//!
+//! Results read from a database cursor or a paginated API don't need to be
+//! collected into one `GValue` to be encoded. `ListWriter` writes `[...]`
+//! one item at a time and `TableWriter` writes an `@tab` block one row at a
+//! time, each straight to an `io::Write`.
+//!
+//! An `@tab` header states its row count before the rows, so a `TableWriter`
+//! only streams when told the count up front (`with_rows`) and the options
+//! don't need every row before the first one is written: `sort_rows`,
+//! `align_tabular` and `tabular_types` do. Otherwise it keeps the rendered
+//! row text, much smaller than the values, and writes it all at `finish`.
+//!
+//! The output is what `canonicalize_loose_with_opts` writes for the
+//! equivalent `GValue::Table`, or for the list with tabular output off.
+//! `limits` apply inside items and cells; `max_output_bytes` counts the
+//! whole output, while the list length limit does not apply to the writers.
+
+use crate::error::GlyphError;
+use crate::loose::{push_tab_header, push_tab_line, tab_column_order, CanonWriter, LooseCanonOpts, RowOrder};
+use crate::types::*;
+use std::io;
+
+/// Writes `[item item ...]` one item at a time; see the module docs
+pub struct ListWriter<W: io::Write> {
+    out: W,
+    opts: LooseCanonOpts,
+    buf: String,
+    items: usize,
+    bytes: usize,
+}
+
+impl<W: io::Write> ListWriter<W> {
+    pub fn new(out: W, opts: &LooseCanonOpts) -> Self {
+        Self {
+            out,
+            opts: opts.clone(),
+            buf: String::new(),
+            items: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Items written so far
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    /// Encode `v` and write it out
+    pub fn push(&mut self, v: &GValue) -> Result<(), GlyphError> {
+        self.buf.clear();
+        self.buf.push(if self.items == 0 { '[' } else { ' ' });
+        CanonWriter::fork(&self.opts, &None, 0, 1).write(&mut self.buf, v)?;
+        self.items += 1;
+        emit(&mut self.out, &self.buf, &mut self.bytes, &self.opts)
+    }
+
+    /// Close the list and return the output
+    pub fn finish(mut self) -> Result<W, GlyphError> {
+        let end = if self.items == 0 { "[]" } else { "]" };
+        emit(&mut self.out, end, &mut self.bytes, &self.opts)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes an `@tab` block one row at a time; see the module docs
+pub struct TableWriter<W: io::Write> {
+    out: W,
+    opts: LooseCanonOpts,
+    columns: Vec<String>,
+    /// Input column of each output column
+    order: Vec<usize>,
+    /// Row count from `with_rows`
+    expected: Option<usize>,
+    rows: usize,
+    /// Rendered rows waiting for `finish`, unless streaming
+    held: Option<Vec<Vec<String>>>,
+    /// Type of the non-null cells of each input column, for `tabular_types`
+    types: Vec<Option<GType>>,
+    /// Input columns whose non-null cells differ in type
+    mixed: Vec<bool>,
+    buf: String,
+    pool: Vec<String>,
+    bytes: usize,
+}
+
+impl<W: io::Write> TableWriter<W> {
+    /// A table with `columns`, given in the order `push_row` cells come in.
+    /// Rows are held until `finish`.
+    pub fn new(out: W, columns: Vec<String>, opts: &LooseCanonOpts) -> Self {
+        Self {
+            out,
+            order: tab_column_order(opts, &columns),
+            types: vec![None; columns.len()],
+            mixed: vec![false; columns.len()],
+            columns,
+            opts: opts.clone(),
+            expected: None,
+            rows: 0,
+            held: Some(Vec::new()),
+            buf: String::new(),
+            pool: Vec::new(),
+            bytes: 0,
+        }
+    }
+
+    /// A table of exactly `rows` rows, streamed as they are pushed when the
+    /// options allow; `finish` fails if a different number arrive
+    pub fn with_rows(out: W, columns: Vec<String>, rows: usize, opts: &LooseCanonOpts) -> Self {
+        let streams = opts.sort_rows == RowOrder::Source && !opts.align_tabular && !opts.tabular_types;
+        Self {
+            expected: Some(rows),
+            held: if streams { None } else { Some(Vec::new()) },
+            ..Self::new(out, columns, opts)
+        }
+    }
+
+    /// Rows pushed so far
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    /// Encode a row, one cell per column in the order given at construction
+    pub fn push_row(&mut self, row: &[GValue]) -> Result<(), GlyphError> {
+        if row.len() != self.columns.len() {
+            return Err(GlyphError::InvalidValue(format!(
+                "table row has {} cells, expected {}",
+                row.len(),
+                self.columns.len()
+            )));
+        }
+        if self.expected == Some(self.rows) {
+            return Err(GlyphError::InvalidValue(format!("table declared {} rows, got more", self.rows)));
+        }
+        if self.opts.tabular_types {
+            for (i, cell) in row.iter().enumerate() {
+                match (self.types[i], cell.gtype()) {
+                    (_, GType::Null) => {}
+                    (None, actual) => self.types[i] = Some(actual),
+                    (Some(seen), actual) => self.mixed[i] |= seen != actual,
+                }
+            }
+        }
+
+        let cells: Vec<Option<&GValue>> = self.order.iter().map(|&i| Some(&row[i])).collect();
+        let mut writer = CanonWriter::fork(&self.opts, &None, 0, 1).with_pool(std::mem::take(&mut self.pool));
+        let result = writer.render_row(&cells);
+        self.pool = writer.into_pool();
+        let line = result?;
+        self.rows += 1;
+
+        if let Some(held) = self.held.as_mut() {
+            held.push(line);
+            return Ok(());
+        }
+        self.buf.clear();
+        if self.rows == 1 {
+            self.push_header(self.expected.unwrap_or_default());
+        }
+        push_tab_line(&mut self.buf, &line, &[]);
+        self.pool.extend(line.into_iter().map(|mut cell| {
+            cell.clear();
+            cell
+        }));
+        emit(&mut self.out, &self.buf, &mut self.bytes, &self.opts)
+    }
+
+    /// Write what remains of the block, through `@end`, and return the output
+    pub fn finish(mut self) -> Result<W, GlyphError> {
+        if let Some(expected) = self.expected.filter(|&n| n != self.rows) {
+            return Err(GlyphError::InvalidValue(format!(
+                "table declared {} rows, got {}",
+                expected, self.rows
+            )));
+        }
+        self.buf.clear();
+        match self.held.take() {
+            Some(lines) => {
+                self.push_header(lines.len());
+                let keys: Vec<String> = self.order.iter().map(|&i| self.columns[i].clone()).collect();
+                CanonWriter::fork(&self.opts, &None, 0, 1).finish_tab_rows(&mut self.buf, lines, &keys);
+            }
+            None => {
+                if self.rows == 0 {
+                    self.push_header(0);
+                }
+                self.buf.push_str("@end");
+            }
+        }
+        emit(&mut self.out, &self.buf, &mut self.bytes, &self.opts)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn push_header(&mut self, rows: usize) {
+        let names: Vec<&str> = self.order.iter().map(|&i| self.columns[i].as_str()).collect();
+        let types: Vec<Option<GType>> = self.order.iter().map(|&i| self.types[i].filter(|_| !self.mixed[i])).collect();
+        push_tab_header(&mut self.buf, &self.opts, &names, &types, rows);
+    }
+}
+
+/// Write `text`, failing first if it would take the output past
+/// `max_output_bytes`
+fn emit<W: io::Write>(out: &mut W, text: &str, bytes: &mut usize, opts: &LooseCanonOpts) -> Result<(), GlyphError> {
+    *bytes += text.len();
+    if let Some(max) = opts.limits.max_output_bytes.filter(|&max| *bytes > max) {
+        return Err(GlyphError::LimitExceeded(format!("output over max_output_bytes {}", max)));
+    }
+    out.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loose::{canonicalize_loose_with_opts, EncodeLimits};
+
+    fn rows() -> Vec<Vec<GValue>> {
+        (0..5)
+            .map(|i| vec![GValue::str(format!("name {}", 4 - i)), GValue::int(i), if i == 2 { GValue::Null } else { GValue::bool(i % 2 == 0) }])
+            .collect()
+    }
+
+    fn columns() -> Vec<String> {
+        vec!["name".to_string(), "id".to_string(), "ok".to_string()]
+    }
+
+    #[test]
+    fn test_table_writer_matches_table_value() {
+        let table = GValue::Table(TableValue::new(columns(), rows()));
+        let sorted = LooseCanonOpts {
+            sort_rows: RowOrder::Canonical,
+            tabular_types: true,
+            ..LooseCanonOpts::default()
+        };
+        for opts in [LooseCanonOpts::default(), LooseCanonOpts::llm(), sorted] {
+            let expected = canonicalize_loose_with_opts(&table, &opts).unwrap();
+            for counted in [false, true] {
+                let mut w = if counted {
+                    TableWriter::with_rows(Vec::new(), columns(), 5, &opts)
+                } else {
+                    TableWriter::new(Vec::new(), columns(), &opts)
+                };
+                for row in rows() {
+                    w.push_row(&row).unwrap();
+                }
+                assert_eq!(String::from_utf8(w.finish().unwrap()).unwrap(), expected);
+            }
+        }
+        let empty = GValue::Table(TableValue::new(columns(), vec![]));
+        let opts = LooseCanonOpts::default();
+        let w = TableWriter::with_rows(Vec::new(), columns(), 0, &opts);
+        assert_eq!(String::from_utf8(w.finish().unwrap()).unwrap(), canonicalize_loose_with_opts(&empty, &opts).unwrap());
+    }
+
+    #[test]
+    fn test_table_writer_streams_with_row_count() {
+        let opts = LooseCanonOpts::default();
+        let mut w = TableWriter::with_rows(Vec::new(), columns(), 2, &opts);
+        w.push_row(&rows()[0]).unwrap();
+        // Header and first row are already out
+        assert_eq!(String::from_utf8_lossy(&w.out), "@tab _ rows=2 cols=3 [id name ok]\n|0|\"name 4\"|t|\n");
+        assert!(w.push_row(&[GValue::int(1)]).is_err());
+        w.push_row(&rows()[1]).unwrap();
+        assert!(w.push_row(&rows()[2]).is_err());
+
+        let mut short = TableWriter::with_rows(Vec::new(), columns(), 3, &opts);
+        short.push_row(&rows()[0]).unwrap();
+        assert!(short.finish().unwrap_err().to_string().contains("declared 3 rows, got 1"));
+    }
+
+    #[test]
+    fn test_list_writer() {
+        let items = vec![GValue::int(1), GValue::str("two words"), GValue::list(vec![GValue::Null]), GValue::map(vec![])];
+        let opts = LooseCanonOpts::default();
+        let mut w = ListWriter::new(Vec::new(), &opts);
+        for item in &items {
+            w.push(item).unwrap();
+        }
+        assert_eq!(w.len(), 4);
+        assert_eq!(
+            String::from_utf8(w.finish().unwrap()).unwrap(),
+            canonicalize_loose_with_opts(&GValue::list(items), &opts).unwrap()
+        );
+        assert_eq!(ListWriter::new(Vec::new(), &opts).finish().unwrap(), b"[]");
+
+        let capped = LooseCanonOpts {
+            limits: EncodeLimits { max_output_bytes: Some(8), ..EncodeLimits::default() },
+            ..LooseCanonOpts::default()
+        };
+        let mut w = ListWriter::new(Vec::new(), &capped);
+        w.push(&GValue::int(1)).unwrap();
+        assert!(matches!(w.push(&GValue::str("long enough")), Err(GlyphError::LimitExceeded(_))));
+    }
+}
@@ -0,0 +1,33 @@
+//! Key ordering conformance vectors from tests/fixtures/key_ordering_vectors.json.
+
+use glyph_rs::{canon_spec_version, canonicalize_loose, canonicalize_loose_no_tabular, from_json};
+use serde_json::Value;
+
+fn vectors() -> Value {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../../tests/fixtures/key_ordering_vectors.json");
+    let text = std::fs::read_to_string(path).expect("read key ordering vectors");
+    serde_json::from_str(&text).expect("parse key ordering vectors")
+}
+
+fn check(cases: &Value, canon: fn(&glyph_rs::GValue) -> glyph_rs::Result<String>) {
+    for case in cases.as_array().unwrap() {
+        let input: Value = serde_json::from_str(case["input_json"].as_str().unwrap()).unwrap();
+        let got = canon(&from_json(&input)).unwrap();
+        assert_eq!(got, case["expected"].as_str().unwrap(), "{}", case["desc"]);
+    }
+}
+
+#[test]
+fn key_ordering_spec_version() {
+    assert_eq!(vectors()["spec_version"], canon_spec_version());
+}
+
+#[test]
+fn key_ordering_no_tabular_vectors() {
+    check(&vectors()["no_tabular"], canonicalize_loose_no_tabular);
+}
+
+#[test]
+fn key_ordering_tabular_vectors() {
+    check(&vectors()["tabular"], canonicalize_loose);
+}
@@ -1,7 +1,7 @@
 //! Truth table tests for glyph - 12 cases from truth_cases.json.
 
 use glyph_rs::{
-    GValue, GlyphError,
+    GValue,
     canonicalize_loose, canonicalize_loose_no_tabular,
     from_json,
 };